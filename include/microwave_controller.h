@@ -0,0 +1,70 @@
+/*
+ * C API of the microwave_controller crate.
+ * Link against the cdylib (libmicrowave_controller.so / microwave_controller.dll)
+ * or the staticlib built by `cargo build --release`.
+ *
+ * Every function returns MW_OK (0) on success or a negative MW_ERR_* code.
+ * Keep this header in sync with src/ffi.rs.
+ */
+#ifndef MICROWAVE_CONTROLLER_H
+#define MICROWAVE_CONTROLLER_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define MW_OK                    0
+#define MW_ERR_NULL_POINTER     -1
+#define MW_ERR_INVALID_ARGUMENT -2
+#define MW_ERR_NO_DEVICE        -3
+#define MW_ERR_CONNECTION       -4
+#define MW_ERR_IO               -5
+#define MW_ERR_TIMEOUT          -6
+#define MW_ERR_DEVICE           -7
+#define MW_ERR_INVALID_RESPONSE -8
+#define MW_ERR_CANCELLED        -9
+#define MW_ERR_INTERLOCK        -10
+#define MW_ERR_INTERNAL         -11
+#define MW_ERR_BUFFER_TOO_SMALL -12
+#define MW_ERR_UNEXPECTED_DEVICE -13
+#define MW_ERR_READ_ONLY        -14
+#define MW_ERR_NOT_AUTHORIZED   -15
+#define MW_ERR_INVALID_STATE    -16
+#define MW_ERR_BUSY             -17
+
+/* Opaque controller handle. Commands from several threads are serialized;
+ * the last reply and error message are those of the latest call from any
+ * thread. */
+typedef struct MwHandle MwHandle;
+
+/* Connect to the first autodetected signal generator board. */
+int mw_connect(MwHandle **out);
+
+/* Open a specific serial port, e.g. "COM3" or "/dev/ttyUSB0". */
+int mw_open(const char *port_name, MwHandle **out);
+
+/* Send a command line such as "$FCS,0,2450", checked like the typed calls:
+ * unknown lines fail with MW_ERR_INVALID_ARGUMENT, "$ECS,0,1" with
+ * MW_ERR_INTERLOCK while an interlock is open. */
+int mw_send(MwHandle *handle, const char *command);
+
+/* Copy the last reply / last error message into buffer (NUL terminated). */
+int mw_read_response(const MwHandle *handle, char *buffer, size_t length);
+int mw_last_error(const MwHandle *handle, char *buffer, size_t length);
+
+/* Typed convenience commands. */
+int mw_set_frequency(MwHandle *handle, float frequency_mhz);
+int mw_set_power(MwHandle *handle, float power_dbm);
+int mw_rf_enable(MwHandle *handle, int enable);
+int mw_get_pa_power(MwHandle *handle, float *forward_dbm, float *reflected_dbm);
+
+/* Release the handle. RF is left as it is. */
+void mw_disconnect(MwHandle *handle);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MICROWAVE_CONTROLLER_H */
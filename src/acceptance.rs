@@ -0,0 +1,394 @@
+//! Acceptance test of a board on a dummy load, for qualifying new firmware.
+//!
+//! [`AcceptanceTest`] runs a fixed sequence of cases: the board identifies
+//! itself, forward power follows each setpoint within a tolerance, a short
+//! sweep measures every point with the load matched, and faults clear. The
+//! [`AcceptanceReport`] is written in the JUnit XML format CI servers
+//! display, one test case per check. RF is off again when the run ends,
+//! however it ends.
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::{check_reply, parse_status};
+use crate::error::{ControllerError, DeviceFault};
+use crate::safety::SafetyGuard;
+use crate::sweep::{measure_point, Sweep, SweepSegment};
+use crate::units::format_timestamp;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceTest {
+    pub frequency_mhz: f32,
+    /// Setpoints checked against the forward power readback.
+    pub power_levels_dbm: Vec<f32>,
+    /// Largest allowed difference between forward power and setpoint.
+    pub tolerance_db: f32,
+    /// Time at each setpoint or sweep point before it is measured.
+    pub dwell: Duration,
+    pub sweep: SweepSegment,
+    /// Power for the sweep, the lowest level by default.
+    pub sweep_power_dbm: Option<f32>,
+    /// Highest S11 a sweep point may have; a dummy load is well matched
+    /// across the band.
+    pub max_s11_db: f32,
+}
+
+impl AcceptanceTest {
+    /// Checks at 2450 MHz, with a 2400-2500 MHz sweep.
+    pub fn new(power_levels_dbm: Vec<f32>) -> AcceptanceTest {
+        AcceptanceTest {
+            frequency_mhz: 2450.0,
+            power_levels_dbm,
+            tolerance_db: 1.0,
+            dwell: Duration::from_millis(500),
+            sweep: SweepSegment {
+                start_mhz: 2400.0,
+                stop_mhz: 2500.0,
+                step_mhz: 10.0,
+            },
+            sweep_power_dbm: None,
+            max_s11_db: -10.0,
+        }
+    }
+
+    pub fn with_frequency(mut self, mhz: f32) -> AcceptanceTest {
+        self.frequency_mhz = mhz;
+        self
+    }
+
+    pub fn with_tolerance(mut self, db: f32) -> AcceptanceTest {
+        self.tolerance_db = db;
+        self
+    }
+
+    pub fn with_dwell(mut self, dwell: Duration) -> AcceptanceTest {
+        self.dwell = dwell;
+        self
+    }
+
+    pub fn with_sweep(mut self, sweep: SweepSegment) -> AcceptanceTest {
+        self.sweep = sweep;
+        self
+    }
+
+    pub fn with_sweep_power(mut self, dbm: f32) -> AcceptanceTest {
+        self.sweep_power_dbm = Some(dbm);
+        self
+    }
+
+    pub fn with_max_s11(mut self, db: f32) -> AcceptanceTest {
+        self.max_s11_db = db;
+        self
+    }
+
+    fn sweep_power(&self) -> f32 {
+        self.sweep_power_dbm.unwrap_or_else(|| {
+            self.power_levels_dbm
+                .iter()
+                .copied()
+                .fold(f32::INFINITY, f32::min)
+        })
+    }
+
+    /// Runs every case in order. A setpoint outside the board's range fails
+    /// before anything is sent; after that, failing cases are recorded and
+    /// the run goes on, except that nothing more is tried once the board
+    /// fails to identify itself or `cancel` is cancelled.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<AcceptanceReport, ControllerError> {
+        if self.power_levels_dbm.is_empty() {
+            return Err(ControllerError::InvalidParameter(
+                "No power levels to check".to_string(),
+            ));
+        }
+        for frequency in [
+            self.frequency_mhz,
+            self.sweep.start_mhz,
+            self.sweep.stop_mhz,
+        ] {
+            Command::SetFrequency(frequency).validate()?;
+        }
+        for power in self.power_levels_dbm.iter().chain([&self.sweep_power()]) {
+            Command::SetPower(*power).validate()?;
+        }
+
+        let mut run = CaseRunner {
+            cancel,
+            stop: None,
+            report: AcceptanceReport {
+                started: SystemTime::now(),
+                properties: Vec::new(),
+                cases: Vec::new(),
+            },
+        };
+
+        let mut properties = Vec::new();
+        run.case("identity", || {
+            for (name, command) in [
+                ("identity", Command::GetIdentity),
+                ("firmware", Command::GetVersion),
+            ] {
+                let reply = controller.send(&command).map_err(|e| e.to_string())?;
+                properties.push((name.to_string(), reply.trim().to_string()));
+            }
+            Ok(())
+        });
+        run.report.properties = properties;
+        if run.report.failures() > 0 {
+            run.stop = Some("The board did not identify itself".to_string());
+        }
+
+        let guard = SafetyGuard::new(controller);
+        for &power_dbm in &self.power_levels_dbm {
+            run.case(&format!("setpoint {:.2} dBm", power_dbm), || {
+                self.check_setpoint(controller, cancel, power_dbm)
+            });
+        }
+        let released = guard.release();
+        run.case("RF off", || released.map_err(|e| e.to_string()));
+        run.case("sweep", || self.check_sweep(controller, cancel));
+        run.case("fault clear", || {
+            check_reply(
+                &controller
+                    .send(&Command::ClearErrors)
+                    .map_err(|e| e.to_string())?,
+            )
+            .map_err(|e| e.to_string())?;
+            let status = controller
+                .send(&Command::GetStatus { verbose: false })
+                .and_then(|reply| parse_status(&reply))
+                .map_err(|e| e.to_string())?;
+            let faults: Vec<String> = DeviceFault::of_status(status)
+                .map(|fault| fault.to_string())
+                .collect();
+            match faults.is_empty() {
+                true => Ok(()),
+                false => Err(format!(
+                    "Still reported after clearing: {}",
+                    faults.join(", ")
+                )),
+            }
+        });
+        Ok(run.report)
+    }
+
+    fn check_setpoint(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        power_dbm: f32,
+    ) -> Result<(), String> {
+        let step = || -> Result<f32, ControllerError> {
+            controller.send(&Command::SetFrequency(self.frequency_mhz))?;
+            controller.send(&Command::SetPower(power_dbm))?;
+            controller.send(&Command::RfEnable)?;
+            if !cancel.sleep(self.dwell) {
+                return Err(ControllerError::Cancelled);
+            }
+            Ok(measure_point(controller, self.frequency_mhz)?.forward_dbm)
+        };
+        let forward_dbm = step().map_err(|e| e.to_string())?;
+        let deviation = forward_dbm - power_dbm;
+        match deviation.abs() <= self.tolerance_db {
+            true => Ok(()),
+            false => Err(format!(
+                "Forward power {:.2} dBm is {:+.2} dB off the setpoint, more than ±{} dB",
+                forward_dbm, deviation, self.tolerance_db
+            )),
+        }
+    }
+
+    fn check_sweep(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<(), String> {
+        let sweep = Sweep::linear(
+            self.sweep.start_mhz,
+            self.sweep.stop_mhz,
+            self.sweep.step_mhz,
+            self.sweep_power(),
+            self.dwell,
+        );
+        let expected = sweep.frequencies().len();
+        let points = sweep.run(controller, cancel).map_err(|e| e.to_string())?;
+        if points.len() != expected {
+            return Err(format!("Measured {} of {} points", points.len(), expected));
+        }
+        let mut problems = Vec::new();
+        for point in &points {
+            if !(point.forward_dbm.is_finite() && point.reflected_dbm.is_finite()) {
+                problems.push(format!("no reading at {:.2} MHz", point.frequency_mhz));
+            } else if (point.forward_dbm - sweep.power_dbm).abs() > self.tolerance_db {
+                problems.push(format!(
+                    "forward {:.2} dBm at {:.2} MHz",
+                    point.forward_dbm, point.frequency_mhz
+                ));
+            } else if point.s11_db() > self.max_s11_db {
+                problems.push(format!(
+                    "S11 {:.2} dB at {:.2} MHz",
+                    point.s11_db(),
+                    point.frequency_mhz
+                ));
+            }
+        }
+        match problems.is_empty() {
+            true => Ok(()),
+            false => Err(problems.join("; ")),
+        }
+    }
+}
+
+/// Runs cases into a report until one stops the run.
+struct CaseRunner<'a> {
+    cancel: &'a CancellationToken,
+    /// Why the remaining cases are skipped.
+    stop: Option<String>,
+    report: AcceptanceReport,
+}
+
+impl CaseRunner<'_> {
+    fn case(&mut self, name: &str, check: impl FnOnce() -> Result<(), String>) {
+        if self.stop.is_none() && self.cancel.is_cancelled() {
+            self.stop = Some("Cancelled".to_string());
+        }
+        let started = Instant::now();
+        let outcome = match &self.stop {
+            Some(reason) => Outcome::Skipped(reason.clone()),
+            None => match check() {
+                Ok(()) => Outcome::Passed,
+                Err(reason) => Outcome::Failed(reason),
+            },
+        };
+        self.report.cases.push(CaseResult {
+            name: name.to_string(),
+            time: started.elapsed(),
+            outcome,
+        });
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    /// Not run, and why.
+    Skipped(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CaseResult {
+    pub name: String,
+    pub time: Duration,
+    pub outcome: Outcome,
+}
+
+/// What an [`AcceptanceTest`] found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AcceptanceReport {
+    pub started: SystemTime,
+    /// The board's `$IDN` and `$VER` replies, as they were read.
+    pub properties: Vec<(String, String)>,
+    pub cases: Vec<CaseResult>,
+}
+
+impl AcceptanceReport {
+    /// Whether every case passed; a skipped case does not.
+    pub fn passed(&self) -> bool {
+        self.cases
+            .iter()
+            .all(|case| case.outcome == Outcome::Passed)
+    }
+
+    pub fn failures(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Failed(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|outcome| matches!(outcome, Outcome::Skipped(_)))
+    }
+
+    fn count(&self, filter: impl Fn(&Outcome) -> bool) -> usize {
+        self.cases
+            .iter()
+            .filter(|case| filter(&case.outcome))
+            .count()
+    }
+
+    /// JUnit XML with one `<testsuite>` named `suite`.
+    pub fn to_junit(&self, suite: &str) -> String {
+        let total: f64 = self.cases.iter().map(|case| case.time.as_secs_f64()).sum();
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        let _ = writeln!(
+            xml,
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"0\" skipped=\"{}\" time=\"{:.3}\" timestamp=\"{}\">",
+            escape_xml(suite),
+            self.cases.len(),
+            self.failures(),
+            self.skipped(),
+            total,
+            format_timestamp(self.started)
+        );
+        if !self.properties.is_empty() {
+            xml.push_str("  <properties>\n");
+            for (name, value) in &self.properties {
+                let _ = writeln!(
+                    xml,
+                    "    <property name=\"{}\" value=\"{}\"/>",
+                    escape_xml(name),
+                    escape_xml(value)
+                );
+            }
+            xml.push_str("  </properties>\n");
+        }
+        for case in &self.cases {
+            let _ = write!(
+                xml,
+                "  <testcase classname=\"{}\" name=\"{}\" time=\"{:.3}\"",
+                escape_xml(suite),
+                escape_xml(&case.name),
+                case.time.as_secs_f64()
+            );
+            match &case.outcome {
+                Outcome::Passed => xml.push_str("/>\n"),
+                Outcome::Failed(reason) => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                        escape_xml(reason)
+                    );
+                }
+                Outcome::Skipped(reason) => {
+                    let _ = writeln!(
+                        xml,
+                        ">\n    <skipped message=\"{}\"/>\n  </testcase>",
+                        escape_xml(reason)
+                    );
+                }
+            }
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+
+    pub fn save_junit(&self, path: impl AsRef<Path>, suite: &str) -> io::Result<()> {
+        fs::write(path, self.to_junit(suite))
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
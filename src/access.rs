@@ -0,0 +1,222 @@
+//! Roles for clients of the network front ends (Modbus TCP, OPC UA and
+//! EPICS). Clients are told apart by their IP address, the one identity all
+//! three protocols provide without extra setup. OPC UA clients can also
+//! present an [`ApiToken`] as the password of a user name identity.
+
+use std::{fmt, fs, io, net::IpAddr, path::Path, str::FromStr};
+
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::json::{self, FromJson, JsonValue, ToJson};
+
+/// What a client may change, each role including the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    /// Queries only.
+    Viewer,
+    /// Frequency, power up to the operator limit, RF on/off and clearing errors.
+    Operator,
+    /// Everything, including DLL configuration and firmware sweeps.
+    Engineer,
+}
+
+impl Role {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Engineer => "engineer",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Role, String> {
+        match text {
+            "viewer" => Ok(Role::Viewer),
+            "operator" => Ok(Role::Operator),
+            "engineer" => Ok(Role::Engineer),
+            other => Err(format!("Unknown role: {}", other)),
+        }
+    }
+}
+
+/// Secret a client presents to get `role` regardless of its address.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiToken {
+    /// Who holds the token, recorded as the actor in the audit log.
+    pub name: String,
+    pub token: String,
+    pub role: Role,
+}
+
+/// Role assignment and the command classes each role may issue.
+///
+/// The default policy makes every client an engineer, which is how the
+/// front ends behave without one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AccessPolicy {
+    /// Role of clients not listed in `clients`.
+    pub default_role: Role,
+    /// Client IP addresses and their roles.
+    pub clients: Vec<(IpAddr, Role)>,
+    /// Highest power setpoint an operator may write; anything above needs
+    /// an engineer.
+    pub operator_power_limit_dbm: Option<f32>,
+    /// Tokens accepted from clients that authenticate.
+    pub tokens: Vec<ApiToken>,
+}
+
+impl Default for AccessPolicy {
+    fn default() -> AccessPolicy {
+        AccessPolicy {
+            default_role: Role::Engineer,
+            clients: Vec::new(),
+            operator_power_limit_dbm: None,
+            tokens: Vec::new(),
+        }
+    }
+}
+
+impl AccessPolicy {
+    pub fn role_for(&self, client: IpAddr) -> Role {
+        self.clients
+            .iter()
+            .find(|(address, _)| *address == client)
+            .map_or(self.default_role, |(_, role)| *role)
+    }
+
+    /// The token matching `secret`, compared in constant time.
+    pub fn token(&self, secret: &[u8]) -> Option<&ApiToken> {
+        self.tokens
+            .iter()
+            .find(|token| constant_time_eq(token.token.as_bytes(), secret))
+    }
+
+    /// Lowest role allowed to send `command`.
+    pub fn required_role(&self, command: &Command) -> Role {
+        match command {
+            command if command.is_query() => Role::Viewer,
+            Command::ConfigureDll { .. }
+            | Command::DllEnable
+            | Command::DllDisable
+            | Command::SetFanSpeed(_) => Role::Engineer,
+            Command::SetPower(dbm) | Command::SweepDbm { power_dbm: dbm, .. }
+                if self
+                    .operator_power_limit_dbm
+                    .is_some_and(|limit| *dbm > limit) =>
+            {
+                Role::Engineer
+            }
+            _ => Role::Operator,
+        }
+    }
+
+    pub fn authorize(&self, role: Role, command: &Command) -> Result<(), ControllerError> {
+        let required = self.required_role(command);
+        if role < required {
+            return Err(ControllerError::NotAuthorized(format!(
+                "{} needs the {} role, client is {}",
+                command, required, role
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<AccessPolicy> {
+        let text = fs::read_to_string(path)?;
+        json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json().to_pretty_string() + "\n")
+    }
+}
+
+impl ToJson for AccessPolicy {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("default_role", self.default_role.name())
+            .with(
+                "clients",
+                JsonValue::Array(
+                    self.clients
+                        .iter()
+                        .map(|(address, role)| {
+                            JsonValue::object()
+                                .with("address", address.to_string())
+                                .with("role", role.name())
+                        })
+                        .collect(),
+                ),
+            )
+            .with("operator_power_limit_dbm", self.operator_power_limit_dbm)
+            .with(
+                "tokens",
+                JsonValue::Array(
+                    self.tokens
+                        .iter()
+                        .map(|token| {
+                            JsonValue::object()
+                                .with("name", token.name.as_str())
+                                .with("token", token.token.as_str())
+                                .with("role", token.role.name())
+                        })
+                        .collect(),
+                ),
+            )
+    }
+}
+
+impl FromJson for AccessPolicy {
+    fn from_json(json: &JsonValue) -> Result<AccessPolicy, String> {
+        let clients = match json.get("clients") {
+            None | Some(JsonValue::Null) => Vec::new(),
+            Some(_) => json
+                .array("clients")?
+                .iter()
+                .map(|client| {
+                    let address = client.string("address")?;
+                    let address = address
+                        .parse()
+                        .map_err(|_| format!("Invalid client address: {}", address))?;
+                    Ok((address, client.string("role")?.parse()?))
+                })
+                .collect::<Result<_, String>>()?,
+        };
+        let tokens = match json.get("tokens") {
+            None | Some(JsonValue::Null) => Vec::new(),
+            Some(_) => json
+                .array("tokens")?
+                .iter()
+                .map(|token| {
+                    Ok(ApiToken {
+                        name: token.string("name")?.to_string(),
+                        token: token.string("token")?.to_string(),
+                        role: token.string("role")?.parse()?,
+                    })
+                })
+                .collect::<Result<_, String>>()?,
+        };
+        Ok(AccessPolicy {
+            default_role: json.string("default_role")?.parse()?,
+            clients,
+            operator_power_limit_dbm: json.optional_f32("operator_power_limit_dbm")?,
+            tokens,
+        })
+    }
+}
+
+/// Compares without an early exit, so timing does not reveal how much of a
+/// guessed token was right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
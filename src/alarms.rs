@@ -0,0 +1,358 @@
+//! Threshold alarms evaluated against the telemetry stream.
+//!
+//! Rules can be built in code or parsed from one-line declarations such as
+//!
+//! ```text
+//! high_reflection: reflected_power > 40 for 5s => log, notify, rf_off
+//! hot_pa: temperature > 65 => notify, reduce_power(3)
+//! drift: power_deviation > 1.5 for 10s => log
+//! worn_pa: efficiency < 40 for 30s => notify
+//! ```
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use crate::alerting::{Alert, AlertDispatcher, Severity};
+use crate::calibration::CalibrationTable;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::events::ControllerEvent;
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+use crate::units::parse_duration;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmCondition {
+    ReflectedPowerAbove(f32),
+    TemperatureAbove(f32),
+    /// Forward power differs from the setpoint by more than this many dB while RF is on.
+    PowerDeviationAbove(f32),
+    /// PA efficiency below this many percent while RF is on, see
+    /// [`TelemetrySample::efficiency_percent`].
+    EfficiencyBelow(f32),
+}
+
+impl AlarmCondition {
+    /// Returns the offending value when the condition is met.
+    pub fn check(&self, sample: &TelemetrySample) -> Option<f32> {
+        self.check_calibrated(sample, None)
+    }
+
+    /// Like [`check`](AlarmCondition::check), but measures power deviation
+    /// from the power `calibration` says the setpoint delivers at the
+    /// sample's frequency.
+    pub fn check_calibrated(
+        &self,
+        sample: &TelemetrySample,
+        calibration: Option<&CalibrationTable>,
+    ) -> Option<f32> {
+        match *self {
+            AlarmCondition::ReflectedPowerAbove(limit) => {
+                Some(sample.reflected_dbm).filter(|value| *value > limit)
+            }
+            AlarmCondition::TemperatureAbove(limit) => {
+                sample.temperature_c.filter(|value| *value > limit)
+            }
+            AlarmCondition::PowerDeviationAbove(limit) => {
+                let expected = sample.power_setpoint_dbm
+                    + calibration.map_or(0.0, |table| table.offset_db(sample.frequency_mhz));
+                let deviation = (sample.forward_dbm - expected).abs();
+                Some(deviation).filter(|value| sample.rf_enabled && *value > limit)
+            }
+            AlarmCondition::EfficiencyBelow(limit) => sample
+                .efficiency_percent()
+                .map(|value| value as f32)
+                .filter(|value| *value < limit),
+        }
+    }
+
+    /// The threshold, in the signal's unit.
+    pub fn limit(&self) -> f32 {
+        match *self {
+            AlarmCondition::ReflectedPowerAbove(limit)
+            | AlarmCondition::TemperatureAbove(limit)
+            | AlarmCondition::PowerDeviationAbove(limit)
+            | AlarmCondition::EfficiencyBelow(limit) => limit,
+        }
+    }
+
+    /// Whether both conditions watch the same signal, whatever the limit.
+    pub fn same_signal(&self, other: &AlarmCondition) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+
+    /// Whether this condition watches the same signal as `other` and is met
+    /// at least whenever `other` is.
+    pub fn is_as_strict_as(&self, other: &AlarmCondition) -> bool {
+        match (self, other) {
+            (AlarmCondition::EfficiencyBelow(limit), AlarmCondition::EfficiencyBelow(other)) => {
+                limit >= other
+            }
+            _ => self.same_signal(other) && self.limit() <= other.limit(),
+        }
+    }
+
+    fn describe(&self, value: f32) -> String {
+        match self {
+            AlarmCondition::ReflectedPowerAbove(limit) => {
+                format!("Reflected power {:.2} dBm above {:.2} dBm", value, limit)
+            }
+            AlarmCondition::TemperatureAbove(limit) => {
+                format!("PA temperature {:.1} °C above {:.1} °C", value, limit)
+            }
+            AlarmCondition::PowerDeviationAbove(limit) => {
+                format!(
+                    "PA power deviates {:.2} dB from setpoint (limit {:.2} dB)",
+                    value, limit
+                )
+            }
+            AlarmCondition::EfficiencyBelow(limit) => {
+                format!("PA efficiency {:.1} % below {:.1} %", value, limit)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlarmAction {
+    /// Print the alarm on stderr.
+    Log,
+    /// Dispatch the alarm to the alert sinks and controller event listeners.
+    Notify,
+    /// Lower the power setpoint by this many dB.
+    ReducePower(f32),
+    RfOff,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlarmRule {
+    pub name: String,
+    pub condition: AlarmCondition,
+    /// How long the condition must persist before the alarm fires.
+    pub hold: Duration,
+    pub severity: Severity,
+    pub actions: Vec<AlarmAction>,
+}
+
+impl AlarmRule {
+    /// Parses `name: signal > value [for duration] => action, ...`.
+    ///
+    /// Signals are `reflected_power` (dBm), `temperature` (°C) and
+    /// `power_deviation` (dB), and `efficiency < value` (%); actions are `log`, `notify`,
+    /// `reduce_power(<dB>)` and `rf_off`. Rules that turn RF off or reduce
+    /// power are critical, the others warnings.
+    pub fn parse(line: &str) -> Result<AlarmRule, String> {
+        let invalid = || format!("Invalid alarm rule: {}", line);
+
+        let (name, rest) = line.split_once(':').ok_or_else(invalid)?;
+        let (condition, actions) = rest.split_once("=>").ok_or_else(invalid)?;
+        let (condition, hold) = match condition.split_once(" for ") {
+            Some((condition, hold)) => (condition, parse_duration(hold)?),
+            None => (condition, Duration::ZERO),
+        };
+        let (signal, operator, limit) = match condition.split_once('>') {
+            Some((signal, limit)) => (signal, '>', limit),
+            None => {
+                let (signal, limit) = condition.split_once('<').ok_or_else(invalid)?;
+                (signal, '<', limit)
+            }
+        };
+        let limit: f32 = limit.trim().parse().map_err(|_| invalid())?;
+
+        let condition = match (signal.trim(), operator) {
+            ("reflected_power", '>') => AlarmCondition::ReflectedPowerAbove(limit),
+            ("temperature", '>') => AlarmCondition::TemperatureAbove(limit),
+            ("power_deviation", '>') => AlarmCondition::PowerDeviationAbove(limit),
+            ("efficiency", '<') => AlarmCondition::EfficiencyBelow(limit),
+            ("reflected_power" | "temperature" | "power_deviation" | "efficiency", _) => {
+                return Err(format!(
+                    "Alarm signal {} does not take {}: {}",
+                    signal.trim(),
+                    operator,
+                    line
+                ))
+            }
+            (other, _) => return Err(format!("Unknown alarm signal: {}", other)),
+        };
+
+        let mut parsed_actions = Vec::new();
+        for action in actions.split(',').map(str::trim) {
+            let parsed = match action {
+                "log" => AlarmAction::Log,
+                "notify" => AlarmAction::Notify,
+                "rf_off" => AlarmAction::RfOff,
+                _ => match action
+                    .strip_prefix("reduce_power(")
+                    .and_then(|a| a.strip_suffix(')'))
+                {
+                    Some(db) => AlarmAction::ReducePower(db.trim().parse().map_err(|_| invalid())?),
+                    None => return Err(format!("Unknown alarm action: {}", action)),
+                },
+            };
+            parsed_actions.push(parsed);
+        }
+
+        let critical = parsed_actions
+            .iter()
+            .any(|a| matches!(a, AlarmAction::RfOff | AlarmAction::ReducePower(_)));
+
+        Ok(AlarmRule {
+            name: name.trim().to_string(),
+            condition,
+            hold,
+            severity: if critical {
+                Severity::Critical
+            } else {
+                Severity::Warning
+            },
+            actions: parsed_actions,
+        })
+    }
+}
+
+/// Parses one rule per line, skipping blank lines and `#` comments.
+pub fn parse_rules(text: &str) -> Result<Vec<AlarmRule>, String> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(AlarmRule::parse)
+        .collect()
+}
+
+#[derive(Default)]
+struct RuleState {
+    /// When the condition was first seen in the current excursion.
+    since: Option<Instant>,
+    /// Whether the alarm already fired for the current excursion.
+    fired: bool,
+}
+
+/// Evaluates alarm rules against telemetry samples and carries out their actions.
+///
+/// An alarm fires once per excursion: it re-arms only after its condition
+/// has cleared.
+pub struct AlarmEngine {
+    controller: Controller,
+    dispatcher: AlertDispatcher,
+    rules: Vec<(AlarmRule, RuleState)>,
+    calibration: Option<CalibrationTable>,
+}
+
+impl AlarmEngine {
+    pub fn new(controller: &Controller, dispatcher: AlertDispatcher) -> AlarmEngine {
+        AlarmEngine {
+            controller: controller.clone(),
+            dispatcher,
+            rules: Vec::new(),
+            calibration: None,
+        }
+    }
+
+    pub fn add_rule(&mut self, rule: AlarmRule) {
+        self.rules.push((rule, RuleState::default()));
+    }
+
+    pub fn rules(&self) -> impl Iterator<Item = &AlarmRule> {
+        self.rules.iter().map(|(rule, _)| rule)
+    }
+
+    /// Replaces the rules. A rule that is unchanged keeps its state, so an
+    /// alarm that already fired does not fire again for the same excursion.
+    pub fn set_rules(&mut self, rules: Vec<AlarmRule>) {
+        let mut previous = std::mem::take(&mut self.rules);
+        for rule in rules {
+            let state = match previous.iter().position(|(kept, _)| *kept == rule) {
+                Some(index) => previous.swap_remove(index).1,
+                None => RuleState::default(),
+            };
+            self.rules.push((rule, state));
+        }
+    }
+
+    /// Table that power deviation is measured against, see
+    /// [`AlarmCondition::check_calibrated`].
+    pub fn set_calibration(&mut self, calibration: Option<CalibrationTable>) {
+        self.calibration = calibration;
+    }
+
+    /// Checks every rule against `sample`, runs the actions of newly fired
+    /// alarms and returns their alerts.
+    pub fn evaluate(&mut self, sample: &TelemetrySample) -> Vec<Alert> {
+        let now = Instant::now();
+        let mut fired = Vec::new();
+
+        for (rule, state) in &mut self.rules {
+            let value = match rule
+                .condition
+                .check_calibrated(sample, self.calibration.as_ref())
+            {
+                Some(value) => value,
+                None => {
+                    *state = RuleState::default();
+                    continue;
+                }
+            };
+            let since = *state.since.get_or_insert(now);
+            if state.fired || now.duration_since(since) < rule.hold {
+                continue;
+            }
+            state.fired = true;
+            let alert = Alert::new(&rule.name, rule.severity, &rule.condition.describe(value));
+            fired.push((rule.actions.clone(), sample.power_setpoint_dbm, alert));
+        }
+
+        fired
+            .into_iter()
+            .map(|(actions, setpoint, alert)| {
+                self.run_actions(&actions, setpoint, &alert);
+                alert
+            })
+            .collect()
+    }
+
+    fn run_actions(&self, actions: &[AlarmAction], setpoint_dbm: f32, alert: &Alert) {
+        for action in actions {
+            let result = match action {
+                AlarmAction::Log => {
+                    eprintln!(
+                        "ALARM [{}] {}: {}",
+                        alert.severity, alert.name, alert.message
+                    );
+                    Ok(())
+                }
+                AlarmAction::Notify => {
+                    self.controller.emit(&ControllerEvent::AlarmRaised {
+                        name: alert.name.clone(),
+                        message: alert.message.clone(),
+                    });
+                    self.dispatcher.dispatch(alert.clone());
+                    Ok(())
+                }
+                AlarmAction::ReducePower(db) => self
+                    .controller
+                    .send_as_system(&Command::SetPower(setpoint_dbm - db))
+                    .map(|_| ()),
+                AlarmAction::RfOff => self.controller.safe_state(),
+            };
+            if let Err(e) = result {
+                eprintln!("Alarm {} action {:?} failed: {}", alert.name, action, e);
+            }
+        }
+    }
+
+    /// Wraps the engine as a telemetry listener.
+    pub fn into_listener(self) -> TelemetryListener {
+        AlarmEngine::shared_listener(Arc::new(Mutex::new(self)))
+    }
+
+    /// Listener evaluating a shared engine, whose rules can still be
+    /// changed through `engine` while it runs.
+    pub fn shared_listener(engine: Arc<Mutex<AlarmEngine>>) -> TelemetryListener {
+        Arc::new(move |sample: &TelemetrySample| {
+            if let Ok(mut engine) = engine.lock() {
+                engine.evaluate(sample);
+            }
+        })
+    }
+}
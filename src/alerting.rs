@@ -0,0 +1,338 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    process::{Command, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::events::{ControllerEvent, EventListener};
+use crate::json::json_string;
+
+const NETWORK_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Critical => write!(f, "critical"),
+        }
+    }
+}
+
+/// A fired alarm, as delivered to alert sinks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    /// Alarm name, e.g. `high_reflected_power`.
+    pub name: String,
+    pub severity: Severity,
+    pub message: String,
+    pub timestamp: SystemTime,
+}
+
+impl Alert {
+    pub fn new(name: &str, severity: Severity, message: &str) -> Alert {
+        Alert {
+            name: name.to_string(),
+            severity,
+            message: message.to_string(),
+            timestamp: SystemTime::now(),
+        }
+    }
+
+    /// JSON payload posted to webhooks. The `text` field makes Slack and
+    /// Teams incoming webhooks render the alert without further mapping.
+    pub fn to_json(&self) -> String {
+        let timestamp = self
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format!(
+            "{{\"alarm\":{},\"severity\":{},\"message\":{},\"timestamp\":{},\"text\":{}}}",
+            json_string(&self.name),
+            json_string(&self.severity.to_string()),
+            json_string(&self.message),
+            timestamp,
+            json_string(&format!(
+                "[{}] {}: {}",
+                self.severity, self.name, self.message
+            ))
+        )
+    }
+}
+
+/// Destination for fired alarms.
+pub trait AlertSink: Send + Sync {
+    fn send(&self, alert: &Alert) -> io::Result<()>;
+}
+
+/// POSTs the alert JSON to a webhook URL.
+///
+/// `http://` URLs are posted directly; `https://` URLs are handed to `curl`,
+/// which must then be installed on the host.
+pub struct WebhookSink {
+    pub url: String,
+}
+
+impl AlertSink for WebhookSink {
+    fn send(&self, alert: &Alert) -> io::Result<()> {
+        post_json(&self.url, &alert.to_json())
+    }
+}
+
+/// POSTs a JSON body, over `curl` for `https://` URLs.
+pub(crate) fn post_json(url: &str, body: &str) -> io::Result<()> {
+    if url.starts_with("https://") {
+        post_with_curl(url, body)
+    } else {
+        post_http(url, body)
+    }
+}
+
+fn post_with_curl(url: &str, body: &str) -> io::Result<()> {
+    let status = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--max-time", "10"])
+        .args([
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            body,
+            url,
+        ])
+        .stdout(Stdio::null())
+        .status()?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("curl exited with {}", status)))
+    }
+}
+
+fn post_http(url: &str, body: &str) -> io::Result<()> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Unsupported URL scheme"))?;
+    let (authority, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let address = if authority.contains(':') {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+
+    let mut stream = TcpStream::connect(address)?;
+    stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+    stream.set_write_timeout(Some(NETWORK_TIMEOUT))?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        body.len(),
+        body
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream.take(1024)).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "Server rejected the request: {}",
+            status_line.trim()
+        ))),
+    }
+}
+
+/// Mails the alert through a plain SMTP relay (no authentication or TLS),
+/// as found on most lab and factory networks.
+pub struct SmtpSink {
+    /// `host:port` of the relay.
+    pub server: String,
+    pub from: String,
+    pub to: Vec<String>,
+}
+
+impl AlertSink for SmtpSink {
+    fn send(&self, alert: &Alert) -> io::Result<()> {
+        let stream = TcpStream::connect(&self.server)?;
+        stream.set_read_timeout(Some(NETWORK_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        smtp_expect(&mut reader, "220")?;
+        smtp_command(&mut writer, &mut reader, "HELO microwave-controller", "250")?;
+        smtp_command(
+            &mut writer,
+            &mut reader,
+            &format!("MAIL FROM:<{}>", self.from),
+            "250",
+        )?;
+        for recipient in &self.to {
+            smtp_command(
+                &mut writer,
+                &mut reader,
+                &format!("RCPT TO:<{}>", recipient),
+                "250",
+            )?;
+        }
+        smtp_command(&mut writer, &mut reader, "DATA", "354")?;
+        let message = format!(
+            "From: {}\r\nTo: {}\r\nSubject: [{}] {}\r\n\r\n{}\r\n.",
+            self.from,
+            self.to.join(", "),
+            alert.severity,
+            alert.name,
+            alert.message.replace("\n.", "\n..")
+        );
+        smtp_command(&mut writer, &mut reader, &message, "250")?;
+        smtp_command(&mut writer, &mut reader, "QUIT", "221")
+    }
+}
+
+fn smtp_command(
+    writer: &mut TcpStream,
+    reader: &mut BufReader<TcpStream>,
+    line: &str,
+    expected: &str,
+) -> io::Result<()> {
+    write!(writer, "{}\r\n", line)?;
+    smtp_expect(reader, expected)
+}
+
+fn smtp_expect(reader: &mut BufReader<TcpStream>, expected: &str) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "SMTP server closed the connection",
+            ));
+        }
+        // Multi-line replies use `250-` for every line but the last.
+        if line.len() > 3 && line.as_bytes()[3] == b'-' {
+            continue;
+        }
+        if line.starts_with(expected) {
+            return Ok(());
+        }
+        return Err(io::Error::other(format!("SMTP error: {}", line.trim())));
+    }
+}
+
+/// Fans alerts out to every sink on a background thread, so a slow webhook
+/// never stalls the thread that raised the alarm.
+#[derive(Clone, Default)]
+pub struct AlertDispatcher {
+    sinks: Vec<Arc<dyn AlertSink>>,
+}
+
+impl AlertDispatcher {
+    pub fn new() -> AlertDispatcher {
+        AlertDispatcher::default()
+    }
+
+    pub fn add_sink(&mut self, sink: Arc<dyn AlertSink>) {
+        self.sinks.push(sink);
+    }
+
+    pub fn dispatch(&self, alert: Alert) {
+        let sinks = self.sinks.clone();
+        thread::spawn(move || {
+            for sink in sinks {
+                if let Err(e) = sink.send(&alert) {
+                    eprintln!("Failed to deliver alert {}: {:?}", alert.name, e);
+                }
+            }
+        });
+    }
+
+    /// Event listener raising alerts for device faults, cleared errors,
+    /// interlock trips, connection loss, heartbeat misses, changes of
+    /// single-writer control and `timeout_limit` command timeouts within
+    /// `window`.
+    pub fn event_listener(&self, timeout_limit: usize, window: Duration) -> EventListener {
+        let dispatcher = self.clone();
+        let timeouts: Mutex<VecDeque<Instant>> = Mutex::new(VecDeque::new());
+
+        Arc::new(move |event: &ControllerEvent| {
+            let alert = match event {
+                ControllerEvent::DeviceFault(reply) => {
+                    Alert::new("device_fault", Severity::Critical, reply)
+                }
+                ControllerEvent::ErrorsCleared(error) => {
+                    Alert::new("errors_cleared", Severity::Warning, error)
+                }
+                ControllerEvent::InterlockTripped(name) => {
+                    Alert::new("interlock_tripped", Severity::Critical, name)
+                }
+                ControllerEvent::ConnectionLost(e) => {
+                    Alert::new("connection_lost", Severity::Critical, e)
+                }
+                ControllerEvent::CommandTimeout(command) => {
+                    let mut timeouts = match timeouts.lock() {
+                        Ok(timeouts) => timeouts,
+                        Err(_) => return,
+                    };
+                    let now = Instant::now();
+                    timeouts.push_back(now);
+                    while timeouts
+                        .front()
+                        .is_some_and(|first| now.duration_since(*first) > window)
+                    {
+                        timeouts.pop_front();
+                    }
+                    if timeouts.len() < timeout_limit {
+                        return;
+                    }
+                    let count = timeouts.len();
+                    timeouts.clear();
+                    Alert::new(
+                        "repeated_timeouts",
+                        Severity::Warning,
+                        &format!(
+                            "{} timeouts within {:?}, last on {}",
+                            count, window, command
+                        ),
+                    )
+                }
+                ControllerEvent::LinkDegraded(_) => {
+                    Alert::new("link_degraded", Severity::Warning, &event.to_string())
+                }
+                ControllerEvent::LinkRestored => {
+                    Alert::new("link_restored", Severity::Info, "The board answers again")
+                }
+                ControllerEvent::PortReopened(_) => {
+                    Alert::new("port_reopened", Severity::Info, &event.to_string())
+                }
+                ControllerEvent::PowerDerated { reduction_db, .. } => Alert::new(
+                    "power_derated",
+                    match *reduction_db > 0.0 {
+                        true => Severity::Warning,
+                        false => Severity::Info,
+                    },
+                    &event.to_string(),
+                ),
+                ControllerEvent::ControlChanged { .. } => {
+                    Alert::new("control_changed", Severity::Info, &event.to_string())
+                }
+                // Alarms dispatch their own alerts.
+                ControllerEvent::AlarmRaised { .. } => return,
+            };
+            dispatcher.dispatch(alert);
+        })
+    }
+}
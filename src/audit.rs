@@ -0,0 +1,95 @@
+//! Tamper-evident record of every command sent to the board.
+//!
+//! Each line is `timestamp, actor, command, outcome, hash`, tab separated.
+//! The hash is the SHA-256 of the previous line's hash followed by this
+//! line's other fields, so editing, reordering or deleting a line breaks
+//! every hash after it. The first line chains from [`GENESIS_HASH`].
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::sha256::sha256_hex;
+use crate::units::format_timestamp;
+
+/// Hash the first entry of a log chains from.
+pub const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Append-only audit log, usually attached with [`Controller::set_audit_log`](crate::Controller::set_audit_log).
+pub struct AuditLog {
+    file: File,
+    last_hash: String,
+}
+
+impl AuditLog {
+    /// Opens or creates the log, continuing the chain of an existing file.
+    /// Fails if the existing entries do not verify.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<AuditLog> {
+        let path = path.as_ref();
+        let last_hash = match fs::read_to_string(path) {
+            Ok(text) => {
+                verify(&text)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?
+                    .last_hash
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => GENESIS_HASH.to_string(),
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(AuditLog { file, last_hash })
+    }
+
+    /// Appends one entry and syncs it to disk.
+    pub fn append(&mut self, actor: &str, command: &str, outcome: &str) -> io::Result<()> {
+        let fields = [
+            format_timestamp(SystemTime::now()),
+            field(actor),
+            field(command),
+            field(outcome),
+        ]
+        .join("\t");
+        let hash = chain_hash(&self.last_hash, &fields);
+        self.file
+            .write_all(format!("{}\t{}\n", fields, hash).as_bytes())?;
+        self.file.sync_data()?;
+        self.last_hash = hash;
+        Ok(())
+    }
+}
+
+/// Result of a successful [`verify`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditSummary {
+    pub entries: usize,
+    pub last_hash: String,
+}
+
+/// Checks the hash chain of a whole log, reporting the first line that
+/// does not verify.
+pub fn verify(log: &str) -> Result<AuditSummary, String> {
+    let mut last_hash = GENESIS_HASH.to_string();
+    let mut entries = 0;
+    for (number, line) in log.lines().enumerate() {
+        let (fields, hash) = line
+            .rsplit_once('\t')
+            .ok_or_else(|| format!("Malformed audit line {}", number + 1))?;
+        if chain_hash(&last_hash, fields) != hash {
+            return Err(format!("Audit chain broken at line {}", number + 1));
+        }
+        last_hash = hash.to_string();
+        entries += 1;
+    }
+    Ok(AuditSummary { entries, last_hash })
+}
+
+fn chain_hash(previous: &str, fields: &str) -> String {
+    sha256_hex(format!("{}\n{}", previous, fields).as_bytes())
+}
+
+/// Keeps a value on one line and in one field.
+fn field(text: &str) -> String {
+    text.trim().replace(['\t', '\r', '\n'], " ")
+}
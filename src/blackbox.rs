@@ -0,0 +1,188 @@
+//! Flight recorder: the last seconds of command lines, replies, telemetry
+//! and controller events, kept in memory and written to a file when the
+//! board faults, an interlock trips, the link is lost or the process
+//! panics.
+//!
+//! Dumps are text, one record per line, oldest first:
+//!
+//! ```text
+//! 2026-10-14T15:54:11.068Z TX $PWRS,0,40.00 RX $PWRS,0,ERR,... (1.2ms, local)
+//! 2026-10-14T15:54:11.070Z SAMPLE 2026-10-14T15:54:11.070Z,2450.00,...
+//! 2026-10-14T15:54:11.071Z EVENT Device fault: $PWRS,0,ERR,...
+//! ```
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::controller::Controller;
+use crate::error::ControllerError;
+use crate::events::{ControllerEvent, Exchange};
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+use crate::units::format_timestamp;
+
+/// Records kept at most, whatever the window, so a runaway poll loop
+/// cannot exhaust memory.
+const MAX_RECORDS: usize = 100_000;
+
+#[derive(Debug, Clone)]
+enum Record {
+    Exchange(Exchange),
+    Sample(TelemetrySample),
+    Event(ControllerEvent),
+}
+
+/// Rolling buffer of the last `window` of activity. Clones share it.
+#[derive(Clone)]
+pub struct BlackBox {
+    window: Duration,
+    directory: PathBuf,
+    state: Arc<Mutex<Recording>>,
+}
+
+struct Recording {
+    records: VecDeque<(SystemTime, Record)>,
+    last_dump: Option<SystemTime>,
+}
+
+impl BlackBox {
+    /// Keeps the last `window` and writes dumps into `directory`.
+    pub fn new(directory: impl AsRef<Path>, window: Duration) -> BlackBox {
+        BlackBox {
+            window,
+            directory: directory.as_ref().to_path_buf(),
+            state: Arc::new(Mutex::new(Recording {
+                records: VecDeque::new(),
+                last_dump: None,
+            })),
+        }
+    }
+
+    /// Records every exchange and event of `controller` and dumps on a
+    /// device fault, an interlock trip or a lost link.
+    pub fn attach(&self, controller: &Controller) -> Result<(), ControllerError> {
+        let recorder = self.clone();
+        controller.observe_exchanges(Arc::new(move |exchange: &Exchange| {
+            recorder.record(exchange.started, Record::Exchange(exchange.clone()))
+        }))?;
+        let recorder = self.clone();
+        controller.subscribe(Arc::new(move |event: &ControllerEvent| {
+            recorder.record(SystemTime::now(), Record::Event(event.clone()));
+            let trigger = matches!(
+                event,
+                ControllerEvent::DeviceFault(_)
+                    | ControllerEvent::InterlockTripped(_)
+                    | ControllerEvent::ConnectionLost(_)
+            );
+            if trigger {
+                recorder.dump_after_fault(&event.to_string());
+            }
+        }))
+    }
+
+    /// Listener recording telemetry samples.
+    pub fn telemetry_listener(&self) -> TelemetryListener {
+        let recorder = self.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            recorder.record(sample.timestamp, Record::Sample(*sample))
+        })
+    }
+
+    /// Dumps the buffer when the process panics, after the default panic
+    /// message.
+    pub fn dump_on_panic(&self) {
+        let recorder = self.clone();
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            previous(info);
+            match recorder.dump(&format!("Panic: {}", info)) {
+                Ok(path) => eprintln!("Black box written to {}", path.display()),
+                Err(e) => eprintln!("Failed to write the black box: {:?}", e),
+            }
+        }));
+    }
+
+    /// Writes the buffer to a new file in the dump directory, headed by
+    /// `reason`, and returns its path.
+    pub fn dump(&self, reason: &str) -> io::Result<PathBuf> {
+        let now = SystemTime::now();
+        let records: Vec<(SystemTime, Record)> = match self.state.lock() {
+            Ok(mut state) => {
+                state.last_dump = Some(now);
+                state.records.iter().cloned().collect()
+            }
+            // Dumping is most useful after a panic, which may have poisoned the lock.
+            Err(poisoned) => poisoned.into_inner().records.iter().cloned().collect(),
+        };
+        fs::create_dir_all(&self.directory)?;
+        let stamp: String = format_timestamp(now)
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect();
+        let path = self.directory.join(format!("blackbox-{}.log", stamp));
+        let mut file = BufWriter::new(File::create(&path)?);
+        writeln!(
+            file,
+            "# {} {}",
+            format_timestamp(now),
+            reason.replace('\n', " ")
+        )?;
+        for (time, record) in &records {
+            write!(file, "{} ", format_timestamp(*time))?;
+            match record {
+                Record::Exchange(exchange) => match &exchange.reply {
+                    Ok(reply) => writeln!(
+                        file,
+                        "TX {} RX {} ({:.1?}, {})",
+                        exchange.command, reply, exchange.duration, exchange.actor
+                    )?,
+                    Err(e) => writeln!(
+                        file,
+                        "TX {} FAILED {} ({:.1?}, {})",
+                        exchange.command, e, exchange.duration, exchange.actor
+                    )?,
+                },
+                Record::Sample(sample) => writeln!(file, "SAMPLE {}", sample.to_csv_row())?,
+                Record::Event(event) => writeln!(file, "EVENT {}", event)?,
+            }
+        }
+        file.flush()?;
+        Ok(path)
+    }
+
+    /// Faults tend to come in bursts; one dump per window covers a burst.
+    fn dump_after_fault(&self, reason: &str) {
+        let recent = self.state.lock().is_ok_and(|state| {
+            state
+                .last_dump
+                .and_then(|last| last.elapsed().ok())
+                .is_some_and(|since| since < self.window)
+        });
+        if recent {
+            return;
+        }
+        match self.dump(reason) {
+            Ok(path) => eprintln!("{}; black box written to {}", reason, path.display()),
+            Err(e) => eprintln!("Failed to write the black box: {:?}", e),
+        }
+    }
+
+    fn record(&self, time: SystemTime, record: Record) {
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.records.push_back((time, record));
+        while state.records.len() > MAX_RECORDS
+            || state.records.front().is_some_and(|(oldest, _)| {
+                time.duration_since(*oldest)
+                    .is_ok_and(|age| age > self.window)
+            })
+        {
+            state.records.pop_front();
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! BLE-to-UART bridges exposing the Nordic UART Service (NUS).
+//!
+//! The link is driven through BlueZ's `gatttool` in interactive mode, so it
+//! needs a Linux host with the bluez tools installed. Command bytes are
+//! written to the NUS RX characteristic and replies arrive as notifications
+//! on the TX characteristic.
+
+use std::{
+    collections::VecDeque,
+    io::{self, BufRead, BufReader, Read, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use crate::error::ControllerError;
+use crate::transport::Transport;
+
+pub const NUS_SERVICE_UUID: &str = "6e400001-b5a3-f393-e0a9-e50e24dcca9e";
+/// Characteristic the host writes to.
+pub const NUS_RX_UUID: &str = "6e400002-b5a3-f393-e0a9-e50e24dcca9e";
+/// Characteristic the bridge notifies on.
+pub const NUS_TX_UUID: &str = "6e400003-b5a3-f393-e0a9-e50e24dcca9e";
+
+/// Payload of a write with the default ATT MTU of 23 bytes.
+const MAX_WRITE_LEN: usize = 20;
+
+/// NUS link to a BLE-to-UART bridge, addressed by its MAC address.
+pub struct BleTransport {
+    address: String,
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+    rx_handle: u16,
+    tx_handle: u16,
+    received: VecDeque<u8>,
+    timeout: Duration,
+}
+
+impl BleTransport {
+    /// Connects to the bridge at `address` and subscribes to its TX
+    /// characteristic. `timeout` bounds connection setup and each read.
+    pub fn connect(address: &str, timeout: Duration) -> Result<BleTransport, ControllerError> {
+        let connection_error =
+            |message: String| ControllerError::Connection(format!("BLE {}: {}", address, message));
+
+        let mut child = Command::new("gatttool")
+            .args(["-b", address, "-t", "random", "-I"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| connection_error(format!("Failed to start gatttool: {:?}", e)))?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                match line {
+                    Ok(line) => {
+                        if sender.send(line).is_err() {
+                            break;
+                        }
+                    }
+                    Err(_) => break,
+                }
+            }
+        });
+
+        let mut transport = BleTransport {
+            address: address.to_string(),
+            child,
+            stdin,
+            lines,
+            rx_handle: 0,
+            tx_handle: 0,
+            received: VecDeque::new(),
+            timeout,
+        };
+
+        transport
+            .command("connect")
+            .map_err(|e| connection_error(format!("{:?}", e)))?;
+        transport
+            .wait_for(timeout, |line| {
+                if line.contains("Connection successful") {
+                    Some(Ok(()))
+                } else if line.contains("Error") {
+                    Some(Err(line.trim().to_string()))
+                } else {
+                    None
+                }
+            })
+            .map_err(connection_error)?;
+
+        transport
+            .command("characteristics")
+            .map_err(|e| connection_error(format!("{:?}", e)))?;
+        let (mut rx_handle, mut tx_handle) = (None, None);
+        transport
+            .wait_for(timeout, |line| {
+                if let Some(handle) = value_handle(line) {
+                    if line.contains(NUS_RX_UUID) {
+                        rx_handle = Some(handle);
+                    } else if line.contains(NUS_TX_UUID) {
+                        tx_handle = Some(handle);
+                    }
+                }
+                (rx_handle.is_some() && tx_handle.is_some()).then_some(Ok(()))
+            })
+            .map_err(|_| connection_error("Nordic UART service not found".to_string()))?;
+        transport.rx_handle = rx_handle.unwrap_or_default();
+        transport.tx_handle = tx_handle.unwrap_or_default();
+
+        // The client characteristic configuration descriptor follows the TX value handle.
+        let cccd = transport.tx_handle + 1;
+        transport
+            .command(&format!("char-write-req 0x{:04x} 0100", cccd))
+            .map_err(|e| connection_error(format!("{:?}", e)))?;
+        println!("Successfully connected to BLE bridge {}", address);
+        Ok(transport)
+    }
+
+    fn command(&mut self, command: &str) -> io::Result<()> {
+        writeln!(self.stdin, "{}", command)?;
+        self.stdin.flush()
+    }
+
+    /// Feeds gatttool output to `check` until it returns a result,
+    /// buffering any notifications seen meanwhile.
+    fn wait_for(
+        &mut self,
+        timeout: Duration,
+        mut check: impl FnMut(&str) -> Option<Result<(), String>>,
+    ) -> Result<(), String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(remaining) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => return Err("Timed out".to_string()),
+                Err(RecvTimeoutError::Disconnected) => return Err("gatttool exited".to_string()),
+            };
+            self.accept_notification(&line);
+            if let Some(result) = check(&line) {
+                return result;
+            }
+        }
+    }
+
+    fn accept_notification(&mut self, line: &str) {
+        if let Some((handle, bytes)) = parse_notification(line) {
+            if handle == self.tx_handle {
+                self.received.extend(bytes);
+            }
+        }
+    }
+}
+
+impl Read for BleTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.received.is_empty() {
+            match self.lines.recv_timeout(self.timeout) {
+                Ok(line) => self.accept_notification(&line),
+                Err(RecvTimeoutError::Timeout) => return Err(io::ErrorKind::TimedOut.into()),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(io::Error::new(io::ErrorKind::BrokenPipe, "gatttool exited"))
+                }
+            }
+        }
+        let count = buf.len().min(self.received.len());
+        for (slot, byte) in buf.iter_mut().zip(self.received.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for BleTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for chunk in buf.chunks(MAX_WRITE_LEN) {
+            let hex: String = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            self.command(&format!("char-write-cmd 0x{:04x} {}", self.rx_handle, hex))?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdin.flush()
+    }
+}
+
+impl Transport for BleTransport {
+    fn name(&self) -> Option<String> {
+        Some(format!("ble:{}", self.address))
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        while let Ok(line) = self.lines.try_recv() {
+            self.accept_notification(&line);
+        }
+        Ok(self.received.drain(..).collect())
+    }
+}
+
+impl Drop for BleTransport {
+    fn drop(&mut self) {
+        let _ = self.command("disconnect");
+        let _ = self.command("exit");
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Parses `... char value handle: 0x000e, uuid: ...` from a characteristics listing.
+fn value_handle(line: &str) -> Option<u16> {
+    let rest = &line[line.find("char value handle: 0x")? + "char value handle: 0x".len()..];
+    u16::from_str_radix(rest.get(..4)?, 16).ok()
+}
+
+/// Parses `Notification handle = 0x000b value: 24 46 43 ...`.
+fn parse_notification(line: &str) -> Option<(u16, Vec<u8>)> {
+    let rest = &line[line.find("Notification handle = 0x")? + "Notification handle = 0x".len()..];
+    let handle = u16::from_str_radix(rest.get(..4)?, 16).ok()?;
+    let value = &rest[rest.find("value:")? + "value:".len()..];
+    let bytes = value
+        .split_whitespace()
+        .map(|byte| u8::from_str_radix(byte, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    Some((handle, bytes))
+}
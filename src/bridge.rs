@@ -0,0 +1,142 @@
+//! Line-protocol TCP front end: clients send the board's own command lines
+//! and get its replies back, so a [`Controller`] on another host connects
+//! to the daemon with [`Controller::connect_tcp`] as if to a
+//! serial-to-Ethernet bridge, while the daemon keeps its interlocks, access
+//! policy and single-writer arbitration.
+//!
+//! Commands refused by the daemon are answered with an error reply in the
+//! board's format, `<mnemonic>,0,ERR,<reason>`.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::access::{AccessPolicy, Role};
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+
+/// Longest line read from a client, terminator included; a client sending a
+/// longer one is disconnected rather than buffered without bound.
+const MAX_LINE: usize = 1024;
+
+#[derive(Clone)]
+pub struct LineBridge {
+    controller: Controller,
+    access: Arc<AccessPolicy>,
+}
+
+impl LineBridge {
+    pub fn new(controller: Controller) -> LineBridge {
+        LineBridge {
+            controller,
+            access: Arc::new(AccessPolicy::default()),
+        }
+    }
+
+    pub fn with_access_policy(mut self, access: AccessPolicy) -> LineBridge {
+        self.access = Arc::new(access);
+        self
+    }
+
+    /// Reply to one command line on behalf of a client with `role`, with
+    /// its terminator.
+    pub fn handle_line(&self, controller: &Controller, role: Role, line: &str) -> String {
+        let mnemonic = line.split(',').next().unwrap_or_default().trim();
+        let result = line
+            .parse::<Command>()
+            .map_err(|e| ControllerError::InvalidParameter(e.to_string()))
+            .and_then(|command| {
+                self.access.authorize(role, &command)?;
+                controller.send(&command)
+            });
+        match result {
+            Ok(reply) => format!("{}\r\n", reply.trim_end()),
+            Err(ControllerError::Device(error)) => format!("{}\r\n", error.reply),
+            Err(e) => format!("{},0,ERR,{}\r\n", mnemonic, e),
+        }
+    }
+
+    /// Serves clients on `addr` until `cancel` is cancelled, each on its own
+    /// thread.
+    pub fn serve<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("Line bridge: {:?}", e));
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        println!(
+            "Line bridge listening on {}",
+            listener.local_addr().map_err(io_error)?
+        );
+
+        while !cancel.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let bridge = self.clone();
+                    let role = self.access.role_for(peer.ip());
+                    let controller = self.controller.with_actor(&format!("bridge {}", peer));
+                    println!("Bridge client connected: {} ({})", peer, role);
+                    let cancel = cancel.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = bridge.serve_client(&controller, role, stream, &cancel) {
+                            eprintln!("Bridge client {}: {:?}", peer, e);
+                        }
+                        let _ = controller.release_control();
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cancel.sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_client(
+        &self,
+        controller: &Controller,
+        role: Role,
+        stream: TcpStream,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while !cancel.is_cancelled() {
+            if line.len() >= MAX_LINE {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "command line too long",
+                ));
+            }
+            let limit = (MAX_LINE - line.len()) as u64;
+            match (&mut reader).take(limit).read_line(&mut line) {
+                Ok(0) => return Ok(()),
+                Ok(_) if !line.ends_with('\n') => continue,
+                Ok(_) => {}
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+            let command = line.trim();
+            if !command.is_empty() {
+                writer.write_all(self.handle_line(controller, role, command).as_bytes())?;
+            }
+            line.clear();
+        }
+        Ok(())
+    }
+}
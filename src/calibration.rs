@@ -0,0 +1,133 @@
+use std::{fs, io, path::Path};
+
+use crate::json::{FromJson, JsonValue, ToJson};
+
+/// Frequency dependent gain correction of the PA output.
+///
+/// Each entry records how far the delivered power deviates from the setpoint
+/// at a given frequency (`delivered = setpoint + offset`). Offsets between
+/// entries are linearly interpolated; outside the table the nearest entry is used.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct CalibrationTable {
+    /// `(frequency_mhz, offset_db)` pairs sorted by frequency.
+    points: Vec<(f32, f32)>,
+}
+
+impl CalibrationTable {
+    pub fn new(mut points: Vec<(f32, f32)>) -> CalibrationTable {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        CalibrationTable { points }
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+
+    /// Gain offset in dB at `frequency_mhz`, or `0.0` for an empty table.
+    pub fn offset_db(&self, frequency_mhz: f32) -> f32 {
+        let (first, last) = match (self.points.first(), self.points.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return 0.0,
+        };
+        if frequency_mhz <= first.0 {
+            return first.1;
+        }
+        if frequency_mhz >= last.0 {
+            return last.1;
+        }
+        for pair in self.points.windows(2) {
+            let ((f0, o0), (f1, o1)) = (pair[0], pair[1]);
+            if frequency_mhz <= f1 {
+                if f1 == f0 {
+                    return o1;
+                }
+                return o0 + (o1 - o0) * (frequency_mhz - f0) / (f1 - f0);
+            }
+        }
+        last.1
+    }
+
+    /// Setpoint that delivers `target_dbm` at `frequency_mhz`.
+    pub fn corrected_setpoint(&self, frequency_mhz: f32, target_dbm: f32) -> f32 {
+        target_dbm - self.offset_db(frequency_mhz)
+    }
+
+    /// Parses `frequency_mhz,offset_db` lines. A header line and `#` comments are skipped.
+    pub fn from_csv(csv: &str) -> Result<CalibrationTable, String> {
+        let mut points = Vec::new();
+        for (number, line) in csv.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("frequency") {
+                continue;
+            }
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let parsed = match fields.as_slice() {
+                [frequency, offset] => frequency
+                    .parse::<f32>()
+                    .ok()
+                    .zip(offset.parse::<f32>().ok()),
+                _ => None,
+            };
+            match parsed {
+                Some(point) => points.push(point),
+                None => {
+                    return Err(format!(
+                        "Invalid calibration entry on line {}: {}",
+                        number + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        Ok(CalibrationTable::new(points))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frequency_mhz,offset_db\n");
+        for (frequency_mhz, offset_db) in &self.points {
+            csv.push_str(&format!("{:.3},{:.3}\n", frequency_mhz, offset_db));
+        }
+        csv
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<CalibrationTable> {
+        let csv = fs::read_to_string(path)?;
+        CalibrationTable::from_csv(&csv).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+}
+
+impl ToJson for CalibrationTable {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::Array(
+            self.points
+                .iter()
+                .map(|(frequency_mhz, offset_db)| {
+                    JsonValue::object()
+                        .with("frequency_mhz", *frequency_mhz)
+                        .with("offset_db", *offset_db)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl FromJson for CalibrationTable {
+    fn from_json(json: &JsonValue) -> Result<CalibrationTable, String> {
+        let entries = json
+            .as_array()
+            .ok_or("Calibration table must be an array")?;
+        let points = entries
+            .iter()
+            .map(|entry| Ok((entry.f32("frequency_mhz")?, entry.f32("offset_db")?)))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(CalibrationTable::new(points))
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU8, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+
+/// Longest a cancellable wait sleeps before re-checking its token.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+const RUNNING: u8 = 0;
+const PAUSED_HOLD_RF: u8 = 1;
+const PAUSED_DISABLE_RF: u8 = 2;
+
+/// What happens to the RF output while an operation is paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseMode {
+    /// Keep transmitting at the current setpoint.
+    HoldRf,
+    /// Disable RF until resumed, then re-enable it.
+    DisableRf,
+}
+
+/// Cloneable flag used to stop or pause sweeps, ramps and recipes mid-flight.
+///
+/// Keep one clone in the operation and hand the others to whatever may need
+/// to abort it (a UI stop button, an interlock monitor, a signal handler).
+#[derive(Clone, Default, Debug)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    pause: Arc<AtomicU8>,
+}
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Asks the operation to pause at its next checkpoint (between sweep points or recipe steps).
+    pub fn pause(&self, mode: PauseMode) {
+        let state = match mode {
+            PauseMode::HoldRf => PAUSED_HOLD_RF,
+            PauseMode::DisableRf => PAUSED_DISABLE_RF,
+        };
+        self.pause.store(state, Ordering::SeqCst);
+    }
+
+    pub fn resume(&self) {
+        self.pause.store(RUNNING, Ordering::SeqCst);
+    }
+
+    pub fn pause_mode(&self) -> Option<PauseMode> {
+        match self.pause.load(Ordering::SeqCst) {
+            PAUSED_HOLD_RF => Some(PauseMode::HoldRf),
+            PAUSED_DISABLE_RF => Some(PauseMode::DisableRf),
+            _ => None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pause_mode().is_some()
+    }
+
+    /// Sleeps for `duration`, returning early with `false` if the token is cancelled.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        loop {
+            if self.is_cancelled() {
+                return false;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return true;
+            }
+            std::thread::sleep((deadline - now).min(CANCEL_POLL_INTERVAL));
+        }
+    }
+
+    /// Operation checkpoint: blocks while the token is paused.
+    ///
+    /// If paused with [`PauseMode::DisableRf`], RF is disabled for the pause
+    /// and `restore` is sent on resume to bring the output back to where the
+    /// operation left off.
+    pub fn checkpoint(
+        &self,
+        controller: &Controller,
+        restore: &[Command],
+    ) -> Result<(), ControllerError> {
+        let mut rf_disabled = false;
+        while let Some(mode) = self.pause_mode() {
+            if self.is_cancelled() {
+                return Err(ControllerError::Cancelled);
+            }
+            if mode == PauseMode::DisableRf && !rf_disabled {
+                controller.send(&Command::RfDisable)?;
+                rf_disabled = true;
+            }
+            std::thread::sleep(CANCEL_POLL_INTERVAL);
+        }
+        if self.is_cancelled() {
+            return Err(ControllerError::Cancelled);
+        }
+        if rf_disabled {
+            for command in restore {
+                controller.send(command)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Holds the current output for `duration` of unpaused time, honouring
+    /// pauses through [`CancellationToken::checkpoint`].
+    pub fn hold(
+        &self,
+        controller: &Controller,
+        duration: Duration,
+        restore: &[Command],
+    ) -> Result<(), ControllerError> {
+        let mut remaining = duration;
+        while !remaining.is_zero() {
+            self.checkpoint(controller, restore)?;
+            let slice = remaining.min(CANCEL_POLL_INTERVAL);
+            if !self.sleep(slice) {
+                return Err(ControllerError::Cancelled);
+            }
+            remaining -= slice;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,783 @@
+//! `mwctl` subcommands. Each submodule exposes `run(args) -> Result<(), String>`.
+
+use std::{
+    io::{self, BufRead, IsTerminal, Write},
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use microwave_controller::{
+    controller_responses::parse_value,
+    downsample::{Downsampled, DownsampledListener, AGGREGATE_CSV_HEADER},
+    interlock::{spawn_interlock_monitor, SerialLine, SerialLineInterlock},
+    safety,
+    telemetry::{csv_logger, TelemetryListener, CSV_HEADER},
+    units::{parse_duration, parse_power},
+    AccessPolicy, AuditLog, BlackBox, CancellationToken, Command, Controller, DeratingPolicy,
+    DeviceProfile, Downsampler, Downsampling, FanPolicy, Interlock, OtlpExporter, PortFilter,
+    RotatingWriter, RotationPolicy, SerialSettings, Simulator, TimeoutBounds,
+};
+
+pub mod audit;
+pub mod batch;
+pub mod calibrate;
+pub mod compare;
+pub mod config;
+pub mod daemon;
+pub mod discover;
+pub mod dll;
+pub mod export;
+pub mod expose;
+pub mod fleet;
+pub mod history;
+pub mod modbus;
+pub mod monitor;
+pub mod ports;
+pub mod replay;
+pub mod run;
+#[cfg(unix)]
+pub mod simulate;
+pub mod soak;
+pub mod sweep;
+pub mod tune;
+pub mod verify;
+
+pub const USAGE: &str = "Usage: mwctl <command> [options]
+
+Commands:
+  audit <audit.log>
+      Verify an audit log's hash chain and report the first altered line.
+  batch [<commands.txt> | -] [--continue]
+      Run one command per line from the file or stdin and print each reply.
+      Lines are raw ($FCS,0,2450.00) or typed: identity, version, status,
+      clear, temperature, supply-voltage, supply-current, fan [<%>],
+      pa-power, frequency [<MHz>], power [<power>], rf on|off, dll on|off,
+      lockout [on|off], sleep <interval>; # starts a comment. Every line is
+      checked before the first runs. The batch stops at the first failure and
+      disables RF unless --continue is given.
+  calibrate --start <MHz> --stop <MHz> --step <MHz> [--power <power,...>] [--dwell <ms>]
+            [--coupling <dB>] (--meter-tcp <addr> | --meter-port <name>) [--output <file>]
+      Measure the calibration table against a SCPI power meter.
+  compare <baseline.csv> <sweep.csv>...
+      Fit the resonance of sweeps saved with `sweep --output`, oldest first,
+      and report each one's frequency shift, depth change and bandwidth
+      change against the baseline.
+  config validate [--profile <profile.json>] [--calibration <table.csv>] [--access <policy.json>]
+                  [<recipe.json>...]
+      Parse the given files and check them against the board's limits and
+      each other: parameters out of range, RF enabled before a frequency is
+      set, DLL presets outside their band, recipes above the operator power
+      limit or outside the calibrated band. Exits non-zero on any error.
+  config export <device.json> [--calibration <table.csv>] [--access <policy.json>]
+  config import <device.json> [--calibration <table.csv>] [--dry-run] [--force]
+      Save the board's identity, firmware, frequency, power setpoint, DLL
+      settings (if sent by this connection, e.g. from the profile's init
+      commands) and limits in use, with a reference to its calibration
+      table; export leaves the board as found. Import checks the file and
+      the board's model (first $IDN field; --force skips this), applies the
+      settings, reads them back and leaves RF off. --dry-run prints the
+      commands instead.
+  daemon [--opcua <addr>] [--modbus <addr> [--unit <id>]] [--epics <prefix> [--epics-listen <addr>]]
+         [--cache <ttl>] [--heartbeat <interval>] [--access <policy.json>] [--single-writer]
+         [--bridge <addr>] [--name <unit>] [--no-mdns] [--health <addr>] [--otlp <endpoint>]
+         [--settings <daemon.json>] [--alarms <rules.txt>] [--calibration <table.csv>]
+         [--derate <°C>:<dB>]... [--hysteresis <°C>]
+         [--fan-curve <°C>:<%>,... [--fan-full-above <power>] [--fan-spin-down <interval>]]
+      Serve the generator to OPC UA (default 127.0.0.1:4840), Modbus TCP and
+      EPICS Channel Access clients (requires the `epics` feature). Client reads
+      are answered from replies up to --cache old (default 200ms, 0 to disable).
+      --heartbeat queries the board when it has been idle that long and reports
+      missed replies. --access assigns viewer, operator and engineer roles to
+      client addresses and API tokens (OPC UA clients send a token as the
+      password of a user name login) and limits what each may change.
+      --single-writer lets one client at a time change the board: the first
+      to write takes control, the others are read-only until it disconnects
+      or they take over (Modbus coil 1, OPC UA Generator.Control, EPICS
+      <prefix>CONTROL). All front ends are plaintext and, without --access,
+      let every client change the board, so the default addresses are local
+      (EPICS --epics-listen 127.0.0.1:5064); to serve a network, pass an
+      address such as 0.0.0.0:4840 with --access and terminate TLS in front
+      of them (e.g. stunnel).
+      --bridge serves the board's line protocol to other mwctl hosts (--tcp or
+      fleet); with --name the daemon answers fleet discovery as that unit
+      (one announcing daemon per host, UDP port 48400).
+      The daemon advertises itself over mDNS as _mwctl._tcp, named --name or
+      the host name, unless --no-mdns is given; see `discover`.
+      --health serves GET /healthz (fails once the link is lost) and /readyz
+      (also fails on missed heartbeats or a board fault) for supervisors.
+      --otlp exports a span for every command sent to the board.
+      --alarms polls telemetry (every telemetry_interval_s of --settings,
+      default 1s) against alarm rules, one per line, such as
+      \"worn_pa: efficiency < 40 for 30s => notify\"; power deviation is
+      measured against the --calibration table. The three files are reloaded
+      when they change. While RF is on, a reload that removes or loosens a
+      critical alarm, or a table not covering the frequency in use, is
+      rejected and the previous settings are kept. --derate derates the
+      power clients set, and --fan-curve drives the fans, as for `run`, from
+      the same telemetry. Under --single-writer alarm actions, derating and
+      fan control act whoever holds control, and never take it.
+  discover [--wait <interval>]
+      List the daemons advertising over mDNS on the LAN (default wait 2s):
+      name, address (line bridge, or OPC UA without one), host and the port
+      of every front end.
+  dll list --profile <profile.json>
+  dll apply <preset> --profile <profile.json>
+      List the DLL presets of a device profile (\"dll_presets\": name,
+      description and the six $DLES parameters), or configure the DLL with
+      one and enable it.
+  dll tune [--lower <MHz>] [--upper <MHz>] [--start <MHz>] [--delay <ms>] [--steps <MHz,...>]
+           [--thresholds <dB,...>] [--power <power>] [--settle <interval>] [--apply]
+           [--save <preset> --profile <profile.json>]
+      Try every step and threshold (default 0.5,1,2 MHz and 10,15,20 dB,
+      2400-2500 MHz at 10 dBm), report lock and S11 for each and recommend
+      the locked one with the least reflection; --apply enables it, --save
+      stores it as a preset.
+  export --log <file.csv> [--from <time>] [--to <time>] [--aggregates <file>]
+         --parquet <file.parquet> [--uncompressed]
+  export --sweep <sweep.csv> --parquet <file.parquet> [--uncompressed]
+      Convert a telemetry log (with its rotated files, between two times as
+      for `history`) or a sweep CSV to Parquet for pandas, Polars or DuckDB:
+      UTC millisecond timestamps, float32 readings and a boolean rf_enabled,
+      gzip-compressed unless --uncompressed. A downsampled log's aggregates
+      go to <file>-aggregates.parquet.
+  expose --power <power> --seconds <n> [--frequency <MHz>]
+      Enable RF for n seconds with a countdown, then disable it. RF is also
+      disabled if the exposure fails or mwctl is interrupted or terminated.
+  fleet discover [--broadcast <addr>] [--wait <interval>]
+  fleet send <command> [--hosts <name=host:port,...>]
+  fleet run <recipe.json> [--hosts <name=host:port,...>] [--telemetry <interval>]
+            [--workers <n>]
+  fleet monitor [--hosts <name=host:port,...>] [--interval <interval>] [--workers <n>]
+      Find daemons started with --bridge and --name (UDP broadcast to port
+      48400, default 255.255.255.255), send one command line to all of them,
+      run a recipe on every unit at once with combined telemetry, or print
+      every unit's readings. Without --hosts every unit that answers
+      discovery is used. Telemetry is polled by --workers threads (default
+      4) shared by the units, most overdue unit first.
+  history --log <file.csv> [--from <time>] [--to <time>] [--signal <name,...>]
+          [--aggregates <file>] [--json]
+      Print signals recorded by --log between two times (UTC timestamps,
+      dates or intervals before now, e.g. --from 2h), with a min/mean/max
+      summary. Rotated files and the downsampled aggregates are included,
+      an aggregate as its mean (--json adds its min and max). Signals:
+      frequency_mhz, power_setpoint_dbm, forward_dbm, reflected_dbm (or
+      reflected_power), temperature_c, delivered_w, rf_enabled,
+      supply_voltage_v, supply_current_a and efficiency_pct; default all.
+  modbus [--listen <addr>] [--rtu <port> [--rtu-baud <baud>]] [--unit <id>] [--access <policy.json>]
+      Serve the generator's registers to Modbus TCP and/or RTU clients
+      (default --listen 127.0.0.1:502; see `daemon` before serving a network).
+  monitor [--interval <interval>] [--json] [<telemetry log options>]
+      Print frequency, power setpoint, PA and reflected power, the supply
+      voltage, current and PA efficiency, and the decoded status every
+      interval (default 500ms) until interrupted; --json streams one JSON
+      object per line.
+  ports [--usb-id <vid:pid>]... [--port-match <text>]... [--save <profile.json>]
+      List the serial ports by their stable /dev/serial/by-id/ names, with
+      the kernel device, USB ID and strings of each; * marks the ports
+      autodetection takes for the board. --save writes the first of them as
+      the port of the profile, creating it if needed.
+  replay <session.log> [--speed <factor>] [--instant]
+      Step through a recorded session, reconstructing the device state.
+  run <recipe.json> [--telemetry <interval>] [--retune <interval>] [--report <file.html|file.pdf>]
+      [--otlp <endpoint>] [--exclusive] [--derate <°C>:<dB>]... [--hysteresis <°C>]
+      [--fan-curve <°C>:<%>,... [--fan-full-above <power>] [--fan-spin-down <interval>]]
+      [<telemetry log options>]
+      Run a recipe, printing telemetry and the energy delivered to the load.
+      Each --derate keeps the power dB below what the recipe sets while the
+      PA is at °C or hotter, until it is --hysteresis (default 2) below; the
+      hottest tier applies, and every change is printed and reported.
+      --exclusive locks the board's front panel out while the recipe runs.
+      --fan-curve sets the fans from the PA temperature, interpolating
+      between the points (e.g. 40:30,60:60,75:100), at full speed with the
+      setpoint at or above --fan-full-above, and no slower than with RF on
+      until --fan-spin-down (default 60s) after RF goes off.
+      --retune re-matches the frequency every interval during holds with RF
+      on (10 MHz sweep at 10 dBm unless the recipe's \"retune\" sets one).
+      --report writes a run report with settings, plots, alarms and device identity.
+      --otlp exports command spans and telemetry gauges to an OpenTelemetry
+      collector over OTLP/HTTP JSON (e.g. http://localhost:4318; https needs
+      curl). Set TRACEPARENT to nest the spans under a test step's trace.
+  simulate --pty [--link <path>] [--quiet]
+      Serve the simulated board on a new pseudo-terminal, print its path
+      (e.g. /dev/pts/4) and answer what is written to it until interrupted,
+      for vendor tools and other programs that need a serial device. --link
+      also makes a symlink to it at a fixed path. Unix only.
+  soak --power <power> --duration <interval> [--frequency <MHz>] [--telemetry <interval>]
+       [--status-interval <interval>] [--derate <°C>:<dB>]... [--hysteresis <°C>]
+       [--max-temperature <°C>] [--max-reflected <power>] [--tolerance <dB>]
+       [--report <file.html|file.pdf>] [<telemetry log options>]
+      Burn-in: hold RF at the power for the duration, reading telemetry every
+      --telemetry (default 1s) and the status word every --status-interval
+      (default 60s). Each --derate lowers the power by dB while the PA is at
+      °C or hotter, until it is --hysteresis (default 2) below. The unit fails
+      and RF goes off on a critical status fault, a command error or a limit
+      exceeded; forward power more than --tolerance dB off the setpoint also
+      fails it. Prints PASS or FAIL and exits non-zero on FAIL.
+  sweep [--start <MHz>] [--stop <MHz>] [--step <MHz> | --list <file>] [--power <power>]
+        [--dwell <interval>] [--output <file.csv>] [--touchstone <file.s1p>] [--plot <file.svg>]
+        [--parquet <file.parquet>]
+      Run a host-side sweep (default 2400-2500 MHz in 1 MHz steps at 10 dBm)
+      with a progress bar and print the best matched frequency and the
+      resonant frequency, bandwidth and loaded Q of a Lorentzian fit.
+      --list visits the frequencies of a file in order instead: CSV with MHz
+      in the first column (further columns ignored) or a JSON array.
+      --touchstone writes S11 magnitude (phase 0) for RF tools; --parquet
+      writes the points as for `export --sweep`.
+  tune [--vna <port>] [--start <MHz>] [--stop <MHz>] [--step <MHz>] [--power <power>]
+       [--dwell <interval>] [--session <file>] [--touchstone <file.s1p>] [--apply]
+      Sweep at low power (default 10 dBm), apply the frequency with the least
+      reflection and report the reflected power before and after. With --vna,
+      measure S11 with a NanoVNA (RF off) instead and apply only with --apply;
+      --touchstone then saves the complex S11.
+  verify [--power <power,...>] [--frequency <MHz>] [--tolerance <dB>] [--dwell <interval>]
+         [--start <MHz>] [--stop <MHz>] [--step <MHz>] [--max-s11 <dB>] [--junit <report.xml>]
+      Acceptance test of a board on a dummy load, e.g. for a new firmware
+      drop: identity and firmware version, forward power at each setpoint
+      (default 10,20 dBm at 2450 MHz) within --tolerance (default 1 dB), a
+      sweep (default 2400-2500 MHz in 10 MHz steps at the lowest setpoint)
+      with every point measured and S11 below --max-s11 (default -10 dB), and
+      a fault clear leaving the status word clean. --junit writes a JUnit XML
+      report for CI. Fails unless every case passed.
+
+Connection options (commands that talk to a board):
+  --port <name>        Serial port of the board (default: the profile's \"port\",
+                       else autodetect); /dev/serial/by-id/ links (see `ports`)
+                       survive the board coming back as another ttyUSB/ttyACM
+  --usb-id <vid:pid>   Autodetect ports with this USB ID, in hex (default
+                       0403:6001, the board's FTDI adapter). May be repeated
+  --port-match <text>  Autodetect only ports whose manufacturer, product
+                       description or Windows friendly name contains this,
+                       ignoring case. May be repeated
+  --tcp <host:port>    Serial-to-Ethernet bridge
+  --ble <address>      BLE-to-UART bridge (requires the `ble` feature)
+  --simulate           Built-in simulated board
+  --read-only          Only send queries; set and enable commands are refused
+  --keep-state         Leave the board as found; otherwise connecting disables RF
+                       and sets the minimum power before anything else
+  --yes                Enable RF without asking (commands that transmit ask to
+                       arm RF first, showing the power setpoint)
+  --audit <file>       Append every command and reply to a hash-chained audit log
+  --profile <file>     Device profile (JSON) with the board's link settings, the
+                       identity it must report and the commands sent on connecting
+  --family <text>      Text the board's $IDN reply must contain (default: the
+                       profile's \"family\"); without either any $IDN reply passes,
+                       and a device that does not answer one gets no set commands
+  --interlock gpio:<pin>[:active-low]
+  --interlock serial:<port>:<cts|dsr|dcd|ri>[:active-low]
+                       Door switch or other input that must be closed (high, or
+                       asserted) for RF; checked every 20ms while RF is on and
+                       RF is disabled the moment it opens. May be repeated
+  --adaptive-timeout <min>,<max>
+                       Learn each command's reply latency and time out after
+                       about that long within min..max, e.g. 50ms,2s (default:
+                       a fixed 500ms)
+
+Serial port options (override the profile's \"serial\" settings):
+  --baud <rate>        Baud rate (default 115200)
+  --data-bits <5-8>    Data bits (default 8)
+  --parity <none|odd|even>
+                       Parity (default none)
+  --stop-bits <1|2>    Stop bits (default 1)
+  --flow-control <none|software|hardware>
+                       Flow control (default none)
+  --read-timeout <interval>
+                       Timeout of a single read from the port (default 10s)
+  --response-timeout <interval>
+                       How long to wait for each reply, also over TCP and BLE
+                       (default 500ms; ignored with --adaptive-timeout)
+
+Telemetry log options (monitor, run and soak):
+  --log <file.csv>     Append every sample (rotated at 64 MiB)
+  --downsample <raw_for>,<bucket>
+                       Log every sample for raw_for only, then min/mean/max per
+                       bucket, e.g. 1h,10s; RF on/off changes stay in the log
+  --aggregates <file>  Where the aggregates go (default <file>-aggregates.csv)
+  --keep-reflected <power>
+                       Also log every sample reflecting more than this
+
+Black box options (monitor, run, soak and daemon):
+  --blackbox <dir>     Keep the last commands, replies, telemetry and events in
+                       memory and write them to <dir> when the board faults, an
+                       interlock trips, the link is lost or mwctl panics
+  --blackbox-window <interval>
+                       How much to keep (default 60s)
+
+Power options take dBm or watts: 30, 30dBm, 1W, 500mW or 1.2kW.
+";
+
+/// Command line arguments of a subcommand: positionals and `--name [value]` options.
+pub struct Args {
+    positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    /// Splits `args`; names listed in `switches` never take a value.
+    pub fn parse(args: &[String], switches: &[&str]) -> Result<Args, String> {
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            let name = match arg.strip_prefix("--") {
+                Some(name) => name,
+                None => {
+                    positional.push(arg.clone());
+                    continue;
+                }
+            };
+            if let Some((name, value)) = name.split_once('=') {
+                options.push((name.to_string(), Some(value.to_string())));
+            } else if switches.contains(&name) {
+                options.push((name.to_string(), None));
+            } else {
+                match iter.next() {
+                    Some(value) => options.push((name.to_string(), Some(value.clone()))),
+                    None => return Err(format!("Missing value for --{}", name)),
+                }
+            }
+        }
+
+        Ok(Args {
+            positional,
+            options,
+        })
+    }
+
+    pub fn positional(&self, index: usize) -> Option<&str> {
+        self.positional.get(index).map(String::as_str)
+    }
+
+    pub fn require_positional(&self, index: usize, name: &str) -> Result<&str, String> {
+        self.positional(index)
+            .ok_or_else(|| format!("Missing argument <{}>\n\n{}", name, USAGE))
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    /// Every value given for `--name`, in order.
+    pub fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        self.options
+            .iter()
+            .filter(move |(option, _)| option == name)
+            .filter_map(|(_, value)| value.as_deref())
+    }
+
+    /// Last value given for `--name`.
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(option, _)| option == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
+    pub fn parse_value<T: FromStr>(&self, name: &str) -> Result<Option<T>, String> {
+        match self.value(name) {
+            Some(value) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| format!("Invalid value for --{}: {}", name, value)),
+            None => Ok(None),
+        }
+    }
+
+    /// Value of a power option in dBm; see [`parse_power`].
+    pub fn parse_power(&self, name: &str) -> Result<Option<f32>, String> {
+        match self.value(name) {
+            Some(value) => parse_power(value)
+                .map(Some)
+                .map_err(|e| format!("Invalid value for --{}: {}", name, e)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Opens the board selected by the connection options in [`USAGE`].
+pub fn connect(args: &Args) -> Result<Controller, String> {
+    let mut profile = match args.value("profile") {
+        Some(path) => {
+            Some(DeviceProfile::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?)
+        }
+        None => None,
+    };
+    let serial = serial_settings(args, profile.as_ref())?;
+    let controller = if args.flag("simulate") {
+        Ok(Controller::from_transport(Simulator::new()))
+    } else if let Some(addr) = args.value("tcp") {
+        Controller::connect_tcp(addr)
+    } else if let Some(address) = args.value("ble") {
+        connect_ble(address)
+    } else if let Some(port) = args
+        .value("port")
+        .or(profile.as_ref().and_then(|profile| profile.port.as_deref()))
+    {
+        Controller::open_with(port, &serial)
+    } else {
+        Controller::connect_matching(&port_filter(args, profile.as_ref())?, &serial)
+    };
+    let controller = controller.map_err(|e| e.to_string())?;
+    controller.set_read_only(args.flag("read-only"));
+    let mut interlocks = 0;
+    for spec in args.values("interlock") {
+        controller
+            .add_interlock(open_interlock(spec)?)
+            .map_err(|e| e.to_string())?;
+        interlocks += 1;
+    }
+    if interlocks > 0 {
+        spawn_interlock_monitor(
+            &controller,
+            INTERLOCK_POLL_INTERVAL,
+            CancellationToken::new(),
+        );
+    }
+    safety::disable_rf_on_panic(&controller);
+    safety::disable_rf_on_signal();
+    controller.set_response_timeout(serial.response_timeout);
+    // Always checked, so set commands only go to a device that answers
+    // `$IDN`; --family or the profile's family narrows it to that board.
+    // Taken out of the profile so it is not checked a second time there.
+    let profile_family = profile.as_mut().and_then(|profile| profile.family.take());
+    let family = args
+        .value("family")
+        .or(profile_family.as_deref())
+        .unwrap_or_default();
+    controller
+        .verify_identity(family)
+        .map_err(|e| e.to_string())?;
+    // After the identity check, so a device that is not the board gets no
+    // `$PWRS`, and before the profile, so its init commands apply on top of
+    // the baseline.
+    if !args.flag("read-only") && !args.flag("keep-state") {
+        controller
+            .force_safe_state()
+            .map_err(|e| format!("Failed to put the board into a safe state: {}", e))?;
+    }
+    if let Some(path) = args.value("audit") {
+        let log = AuditLog::open(path).map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        controller.set_audit_log(log).map_err(|e| e.to_string())?;
+    }
+    if let Some(profile) = profile {
+        profile
+            .apply(&controller)
+            .map_err(|e| format!("Failed to initialize the board: {}", e))?;
+        // The profile's own timeout, if any, gives way to --response-timeout.
+        controller.set_response_timeout(serial.response_timeout);
+    }
+    if let Some(bounds) = args.value("adaptive-timeout") {
+        let (min, max) = bounds
+            .split_once(',')
+            .ok_or_else(|| format!("Invalid value for --adaptive-timeout: {}", bounds))?;
+        let bounds = TimeoutBounds::new(parse_duration(min)?, parse_duration(max)?)?;
+        controller.set_adaptive_timeout(Some(bounds));
+    }
+    Ok(controller)
+}
+
+/// The profile's port filter, or the board's VID/PID, narrowed by `--usb-id`
+/// and `--port-match`.
+fn port_filter(args: &Args, profile: Option<&DeviceProfile>) -> Result<PortFilter, String> {
+    let mut filter = profile
+        .and_then(|profile| profile.port_filter.clone())
+        .unwrap_or_default();
+    let ids: Vec<&str> = args.values("usb-id").collect();
+    if !ids.is_empty() {
+        filter.usb_ids.clear();
+        for id in ids {
+            filter = filter.with_usb_id(id.parse()?);
+        }
+    }
+    for text in args.values("port-match") {
+        filter = filter.with_description(text);
+    }
+    Ok(filter)
+}
+
+/// The profile's serial settings, or the defaults, with the serial port
+/// options applied on top.
+fn serial_settings(args: &Args, profile: Option<&DeviceProfile>) -> Result<SerialSettings, String> {
+    let mut settings = profile
+        .and_then(|profile| profile.serial)
+        .unwrap_or_default();
+    if let Some(baud_rate) = args.parse_value("baud")? {
+        settings.baud_rate = baud_rate;
+    }
+    if let Some(data_bits) = args.parse_value("data-bits")? {
+        settings.data_bits = data_bits;
+    }
+    if let Some(parity) = args.value("parity") {
+        settings.parity = parity.parse()?;
+    }
+    if let Some(stop_bits) = args.parse_value("stop-bits")? {
+        settings.stop_bits = stop_bits;
+    }
+    if let Some(flow_control) = args.value("flow-control") {
+        settings.flow_control = flow_control.parse()?;
+    }
+    if let Some(timeout) = args.value("read-timeout") {
+        settings.read_timeout = parse_duration(timeout)?;
+    }
+    if let Some(timeout) = args.value("response-timeout") {
+        settings.response_timeout = parse_duration(timeout)?;
+    }
+    settings.validate()?;
+    Ok(settings)
+}
+
+/// `--interlock gpio:<pin>[:active-low]` or
+/// `--interlock serial:<port>:<cts|dsr|dcd|ri>[:active-low]`.
+fn open_interlock(spec: &str) -> Result<Arc<dyn Interlock>, String> {
+    let invalid = || format!("Invalid value for --interlock: {}", spec);
+    let mut fields: Vec<&str> = spec.split(':').collect();
+    let active_low = fields.last() == Some(&"active-low");
+    if active_low {
+        fields.pop();
+    }
+    match fields.as_slice() {
+        ["gpio", pin] => open_gpio_interlock(spec, pin.parse().map_err(|_| invalid())?, active_low),
+        ["serial", port, line] => {
+            let line: SerialLine = line.parse()?;
+            SerialLineInterlock::open(spec, port, line, active_low)
+                .map(|interlock| Arc::new(interlock) as Arc<dyn Interlock>)
+                .map_err(|e| format!("Failed to open interlock {}: {}", spec, e))
+        }
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(feature = "gpio")]
+fn open_gpio_interlock(
+    name: &str,
+    pin: u32,
+    active_low: bool,
+) -> Result<Arc<dyn Interlock>, String> {
+    microwave_controller::gpio::GpioInput::new(name, pin, active_low)
+        .map(|input| Arc::new(input) as Arc<dyn Interlock>)
+        .map_err(|e| format!("Failed to open interlock {}: {}", name, e))
+}
+
+#[cfg(not(feature = "gpio"))]
+fn open_gpio_interlock(
+    _name: &str,
+    _pin: u32,
+    _active_low: bool,
+) -> Result<Arc<dyn Interlock>, String> {
+    Err("mwctl was built without the `gpio` feature".to_string())
+}
+
+#[cfg(feature = "ble")]
+fn connect_ble(address: &str) -> Result<Controller, microwave_controller::ControllerError> {
+    Controller::connect_ble(address)
+}
+
+#[cfg(not(feature = "ble"))]
+fn connect_ble(_address: &str) -> Result<Controller, microwave_controller::ControllerError> {
+    Err(microwave_controller::ControllerError::Connection(
+        "mwctl was built without the `ble` feature".to_string(),
+    ))
+}
+
+/// Loads the `--access` policy of a server command; without one every
+/// client may do everything.
+pub fn access_policy(args: &Args) -> Result<AccessPolicy, String> {
+    match args.value("access") {
+        Some(path) => {
+            AccessPolicy::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))
+        }
+        None => Ok(AccessPolicy::default()),
+    }
+}
+
+/// The `--derate <°C>:<dB>` tiers and `--hysteresis` of a long run; `None`
+/// without tiers.
+pub fn derating_policy(args: &Args) -> Result<Option<DeratingPolicy>, String> {
+    let mut policy = DeratingPolicy::default();
+    for rule in args.values("derate") {
+        policy = policy.with_tier(rule.parse()?);
+    }
+    if let Some(celsius) = args.parse_value("hysteresis")? {
+        policy = policy.with_hysteresis(celsius);
+    }
+    Ok((!policy.tiers.is_empty()).then_some(policy))
+}
+
+/// The `--fan-curve <°C>:<%>,...` to drive the fans along, with
+/// `--fan-full-above <power>` and `--fan-spin-down <interval>`; `None`
+/// without a curve.
+pub fn fan_policy(args: &Args) -> Result<Option<FanPolicy>, String> {
+    let Some(curve) = args.value("fan-curve") else {
+        for option in ["fan-full-above", "fan-spin-down"] {
+            if args.value(option).is_some() {
+                return Err(format!("--{} needs --fan-curve", option));
+            }
+        }
+        return Ok(None);
+    };
+    let mut policy = FanPolicy::new(curve.parse()?);
+    if let Some(dbm) = args.parse_power("fan-full-above")? {
+        policy = policy.with_full_speed_above(dbm);
+    }
+    if let Some(spin_down) = args.value("fan-spin-down") {
+        policy = policy.with_spin_down(parse_duration(spin_down)?);
+    }
+    Ok(Some(policy))
+}
+
+/// Telemetry CSV written by `--log <file.csv>`. With `--downsample
+/// <raw_for>,<bucket>` only the first `raw_for` is logged in full, then
+/// min/mean/max aggregates per bucket go to `--aggregates` (default
+/// `<file>-aggregates.csv`); `--keep-reflected <power>` keeps samples
+/// reflecting more than that in the raw log.
+pub struct TelemetryLog {
+    pub listener: TelemetryListener,
+    downsampling: Option<(Downsampler, DownsampledListener)>,
+}
+
+impl TelemetryLog {
+    pub fn open(args: &Args) -> Result<Option<TelemetryLog>, String> {
+        let Some(path) = args.value("log") else {
+            for option in ["downsample", "aggregates", "keep-reflected"] {
+                if args.value(option).is_some() {
+                    return Err(format!(
+                        "--{} thins the telemetry log; add --log <file.csv>",
+                        option
+                    ));
+                }
+            }
+            return Ok(None);
+        };
+        let open = |path: &str, header: &str| {
+            RotatingWriter::open(path, RotationPolicy::default(), Some(header))
+                .map_err(|e| format!("Failed to open {}: {}", path, e))
+        };
+        let raw = csv_logger(open(path, CSV_HEADER)?);
+        let Some(downsample) = args.value("downsample") else {
+            return Ok(Some(TelemetryLog {
+                listener: raw,
+                downsampling: None,
+            }));
+        };
+
+        let (raw_for, bucket) = downsample
+            .split_once(',')
+            .ok_or_else(|| format!("Invalid value for --downsample: {}", downsample))?;
+        let mut policy = Downsampling::new(parse_duration(raw_for)?, parse_duration(bucket)?);
+        if policy.bucket.is_zero() {
+            return Err("The --downsample bucket must be longer than zero".to_string());
+        }
+        if let Some(dbm) = args.parse_power("keep-reflected")? {
+            policy = policy.with_reflected_limit(dbm);
+        }
+        let aggregates_path = match args.value("aggregates") {
+            Some(path) => path.to_string(),
+            None => format!("{}-aggregates.csv", path.trim_end_matches(".csv")),
+        };
+        let aggregates = Mutex::new(open(&aggregates_path, AGGREGATE_CSV_HEADER)?);
+        let sink: DownsampledListener = Arc::new(move |kept: &Downsampled| match kept {
+            Downsampled::Raw(sample) => raw(sample),
+            Downsampled::Aggregate(aggregate) => {
+                if let Ok(mut aggregates) = aggregates.lock() {
+                    let written = writeln!(aggregates, "{}", aggregate.to_csv_row())
+                        .and_then(|_| aggregates.flush());
+                    if let Err(e) = written {
+                        eprintln!("Failed to write telemetry aggregate: {:?}", e);
+                    }
+                }
+            }
+        });
+        let downsampler = Downsampler::new(policy);
+        Ok(Some(TelemetryLog {
+            listener: downsampler.listener(sink.clone()),
+            downsampling: Some((downsampler, sink)),
+        }))
+    }
+
+    /// Writes the aggregate of the last, incomplete bucket.
+    pub fn finish(&self) {
+        if let Some((downsampler, sink)) = &self.downsampling {
+            if let Some(aggregate) = downsampler.finish() {
+                sink(&Downsampled::Aggregate(Box::new(aggregate)));
+            }
+        }
+    }
+}
+
+/// Flight recorder of the last `--blackbox-window` (default 60s), dumped
+/// into the `--blackbox` directory on a fault or a panic.
+pub fn black_box(controller: &Controller, args: &Args) -> Result<Option<BlackBox>, String> {
+    let Some(directory) = args.value("blackbox") else {
+        return Ok(None);
+    };
+    let window = match args.value("blackbox-window") {
+        Some(window) => parse_duration(window)?,
+        None => std::time::Duration::from_secs(60),
+    };
+    let black_box = BlackBox::new(directory, window);
+    black_box.attach(controller).map_err(|e| e.to_string())?;
+    black_box.dump_on_panic();
+    Ok(Some(black_box))
+}
+
+/// Exports the controller's command spans to the `--otlp` collector, if
+/// one is given. Spans join the trace of `TRACEPARENT` when the caller
+/// sets it.
+pub fn otlp_exporter(
+    controller: &Controller,
+    args: &Args,
+    service_name: &str,
+) -> Result<Option<OtlpExporter>, String> {
+    let Some(endpoint) = args.value("otlp") else {
+        return Ok(None);
+    };
+    let mut exporter = OtlpExporter::new(endpoint, service_name);
+    if let Ok(traceparent) = std::env::var("TRACEPARENT") {
+        exporter = exporter.with_parent(&traceparent)?;
+    }
+    controller
+        .observe_exchanges(exporter.exchange_listener())
+        .map_err(|e| e.to_string())?;
+    Ok(Some(exporter))
+}
+
+/// How often `--interlock` inputs are read while RF is on.
+const INTERLOCK_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(20);
+
+/// Switches shared by every command that calls [`connect`].
+pub const CONNECTION_SWITCHES: &[&str] = &["simulate", "read-only", "keep-state"];
+
+/// Asks the operator to arm RF before a command enables it, showing the
+/// power it will transmit at (the current setpoint unless `power_dbm` is
+/// given). `--yes` skips the prompt and is required without a terminal.
+pub fn arm_rf(controller: &Controller, args: &Args, power_dbm: Option<f32>) -> Result<(), String> {
+    if args.flag("yes") {
+        return Ok(());
+    }
+    if !io::stdin().is_terminal() {
+        return Err("This command enables RF; pass --yes to confirm".to_string());
+    }
+    let power_dbm = match power_dbm {
+        Some(power_dbm) => power_dbm,
+        None => controller
+            .send(&Command::GetPowerSetpoint)
+            .and_then(|reply| parse_value(&reply))
+            .map_err(|e| format!("Failed to read the power setpoint: {}", e))?,
+    };
+    eprint!(
+        "RF will be enabled on {} at {:.2} dBm. Type \"arm\" to continue: ",
+        controller.port_name(),
+        power_dbm
+    );
+    let _ = io::stderr().flush();
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|e| e.to_string())?;
+    if answer.trim() != "arm" {
+        return Err("RF not armed".to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,18 @@
+use std::fs;
+
+use microwave_controller::audit::verify;
+
+use super::Args;
+
+/// `mwctl audit <audit.log>`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let path = args.require_positional(0, "audit.log")?;
+    let log = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let summary = verify(&log)?;
+    println!(
+        "{}: {} entries, chain intact (last hash {})",
+        path, summary.entries, summary.last_hash
+    );
+    Ok(())
+}
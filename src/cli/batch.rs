@@ -0,0 +1,163 @@
+use std::{
+    fs,
+    io::{self, Read},
+    thread,
+    time::Duration,
+};
+
+use microwave_controller::{
+    units::{parse_duration, parse_power},
+    Command, Controller,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// One line of a batch script.
+enum Step {
+    Command(Command),
+    Sleep(Duration),
+}
+
+/// `mwctl batch [<commands.txt> | -] [--continue]`
+///
+/// Runs one command per line, read from the file or from stdin. Lines are
+/// the board's own (`$FCS,0,2450`) or the typed forms below; `#` starts a
+/// comment. The batch stops at the first failure, disabling RF, unless
+/// `--continue` is given.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.extend(["yes", "continue"]);
+    let args = Args::parse(args, &switches)?;
+    let script = match args.positional(0).unwrap_or("-") {
+        "-" => {
+            let mut script = String::new();
+            io::stdin()
+                .read_to_string(&mut script)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+            script
+        }
+        path => fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?,
+    };
+
+    // Parse everything first so a typo on line 20 is caught before line 1 runs.
+    let mut steps = Vec::new();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.split('#').next().unwrap_or_default().trim();
+        if !line.is_empty() {
+            let step = parse_step(line).map_err(|e| format!("Line {}: {}", number + 1, e))?;
+            steps.push((number + 1, line, step));
+        }
+    }
+    let enables_rf = steps
+        .iter()
+        .any(|(_, _, step)| matches!(step, Step::Command(Command::RfEnable)));
+    let max_power = steps
+        .iter()
+        .filter_map(|(_, _, step)| match step {
+            Step::Command(Command::SetPower(dbm)) => Some(*dbm),
+            _ => None,
+        })
+        .reduce(f32::max);
+
+    let controller = connect(&args)?;
+    controller.set_echo(false);
+    if enables_rf {
+        arm_rf(&controller, &args, max_power)?;
+    }
+
+    let keep_going = args.flag("continue");
+    let (mut succeeded, mut failed) = (0, 0);
+    for (index, (number, line, step)) in steps.iter().enumerate() {
+        match execute(&controller, step) {
+            Ok(reply) => {
+                succeeded += 1;
+                println!("{:>4}  {:<24} {}", number, line, reply);
+            }
+            Err(e) => {
+                failed += 1;
+                println!("{:>4}  {:<24} FAILED: {}", number, line, e);
+                if !keep_going {
+                    let _ = controller.safe_state();
+                    println!(
+                        "{} succeeded, {} failed, {} skipped; RF disabled",
+                        succeeded,
+                        failed,
+                        steps.len() - index - 1
+                    );
+                    return Err(format!("Stopped at line {}", number));
+                }
+            }
+        }
+    }
+    println!("{} succeeded, {} failed", succeeded, failed);
+    match failed {
+        0 => Ok(()),
+        failed => Err(format!("{} command(s) failed", failed)),
+    }
+}
+
+fn execute(controller: &Controller, step: &Step) -> Result<String, String> {
+    match step {
+        Step::Command(command) => controller
+            .send(command)
+            .map(|reply| reply.trim().to_string())
+            .map_err(|e| e.to_string()),
+        Step::Sleep(duration) => {
+            thread::sleep(*duration);
+            Ok(format!("slept {:?}", duration))
+        }
+    }
+}
+
+/// A raw command line, or one of
+/// `identity`, `version`, `status`, `clear`, `temperature`,
+/// `supply-voltage`, `supply-current`, `fan [<%>]`, `pa-power`,
+/// `frequency [<MHz>]`, `power [<power>]`, `rf on|off`, `dll on|off`,
+/// `lockout [on|off]` and `sleep <interval>`.
+fn parse_step(line: &str) -> Result<Step, String> {
+    if line.starts_with('$') {
+        return line
+            .parse()
+            .map(Step::Command)
+            .map_err(|_| format!("Not a command line: {}", line));
+    }
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let command = match words.as_slice() {
+        ["sleep", interval] => return Ok(Step::Sleep(parse_duration(interval)?)),
+        ["identity"] => Command::GetIdentity,
+        ["version"] => Command::GetVersion,
+        ["status"] => Command::GetStatus { verbose: false },
+        ["clear"] => Command::ClearErrors,
+        ["temperature"] => Command::GetPaTemperature,
+        ["supply-voltage"] => Command::GetSupplyVoltage,
+        ["supply-current"] => Command::GetSupplyCurrent,
+        ["lockout"] => Command::GetLocalLockout,
+        ["lockout", "on"] => Command::LockLocal,
+        ["lockout", "off"] => Command::UnlockLocal,
+        ["fan"] => Command::GetFanSpeed,
+        ["fan", percent] => {
+            let percent = percent
+                .trim_end_matches('%')
+                .parse()
+                .map_err(|_| format!("Invalid fan speed: {}", percent))?;
+            Command::set_fan_speed(percent).map_err(|e| e.to_string())?
+        }
+        ["pa-power"] => Command::GetPaPower,
+        ["frequency"] => Command::GetFrequency,
+        ["frequency", mhz] => {
+            let mhz = mhz
+                .trim_end_matches("MHz")
+                .parse()
+                .map_err(|_| format!("Invalid frequency: {}", mhz))?;
+            Command::set_frequency(mhz).map_err(|e| e.to_string())?
+        }
+        ["power"] => Command::GetPowerSetpoint,
+        ["power", power] => Command::set_power(parse_power(power)?).map_err(|e| e.to_string())?,
+        ["rf", "on"] => Command::RfEnable,
+        ["rf", "off"] => Command::RfDisable,
+        ["dll", "on"] => Command::DllEnable,
+        ["dll", "off"] => Command::DllDisable,
+        _ => return Err(format!("Unknown command: {}", line)),
+    };
+    Ok(Step::Command(command))
+}
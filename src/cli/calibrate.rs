@@ -0,0 +1,97 @@
+use std::time::Duration;
+
+use microwave_controller::{
+    power_meter::ScpiPowerMeter, sweep::SweepSegment, units::parse_power, CalibrationRun,
+    CancellationToken, Controller, PowerMeter, Progress, Simulator,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl calibrate --start <MHz> --stop <MHz> --step <MHz> [--power <power,...>]
+/// [--dwell <ms>] [--coupling <dB>] (--meter-tcp <addr> | --meter-port <name>) [--output <file>]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let required = |name: &str| -> Result<f32, String> {
+        args.parse_value(name)?
+            .ok_or_else(|| format!("Missing --{}\n\n{}", name, super::USAGE))
+    };
+    let power_levels_dbm = args
+        .value("power")
+        .unwrap_or("30")
+        .split(',')
+        .map(|level| parse_power(level).map_err(|e| format!("Invalid value for --power: {}", e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let calibration = CalibrationRun {
+        segment: SweepSegment {
+            start_mhz: required("start")?,
+            stop_mhz: required("stop")?,
+            step_mhz: required("step")?,
+        },
+        power_levels_dbm,
+        dwell: Duration::from_millis(args.parse_value("dwell")?.unwrap_or(500)),
+        coupling_db: args.parse_value("coupling")?.unwrap_or(0.0),
+    };
+
+    let (controller, mut meter) = connect_with_meter(&args)?;
+    let max_power_dbm = calibration
+        .power_levels_dbm
+        .iter()
+        .copied()
+        .fold(f32::NEG_INFINITY, f32::max);
+    arm_rf(&controller, &args, Some(max_power_dbm))?;
+    println!("Calibrating against {}", meter.name());
+    let table = calibration
+        .run(
+            &controller,
+            meter.as_mut(),
+            &CancellationToken::new(),
+            &mut |progress: &Progress| {
+                println!(
+                    "{:>5.1}%  {:.2} MHz  {:.2} dBm",
+                    progress.percent(),
+                    progress.frequency_mhz.unwrap_or_default(),
+                    progress.power_dbm.unwrap_or_default()
+                );
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    match args.value("output") {
+        Some(path) => {
+            table
+                .save(path)
+                .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            println!(
+                "Wrote {} calibration points to {}",
+                table.points().len(),
+                path
+            );
+        }
+        None => print!("{}", table.to_csv()),
+    }
+    Ok(())
+}
+
+/// Opens the board and the meter. With `--simulate` and no meter option the
+/// simulated board doubles as the meter.
+fn connect_with_meter(args: &Args) -> Result<(Controller, Box<dyn PowerMeter>), String> {
+    let meter: Box<dyn PowerMeter> = if let Some(addr) = args.value("meter-tcp") {
+        Box::new(ScpiPowerMeter::connect_tcp(addr).map_err(|e| e.to_string())?)
+    } else if let Some(port_name) = args.value("meter-port") {
+        let baud: u32 = args.parse_value("meter-baud")?.unwrap_or(115_200);
+        let port = serialport::new(port_name, baud)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(|e| format!("Failed to open {}: {}", port_name, e))?;
+        Box::new(ScpiPowerMeter::new(port).map_err(|e| e.to_string())?)
+    } else if args.flag("simulate") {
+        let simulator = Simulator::new();
+        let controller = Controller::from_transport(simulator.clone());
+        return Ok((controller, Box::new(simulator)));
+    } else {
+        return Err("Calibration needs a power meter: --meter-tcp or --meter-port".to_string());
+    };
+    Ok((connect(args)?, meter))
+}
@@ -0,0 +1,36 @@
+use microwave_controller::{
+    resonance::{fit_resonance, Resonance},
+    sweep::load_points,
+};
+
+use super::Args;
+
+/// `mwctl compare <baseline.csv> <sweep.csv>...`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let baseline_path = args.require_positional(0, "baseline.csv")?;
+    args.require_positional(1, "sweep.csv")?;
+
+    let baseline = fit(baseline_path)?
+        .ok_or_else(|| format!("{}: no dip to fit in the baseline", baseline_path))?;
+    println!("{}  baseline  {}", baseline_path, baseline);
+    let mut index = 1;
+    while let Some(path) = args.positional(index) {
+        match fit(path)? {
+            Some(resonance) => println!(
+                "{}  {}  (loaded Q {:.0})",
+                path,
+                resonance.drift(&baseline),
+                resonance.loaded_q
+            ),
+            None => println!("{}  no dip to fit", path),
+        }
+        index += 1;
+    }
+    Ok(())
+}
+
+fn fit(path: &str) -> Result<Option<Resonance>, String> {
+    let points = load_points(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    Ok(fit_resonance(&points))
+}
@@ -0,0 +1,193 @@
+use std::io;
+
+use microwave_controller::{
+    device_config::DeviceConfig,
+    recipe::Recipe,
+    validate::{Finding, Level},
+    AccessPolicy, CalibrationTable, ConfigSet, DeviceProfile,
+};
+
+use super::{connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl config validate [--profile <profile.json>] [--calibration <table.csv>] [--access <policy.json>] [<recipe.json>...]`
+/// `mwctl config export <device.json> [--calibration <table.csv>] [--access <policy.json>]`
+/// `mwctl config import <device.json> [--calibration <table.csv>] [--dry-run] [--force]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.extend(["dry-run", "force"]);
+    let mut args = args.to_vec();
+    // Exporting only queries, so the board is left as found.
+    if args.first().is_some_and(|action| action == "export") {
+        args.push("--read-only".to_string());
+    }
+    let args = Args::parse(&args, &switches)?;
+    match args.require_positional(0, "validate|export|import")? {
+        "validate" => validate(&args),
+        "export" => export(&args),
+        "import" => import(&args),
+        other => Err(format!("Unknown config action: {}", other)),
+    }
+}
+
+fn export(args: &Args) -> Result<(), String> {
+    let path = args.require_positional(1, "device.json")?;
+    let calibration = args
+        .value("calibration")
+        .map(|path| {
+            CalibrationTable::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))
+        })
+        .transpose()?;
+    let access = args
+        .value("access")
+        .map(|path| AccessPolicy::load(path).map_err(|e| format!("Failed to load {}: {}", path, e)))
+        .transpose()?;
+
+    let controller = connect(args)?;
+    let mut config = DeviceConfig::read(&controller).map_err(|e| e.to_string())?;
+    if let Some(table) = &calibration {
+        config = config.with_calibration(table);
+    }
+    if let Some(limit) = access.and_then(|access| access.operator_power_limit_dbm) {
+        config = config.with_operator_power_limit(limit);
+    }
+    config
+        .save(path)
+        .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+    if config.dll.parameters.is_none() {
+        println!("DLL settings unknown (the board cannot be asked for them); not exported");
+    }
+    println!(
+        "Wrote {}: {:.2} MHz, {:.2} dBm from {}",
+        path,
+        config.frequency_mhz,
+        config.power_setpoint_dbm,
+        config
+            .identity
+            .as_deref()
+            .unwrap_or("an unidentified board")
+    );
+    Ok(())
+}
+
+fn import(args: &Args) -> Result<(), String> {
+    let path = args.require_positional(1, "device.json")?;
+    let mut config =
+        DeviceConfig::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    config.validate().map_err(|e| format!("{}: {}", path, e))?;
+    if args.flag("dry-run") {
+        for command in config.commands() {
+            println!("{}", command);
+        }
+        return Ok(());
+    }
+    if let (Some(reference), Some(table_path)) = (&config.calibration, args.value("calibration")) {
+        let table = CalibrationTable::load(table_path)
+            .map_err(|e| format!("Failed to load {}: {}", table_path, e))?;
+        match reference.matches(&table) {
+            true => println!(
+                "{} is the calibration the configuration was exported with",
+                table_path
+            ),
+            false => println!(
+                "{} differs from the exported calibration ({} points, sha256 {:.12})",
+                table_path, reference.points, reference.sha256
+            ),
+        }
+    }
+
+    let controller = connect(args)?;
+    if let Some(firmware) = &config.firmware {
+        let board = DeviceConfig::read(&controller)
+            .map_err(|e| e.to_string())?
+            .firmware
+            .unwrap_or_default();
+        if &board != firmware {
+            eprintln!(
+                "Warning: exported from firmware {}, this board runs {}",
+                firmware, board
+            );
+        }
+    }
+    if args.flag("force") {
+        config.identity = None;
+    }
+    config
+        .apply(&controller)
+        .map_err(|e| format!("Failed to apply {}: {}", path, e))?;
+    println!(
+        "Applied {}: {} command(s), {:.2} MHz at {:.2} dBm; RF left off",
+        path,
+        config.commands().len(),
+        config.frequency_mhz,
+        config.power_setpoint_dbm
+    );
+    Ok(())
+}
+
+fn validate(args: &Args) -> Result<(), String> {
+    let mut config = ConfigSet::default();
+    let mut files = 0;
+    // Files that fail to parse are reported with the other findings.
+    let mut findings = Vec::new();
+    let mut load = |path: &str, result: io::Result<()>| {
+        files += 1;
+        if let Err(e) = result {
+            findings.push(Finding {
+                level: Level::Error,
+                file: path.to_string(),
+                message: e.to_string(),
+            });
+        }
+    };
+    if let Some(path) = args.value("profile") {
+        let result = DeviceProfile::load(path).map(|profile| {
+            config.profile = Some((path.to_string(), profile));
+        });
+        load(path, result);
+    }
+    if let Some(path) = args.value("calibration") {
+        let result = CalibrationTable::load(path).map(|table| {
+            config.calibration = Some((path.to_string(), table));
+        });
+        load(path, result);
+    }
+    if let Some(path) = args.value("access") {
+        let result = AccessPolicy::load(path).map(|access| {
+            config.access = Some((path.to_string(), access));
+        });
+        load(path, result);
+    }
+    let mut index = 1;
+    while let Some(path) = args.positional(index) {
+        let result = Recipe::load(path).map(|recipe| {
+            config.recipes.push((path.to_string(), recipe));
+        });
+        load(path, result);
+        index += 1;
+    }
+    if files == 0 {
+        return Err(
+            "Nothing to validate; give --profile, --calibration, --access or recipe files"
+                .to_string(),
+        );
+    }
+
+    findings.extend(config.validate());
+    for finding in &findings {
+        println!("{}", finding);
+    }
+    let errors = findings
+        .iter()
+        .filter(|finding| finding.level == Level::Error)
+        .count();
+    println!(
+        "{} file(s) checked: {} error(s), {} warning(s)",
+        files,
+        errors,
+        findings.len() - errors
+    );
+    match errors {
+        0 => Ok(()),
+        errors => Err(format!("{} error(s) found", errors)),
+    }
+}
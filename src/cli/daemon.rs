@@ -0,0 +1,336 @@
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use microwave_controller::{
+    fleet::serve_discovery,
+    mdns::ServiceAdvertisement,
+    modbus::ModbusGateway,
+    reload::{self, ConfigWatcher, LiveConfig, RuntimeSettings},
+    units::parse_duration,
+    AccessPolicy, AlarmEngine, AlertDispatcher, CancellationToken, Controller, ControllerError,
+    ControllerEvent, Derater, FanControl, HealthServer, Heartbeat, LineBridge, OpcUaServer,
+    TelemetryPoller,
+};
+
+use super::{
+    access_policy, black_box, connect, derating_policy, fan_policy, otlp_exporter, Args,
+    CONNECTION_SWITCHES,
+};
+
+/// `mwctl daemon [--opcua <addr>] [--modbus <addr> [--unit <id>]] [--epics <prefix> [--epics-listen <addr>]] [--cache <ttl>] [--heartbeat <interval>] [--access <policy.json>] [--single-writer] [--bridge <addr>] [--name <unit>] [--no-mdns] [--health <addr>] [--otlp <endpoint>] [--settings <daemon.json>] [--alarms <rules.txt>] [--calibration <table.csv>] [--derate <°C>:<dB>]... [--hysteresis <°C>] [--fan-curve <°C>:<%>,... [--fan-full-above <power>] [--fan-spin-down <interval>]]`
+///
+/// Keeps one connection to the board and serves it to every configured
+/// network front end until the process is killed.
+///
+/// With any of `--settings`, `--alarms` or `--calibration` it also polls
+/// telemetry for the alarm rules and reloads those files when they change;
+/// `--derate` derates and `--fan-curve` drives the fans from the same
+/// telemetry.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.extend(["single-writer", "no-mdns"]);
+    let args = Args::parse(args, &switches)?;
+    let opcua = args.value("opcua").unwrap_or("127.0.0.1:4840").to_string();
+    let modbus = args.value("modbus").map(str::to_string);
+    let unit: u8 = args.parse_value("unit")?.unwrap_or(1);
+    let epics = args.value("epics").map(str::to_string);
+    let epics_listen = args
+        .value("epics-listen")
+        .unwrap_or("127.0.0.1:5064")
+        .to_string();
+    let cache_ttl = match args.value("cache") {
+        Some(ttl) => parse_duration(ttl)?,
+        None => Duration::from_millis(200),
+    };
+
+    let bridge: Option<SocketAddr> = args.parse_value("bridge")?;
+    let name = args.value("name").map(str::to_string);
+    let opcua_port = listen_port(&opcua).ok_or(format!("Invalid value for --opcua: {}", opcua))?;
+
+    let health: Option<SocketAddr> = args.parse_value("health")?;
+    let heartbeat = args.value("heartbeat").map(parse_duration).transpose()?;
+    let access = access_policy(&args)?;
+
+    let controller = connect(&args)?;
+    // Clients polling at once share one bus transaction per query.
+    controller
+        .set_cache_ttl(cache_ttl)
+        .map_err(|e| e.to_string())?;
+    if args.flag("single-writer") {
+        controller
+            .set_single_writer(true)
+            .map_err(|e| e.to_string())?;
+        controller
+            .subscribe(Arc::new(|event: &ControllerEvent| {
+                if let ControllerEvent::ControlChanged { .. } = event {
+                    println!("{}", event);
+                }
+            }))
+            .map_err(|e| e.to_string())?;
+    }
+    let _exporter = otlp_exporter(&controller, &args, "mwctl daemon")?;
+    black_box(&controller, &args)?;
+    // Subscribed before the heartbeat starts so no missed reply goes unseen.
+    let health = match health {
+        Some(addr) => Some((
+            HealthServer::new(&controller).map_err(|e| e.to_string())?,
+            addr,
+        )),
+        None => None,
+    };
+    let cancel = CancellationToken::new();
+    if let Some(interval) = heartbeat {
+        controller
+            .subscribe(Arc::new(|event: &ControllerEvent| match event {
+                ControllerEvent::LinkDegraded(_)
+                | ControllerEvent::LinkRestored
+                | ControllerEvent::PortReopened(_)
+                | ControllerEvent::ConnectionLost(_) => eprintln!("{}", event),
+                _ => {}
+            }))
+            .map_err(|e| e.to_string())?;
+        Heartbeat::new(&controller, interval).spawn(cancel.clone());
+    }
+    live_config(&controller, &args, &cancel)?;
+
+    let modbus_thread = match modbus {
+        Some(addr) => {
+            let gateway =
+                ModbusGateway::new(controller.clone(), unit).with_access_policy(access.clone());
+            let cancel = cancel.clone();
+            Some(thread::spawn(move || {
+                gateway.serve_tcp(addr.as_str(), &cancel)
+            }))
+        }
+        None => None,
+    };
+
+    let epics_thread = match epics {
+        Some(prefix) => Some(spawn_epics(
+            controller.clone(),
+            prefix,
+            epics_listen,
+            access.clone(),
+            cancel.clone(),
+        )?),
+        None => None,
+    };
+
+    let bridge_thread = bridge.map(|addr| {
+        let bridge = LineBridge::new(controller.clone()).with_access_policy(access.clone());
+        let cancel = cancel.clone();
+        thread::spawn(move || bridge.serve(addr, &cancel))
+    });
+    let mdns_thread = match args.flag("no-mdns") {
+        true => None,
+        false => {
+            let advertisement = advertisement(&args, opcua_port, bridge, name.as_deref())?;
+            let cancel = cancel.clone();
+            // Not fatal, e.g. when port 5353 is taken by a responder that
+            // does not share it.
+            Some(thread::spawn(move || {
+                if let Err(e) = advertisement.serve(&cancel) {
+                    eprintln!("Not advertising over mDNS: {}", e);
+                }
+                Ok(())
+            }))
+        }
+    };
+    let discovery_thread = match (name, bridge) {
+        (Some(name), Some(addr)) => {
+            let cancel = cancel.clone();
+            Some(thread::spawn(move || {
+                serve_discovery(&name, addr.port(), &cancel)
+            }))
+        }
+        _ => None,
+    };
+
+    let health_thread = health.map(|(health, addr)| {
+        let cancel = cancel.clone();
+        thread::spawn(move || health.serve(addr, &cancel))
+    });
+
+    let server = OpcUaServer::new(controller)
+        .map_err(|e| e.to_string())?
+        .with_access_policy(access);
+    let result = server.serve(opcua.as_str(), &cancel);
+    // Stop the other front ends if the OPC UA listener fails.
+    cancel.cancel();
+    result.map_err(|e| e.to_string())?;
+    for (name, handle) in [
+        ("Modbus", modbus_thread),
+        ("EPICS", epics_thread),
+        ("Line bridge", bridge_thread),
+        ("Discovery", discovery_thread),
+        ("mDNS", mdns_thread),
+        ("Health", health_thread),
+    ] {
+        if let Some(handle) = handle {
+            handle
+                .join()
+                .map_err(|_| format!("{} server panicked", name))?
+                .map_err(|e| e.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+/// Starts telemetry polling and alarms from the files given, and watches
+/// them for changes. The files must be valid at startup; later changes that
+/// are not are rejected and logged, and the daemon carries on.
+fn live_config(
+    controller: &Controller,
+    args: &Args,
+    cancel: &CancellationToken,
+) -> Result<(), String> {
+    let settings_path = args.value("settings").map(Path::new);
+    let alarms_path = args.value("alarms").map(Path::new);
+    let calibration_path = args.value("calibration").map(Path::new);
+    let derating = derating_policy(args)?;
+    let fans = fan_policy(args)?;
+    if settings_path.is_none()
+        && alarms_path.is_none()
+        && calibration_path.is_none()
+        && derating.is_none()
+        && fans.is_none()
+    {
+        return Ok(());
+    }
+    let load_error = |path: &Path, e: String| format!("Failed to load {}: {}", path.display(), e);
+    let settings = match settings_path {
+        Some(path) => reload::load_settings(path).map_err(|e| load_error(path, e))?,
+        None => RuntimeSettings::default(),
+    };
+    settings.validate()?;
+    let rules = match alarms_path {
+        Some(path) => reload::load_alarms(path).map_err(|e| load_error(path, e))?,
+        None => Vec::new(),
+    };
+    let calibration = match calibration_path {
+        Some(path) => Some(reload::load_calibration(path).map_err(|e| load_error(path, e))?),
+        None => None,
+    };
+
+    let mut engine = AlarmEngine::new(controller, AlertDispatcher::new());
+    engine.set_rules(rules.clone());
+    engine.set_calibration(calibration);
+    let engine = Arc::new(Mutex::new(engine));
+    let mut poller = TelemetryPoller::new(controller, settings.telemetry_interval);
+    poller.subscribe(AlarmEngine::shared_listener(engine.clone()));
+    if let Some(policy) = derating {
+        let derater = Derater::attach(controller, policy).map_err(|e| e.to_string())?;
+        poller.subscribe(derater.listener());
+        controller
+            .subscribe(Arc::new(|event: &ControllerEvent| {
+                if let ControllerEvent::PowerDerated { .. } = event {
+                    eprintln!("{}", event);
+                }
+            }))
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(policy) = fans {
+        poller.subscribe(FanControl::new(controller, policy).listener());
+    }
+    let live = Arc::new(Mutex::new(LiveConfig::new(
+        controller,
+        poller.interval(),
+        engine,
+        rules,
+    )));
+    poller.spawn(cancel.clone());
+
+    let mut watcher = ConfigWatcher::new(Duration::from_secs(1));
+    if let Some(path) = settings_path {
+        watcher = watcher.watch(
+            path,
+            reload::reloader(&live, reload::load_settings, LiveConfig::reload_settings),
+        );
+    }
+    if let Some(path) = alarms_path {
+        watcher = watcher.watch(
+            path,
+            reload::reloader(&live, reload::load_alarms, LiveConfig::reload_alarms),
+        );
+    }
+    if let Some(path) = calibration_path {
+        watcher = watcher.watch(
+            path,
+            reload::reloader(
+                &live,
+                reload::load_calibration,
+                LiveConfig::reload_calibration,
+            ),
+        );
+    }
+    watcher.spawn(cancel.clone());
+    Ok(())
+}
+
+type FrontEnd = thread::JoinHandle<Result<(), ControllerError>>;
+
+/// The service announced over mDNS: the line bridge if there is one, the
+/// OPC UA server otherwise, with every front end's port in the TXT record.
+fn advertisement(
+    args: &Args,
+    opcua_port: u16,
+    bridge: Option<SocketAddr>,
+    name: Option<&str>,
+) -> Result<ServiceAdvertisement, String> {
+    let port = bridge.map_or(opcua_port, |addr| addr.port());
+    let mut advertisement = ServiceAdvertisement::new(port);
+    if let Some(name) = name {
+        advertisement = advertisement.with_instance(name);
+    }
+    if let Some(addr) = bridge {
+        advertisement = advertisement.with_txt("bridge", addr.port());
+    }
+    advertisement = advertisement.with_txt("opcua", opcua_port);
+    for front_end in ["modbus", "health"] {
+        if let Some(addr) = args.value(front_end) {
+            let port =
+                listen_port(addr).ok_or(format!("Invalid value for --{}: {}", front_end, addr))?;
+            advertisement = advertisement.with_txt(front_end, port);
+        }
+    }
+    if let Some(prefix) = args.value("epics") {
+        advertisement = advertisement.with_txt("epics", prefix);
+    }
+    Ok(advertisement.with_txt("version", env!("CARGO_PKG_VERSION")))
+}
+
+/// Port of a `host:port` listen address.
+fn listen_port(addr: &str) -> Option<u16> {
+    addr.rsplit_once(':')?.1.parse().ok()
+}
+
+#[cfg(feature = "epics")]
+fn spawn_epics(
+    controller: Controller,
+    prefix: String,
+    listen: String,
+    access: AccessPolicy,
+    cancel: CancellationToken,
+) -> Result<FrontEnd, String> {
+    let server = microwave_controller::epics::EpicsServer::new(controller, &prefix)
+        .with_access_policy(access);
+    Ok(thread::spawn(move || {
+        server.serve(listen.as_str(), &cancel)
+    }))
+}
+
+#[cfg(not(feature = "epics"))]
+fn spawn_epics(
+    _controller: Controller,
+    _prefix: String,
+    _listen: String,
+    _access: AccessPolicy,
+    _cancel: CancellationToken,
+) -> Result<FrontEnd, String> {
+    Err("mwctl was built without the `epics` feature".to_string())
+}
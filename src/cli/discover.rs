@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use microwave_controller::{mdns::browse, units::parse_duration};
+
+use super::Args;
+
+/// `mwctl discover [--wait <interval>]`
+///
+/// Lists the daemons advertising themselves over mDNS on the LAN.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let wait = match args.value("wait") {
+        Some(wait) => parse_duration(wait)?,
+        None => Duration::from_secs(2),
+    };
+    let services = browse(wait).map_err(|e| format!("mDNS query failed: {}", e))?;
+    if services.is_empty() {
+        println!("No controllers answered");
+    }
+    for service in services {
+        let txt: Vec<String> = service
+            .txt
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        println!(
+            "{:<16} {:<21} {:<20} {}",
+            service.instance,
+            service.addr,
+            service.host,
+            txt.join(" ")
+        );
+    }
+    Ok(())
+}
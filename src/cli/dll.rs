@@ -0,0 +1,155 @@
+use microwave_controller::{
+    dll::{recommend, tune_dll, DllTrial, DllTuning},
+    units::parse_duration,
+    CancellationToken, DeviceProfile,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl dll list --profile <profile.json>`,
+/// `mwctl dll apply <preset> --profile <profile.json>` or
+/// `mwctl dll tune [--lower <MHz>] [--upper <MHz>] [--start <MHz>] [--delay <ms>] [--steps <MHz,...>] [--thresholds <dB,...>] [--power <power>] [--settle <interval>] [--apply] [--save <preset> --profile <profile.json>]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("apply");
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let action = args.require_positional(0, "list|apply|tune")?;
+    if action == "tune" {
+        return tune(&args);
+    }
+    let (path, profile) = load_profile(&args)?;
+
+    match action {
+        "list" => {
+            if profile.dll_presets.is_empty() {
+                println!("{} has no DLL presets", path);
+            }
+            for preset in &profile.dll_presets {
+                println!(
+                    "{:<16} {:<40} {}",
+                    preset.name,
+                    preset.description,
+                    format_parameters(&preset.parameters)
+                );
+            }
+            Ok(())
+        }
+        "apply" => {
+            let name = args.require_positional(1, "preset")?;
+            let preset = profile
+                .find_dll_preset(name)
+                .ok_or_else(|| format!("{} has no DLL preset named {}", path, name))?;
+            let controller = connect(&args)?;
+            preset.apply(&controller).map_err(|e| e.to_string())?;
+            println!("DLL configured with {} and enabled", preset.name);
+            Ok(())
+        }
+        other => Err(format!("Unknown dll action: {}", other)),
+    }
+}
+
+fn tune(args: &Args) -> Result<(), String> {
+    let mut tuning = DllTuning::new();
+    for (index, name) in ["lower", "upper", "start"].into_iter().enumerate() {
+        if let Some(value) = args.parse_value(name)? {
+            tuning.base[index] = value;
+        }
+    }
+    if args.value("start").is_none() {
+        tuning.base[2] = tuning.base[0];
+    }
+    if let Some(delay) = args.parse_value("delay")? {
+        tuning.base[5] = delay;
+    }
+    if let Some(steps) = args.value("steps") {
+        tuning.steps_mhz = parse_list("steps", steps)?;
+    }
+    if let Some(thresholds) = args.value("thresholds") {
+        tuning.thresholds_db = parse_list("thresholds", thresholds)?;
+    }
+    if let Some(power) = args.parse_power("power")? {
+        tuning.power_dbm = power;
+    }
+    if let Some(settle) = args.value("settle") {
+        tuning.settle = parse_duration(settle)?;
+    }
+    let save = match args.value("save") {
+        Some(name) => Some((name, load_profile(args)?)),
+        None => None,
+    };
+
+    let controller = connect(args)?;
+    arm_rf(&controller, args, Some(tuning.power_dbm))?;
+    controller.set_echo(false);
+    println!(
+        "{:>8} {:>10} {:>7} {:>12} {:>10}",
+        "step", "threshold", "locked", "frequency", "S11"
+    );
+    let trials = tune_dll(
+        &controller,
+        &CancellationToken::new(),
+        &tuning,
+        &mut print_trial,
+    )
+    .map_err(|e| e.to_string())?;
+
+    let best = recommend(&trials).ok_or("The DLL did not lock with any candidate")?;
+    println!("Recommended: {}", format_parameters(&best.parameters));
+    if args.flag("apply") {
+        best.preset("tuned")
+            .apply(&controller)
+            .map_err(|e| e.to_string())?;
+        println!("DLL configured and enabled");
+    }
+    if let Some((name, (path, mut profile))) = save {
+        profile.dll_presets.retain(|preset| preset.name != name);
+        profile
+            .dll_presets
+            .push(best.preset(name).with_description(&format!(
+                "Tuned, S11 {:.2} dB at {:.2} MHz",
+                best.s11_db, best.frequency_mhz
+            )));
+        profile
+            .save(path)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Saved preset {} to {}", name, path);
+    }
+    Ok(())
+}
+
+fn print_trial(trial: &DllTrial) {
+    println!(
+        "{:>4.2} MHz {:>7.1} dB {:>7} {:>8.2} MHz {:>7.2} dB",
+        trial.parameters[3],
+        trial.parameters[4],
+        if trial.locked { "yes" } else { "no" },
+        trial.frequency_mhz,
+        trial.s11_db
+    );
+}
+
+fn load_profile(args: &Args) -> Result<(&str, DeviceProfile), String> {
+    let path = args
+        .value("profile")
+        .ok_or("The DLL presets are read from --profile <profile.json>")?;
+    let profile =
+        DeviceProfile::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    Ok((path, profile))
+}
+
+fn parse_list(name: &str, list: &str) -> Result<Vec<f32>, String> {
+    list.split(',')
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid value for --{}: {}", name, value))
+        })
+        .collect()
+}
+
+fn format_parameters(parameters: &[f32; 6]) -> String {
+    let parameters: Vec<String> = parameters.iter().map(|p| format!("{:.2}", p)).collect();
+    parameters.join(",")
+}
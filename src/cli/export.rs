@@ -0,0 +1,69 @@
+use microwave_controller::{
+    history::{HistoryRow, TelemetryHistory},
+    parquet::{Compression, ParquetTable},
+    sweep::load_points,
+};
+
+use super::{history::parse_time, Args};
+
+/// `mwctl export (--log <file.csv> [--from <time>] [--to <time>] [--aggregates <file>] | --sweep <sweep.csv>) --parquet <file.parquet> [--uncompressed]`
+///
+/// Converts a telemetry log or a saved sweep to Parquet.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["uncompressed"])?;
+    let output = args
+        .value("parquet")
+        .ok_or("Missing required option --parquet <file.parquet>")?;
+    let compression = match args.flag("uncompressed") {
+        true => Compression::None,
+        false => Compression::Gzip,
+    };
+    let save = |table: &ParquetTable, path: &str| {
+        table
+            .save(path, compression)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {} ({} rows)", path, table.rows());
+        Ok::<(), String>(())
+    };
+
+    match (args.value("log"), args.value("sweep")) {
+        (Some(_), Some(_)) => Err("Give either --log or --sweep, not both".to_string()),
+        (None, None) => {
+            Err("Missing required option --log <file.csv> or --sweep <sweep.csv>".to_string())
+        }
+        (None, Some(path)) => {
+            let points =
+                load_points(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+            save(&ParquetTable::from_sweep(&points), output)
+        }
+        (Some(log), None) => {
+            let from = args.value("from").map(parse_time).transpose()?;
+            let to = args.value("to").map(parse_time).transpose()?;
+            let mut history = TelemetryHistory::open(log)
+                .map_err(|e| format!("Failed to open {}: {}", log, e))?;
+            if let Some(path) = args.value("aggregates") {
+                history = history.with_aggregates(path);
+            }
+            let rows = history
+                .rows(from, to)
+                .map_err(|e| format!("Failed to read the history: {}", e))?;
+            let mut samples = Vec::new();
+            let mut aggregates = Vec::new();
+            for row in rows {
+                match row {
+                    HistoryRow::Sample(sample) => samples.push(sample),
+                    HistoryRow::Aggregate(aggregate) => aggregates.push(*aggregate),
+                }
+            }
+            save(&ParquetTable::from_telemetry(&samples), output)?;
+            if !aggregates.is_empty() {
+                let stem = output.trim_end_matches(".parquet");
+                save(
+                    &ParquetTable::from_aggregates(&aggregates),
+                    &format!("{}-aggregates.parquet", stem),
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
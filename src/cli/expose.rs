@@ -0,0 +1,71 @@
+use std::{
+    io::{self, Write},
+    time::Duration,
+};
+
+use microwave_controller::{CancellationToken, Exposure, Progress};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl expose --power <power> --seconds <n> [--frequency <MHz>]`
+///
+/// Enables RF for the given time with a countdown, then disables it. RF is
+/// also disabled when mwctl is interrupted or terminated.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let power_dbm = args
+        .parse_power("power")?
+        .ok_or("Missing required option --power")?;
+    let seconds: f64 = args
+        .parse_value("seconds")?
+        .ok_or("Missing required option --seconds")?;
+    if !(seconds > 0.0 && seconds.is_finite()) {
+        return Err(format!("--seconds must be above zero, got {}", seconds));
+    }
+    let mut exposure = Exposure::new(power_dbm, Duration::from_secs_f64(seconds));
+    if let Some(mhz) = args.parse_value("frequency")? {
+        exposure = exposure.with_frequency(mhz);
+    }
+
+    let controller = connect(&args)?;
+    arm_rf(&controller, &args, Some(power_dbm))?;
+    controller.set_echo(false);
+    let total = exposure.duration;
+    draw_countdown(
+        &Progress {
+            completed: 0,
+            total: 0,
+            elapsed: Duration::ZERO,
+            frequency_mhz: exposure.frequency_mhz,
+            power_dbm: Some(power_dbm),
+        },
+        total,
+    );
+    let exposed = exposure
+        .run_with_progress(&controller, &CancellationToken::new(), &mut |progress| {
+            draw_countdown(progress, total)
+        })
+        .map_err(|e| format!("Exposure stopped, RF disabled: {}", e));
+    eprintln!();
+    let exposed = exposed?;
+    println!(
+        "Exposed for {:.1} s at {:.2} dBm; RF disabled",
+        exposed.as_secs_f64(),
+        power_dbm
+    );
+    Ok(())
+}
+
+fn draw_countdown(progress: &Progress, total: Duration) {
+    let remaining = total.saturating_sub(Duration::from_secs(progress.completed as u64));
+    let seconds = remaining.as_secs_f64().ceil() as u64;
+    eprint!(
+        "\rRF on at {:.2} dBm  {}:{:02} remaining   ",
+        progress.power_dbm.unwrap_or_default(),
+        seconds / 60,
+        seconds % 60
+    );
+    let _ = io::stderr().flush();
+}
@@ -0,0 +1,194 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use microwave_controller::{
+    fleet::{discover, DISCOVERY_PORT},
+    recipe::Recipe,
+    units::parse_duration,
+    CancellationToken, Command, Fleet, FleetMember, FleetTelemetry, TelemetrySample,
+};
+
+use super::Args;
+
+/// `mwctl fleet discover [--broadcast <addr>] [--wait <interval>]`,
+/// `mwctl fleet send <command> [--hosts <name=host:port,...>]`,
+/// `mwctl fleet run <recipe.json> [--hosts <name=host:port,...>] [--telemetry <interval>]
+/// [--workers <n>] --yes` or
+/// `mwctl fleet monitor [--hosts <name=host:port,...>] [--interval <interval>] [--workers <n>]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["yes"])?;
+    match args.require_positional(0, "discover|send|run|monitor")? {
+        "discover" => {
+            let members = members(&args)?;
+            if members.is_empty() {
+                println!("No units answered");
+            }
+            for member in members {
+                println!("{:<16} {}", member.name, member.addr);
+            }
+            Ok(())
+        }
+        "send" => {
+            let line = args.require_positional(1, "command")?;
+            let command: Command = line
+                .parse()
+                .map_err(|_| format!("Not a command line: {}", line))?;
+            let fleet = connect(&args)?;
+            let mut failed = 0;
+            for (name, result) in fleet.send(&command) {
+                match result {
+                    Ok(reply) => println!("{:<16} {}", name, reply.trim()),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{:<16} failed: {}", name, e);
+                    }
+                }
+            }
+            match failed {
+                0 => Ok(()),
+                failed => Err(format!("{} unit(s) failed", failed)),
+            }
+        }
+        "run" => run_recipe(&args),
+        "monitor" => monitor(&args),
+        other => Err(format!("Unknown fleet action: {}", other)),
+    }
+}
+
+fn run_recipe(args: &Args) -> Result<(), String> {
+    let path = args.require_positional(1, "recipe.json")?;
+    let recipe = Recipe::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    let interval = match args.value("telemetry") {
+        Some(interval) => parse_duration(interval)?,
+        None => Duration::from_secs(1),
+    };
+    if !args.flag("yes") {
+        return Err("This command enables RF on every unit; pass --yes to confirm".to_string());
+    }
+    let fleet = connect(args)?;
+
+    let telemetry = FleetTelemetry::new();
+    let totals = telemetry.clone();
+    telemetry.subscribe(Arc::new(move |name: &str, sample: &TelemetrySample| {
+        println!(
+            "{:<16} {:.2} MHz  fwd {:.2} dBm  refl {:.2} dBm  delivered {:.1} W  fleet {:.1} W",
+            name,
+            sample.frequency_mhz,
+            sample.forward_dbm,
+            sample.reflected_dbm,
+            sample.delivered_watts(),
+            totals.total_delivered_watts()
+        );
+    }));
+    let telemetry_cancel = CancellationToken::new();
+    let pollers = fleet.spawn_telemetry(
+        interval,
+        workers(args)?,
+        &telemetry,
+        telemetry_cancel.clone(),
+    );
+
+    println!(
+        "Running recipe {} on {} unit(s)",
+        recipe.name,
+        fleet.members().count()
+    );
+    let results = fleet.run_recipe(&recipe, &CancellationToken::new());
+    telemetry_cancel.cancel();
+    pollers.join();
+    let mut failed = 0;
+    for (name, result) in results {
+        match result {
+            Ok(()) => println!("{:<16} completed", name),
+            Err(e) => {
+                failed += 1;
+                println!("{:<16} failed: {}", name, e);
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        failed => Err(format!("{} unit(s) failed", failed)),
+    }
+}
+
+/// Prints every unit's readings until interrupted.
+fn monitor(args: &Args) -> Result<(), String> {
+    let interval = match args.value("interval") {
+        Some(interval) => parse_duration(interval)?,
+        None => Duration::from_millis(500),
+    };
+    let fleet = connect(args)?;
+    let telemetry = FleetTelemetry::new();
+    telemetry.subscribe(Arc::new(move |name: &str, sample: &TelemetrySample| {
+        println!(
+            "{:<16} {:.2} MHz  set {:.2} dBm  PA {:.2} dBm  refl {:.2} dBm",
+            name,
+            sample.frequency_mhz,
+            sample.power_setpoint_dbm,
+            sample.forward_dbm,
+            sample.reflected_dbm
+        );
+    }));
+    fleet
+        .spawn_telemetry(
+            interval,
+            workers(args)?,
+            &telemetry,
+            CancellationToken::new(),
+        )
+        .join();
+    Ok(())
+}
+
+/// Threads shared by the units' pollers: `--workers`, default 4.
+fn workers(args: &Args) -> Result<usize, String> {
+    Ok(args.parse_value("workers")?.unwrap_or(4))
+}
+
+/// Units given with `--hosts`, or found by discovery.
+fn members(args: &Args) -> Result<Vec<FleetMember>, String> {
+    if let Some(hosts) = args.value("hosts") {
+        return hosts
+            .split(',')
+            .map(|host| {
+                let (name, addr) = host.trim().split_once('=').unwrap_or(("", host.trim()));
+                let addr: SocketAddr = addr
+                    .parse()
+                    .map_err(|_| format!("Invalid value for --hosts: {}", host))?;
+                Ok(FleetMember {
+                    name: if name.is_empty() {
+                        addr.to_string()
+                    } else {
+                        name.to_string()
+                    },
+                    addr,
+                })
+            })
+            .collect();
+    }
+    let broadcast = match args.value("broadcast") {
+        Some(addr) if addr.contains(':') => addr.to_string(),
+        Some(addr) => format!("{}:{}", addr, DISCOVERY_PORT),
+        None => format!("255.255.255.255:{}", DISCOVERY_PORT),
+    };
+    let broadcast: SocketAddr = broadcast
+        .parse()
+        .map_err(|_| format!("Invalid value for --broadcast: {}", broadcast))?;
+    let wait = match args.value("wait") {
+        Some(wait) => parse_duration(wait)?,
+        None => Duration::from_secs(1),
+    };
+    discover(broadcast, wait).map_err(|e| format!("Discovery failed: {}", e))
+}
+
+fn connect(args: &Args) -> Result<Fleet, String> {
+    let members = members(args)?;
+    if members.is_empty() {
+        return Err("No units to command; none answered discovery".to_string());
+    }
+    let fleet = Fleet::connect(members).map_err(|e| e.to_string())?;
+    for (_, controller) in fleet.units() {
+        controller.set_echo(false);
+    }
+    Ok(fleet)
+}
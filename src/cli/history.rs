@@ -0,0 +1,139 @@
+use std::{
+    io::{self, Write},
+    time::SystemTime,
+};
+
+use microwave_controller::{
+    history::{HistoryRow, Signal, TelemetryHistory},
+    json::JsonValue,
+    units::{format_timestamp, parse_duration, parse_timestamp},
+};
+
+use super::Args;
+
+/// `mwctl history --log <file.csv> [--from <time>] [--to <time>] [--signal <name>]... [--aggregates <file>] [--json]`
+///
+/// Prints the signals recorded in a telemetry log between two times, one
+/// row per sample or aggregate, followed by a min/mean/max summary on stderr.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["json"])?;
+    let log = args
+        .value("log")
+        .ok_or("Missing required option --log <file.csv>")?;
+    let from = args.value("from").map(parse_time).transpose()?;
+    let to = args.value("to").map(parse_time).transpose()?;
+    let mut signals = Vec::new();
+    for names in args.values("signal") {
+        for name in names.split(',') {
+            signals.push(name.parse::<Signal>()?);
+        }
+    }
+    if signals.is_empty() {
+        signals = Signal::ALL.to_vec();
+    }
+
+    let mut history =
+        TelemetryHistory::open(log).map_err(|e| format!("Failed to open {}: {}", log, e))?;
+    if let Some(path) = args.value("aggregates") {
+        history = history.with_aggregates(path);
+    }
+    let rows = history
+        .rows(from, to)
+        .map_err(|e| format!("Failed to read the history: {}", e))?;
+
+    let mut stdout = io::stdout().lock();
+    let mut print = |line: String| writeln!(stdout, "{}", line).map_err(|e| e.to_string());
+    if !args.flag("json") {
+        let names: Vec<&str> = signals.iter().map(Signal::name).collect();
+        print(format!("timestamp,{}", names.join(",")))?;
+    }
+    for row in &rows {
+        if args.flag("json") {
+            print(row_json(row, &signals).to_string())?;
+            continue;
+        }
+        let values: Vec<String> = signals
+            .iter()
+            .map(|signal| {
+                row.value(*signal)
+                    .map(|value| format!("{:.3}", value))
+                    .unwrap_or_default()
+            })
+            .collect();
+        print(format!(
+            "{},{}",
+            format_timestamp(row.timestamp()),
+            values.join(",")
+        ))?;
+    }
+
+    let aggregates = rows
+        .iter()
+        .filter(|row| matches!(row, HistoryRow::Aggregate(_)))
+        .count();
+    eprintln!(
+        "{} samples, {} aggregates from {} file(s)",
+        rows.len() - aggregates,
+        aggregates,
+        history.files().count()
+    );
+    for signal in &signals {
+        let values: Vec<f64> = rows.iter().filter_map(|row| row.value(*signal)).collect();
+        if values.is_empty() {
+            continue;
+        }
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        eprintln!(
+            "  {:<20} min {:>10.3}  mean {:>10.3}  max {:>10.3}",
+            signal.name(),
+            min,
+            mean,
+            max
+        );
+    }
+    Ok(())
+}
+
+/// A UTC timestamp (`2026-10-14T09:30:00Z`), a date, or an interval before
+/// now (`2h`).
+pub fn parse_time(text: &str) -> Result<SystemTime, String> {
+    if let Ok(timestamp) = parse_timestamp(text) {
+        return Ok(timestamp);
+    }
+    if let Ok(midnight) = parse_timestamp(&format!("{}T00:00:00", text.trim())) {
+        return Ok(midnight);
+    }
+    let ago = parse_duration(text).map_err(|_| {
+        format!(
+            "Invalid time {}; use 2026-10-14T09:30:00Z, 2026-10-14 or 2h",
+            text
+        )
+    })?;
+    SystemTime::now()
+        .checked_sub(ago)
+        .ok_or_else(|| format!("Invalid time: {}", text))
+}
+
+/// `{"timestamp": ..., "<signal>": value}`, plus `end`, `samples` and each
+/// signal's `_min` and `_max` for an aggregate.
+fn row_json(row: &HistoryRow, signals: &[Signal]) -> JsonValue {
+    let mut json = JsonValue::object().with("timestamp", format_timestamp(row.timestamp()));
+    if let HistoryRow::Aggregate(aggregate) = row {
+        json = json
+            .with("end", format_timestamp(aggregate.end))
+            .with("samples", aggregate.samples);
+    }
+    for signal in signals {
+        json = json.with(signal.name(), row.value(*signal));
+        if let HistoryRow::Aggregate(aggregate) = row {
+            if let Some(stats) = signal.of_aggregate(aggregate) {
+                json = json
+                    .with(&format!("{}_min", signal.name()), stats.min)
+                    .with(&format!("{}_max", signal.name()), stats.max);
+            }
+        }
+    }
+    json
+}
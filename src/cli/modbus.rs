@@ -0,0 +1,53 @@
+use std::{thread, time::Duration};
+
+use microwave_controller::{modbus::ModbusGateway, CancellationToken};
+
+use super::{access_policy, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl modbus [--listen <addr>] [--rtu <port> [--rtu-baud <baud>]] [--unit <id>] [--access <policy.json>]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, CONNECTION_SWITCHES)?;
+    let unit: u8 = args.parse_value("unit")?.unwrap_or(1);
+    let rtu_baud: u32 = args.parse_value("rtu-baud")?.unwrap_or(19_200);
+    let rtu = args.value("rtu").map(str::to_string);
+    let listen = match (args.value("listen"), &rtu) {
+        (Some(addr), _) => Some(addr.to_string()),
+        (None, Some(_)) => None,
+        (None, None) => Some("127.0.0.1:502".to_string()),
+    };
+
+    let access = access_policy(&args)?;
+    let gateway = ModbusGateway::new(connect(&args)?, unit).with_access_policy(access);
+    let cancel = CancellationToken::new();
+
+    let rtu_thread = match rtu {
+        Some(port_name) => {
+            // At 19200 baud a character takes ~0.5 ms, so this is a few times the 3.5 character gap.
+            let mut port = serialport::new(&port_name, rtu_baud)
+                .timeout(Duration::from_millis(5))
+                .open()
+                .map_err(|e| format!("Failed to open {}: {}", port_name, e))?;
+            println!(
+                "Modbus RTU unit {} on {} at {} baud",
+                unit, port_name, rtu_baud
+            );
+            let gateway = gateway.clone();
+            let cancel = cancel.clone();
+            Some(thread::spawn(move || gateway.serve_rtu(&mut port, &cancel)))
+        }
+        None => None,
+    };
+
+    if let Some(addr) = listen {
+        gateway
+            .serve_tcp(addr.as_str(), &cancel)
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(rtu_thread) = rtu_thread {
+        rtu_thread
+            .join()
+            .map_err(|_| "Modbus RTU server panicked".to_string())?
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::{self, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use microwave_controller::{
+    controller_responses::parse_status, json::ToJson, units::parse_duration, Command, Controller,
+    ControllerError, StatusFlags, TelemetrySample,
+};
+
+use super::{black_box, connect, Args, TelemetryLog, CONNECTION_SWITCHES};
+
+/// `mwctl monitor [--interval <interval>] [--json] [--log <file.csv> [--downsample <raw_for>,<bucket>] ...]`
+///
+/// Prints one reading per interval until interrupted. With `--json` every
+/// reading is a single JSON object per line, for piping into other tools.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("json");
+    let args = Args::parse(args, &switches)?;
+    let interval = match args.value("interval") {
+        Some(interval) => parse_duration(interval)?,
+        None => Duration::from_millis(500),
+    };
+    let json = args.flag("json");
+    // Interrupting the monitor loses the last, incomplete aggregate.
+    let log = TelemetryLog::open(&args)?;
+
+    let controller = connect(&args)?;
+    controller.set_echo(false);
+    let recorder = black_box(&controller, &args)?.map(|black_box| black_box.telemetry_listener());
+    let mut stdout = io::stdout();
+    loop {
+        let started = Instant::now();
+        match read(&controller) {
+            Ok((sample, status)) => {
+                if let Some(log) = &log {
+                    (log.listener)(&sample);
+                }
+                if let Some(recorder) = &recorder {
+                    recorder(&sample);
+                }
+                let line = if json {
+                    sample
+                        .to_json()
+                        .with("efficiency_pct", sample.efficiency_percent())
+                        .with("status", status.to_string())
+                        .with("status_code", u64::from(status.0))
+                        .to_string()
+                } else {
+                    let mut supply = match (sample.supply_voltage_v, sample.supply_current_a) {
+                        (Some(volts), Some(amps)) => {
+                            format!("  supply {:.2} V {:.2} A", volts, amps)
+                        }
+                        _ => String::new(),
+                    };
+                    if let Some(efficiency) = sample.efficiency_percent() {
+                        supply += &format!("  eff {:.1} %", efficiency);
+                    }
+                    format!(
+                        "{:.2} MHz  set {:.2} dBm  PA {:.2} dBm  refl {:.2} dBm{}  status {}",
+                        sample.frequency_mhz,
+                        sample.power_setpoint_dbm,
+                        sample.forward_dbm,
+                        sample.reflected_dbm,
+                        supply,
+                        status
+                    )
+                };
+                writeln!(stdout, "{}", line).map_err(|e| e.to_string())?;
+                stdout.flush().map_err(|e| e.to_string())?;
+            }
+            // Keep watching through a missed reply; a dead link ends the monitor.
+            Err(ControllerError::Io(e)) => return Err(e),
+            Err(e) => eprintln!("{}", e),
+        }
+        thread::sleep(interval.saturating_sub(started.elapsed()));
+    }
+}
+
+fn read(controller: &Controller) -> Result<(TelemetrySample, StatusFlags), ControllerError> {
+    let sample = TelemetrySample::read(controller)?;
+    let status = parse_status(&controller.send(&Command::GetStatus { verbose: false })?)?;
+    Ok((sample, status))
+}
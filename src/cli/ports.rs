@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use microwave_controller::{port_path, DeviceProfile, UsbId};
+
+use super::{port_filter, Args};
+
+/// `mwctl ports [--usb-id <vid:pid>]... [--port-match <text>]... [--profile <file>] [--save <profile.json>]`
+///
+/// Lists the serial ports with their `/dev/serial/by-id/` links and USB
+/// strings, marking the ones autodetection would take for the board.
+/// `--save` stores the first of those, by its stable name, as the port of
+/// a profile.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &[])?;
+    let profile = match args.value("profile") {
+        Some(path) => {
+            Some(DeviceProfile::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?)
+        }
+        None => None,
+    };
+    let filter = port_filter(&args, profile.as_ref())?;
+    let ports =
+        serialport::available_ports().map_err(|e| format!("Failed to list serial ports: {}", e))?;
+    if ports.is_empty() {
+        println!("No serial ports found");
+    }
+
+    let mut board = None;
+    for port in &ports {
+        let matches = filter.matches(port);
+        let name = port_path::stable_name(&port.port_name);
+        if matches && board.is_none() {
+            board = Some(name.clone());
+        }
+        println!("{} {}", if matches { '*' } else { ' ' }, name);
+        if name != port.port_name {
+            println!("    device:       {}", port.port_name);
+        }
+        match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => {
+                let id = UsbId {
+                    vid: usb.vid,
+                    pid: usb.pid,
+                };
+                println!("    USB ID:       {}", id);
+                for (label, text) in [
+                    ("manufacturer", &usb.manufacturer),
+                    ("product", &usb.product),
+                    ("serial", &usb.serial_number),
+                ] {
+                    if let Some(text) = text {
+                        println!("    {:<13} {}", format!("{}:", label), text);
+                    }
+                }
+            }
+            other => println!("    type:         {:?}", other),
+        }
+    }
+    println!("\n* matches {}", filter);
+
+    if let Some(path) = args.value("save") {
+        let board = board.ok_or("No port matches; nothing to save")?;
+        let profile = if Path::new(path).exists() {
+            DeviceProfile::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?
+        } else {
+            let name = Path::new(path).file_stem().unwrap_or_default();
+            DeviceProfile::new(&name.to_string_lossy())
+        };
+        profile
+            .with_port(&board)
+            .save(path)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Saved {} as the port in {}", board, path);
+    }
+    Ok(())
+}
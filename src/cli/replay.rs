@@ -0,0 +1,57 @@
+use std::{fs, thread, time::Duration};
+
+use microwave_controller::{
+    session::{parse_session_log, RecordKind, SessionReplay},
+    Command,
+};
+
+use super::Args;
+
+/// `mwctl replay <session.log> [--speed <factor>] [--instant]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["instant"])?;
+    let path = args.require_positional(0, "session.log")?;
+    let speed: f64 = args.parse_value("speed")?.unwrap_or(1.0);
+    if speed <= 0.0 {
+        return Err("--speed must be positive".to_string());
+    }
+    let instant = args.flag("instant");
+
+    let log = fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let mut replay = SessionReplay::new(parse_session_log(&log)?);
+    println!("Replaying {} records from {}", replay.len(), path);
+
+    let mut previous_offset = Duration::ZERO;
+    while let Some(step) = replay.next_step() {
+        if !instant {
+            let wait = step.offset.saturating_sub(previous_offset);
+            thread::sleep(wait.div_f64(speed));
+        }
+        previous_offset = step.offset;
+
+        let kind = match &step.record.kind {
+            RecordKind::Tx => ">",
+            RecordKind::Rx => "<",
+            RecordKind::Err => "!",
+            RecordKind::Telemetry(_) => "~",
+            RecordKind::Other(kind) => kind.as_str(),
+        };
+        let command = match step.record.kind {
+            RecordKind::Tx => Command::parse(&step.record.text)
+                .map(|command| format!("\t({})", command.name()))
+                .unwrap_or_default(),
+            _ => String::new(),
+        };
+        println!(
+            "[{:>9.3}s] {}\t{}{}",
+            step.offset.as_secs_f64(),
+            kind,
+            step.record.text,
+            command
+        );
+        if step.state_changed {
+            println!("            state: {}", replay.state());
+        }
+    }
+    Ok(())
+}
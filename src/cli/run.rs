@@ -0,0 +1,148 @@
+use std::{sync::Arc, time::Duration};
+
+use microwave_controller::{
+    json, recipe::Recipe, units::parse_duration, CancellationToken, ControllerEvent, Derater,
+    EnergyMeter, FanControl, LocalLockout, ReportRecorder, Retune, SafetyGuard, TelemetryPoller,
+    TelemetrySample,
+};
+
+use super::{
+    arm_rf, black_box, connect, derating_policy, fan_policy, otlp_exporter, Args, TelemetryLog,
+    CONNECTION_SWITCHES,
+};
+
+/// `mwctl run <recipe.json> [--telemetry <interval>] [--retune <interval>] [--report <file>] [--otlp <endpoint>] [--exclusive]
+/// [--derate <°C>:<dB>]... [--hysteresis <°C>]
+/// [--fan-curve <°C>:<%>,... [--fan-full-above <power>] [--fan-spin-down <interval>]]
+/// [--log <file.csv> [--downsample <raw_for>,<bucket>] [--aggregates <file.csv>] [--keep-reflected <power>]]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.extend(["yes", "exclusive"]);
+    let args = Args::parse(args, &switches)?;
+    let path = args.require_positional(0, "recipe.json")?;
+    let interval = match args.value("telemetry") {
+        Some(interval) => parse_duration(interval)?,
+        None => Duration::from_secs(1),
+    };
+    let mut recipe = Recipe::load(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+    if let Some(interval) = args.value("retune") {
+        let interval = parse_duration(interval)?;
+        recipe.retune = Some(match recipe.retune.take() {
+            Some(retune) => Retune { interval, ..retune },
+            None => Retune::new(interval),
+        });
+    }
+
+    let derating = derating_policy(&args)?;
+    let fans = fan_policy(&args)?;
+    let log = TelemetryLog::open(&args)?;
+
+    let controller = connect(&args)?;
+    arm_rf(&controller, &args, recipe.max_power_dbm())?;
+    // Locked out until the recipe is over, so nobody at the board changes it.
+    let lockout = match args.flag("exclusive") {
+        true => Some(LocalLockout::engage(&controller).map_err(|e| {
+            format!(
+                "Could not lock the front panel out, run without --exclusive: {}",
+                e
+            )
+        })?),
+        false => None,
+    };
+    let exporter = otlp_exporter(&controller, &args, "mwctl run")?;
+    let black_box = black_box(&controller, &args)?;
+    let energy = EnergyMeter::new();
+    let mut poller = TelemetryPoller::new(&controller, interval);
+    poller.subscribe(energy.listener());
+    if let Some(log) = &log {
+        poller.subscribe(log.listener.clone());
+    }
+    if let Some(black_box) = &black_box {
+        poller.subscribe(black_box.telemetry_listener());
+    }
+    if let Some(exporter) = &exporter {
+        poller.subscribe(exporter.telemetry_listener());
+    }
+    let recorder = match args.value("report") {
+        Some(_) => {
+            let recorder = ReportRecorder::new(&controller).map_err(|e| e.to_string())?;
+            poller.subscribe(recorder.listener());
+            Some(recorder)
+        }
+        None => None,
+    };
+    if let Some(policy) = derating {
+        let derater = Derater::attach(&controller, policy).map_err(|e| e.to_string())?;
+        poller.subscribe(derater.listener());
+        controller
+            .subscribe(Arc::new(|event: &ControllerEvent| {
+                if let ControllerEvent::PowerDerated { .. } = event {
+                    println!("{}", event);
+                }
+            }))
+            .map_err(|e| e.to_string())?;
+    }
+    if let Some(policy) = fans {
+        poller.subscribe(FanControl::new(&controller, policy).listener());
+    }
+    let readout = energy.clone();
+    poller.subscribe(Arc::new(move |sample: &TelemetrySample| {
+        let efficiency = sample
+            .efficiency_percent()
+            .map(|efficiency| format!("  eff {:.1} %", efficiency))
+            .unwrap_or_default();
+        println!(
+            "{:.2} MHz  fwd {:.2} dBm  refl {:.2} dBm  delivered {:.1} W  {:.3} kJ{}",
+            sample.frequency_mhz,
+            sample.forward_dbm,
+            sample.reflected_dbm,
+            sample.delivered_watts(),
+            readout.joules() / 1000.0,
+            efficiency
+        );
+    }));
+    let telemetry_cancel = CancellationToken::new();
+    let telemetry = poller.spawn(telemetry_cancel.clone());
+
+    println!(
+        "Running recipe {} ({} steps)",
+        recipe.name,
+        recipe.steps.len()
+    );
+    let guard = SafetyGuard::new(&controller);
+    let result = recipe.run(&controller, &CancellationToken::new());
+    drop(guard);
+    drop(lockout);
+    telemetry_cancel.cancel();
+    let _ = telemetry.join();
+    if let Some(log) = &log {
+        log.finish();
+    }
+    if let Some(exporter) = &exporter {
+        exporter.flush(Duration::from_secs(5));
+    }
+    println!(
+        "Delivered {:.3} kJ ({:.6} kWh)",
+        energy.joules() / 1000.0,
+        energy.kwh()
+    );
+
+    if let (Some(path), Some(recorder)) = (args.value("report"), recorder) {
+        let settings = recipe
+            .steps
+            .iter()
+            .enumerate()
+            .map(|(i, step)| (format!("Step {}", i + 1), json::to_string(step)))
+            .collect();
+        let outcome = match &result {
+            Ok(()) => "Completed".to_string(),
+            Err(e) => format!("Failed: {}", e),
+        };
+        recorder
+            .finish(&format!("Recipe {}", recipe.name), settings, &outcome)
+            .save(path)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote report to {}", path);
+    }
+    result.map_err(|e| e.to_string())
+}
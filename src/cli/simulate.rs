@@ -0,0 +1,43 @@
+use std::{fs, os::unix::fs::symlink, path::Path};
+
+use microwave_controller::{pty::VirtualDevice, CancellationToken, Simulator};
+
+use super::Args;
+
+/// `mwctl simulate --pty [--link <path>] [--quiet]`
+///
+/// Runs the simulated board on a new pseudo-terminal and prints its path
+/// for a serial program to open, until interrupted.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let args = Args::parse(args, &["pty", "quiet"])?;
+    if !args.flag("pty") {
+        return Err("Missing --pty; the simulator is only served on a pseudo-terminal".to_string());
+    }
+    let device = VirtualDevice::open().map_err(|e| format!("Failed to create a pty: {}", e))?;
+    println!("Simulated board on {}", device.path().display());
+    if let Some(link) = args.value("link") {
+        // A link left behind by an earlier run points at a pty that is gone.
+        if fs::symlink_metadata(link).is_ok_and(|metadata| metadata.file_type().is_symlink()) {
+            fs::remove_file(link).map_err(|e| format!("Failed to replace {}: {}", link, e))?;
+        }
+        symlink(device.path(), Path::new(link))
+            .map_err(|e| format!("Failed to link {}: {}", link, e))?;
+        println!("Linked as {}", link);
+    }
+
+    let quiet = args.flag("quiet");
+    device
+        .serve(
+            &Simulator::new(),
+            &CancellationToken::new(),
+            &mut |line, reply| {
+                if !quiet {
+                    println!("RX:\t{}", line);
+                    for reply in reply.lines() {
+                        println!("TX:\t{}", reply);
+                    }
+                }
+            },
+        )
+        .map_err(|e| format!("Simulated board stopped: {}", e))
+}
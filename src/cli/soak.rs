@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use microwave_controller::{
+    units::{format_timestamp, parse_duration},
+    CancellationToken, DeratingRule, ReportRecorder, SoakTest,
+};
+
+use super::{arm_rf, black_box, connect, Args, TelemetryLog, CONNECTION_SWITCHES};
+
+/// `mwctl soak --power <power> --duration <interval> [--frequency <MHz>] [--telemetry <interval>] [--status-interval <interval>]
+/// [--derate <°C>:<dB>]... [--hysteresis <°C>] [--max-temperature <°C>] [--max-reflected <power>] [--tolerance <dB>]
+/// [--report <file>] [<telemetry log options>]`
+///
+/// Burn-in of one unit: fails, after writing the report, unless the unit
+/// passed.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let power_dbm = args
+        .parse_power("power")?
+        .ok_or("Missing required option --power")?;
+    let duration = parse_duration(
+        args.value("duration")
+            .ok_or("Missing required option --duration, e.g. 8h")?,
+    )?;
+    let mut test = SoakTest::new(power_dbm, duration);
+    if let Some(mhz) = args.parse_value("frequency")? {
+        test = test.with_frequency(mhz);
+    }
+    if let Some(interval) = args.value("telemetry") {
+        test = test.with_telemetry_interval(parse_duration(interval)?);
+    }
+    if let Some(interval) = args.value("status-interval") {
+        test = test.with_status_interval(parse_duration(interval)?);
+    }
+    if test.telemetry_interval.is_zero() {
+        return Err("--telemetry must be longer than zero".to_string());
+    }
+    for rule in args.values("derate") {
+        test = test.with_derating(rule.parse::<DeratingRule>()?);
+    }
+    if let Some(celsius) = args.parse_value("hysteresis")? {
+        test = test.with_hysteresis(celsius);
+    }
+    if let Some(celsius) = args.parse_value("max-temperature")? {
+        test = test.with_max_temperature(celsius);
+    }
+    if let Some(dbm) = args.parse_power("max-reflected")? {
+        test = test.with_max_reflected(dbm);
+    }
+    if let Some(db) = args.parse_value("tolerance")? {
+        test = test.with_tolerance(db);
+    }
+
+    let log = TelemetryLog::open(&args)?;
+    let controller = connect(&args)?;
+    arm_rf(&controller, &args, Some(power_dbm))?;
+    controller.set_echo(false);
+    let black_box = black_box(&controller, &args)?;
+    let recorder = ReportRecorder::new(&controller).map_err(|e| e.to_string())?;
+    let mut listeners = vec![recorder.listener()];
+    listeners.extend(log.as_ref().map(|log| log.listener.clone()));
+    listeners.extend(
+        black_box
+            .as_ref()
+            .map(|black_box| black_box.telemetry_listener()),
+    );
+
+    println!(
+        "Soaking at {:.2} dBm for {:.2} h",
+        power_dbm,
+        duration.as_secs_f64() / 3600.0
+    );
+    let mut last_print = None::<Instant>;
+    let result = test
+        .run_with_samples(&controller, &CancellationToken::new(), &mut |sample| {
+            for listener in &listeners {
+                listener(sample);
+            }
+            // One line a minute keeps a shift's worth of output readable.
+            if last_print.is_none_or(|at| at.elapsed() >= Duration::from_secs(60)) {
+                last_print = Some(Instant::now());
+                println!(
+                    "{}  fwd {:.2} dBm  refl {:.2} dBm  {}",
+                    format_timestamp(sample.timestamp),
+                    sample.forward_dbm,
+                    sample.reflected_dbm,
+                    sample
+                        .temperature_c
+                        .map(|t| format!("{:.1} °C", t))
+                        .unwrap_or_default()
+                );
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    if let Some(log) = &log {
+        log.finish();
+    }
+    for (_, event) in &result.events {
+        println!("{}", event);
+    }
+
+    let mut summary = vec![
+        (
+            "RF on".to_string(),
+            format!("{:.2} h", result.rf_on.as_secs_f64() / 3600.0),
+        ),
+        ("Samples".to_string(), result.samples.to_string()),
+        (
+            "Status checks".to_string(),
+            result.status_checks.to_string(),
+        ),
+        (
+            "Max derating".to_string(),
+            format!("{} dB", result.max_derating_db),
+        ),
+    ];
+    if let Some(celsius) = result.max_temperature_c {
+        summary.push(("Max temperature".to_string(), format!("{:.1} °C", celsius)));
+    }
+    if let Some(dbm) = result.max_reflected_dbm {
+        summary.push(("Max reflected".to_string(), format!("{:.2} dBm", dbm)));
+    }
+    for (label, value) in &summary {
+        println!("{:<18} {}", label, value);
+    }
+    println!("{}", result.verdict());
+
+    if let Some(path) = args.value("report") {
+        let mut settings = test.settings();
+        settings.extend(summary);
+        let mut report = recorder.finish("Burn-in", settings, &result.verdict());
+        report.events.extend(result.events.iter().cloned());
+        report.events.sort_by_key(|(time, _)| *time);
+        report
+            .save(path)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote report to {}", path);
+    }
+    match result.passed() {
+        true => Ok(()),
+        false => Err(format!("Burn-in failed: {}", result.failures.join("; "))),
+    }
+}
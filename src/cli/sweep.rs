@@ -0,0 +1,123 @@
+use std::{
+    fs,
+    io::{self, Write},
+    time::Duration,
+};
+
+use microwave_controller::{
+    parquet::{Compression, ParquetTable},
+    report::sweep_svg,
+    resonance::fit_resonance,
+    sweep::{best_match, load_frequencies, points_to_csv, points_to_touchstone},
+    units::parse_duration,
+    CancellationToken, Progress, Sweep,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// Width of the progress bar in characters.
+const BAR_WIDTH: usize = 40;
+
+/// `mwctl sweep [--start <MHz>] [--stop <MHz>] [--step <MHz> | --list <file>] [--power <power>] [--dwell <interval>] [--output <file.csv>] [--touchstone <file.s1p>] [--plot <file.svg>] [--parquet <file.parquet>]`
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let dwell = match args.value("dwell") {
+        Some(dwell) => parse_duration(dwell)?,
+        None => Duration::from_millis(50),
+    };
+    let power_dbm = args.parse_power("power")?.unwrap_or(10.0);
+    let sweep = match args.value("list") {
+        Some(path) => {
+            if ["start", "stop", "step"]
+                .iter()
+                .any(|option| args.value(option).is_some())
+            {
+                return Err("--list replaces --start, --stop and --step".to_string());
+            }
+            let frequencies =
+                load_frequencies(path).map_err(|e| format!("Failed to load {}: {}", path, e))?;
+            Sweep::list(frequencies, power_dbm, dwell)
+        }
+        None => Sweep::linear(
+            args.parse_value("start")?.unwrap_or(2400.0),
+            args.parse_value("stop")?.unwrap_or(2500.0),
+            args.parse_value("step")?.unwrap_or(1.0),
+            power_dbm,
+            dwell,
+        ),
+    };
+
+    let controller = connect(&args)?;
+    arm_rf(&controller, &args, Some(sweep.power_dbm))?;
+    // The progress bar owns the terminal while the sweep runs.
+    controller.set_echo(false);
+    let points = sweep
+        .run_with_progress(&controller, &CancellationToken::new(), &mut draw_progress)
+        .map_err(|e| e.to_string());
+    eprintln!();
+    let points = points?;
+
+    let best = best_match(&points).ok_or("The sweep measured no points")?;
+    println!("Points             {}", points.len());
+    println!("Best frequency     {:.2} MHz", best.frequency_mhz);
+    println!(
+        "Min S11            {:.2} dB (return loss {:.2} dB)",
+        best.s11_db(),
+        -best.s11_db()
+    );
+    println!(
+        "Reflected / fwd    {:.2} / {:.2} dBm",
+        best.reflected_dbm, best.forward_dbm
+    );
+    match fit_resonance(&points) {
+        Some(resonance) => {
+            println!("Resonance          {:.3} MHz", resonance.frequency_mhz);
+            println!("Bandwidth          {:.3} MHz", resonance.bandwidth_mhz);
+            println!("Loaded Q           {:.0}", resonance.loaded_q);
+        }
+        None => println!("Resonance          no dip to fit"),
+    }
+
+    if let Some(path) = args.value("output") {
+        fs::write(path, points_to_csv(&points))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+    if let Some(path) = args.value("touchstone") {
+        fs::write(path, points_to_touchstone(&points))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+    if let Some(path) = args.value("parquet") {
+        ParquetTable::from_sweep(&points)
+            .save(path, Compression::Gzip)
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+    if let Some(path) = args.value("plot") {
+        fs::write(path, sweep_svg(&points))
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+    Ok(())
+}
+
+fn draw_progress(progress: &Progress) {
+    let filled = (progress.fraction() * BAR_WIDTH as f32).round() as usize;
+    let eta = progress
+        .eta()
+        .map(|eta| format!("ETA {:.0} s", eta.as_secs_f32()))
+        .unwrap_or_default();
+    eprint!(
+        "\r[{}{}] {}/{}  {:.2} MHz  {}   ",
+        "#".repeat(filled),
+        "-".repeat(BAR_WIDTH - filled),
+        progress.completed,
+        progress.total,
+        progress.frequency_mhz.unwrap_or_default(),
+        eta
+    );
+    let _ = io::stderr().flush();
+}
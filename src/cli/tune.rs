@@ -0,0 +1,108 @@
+use std::{
+    fs::{self, OpenOptions},
+    time::Duration,
+};
+
+use microwave_controller::{
+    nanovna::{characterize, NanoVna},
+    sweep::{auto_match, SweepSegment},
+    units::parse_duration,
+    CancellationToken, Command, Controller, Sweep,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl tune [--vna <port>] [--start <MHz>] [--stop <MHz>] [--step <MHz>] [--power <power>] [--dwell <interval>] [--session <file>] [--touchstone <file.s1p>] [--apply]`
+///
+/// Without `--vna` the generator sweeps itself at low power and the best
+/// frequency is always applied.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("apply");
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let segment = SweepSegment {
+        start_mhz: args.parse_value("start")?.unwrap_or(2400.0),
+        stop_mhz: args.parse_value("stop")?.unwrap_or(2500.0),
+        step_mhz: args.parse_value("step")?.unwrap_or(1.0),
+    };
+
+    let controller = connect(&args)?;
+    if let Some(path) = args.value("session") {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path, e))?;
+        controller
+            .set_trace(Box::new(file))
+            .map_err(|e| e.to_string())?;
+    }
+    let vna_port = match args.value("vna") {
+        Some(port) => port,
+        None => return tune_with_generator(&controller, &args, segment),
+    };
+    let mut vna = NanoVna::open(vna_port).map_err(|e| e.to_string())?;
+    let measurement = characterize(&controller, &mut vna, &segment).map_err(|e| e.to_string())?;
+    print!("{}", measurement.to_csv());
+    if let Some(path) = args.value("touchstone") {
+        fs::write(path, measurement.to_touchstone())
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote {}", path);
+    }
+
+    let best = measurement
+        .best_match()
+        .ok_or("The NanoVNA returned no points")?;
+    println!(
+        "Best match: {:.2} dB at {:.2} MHz",
+        best.s11_db(),
+        best.frequency_mhz
+    );
+    if args.flag("apply") {
+        controller
+            .send(&Command::SetFrequency(best.frequency_mhz))
+            .map_err(|e| e.to_string())?;
+        println!("Generator frequency set to {:.2} MHz", best.frequency_mhz);
+    }
+    Ok(())
+}
+
+/// Matches with the generator's own forward/reflected readback.
+fn tune_with_generator(
+    controller: &Controller,
+    args: &Args,
+    segment: SweepSegment,
+) -> Result<(), String> {
+    let dwell = match args.value("dwell") {
+        Some(dwell) => parse_duration(dwell)?,
+        None => Duration::from_millis(50),
+    };
+    let sweep = Sweep::linear(
+        segment.start_mhz,
+        segment.stop_mhz,
+        segment.step_mhz,
+        args.parse_power("power")?.unwrap_or(10.0),
+        dwell,
+    );
+    arm_rf(controller, args, Some(sweep.power_dbm))?;
+    let result =
+        auto_match(controller, &CancellationToken::new(), &sweep).map_err(|e| e.to_string())?;
+    println!(
+        "Before: {:.2} MHz  reflected {:.2} dBm  S11 {:.2} dB",
+        result.before.frequency_mhz,
+        result.before.reflected_dbm,
+        result.before.s11_db()
+    );
+    println!(
+        "After:  {:.2} MHz  reflected {:.2} dBm  S11 {:.2} dB",
+        result.after.frequency_mhz,
+        result.after.reflected_dbm,
+        result.after.s11_db()
+    );
+    println!(
+        "Reflected power changed by {:+.2} dB",
+        result.after.reflected_dbm - result.before.reflected_dbm
+    );
+    Ok(())
+}
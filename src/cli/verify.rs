@@ -0,0 +1,93 @@
+use microwave_controller::{
+    acceptance::Outcome,
+    sweep::SweepSegment,
+    units::{parse_duration, parse_power},
+    AcceptanceTest, CancellationToken,
+};
+
+use super::{arm_rf, connect, Args, CONNECTION_SWITCHES};
+
+/// `mwctl verify [--power <power,...>] [--frequency <MHz>] [--tolerance <dB>] [--dwell <interval>]
+/// [--start <MHz>] [--stop <MHz>] [--step <MHz>] [--max-s11 <dB>] [--junit <report.xml>]`
+///
+/// Acceptance test of a board on a dummy load: fails, after writing the
+/// report, unless every case passed.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let mut switches = CONNECTION_SWITCHES.to_vec();
+    switches.push("yes");
+    let args = Args::parse(args, &switches)?;
+    let power_levels_dbm = args
+        .value("power")
+        .unwrap_or("10,20")
+        .split(',')
+        .map(|level| parse_power(level).map_err(|e| format!("Invalid value for --power: {}", e)))
+        .collect::<Result<Vec<f32>, String>>()?;
+    let mut test = AcceptanceTest::new(power_levels_dbm);
+    if let Some(mhz) = args.parse_value("frequency")? {
+        test = test.with_frequency(mhz);
+    }
+    if let Some(db) = args.parse_value("tolerance")? {
+        test = test.with_tolerance(db);
+    }
+    if let Some(dwell) = args.value("dwell") {
+        test = test.with_dwell(parse_duration(dwell)?);
+    }
+    if let Some(db) = args.parse_value("max-s11")? {
+        test = test.with_max_s11(db);
+    }
+    let sweep = SweepSegment {
+        start_mhz: args.parse_value("start")?.unwrap_or(test.sweep.start_mhz),
+        stop_mhz: args.parse_value("stop")?.unwrap_or(test.sweep.stop_mhz),
+        step_mhz: args.parse_value("step")?.unwrap_or(test.sweep.step_mhz),
+    };
+    test = test.with_sweep(sweep);
+
+    let controller = connect(&args)?;
+    let max_power_dbm = test
+        .power_levels_dbm
+        .iter()
+        .copied()
+        .fold(f32::NEG_INFINITY, f32::max);
+    arm_rf(&controller, &args, Some(max_power_dbm))?;
+    controller.set_echo(false);
+
+    let report = test
+        .run(&controller, &CancellationToken::new())
+        .map_err(|e| e.to_string())?;
+    for (name, value) in &report.properties {
+        println!("{:<10} {}", name, value);
+    }
+    for case in &report.cases {
+        let (verdict, reason) = match &case.outcome {
+            Outcome::Passed => ("PASS", None),
+            Outcome::Failed(reason) => ("FAIL", Some(reason)),
+            Outcome::Skipped(reason) => ("SKIP", Some(reason)),
+        };
+        print!(
+            "{}  {:<18} {:>7.2} s",
+            verdict,
+            case.name,
+            case.time.as_secs_f64()
+        );
+        match reason {
+            Some(reason) => println!("  {}", reason),
+            None => println!(),
+        }
+    }
+
+    if let Some(path) = args.value("junit") {
+        report
+            .save_junit(path, "mwctl verify")
+            .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+        println!("Wrote report to {}", path);
+    }
+    match report.passed() {
+        true => Ok(()),
+        false => Err(format!(
+            "Acceptance failed: {} failed, {} skipped of {} cases",
+            report.failures(),
+            report.skipped(),
+            report.cases.len()
+        )),
+    }
+}
@@ -0,0 +1,1313 @@
+#[cfg(feature = "serial")]
+use serialport::{available_ports, SerialPort, SerialPortInfo};
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Mutex, TryLockError,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::audit::AuditLog;
+use crate::controller_commands::Command;
+use crate::controller_responses::{
+    check_reply, parse_status, parse_value, parse_values, parse_verbose_status,
+};
+use crate::error::{ControllerError, DeviceErrorKind, DeviceFault};
+use crate::events::{ControllerEvent, EventListener, Exchange, ExchangeListener};
+use crate::interlock::Interlock;
+use crate::latency::{AdaptiveTimeouts, LatencyEstimate, TimeoutBounds, DEFAULT_RESPONSE_TIMEOUT};
+use crate::lifecycle::Lifecycle;
+#[cfg(feature = "serial")]
+use crate::port_filter::PortFilter;
+use crate::port_lock::PortLock;
+#[cfg(feature = "serial")]
+use crate::port_path;
+use crate::priority::{PortQueue, Priority};
+use crate::protocol::{is_error_reply, StatusFlags, POWER_RANGE_DBM};
+#[cfg(feature = "serial")]
+use crate::serial_settings::SerialSettings;
+use crate::settings::{DeviceSettings, DllSettings};
+use crate::transport::{ClosedTransport, Reopener, TcpTransport, Transport};
+use crate::units::format_timestamp;
+
+/// Tries at reopening a stale port, the first right away and the rest after
+/// a doubling backoff, about four seconds in all.
+const REOPEN_ATTEMPTS: u32 = 5;
+const REOPEN_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Shared handle to a connected signal generator.
+///
+/// Cloning is cheap and every clone talks to the same link. Each command is
+/// written and its reply read while holding the port lock, so transactions
+/// issued from different threads never interleave on the bus.
+///
+/// The link is any [`Transport`]: a serial port, TCP bridge, [`Simulator`]
+/// or [`MockTransport`]. It is boxed rather than a type parameter so that
+/// handles over different links stay interchangeable.
+///
+/// [`Simulator`]: crate::simulator::Simulator
+/// [`MockTransport`]: crate::transport::MockTransport
+#[derive(Clone)]
+pub struct Controller {
+    port: Arc<Mutex<Box<dyn Transport>>>,
+    queue: Arc<PortQueue>,
+    /// Priority of this handle's exchanges while waiting for the port.
+    priority: Priority,
+    port_name: Arc<str>,
+    rf_enabled: Arc<AtomicBool>,
+    dll: Arc<Mutex<DllSettings>>,
+    interlocks: Arc<Mutex<Vec<Arc<dyn Interlock>>>>,
+    listeners: Arc<Mutex<Vec<EventListener>>>,
+    exchange_listeners: Arc<Mutex<Vec<ExchangeListener>>>,
+    trace: Arc<Mutex<Option<Box<dyn Write + Send>>>>,
+    cache: Arc<Mutex<ReplyCache>>,
+    last_reply: Arc<Mutex<Instant>>,
+    error_retries: Arc<AtomicU32>,
+    pacing: Arc<Mutex<Pacing>>,
+    /// Learned reply timeouts; `None` waits `response_timeout` for every reply.
+    timeouts: Arc<Mutex<Option<AdaptiveTimeouts>>>,
+    /// Reply timeout while adaptive timeouts are off.
+    response_timeout: Arc<Mutex<Duration>>,
+    /// Why set commands are refused, while an identity check has not passed.
+    identity_gate: Arc<Mutex<Option<String>>>,
+    /// The board's `$IDN` reply, checked again after the port is reopened.
+    identity: Arc<Mutex<Option<String>>>,
+    /// Opens the port again when its handle goes stale; `None` for links
+    /// that cannot be reopened.
+    reopen: Arc<Mutex<Option<Reopener>>>,
+    echo: Arc<AtomicBool>,
+    read_only: Arc<AtomicBool>,
+    /// Whether the front panel is locked out; locked again after the port
+    /// is reopened.
+    exclusive_remote: Arc<AtomicBool>,
+    audit: Arc<Mutex<Option<AuditLog>>>,
+    /// Who is issuing commands through this handle, for the audit log.
+    actor: Arc<str>,
+    writer: Arc<Mutex<WriterLock>>,
+    lifecycle: Arc<Mutex<Lifecycle>>,
+    /// Keeps other processes off the serial port until the last clone is dropped.
+    port_lock: Option<Arc<PortLock>>,
+}
+
+/// Which actor may change the board while single-writer arbitration is on.
+#[derive(Default)]
+struct WriterLock {
+    enabled: bool,
+    owner: Option<Arc<str>>,
+}
+
+/// Recent query replies served by [`Controller::send_cached`].
+#[derive(Default)]
+struct ReplyCache {
+    ttl: Duration,
+    /// Command line, time received and reply.
+    replies: Vec<(String, Instant, String)>,
+}
+
+impl ReplyCache {
+    fn get(&self, line: &str) -> Option<String> {
+        self.replies
+            .iter()
+            .find(|(cached, received, _)| cached == line && received.elapsed() < self.ttl)
+            .map(|(_, _, reply)| reply.clone())
+    }
+
+    fn insert(&mut self, line: String, reply: &str) {
+        if self.ttl.is_zero() {
+            return;
+        }
+        self.replies.retain(|(cached, _, _)| *cached != line);
+        self.replies.push((line, Instant::now(), reply.to_string()));
+    }
+}
+
+/// How [`Controller::send_checked`] treats a command beyond the checks
+/// every command gets.
+#[derive(Clone, Copy)]
+struct SendMode {
+    /// Subject to single-writer arbitration.
+    arbitrated: bool,
+    /// Error replies are reported as [`ControllerEvent::DeviceFault`].
+    report_faults: bool,
+}
+
+impl SendMode {
+    const NORMAL: SendMode = SendMode {
+        arbitrated: true,
+        report_faults: true,
+    };
+    const SYSTEM: SendMode = SendMode {
+        arbitrated: false,
+        report_faults: true,
+    };
+    const OPTIONAL: SendMode = SendMode {
+        arbitrated: true,
+        report_faults: false,
+    };
+}
+
+/// Minimum quiet time between exchanges, see [`Controller::set_min_command_interval`].
+#[derive(Default)]
+struct Pacing {
+    min_interval: Duration,
+    last_exchange: Option<Instant>,
+}
+
+impl Controller {
+    /// Connects to the first autodetected signal generator board.
+    #[cfg(feature = "serial")]
+    pub fn connect() -> Result<Controller, ControllerError> {
+        Controller::connect_with(&SerialSettings::default())
+    }
+
+    /// Same as [`Controller::connect`], opening the port with `settings`.
+    #[cfg(feature = "serial")]
+    pub fn connect_with(settings: &SerialSettings) -> Result<Controller, ControllerError> {
+        Controller::connect_matching(&PortFilter::default(), settings)
+    }
+
+    /// Same as [`Controller::connect_with`], taking the first port that
+    /// matches `filter` rather than the board's VID/PID alone.
+    #[cfg(feature = "serial")]
+    pub fn connect_matching(
+        filter: &PortFilter,
+        settings: &SerialSettings,
+    ) -> Result<Controller, ControllerError> {
+        let signal_generators = autodetect_ports(filter)?;
+
+        let first_signal_generator = match signal_generators.first() {
+            Some(info) => info,
+            None => return Err(ControllerError::NoDeviceFound),
+        };
+        // Opened by its by-id link where there is one, so a reopen finds it
+        // again after re-enumeration.
+        let port_name = port_path::stable_name(&first_signal_generator.port_name);
+        println!("Connecting to signal generator: {:?}", port_name);
+
+        Controller::open_with(&port_name, settings)
+    }
+
+    /// Opens the named port with the board's default serial settings. Fails
+    /// with [`ControllerError::Busy`] while another process has it open.
+    #[cfg(feature = "serial")]
+    pub fn open(port_name: &str) -> Result<Controller, ControllerError> {
+        Controller::open_with(port_name, &SerialSettings::default())
+    }
+
+    /// Opens the named port with `settings`, for boards whose UART has been
+    /// reconfigured.
+    ///
+    /// A `/dev/serial/by-id/` link is resolved to the device it points to
+    /// when opening and again on every reopen, and the lock is taken on that
+    /// device, so the same port cannot be opened twice under two names.
+    #[cfg(feature = "serial")]
+    pub fn open_with(
+        port_name: &str,
+        settings: &SerialSettings,
+    ) -> Result<Controller, ControllerError> {
+        settings
+            .validate()
+            .map_err(ControllerError::InvalidParameter)?;
+        let device = port_path::resolve(port_name);
+        let lock = PortLock::acquire(&device)?;
+        match settings.builder(&device).open() {
+            Ok(port) => {
+                if device == port_name {
+                    println!("Successfully connected to {} at {}", port_name, settings);
+                } else {
+                    println!(
+                        "Successfully connected to {} ({}) at {}",
+                        port_name, device, settings
+                    );
+                }
+                let controller = Controller {
+                    port_name: port_name.into(),
+                    port_lock: Some(Arc::new(lock)),
+                    ..Controller::from_port(port)
+                };
+                controller.set_response_timeout(settings.response_timeout);
+                let (port_name, settings) = (port_name.to_string(), *settings);
+                controller.set_reopen(Some(Arc::new(move || {
+                    settings
+                        .builder(&port_path::resolve(&port_name))
+                        .open()
+                        .map(|port| Box::new(port) as Box<dyn Transport>)
+                        .map_err(|e| ControllerError::Connection(format!("{}: {:?}", port_name, e)))
+                })))?;
+                Ok(controller)
+            }
+            Err(e) => Err(ControllerError::Connection(format!(
+                "{}: {:?}",
+                port_name, e
+            ))),
+        }
+    }
+
+    /// Connects to a serial-to-Ethernet bridge at `addr` (`host:port`).
+    pub fn connect_tcp(addr: &str) -> Result<Controller, ControllerError> {
+        let transport = TcpTransport::connect(addr, Duration::from_millis(100))?;
+        println!("Successfully connected to {}", addr);
+        Ok(Controller::from_transport(transport))
+    }
+
+    /// Connects to a BLE-to-UART bridge by its MAC address.
+    #[cfg(feature = "ble")]
+    pub fn connect_ble(address: &str) -> Result<Controller, ControllerError> {
+        let mut transport = crate::ble::BleTransport::connect(address, Duration::from_secs(10))?;
+        transport
+            .set_timeout(Duration::from_millis(100))
+            .map_err(|e| ControllerError::Connection(format!("{:?}", e)))?;
+        Ok(Controller::from_transport(transport))
+    }
+
+    /// Wraps an already opened port.
+    #[cfg(feature = "serial")]
+    pub fn from_port(port: Box<dyn SerialPort>) -> Controller {
+        Controller::from_transport(port)
+    }
+
+    /// Wraps any byte link that carries the board's line protocol.
+    pub fn from_transport<T: Transport + 'static>(transport: T) -> Controller {
+        let port_name = transport.name().unwrap_or_else(|| "Unknown".to_string());
+        Controller {
+            port: Arc::new(Mutex::new(Box::new(transport))),
+            queue: Arc::new(PortQueue::default()),
+            priority: Priority::Normal,
+            port_name: port_name.into(),
+            rf_enabled: Arc::new(AtomicBool::new(false)),
+            dll: Arc::new(Mutex::new(DllSettings::default())),
+            interlocks: Arc::new(Mutex::new(Vec::new())),
+            listeners: Arc::new(Mutex::new(Vec::new())),
+            exchange_listeners: Arc::new(Mutex::new(Vec::new())),
+            trace: Arc::new(Mutex::new(None)),
+            cache: Arc::new(Mutex::new(ReplyCache::default())),
+            last_reply: Arc::new(Mutex::new(Instant::now())),
+            error_retries: Arc::new(AtomicU32::new(0)),
+            pacing: Arc::new(Mutex::new(Pacing::default())),
+            timeouts: Arc::new(Mutex::new(None)),
+            response_timeout: Arc::new(Mutex::new(DEFAULT_RESPONSE_TIMEOUT)),
+            identity_gate: Arc::new(Mutex::new(None)),
+            identity: Arc::new(Mutex::new(None)),
+            reopen: Arc::new(Mutex::new(None)),
+            echo: Arc::new(AtomicBool::new(true)),
+            read_only: Arc::new(AtomicBool::new(false)),
+            exclusive_remote: Arc::new(AtomicBool::new(false)),
+            audit: Arc::new(Mutex::new(None)),
+            actor: "local".into(),
+            writer: Arc::new(Mutex::new(WriterLock::default())),
+            lifecycle: Arc::new(Mutex::new(Lifecycle::Connected)),
+            port_lock: None,
+        }
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    /// Lock file held for the serial port, if this handle opened one.
+    pub fn lock_file(&self) -> Option<&std::path::Path> {
+        self.port_lock.as_deref().map(PortLock::path)
+    }
+
+    /// RF state as last commanded through this controller.
+    pub fn rf_enabled(&self) -> bool {
+        self.rf_enabled.load(Ordering::SeqCst)
+    }
+
+    /// Adds an interlock that must be closed for `RfEnable` to be sent.
+    pub fn add_interlock(&self, interlock: Arc<dyn Interlock>) -> Result<(), ControllerError> {
+        let mut interlocks = self
+            .interlocks
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?;
+        interlocks.push(interlock);
+        Ok(())
+    }
+
+    /// Returns the name of the first open interlock, if any.
+    pub fn open_interlock(&self) -> Result<Option<String>, ControllerError> {
+        let interlocks = self
+            .interlocks
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?;
+        Ok(interlocks
+            .iter()
+            .find(|interlock| !interlock.is_closed())
+            .map(|interlock| interlock.name().to_string()))
+    }
+
+    /// Registers a listener for controller events.
+    pub fn subscribe(&self, listener: EventListener) -> Result<(), ControllerError> {
+        let mut listeners = self
+            .listeners
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?;
+        listeners.push(listener);
+        Ok(())
+    }
+
+    /// Registers a listener called after every command line exchanged with
+    /// the board, e.g. to export it as a trace span.
+    pub fn observe_exchanges(&self, listener: ExchangeListener) -> Result<(), ControllerError> {
+        let mut listeners = self
+            .exchange_listeners
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?;
+        listeners.push(listener);
+        Ok(())
+    }
+
+    /// Delivers an event to every listener.
+    pub fn emit(&self, event: &ControllerEvent) {
+        let listeners = match self.listeners.lock() {
+            Ok(listeners) => listeners.clone(),
+            Err(_) => return,
+        };
+        for listener in listeners {
+            listener(event);
+        }
+    }
+
+    /// Restricts this link to queries: every other command, `RfDisable`
+    /// included, is refused with [`ControllerError::ReadOnly`] without being
+    /// sent. The setting is shared by all clones of the handle.
+    pub fn set_read_only(&self, read_only: bool) {
+        self.read_only.store(read_only, Ordering::SeqCst);
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only.load(Ordering::SeqCst)
+    }
+
+    /// Locks the board's front panel out (`$RLS`) or lets it back in, so
+    /// nobody at the board changes a setpoint under an automated run. A
+    /// board that resets unlocks itself, so the lockout is sent again
+    /// whenever the port is reopened. Shared by all clones of the handle.
+    ///
+    /// `$RLS` is unverified on hardware: a board that rejects it fails this
+    /// with [`ControllerError::Device`], which is not reported as a
+    /// [`ControllerEvent::DeviceFault`].
+    pub fn set_exclusive_remote(&self, exclusive: bool) -> Result<(), ControllerError> {
+        let command = match exclusive {
+            true => Command::LockLocal,
+            false => Command::UnlockLocal,
+        };
+        self.send_checked(&command, SendMode::OPTIONAL)?;
+        self.exclusive_remote.store(exclusive, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Whether this link has locked the front panel out.
+    pub fn is_exclusive_remote(&self) -> bool {
+        self.exclusive_remote.load(Ordering::SeqCst)
+    }
+
+    /// Turns the `TX:`/`RX:` echo of every exchange on stdout on or off. It
+    /// is on by default; tools that write their own output to stdout turn it
+    /// off and use [`set_trace`](Controller::set_trace) instead.
+    pub fn set_echo(&self, echo: bool) {
+        self.echo.store(echo, Ordering::SeqCst);
+    }
+
+    /// Records every transmitted command and received reply to `writer` as
+    /// tab separated `timestamp, kind, text` lines. Pair it with a
+    /// [`RotatingWriter`](crate::rotation::RotatingWriter) for long runs.
+    pub fn set_trace(&self, writer: Box<dyn Write + Send>) -> Result<(), ControllerError> {
+        let mut trace = self.trace.lock().map_err(|_| ControllerError::Poisoned)?;
+        *trace = Some(writer);
+        Ok(())
+    }
+
+    /// Records every command written to the board, with the actor of the
+    /// handle that sent it and the reply or error, to a hash-chained
+    /// [`AuditLog`].
+    pub fn set_audit_log(&self, log: AuditLog) -> Result<(), ControllerError> {
+        let mut audit = self.audit.lock().map_err(|_| ControllerError::Poisoned)?;
+        *audit = Some(log);
+        Ok(())
+    }
+
+    /// A handle to the same link whose commands are audited as sent by
+    /// `actor`, e.g. a network client's address. Plain handles are `local`.
+    pub fn with_actor(&self, actor: &str) -> Controller {
+        Controller {
+            actor: actor.into(),
+            ..self.clone()
+        }
+    }
+
+    /// A handle to the same link whose exchanges wait for the port at
+    /// `priority`, e.g. [`Priority::Background`] for a telemetry poller.
+    /// `RfDisable` is sent at [`Priority::Emergency`] from any handle.
+    pub fn with_priority(&self, priority: Priority) -> Controller {
+        Controller {
+            priority,
+            ..self.clone()
+        }
+    }
+
+    pub fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// Actor of this handle, as recorded in the audit log.
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// Turns single-writer arbitration on or off. While it is on, only the
+    /// actor holding control may send commands other than queries and
+    /// `RfDisable`; the first actor to send one takes control if nobody
+    /// holds it. Others are refused with [`ControllerError::NotAuthorized`]
+    /// until the owner releases control or they [take it
+    /// over](Controller::take_control). Commands sent with
+    /// [`Controller::send_as_system`] are exempt.
+    pub fn set_single_writer(&self, enabled: bool) -> Result<(), ControllerError> {
+        let mut writer = self.writer.lock().map_err(|_| ControllerError::Poisoned)?;
+        writer.enabled = enabled;
+        Ok(())
+    }
+
+    /// Actor currently holding control, if any.
+    pub fn control_owner(&self) -> Result<Option<String>, ControllerError> {
+        let writer = self.writer.lock().map_err(|_| ControllerError::Poisoned)?;
+        Ok(writer.owner.as_deref().map(str::to_string))
+    }
+
+    /// Makes this handle's actor the owner, taking control from whoever
+    /// holds it. Emits [`ControllerEvent::ControlChanged`] if the owner changes.
+    pub fn take_control(&self) -> Result<(), ControllerError> {
+        self.change_control(Some(self.actor.clone()), |_| true)
+    }
+
+    /// Gives up control if this handle's actor holds it.
+    pub fn release_control(&self) -> Result<(), ControllerError> {
+        self.change_control(None, |owner| owner == Some(&*self.actor))
+    }
+
+    fn change_control(
+        &self,
+        owner: Option<Arc<str>>,
+        allowed: impl FnOnce(Option<&str>) -> bool,
+    ) -> Result<(), ControllerError> {
+        let previous = {
+            let mut writer = self.writer.lock().map_err(|_| ControllerError::Poisoned)?;
+            if writer.owner == owner || !allowed(writer.owner.as_deref()) {
+                return Ok(());
+            }
+            std::mem::replace(&mut writer.owner, owner.clone())
+        };
+        self.emit(&ControllerEvent::ControlChanged {
+            owner: owner.as_deref().map(str::to_string),
+            previous: previous.as_deref().map(str::to_string),
+        });
+        Ok(())
+    }
+
+    /// Checks that this handle may change the board under single-writer
+    /// arbitration, taking control if nobody holds it.
+    fn claim_control(&self, command: &Command) -> Result<(), ControllerError> {
+        if !self
+            .writer
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?
+            .enabled
+        {
+            return Ok(());
+        }
+        // Checked and taken under one lock, so of two actors claiming at
+        // once only one becomes the owner.
+        let mut holder = None;
+        self.change_control(Some(self.actor.clone()), |owner| {
+            holder = owner.map(str::to_string);
+            owner.is_none()
+        })?;
+        match holder {
+            None => Ok(()),
+            Some(owner) => Err(ControllerError::NotAuthorized(format!(
+                "{} needs control, which {} holds",
+                command, owner
+            ))),
+        }
+    }
+
+    /// Appends a line to the protocol trace, if one is set.
+    pub fn trace(&self, kind: &str, text: &str) {
+        if let Ok(mut trace) = self.trace.lock() {
+            if let Some(writer) = trace.as_mut() {
+                let line = format!(
+                    "{}\t{}\t{}\n",
+                    format_timestamp(SystemTime::now()),
+                    kind,
+                    text.trim_end()
+                );
+                if let Err(e) = writer.write_all(line.as_bytes()) {
+                    eprintln!("Failed to write protocol trace: {:?}", e);
+                }
+            }
+        }
+    }
+
+    /// Stage of the board's [`Lifecycle`], shared by all clones of the handle.
+    pub fn lifecycle(&self) -> Result<Lifecycle, ControllerError> {
+        self.lifecycle
+            .lock()
+            .map(|lifecycle| *lifecycle)
+            .map_err(|_| ControllerError::Poisoned)
+    }
+
+    fn set_lifecycle(&self, update: impl FnOnce(Lifecycle) -> Lifecycle) {
+        if let Ok(mut lifecycle) = self.lifecycle.lock() {
+            *lifecycle = update(*lifecycle);
+        }
+    }
+
+    /// Sends a command and returns the raw response line.
+    ///
+    /// Error replies from the board are returned as [`ControllerError::Device`].
+    /// Commands the current [`Lifecycle`] stage does not allow are refused
+    /// with [`ControllerError::InvalidState`]. `RfEnable` is refused while
+    /// any interlock is open, and every command but queries and `RfDisable`
+    /// while an identity check has not passed or another actor holds control.
+    pub fn send(&self, command: &Command) -> Result<String, ControllerError> {
+        self.send_checked(command, SendMode::NORMAL)
+    }
+
+    /// Sends a command for the host's own protection, such as an alarm
+    /// action lowering the power. Checked as by [`Controller::send`], but
+    /// exempt from single-writer arbitration: it needs no control while a
+    /// client holds it, and takes none when nobody does.
+    pub fn send_as_system(&self, command: &Command) -> Result<String, ControllerError> {
+        self.send_checked(command, SendMode::SYSTEM)
+    }
+
+    /// Sends a query the firmware may not implement, such as an optional
+    /// telemetry reading. An error reply comes back as `Ok(None)` and is not
+    /// reported as a [`ControllerEvent::DeviceFault`], so polling a reading
+    /// the board lacks raises no alerts.
+    pub fn send_optional(&self, command: &Command) -> Result<Option<String>, ControllerError> {
+        match self.send_checked(command, SendMode::OPTIONAL) {
+            Ok(response) => Ok(Some(response)),
+            Err(ControllerError::Device(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn send_checked(&self, command: &Command, mode: SendMode) -> Result<String, ControllerError> {
+        if let Some(reason) = self.lifecycle()?.refusal(command) {
+            return Err(ControllerError::InvalidState(reason));
+        }
+        if let Command::RfEnable = command {
+            if let Some(name) = self.open_interlock()? {
+                return Err(ControllerError::InterlockOpen(name));
+            }
+        }
+        if !command.is_query() && *command != Command::RfDisable {
+            let gate = self
+                .identity_gate
+                .lock()
+                .map_err(|_| ControllerError::Poisoned)?;
+            if let Some(reason) = gate.as_ref() {
+                return Err(ControllerError::UnexpectedDevice(reason.clone()));
+            }
+            drop(gate);
+            if mode.arbitrated {
+                self.claim_control(command)?;
+            }
+        }
+
+        let line = command.to_string();
+        let mut retries_left = self.error_retries.load(Ordering::SeqCst);
+        let mut reopened = false;
+        let response = loop {
+            let result = match self.write_read(&line) {
+                Err(ControllerError::Io(message)) if !reopened && is_stale_handle(&message) => {
+                    reopened = true;
+                    match self.reopen(&message) {
+                        Ok(true) => continue,
+                        Ok(false) => Err(ControllerError::Io(message)),
+                        Err(e) => Err(e),
+                    }
+                }
+                result => result,
+            };
+            let response = match result {
+                Ok(response) => response,
+                Err(e) => {
+                    match &e {
+                        ControllerError::Io(message) => {
+                            self.set_lifecycle(|_| Lifecycle::Disconnected);
+                            self.emit(&ControllerEvent::ConnectionLost(message.clone()))
+                        }
+                        ControllerError::Timeout => {
+                            self.emit(&ControllerEvent::CommandTimeout(line.clone()))
+                        }
+                        _ => {}
+                    }
+                    return Err(e);
+                }
+            };
+            let mut error = match check_reply(&response) {
+                Ok(()) => break response,
+                Err(ControllerError::Device(error)) => error,
+                Err(e) => return Err(e),
+            };
+            if retries_left > 0 {
+                error.status = self.read_status();
+                if error.kind() == DeviceErrorKind::Latched && self.clear_errors() {
+                    retries_left -= 1;
+                    self.emit(&ControllerEvent::ErrorsCleared(error.to_string()));
+                    continue;
+                }
+            }
+            if error.kind() == DeviceErrorKind::Shutdown {
+                self.set_lifecycle(|_| Lifecycle::Fault);
+            }
+            if mode.report_faults {
+                self.emit(&ControllerEvent::DeviceFault(response.trim().to_string()));
+            }
+            return Err(ControllerError::Device(error));
+        };
+
+        let shutdown = matches!(command, Command::GetStatus { .. })
+            && parse_status(&response).is_ok_and(|status| status.is_shutdown());
+        self.set_lifecycle(|lifecycle| match shutdown {
+            true => Lifecycle::Fault,
+            false => lifecycle.after(command),
+        });
+
+        match command {
+            Command::RfEnable => self.rf_enabled.store(true, Ordering::SeqCst),
+            Command::RfDisable => self.rf_enabled.store(false, Ordering::SeqCst),
+            Command::DllEnable | Command::DllDisable | Command::ConfigureDll { .. } => {
+                if let Ok(mut dll) = self.dll.lock() {
+                    match *command {
+                        Command::ConfigureDll {
+                            param1,
+                            param2,
+                            param3,
+                            param4,
+                            param5,
+                            param6,
+                        } => {
+                            dll.parameters = Some([param1, param2, param3, param4, param5, param6])
+                        }
+                        _ => dll.enabled = Some(*command == Command::DllEnable),
+                    }
+                }
+            }
+            _ => {}
+        }
+        if command.is_query() {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.insert(command.to_string(), &response);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Enables automatic recovery from error replies caused by latched
+    /// warning bits: the status is read, `$ERRC` is sent and the command is
+    /// retried up to `retries` times. Zero, the default, disables it.
+    ///
+    /// Errors with the status clear (rejected commands) or with a shutdown
+    /// bit set are never retried; they are returned with the status filled
+    /// in and reported as [`ControllerEvent::DeviceFault`].
+    pub fn set_error_recovery(&self, retries: u32) {
+        self.error_retries.store(retries, Ordering::SeqCst);
+    }
+
+    /// Status word read for error classification, bypassing `send`.
+    fn read_status(&self) -> Option<StatusFlags> {
+        let reply = self
+            .write_read(&Command::GetStatus { verbose: false }.to_string())
+            .ok()?;
+        parse_status(&reply).ok()
+    }
+
+    fn clear_errors(&self) -> bool {
+        self.write_read(&Command::ClearErrors.to_string())
+            .is_ok_and(|reply| check_reply(&reply).is_ok())
+    }
+
+    /// Holds every command back until at least `interval` has passed since
+    /// the previous exchange ended, whichever thread sent it. Zero, the
+    /// default, sends commands as soon as the bus is free.
+    ///
+    /// Usually set from a [`DeviceProfile`](crate::profile::DeviceProfile).
+    pub fn set_min_command_interval(&self, interval: Duration) {
+        if let Ok(mut pacing) = self.pacing.lock() {
+            pacing.min_interval = interval;
+        }
+    }
+
+    /// Learns how long the board takes to answer each command and waits for
+    /// about that long, within `bounds`, instead of a fixed
+    /// [`DEFAULT_RESPONSE_TIMEOUT`]. Slow commands then stop timing out while
+    /// a dead link is noticed after the fast ones' timeout. `None` restores
+    /// the fixed timeout and forgets what was learned.
+    pub fn set_adaptive_timeout(&self, bounds: Option<TimeoutBounds>) {
+        if let Ok(mut timeouts) = self.timeouts.lock() {
+            *timeouts = bounds.map(AdaptiveTimeouts::new);
+        }
+    }
+
+    /// Latency learned per command mnemonic, empty unless adaptive timeouts
+    /// are enabled.
+    pub fn latency_estimates(&self) -> Vec<(String, LatencyEstimate)> {
+        match self.timeouts.lock() {
+            Ok(timeouts) => timeouts
+                .as_ref()
+                .map(AdaptiveTimeouts::estimates)
+                .unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// How long the reply to command line `tx` will be waited for.
+    pub fn response_timeout(&self, tx: &str) -> Duration {
+        let fixed = self
+            .response_timeout
+            .lock()
+            .map_or(DEFAULT_RESPONSE_TIMEOUT, |timeout| *timeout);
+        match self.timeouts.lock() {
+            Ok(timeouts) => timeouts
+                .as_ref()
+                .map_or(fixed, |timeouts| timeouts.timeout(tx)),
+            Err(_) => fixed,
+        }
+    }
+
+    /// Sets how long to wait for a reply while adaptive timeouts are off,
+    /// [`DEFAULT_RESPONSE_TIMEOUT`] unless changed.
+    pub fn set_response_timeout(&self, timeout: Duration) {
+        if let Ok(mut fixed) = self.response_timeout.lock() {
+            *fixed = timeout;
+        }
+    }
+
+    /// Sends `$IDN,0` and checks that the reply names `family` (ignoring
+    /// case), returning the reply. Until a check passes, set commands are
+    /// refused with [`ControllerError::UnexpectedDevice`], so a different
+    /// USB-serial device that shares the board's VID/PID never receives
+    /// `$PWRS` or `$ECS,0,1`. An empty `family` accepts any `$IDN` reply.
+    pub fn verify_identity(&self, family: &str) -> Result<String, ControllerError> {
+        let set_gate = |reason: Option<String>| -> Result<(), ControllerError> {
+            *self
+                .identity_gate
+                .lock()
+                .map_err(|_| ControllerError::Poisoned)? = reason;
+            Ok(())
+        };
+        set_gate(Some(format!("Identity not verified as {}", family)))?;
+        let reply = self.write_read(&Command::GetIdentity.to_string())?;
+        let identity = reply.trim().to_string();
+        if !names_family(&identity, family) {
+            set_gate(Some(identity.clone()))?;
+            return Err(ControllerError::UnexpectedDevice(identity));
+        }
+        set_gate(None)?;
+        *self
+            .identity
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)? = Some(identity.clone());
+        Ok(identity)
+    }
+
+    /// Sets how a stale port handle is replaced. When an exchange fails the
+    /// way a port does after a USB suspend/resume or re-enumeration
+    /// (broken pipe, `EIO`, `ENODEV`, ...), the old handle is closed,
+    /// `reopen` is retried with backoff for a few seconds while the port
+    /// re-enumerates, and the command is sent once more, provided the board still
+    /// answers `$IDN` as before. Ports opened with
+    /// [`open_with`](Controller::open_with) are reopened with the same
+    /// settings; other links are not reopened unless set here.
+    pub fn set_reopen(&self, reopen: Option<Reopener>) -> Result<(), ControllerError> {
+        *self.reopen.lock().map_err(|_| ControllerError::Poisoned)? = reopen;
+        Ok(())
+    }
+
+    /// Replaces the port after `reason`, a stale handle error, and checks the
+    /// identity of the board behind the new one. `Ok(false)` if this link
+    /// cannot be reopened.
+    fn reopen(&self, reason: &str) -> Result<bool, ControllerError> {
+        let Some(reopen) = self
+            .reopen
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?
+            .clone()
+        else {
+            return Ok(false);
+        };
+        let turn = self.queue.acquire(Priority::Emergency)?;
+        let mut port = self.port.lock().map_err(|_| ControllerError::Poisoned)?;
+        *port = Box::new(ClosedTransport);
+        let mut backoff = REOPEN_BACKOFF;
+        let mut attempt = 1;
+        *port = loop {
+            match reopen() {
+                Ok(fresh) => break fresh,
+                Err(e) if attempt >= REOPEN_ATTEMPTS => {
+                    return Err(ControllerError::Io(format!(
+                        "{}; reopening the port failed: {}",
+                        reason, e
+                    )))
+                }
+                Err(_) => {
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        };
+        drop(port);
+        drop(turn);
+        self.trace("REOPEN", reason);
+
+        let known = self
+            .identity
+            .lock()
+            .map_err(|_| ControllerError::Poisoned)?
+            .clone();
+        let identity = self
+            .write_read(&Command::GetIdentity.to_string())?
+            .trim()
+            .to_string();
+        if let Some(known) = known.filter(|known| *known != identity) {
+            let reason = format!("{} after reopening the port, was {}", identity, known);
+            *self
+                .identity_gate
+                .lock()
+                .map_err(|_| ControllerError::Poisoned)? = Some(reason.clone());
+            return Err(ControllerError::UnexpectedDevice(reason));
+        }
+        if self.is_exclusive_remote() {
+            check_reply(&self.write_read(&Command::LockLocal.to_string())?)?;
+        }
+        self.emit(&ControllerEvent::PortReopened(reason.to_string()));
+        Ok(true)
+    }
+
+    /// Sets how long query replies stay fresh for [`send_cached`](Controller::send_cached).
+    /// Zero, the default, disables the cache.
+    pub fn set_cache_ttl(&self, ttl: Duration) -> Result<(), ControllerError> {
+        let mut cache = self.cache.lock().map_err(|_| ControllerError::Poisoned)?;
+        cache.ttl = ttl;
+        cache.replies.clear();
+        Ok(())
+    }
+
+    /// Like [`send`](Controller::send), but answers a query from the reply
+    /// cache if the same query was answered within the cache TTL, without
+    /// touching the bus. Replies are cached whichever way the query was sent,
+    /// and any command that is not a query clears the cache.
+    ///
+    /// Meant for displays and remote front ends polling alongside a control
+    /// loop; the loop itself should keep using `send`.
+    pub fn send_cached(&self, command: &Command) -> Result<String, ControllerError> {
+        match self.cached_reply(command)? {
+            Some(reply) => Ok(reply),
+            None => self.send(command),
+        }
+    }
+
+    fn cached_reply(&self, command: &Command) -> Result<Option<String>, ControllerError> {
+        if !command.is_query() {
+            return Ok(None);
+        }
+        let cache = self.cache.lock().map_err(|_| ControllerError::Poisoned)?;
+        Ok(cache.get(&command.to_string()))
+    }
+
+    /// Reads the board's faults as listed by a verbose `$ST,0,1` reply, see
+    /// [`parse_verbose_status`].
+    pub fn read_faults(&self) -> Result<Vec<DeviceFault>, ControllerError> {
+        parse_verbose_status(&self.send(&Command::GetStatus { verbose: true })?)
+    }
+
+    /// Sends a query command and returns the numeric values of its reply.
+    pub fn query(&self, command: &Command) -> Result<Vec<f32>, ControllerError> {
+        parse_values(&self.send(command)?)
+    }
+
+    /// Reads the frequency, power setpoint, forward and reflected power,
+    /// status and PA temperature back to back, answering from the reply
+    /// cache where it is fresh (see [`set_cache_ttl`](Controller::set_cache_ttl)),
+    /// and adds the DLL settings and RF state this handle has sent.
+    pub fn read_all_settings(&self) -> Result<DeviceSettings, ControllerError> {
+        let frequency_mhz = parse_value(&self.send_cached(&Command::GetFrequency)?)?;
+        let power_setpoint_dbm = parse_value(&self.send_cached(&Command::GetPowerSetpoint)?)?;
+        let measured = parse_values(&self.send_cached(&Command::GetPaPower)?)?;
+        let [forward_dbm, reflected_dbm, ..] = measured[..] else {
+            return Err(ControllerError::InvalidResponse(format!(
+                "Expected forward and reflected power, got {:?}",
+                measured
+            )));
+        };
+        let status = parse_status(&self.send_cached(&Command::GetStatus { verbose: false })?)?;
+        // Optional: firmware without `$PTG` leaves it out, raising no fault.
+        let temperature_c = match self.cached_reply(&Command::GetPaTemperature)? {
+            Some(reply) => Some(reply),
+            None => self
+                .send_optional(&Command::GetPaTemperature)
+                .ok()
+                .flatten(),
+        }
+        .and_then(|reply| parse_value(&reply).ok());
+        Ok(DeviceSettings {
+            read_at: SystemTime::now(),
+            frequency_mhz,
+            power_setpoint_dbm,
+            forward_dbm,
+            reflected_dbm,
+            status,
+            temperature_c,
+            dll: self.dll.lock().map(|dll| *dll).unwrap_or_default(),
+            rf_enabled: self.rf_enabled(),
+        })
+    }
+
+    /// Puts the output into its defined safe state (RF disabled).
+    pub fn safe_state(&self) -> Result<(), ControllerError> {
+        self.send(&Command::RfDisable).map(|_| ())
+    }
+
+    /// Known baseline for a board another tool may have left running: RF
+    /// disabled, then the power setpoint at its minimum.
+    pub fn force_safe_state(&self) -> Result<(), ControllerError> {
+        self.send(&Command::RfDisable)?;
+        self.send(&Command::SetPower(*POWER_RANGE_DBM.start()))?;
+        Ok(())
+    }
+
+    /// Last-resort `RfDisable` for panic hooks and drop guards: skips the
+    /// interlocks, access checks and listeners, and gives up if the port
+    /// stays locked for `timeout`, since the thread holding it may be the
+    /// one that panicked. Read-only handles only act if they saw RF enabled.
+    pub fn emergency_rf_off(&self, timeout: Duration) -> Result<(), ControllerError> {
+        if self.is_read_only() && !self.rf_enabled() {
+            return Ok(());
+        }
+        self.send_emergency(&Command::RfDisable, timeout)?;
+        self.rf_enabled.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Lets the front panel back in (`$RLS`) the way
+    /// [`emergency_rf_off`](Controller::emergency_rf_off) disables RF, for
+    /// a process exiting without dropping its
+    /// [`LocalLockout`](crate::lockout::LocalLockout). Does nothing unless
+    /// this link locked the panel out.
+    pub fn emergency_unlock(&self, timeout: Duration) -> Result<(), ControllerError> {
+        if !self.is_exclusive_remote() {
+            return Ok(());
+        }
+        self.send_emergency(&Command::UnlockLocal, timeout)?;
+        self.exclusive_remote.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    fn send_emergency(&self, command: &Command, timeout: Duration) -> Result<(), ControllerError> {
+        let start = Instant::now();
+        let mut port = loop {
+            match self.port.try_lock() {
+                Ok(port) => break port,
+                Err(TryLockError::Poisoned(poisoned)) => break poisoned.into_inner(),
+                Err(TryLockError::WouldBlock) if start.elapsed() < timeout => {
+                    thread::sleep(Duration::from_millis(5))
+                }
+                Err(TryLockError::WouldBlock) => return Err(ControllerError::Timeout),
+            }
+        };
+        // No trace or audit: their locks may be held by the panicking thread too.
+        let reply = write_read(
+            &mut **port,
+            &command.to_string(),
+            false,
+            DEFAULT_RESPONSE_TIMEOUT,
+            false,
+        )?;
+        check_reply(&reply)?;
+        Ok(())
+    }
+
+    /// Writes a raw command string and waits for a `\r\n` terminated reply,
+    /// or for the terminating `OK` line of a verbose `$ST,0,1` reply.
+    ///
+    /// The exchange waits for the port behind higher-priority ones, see
+    /// [`priority`](crate::priority). Bytes left over from an earlier exchange are discarded first and
+    /// recorded as a `STALE` trace line, so they cannot be mistaken for the
+    /// reply.
+    pub fn write_read(&self, tx: &str) -> Result<String, ControllerError> {
+        let command: Option<Command> = tx.parse().ok();
+        let is_query = command.as_ref().is_some_and(Command::is_query);
+        if !is_query && self.is_read_only() {
+            return Err(ControllerError::ReadOnly(tx.trim().to_string()));
+        }
+        let priority = match command {
+            Some(Command::RfDisable) => Priority::Emergency,
+            _ => self.priority,
+        };
+        let turn = self.queue.acquire(priority)?;
+        let mut port = self.port.lock().map_err(|_| ControllerError::Poisoned)?;
+        if !is_query {
+            if let Ok(mut cache) = self.cache.lock() {
+                cache.replies.clear();
+            }
+        }
+        let mut pacing = self.pacing.lock().map_err(|_| ControllerError::Poisoned)?;
+        if let Some(last_exchange) = pacing.last_exchange {
+            let wait = pacing.min_interval.saturating_sub(last_exchange.elapsed());
+            if !wait.is_zero() {
+                thread::sleep(wait);
+            }
+        }
+        let echo = self.echo.load(Ordering::SeqCst);
+        match port.discard_input() {
+            Ok(stale) if !stale.is_empty() => {
+                let stale = stale.escape_ascii().to_string();
+                if echo {
+                    println!("STALE:\t{}", stale);
+                }
+                self.trace("STALE", &stale);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let e = ControllerError::Io(format!("Failed to drain the port: {:?}", e));
+                self.trace("ERR", &e.to_string());
+                return Err(e);
+            }
+        }
+        self.trace("TX", tx);
+        let timeout = self.response_timeout(tx);
+        let (started, start_time) = (SystemTime::now(), Instant::now());
+        let multi_line = command == Some(Command::GetStatus { verbose: true });
+        let result = write_read(&mut **port, tx, echo, timeout, multi_line);
+        let duration = start_time.elapsed();
+        if let Ok(mut timeouts) = self.timeouts.lock() {
+            match (timeouts.as_mut(), &result) {
+                (Some(timeouts), Ok(_)) => timeouts.observe(tx, duration),
+                (Some(timeouts), Err(ControllerError::Timeout)) => timeouts.timed_out(tx),
+                _ => {}
+            }
+        }
+        pacing.last_exchange = Some(Instant::now());
+        match &result {
+            Ok(response) => {
+                self.trace("RX", response);
+                if let Ok(mut last_reply) = self.last_reply.lock() {
+                    *last_reply = Instant::now();
+                }
+                if command == Some(Command::GetIdentity) && response.starts_with("$IDN") {
+                    if let Ok(mut identity) = self.identity.lock() {
+                        identity.get_or_insert_with(|| response.trim().to_string());
+                    }
+                }
+            }
+            Err(e) => self.trace("ERR", &e.to_string()),
+        }
+        if let Ok(mut audit) = self.audit.lock() {
+            if let Some(log) = audit.as_mut() {
+                let outcome = match &result {
+                    Ok(response) => response.clone(),
+                    Err(e) => format!("ERR {}", e),
+                };
+                if let Err(e) = log.append(&self.actor, tx, &outcome) {
+                    eprintln!("Failed to write audit log: {:?}", e);
+                }
+            }
+        }
+        drop(pacing);
+        drop(port);
+        drop(turn);
+        let observers = match self.exchange_listeners.lock() {
+            Ok(listeners) => listeners.clone(),
+            Err(_) => Vec::new(),
+        };
+        if !observers.is_empty() {
+            let exchange = Exchange {
+                command: tx.to_string(),
+                reply: match &result {
+                    Ok(response) => Ok(response.trim_end().to_string()),
+                    Err(e) => Err(e.to_string()),
+                },
+                actor: self.actor.to_string(),
+                started,
+                duration,
+            };
+            for observer in observers {
+                observer(&exchange);
+            }
+        }
+        result
+    }
+
+    /// Time since the board last answered anything, measured from connecting
+    /// if it has not answered yet.
+    pub fn last_reply_age(&self) -> Duration {
+        self.last_reply
+            .lock()
+            .map(|last_reply| last_reply.elapsed())
+            .unwrap_or_default()
+    }
+
+    /// Closes this handle. The port itself is released once the last clone is dropped.
+    pub fn disconnect(self) {
+        println!("Disconnecting from port: {}", self.port_name);
+        let port_name = self.port_name.clone();
+        drop(self);
+        println!("Disconnected from port: {}", port_name);
+    }
+}
+
+/// Whether an I/O failure means the port handle went stale, as after a USB
+/// suspend/resume, rather than the link failing some other way.
+fn is_stale_handle(message: &str) -> bool {
+    // EIO, ENXIO, ENODEV
+    #[cfg(not(windows))]
+    const OS_ERRORS: [&str; 3] = ["code: 5,", "code: 6,", "code: 19,"];
+    // ERROR_BAD_COMMAND, ERROR_GEN_FAILURE, ERROR_OPERATION_ABORTED,
+    // ERROR_DEVICE_NOT_CONNECTED
+    #[cfg(windows)]
+    const OS_ERRORS: [&str; 4] = ["code: 22,", "code: 31,", "code: 995,", "code: 1167,"];
+    ["BrokenPipe", "NotConnected", "NotFound"]
+        .iter()
+        .chain(&OS_ERRORS)
+        .any(|marker| message.contains(marker))
+}
+
+/// Whether an `$IDN,0,...` reply has a field containing `family`.
+fn names_family(reply: &str, family: &str) -> bool {
+    let mut fields = reply.split(',');
+    if fields.next() != Some("$IDN") {
+        return false;
+    }
+    let family = family.to_ascii_lowercase();
+    fields
+        .skip(1)
+        .any(|field| field.to_ascii_lowercase().contains(&family))
+}
+
+// Handles are handed to GUI, polling and recipe threads; keep that a compile-time guarantee.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Controller>();
+};
+
+/// Lists the serial ports whose USB VID/PID match the signal generator board.
+#[cfg(feature = "serial")]
+pub fn autodetect_sg_port() -> Result<Vec<SerialPortInfo>, ControllerError> {
+    autodetect_ports(&PortFilter::default())
+}
+
+/// Lists the serial ports that `filter` takes for the board.
+#[cfg(feature = "serial")]
+pub fn autodetect_ports(filter: &PortFilter) -> Result<Vec<SerialPortInfo>, ControllerError> {
+    let available_ports = match available_ports() {
+        Ok(ports) => ports,
+        Err(e) => {
+            return Err(ControllerError::Connection(format!(
+                "Failed to list serial ports: {:?}",
+                e
+            )))
+        }
+    };
+    println!("Available ports to connect to:\n{:#?}\n", available_ports);
+
+    Ok(available_ports
+        .into_iter()
+        .filter(|port| filter.matches(port))
+        .collect())
+}
+
+/// Writes `tx` and reads until the first `\r\n`.
+///
+/// The reply is collected as bytes and decoded once, so multi-byte characters
+/// split across reads survive. Anything after the first terminator belongs to
+/// no command and is dropped.
+/// End of the complete `\r\n` terminated lines in `buffer`, and whether
+/// they make up the reply: the first line, or with `multi_line` the lines up
+/// to an `OK` or error line.
+fn reply_end(buffer: &[u8], multi_line: bool) -> (usize, bool) {
+    let mut end = 0;
+    while let Some(length) = buffer[end..].windows(2).position(|w| w == b"\r\n") {
+        let line = String::from_utf8_lossy(&buffer[end..end + length]);
+        end += length + 2;
+        if !multi_line || line.trim() == "OK" || is_error_reply(&line) {
+            return (end, true);
+        }
+    }
+    (end, false)
+}
+
+fn write_read(
+    port: &mut dyn Transport,
+    tx: &str,
+    echo: bool,
+    timeout: Duration,
+    multi_line: bool,
+) -> Result<String, ControllerError> {
+    let command = format!("{}\r\n", tx);
+    if echo {
+        println!("TX:\t{}", command);
+    }
+
+    if let Err(e) = port.write_all(command.as_bytes()) {
+        return Err(ControllerError::Io(format!(
+            "Failed to write to the port: {:?}",
+            e
+        )));
+    }
+
+    if let Err(e) = port.flush() {
+        return Err(ControllerError::Io(format!(
+            "Failed to flush the port: {:?}",
+            e
+        )));
+    }
+
+    let mut buffer = Vec::new();
+    let mut temp_buffer = [0; 256];
+    let start_time = Instant::now();
+
+    let end = loop {
+        let (lines_end, complete) = reply_end(&buffer, multi_line);
+        if complete {
+            break lines_end;
+        }
+        if start_time.elapsed() >= timeout {
+            // Firmware that answers `$ST,0,1` without the `OK` line.
+            if lines_end > 0 {
+                break lines_end;
+            }
+            return Err(ControllerError::Timeout);
+        }
+
+        match port.read(&mut temp_buffer) {
+            Ok(bytes_read) => buffer.extend_from_slice(&temp_buffer[..bytes_read]),
+            Err(ref e)
+                if matches!(
+                    e.kind(),
+                    std::io::ErrorKind::TimedOut | std::io::ErrorKind::Interrupted
+                ) =>
+            {
+                continue
+            }
+            Err(e) => {
+                return Err(ControllerError::Io(format!(
+                    "Failed to read from the port: {:?}",
+                    e
+                )))
+            }
+        }
+    };
+
+    let buffer = String::from_utf8_lossy(&buffer[..end]).into_owned();
+    if echo {
+        println!("RX:\t{}", buffer);
+    }
+    Ok(buffer)
+}
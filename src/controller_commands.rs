@@ -15,58 +15,58 @@
 // DLL Disable - $DLES,0,0
 // RF Enable - $ECS,0,1
 // RF Disable - $ECS,0,0
-// Sweep (dBm) - $SWPD,0,?,?,?,?,0 - Fills ? with value(s) from the adjacent lineEdit(s).
+// Sweep (dBm) - $SWPD,0,?,?,?,?,0 - Fills ? with value(s) from the adjacent lineEdit(s): start, stop and step in MHz, then the power in dBm.
+//
+// Unverified: the readings below are not in the vendor's list above or in
+// mainwindow.cpp, and have not been checked against a board. Telemetry reads
+// them with `Controller::send_optional`, so firmware that rejects them only
+// leaves the reading out.
+// Get PA Temperature - $PTG,0
+// Get Supply Voltage - $PVG,0 - DC rail of the PA, in volts.
+// Get Supply Current - $PCG,0 - Drawn from the same rail, in amps.
+//
+// Unverified as well. Fan control turns itself off, with one error on stderr,
+// if the board rejects the first speed it is sent.
+// Get Fan Speed - $FNG,0
+// Set Fan Speed - $FNS,0,? - Duty cycle in whole percent, 0 to 100.
+//
+// Unverified too; `mwctl run --exclusive` stops before the recipe if the board
+// rejects the lockout.
+// Lock Local - $RLS,0,1 - The front panel can no longer change the board.
+// Unlock Local - $RLS,0,0
+// Get Local Lockout - $RLG,0
+//
+// The firmware has no uptime or tick counter query, so there is no device clock
+// to correlate host logs with. Faults, sweep completions and telemetry are
+// timestamped on the host when their reply arrives; an `Exchange` records when
+// the command was sent and how long the reply took, which bounds the device-side
+// time of the event.
 
-pub enum Command {
-    GetIdentity,
-    GetVersion,
-    GetStatus {
-        verbose: bool,
-    },
-    ClearErrors,
-    GetFrequency,
-    SetFrequency(f32),
-    GetPaPower,
-    GetPowerSetpoint,
-    SetPower(f32),
-    ConfigureDll {
-        param1: f32,
-        param2: f32,
-        param3: f32,
-        param4: f32,
-        param5: f32,
-        param6: f32,
-    },
-    DllEnable,
-    DllDisable,
-    RfEnable,
-    RfDisable,
-    SweepDbm {
-        start: f32,
-        stop: f32,
-        step: f32,
-        dwell: f32,
-    },
-}
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::protocol::ValidationError;
+use crate::units::watts_to_dbm;
+
+pub use crate::protocol::Command;
 
 impl Command {
-    pub fn to_string(&self) -> String {
+    /// `SetPower` from a level in watts, converted to dBm and checked like
+    /// [`Command::set_power`].
+    pub fn set_power_watts(watts: f64) -> Result<Command, ValidationError> {
+        if watts.is_nan() || watts <= 0.0 {
+            return Err(ValidationError::NotPositive("power"));
+        }
+        Command::set_power(watts_to_dbm(watts))
+    }
+}
+
+impl ToJson for Command {
+    fn to_json(&self) -> JsonValue {
+        let json = JsonValue::object().with("command", self.name());
         match self {
-            Command::GetIdentity => "$IDN,0".to_string(),
-            Command::GetVersion => "$VER,0".to_string(),
-            Command::GetStatus { verbose } => {
-                if *verbose {
-                    "$ST,0,1".to_string()
-                } else {
-                    "$ST,0".to_string()
-                }
-            }
-            Command::ClearErrors => "$ERRC,0".to_string(),
-            Command::GetFrequency => "$FCG,0".to_string(),
-            Command::SetFrequency(value) => format!("$FCS,0,{:.2}", value),
-            Command::GetPaPower => "$PPG,0".to_string(),
-            Command::GetPowerSetpoint => "$PWRG,0".to_string(),
-            Command::SetPower(value) => format!("$PWRS,0,{:.2}", value),
+            Command::GetStatus { verbose } => json.with("verbose", *verbose),
+            Command::SetFrequency(value)
+            | Command::SetPower(value)
+            | Command::SetFanSpeed(value) => json.with("value", *value),
             Command::ConfigureDll {
                 param1,
                 param2,
@@ -74,23 +74,73 @@ impl Command {
                 param4,
                 param5,
                 param6,
-            } => format!(
-                "$DLES,0,{:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
-                param1, param2, param3, param4, param5, param6
-            ),
-            Command::DllEnable => "$DLES,0,1".to_string(),
-            Command::DllDisable => "$DLES,0,0".to_string(),
-            Command::RfEnable => "$ECS,0,1".to_string(),
-            Command::RfDisable => "$ECS,0,0".to_string(),
+            } => json
+                .with("param1", *param1)
+                .with("param2", *param2)
+                .with("param3", *param3)
+                .with("param4", *param4)
+                .with("param5", *param5)
+                .with("param6", *param6),
             Command::SweepDbm {
                 start,
                 stop,
                 step,
-                dwell,
-            } => format!(
-                "$SWPD,0,{:.2},{:.2},{:.2},{:.2},0",
-                start, stop, step, dwell
-            ),
+                power_dbm,
+            } => json
+                .with("start", *start)
+                .with("stop", *stop)
+                .with("step", *step)
+                .with("power_dbm", *power_dbm),
+            _ => json,
         }
     }
 }
+
+impl FromJson for Command {
+    fn from_json(json: &JsonValue) -> Result<Command, String> {
+        let command = match json.string("command")? {
+            "get_identity" => Command::GetIdentity,
+            "get_version" => Command::GetVersion,
+            "get_status" => Command::GetStatus {
+                verbose: json
+                    .get("verbose")
+                    .and_then(JsonValue::as_bool)
+                    .unwrap_or(false),
+            },
+            "clear_errors" => Command::ClearErrors,
+            "get_frequency" => Command::GetFrequency,
+            "set_frequency" => Command::SetFrequency(json.f32("value")?),
+            "get_pa_power" => Command::GetPaPower,
+            "get_power_setpoint" => Command::GetPowerSetpoint,
+            "set_power" => Command::SetPower(json.f32("value")?),
+            "configure_dll" => Command::ConfigureDll {
+                param1: json.f32("param1")?,
+                param2: json.f32("param2")?,
+                param3: json.f32("param3")?,
+                param4: json.f32("param4")?,
+                param5: json.f32("param5")?,
+                param6: json.f32("param6")?,
+            },
+            "dll_enable" => Command::DllEnable,
+            "dll_disable" => Command::DllDisable,
+            "rf_enable" => Command::RfEnable,
+            "rf_disable" => Command::RfDisable,
+            "sweep_dbm" => Command::SweepDbm {
+                start: json.f32("start")?,
+                stop: json.f32("stop")?,
+                step: json.f32("step")?,
+                power_dbm: json.f32("power_dbm")?,
+            },
+            "get_pa_temperature" => Command::GetPaTemperature,
+            "get_supply_voltage" => Command::GetSupplyVoltage,
+            "get_supply_current" => Command::GetSupplyCurrent,
+            "get_fan_speed" => Command::GetFanSpeed,
+            "set_fan_speed" => Command::SetFanSpeed(json.f32("value")?),
+            "lock_local" => Command::LockLocal,
+            "unlock_local" => Command::UnlockLocal,
+            "get_local_lockout" => Command::GetLocalLockout,
+            other => return Err(format!("Unknown command: {}", other)),
+        };
+        Ok(command)
+    }
+}
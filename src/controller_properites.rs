@@ -3,8 +3,12 @@
 pub const TARGET_VENDOR_ID: u16 = 1027;
 pub const TARGET_PRODUCT_ID: u16 = 24577;
 pub const BAUD_RATE: u32 = 115_200;
+#[cfg(feature = "serial")]
 pub const DATA_BITS: serialport::DataBits = serialport::DataBits::Eight;
+#[cfg(feature = "serial")]
 pub const PARITY: serialport::Parity = serialport::Parity::None;
+#[cfg(feature = "serial")]
 pub const FLOW_CONTROL: serialport::FlowControl = serialport::FlowControl::None;
+#[cfg(feature = "serial")]
 pub const STOP_BITS: serialport::StopBits = serialport::StopBits::One;
 pub const CONNECTION_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
@@ -0,0 +1,133 @@
+use crate::error::{ControllerError, DeviceError, DeviceFault};
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::protocol::{is_error_reply, Reply, StatusFlags};
+
+/// A reply line split into its comma separated fields.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Response {
+    /// The reply with its line terminator removed.
+    pub raw: String,
+    /// Leading `$` field, e.g. `$FCG`.
+    pub mnemonic: String,
+    /// Fields after the mnemonic, starting with the channel.
+    pub fields: Vec<String>,
+}
+
+impl Response {
+    pub fn parse(raw: &str) -> Response {
+        let raw = raw.trim().to_string();
+        let mut fields = raw.split(',').map(|field| field.trim().to_string());
+        let mnemonic = fields.next().unwrap_or_default();
+        Response {
+            mnemonic,
+            fields: fields.collect(),
+            raw,
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        is_error_reply(&self.raw)
+    }
+
+    /// Numeric values after the channel field, as [`parse_values`].
+    pub fn values(&self) -> Result<Vec<f32>, ControllerError> {
+        parse_values(&self.raw)
+    }
+}
+
+impl ToJson for Response {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("raw", self.raw.as_str())
+            .with("mnemonic", self.mnemonic.as_str())
+            .with(
+                "fields",
+                self.fields
+                    .iter()
+                    .map(|field| field.as_str())
+                    .collect::<Vec<_>>(),
+            )
+            .with("error", self.is_error())
+    }
+}
+
+impl FromJson for Response {
+    /// The other fields are derived, so only `raw` is read back.
+    fn from_json(json: &JsonValue) -> Result<Response, String> {
+        Ok(Response::parse(json.string("raw")?))
+    }
+}
+
+/// Splits a `$MNEMONIC,<channel>,<values...>` reply into its numeric values.
+///
+/// The mnemonic and channel fields are skipped; replies flagged with `ERR`
+/// by the board are returned as [`ControllerError::Device`].
+pub fn parse_values(response: &str) -> Result<Vec<f32>, ControllerError> {
+    check_reply(response)?;
+
+    let line = response.trim();
+    let invalid = || ControllerError::InvalidResponse(line.to_string());
+    let reply = Reply::parse(line).map_err(|_| invalid())?;
+    if reply.fields().next().is_none() {
+        return Err(invalid());
+    }
+
+    reply
+        .values()
+        .map(|value| value.map_err(|_| invalid()))
+        .collect()
+}
+
+/// Returns the first numeric value of a reply.
+pub fn parse_value(response: &str) -> Result<f32, ControllerError> {
+    match parse_values(response)?.first() {
+        Some(value) => Ok(*value),
+        None => Err(ControllerError::InvalidResponse(
+            response.trim().to_string(),
+        )),
+    }
+}
+
+/// Fails if the board answered with an error reply.
+pub fn check_reply(response: &str) -> Result<(), ControllerError> {
+    if is_error_reply(response) {
+        Err(ControllerError::Device(DeviceError::parse(response)))
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads the faults of a verbose `$ST,0,1` reply: one `$ST,0,<description>`
+/// line per error, then `OK`. Lines carrying a status code instead are
+/// decoded bit by bit, and descriptions that name no known fault are
+/// returned as [`DeviceFault::Unknown`].
+pub fn parse_verbose_status(response: &str) -> Result<Vec<DeviceFault>, ControllerError> {
+    let mut faults = Vec::new();
+    for line in response.lines().map(str::trim) {
+        if line.is_empty() || line == "OK" {
+            continue;
+        }
+        check_reply(line)?;
+        if let Ok(status) = StatusFlags::from_reply(line) {
+            faults.extend(DeviceFault::of_status(status));
+            continue;
+        }
+        let reply =
+            Reply::parse(line).map_err(|_| ControllerError::InvalidResponse(line.to_string()))?;
+        let description: Vec<&str> = reply.fields().collect();
+        if description.is_empty() {
+            return Err(ControllerError::InvalidResponse(line.to_string()));
+        }
+        faults.push(
+            DeviceFault::from_description(&description.join(",")).unwrap_or(DeviceFault::Unknown),
+        );
+    }
+    Ok(faults)
+}
+
+/// Decodes a `$ST` status reply into its error flags.
+pub fn parse_status(response: &str) -> Result<StatusFlags, ControllerError> {
+    check_reply(response)?;
+    StatusFlags::from_reply(response)
+        .map_err(|_| ControllerError::InvalidResponse(response.trim().to_string()))
+}
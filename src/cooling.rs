@@ -0,0 +1,224 @@
+//! Fan control from the host.
+//!
+//! The board sets its fans to whatever duty cycle it is sent (`$FNS`); the
+//! curve lives here. A [`FanControl`] follows the telemetry: a [`FanCurve`]
+//! maps the PA temperature to a speed, a run above a given setpoint can
+//! force the fans to full speed, and after RF goes off the fans keep the
+//! speed they last ran at for a spin-down delay, to carry off the heat left
+//! in the heatsink.
+
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::protocol::FAN_SPEED_RANGE_PERCENT;
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+
+/// Actor of the fan speed changes a [`FanControl`] makes, in audit logs and
+/// exchange traces.
+pub const FAN_CONTROL_ACTOR: &str = "fan_control";
+
+/// Fan speed against PA temperature: linear between the points, flat
+/// below the first and above the last.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanCurve {
+    /// Temperature (°C) and speed (%), by temperature.
+    points: Vec<(f32, f32)>,
+}
+
+impl FanCurve {
+    pub fn new(mut points: Vec<(f32, f32)>) -> Result<FanCurve, String> {
+        if points.is_empty() {
+            return Err("A fan curve needs at least one point".to_string());
+        }
+        if let Some((celsius, percent)) = points.iter().find(|(celsius, percent)| {
+            !celsius.is_finite() || !FAN_SPEED_RANGE_PERCENT.contains(percent)
+        }) {
+            return Err(format!(
+                "Invalid fan curve point {}:{}; speeds are 0 to 100 %",
+                celsius, percent
+            ));
+        }
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Ok(FanCurve { points })
+    }
+
+    pub fn points(&self) -> &[(f32, f32)] {
+        &self.points
+    }
+
+    /// Fan speed for the PA at `temperature_c`, %.
+    pub fn speed_at(&self, temperature_c: f32) -> f32 {
+        let (first, last) = (self.points[0], self.points[self.points.len() - 1]);
+        if temperature_c <= first.0 {
+            return first.1;
+        }
+        self.points
+            .windows(2)
+            .find(|pair| temperature_c < pair[1].0)
+            .map_or(last.1, |pair| {
+                let ((from_c, from), (to_c, to)) = (pair[0], pair[1]);
+                from + (to - from) * (temperature_c - from_c) / (to_c - from_c)
+            })
+    }
+}
+
+impl FromStr for FanCurve {
+    type Err = String;
+
+    /// `<°C>:<%>,...`, e.g. `40:30,60:60,75:100`.
+    fn from_str(text: &str) -> Result<FanCurve, String> {
+        let invalid = || {
+            format!(
+                "Invalid fan curve {}; use <°C>:<%>,..., e.g. 40:30,60:60,75:100",
+                text
+            )
+        };
+        let points = text
+            .split(',')
+            .map(|point| {
+                let (celsius, percent) = point.split_once(':').ok_or_else(invalid)?;
+                Ok((
+                    celsius.trim().parse().map_err(|_| invalid())?,
+                    percent
+                        .trim()
+                        .trim_end_matches('%')
+                        .parse()
+                        .map_err(|_| invalid())?,
+                ))
+            })
+            .collect::<Result<Vec<(f32, f32)>, String>>()?;
+        FanCurve::new(points)
+    }
+}
+
+/// What a [`FanControl`] runs the fans at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanPolicy {
+    pub curve: FanCurve,
+    /// Full speed while RF is on with the power setpoint at or above this.
+    pub full_speed_above_dbm: Option<f32>,
+    /// How long after RF off the fans stay at least at the speed they last
+    /// ran at with RF on.
+    pub spin_down: Duration,
+}
+
+impl FanPolicy {
+    pub fn new(curve: FanCurve) -> FanPolicy {
+        FanPolicy {
+            curve,
+            full_speed_above_dbm: None,
+            spin_down: Duration::from_secs(60),
+        }
+    }
+
+    pub fn with_full_speed_above(mut self, dbm: f32) -> FanPolicy {
+        self.full_speed_above_dbm = Some(dbm);
+        self
+    }
+
+    pub fn with_spin_down(mut self, spin_down: Duration) -> FanPolicy {
+        self.spin_down = spin_down;
+        self
+    }
+}
+
+#[derive(Default)]
+struct FanState {
+    /// The speed last sent to the board.
+    sent: Option<f32>,
+    /// The last sample with RF on and the speed chosen for it.
+    rf_on: Option<(SystemTime, f32)>,
+    /// Set when the board rejected the first speed sent.
+    unsupported: bool,
+}
+
+/// Applies a [`FanPolicy`] to telemetry samples.
+///
+/// Speeds are sent in whole percent, and only when they change. Without a
+/// temperature reading the curve cannot be followed, so the fans are left
+/// where they are unless full speed or the spin-down applies. Like derating,
+/// fan control is exempt from single-writer arbitration.
+pub struct FanControl {
+    controller: Controller,
+    policy: FanPolicy,
+    state: Arc<Mutex<FanState>>,
+}
+
+impl FanControl {
+    pub fn new(controller: &Controller, policy: FanPolicy) -> FanControl {
+        FanControl {
+            controller: controller.with_actor(FAN_CONTROL_ACTOR),
+            policy,
+            state: Arc::default(),
+        }
+    }
+
+    /// The speed last set, %.
+    pub fn speed_percent(&self) -> Option<f32> {
+        self.state.lock().ok()?.sent
+    }
+
+    /// Listener for a [`TelemetryPoller`](crate::telemetry::TelemetryPoller)
+    /// that sets the fan speed. Failed changes are reported on stderr and
+    /// tried again at the next sample, except that fan control stops if the
+    /// board rejects the first speed it is sent.
+    pub fn listener(&self) -> TelemetryListener {
+        let controller = self.controller.clone();
+        let policy = self.policy.clone();
+        let state = self.state.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            let Ok(mut state) = state.lock() else {
+                return;
+            };
+            if state.unsupported {
+                return;
+            }
+            let mut speed = sample
+                .temperature_c
+                .map(|celsius| policy.curve.speed_at(celsius));
+            let high_power = policy
+                .full_speed_above_dbm
+                .is_some_and(|dbm| sample.power_setpoint_dbm >= dbm);
+            if sample.rf_enabled && high_power {
+                speed = Some(*FAN_SPEED_RANGE_PERCENT.end());
+            }
+            if sample.rf_enabled {
+                if let Some(percent) = speed.or(state.sent) {
+                    state.rf_on = Some((sample.timestamp, percent));
+                }
+            } else if let Some((last_on, percent)) = state.rf_on {
+                let spinning_down = sample
+                    .timestamp
+                    .duration_since(last_on)
+                    .is_ok_and(|off_for| off_for < policy.spin_down);
+                if spinning_down {
+                    speed = Some(speed.map_or(percent, |speed| speed.max(percent)));
+                } else {
+                    state.rf_on = None;
+                }
+            }
+
+            let Some(speed) = speed.map(f32::round) else {
+                return;
+            };
+            if state.sent == Some(speed) {
+                return;
+            }
+            match controller.send_as_system(&Command::SetFanSpeed(speed)) {
+                Ok(_) => state.sent = Some(speed),
+                Err(ControllerError::Device(e)) if state.sent.is_none() => {
+                    state.unsupported = true;
+                    eprintln!(
+                        "The board does not take fan speeds, fan control is off: {}",
+                        e
+                    );
+                }
+                Err(e) => eprintln!("Setting the fans to {:.0} % failed: {}", speed, e),
+            }
+        })
+    }
+}
@@ -0,0 +1,207 @@
+//! Thermal derating: less power while the PA runs hot.
+//!
+//! A [`DeratingPolicy`] is a set of temperature tiers, each lowering the
+//! power setpoint by a fixed amount, and a hysteresis so the power is not
+//! restored until the PA has cooled clearly below a tier. [`SoakTest`] applies
+//! one itself; for anything else, such as recipes or a daemon's clients, a
+//! [`Derater`] applies it from telemetry underneath whoever sets the power,
+//! and reports every change as [`ControllerEvent::PowerDerated`].
+//!
+//! [`SoakTest`]: crate::soak::SoakTest
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::check_reply;
+use crate::error::ControllerError;
+use crate::events::{ControllerEvent, Exchange};
+use crate::protocol::POWER_RANGE_DBM;
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+
+/// Actor of the setpoint changes a [`Derater`] makes, in audit logs and
+/// exchange traces.
+pub const DERATING_ACTOR: &str = "derating";
+
+/// Reduce the power by `reduce_db` while the PA is at `above_c` or hotter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeratingRule {
+    pub above_c: f32,
+    pub reduce_db: f32,
+}
+
+impl FromStr for DeratingRule {
+    type Err = String;
+
+    /// `<°C>:<dB>`, e.g. `60:3`.
+    fn from_str(text: &str) -> Result<DeratingRule, String> {
+        let invalid = || format!("Invalid derating rule {}; use <°C>:<dB>, e.g. 60:3", text);
+        let (above, reduce) = text.split_once(':').ok_or_else(invalid)?;
+        let rule = DeratingRule {
+            above_c: above.trim().parse().map_err(|_| invalid())?,
+            reduce_db: reduce.trim().parse().map_err(|_| invalid())?,
+        };
+        if !(rule.reduce_db >= 0.0 && rule.reduce_db.is_finite() && rule.above_c.is_finite()) {
+            return Err(invalid());
+        }
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for DeratingRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "-{} dB from {} °C", self.reduce_db, self.above_c)
+    }
+}
+
+/// Temperature tiers and the hysteresis for lifting them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeratingPolicy {
+    /// The hottest tier that applies wins; tiers do not add up.
+    pub tiers: Vec<DeratingRule>,
+    /// How far below a tier's temperature the PA must cool before its
+    /// reduction is lifted.
+    pub hysteresis_c: f32,
+}
+
+impl Default for DeratingPolicy {
+    fn default() -> DeratingPolicy {
+        DeratingPolicy {
+            tiers: Vec::new(),
+            hysteresis_c: 2.0,
+        }
+    }
+}
+
+impl DeratingPolicy {
+    pub fn with_tier(mut self, rule: DeratingRule) -> DeratingPolicy {
+        self.tiers.push(rule);
+        self
+    }
+
+    pub fn with_hysteresis(mut self, celsius: f32) -> DeratingPolicy {
+        self.hysteresis_c = celsius;
+        self
+    }
+
+    /// Power reduction for the PA at `temperature_c` when `active_db` is in
+    /// effect: raised as soon as a tier applies, lowered only once the PA
+    /// is `hysteresis_c` below the tier.
+    pub fn reduction_at(&self, temperature_c: f32, active_db: f32) -> f32 {
+        let reduction = |margin: f32| {
+            self.tiers
+                .iter()
+                .filter(|rule| temperature_c >= rule.above_c - margin)
+                .map(|rule| rule.reduce_db)
+                .fold(0.0, f32::max)
+        };
+        let rising = reduction(0.0);
+        if rising >= active_db {
+            return rising;
+        }
+        reduction(self.hysteresis_c).min(active_db)
+    }
+}
+
+#[derive(Default)]
+struct DeraterState {
+    /// The setpoint last asked for by anyone but the derater.
+    commanded_dbm: Option<f32>,
+    reduction_db: f32,
+}
+
+/// Applies a [`DeratingPolicy`] to telemetry samples.
+///
+/// The derater follows the setpoint others send (`$PWRS`) and keeps the
+/// board that far below it while a tier applies. A setpoint changed during
+/// derating, e.g. by the next recipe step, is lowered at the next sample.
+/// Under single-writer arbitration it derates whoever holds control, and
+/// never takes control itself.
+pub struct Derater {
+    controller: Controller,
+    policy: DeratingPolicy,
+    state: Arc<Mutex<DeraterState>>,
+}
+
+impl Derater {
+    /// Starts following the setpoints sent through `controller`.
+    pub fn attach(
+        controller: &Controller,
+        policy: DeratingPolicy,
+    ) -> Result<Derater, ControllerError> {
+        let state = Arc::new(Mutex::new(DeraterState::default()));
+        let observed = state.clone();
+        controller.observe_exchanges(Arc::new(move |exchange: &Exchange| {
+            if exchange.actor == DERATING_ACTOR {
+                return;
+            }
+            let accepted = exchange
+                .reply
+                .as_ref()
+                .is_ok_and(|reply| check_reply(reply).is_ok());
+            let setpoint = exchange
+                .command
+                .trim()
+                .strip_prefix("$PWRS,0,")
+                .and_then(|value| value.parse::<f32>().ok());
+            if let (true, Some(dbm)) = (accepted, setpoint) {
+                if let Ok(mut state) = observed.lock() {
+                    state.commanded_dbm = Some(dbm);
+                }
+            }
+        }))?;
+        Ok(Derater {
+            controller: controller.with_actor(DERATING_ACTOR),
+            policy,
+            state,
+        })
+    }
+
+    /// The reduction in effect, in dB.
+    pub fn reduction_db(&self) -> f32 {
+        self.state.lock().map_or(0.0, |state| state.reduction_db)
+    }
+
+    /// Listener for a [`TelemetryPoller`](crate::telemetry::TelemetryPoller)
+    /// that derates and restores the power. Failed setpoint changes are
+    /// reported on stderr and tried again at the next sample.
+    pub fn listener(&self) -> TelemetryListener {
+        let controller = self.controller.clone();
+        let policy = self.policy.clone();
+        let state = self.state.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            let Some(temperature_c) = sample.temperature_c else {
+                return;
+            };
+            let Ok(mut state) = state.lock() else {
+                return;
+            };
+            // Before anyone sets the power, the board's setpoint is taken
+            // as asked for, unless it is already derated.
+            let commanded_dbm = match state.commanded_dbm {
+                Some(dbm) => dbm,
+                None if state.reduction_db == 0.0 => sample.power_setpoint_dbm,
+                None => return,
+            };
+            let reduction_db = policy.reduction_at(temperature_c, state.reduction_db);
+            let target_dbm = (commanded_dbm - reduction_db).max(*POWER_RANGE_DBM.start());
+            if (sample.power_setpoint_dbm - target_dbm).abs() >= 0.005 {
+                if let Err(e) = controller.send_as_system(&Command::SetPower(target_dbm)) {
+                    eprintln!("Derating to {:.2} dBm failed: {}", target_dbm, e);
+                    return;
+                }
+            }
+            state.commanded_dbm = Some(commanded_dbm);
+            if reduction_db != state.reduction_db {
+                state.reduction_db = reduction_db;
+                controller.emit(&ControllerEvent::PowerDerated {
+                    reduction_db,
+                    setpoint_dbm: target_dbm,
+                    temperature_c,
+                });
+            }
+        })
+    }
+}
@@ -0,0 +1,348 @@
+//! A board's configuration as a JSON file, for cloning it across a fleet:
+//! [`DeviceConfig::read`] queries the board, [`DeviceConfig::apply`] sends
+//! the same setpoints and DLL settings to another board of the same model.
+//!
+//! RF state is not part of the configuration, and importing never enables
+//! RF. The DLL settings are only known when this handle sent them (the
+//! board has no query for them), e.g. through a profile's init commands.
+
+use std::{fs, io, path::Path, time::SystemTime};
+
+use crate::calibration::CalibrationTable;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::error::ControllerError;
+use crate::json::{self, FromJson, JsonValue, ToJson};
+use crate::protocol::{Reply, FREQUENCY_RANGE_MHZ, POWER_RANGE_DBM};
+use crate::settings::DllSettings;
+use crate::sha256::sha256_hex;
+use crate::units::{format_timestamp, parse_timestamp};
+
+/// Identifies the calibration table a configuration was used with, without
+/// copying it: tables are measured per board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationReference {
+    /// SHA-256 of the table as [`CalibrationTable::to_csv`] writes it, so
+    /// reformatting the file does not change it.
+    pub sha256: String,
+    pub points: usize,
+    /// Lowest and highest calibrated frequency.
+    pub frequency_range_mhz: Option<(f32, f32)>,
+}
+
+impl CalibrationReference {
+    pub fn of(table: &CalibrationTable) -> CalibrationReference {
+        let points = table.points();
+        CalibrationReference {
+            sha256: sha256_hex(table.to_csv().as_bytes()),
+            points: points.len(),
+            frequency_range_mhz: points.first().zip(points.last()).map(|(a, b)| (a.0, b.0)),
+        }
+    }
+
+    pub fn matches(&self, table: &CalibrationTable) -> bool {
+        CalibrationReference::of(table).sha256 == self.sha256
+    }
+}
+
+/// Limits the configuration was exported under.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigLimits {
+    pub frequency_range_mhz: (f32, f32),
+    pub power_range_dbm: (f32, f32),
+    /// Highest setpoint operators were allowed, from the access policy.
+    pub operator_power_limit_dbm: Option<f32>,
+}
+
+impl Default for ConfigLimits {
+    fn default() -> ConfigLimits {
+        ConfigLimits {
+            frequency_range_mhz: (*FREQUENCY_RANGE_MHZ.start(), *FREQUENCY_RANGE_MHZ.end()),
+            power_range_dbm: (*POWER_RANGE_DBM.start(), *POWER_RANGE_DBM.end()),
+            operator_power_limit_dbm: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceConfig {
+    pub exported_at: SystemTime,
+    /// `$IDN` reply fields of the exported board, e.g. `Simulator, ISC-SIM`.
+    pub identity: Option<String>,
+    /// `$VER` reply fields.
+    pub firmware: Option<String>,
+    pub frequency_mhz: f32,
+    pub power_setpoint_dbm: f32,
+    pub dll: DllSettings,
+    pub limits: ConfigLimits,
+    pub calibration: Option<CalibrationReference>,
+}
+
+impl DeviceConfig {
+    /// Queries the board's identity, firmware, frequency and power setpoint,
+    /// and takes the DLL settings this handle has sent.
+    pub fn read(controller: &Controller) -> Result<DeviceConfig, ControllerError> {
+        let settings = controller.read_all_settings()?;
+        Ok(DeviceConfig {
+            exported_at: SystemTime::now(),
+            identity: query_fields(controller, &Command::GetIdentity),
+            firmware: query_fields(controller, &Command::GetVersion),
+            frequency_mhz: settings.frequency_mhz,
+            power_setpoint_dbm: settings.power_setpoint_dbm,
+            dll: settings.dll,
+            limits: ConfigLimits::default(),
+            calibration: None,
+        })
+    }
+
+    pub fn with_calibration(mut self, table: &CalibrationTable) -> DeviceConfig {
+        self.calibration = Some(CalibrationReference::of(table));
+        self
+    }
+
+    pub fn with_operator_power_limit(mut self, dbm: f32) -> DeviceConfig {
+        self.limits.operator_power_limit_dbm = Some(dbm);
+        self
+    }
+
+    /// Commands that reproduce the configuration, in the order they are sent.
+    pub fn commands(&self) -> Vec<Command> {
+        let mut commands = vec![
+            Command::SetFrequency(self.frequency_mhz),
+            Command::SetPower(self.power_setpoint_dbm),
+        ];
+        if let Some([param1, param2, param3, param4, param5, param6]) = self.dll.parameters {
+            commands.push(Command::ConfigureDll {
+                param1,
+                param2,
+                param3,
+                param4,
+                param5,
+                param6,
+            });
+        }
+        match self.dll.enabled {
+            Some(true) => commands.push(Command::DllEnable),
+            Some(false) => commands.push(Command::DllDisable),
+            None => {}
+        }
+        commands
+    }
+
+    /// Checks the commands and the setpoint against the limits, without
+    /// talking to a board.
+    pub fn validate(&self) -> Result<(), ControllerError> {
+        for command in self.commands() {
+            command.validate()?;
+        }
+        let (low, high) = self.limits.frequency_range_mhz;
+        if !(low..=high).contains(&self.frequency_mhz) {
+            return Err(ControllerError::InvalidParameter(format!(
+                "frequency {} MHz outside the exported limits {}..={} MHz",
+                self.frequency_mhz, low, high
+            )));
+        }
+        let (low, high) = self.limits.power_range_dbm;
+        let high = self
+            .limits
+            .operator_power_limit_dbm
+            .map_or(high, |limit| limit.min(high));
+        if !(low..=high).contains(&self.power_setpoint_dbm) {
+            return Err(ControllerError::InvalidParameter(format!(
+                "power {} dBm outside the exported limits {}..={} dBm",
+                self.power_setpoint_dbm, low, high
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends the configuration to `controller` after checking it and the
+    /// board's model, then reads the frequency and setpoint back.
+    ///
+    /// The model is the first two `$IDN` fields, manufacturer and model;
+    /// further fields, such as a serial number, may differ. A configuration without an identity is
+    /// applied to any board.
+    pub fn apply(&self, controller: &Controller) -> Result<(), ControllerError> {
+        self.validate()?;
+        if let Some(identity) = &self.identity {
+            let board = query_fields(controller, &Command::GetIdentity).unwrap_or_default();
+            if model(&board) != model(identity) {
+                return Err(ControllerError::UnexpectedDevice(format!(
+                    "{} (the configuration is for {} boards)",
+                    board,
+                    model(identity)
+                )));
+            }
+        }
+        for command in self.commands() {
+            controller.send(&command)?;
+        }
+        let frequency_mhz = parse_value(&controller.send(&Command::GetFrequency)?)?;
+        let power_setpoint_dbm = parse_value(&controller.send(&Command::GetPowerSetpoint)?)?;
+        if (frequency_mhz - self.frequency_mhz).abs() > 0.01
+            || (power_setpoint_dbm - self.power_setpoint_dbm).abs() > 0.01
+        {
+            return Err(ControllerError::InvalidResponse(format!(
+                "Read back {:.2} MHz and {:.2} dBm after applying {:.2} MHz and {:.2} dBm",
+                frequency_mhz, power_setpoint_dbm, self.frequency_mhz, self.power_setpoint_dbm
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<DeviceConfig> {
+        let text = fs::read_to_string(path)?;
+        json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json().to_pretty_string() + "\n")
+    }
+}
+
+/// Reply fields of a query joined with `, `, as run reports show them.
+fn query_fields(controller: &Controller, command: &Command) -> Option<String> {
+    let reply = controller.send(command).ok()?;
+    let fields: Vec<&str> = Reply::parse(&reply).ok()?.fields().collect();
+    Some(fields.join(", "))
+}
+
+/// Manufacturer and model, without the fields after them.
+fn model(identity: &str) -> String {
+    let fields: Vec<&str> = identity.split(',').map(str::trim).take(2).collect();
+    fields.join(", ")
+}
+
+fn range_json((low, high): (f32, f32)) -> JsonValue {
+    JsonValue::Array(vec![low.into(), high.into()])
+}
+
+fn range_from_json(json: &JsonValue, key: &str) -> Result<(f32, f32), String> {
+    match json.array(key)? {
+        [low, high] => match (low.as_f64(), high.as_f64()) {
+            (Some(low), Some(high)) => Ok((low as f32, high as f32)),
+            _ => Err(format!("{} must hold two numbers", key)),
+        },
+        _ => Err(format!("{} must hold two numbers", key)),
+    }
+}
+
+impl ToJson for CalibrationReference {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("sha256", self.sha256.as_str())
+            .with("points", self.points)
+            .with(
+                "frequency_range_mhz",
+                self.frequency_range_mhz.map(range_json),
+            )
+    }
+}
+
+impl FromJson for CalibrationReference {
+    fn from_json(json: &JsonValue) -> Result<CalibrationReference, String> {
+        Ok(CalibrationReference {
+            sha256: json.string("sha256")?.to_string(),
+            points: json.number("points")? as usize,
+            frequency_range_mhz: match json.get("frequency_range_mhz") {
+                None | Some(JsonValue::Null) => None,
+                Some(_) => Some(range_from_json(json, "frequency_range_mhz")?),
+            },
+        })
+    }
+}
+
+impl ToJson for ConfigLimits {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("frequency_range_mhz", range_json(self.frequency_range_mhz))
+            .with("power_range_dbm", range_json(self.power_range_dbm))
+            .with("operator_power_limit_dbm", self.operator_power_limit_dbm)
+    }
+}
+
+impl FromJson for ConfigLimits {
+    fn from_json(json: &JsonValue) -> Result<ConfigLimits, String> {
+        let defaults = ConfigLimits::default();
+        let range = |key: &str, default: (f32, f32)| match json.get(key) {
+            None | Some(JsonValue::Null) => Ok(default),
+            Some(_) => range_from_json(json, key),
+        };
+        Ok(ConfigLimits {
+            frequency_range_mhz: range("frequency_range_mhz", defaults.frequency_range_mhz)?,
+            power_range_dbm: range("power_range_dbm", defaults.power_range_dbm)?,
+            operator_power_limit_dbm: json.optional_f32("operator_power_limit_dbm")?,
+        })
+    }
+}
+
+impl ToJson for DeviceConfig {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("exported_at", format_timestamp(self.exported_at))
+            .with("identity", self.identity.clone())
+            .with("firmware", self.firmware.clone())
+            .with("frequency_mhz", self.frequency_mhz)
+            .with("power_setpoint_dbm", self.power_setpoint_dbm)
+            .with("dll_enabled", self.dll.enabled)
+            .with(
+                "dll_parameters",
+                self.dll.parameters.map(|parameters| parameters.to_vec()),
+            )
+            .with("limits", self.limits.to_json())
+            .with(
+                "calibration",
+                self.calibration.as_ref().map(ToJson::to_json),
+            )
+    }
+}
+
+impl FromJson for DeviceConfig {
+    fn from_json(json: &JsonValue) -> Result<DeviceConfig, String> {
+        let text = |key: &str| match json.get(key) {
+            None | Some(JsonValue::Null) => Ok(None),
+            Some(_) => json.string(key).map(|text| Some(text.to_string())),
+        };
+        let dll_parameters = match json.get("dll_parameters") {
+            None | Some(JsonValue::Null) => None,
+            Some(_) => {
+                let values = json
+                    .array("dll_parameters")?
+                    .iter()
+                    .map(|value| value.as_f64().map(|value| value as f32))
+                    .collect::<Option<Vec<f32>>>();
+                Some(
+                    values
+                        .and_then(|values| <[f32; 6]>::try_from(values).ok())
+                        .ok_or("dll_parameters needs six numbers")?,
+                )
+            }
+        };
+        Ok(DeviceConfig {
+            exported_at: match json.get("exported_at") {
+                None | Some(JsonValue::Null) => SystemTime::now(),
+                Some(_) => parse_timestamp(json.string("exported_at")?)?,
+            },
+            identity: text("identity")?,
+            firmware: text("firmware")?,
+            frequency_mhz: json.f32("frequency_mhz")?,
+            power_setpoint_dbm: json.f32("power_setpoint_dbm")?,
+            dll: DllSettings {
+                enabled: match json.get("dll_enabled") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(_) => Some(json.boolean("dll_enabled")?),
+                },
+                parameters: dll_parameters,
+            },
+            limits: match json.get("limits") {
+                None | Some(JsonValue::Null) => ConfigLimits::default(),
+                Some(limits) => ConfigLimits::from_json(limits)?,
+            },
+            calibration: match json.get("calibration") {
+                None | Some(JsonValue::Null) => None,
+                Some(reference) => Some(CalibrationReference::from_json(reference)?),
+            },
+        })
+    }
+}
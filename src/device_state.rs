@@ -0,0 +1,172 @@
+use std::fmt;
+
+use crate::controller_responses::parse_values;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::protocol::is_error_reply;
+use crate::telemetry::TelemetrySample;
+
+/// Host-side picture of the generator, built from observed commands,
+/// replies and telemetry. Fields stay `None` until first observed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeviceState {
+    pub frequency_mhz: Option<f32>,
+    pub power_setpoint_dbm: Option<f32>,
+    pub rf_enabled: Option<bool>,
+    pub dll_enabled: Option<bool>,
+    pub forward_dbm: Option<f32>,
+    pub reflected_dbm: Option<f32>,
+    pub temperature_c: Option<f32>,
+    /// PA supply rail voltage and current draw.
+    pub supply_voltage_v: Option<f32>,
+    pub supply_current_a: Option<f32>,
+    pub fan_speed_percent: Option<f32>,
+    /// Whether the front panel is locked out.
+    pub local_lockout: Option<bool>,
+    /// Last error reply from the board.
+    pub last_error: Option<String>,
+}
+
+impl DeviceState {
+    /// Applies a command and the board's reply to it. Setpoints only change
+    /// when the reply is not an error.
+    pub fn apply_exchange(&mut self, tx: &str, rx: &str) {
+        if is_error_reply(rx) {
+            self.last_error = Some(rx.trim().to_string());
+            return;
+        }
+
+        let fields: Vec<&str> = tx.trim().split(',').collect();
+        let argument = |index: usize| fields.get(index).and_then(|f| f.trim().parse::<f32>().ok());
+        match fields[0] {
+            "$FCS" => self.frequency_mhz = argument(2).or(self.frequency_mhz),
+            "$PWRS" => self.power_setpoint_dbm = argument(2).or(self.power_setpoint_dbm),
+            "$FNS" => self.fan_speed_percent = argument(2).or(self.fan_speed_percent),
+            "$RLS" => self.local_lockout = argument(2).map(|v| v != 0.0).or(self.local_lockout),
+            "$ECS" => self.rf_enabled = argument(2).map(|v| v != 0.0).or(self.rf_enabled),
+            // `$DLES,0,<0|1>` toggles the DLL; the six parameter form configures it.
+            "$DLES" if fields.len() == 3 => {
+                self.dll_enabled = argument(2).map(|v| v != 0.0).or(self.dll_enabled)
+            }
+            "$ERRC" => self.last_error = None,
+            _ => {}
+        }
+
+        let values = match parse_values(rx) {
+            Ok(values) => values,
+            Err(_) => return,
+        };
+        match (fields[0], values.as_slice()) {
+            ("$FCG", [frequency, ..]) => self.frequency_mhz = Some(*frequency),
+            ("$PWRG", [power, ..]) => self.power_setpoint_dbm = Some(*power),
+            ("$PPG", [forward, reflected, ..]) => {
+                self.forward_dbm = Some(*forward);
+                self.reflected_dbm = Some(*reflected);
+            }
+            ("$PTG", [temperature, ..]) => self.temperature_c = Some(*temperature),
+            ("$PVG", [voltage, ..]) => self.supply_voltage_v = Some(*voltage),
+            ("$PCG", [current, ..]) => self.supply_current_a = Some(*current),
+            ("$FNG", [speed, ..]) => self.fan_speed_percent = Some(*speed),
+            ("$RLG", [lock, ..]) => self.local_lockout = Some(*lock != 0.0),
+            _ => {}
+        }
+    }
+
+    pub fn apply_telemetry(&mut self, sample: &TelemetrySample) {
+        self.frequency_mhz = Some(sample.frequency_mhz);
+        self.power_setpoint_dbm = Some(sample.power_setpoint_dbm);
+        self.forward_dbm = Some(sample.forward_dbm);
+        self.reflected_dbm = Some(sample.reflected_dbm);
+        self.temperature_c = sample.temperature_c.or(self.temperature_c);
+        self.supply_voltage_v = sample.supply_voltage_v.or(self.supply_voltage_v);
+        self.supply_current_a = sample.supply_current_a.or(self.supply_current_a);
+        self.rf_enabled = Some(sample.rf_enabled);
+    }
+}
+
+impl fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn field(value: Option<f32>, unit: &str) -> String {
+            value
+                .map(|v| format!("{:.2} {}", v, unit))
+                .unwrap_or_else(|| "?".to_string())
+        }
+        fn switch(value: Option<bool>) -> &'static str {
+            match value {
+                Some(true) => "on",
+                Some(false) => "off",
+                None => "?",
+            }
+        }
+
+        write!(
+            f,
+            "freq {} | setpoint {} | RF {} | DLL {} | fwd {} | refl {} | temp {}",
+            field(self.frequency_mhz, "MHz"),
+            field(self.power_setpoint_dbm, "dBm"),
+            switch(self.rf_enabled),
+            switch(self.dll_enabled),
+            field(self.forward_dbm, "dBm"),
+            field(self.reflected_dbm, "dBm"),
+            field(self.temperature_c, "°C"),
+        )?;
+        if self.supply_voltage_v.is_some() || self.supply_current_a.is_some() {
+            write!(
+                f,
+                " | supply {} {}",
+                field(self.supply_voltage_v, "V"),
+                field(self.supply_current_a, "A")
+            )?;
+        }
+        if let Some(speed) = self.fan_speed_percent {
+            write!(f, " | fan {:.0} %", speed)?;
+        }
+        if let Some(locked) = self.local_lockout {
+            write!(f, " | panel {}", if locked { "locked" } else { "unlocked" })?;
+        }
+        if let Some(error) = &self.last_error {
+            write!(f, " | error {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for DeviceState {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("frequency_mhz", self.frequency_mhz)
+            .with("power_setpoint_dbm", self.power_setpoint_dbm)
+            .with("rf_enabled", self.rf_enabled)
+            .with("dll_enabled", self.dll_enabled)
+            .with("forward_dbm", self.forward_dbm)
+            .with("reflected_dbm", self.reflected_dbm)
+            .with("temperature_c", self.temperature_c)
+            .with("supply_voltage_v", self.supply_voltage_v)
+            .with("supply_current_a", self.supply_current_a)
+            .with("fan_speed_percent", self.fan_speed_percent)
+            .with("local_lockout", self.local_lockout)
+            .with("last_error", self.last_error.clone())
+    }
+}
+
+impl FromJson for DeviceState {
+    fn from_json(json: &JsonValue) -> Result<DeviceState, String> {
+        let switch = |key: &str| json.get(key).and_then(JsonValue::as_bool);
+        Ok(DeviceState {
+            frequency_mhz: json.optional_f32("frequency_mhz")?,
+            power_setpoint_dbm: json.optional_f32("power_setpoint_dbm")?,
+            rf_enabled: switch("rf_enabled"),
+            dll_enabled: switch("dll_enabled"),
+            forward_dbm: json.optional_f32("forward_dbm")?,
+            reflected_dbm: json.optional_f32("reflected_dbm")?,
+            temperature_c: json.optional_f32("temperature_c")?,
+            supply_voltage_v: json.optional_f32("supply_voltage_v")?,
+            supply_current_a: json.optional_f32("supply_current_a")?,
+            fan_speed_percent: json.optional_f32("fan_speed_percent")?,
+            local_lockout: switch("local_lockout"),
+            last_error: json
+                .get("last_error")
+                .and_then(JsonValue::as_str)
+                .map(str::to_string),
+        })
+    }
+}
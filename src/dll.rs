@@ -0,0 +1,264 @@
+//! Named `$DLES` parameter sets for the board's DLL, kept in the
+//! [`DeviceProfile`](crate::profile::DeviceProfile) so a validated
+//! combination is picked by name instead of typed as six numbers, and a
+//! tuning routine, [`tune_dll`], to find such a combination.
+//!
+//! The parameters are the lower, upper, start and step frequency (MHz), the
+//! reflection threshold (dB) and the main delay (ms).
+
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::sweep::measure_point;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DllPreset {
+    pub name: String,
+    /// What the preset is for, e.g. the load it was validated with.
+    pub description: String,
+    /// The six `$DLES,0,...` parameters, in order.
+    pub parameters: [f32; 6],
+}
+
+impl DllPreset {
+    pub fn new(name: &str, parameters: [f32; 6]) -> DllPreset {
+        DllPreset {
+            name: name.to_string(),
+            description: String::new(),
+            parameters,
+        }
+    }
+
+    pub fn with_description(mut self, description: &str) -> DllPreset {
+        self.description = description.to_string();
+        self
+    }
+
+    pub fn command(&self) -> Command {
+        let [param1, param2, param3, param4, param5, param6] = self.parameters;
+        Command::ConfigureDll {
+            param1,
+            param2,
+            param3,
+            param4,
+            param5,
+            param6,
+        }
+    }
+
+    /// Configures the DLL with the preset and enables it.
+    pub fn apply(&self, controller: &Controller) -> Result<(), ControllerError> {
+        let command = self.command();
+        command.validate()?;
+        controller.send(&command)?;
+        controller.send(&Command::DllEnable)?;
+        Ok(())
+    }
+}
+
+/// Candidate settings for [`tune_dll`]: every step and threshold combination
+/// on top of `base`, which supplies the frequency range, start and delay.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DllTuning {
+    pub base: [f32; 6],
+    pub steps_mhz: Vec<f32>,
+    pub thresholds_db: Vec<f32>,
+    /// RF power during the trials.
+    pub power_dbm: f32,
+    /// Time the DLL gets to find the match before it is observed.
+    pub settle: Duration,
+    /// Readings taken after settling. The DLL counts as locked if their
+    /// frequencies agree within one step.
+    pub readings: usize,
+    pub interval: Duration,
+}
+
+impl DllTuning {
+    /// Searches 2400-2500 MHz from 2400 MHz with a 50 ms main delay, trying
+    /// 0.5, 1 and 2 MHz steps against 10, 15 and 20 dB thresholds at 10 dBm.
+    pub fn new() -> DllTuning {
+        DllTuning {
+            base: [2400.0, 2500.0, 2400.0, 1.0, 15.0, 50.0],
+            steps_mhz: vec![0.5, 1.0, 2.0],
+            thresholds_db: vec![10.0, 15.0, 20.0],
+            power_dbm: 10.0,
+            settle: Duration::from_secs(2),
+            readings: 5,
+            interval: Duration::from_millis(200),
+        }
+    }
+
+    /// Parameter sets in the order they are tried.
+    pub fn candidates(&self) -> Vec<[f32; 6]> {
+        let mut candidates = Vec::new();
+        for &step in &self.steps_mhz {
+            for &threshold in &self.thresholds_db {
+                let mut parameters = self.base;
+                parameters[3] = step;
+                parameters[4] = threshold;
+                candidates.push(parameters);
+            }
+        }
+        candidates
+    }
+}
+
+impl Default for DllTuning {
+    fn default() -> DllTuning {
+        DllTuning::new()
+    }
+}
+
+/// What one candidate did.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DllTrial {
+    pub parameters: [f32; 6],
+    pub locked: bool,
+    /// Frequency at the last reading.
+    pub frequency_mhz: f32,
+    /// Mean reflected power over the readings.
+    pub reflected_dbm: f32,
+    /// Mean S11 over the readings.
+    pub s11_db: f32,
+}
+
+impl DllTrial {
+    pub fn preset(&self, name: &str) -> DllPreset {
+        DllPreset::new(name, self.parameters)
+    }
+}
+
+/// Tries every candidate of `tuning` in turn: configures and enables the
+/// DLL, enables RF, waits for it to settle and records lock and reflection.
+/// `on_trial` sees each result as it is measured.
+///
+/// RF and the DLL are off afterwards and the power setpoint is restored,
+/// whatever the outcome; apply the chosen result with [`DllPreset::apply`].
+pub fn tune_dll(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    tuning: &DllTuning,
+    on_trial: &mut dyn FnMut(&DllTrial),
+) -> Result<Vec<DllTrial>, ControllerError> {
+    let setpoint_dbm = parse_value(&controller.send(&Command::GetPowerSetpoint)?)?;
+    let result = run_trials(controller, cancel, tuning, on_trial);
+    let safe = controller.safe_state();
+    let dll_off = controller.send(&Command::DllDisable);
+    let restored = controller.send(&Command::SetPower(setpoint_dbm));
+    let trials = result?;
+    safe?;
+    dll_off?;
+    restored?;
+    Ok(trials)
+}
+
+fn run_trials(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    tuning: &DllTuning,
+    on_trial: &mut dyn FnMut(&DllTrial),
+) -> Result<Vec<DllTrial>, ControllerError> {
+    // RF enable needs a frequency set; the DLL moves it from there.
+    controller.send(&Command::set_frequency(tuning.base[2])?)?;
+    controller.send(&Command::set_power(tuning.power_dbm)?)?;
+    let mut trials = Vec::new();
+    for parameters in tuning.candidates() {
+        if cancel.is_cancelled() {
+            return Err(ControllerError::Cancelled);
+        }
+        cancel.checkpoint(controller, &[])?;
+        let trial = run_trial(controller, cancel, tuning, parameters)?;
+        on_trial(&trial);
+        trials.push(trial);
+    }
+    Ok(trials)
+}
+
+fn run_trial(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    tuning: &DllTuning,
+    parameters: [f32; 6],
+) -> Result<DllTrial, ControllerError> {
+    DllPreset::new("trial", parameters).apply(controller)?;
+    controller.send(&Command::RfEnable)?;
+    if !cancel.sleep(tuning.settle) {
+        return Err(ControllerError::Cancelled);
+    }
+    let readings = tuning.readings.max(1);
+    let mut frequencies = Vec::with_capacity(readings);
+    let (mut reflected, mut s11) = (0.0, 0.0);
+    for i in 0..readings {
+        if i > 0 && !cancel.sleep(tuning.interval) {
+            return Err(ControllerError::Cancelled);
+        }
+        let frequency_mhz: f32 = parse_value(&controller.send(&Command::GetFrequency)?)?;
+        let point = measure_point(controller, frequency_mhz)?;
+        frequencies.push(frequency_mhz);
+        reflected += point.reflected_dbm;
+        s11 += point.s11_db();
+    }
+    controller.send(&Command::RfDisable)?;
+    controller.send(&Command::DllDisable)?;
+
+    let (low, high) = frequencies
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(low, high), &f| {
+            (low.min(f), high.max(f))
+        });
+    Ok(DllTrial {
+        parameters,
+        locked: high - low <= parameters[3] + 1e-3,
+        frequency_mhz: frequencies[readings - 1],
+        reflected_dbm: reflected / readings as f32,
+        s11_db: s11 / readings as f32,
+    })
+}
+
+/// Locked trial with the lowest S11; on a tie the one tried first, i.e. the
+/// finest step and lowest threshold.
+pub fn recommend(trials: &[DllTrial]) -> Option<&DllTrial> {
+    trials
+        .iter()
+        .filter(|trial| trial.locked)
+        .min_by(|a, b| a.s11_db.total_cmp(&b.s11_db))
+}
+
+impl ToJson for DllPreset {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("name", self.name.as_str())
+            .with("description", self.description.as_str())
+            .with(
+                "parameters",
+                JsonValue::Array(self.parameters.iter().map(|&p| p.into()).collect()),
+            )
+    }
+}
+
+impl FromJson for DllPreset {
+    fn from_json(json: &JsonValue) -> Result<DllPreset, String> {
+        let name = json.string("name")?;
+        let values = json
+            .array("parameters")?
+            .iter()
+            .map(|value| value.as_f64().map(|value| value as f32))
+            .collect::<Option<Vec<f32>>>();
+        let parameters = values
+            .and_then(|values| <[f32; 6]>::try_from(values).ok())
+            .ok_or_else(|| format!("DLL preset {} needs six numeric parameters", name))?;
+        Ok(DllPreset {
+            name: name.to_string(),
+            description: match json.get("description") {
+                None | Some(JsonValue::Null) => String::new(),
+                Some(_) => json.string("description")?.to_string(),
+            },
+            parameters,
+        })
+    }
+}
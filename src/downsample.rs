@@ -0,0 +1,355 @@
+//! Thinning of long telemetry logs: every sample is kept for an initial
+//! window, after which samples are summarised into fixed-width aggregates
+//! with their minimum, mean and maximum. A week at 1 Hz shrinks to a few
+//! thousand rows, while the maxima keep any spike visible.
+//!
+//! Samples around a fault are still kept in full: the two samples either
+//! side of an RF state change, and every sample with reflected power above
+//! the configured limit.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+use crate::units::{format_timestamp, parse_timestamp};
+
+pub const AGGREGATE_CSV_HEADER: &str = "start,end,samples,\
+frequency_min,frequency_mean,frequency_max,\
+forward_min,forward_mean,forward_max,\
+reflected_min,reflected_mean,reflected_max,\
+temperature_min,temperature_mean,temperature_max,\
+delivered_w_min,delivered_w_mean,delivered_w_max,rf_on_fraction,\
+supply_voltage_min,supply_voltage_mean,supply_voltage_max,\
+supply_current_min,supply_current_mean,supply_current_max,\
+efficiency_min,efficiency_mean,efficiency_max";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Downsampling {
+    /// Every sample is kept for this long after the first one.
+    pub raw_for: Duration,
+    /// Width of each aggregate after that.
+    pub bucket: Duration,
+    /// Samples reflecting more than this are kept as well.
+    pub reflected_limit_dbm: Option<f32>,
+}
+
+impl Downsampling {
+    pub fn new(raw_for: Duration, bucket: Duration) -> Downsampling {
+        Downsampling {
+            raw_for,
+            bucket,
+            reflected_limit_dbm: None,
+        }
+    }
+
+    pub fn with_reflected_limit(mut self, dbm: f32) -> Downsampling {
+        self.reflected_limit_dbm = Some(dbm);
+        self
+    }
+}
+
+/// Minimum, mean and maximum of one quantity over an aggregate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub min: f64,
+    pub mean: f64,
+    pub max: f64,
+}
+
+/// Summary of the samples read during one bucket.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TelemetryAggregate {
+    pub start: SystemTime,
+    pub end: SystemTime,
+    pub samples: usize,
+    pub frequency_mhz: Stats,
+    pub forward_dbm: Stats,
+    pub reflected_dbm: Stats,
+    /// `None` when no sample in the bucket had a temperature.
+    pub temperature_c: Option<Stats>,
+    pub delivered_watts: Stats,
+    /// Share of the samples taken with RF on.
+    pub rf_on_fraction: f64,
+    /// `None` when no sample in the bucket had a supply reading.
+    pub supply_voltage_v: Option<Stats>,
+    pub supply_current_a: Option<Stats>,
+    /// PA efficiency, %, over the samples with RF on and supply readings.
+    pub efficiency_percent: Option<Stats>,
+}
+
+impl TelemetryAggregate {
+    /// Parses a row written by [`TelemetryAggregate::to_csv_row`], or by
+    /// versions without the supply or efficiency columns.
+    pub fn from_csv_row(row: &str) -> Result<TelemetryAggregate, String> {
+        let invalid = || format!("Invalid aggregate row: {}", row);
+        let fields: Vec<&str> = row.trim().split(',').collect();
+        if !matches!(fields.len(), 19 | 25 | 28) {
+            return Err(invalid());
+        }
+        let number = |index: usize| fields[index].parse::<f64>().map_err(|_| invalid());
+        let stats = |first: usize| -> Result<Stats, String> {
+            Ok(Stats {
+                min: number(first)?,
+                mean: number(first + 1)?,
+                max: number(first + 2)?,
+            })
+        };
+        let optional = |first: usize| match fields.get(first) {
+            None | Some(&"") => Ok(None),
+            Some(_) => stats(first).map(Some),
+        };
+
+        Ok(TelemetryAggregate {
+            start: parse_timestamp(fields[0])?,
+            end: parse_timestamp(fields[1])?,
+            samples: fields[2].parse().map_err(|_| invalid())?,
+            frequency_mhz: stats(3)?,
+            forward_dbm: stats(6)?,
+            reflected_dbm: stats(9)?,
+            temperature_c: optional(12)?,
+            delivered_watts: stats(15)?,
+            rf_on_fraction: number(18)?,
+            supply_voltage_v: optional(19)?,
+            supply_current_a: optional(22)?,
+            efficiency_percent: optional(25)?,
+        })
+    }
+
+    /// CSV row matching [`AGGREGATE_CSV_HEADER`].
+    pub fn to_csv_row(&self) -> String {
+        let stats = |stats: &Stats, precision: usize| {
+            format!(
+                "{:.p$},{:.p$},{:.p$}",
+                stats.min,
+                stats.mean,
+                stats.max,
+                p = precision
+            )
+        };
+        let optional = |value: &Option<Stats>, precision: usize| {
+            value
+                .map(|value| stats(&value, precision))
+                .unwrap_or_else(|| ",,".to_string())
+        };
+        format!(
+            "{},{},{},{},{},{},{},{},{:.3},{},{},{}",
+            format_timestamp(self.start),
+            format_timestamp(self.end),
+            self.samples,
+            stats(&self.frequency_mhz, 2),
+            stats(&self.forward_dbm, 2),
+            stats(&self.reflected_dbm, 2),
+            optional(&self.temperature_c, 1),
+            stats(&self.delivered_watts, 2),
+            self.rf_on_fraction,
+            optional(&self.supply_voltage_v, 2),
+            optional(&self.supply_current_a, 2),
+            optional(&self.efficiency_percent, 1)
+        )
+    }
+}
+
+/// What a [`Downsampler`] keeps of the samples fed to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Downsampled {
+    Raw(TelemetrySample),
+    Aggregate(Box<TelemetryAggregate>),
+}
+
+/// Called with everything a downsampling listener keeps.
+pub type DownsampledListener = Arc<dyn Fn(&Downsampled) + Send + Sync>;
+
+/// Applies a [`Downsampling`] policy to a stream of samples. Clones share
+/// their state, so a clone subscribed to a poller can be finished by the
+/// code that stops it.
+#[derive(Clone)]
+pub struct Downsampler {
+    state: Arc<Mutex<DownsamplerState>>,
+}
+
+struct DownsamplerState {
+    policy: Downsampling,
+    first: Option<SystemTime>,
+    bucket_start: Option<SystemTime>,
+    bucket: Accumulator,
+    previous: Option<TelemetrySample>,
+    previous_kept: bool,
+}
+
+impl Downsampler {
+    pub fn new(policy: Downsampling) -> Downsampler {
+        Downsampler {
+            state: Arc::new(Mutex::new(DownsamplerState {
+                policy,
+                first: None,
+                bucket_start: None,
+                bucket: Accumulator::default(),
+                previous: None,
+                previous_kept: false,
+            })),
+        }
+    }
+
+    /// Feeds one sample and returns what to store.
+    pub fn push(&self, sample: &TelemetrySample) -> Vec<Downsampled> {
+        match self.state.lock() {
+            Ok(mut state) => state.push(sample),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    /// Aggregate of the samples since the last complete bucket, e.g. when a
+    /// run ends.
+    pub fn finish(&self) -> Option<TelemetryAggregate> {
+        self.state.lock().ok()?.finish()
+    }
+
+    /// Telemetry listener passing what is kept to `sink`.
+    pub fn listener(&self, sink: DownsampledListener) -> TelemetryListener {
+        let downsampler = self.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            for kept in &downsampler.push(sample) {
+                sink(kept);
+            }
+        })
+    }
+}
+
+impl DownsamplerState {
+    fn push(&mut self, sample: &TelemetrySample) -> Vec<Downsampled> {
+        let mut kept = Vec::new();
+        let first = *self.first.get_or_insert(sample.timestamp);
+        let in_raw_window = sample
+            .timestamp
+            .duration_since(first)
+            .is_ok_and(|age| age < self.policy.raw_for);
+
+        if !in_raw_window {
+            let bucket_start = *self.bucket_start.get_or_insert(sample.timestamp);
+            let bucket_end = bucket_start + self.policy.bucket;
+            if sample.timestamp >= bucket_end {
+                kept.extend(self.bucket.finish(bucket_start, bucket_end));
+                // Skip buckets without samples, e.g. while the link was down.
+                let mut next = bucket_end;
+                while sample.timestamp >= next + self.policy.bucket {
+                    next += self.policy.bucket;
+                }
+                self.bucket_start = Some(next);
+            }
+            self.bucket.add(sample);
+        }
+
+        let rf_changed = self
+            .previous
+            .is_some_and(|previous| previous.rf_enabled != sample.rf_enabled);
+        if rf_changed && !self.previous_kept {
+            if let Some(previous) = self.previous {
+                kept.push(Downsampled::Raw(previous));
+            }
+        }
+        let reflecting = self
+            .policy
+            .reflected_limit_dbm
+            .is_some_and(|limit| sample.rf_enabled && sample.reflected_dbm > limit);
+        self.previous_kept = in_raw_window || rf_changed || reflecting;
+        if self.previous_kept {
+            kept.push(Downsampled::Raw(*sample));
+        }
+        self.previous = Some(*sample);
+        kept
+    }
+
+    fn finish(&mut self) -> Option<TelemetryAggregate> {
+        let start = self.bucket_start.take()?;
+        let end = self.previous.map(|sample| sample.timestamp)?;
+        match self.bucket.finish(start, end) {
+            Some(Downsampled::Aggregate(aggregate)) => Some(*aggregate),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Accumulator {
+    samples: usize,
+    rf_on: usize,
+    frequency: Running,
+    forward: Running,
+    reflected: Running,
+    temperature: Running,
+    delivered: Running,
+    supply_voltage: Running,
+    supply_current: Running,
+    efficiency: Running,
+}
+
+impl Accumulator {
+    fn add(&mut self, sample: &TelemetrySample) {
+        self.samples += 1;
+        self.rf_on += usize::from(sample.rf_enabled);
+        self.frequency.add(sample.frequency_mhz as f64);
+        self.forward.add(sample.forward_dbm as f64);
+        self.reflected.add(sample.reflected_dbm as f64);
+        if let Some(temperature) = sample.temperature_c {
+            self.temperature.add(temperature as f64);
+        }
+        self.delivered.add(sample.delivered_watts());
+        if let Some(voltage) = sample.supply_voltage_v {
+            self.supply_voltage.add(voltage as f64);
+        }
+        if let Some(current) = sample.supply_current_a {
+            self.supply_current.add(current as f64);
+        }
+        if let Some(efficiency) = sample.efficiency_percent() {
+            self.efficiency.add(efficiency);
+        }
+    }
+
+    fn finish(&mut self, start: SystemTime, end: SystemTime) -> Option<Downsampled> {
+        let done = std::mem::take(self);
+        Some(Downsampled::Aggregate(Box::new(TelemetryAggregate {
+            start,
+            end,
+            samples: done.samples,
+            frequency_mhz: done.frequency.stats()?,
+            forward_dbm: done.forward.stats()?,
+            reflected_dbm: done.reflected.stats()?,
+            temperature_c: done.temperature.stats(),
+            delivered_watts: done.delivered.stats()?,
+            rf_on_fraction: done.rf_on as f64 / done.samples as f64,
+            supply_voltage_v: done.supply_voltage.stats(),
+            supply_current_a: done.supply_current.stats(),
+            efficiency_percent: done.efficiency.stats(),
+        })))
+    }
+}
+
+#[derive(Default)]
+struct Running {
+    count: usize,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Running {
+    fn add(&mut self, value: f64) {
+        if self.count == 0 {
+            (self.min, self.max) = (value, value);
+        }
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn stats(&self) -> Option<Stats> {
+        (self.count > 0).then(|| Stats {
+            min: self.min,
+            mean: self.sum / self.count as f64,
+            max: self.max,
+        })
+    }
+}
@@ -0,0 +1,799 @@
+//! EPICS Channel Access server publishing the generator as process variables.
+//!
+//! Implements the server side of CA protocol version 4.13: UDP name
+//! resolution and TCP virtual circuits with get, put and monitor requests,
+//! for the plain, STS, TIME, GR and CTRL DBR types clients commonly ask for.
+//! pvAccess is not implemented; pvAccess clients can reach CA servers
+//! through their `ca` provider.
+//!
+//! PV names are prefixed, e.g. with the default `MW:` prefix:
+//!
+//! | PV                | Type   | Value                            | Access |
+//! |-------------------|--------|----------------------------------|--------|
+//! | `MW:FREQ`         | DOUBLE | Frequency setpoint, MHz          | R/W    |
+//! | `MW:PWR_SP`       | DOUBLE | Power setpoint, dBm              | R/W    |
+//! | `MW:RF_EN`        | LONG   | RF enable, 0 or 1                | R/W    |
+//! | `MW:FWD_PWR`      | DOUBLE | Forward power, dBm               | R      |
+//! | `MW:REFL_PWR`     | DOUBLE | Reflected power, dBm             | R      |
+//! | `MW:TEMP`         | DOUBLE | PA temperature, °C               | R      |
+//! | `MW:STATUS`       | LONG   | Status word (MAJOR alarm if set) | R      |
+//! | `MW:STATUS_TEXT`  | STRING | Decoded status word              | R      |
+//! | `MW:INTERLOCK`    | LONG   | 1 while an interlock is open     | R      |
+//! | `MW:CONTROL`      | LONG   | 1 while this client has control  | R/W    |
+//!
+//! Reads query the board; monitored PVs are rescanned every scan period
+//! and an update is posted when the value or alarm changes. Viewers (see
+//! [`Role`]) are granted read access only. Under single-writer arbitration
+//! puts fail while another client holds control; putting 1 to `CONTROL`
+//! takes it over, 0 releases it, and closing the circuit releases it too.
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs, UdpSocket},
+    sync::Arc,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::access::{AccessPolicy, Role};
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::{parse_status, parse_value, parse_values};
+use crate::error::ControllerError;
+
+/// Default CA server port (`EPICS_CA_SERVER_PORT`).
+pub const CA_SERVER_PORT: u16 = 5064;
+const CA_MINOR_VERSION: u16 = 13;
+const HEADER_LEN: usize = 16;
+const MAX_PAYLOAD: usize = 0x4000;
+
+const CA_PROTO_VERSION: u16 = 0;
+const CA_PROTO_EVENT_ADD: u16 = 1;
+const CA_PROTO_EVENT_CANCEL: u16 = 2;
+const CA_PROTO_WRITE: u16 = 4;
+const CA_PROTO_SEARCH: u16 = 6;
+const CA_PROTO_CLEAR_CHANNEL: u16 = 12;
+const CA_PROTO_NOT_FOUND: u16 = 14;
+const CA_PROTO_READ_NOTIFY: u16 = 15;
+const CA_PROTO_CREATE_CHAN: u16 = 18;
+const CA_PROTO_WRITE_NOTIFY: u16 = 19;
+const CA_PROTO_ACCESS_RIGHTS: u16 = 22;
+const CA_PROTO_ECHO: u16 = 23;
+const CA_PROTO_CREATE_CH_FAIL: u16 = 26;
+
+/// Search flag asking the server to answer even when it lacks the PV.
+const DO_REPLY: u16 = 10;
+
+const ECA_NORMAL: u32 = 1;
+const ECA_BADTYPE: u32 = 114;
+const ECA_GETFAIL: u32 = 152;
+const ECA_PUTFAIL: u32 = 160;
+const ECA_BADCOUNT: u32 = 176;
+const ECA_NOWTACCESS: u32 = 376;
+
+const DBF_STRING: u16 = 0;
+const DBF_SHORT: u16 = 1;
+const DBF_FLOAT: u16 = 2;
+const DBF_ENUM: u16 = 3;
+const DBF_CHAR: u16 = 4;
+const DBF_LONG: u16 = 5;
+const DBF_DOUBLE: u16 = 6;
+const MAX_STRING_SIZE: usize = 40;
+
+const NO_ALARM: (i16, i16) = (0, 0);
+/// STATE_ALARM condition with MAJOR severity.
+const MAJOR_STATE_ALARM: (i16, i16) = (7, 2);
+
+/// Seconds between the Unix epoch and the EPICS epoch (1990-01-01).
+const EPICS_EPOCH_OFFSET: u64 = 631_152_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pv {
+    Frequency,
+    PowerSetpoint,
+    RfEnable,
+    ForwardPower,
+    ReflectedPower,
+    Temperature,
+    Status,
+    StatusText,
+    Interlock,
+    Control,
+}
+
+impl Pv {
+    const ALL: [Pv; 10] = [
+        Pv::Frequency,
+        Pv::PowerSetpoint,
+        Pv::RfEnable,
+        Pv::ForwardPower,
+        Pv::ReflectedPower,
+        Pv::Temperature,
+        Pv::Status,
+        Pv::StatusText,
+        Pv::Interlock,
+        Pv::Control,
+    ];
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Pv::Frequency => "FREQ",
+            Pv::PowerSetpoint => "PWR_SP",
+            Pv::RfEnable => "RF_EN",
+            Pv::ForwardPower => "FWD_PWR",
+            Pv::ReflectedPower => "REFL_PWR",
+            Pv::Temperature => "TEMP",
+            Pv::Status => "STATUS",
+            Pv::StatusText => "STATUS_TEXT",
+            Pv::Interlock => "INTERLOCK",
+            Pv::Control => "CONTROL",
+        }
+    }
+
+    fn native_type(&self) -> u16 {
+        match self {
+            Pv::RfEnable | Pv::Status | Pv::Interlock | Pv::Control => DBF_LONG,
+            Pv::StatusText => DBF_STRING,
+            _ => DBF_DOUBLE,
+        }
+    }
+
+    fn units(&self) -> &'static str {
+        match self {
+            Pv::Frequency => "MHz",
+            Pv::PowerSetpoint | Pv::ForwardPower | Pv::ReflectedPower => "dBm",
+            Pv::Temperature => "C",
+            _ => "",
+        }
+    }
+
+    fn writable(&self) -> bool {
+        matches!(
+            self,
+            Pv::Frequency | Pv::PowerSetpoint | Pv::RfEnable | Pv::Control
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+impl Value {
+    fn as_f64(&self) -> f64 {
+        match self {
+            Value::Number(value) => *value,
+            Value::Text(text) => text.trim().parse().unwrap_or(0.0),
+        }
+    }
+}
+
+/// A reading with its alarm status and severity.
+#[derive(Debug, Clone, PartialEq)]
+struct Reading {
+    value: Value,
+    alarm: (i16, i16),
+}
+
+/// Channel Access front end for a [`Controller`].
+#[derive(Clone)]
+pub struct EpicsServer {
+    controller: Controller,
+    prefix: String,
+    scan_period: Duration,
+    access: Arc<AccessPolicy>,
+}
+
+impl EpicsServer {
+    /// Creates a server publishing PVs named `<prefix><suffix>`, e.g. `MW:FREQ`.
+    pub fn new(controller: Controller, prefix: &str) -> EpicsServer {
+        EpicsServer {
+            controller,
+            prefix: prefix.to_string(),
+            scan_period: Duration::from_secs(1),
+            access: Arc::new(AccessPolicy::default()),
+        }
+    }
+
+    pub fn with_access_policy(mut self, access: AccessPolicy) -> EpicsServer {
+        self.access = Arc::new(access);
+        self
+    }
+
+    /// How often monitored PVs are re-read from the board.
+    pub fn with_scan_period(mut self, scan_period: Duration) -> EpicsServer {
+        self.scan_period = scan_period;
+        self
+    }
+
+    /// Answers searches on UDP and serves virtual circuits on TCP, both on
+    /// `addr` (port [`CA_SERVER_PORT`] by convention), until `cancel` is
+    /// cancelled.
+    pub fn serve<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("EPICS CA: {:?}", e));
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(io_error)?
+            .next()
+            .ok_or_else(|| ControllerError::Io("EPICS CA: No address to listen on".to_string()))?;
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        let tcp_port = listener.local_addr().map_err(io_error)?.port();
+        let udp = UdpSocket::bind(SocketAddr::new(addr.ip(), tcp_port)).map_err(io_error)?;
+        udp.set_read_timeout(Some(Duration::from_millis(100)))
+            .map_err(io_error)?;
+        println!(
+            "EPICS CA server on port {} serving {}*",
+            tcp_port, self.prefix
+        );
+
+        let search = {
+            let server = self.clone();
+            let cancel = cancel.clone();
+            thread::spawn(move || server.answer_searches(udp, tcp_port, &cancel))
+        };
+
+        while !cancel.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let role = self.access.role_for(peer.ip());
+                    println!("EPICS CA client connected: {} ({})", peer, role);
+                    let mut server = self.clone();
+                    server.controller = self.controller.with_actor(&format!("epics {}", peer));
+                    let cancel = cancel.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = server.serve_client(stream, role, &cancel) {
+                            eprintln!("EPICS CA client {}: {:?}", peer, e);
+                        }
+                        let _ = server.controller.release_control();
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cancel.sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        search
+            .join()
+            .map_err(|_| ControllerError::Io("EPICS CA search thread panicked".to_string()))?
+            .map_err(io_error)
+    }
+
+    fn pv(&self, name: &str) -> Option<Pv> {
+        let suffix = name.strip_prefix(self.prefix.as_str())?;
+        Pv::ALL.into_iter().find(|pv| pv.suffix() == suffix)
+    }
+
+    fn answer_searches(
+        &self,
+        udp: UdpSocket,
+        tcp_port: u16,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        let mut datagram = [0; 1500];
+        while !cancel.is_cancelled() {
+            let (len, peer) = match udp.recv_from(&mut datagram) {
+                Ok(received) => received,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            };
+            let mut reply = Vec::new();
+            for (header, payload) in messages(&datagram[..len]) {
+                if header.command != CA_PROTO_SEARCH {
+                    continue;
+                }
+                if self.pv(&c_string(payload)).is_some() {
+                    if reply.is_empty() {
+                        reply.extend(encode(CA_PROTO_VERSION, 0, CA_MINOR_VERSION, 0, 0, &[]));
+                    }
+                    // An address of all ones tells the client to use the reply's source address.
+                    reply.extend(encode(
+                        CA_PROTO_SEARCH,
+                        tcp_port,
+                        0,
+                        u32::MAX,
+                        header.param1,
+                        &CA_MINOR_VERSION.to_be_bytes(),
+                    ));
+                } else if header.data_type == DO_REPLY && !peer.ip().is_unspecified() {
+                    reply.extend(encode(
+                        CA_PROTO_NOT_FOUND,
+                        DO_REPLY,
+                        header.data_count,
+                        header.param1,
+                        header.param1,
+                        &[],
+                    ));
+                }
+            }
+            if !reply.is_empty() {
+                udp.send_to(&reply, peer)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_client(
+        &self,
+        mut stream: TcpStream,
+        role: Role,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let mut circuit = Circuit {
+            server: self,
+            role,
+            channels: Vec::new(),
+            monitors: Vec::new(),
+            next_sid: 1,
+        };
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 1024];
+        let mut last_scan = Instant::now();
+        while !cancel.is_cancelled() {
+            match stream.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(count) => buffer.extend_from_slice(&chunk[..count]),
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(e),
+            }
+            while let Some(header) = Header::parse(&buffer) {
+                let end = HEADER_LEN + header.payload_size;
+                if header.payload_size > MAX_PAYLOAD {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "CA message too large",
+                    ));
+                }
+                if buffer.len() < end {
+                    break;
+                }
+                let payload: Vec<u8> = buffer.drain(..end).skip(HEADER_LEN).collect();
+                let reply = circuit.handle(&header, &payload);
+                stream.write_all(&reply)?;
+            }
+            if last_scan.elapsed() >= self.scan_period {
+                last_scan = Instant::now();
+                let updates = circuit.scan();
+                stream.write_all(&updates)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, pv: Pv) -> Result<Reading, ControllerError> {
+        let controller = &self.controller;
+        let number = |value: f32| Value::Number(value as f64);
+        let measured = || parse_values(&controller.send_cached(&Command::GetPaPower)?);
+        let status =
+            || parse_status(&controller.send_cached(&Command::GetStatus { verbose: false })?);
+        let mut alarm = NO_ALARM;
+        let value = match pv {
+            Pv::Frequency => number(parse_value(
+                &controller.send_cached(&Command::GetFrequency)?,
+            )?),
+            Pv::PowerSetpoint => number(parse_value(
+                &controller.send_cached(&Command::GetPowerSetpoint)?,
+            )?),
+            Pv::RfEnable => Value::Number(controller.rf_enabled() as u8 as f64),
+            Pv::ForwardPower => number(measured()?.first().copied().unwrap_or_default()),
+            Pv::ReflectedPower => number(measured()?.get(1).copied().unwrap_or_default()),
+            Pv::Temperature => number(parse_value(
+                &controller.send_cached(&Command::GetPaTemperature)?,
+            )?),
+            Pv::Status => {
+                let status = status()?;
+                if !status.is_ok() {
+                    alarm = MAJOR_STATE_ALARM;
+                }
+                Value::Number(status.0 as f64)
+            }
+            Pv::StatusText => Value::Text(status()?.to_string()),
+            Pv::Interlock => {
+                let open = controller.open_interlock()?.is_some();
+                if open {
+                    alarm = MAJOR_STATE_ALARM;
+                }
+                Value::Number(open as u8 as f64)
+            }
+            Pv::Control => {
+                let held = controller.control_owner()?.as_deref() == Some(controller.actor());
+                Value::Number(held as u8 as f64)
+            }
+        };
+        Ok(Reading { value, alarm })
+    }
+
+    fn write(&self, pv: Pv, value: f64, role: Role) -> u32 {
+        let command = match pv {
+            Pv::Frequency => Command::set_frequency(value as f32),
+            Pv::PowerSetpoint => Command::set_power(value as f32),
+            Pv::RfEnable if value != 0.0 => Ok(Command::RfEnable),
+            Pv::RfEnable => Ok(Command::RfDisable),
+            Pv::Control if role < Role::Operator => return ECA_NOWTACCESS,
+            Pv::Control => {
+                let result = if value != 0.0 {
+                    self.controller.take_control()
+                } else {
+                    self.controller.release_control()
+                };
+                return match result {
+                    Ok(()) => ECA_NORMAL,
+                    Err(_) => ECA_PUTFAIL,
+                };
+            }
+            _ => return ECA_NOWTACCESS,
+        };
+        match command.map_err(ControllerError::from).and_then(|command| {
+            self.access.authorize(role, &command)?;
+            self.controller.send(&command)
+        }) {
+            Ok(_) => ECA_NORMAL,
+            Err(ControllerError::NotAuthorized(_)) => ECA_NOWTACCESS,
+            Err(_) => ECA_PUTFAIL,
+        }
+    }
+}
+
+/// Per-connection channels and subscriptions.
+struct Circuit<'a> {
+    server: &'a EpicsServer,
+    role: Role,
+    /// Server id and PV of each open channel.
+    channels: Vec<(u32, Pv)>,
+    monitors: Vec<Monitor>,
+    next_sid: u32,
+}
+
+struct Monitor {
+    sid: u32,
+    subscription: u32,
+    data_type: u16,
+    last: Option<Reading>,
+}
+
+impl Circuit<'_> {
+    fn channel(&self, sid: u32) -> Option<Pv> {
+        self.channels
+            .iter()
+            .find(|(id, _)| *id == sid)
+            .map(|(_, pv)| *pv)
+    }
+
+    fn handle(&mut self, header: &Header, payload: &[u8]) -> Vec<u8> {
+        match header.command {
+            CA_PROTO_VERSION => encode(CA_PROTO_VERSION, 0, CA_MINOR_VERSION, 0, 0, &[]),
+            CA_PROTO_ECHO => encode(CA_PROTO_ECHO, 0, 0, 0, 0, &[]),
+            CA_PROTO_CREATE_CHAN => {
+                let cid = header.param1;
+                let pv = match self.server.pv(&c_string(payload)) {
+                    Some(pv) => pv,
+                    None => return encode(CA_PROTO_CREATE_CH_FAIL, 0, 0, cid, 0, &[]),
+                };
+                let sid = self.next_sid;
+                self.next_sid += 1;
+                self.channels.push((sid, pv));
+                let rights = if pv.writable() && self.role > Role::Viewer {
+                    3
+                } else {
+                    1
+                };
+                let mut reply = encode(CA_PROTO_ACCESS_RIGHTS, 0, 0, cid, rights, &[]);
+                reply.extend(encode(
+                    CA_PROTO_CREATE_CHAN,
+                    pv.native_type(),
+                    1,
+                    cid,
+                    sid,
+                    &[],
+                ));
+                reply
+            }
+            CA_PROTO_CLEAR_CHANNEL => {
+                let sid = header.param1;
+                self.channels.retain(|(id, _)| *id != sid);
+                self.monitors.retain(|monitor| monitor.sid != sid);
+                encode(CA_PROTO_CLEAR_CHANNEL, 0, 0, sid, header.param2, &[])
+            }
+            CA_PROTO_READ_NOTIFY => {
+                let (status, body) = self.read(header.param1, header.data_type, header.data_count);
+                encode(
+                    CA_PROTO_READ_NOTIFY,
+                    header.data_type,
+                    1,
+                    status,
+                    header.param2,
+                    &body,
+                )
+            }
+            CA_PROTO_WRITE | CA_PROTO_WRITE_NOTIFY => {
+                let status = match self.channel(header.param1) {
+                    Some(pv) if pv.writable() => match decode_number(header.data_type, payload) {
+                        Some(value) => self.server.write(pv, value, self.role),
+                        None => ECA_BADTYPE,
+                    },
+                    _ => ECA_NOWTACCESS,
+                };
+                if header.command == CA_PROTO_WRITE {
+                    return Vec::new();
+                }
+                encode(
+                    CA_PROTO_WRITE_NOTIFY,
+                    header.data_type,
+                    header.data_count,
+                    status,
+                    header.param2,
+                    &[],
+                )
+            }
+            CA_PROTO_EVENT_ADD => {
+                self.monitors.push(Monitor {
+                    sid: header.param1,
+                    subscription: header.param2,
+                    data_type: header.data_type,
+                    last: None,
+                });
+                // The first update carries the current value.
+                self.scan_monitor(self.monitors.len() - 1)
+            }
+            CA_PROTO_EVENT_CANCEL => {
+                let subscription = header.param2;
+                self.monitors
+                    .retain(|monitor| monitor.subscription != subscription);
+                encode(
+                    CA_PROTO_EVENT_ADD,
+                    header.data_type,
+                    header.data_count,
+                    header.param1,
+                    subscription,
+                    &[],
+                )
+            }
+            // Client and host names, flow control and read sync need no reply.
+            _ => Vec::new(),
+        }
+    }
+
+    fn read(&self, sid: u32, data_type: u16, count: u16) -> (u32, Vec<u8>) {
+        let pv = match self.channel(sid) {
+            Some(pv) => pv,
+            None => return (ECA_GETFAIL, Vec::new()),
+        };
+        if count > 1 {
+            return (ECA_BADCOUNT, Vec::new());
+        }
+        match self.server.read(pv) {
+            Ok(reading) => match encode_dbr(pv, data_type, &reading) {
+                Some(body) => (ECA_NORMAL, body),
+                None => (ECA_BADTYPE, Vec::new()),
+            },
+            Err(_) => (ECA_GETFAIL, Vec::new()),
+        }
+    }
+
+    /// Posts updates for monitors whose value or alarm changed.
+    fn scan(&mut self) -> Vec<u8> {
+        (0..self.monitors.len())
+            .flat_map(|index| self.scan_monitor(index))
+            .collect()
+    }
+
+    fn scan_monitor(&mut self, index: usize) -> Vec<u8> {
+        let monitor = &self.monitors[index];
+        let pv = match self.channel(monitor.sid) {
+            Some(pv) => pv,
+            None => return Vec::new(),
+        };
+        let reading = match self.server.read(pv) {
+            Ok(reading) => reading,
+            // Keep the last value; the next scan retries.
+            Err(_) => return Vec::new(),
+        };
+        if monitor.last.as_ref() == Some(&reading) {
+            return Vec::new();
+        }
+        let (status, body) = match encode_dbr(pv, monitor.data_type, &reading) {
+            Some(body) => (ECA_NORMAL, body),
+            None => (ECA_BADTYPE, Vec::new()),
+        };
+        let reply = encode(
+            CA_PROTO_EVENT_ADD,
+            monitor.data_type,
+            1,
+            status,
+            monitor.subscription,
+            &body,
+        );
+        self.monitors[index].last = Some(reading);
+        reply
+    }
+}
+
+#[derive(Debug)]
+struct Header {
+    command: u16,
+    payload_size: usize,
+    data_type: u16,
+    data_count: u16,
+    param1: u32,
+    param2: u32,
+}
+
+impl Header {
+    fn parse(bytes: &[u8]) -> Option<Header> {
+        let bytes = bytes.get(..HEADER_LEN)?;
+        let u16_at = |i: usize| u16::from_be_bytes([bytes[i], bytes[i + 1]]);
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        Some(Header {
+            command: u16_at(0),
+            payload_size: u16_at(2) as usize,
+            data_type: u16_at(4),
+            data_count: u16_at(6),
+            param1: u32_at(8),
+            param2: u32_at(12),
+        })
+    }
+}
+
+/// Splits a datagram into complete messages.
+fn messages(mut bytes: &[u8]) -> Vec<(Header, &[u8])> {
+    let mut messages = Vec::new();
+    while let Some(header) = Header::parse(bytes) {
+        let end = HEADER_LEN + header.payload_size;
+        if bytes.len() < end {
+            break;
+        }
+        messages.push((header, &bytes[HEADER_LEN..end]));
+        bytes = &bytes[end..];
+    }
+    messages
+}
+
+/// Encodes a message, padding the payload to a multiple of 8 bytes.
+fn encode(
+    command: u16,
+    data_type: u16,
+    data_count: u16,
+    param1: u32,
+    param2: u32,
+    payload: &[u8],
+) -> Vec<u8> {
+    let padded = payload.len().div_ceil(8) * 8;
+    let mut message = Vec::with_capacity(HEADER_LEN + padded);
+    message.extend_from_slice(&command.to_be_bytes());
+    message.extend_from_slice(&(padded as u16).to_be_bytes());
+    message.extend_from_slice(&data_type.to_be_bytes());
+    message.extend_from_slice(&data_count.to_be_bytes());
+    message.extend_from_slice(&param1.to_be_bytes());
+    message.extend_from_slice(&param2.to_be_bytes());
+    message.extend_from_slice(payload);
+    message.resize(HEADER_LEN + padded, 0);
+    message
+}
+
+fn c_string(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+/// Decodes the first element of a plain DBR value.
+fn decode_number(data_type: u16, payload: &[u8]) -> Option<f64> {
+    let bytes = |len: usize| payload.get(..len);
+    let value = match data_type {
+        DBF_STRING => c_string(bytes(MAX_STRING_SIZE.min(payload.len()))?)
+            .trim()
+            .parse()
+            .ok()?,
+        DBF_SHORT => i16::from_be_bytes(bytes(2)?.try_into().ok()?) as f64,
+        DBF_ENUM => u16::from_be_bytes(bytes(2)?.try_into().ok()?) as f64,
+        DBF_FLOAT => f32::from_be_bytes(bytes(4)?.try_into().ok()?) as f64,
+        DBF_CHAR => *bytes(1)?.first()? as f64,
+        DBF_LONG => i32::from_be_bytes(bytes(4)?.try_into().ok()?) as f64,
+        DBF_DOUBLE => f64::from_be_bytes(bytes(8)?.try_into().ok()?),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Encodes `reading` as DBR type `data_type`, or `None` if it is unsupported.
+///
+/// Types 0-6 are plain values, 7-13 add status and severity, 14-20 add a
+/// timestamp, 21-27 add display metadata and 28-34 add control limits.
+fn encode_dbr(pv: Pv, data_type: u16, reading: &Reading) -> Option<Vec<u8>> {
+    let field = data_type % 7;
+    let class = data_type / 7;
+    let mut body = Vec::new();
+    let (status, severity) = reading.alarm;
+    if class >= 1 {
+        body.extend_from_slice(&status.to_be_bytes());
+        body.extend_from_slice(&severity.to_be_bytes());
+    }
+    match class {
+        0 => {}
+        1 => match field {
+            DBF_CHAR => body.push(0),
+            DBF_DOUBLE => body.extend_from_slice(&[0; 4]),
+            _ => {}
+        },
+        2 => {
+            let since = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default();
+            let seconds = since.as_secs().saturating_sub(EPICS_EPOCH_OFFSET) as u32;
+            body.extend_from_slice(&seconds.to_be_bytes());
+            body.extend_from_slice(&since.subsec_nanos().to_be_bytes());
+            match field {
+                DBF_SHORT | DBF_ENUM => body.extend_from_slice(&[0; 2]),
+                DBF_CHAR => body.extend_from_slice(&[0; 3]),
+                DBF_DOUBLE => body.extend_from_slice(&[0; 4]),
+                _ => {}
+            }
+        }
+        // Display and control metadata are provided for the native types.
+        3 | 4 => {
+            let control = class == 4;
+            let mut units = [0u8; 8];
+            for (slot, byte) in units.iter_mut().zip(pv.units().bytes()) {
+                *slot = byte;
+            }
+            match field {
+                // dbr_gr_string and dbr_ctrl_string are dbr_sts_string.
+                DBF_STRING => {}
+                DBF_LONG => {
+                    body.extend_from_slice(&units);
+                    let limits = if control { 8 } else { 6 };
+                    body.extend(std::iter::repeat_n(0, limits * 4));
+                }
+                DBF_DOUBLE => {
+                    body.extend_from_slice(&2i16.to_be_bytes()); // precision
+                    body.extend_from_slice(&[0; 2]);
+                    body.extend_from_slice(&units);
+                    let limits = if control { 8 } else { 6 };
+                    body.extend(std::iter::repeat_n(0, limits * 8));
+                }
+                _ => return None,
+            }
+        }
+        _ => return None,
+    }
+
+    match (field, &reading.value) {
+        (DBF_STRING, value) => {
+            let text = match value {
+                Value::Text(text) => text.clone(),
+                Value::Number(number) => number.to_string(),
+            };
+            let mut bytes = [0u8; MAX_STRING_SIZE];
+            // Leave room for the terminator.
+            for (slot, byte) in bytes[..MAX_STRING_SIZE - 1].iter_mut().zip(text.bytes()) {
+                *slot = byte;
+            }
+            body.extend_from_slice(&bytes);
+        }
+        (DBF_SHORT, value) => body.extend_from_slice(&(value.as_f64() as i16).to_be_bytes()),
+        (DBF_FLOAT, value) => body.extend_from_slice(&(value.as_f64() as f32).to_be_bytes()),
+        (DBF_ENUM, value) => body.extend_from_slice(&(value.as_f64() as u16).to_be_bytes()),
+        (DBF_CHAR, value) => body.push(value.as_f64() as u8),
+        (DBF_LONG, value) => body.extend_from_slice(&(value.as_f64() as i64 as i32).to_be_bytes()),
+        (DBF_DOUBLE, value) => body.extend_from_slice(&value.as_f64().to_be_bytes()),
+        _ => return None,
+    }
+    Some(body)
+}
@@ -0,0 +1,417 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::alerting::Severity;
+use crate::protocol::{StatusFlags, ValidationError};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControllerError {
+    /// No signal generator board matched the autodetect filter.
+    NoDeviceFound,
+    /// The serial port could not be opened.
+    Connection(String),
+    /// Writing to, flushing or reading from the port failed.
+    Io(String),
+    /// No complete response arrived before the read timeout expired.
+    Timeout,
+    /// The board answered with an error reply.
+    Device(DeviceError),
+    /// The reply could not be parsed.
+    InvalidResponse(String),
+    /// The operation was stopped through its cancellation token.
+    Cancelled,
+    /// RF enable was refused because the named interlock is open.
+    InterlockOpen(String),
+    /// The handle's internal lock was poisoned by a panicking thread.
+    Poisoned,
+    /// A command parameter was rejected before anything was sent.
+    InvalidParameter(String),
+    /// The board's `$IDN` reply did not name the expected device family, so
+    /// set commands are refused.
+    UnexpectedDevice(String),
+    /// The handle is read-only and the command would change the output.
+    ReadOnly(String),
+    /// A network client's role does not allow the command, or another
+    /// client holds single-writer control.
+    NotAuthorized(String),
+    /// The command is not allowed in the board's current
+    /// [`Lifecycle`](crate::lifecycle::Lifecycle) stage.
+    InvalidState(String),
+    /// The serial port is locked by another process.
+    Busy(String),
+}
+
+impl fmt::Display for ControllerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControllerError::NoDeviceFound => write!(f, "No signal generator boards detected."),
+            ControllerError::Connection(e) => write!(f, "Failed to connect: {}", e),
+            ControllerError::Io(e) => write!(f, "{}", e),
+            ControllerError::Timeout => write!(f, "Timeout while waiting for response."),
+            ControllerError::Device(reply) => write!(f, "Device reported an error: {}", reply),
+            ControllerError::InvalidResponse(reply) => write!(f, "Unexpected response: {}", reply),
+            ControllerError::Cancelled => write!(f, "Operation cancelled."),
+            ControllerError::InterlockOpen(name) => {
+                write!(f, "Interlock open, RF enable refused: {}", name)
+            }
+            ControllerError::Poisoned => {
+                write!(f, "Controller lock poisoned by a panicked thread.")
+            }
+            ControllerError::InvalidParameter(e) => write!(f, "Invalid parameter: {}", e),
+            ControllerError::UnexpectedDevice(identity) => {
+                write!(
+                    f,
+                    "Not the expected device, set commands refused: {}",
+                    identity
+                )
+            }
+            ControllerError::ReadOnly(command) => {
+                write!(f, "Read-only connection, command refused: {}", command)
+            }
+            ControllerError::NotAuthorized(e) => write!(f, "Not authorized: {}", e),
+            ControllerError::InvalidState(e) => write!(f, "{}", e),
+            ControllerError::Busy(holder) => write!(f, "Device busy: {}", holder),
+        }
+    }
+}
+
+impl std::error::Error for ControllerError {}
+
+/// An error reply from the board, e.g. `$PWRS,0,ERR` or `$ECS,0,ERR,4,Shutdown`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceError {
+    /// The reply without its terminator.
+    pub reply: String,
+    /// Mnemonic of the rejected command, e.g. `$PWRS`.
+    pub mnemonic: String,
+    /// Numeric code following `ERR`, if the firmware sent one.
+    pub code: Option<u32>,
+    /// Any further text following `ERR` and the code.
+    pub detail: String,
+    /// Status word read after the error, if error recovery is enabled.
+    pub status: Option<StatusFlags>,
+}
+
+/// What an error reply means for retrying the command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceErrorKind {
+    /// No status was read, so the cause is unknown.
+    Unknown,
+    /// The status word is clear: the board rejected the command itself,
+    /// e.g. an out of range setpoint. Retrying cannot help.
+    Rejected,
+    /// Latched warning bits are set. Clearing them may let the command through.
+    Latched,
+    /// The board shut RF down (over temperature, reflection or external
+    /// shutdown). This needs an operator, not an automatic retry.
+    Shutdown,
+}
+
+impl DeviceError {
+    pub fn parse(reply: &str) -> DeviceError {
+        let reply = reply.trim();
+        let mut fields = reply.split(',').map(str::trim);
+        let mnemonic = fields.next().unwrap_or_default().to_string();
+        let mut after_err = fields.skip_while(|field| !field.starts_with("ERR")).skip(1);
+        let mut detail: Vec<&str> = Vec::new();
+        let code = match after_err.next() {
+            Some(field) => match field.parse() {
+                Ok(code) => Some(code),
+                Err(_) => {
+                    detail.push(field);
+                    None
+                }
+            },
+            None => None,
+        };
+        detail.extend(after_err);
+        DeviceError {
+            reply: reply.to_string(),
+            mnemonic,
+            code,
+            detail: detail.join(","),
+            status: None,
+        }
+    }
+
+    /// What went wrong: what the reply itself says, then the faults of the
+    /// status word read after it. Never empty.
+    pub fn faults(&self) -> Vec<DeviceFault> {
+        let mut faults = Vec::new();
+        // `$FCG,ERR`: the board flags the channel field itself.
+        let channel = self.reply.split(',').nth(1).map(str::trim);
+        if channel.is_some_and(|channel| channel.starts_with("ERR")) {
+            faults.push(DeviceFault::InvalidChannel);
+        } else if let Some(fault) = DeviceFault::from_description(&self.detail) {
+            faults.push(fault);
+        }
+        if let Some(status) = self.status {
+            let latched: Vec<DeviceFault> = DeviceFault::of_status(status)
+                .filter(|fault| !faults.contains(fault))
+                .collect();
+            faults.extend(latched);
+        }
+        if faults.is_empty() {
+            faults.push(match self.status {
+                Some(_) => DeviceFault::Rejected,
+                None => DeviceFault::Unknown,
+            });
+        }
+        faults
+    }
+
+    /// The most severe of [`faults`](Self::faults).
+    pub fn fault(&self) -> DeviceFault {
+        self.faults()
+            .into_iter()
+            .max_by_key(DeviceFault::severity)
+            .unwrap_or(DeviceFault::Unknown)
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.fault().severity()
+    }
+
+    pub fn kind(&self) -> DeviceErrorKind {
+        match self.status {
+            None => DeviceErrorKind::Unknown,
+            Some(status) if status.is_shutdown() => DeviceErrorKind::Shutdown,
+            Some(status) if status.is_ok() => DeviceErrorKind::Rejected,
+            Some(_) => DeviceErrorKind::Latched,
+        }
+    }
+}
+
+/// Cause of an error reply or a status bit, so callers can tell a
+/// setpoint the board refused from protection that tripped.
+///
+/// Names are stable across releases and firmware versions; new causes are
+/// added as new variants rather than by changing what an existing one means.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeviceFault {
+    /// The mnemonic or its arguments were not understood.
+    InvalidCommand,
+    /// The channel field does not name a channel of the board.
+    InvalidChannel,
+    /// A parameter is outside the range the board accepts.
+    OutOfRange,
+    /// The board refused the command with its status word clear, without
+    /// saying why.
+    Rejected,
+    HighTemperature,
+    TemperatureShutdown,
+    HighReflection,
+    ReflectionShutdown,
+    ExternalShutdown,
+    ResetDetected,
+    TemperatureReadError,
+    PowerMeasurementFailure,
+    RfEnableFailure,
+    MultiplexerFailure,
+    OutOfMemory,
+    I2cError,
+    SpiError,
+    IqConversionError,
+    SoaMeasurementError,
+    WatchdogTimeout,
+    CalibrationMissing,
+    /// An error reply without a status word or a recognised reason.
+    Unknown,
+}
+
+impl DeviceFault {
+    pub const ALL: [DeviceFault; 22] = [
+        DeviceFault::InvalidCommand,
+        DeviceFault::InvalidChannel,
+        DeviceFault::OutOfRange,
+        DeviceFault::Rejected,
+        DeviceFault::HighTemperature,
+        DeviceFault::TemperatureShutdown,
+        DeviceFault::HighReflection,
+        DeviceFault::ReflectionShutdown,
+        DeviceFault::ExternalShutdown,
+        DeviceFault::ResetDetected,
+        DeviceFault::TemperatureReadError,
+        DeviceFault::PowerMeasurementFailure,
+        DeviceFault::RfEnableFailure,
+        DeviceFault::MultiplexerFailure,
+        DeviceFault::OutOfMemory,
+        DeviceFault::I2cError,
+        DeviceFault::SpiError,
+        DeviceFault::IqConversionError,
+        DeviceFault::SoaMeasurementError,
+        DeviceFault::WatchdogTimeout,
+        DeviceFault::CalibrationMissing,
+        DeviceFault::Unknown,
+    ];
+
+    /// Stable identifier, e.g. `out_of_range`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceFault::InvalidCommand => "invalid_command",
+            DeviceFault::InvalidChannel => "invalid_channel",
+            DeviceFault::OutOfRange => "out_of_range",
+            DeviceFault::Rejected => "rejected",
+            DeviceFault::HighTemperature => "high_temperature",
+            DeviceFault::TemperatureShutdown => "temperature_shutdown",
+            DeviceFault::HighReflection => "high_reflection",
+            DeviceFault::ReflectionShutdown => "reflection_shutdown",
+            DeviceFault::ExternalShutdown => "external_shutdown",
+            DeviceFault::ResetDetected => "reset_detected",
+            DeviceFault::TemperatureReadError => "temperature_read_error",
+            DeviceFault::PowerMeasurementFailure => "power_measurement_failure",
+            DeviceFault::RfEnableFailure => "rf_enable_failure",
+            DeviceFault::MultiplexerFailure => "multiplexer_failure",
+            DeviceFault::OutOfMemory => "out_of_memory",
+            DeviceFault::I2cError => "i2c_error",
+            DeviceFault::SpiError => "spi_error",
+            DeviceFault::IqConversionError => "iq_conversion_error",
+            DeviceFault::SoaMeasurementError => "soa_measurement_error",
+            DeviceFault::WatchdogTimeout => "watchdog_timeout",
+            DeviceFault::CalibrationMissing => "calibration_missing",
+            DeviceFault::Unknown => "unknown",
+        }
+    }
+
+    /// Critical when RF was shut down or hardware failed, a warning when
+    /// the command was refused or a limit is near, info for a reset.
+    pub fn severity(&self) -> Severity {
+        match self {
+            DeviceFault::ResetDetected => Severity::Info,
+            DeviceFault::InvalidCommand
+            | DeviceFault::InvalidChannel
+            | DeviceFault::OutOfRange
+            | DeviceFault::Rejected
+            | DeviceFault::HighTemperature
+            | DeviceFault::HighReflection
+            | DeviceFault::TemperatureReadError
+            | DeviceFault::CalibrationMissing
+            | DeviceFault::Unknown => Severity::Warning,
+            DeviceFault::TemperatureShutdown
+            | DeviceFault::ReflectionShutdown
+            | DeviceFault::ExternalShutdown
+            | DeviceFault::PowerMeasurementFailure
+            | DeviceFault::RfEnableFailure
+            | DeviceFault::MultiplexerFailure
+            | DeviceFault::OutOfMemory
+            | DeviceFault::I2cError
+            | DeviceFault::SpiError
+            | DeviceFault::IqConversionError
+            | DeviceFault::SoaMeasurementError
+            | DeviceFault::WatchdogTimeout => Severity::Critical,
+        }
+    }
+
+    /// The fault a status bit stands for.
+    pub fn from_status_bit(bit: u32) -> Option<DeviceFault> {
+        let fault = match bit {
+            StatusFlags::HIGH_TEMPERATURE => DeviceFault::HighTemperature,
+            StatusFlags::SHUTDOWN_TEMPERATURE => DeviceFault::TemperatureShutdown,
+            StatusFlags::HIGH_REFLECTION => DeviceFault::HighReflection,
+            StatusFlags::SHUTDOWN_REFLECTION => DeviceFault::ReflectionShutdown,
+            StatusFlags::RESET_DETECTED => DeviceFault::ResetDetected,
+            StatusFlags::TEMPERATURE_READ_ERROR => DeviceFault::TemperatureReadError,
+            StatusFlags::POWER_MEASUREMENT_FAILURE => DeviceFault::PowerMeasurementFailure,
+            StatusFlags::RF_ENABLE_FAILURE => DeviceFault::RfEnableFailure,
+            StatusFlags::MULTIPLEXER_FAILURE => DeviceFault::MultiplexerFailure,
+            StatusFlags::EXTERNAL_SHUTDOWN => DeviceFault::ExternalShutdown,
+            StatusFlags::OUT_OF_MEMORY => DeviceFault::OutOfMemory,
+            StatusFlags::I2C_ERROR => DeviceFault::I2cError,
+            StatusFlags::SPI_ERROR => DeviceFault::SpiError,
+            StatusFlags::IQ_CONVERSION_ERROR => DeviceFault::IqConversionError,
+            StatusFlags::SOA_MEASUREMENT_ERROR => DeviceFault::SoaMeasurementError,
+            StatusFlags::WATCHDOG_TIMEOUT => DeviceFault::WatchdogTimeout,
+            StatusFlags::CALIBRATION_MISSING => DeviceFault::CalibrationMissing,
+            _ => return None,
+        };
+        Some(fault)
+    }
+
+    /// Faults of the set bits, lowest bit first. Unknown bits are skipped.
+    pub fn of_status(status: StatusFlags) -> impl Iterator<Item = DeviceFault> {
+        (0..u32::BITS)
+            .map(|bit| 1 << bit)
+            .filter(move |bit| status.contains(*bit))
+            .filter_map(DeviceFault::from_status_bit)
+    }
+
+    /// Reads the text of an error reply or a verbose `$ST,0,1` line, e.g.
+    /// `Outside of range` or `PA temperature shutdown`.
+    pub fn from_description(text: &str) -> Option<DeviceFault> {
+        let text = text.trim().to_ascii_lowercase();
+        if text.is_empty() {
+            return None;
+        }
+        if let Some(bit) = (0..u32::BITS).map(|bit| 1 << bit).find(|bit| {
+            StatusFlags(*bit)
+                .descriptions()
+                .any(|description| description.eq_ignore_ascii_case(&text))
+        }) {
+            return DeviceFault::from_status_bit(bit);
+        }
+        if let Ok(fault) = text.parse() {
+            return Some(fault);
+        }
+        let has = |words: &[&str]| words.iter().all(|word| text.contains(word));
+        let fault = if has(&["range"]) || has(&["limit"]) {
+            DeviceFault::OutOfRange
+        } else if has(&["channel"]) {
+            DeviceFault::InvalidChannel
+        } else if has(&["command"]) || has(&["argument"]) || has(&["syntax"]) {
+            DeviceFault::InvalidCommand
+        } else if has(&["temperature", "shutdown"]) || has(&["temp", "shutdown"]) {
+            DeviceFault::TemperatureShutdown
+        } else if has(&["reflect", "shutdown"]) {
+            DeviceFault::ReflectionShutdown
+        } else if has(&["shutdown"]) {
+            DeviceFault::ExternalShutdown
+        } else if has(&["temperature"]) {
+            DeviceFault::HighTemperature
+        } else if has(&["reflect"]) {
+            DeviceFault::HighReflection
+        } else if has(&["reset"]) {
+            DeviceFault::ResetDetected
+        } else if has(&["watchdog"]) {
+            DeviceFault::WatchdogTimeout
+        } else if has(&["calibration"]) {
+            DeviceFault::CalibrationMissing
+        } else {
+            return None;
+        };
+        Some(fault)
+    }
+}
+
+impl fmt::Display for DeviceFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for DeviceFault {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<DeviceFault, String> {
+        let name = name.trim().to_ascii_lowercase().replace(['-', ' '], "_");
+        DeviceFault::ALL
+            .into_iter()
+            .find(|fault| fault.name() == name)
+            .ok_or_else(|| format!("Unknown device fault: {}", name))
+    }
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.reply)?;
+        if let Some(status) = self.status {
+            write!(f, " (status {})", status)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<ValidationError> for ControllerError {
+    fn from(error: ValidationError) -> ControllerError {
+        ControllerError::InvalidParameter(error.to_string())
+    }
+}
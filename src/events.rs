@@ -0,0 +1,116 @@
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+/// Notable conditions observed by a [`Controller`](crate::Controller).
+#[derive(Debug, Clone, PartialEq)]
+pub enum ControllerEvent {
+    /// The board answered a command with an error reply.
+    DeviceFault(String),
+    /// Latched errors were cleared to retry a command after this error reply.
+    ErrorsCleared(String),
+    /// An interlock opened while RF was enabled.
+    InterlockTripped(String),
+    /// The serial link failed.
+    ConnectionLost(String),
+    /// The named command got no reply before the read timeout.
+    CommandTimeout(String),
+    /// A telemetry alarm fired with a `notify` action.
+    AlarmRaised { name: String, message: String },
+    /// The idle link missed this many consecutive heartbeats.
+    LinkDegraded(u32),
+    /// The board answered again after missed heartbeats.
+    LinkRestored,
+    /// The port handle went stale with this error, e.g. across a USB
+    /// suspend/resume, and was closed and opened again.
+    PortReopened(String),
+    /// A [`Derater`](crate::derating::Derater) changed the power setpoint
+    /// at this PA temperature; a `reduction_db` of zero restores it.
+    PowerDerated {
+        reduction_db: f32,
+        setpoint_dbm: f32,
+        temperature_c: f32,
+    },
+    /// Single-writer control passed from `previous` to `owner` (`None` when
+    /// nobody holds it).
+    ControlChanged {
+        owner: Option<String>,
+        previous: Option<String>,
+    },
+}
+
+impl fmt::Display for ControllerEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControllerEvent::DeviceFault(reply) => write!(f, "Device fault: {}", reply),
+            ControllerEvent::ErrorsCleared(error) => {
+                write!(f, "Cleared latched errors after {}", error)
+            }
+            ControllerEvent::InterlockTripped(name) => write!(f, "Interlock tripped: {}", name),
+            ControllerEvent::ConnectionLost(e) => write!(f, "Connection lost: {}", e),
+            ControllerEvent::CommandTimeout(command) => {
+                write!(f, "No response to {}", command)
+            }
+            ControllerEvent::AlarmRaised { name, message } => {
+                write!(f, "Alarm {}: {}", name, message)
+            }
+            ControllerEvent::LinkDegraded(missed) => write!(
+                f,
+                "Link degraded: {} missed heartbeat{}",
+                missed,
+                if *missed == 1 { "" } else { "s" }
+            ),
+            ControllerEvent::LinkRestored => write!(f, "Link restored"),
+            ControllerEvent::PortReopened(e) => write!(f, "Port reopened after: {}", e),
+            ControllerEvent::PowerDerated {
+                reduction_db,
+                setpoint_dbm,
+                temperature_c,
+            } => match *reduction_db > 0.0 {
+                true => write!(
+                    f,
+                    "Derated to {:.2} dBm (-{} dB) at {:.1} °C",
+                    setpoint_dbm, reduction_db, temperature_c
+                ),
+                false => write!(
+                    f,
+                    "Back to {:.2} dBm at {:.1} °C",
+                    setpoint_dbm, temperature_c
+                ),
+            },
+            ControllerEvent::ControlChanged { owner, previous } => match (owner, previous) {
+                (Some(owner), Some(previous)) => {
+                    write!(f, "Control taken by {} from {}", owner, previous)
+                }
+                (Some(owner), None) => write!(f, "Control taken by {}", owner),
+                (None, Some(previous)) => write!(f, "Control released by {}", previous),
+                (None, None) => write!(f, "Control released"),
+            },
+        }
+    }
+}
+
+/// One command line sent to the board and its outcome, as seen by
+/// [`Controller::observe_exchanges`](crate::Controller::observe_exchanges).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exchange {
+    pub command: String,
+    /// The reply without its terminator, or why none arrived.
+    pub reply: Result<String, String>,
+    pub actor: String,
+    pub started: SystemTime,
+    pub duration: Duration,
+}
+
+/// Callback registered with
+/// [`Controller::observe_exchanges`](crate::Controller::observe_exchanges).
+/// It runs after every exchange, so it has to be cheap.
+pub type ExchangeListener = Arc<dyn Fn(&Exchange) + Send + Sync>;
+
+/// Callback registered with [`Controller::subscribe`](crate::Controller::subscribe).
+///
+/// Listeners run on the thread that observed the event, so they should hand
+/// slow work (network requests, UI updates) off rather than block.
+pub type EventListener = Arc<dyn Fn(&ControllerEvent) + Send + Sync>;
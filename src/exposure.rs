@@ -0,0 +1,94 @@
+//! Timed exposure: RF on at one setpoint for a set time, then off, like a
+//! microwave oven's timer.
+
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::progress::{Progress, ProgressCallback};
+use crate::safety::SafetyGuard;
+
+/// Progress is reported once per this much exposure.
+const TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exposure {
+    pub power_dbm: f32,
+    /// Time with RF on; pauses do not count.
+    pub duration: Duration,
+    /// Frequency set before RF is enabled; the board's current one otherwise.
+    pub frequency_mhz: Option<f32>,
+}
+
+impl Exposure {
+    pub fn new(power_dbm: f32, duration: Duration) -> Exposure {
+        Exposure {
+            power_dbm,
+            duration,
+            frequency_mhz: None,
+        }
+    }
+
+    pub fn with_frequency(mut self, mhz: f32) -> Exposure {
+        self.frequency_mhz = Some(mhz);
+        self
+    }
+
+    /// Sets the output, enables RF for `duration` and disables it again,
+    /// also when cancelled, when a command fails or when the thread panics.
+    /// Returns how long RF was on.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<Duration, ControllerError> {
+        self.run_with_progress(controller, cancel, &mut |_| {})
+    }
+
+    /// Same as [`Exposure::run`], reporting progress every second, with
+    /// `completed` and `total` in seconds.
+    pub fn run_with_progress(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<Duration, ControllerError> {
+        let setup: Vec<Command> = self
+            .frequency_mhz
+            .map(Command::SetFrequency)
+            .into_iter()
+            .chain([Command::SetPower(self.power_dbm)])
+            .collect();
+        for command in &setup {
+            command.validate()?;
+        }
+        for command in &setup {
+            controller.send(command)?;
+        }
+
+        let guard = SafetyGuard::new(controller);
+        controller.send(&Command::RfEnable)?;
+        let started = Instant::now();
+        let total = self.duration.as_secs_f64().ceil() as usize;
+        let mut remaining = self.duration;
+        let mut completed = 0;
+        while !remaining.is_zero() {
+            let slice = remaining.min(TICK);
+            cancel.hold(controller, slice, &[Command::RfEnable])?;
+            remaining -= slice;
+            completed += 1;
+            on_progress(&Progress {
+                completed,
+                total,
+                elapsed: started.elapsed(),
+                frequency_mhz: self.frequency_mhz,
+                power_dbm: Some(self.power_dbm),
+            });
+        }
+        let exposed = started.elapsed();
+        guard.release()?;
+        Ok(exposed)
+    }
+}
@@ -0,0 +1,313 @@
+//! C API for test executives (LabVIEW, C++) that drive the generator through
+//! this crate. The matching header is `include/microwave_controller.h`.
+//!
+//! All functions return `MW_OK` (0) or a negative `MW_ERR_*` code. A handle
+//! may be used from several threads; it serializes commands internally. The
+//! reply and error message it keeps are those of the latest call from any
+//! thread, so threads that read them back need a handle each.
+
+use std::{
+    ffi::{c_char, c_int, CStr, CString},
+    ptr,
+    sync::Mutex,
+};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_values;
+use crate::error::ControllerError;
+use crate::protocol::ValidationError;
+
+pub const MW_OK: c_int = 0;
+pub const MW_ERR_NULL_POINTER: c_int = -1;
+pub const MW_ERR_INVALID_ARGUMENT: c_int = -2;
+pub const MW_ERR_NO_DEVICE: c_int = -3;
+pub const MW_ERR_CONNECTION: c_int = -4;
+pub const MW_ERR_IO: c_int = -5;
+pub const MW_ERR_TIMEOUT: c_int = -6;
+pub const MW_ERR_DEVICE: c_int = -7;
+pub const MW_ERR_INVALID_RESPONSE: c_int = -8;
+pub const MW_ERR_CANCELLED: c_int = -9;
+pub const MW_ERR_INTERLOCK: c_int = -10;
+pub const MW_ERR_INTERNAL: c_int = -11;
+pub const MW_ERR_BUFFER_TOO_SMALL: c_int = -12;
+pub const MW_ERR_UNEXPECTED_DEVICE: c_int = -13;
+pub const MW_ERR_READ_ONLY: c_int = -14;
+pub const MW_ERR_NOT_AUTHORIZED: c_int = -15;
+pub const MW_ERR_INVALID_STATE: c_int = -16;
+pub const MW_ERR_BUSY: c_int = -17;
+
+/// Opaque handle returned by `mw_connect`/`mw_open`.
+pub struct MwHandle {
+    controller: Controller,
+    last_response: Mutex<CString>,
+    last_error: Mutex<CString>,
+}
+
+fn error_code(error: &ControllerError) -> c_int {
+    match error {
+        ControllerError::NoDeviceFound => MW_ERR_NO_DEVICE,
+        ControllerError::Connection(_) => MW_ERR_CONNECTION,
+        ControllerError::Io(_) => MW_ERR_IO,
+        ControllerError::Timeout => MW_ERR_TIMEOUT,
+        ControllerError::Device(_) => MW_ERR_DEVICE,
+        ControllerError::InvalidResponse(_) => MW_ERR_INVALID_RESPONSE,
+        ControllerError::Cancelled => MW_ERR_CANCELLED,
+        ControllerError::InterlockOpen(_) => MW_ERR_INTERLOCK,
+        ControllerError::Poisoned => MW_ERR_INTERNAL,
+        ControllerError::InvalidParameter(_) => MW_ERR_INVALID_ARGUMENT,
+        ControllerError::UnexpectedDevice(_) => MW_ERR_UNEXPECTED_DEVICE,
+        ControllerError::ReadOnly(_) => MW_ERR_READ_ONLY,
+        ControllerError::NotAuthorized(_) => MW_ERR_NOT_AUTHORIZED,
+        ControllerError::InvalidState(_) => MW_ERR_INVALID_STATE,
+        ControllerError::Busy(_) => MW_ERR_BUSY,
+    }
+}
+
+fn to_c_string(text: &str) -> CString {
+    CString::new(text.replace('\0', "")).unwrap_or_default()
+}
+
+impl MwHandle {
+    fn new(controller: Controller) -> *mut MwHandle {
+        Box::into_raw(Box::new(MwHandle {
+            controller,
+            last_response: Mutex::default(),
+            last_error: Mutex::default(),
+        }))
+    }
+
+    fn record<T>(&self, result: Result<T, ControllerError>) -> Result<T, c_int> {
+        result.map_err(|e| {
+            if let Ok(mut last_error) = self.last_error.lock() {
+                *last_error = to_c_string(&e.to_string());
+            }
+            error_code(&e)
+        })
+    }
+
+    /// Sends `command` and keeps its reply for `mw_read_response`. The reply
+    /// is returned too, since another thread may replace the kept one.
+    fn exchange(&self, command: &Command) -> Result<String, c_int> {
+        let response = self.record(self.controller.send(command))?;
+        let mut last_response = self.last_response.lock().map_err(|_| MW_ERR_INTERNAL)?;
+        *last_response = to_c_string(response.trim());
+        Ok(response)
+    }
+
+    fn send(&self, command: &Command) -> c_int {
+        match self.exchange(command) {
+            Ok(_) => MW_OK,
+            Err(code) => code,
+        }
+    }
+
+    fn send_validated(&self, command: Result<Command, ValidationError>) -> c_int {
+        match self.record(command.map_err(ControllerError::from)) {
+            Ok(command) => self.send(&command),
+            Err(code) => code,
+        }
+    }
+}
+
+unsafe fn store_handle(
+    result: Result<Controller, ControllerError>,
+    out: *mut *mut MwHandle,
+) -> c_int {
+    match result {
+        Ok(controller) => {
+            *out = MwHandle::new(controller);
+            MW_OK
+        }
+        Err(e) => {
+            *out = ptr::null_mut();
+            error_code(&e)
+        }
+    }
+}
+
+/// Connects to the first autodetected board and stores the handle in `*out`.
+///
+/// # Safety
+/// `out` must be a valid pointer to writable storage for a handle pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mw_connect(out: *mut *mut MwHandle) -> c_int {
+    if out.is_null() {
+        return MW_ERR_NULL_POINTER;
+    }
+    store_handle(Controller::connect(), out)
+}
+
+/// Opens the named serial port and stores the handle in `*out`.
+///
+/// # Safety
+/// `port_name` must be a NUL terminated string and `out` a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn mw_open(port_name: *const c_char, out: *mut *mut MwHandle) -> c_int {
+    if port_name.is_null() || out.is_null() {
+        return MW_ERR_NULL_POINTER;
+    }
+    let port_name = match CStr::from_ptr(port_name).to_str() {
+        Ok(port_name) => port_name,
+        Err(_) => return MW_ERR_INVALID_ARGUMENT,
+    };
+    store_handle(Controller::open(port_name), out)
+}
+
+/// Sends a command line such as `"$FCS,0,2450"`, with the same checks as
+/// the typed calls: lines that are not a known command are refused with
+/// `MW_ERR_INVALID_ARGUMENT`, and `$ECS,0,1` with an interlock open. The
+/// reply can be fetched with `mw_read_response`.
+///
+/// # Safety
+/// `handle` must come from `mw_connect`/`mw_open` and `command` must be a
+/// NUL terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mw_send(handle: *mut MwHandle, command: *const c_char) -> c_int {
+    let handle = match handle.as_ref() {
+        Some(handle) => handle,
+        None => return MW_ERR_NULL_POINTER,
+    };
+    if command.is_null() {
+        return MW_ERR_NULL_POINTER;
+    }
+    let line = match CStr::from_ptr(command).to_str() {
+        Ok(line) => line,
+        Err(_) => return MW_ERR_INVALID_ARGUMENT,
+    };
+    let command = line
+        .parse::<Command>()
+        .map_err(|_| ControllerError::InvalidParameter(format!("Unknown command: {}", line)));
+    match handle.record(command) {
+        Ok(command) => handle.send(&command),
+        Err(code) => code,
+    }
+}
+
+/// Copies the last reply (without line terminator) into `buffer`, NUL
+/// terminated. Returns `MW_ERR_BUFFER_TOO_SMALL` if it does not fit.
+///
+/// # Safety
+/// `handle` must be valid and `buffer` must point to `length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mw_read_response(
+    handle: *const MwHandle,
+    buffer: *mut c_char,
+    length: usize,
+) -> c_int {
+    match handle.as_ref() {
+        Some(handle) => copy_out(&handle.last_response, buffer, length),
+        None => MW_ERR_NULL_POINTER,
+    }
+}
+
+/// Copies the message of the last failed call into `buffer`, NUL terminated.
+///
+/// # Safety
+/// `handle` must be valid and `buffer` must point to `length` writable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn mw_last_error(
+    handle: *const MwHandle,
+    buffer: *mut c_char,
+    length: usize,
+) -> c_int {
+    match handle.as_ref() {
+        Some(handle) => copy_out(&handle.last_error, buffer, length),
+        None => MW_ERR_NULL_POINTER,
+    }
+}
+
+unsafe fn copy_out(text: &Mutex<CString>, buffer: *mut c_char, length: usize) -> c_int {
+    if buffer.is_null() {
+        return MW_ERR_NULL_POINTER;
+    }
+    let Ok(text) = text.lock() else {
+        return MW_ERR_INTERNAL;
+    };
+    let bytes = text.as_bytes_with_nul();
+    if bytes.len() > length {
+        return MW_ERR_BUFFER_TOO_SMALL;
+    }
+    ptr::copy_nonoverlapping(bytes.as_ptr() as *const c_char, buffer, bytes.len());
+    MW_OK
+}
+
+/// # Safety
+/// `handle` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn mw_set_frequency(handle: *mut MwHandle, frequency_mhz: f32) -> c_int {
+    match handle.as_ref() {
+        Some(handle) => handle.send_validated(Command::set_frequency(frequency_mhz)),
+        None => MW_ERR_NULL_POINTER,
+    }
+}
+
+/// # Safety
+/// `handle` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn mw_set_power(handle: *mut MwHandle, power_dbm: f32) -> c_int {
+    match handle.as_ref() {
+        Some(handle) => handle.send_validated(Command::set_power(power_dbm)),
+        None => MW_ERR_NULL_POINTER,
+    }
+}
+
+/// Enables (`enable != 0`) or disables RF.
+///
+/// # Safety
+/// `handle` must be valid.
+#[no_mangle]
+pub unsafe extern "C" fn mw_rf_enable(handle: *mut MwHandle, enable: c_int) -> c_int {
+    let command = if enable != 0 {
+        Command::RfEnable
+    } else {
+        Command::RfDisable
+    };
+    match handle.as_ref() {
+        Some(handle) => handle.send(&command),
+        None => MW_ERR_NULL_POINTER,
+    }
+}
+
+/// Reads forward and reflected PA power in dBm.
+///
+/// # Safety
+/// `handle` must be valid; `forward_dbm` and `reflected_dbm` must be valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn mw_get_pa_power(
+    handle: *mut MwHandle,
+    forward_dbm: *mut f32,
+    reflected_dbm: *mut f32,
+) -> c_int {
+    let handle = match handle.as_ref() {
+        Some(handle) => handle,
+        None => return MW_ERR_NULL_POINTER,
+    };
+    if forward_dbm.is_null() || reflected_dbm.is_null() {
+        return MW_ERR_NULL_POINTER;
+    }
+    let response = match handle.exchange(&Command::GetPaPower) {
+        Ok(response) => response,
+        Err(code) => return code,
+    };
+    match handle.record(parse_values(&response)) {
+        Ok(values) if values.len() >= 2 => {
+            *forward_dbm = values[0];
+            *reflected_dbm = values[1];
+            MW_OK
+        }
+        Ok(_) => MW_ERR_INVALID_RESPONSE,
+        Err(code) => code,
+    }
+}
+
+/// Releases the handle. RF is left as it is; disable it first if required.
+///
+/// # Safety
+/// `handle` must come from `mw_connect`/`mw_open` and must not be used afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn mw_disconnect(handle: *mut MwHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
@@ -0,0 +1,250 @@
+//! Several daemons driven from one host: discovery by UDP broadcast, and
+//! recipes, commands and telemetry across every unit at once over each
+//! daemon's [`LineBridge`](crate::bridge::LineBridge).
+//!
+//! A daemon started with a unit name answers the probe `mwctl-discover` on
+//! [`DISCOVERY_PORT`] with `mwctl <name> <bridge port>`.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::recipe::Recipe;
+use crate::scheduler::{PollHandle, PollScheduler};
+use crate::telemetry::TelemetrySample;
+
+pub const DISCOVERY_PORT: u16 = 48_400;
+const PROBE: &str = "mwctl-discover";
+
+/// A daemon reachable through its line bridge.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FleetMember {
+    pub name: String,
+    pub addr: SocketAddr,
+}
+
+/// Answers discovery probes as unit `name` until `cancel` is cancelled.
+pub fn serve_discovery(
+    name: &str,
+    bridge_port: u16,
+    cancel: &CancellationToken,
+) -> Result<(), ControllerError> {
+    let io_error = |e: io::Error| ControllerError::Io(format!("Discovery: {:?}", e));
+    let socket = UdpSocket::bind(("0.0.0.0", DISCOVERY_PORT)).map_err(io_error)?;
+    socket
+        .set_read_timeout(Some(Duration::from_millis(200)))
+        .map_err(io_error)?;
+    let answer = format!("mwctl {} {}\n", name, bridge_port);
+    let mut buffer = [0; 64];
+    while !cancel.is_cancelled() {
+        match socket.recv_from(&mut buffer) {
+            Ok((count, peer)) if buffer[..count].trim_ascii() == PROBE.as_bytes() => {
+                socket.send_to(answer.as_bytes(), peer).map_err(io_error)?;
+            }
+            Ok(_) => {}
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut => {
+            }
+            Err(e) => return Err(io_error(e)),
+        }
+    }
+    Ok(())
+}
+
+/// Sends a probe to `target`, e.g. `255.255.255.255:48400` or a subnet's
+/// broadcast address, and collects the answers that arrive within `wait`.
+pub fn discover(target: SocketAddr, wait: Duration) -> io::Result<Vec<FleetMember>> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(PROBE.as_bytes(), target)?;
+    let start = Instant::now();
+    let mut members = Vec::new();
+    let mut buffer = [0; 256];
+    while let Some(remaining) = wait.checked_sub(start.elapsed()) {
+        socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+        let (count, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        };
+        let answer = String::from_utf8_lossy(&buffer[..count]);
+        let fields: Vec<&str> = answer.split_whitespace().collect();
+        if let ["mwctl", name, port] = fields.as_slice() {
+            if let Ok(port) = port.parse() {
+                let member = FleetMember {
+                    name: name.to_string(),
+                    addr: SocketAddr::new(peer.ip(), port),
+                };
+                if !members.contains(&member) {
+                    members.push(member);
+                }
+            }
+        }
+    }
+    members.sort();
+    Ok(members)
+}
+
+/// Connected units, in the order they were given.
+pub struct Fleet {
+    units: Vec<(FleetMember, Controller)>,
+}
+
+impl Fleet {
+    /// Connects to every member; fails if any one cannot be reached.
+    pub fn connect(members: Vec<FleetMember>) -> Result<Fleet, ControllerError> {
+        let mut units = Vec::with_capacity(members.len());
+        for member in members {
+            let controller = Controller::connect_tcp(&member.addr.to_string()).map_err(|e| {
+                ControllerError::Connection(format!("{} ({}): {}", member.name, member.addr, e))
+            })?;
+            units.push((member, controller));
+        }
+        Ok(Fleet { units })
+    }
+
+    pub fn members(&self) -> impl Iterator<Item = &FleetMember> {
+        self.units.iter().map(|(member, _)| member)
+    }
+
+    pub fn units(&self) -> impl Iterator<Item = (&FleetMember, &Controller)> {
+        self.units
+            .iter()
+            .map(|(member, controller)| (member, controller))
+    }
+
+    pub fn controller(&self, name: &str) -> Option<&Controller> {
+        self.units
+            .iter()
+            .find(|(member, _)| member.name == name)
+            .map(|(_, controller)| controller)
+    }
+
+    /// Sends `command` to every unit, one thread per unit.
+    pub fn send(&self, command: &Command) -> Vec<(String, Result<String, ControllerError>)> {
+        self.each(|controller| controller.send(command))
+    }
+
+    /// Runs `recipe` on every unit at once. Cancelling `cancel` stops all of
+    /// them; a failure on one unit does not stop the others.
+    pub fn run_recipe(
+        &self,
+        recipe: &Recipe,
+        cancel: &CancellationToken,
+    ) -> Vec<(String, Result<(), ControllerError>)> {
+        self.each(|controller| recipe.run(controller, cancel))
+    }
+
+    /// RF off on every unit.
+    pub fn safe_state(&self) -> Vec<(String, Result<(), ControllerError>)> {
+        self.each(Controller::safe_state)
+    }
+
+    fn each<T: Send>(
+        &self,
+        operation: impl Fn(&Controller) -> Result<T, ControllerError> + Sync,
+    ) -> Vec<(String, Result<T, ControllerError>)> {
+        thread::scope(|scope| {
+            let handles: Vec<_> = self
+                .units
+                .iter()
+                .map(|(member, controller)| {
+                    let operation = &operation;
+                    (
+                        member.name.clone(),
+                        scope.spawn(move || operation(controller)),
+                    )
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|(name, handle)| {
+                    let result = handle.join().unwrap_or(Err(ControllerError::Poisoned));
+                    (name, result)
+                })
+                .collect()
+        })
+    }
+
+    /// Polls every unit every `interval` into `telemetry` until `cancel` is
+    /// cancelled, sharing `workers` threads between the units.
+    pub fn spawn_telemetry(
+        &self,
+        interval: Duration,
+        workers: usize,
+        telemetry: &FleetTelemetry,
+        cancel: CancellationToken,
+    ) -> PollHandle {
+        let mut scheduler = self.units.iter().fold(
+            PollScheduler::new(workers),
+            |scheduler, (member, controller)| scheduler.add(&member.name, controller, interval),
+        );
+        let telemetry = telemetry.clone();
+        scheduler.subscribe(Arc::new(move |name: &str, sample: &TelemetrySample| {
+            telemetry.record(name, sample)
+        }));
+        scheduler.spawn(cancel)
+    }
+}
+
+/// Latest sample of every unit, shared by the fleet's pollers.
+#[derive(Clone, Default)]
+pub struct FleetTelemetry {
+    latest: Arc<Mutex<BTreeMap<String, TelemetrySample>>>,
+    listeners: Arc<Mutex<Vec<FleetListener>>>,
+}
+
+/// Called with the unit name and its sample after each reading.
+pub type FleetListener = Arc<dyn Fn(&str, &TelemetrySample) + Send + Sync>;
+
+impl FleetTelemetry {
+    pub fn new() -> FleetTelemetry {
+        FleetTelemetry::default()
+    }
+
+    pub fn subscribe(&self, listener: FleetListener) {
+        if let Ok(mut listeners) = self.listeners.lock() {
+            listeners.push(listener);
+        }
+    }
+
+    fn record(&self, name: &str, sample: &TelemetrySample) {
+        if let Ok(mut latest) = self.latest.lock() {
+            latest.insert(name.to_string(), *sample);
+        }
+        let listeners = match self.listeners.lock() {
+            Ok(listeners) => listeners.clone(),
+            Err(_) => return,
+        };
+        for listener in listeners {
+            listener(name, sample);
+        }
+    }
+
+    /// Latest sample of each unit that has reported, by name.
+    pub fn latest(&self) -> BTreeMap<String, TelemetrySample> {
+        self.latest
+            .lock()
+            .map(|latest| latest.clone())
+            .unwrap_or_default()
+    }
+
+    /// Sum of the latest delivered power of every unit, W.
+    pub fn total_delivered_watts(&self) -> f64 {
+        self.latest()
+            .values()
+            .map(TelemetrySample::delivered_watts)
+            .sum()
+    }
+}
@@ -0,0 +1,38 @@
+//! Sans-IO line framing for links that deliver reply bytes in arbitrary chunks.
+
+/// Accumulates received bytes and yields complete `\r\n` terminated reply lines.
+#[derive(Debug, Clone, Default)]
+pub struct LineFramer {
+    buffer: Vec<u8>,
+}
+
+impl LineFramer {
+    pub fn new() -> LineFramer {
+        LineFramer::default()
+    }
+
+    /// Appends `bytes` and returns every line completed by them, without terminators.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<String> {
+        self.buffer.extend_from_slice(bytes);
+        let mut lines = Vec::new();
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+            let line: Vec<u8> = self.buffer.drain(..end + 2).take(end).collect();
+            lines.push(String::from_utf8_lossy(&line).into_owned());
+        }
+        lines
+    }
+
+    /// Bytes received since the last complete line.
+    pub fn pending(&self) -> &[u8] {
+        &self.buffer
+    }
+
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+/// Encodes a command string as it is written to the wire.
+pub fn encode_line(command: &str) -> String {
+    format!("{}\r\n", command)
+}
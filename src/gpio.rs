@@ -0,0 +1,124 @@
+//! Raspberry Pi GPIO integration through the Linux sysfs GPIO interface.
+//!
+//! An input pin (door switch, footswitch) can gate RF enable as an
+//! [`Interlock`], and an output pin can mirror the RF state for an external
+//! warning lamp. Pins are addressed by their sysfs (BCM) number.
+
+use std::{
+    fs, io,
+    path::PathBuf,
+    sync::Arc,
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::error::ControllerError;
+use crate::events::ControllerEvent;
+use crate::interlock::Interlock;
+
+const GPIO_ROOT: &str = "/sys/class/gpio";
+
+fn pin_path(pin: u32) -> PathBuf {
+    PathBuf::from(format!("{}/gpio{}", GPIO_ROOT, pin))
+}
+
+fn export(pin: u32, direction: &str) -> io::Result<()> {
+    if !pin_path(pin).exists() {
+        fs::write(format!("{}/export", GPIO_ROOT), pin.to_string())?;
+        // udev needs a moment to apply permissions to the new pin directory.
+        thread::sleep(Duration::from_millis(100));
+    }
+    fs::write(pin_path(pin).join("direction"), direction)
+}
+
+/// GPIO input used as an interlock.
+pub struct GpioInput {
+    name: String,
+    pin: u32,
+    /// Input reads low when the interlock is closed (switch to ground with pull-up).
+    active_low: bool,
+}
+
+impl GpioInput {
+    pub fn new(name: &str, pin: u32, active_low: bool) -> io::Result<GpioInput> {
+        export(pin, "in")?;
+        Ok(GpioInput {
+            name: name.to_string(),
+            pin,
+            active_low,
+        })
+    }
+
+    /// Reads the pin level and applies the active-low setting.
+    pub fn is_active(&self) -> io::Result<bool> {
+        let value = fs::read_to_string(pin_path(self.pin).join("value"))?;
+        let high = value.trim() == "1";
+        Ok(high != self.active_low)
+    }
+}
+
+impl Interlock for GpioInput {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A read failure counts as open.
+    fn is_closed(&self) -> bool {
+        self.is_active().unwrap_or(false)
+    }
+}
+
+/// GPIO output, e.g. an RF-on warning lamp.
+pub struct GpioOutput {
+    pin: u32,
+}
+
+impl GpioOutput {
+    pub fn new(pin: u32) -> io::Result<GpioOutput> {
+        export(pin, "out")?;
+        Ok(GpioOutput { pin })
+    }
+
+    pub fn set(&self, high: bool) -> io::Result<()> {
+        fs::write(
+            pin_path(self.pin).join("value"),
+            if high { "1" } else { "0" },
+        )
+    }
+}
+
+/// Registers `input` as an interlock and starts a thread that disables RF
+/// as soon as it opens and keeps `lamp` in step with the RF state.
+pub fn spawn_gpio_monitor(
+    controller: &Controller,
+    input: GpioInput,
+    lamp: Option<GpioOutput>,
+    interval: Duration,
+    cancel: CancellationToken,
+) -> Result<JoinHandle<()>, ControllerError> {
+    let input = Arc::new(input);
+    controller.add_interlock(input.clone())?;
+
+    let controller = controller.clone();
+    Ok(thread::spawn(move || {
+        while cancel.sleep(interval) {
+            if controller.rf_enabled() && !input.is_closed() {
+                eprintln!("Interlock {} opened, disabling RF.", input.name());
+                controller.emit(&ControllerEvent::InterlockTripped(input.name().to_string()));
+                if let Err(e) = controller.safe_state() {
+                    eprintln!("Failed to disable RF: {}", e);
+                }
+            }
+            if let Some(lamp) = &lamp {
+                if let Err(e) = lamp.set(controller.rf_enabled()) {
+                    eprintln!("Failed to update RF lamp: {:?}", e);
+                }
+            }
+        }
+        if let Some(lamp) = &lamp {
+            let _ = lamp.set(false);
+        }
+    }))
+}
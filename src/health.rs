@@ -0,0 +1,244 @@
+//! HTTP health endpoints for supervisors such as Kubernetes probes.
+//!
+//! `GET /healthz` (liveness) fails only when restarting the process could
+//! help: the serial link was lost and the board has not answered since.
+//! `GET /readyz` (readiness) also fails while the heartbeat misses replies
+//! or the board is in a fault, so the instance is fenced from new work
+//! without being restarted. Both answer `200` or `503` with a JSON body
+//! listing each check. Neither touches the bus; they report what the
+//! controller and its heartbeat last observed.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::error::ControllerError;
+use crate::events::ControllerEvent;
+use crate::json::{JsonValue, ToJson};
+use crate::lifecycle::Lifecycle;
+
+/// Link failures seen through controller events, each cleared by the next
+/// reply from the board.
+#[derive(Default)]
+struct LinkEvents {
+    lost: Option<(Instant, String)>,
+    degraded: Option<(Instant, u32)>,
+}
+
+#[derive(Clone)]
+pub struct HealthServer {
+    controller: Controller,
+    link: Arc<Mutex<LinkEvents>>,
+}
+
+/// Outcome of one check.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthCheck {
+    pub name: &'static str,
+    pub ok: bool,
+    pub detail: String,
+}
+
+/// Checks behind one endpoint; healthy when all of them pass.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthReport {
+    pub checks: Vec<HealthCheck>,
+}
+
+impl HealthReport {
+    pub fn healthy(&self) -> bool {
+        self.checks.iter().all(|check| check.ok)
+    }
+}
+
+impl ToJson for HealthReport {
+    fn to_json(&self) -> JsonValue {
+        let checks = self
+            .checks
+            .iter()
+            .fold(JsonValue::object(), |checks, check| {
+                checks.with(
+                    check.name,
+                    JsonValue::object()
+                        .with("ok", check.ok)
+                        .with("detail", check.detail.as_str()),
+                )
+            });
+        JsonValue::object()
+            .with("status", if self.healthy() { "ok" } else { "fail" })
+            .with("checks", checks)
+    }
+}
+
+impl HealthServer {
+    pub fn new(controller: &Controller) -> Result<HealthServer, ControllerError> {
+        let link = Arc::new(Mutex::new(LinkEvents::default()));
+        let events = link.clone();
+        controller.subscribe(Arc::new(move |event: &ControllerEvent| {
+            let Ok(mut events) = events.lock() else {
+                return;
+            };
+            match event {
+                ControllerEvent::ConnectionLost(reason) => {
+                    events.lost = Some((Instant::now(), reason.clone()))
+                }
+                ControllerEvent::LinkDegraded(missed) => {
+                    events.degraded = Some((Instant::now(), *missed))
+                }
+                ControllerEvent::LinkRestored => *events = LinkEvents::default(),
+                _ => {}
+            }
+        }))?;
+        Ok(HealthServer {
+            controller: controller.clone(),
+            link,
+        })
+    }
+
+    /// `/healthz`: the link has not been lost for good.
+    pub fn liveness(&self) -> HealthReport {
+        HealthReport {
+            checks: vec![self.link_check()],
+        }
+    }
+
+    /// `/readyz`: the link is up, the heartbeat is answered and the board is
+    /// not in a fault.
+    pub fn readiness(&self) -> HealthReport {
+        HealthReport {
+            checks: vec![
+                self.link_check(),
+                self.heartbeat_check(),
+                self.lifecycle_check(),
+            ],
+        }
+    }
+
+    fn link_check(&self) -> HealthCheck {
+        let reply_age = self.controller.last_reply_age();
+        let lost = self.link.lock().ok().and_then(|link| link.lost.clone());
+        match lost {
+            // Lost and not answered since.
+            Some((at, reason)) if reply_age >= at.elapsed() => HealthCheck {
+                name: "link",
+                ok: false,
+                detail: format!("Connection lost {:.0?} ago: {}", at.elapsed(), reason),
+            },
+            _ => HealthCheck {
+                name: "link",
+                ok: true,
+                detail: format!("Last reply {:.1?} ago", reply_age),
+            },
+        }
+    }
+
+    fn heartbeat_check(&self) -> HealthCheck {
+        let reply_age = self.controller.last_reply_age();
+        let degraded = self.link.lock().ok().and_then(|link| link.degraded);
+        match degraded {
+            Some((at, missed)) if reply_age >= at.elapsed() => HealthCheck {
+                name: "heartbeat",
+                ok: false,
+                detail: format!("{} missed in a row", missed),
+            },
+            _ => HealthCheck {
+                name: "heartbeat",
+                ok: true,
+                detail: "Answered".to_string(),
+            },
+        }
+    }
+
+    fn lifecycle_check(&self) -> HealthCheck {
+        match self.controller.lifecycle() {
+            Ok(lifecycle) => HealthCheck {
+                name: "lifecycle",
+                ok: !matches!(lifecycle, Lifecycle::Fault | Lifecycle::Disconnected),
+                detail: lifecycle.name().to_string(),
+            },
+            Err(e) => HealthCheck {
+                name: "lifecycle",
+                ok: false,
+                detail: e.to_string(),
+            },
+        }
+    }
+
+    /// Serves the endpoints on `addr` until `cancel` is cancelled.
+    pub fn serve<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("Health endpoints: {:?}", e));
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        println!(
+            "Health endpoints listening on {}",
+            listener.local_addr().map_err(io_error)?
+        );
+
+        while !cancel.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if let Err(e) = self.answer(stream) {
+                        eprintln!("Health probe from {}: {:?}", peer, e);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cancel.sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn answer(&self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line)?;
+        // Headers are not needed; read them so the client sees a clean close.
+        let mut header = String::new();
+        while reader.read_line(&mut header)? > 2 {
+            header.clear();
+        }
+
+        let mut fields = request_line.split_whitespace();
+        let (method, path) = (fields.next().unwrap_or(""), fields.next().unwrap_or(""));
+        let report = match path.split('?').next() {
+            Some("/healthz") => Some(self.liveness()),
+            Some("/readyz") => Some(self.readiness()),
+            _ => None,
+        };
+        let (status, body) = match (method, report) {
+            ("GET" | "HEAD", Some(report)) if report.healthy() => {
+                ("200 OK", report.to_json().to_string())
+            }
+            ("GET" | "HEAD", Some(report)) => {
+                ("503 Service Unavailable", report.to_json().to_string())
+            }
+            ("GET" | "HEAD", None) => ("404 Not Found", "{\"status\":\"not found\"}".to_string()),
+            _ => (
+                "405 Method Not Allowed",
+                "{\"status\":\"method not allowed\"}".to_string(),
+            ),
+        };
+        write!(
+            writer,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            body.len()
+        )?;
+        if method != "HEAD" {
+            writer.write_all(body.as_bytes())?;
+        }
+        writer.flush()
+    }
+}
@@ -0,0 +1,90 @@
+//! Keepalive for links that sit idle between runs.
+//!
+//! A dead USB cable or a bridge that lost its Wi-Fi otherwise goes unnoticed
+//! until the next real command times out. The heartbeat sends `$IDN` whenever
+//! the board has not answered anything for a full interval and reports
+//! consecutive misses as [`ControllerEvent`]s.
+
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::events::ControllerEvent;
+use crate::priority::Priority;
+
+/// Sends a lightweight query when the link has been idle for `interval`.
+///
+/// Every missed heartbeat emits [`ControllerEvent::LinkDegraded`] until
+/// `misses_until_lost` are missed in a row, which emits
+/// [`ControllerEvent::ConnectionLost`] once. The next reply, to a heartbeat
+/// or to any other command, emits [`ControllerEvent::LinkRestored`].
+pub struct Heartbeat {
+    controller: Controller,
+    interval: Duration,
+    misses_until_lost: u32,
+}
+
+impl Heartbeat {
+    pub fn new(controller: &Controller, interval: Duration) -> Heartbeat {
+        Heartbeat {
+            controller: controller.with_priority(Priority::Background),
+            interval,
+            misses_until_lost: 3,
+        }
+    }
+
+    pub fn with_misses_until_lost(mut self, misses: u32) -> Heartbeat {
+        self.misses_until_lost = misses.max(1);
+        self
+    }
+
+    /// Runs the heartbeat on a background thread until `cancel` is cancelled.
+    pub fn spawn(self, cancel: CancellationToken) -> JoinHandle<()> {
+        thread::spawn(move || {
+            let mut missed = 0;
+            loop {
+                let idle = self.controller.last_reply_age();
+                if idle < self.interval {
+                    if missed > 0 {
+                        missed = 0;
+                        self.controller.emit(&ControllerEvent::LinkRestored);
+                    }
+                    if !cancel.sleep(self.interval - idle) {
+                        return;
+                    }
+                    continue;
+                }
+
+                // Raw exchange, so a miss is reported here rather than as a command timeout.
+                let alive = self
+                    .controller
+                    .write_read(&Command::GetIdentity.to_string())
+                    .is_ok();
+                if alive {
+                    if missed > 0 {
+                        missed = 0;
+                        self.controller.emit(&ControllerEvent::LinkRestored);
+                    }
+                } else {
+                    missed += 1;
+                    if missed < self.misses_until_lost {
+                        self.controller.emit(&ControllerEvent::LinkDegraded(missed));
+                    } else if missed == self.misses_until_lost {
+                        self.controller
+                            .emit(&ControllerEvent::ConnectionLost(format!(
+                                "No reply to {} heartbeats",
+                                missed
+                            )));
+                    }
+                }
+                if !cancel.sleep(self.interval) {
+                    return;
+                }
+            }
+        })
+    }
+}
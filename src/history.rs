@@ -0,0 +1,342 @@
+//! Queries over the telemetry logs written with `--log`, so a past run can
+//! be looked at without opening the CSV files by hand.
+//!
+//! A [`TelemetryHistory`] reads the log, the files it was rotated into
+//! (`<log>.1`, `<log>.2.gz`, ...) and the aggregates a downsampled log
+//! keeps next to it, and returns what falls within a time range in time
+//! order. Compressed files are read through the system `gzip` tool, as
+//! they were written.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use crate::downsample::{Stats, TelemetryAggregate};
+use crate::telemetry::TelemetrySample;
+
+/// Rotated files looked for beyond the current one; more than any
+/// retention in use.
+const MAX_ROTATED: usize = 1000;
+
+/// A quantity recorded in the telemetry log.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Frequency,
+    PowerSetpoint,
+    Forward,
+    Reflected,
+    Temperature,
+    /// Forward minus reflected power in watts.
+    Delivered,
+    /// 1 with RF on, 0 with it off; the share of samples with RF on for an
+    /// aggregate.
+    RfEnabled,
+    SupplyVoltage,
+    SupplyCurrent,
+    /// PA efficiency in percent, while RF is on.
+    Efficiency,
+}
+
+impl Signal {
+    pub const ALL: [Signal; 10] = [
+        Signal::Frequency,
+        Signal::PowerSetpoint,
+        Signal::Forward,
+        Signal::Reflected,
+        Signal::Temperature,
+        Signal::Delivered,
+        Signal::RfEnabled,
+        Signal::SupplyVoltage,
+        Signal::SupplyCurrent,
+        Signal::Efficiency,
+    ];
+
+    /// Column name, as in the telemetry CSV.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Signal::Frequency => "frequency_mhz",
+            Signal::PowerSetpoint => "power_setpoint_dbm",
+            Signal::Forward => "forward_dbm",
+            Signal::Reflected => "reflected_dbm",
+            Signal::Temperature => "temperature_c",
+            Signal::Delivered => "delivered_w",
+            Signal::RfEnabled => "rf_enabled",
+            Signal::SupplyVoltage => "supply_voltage_v",
+            Signal::SupplyCurrent => "supply_current_a",
+            Signal::Efficiency => "efficiency_pct",
+        }
+    }
+
+    pub fn of_sample(&self, sample: &TelemetrySample) -> Option<f64> {
+        match self {
+            Signal::Frequency => Some(sample.frequency_mhz.into()),
+            Signal::PowerSetpoint => Some(sample.power_setpoint_dbm.into()),
+            Signal::Forward => Some(sample.forward_dbm.into()),
+            Signal::Reflected => Some(sample.reflected_dbm.into()),
+            Signal::Temperature => sample.temperature_c.map(f64::from),
+            Signal::Delivered => Some(sample.delivered_watts()),
+            Signal::RfEnabled => Some(f64::from(u8::from(sample.rf_enabled))),
+            Signal::SupplyVoltage => sample.supply_voltage_v.map(f64::from),
+            Signal::SupplyCurrent => sample.supply_current_a.map(f64::from),
+            Signal::Efficiency => sample.efficiency_percent(),
+        }
+    }
+
+    /// `None` for the power setpoint, which aggregates do not keep.
+    pub fn of_aggregate(&self, aggregate: &TelemetryAggregate) -> Option<Stats> {
+        match self {
+            Signal::Frequency => Some(aggregate.frequency_mhz),
+            Signal::PowerSetpoint => None,
+            Signal::Forward => Some(aggregate.forward_dbm),
+            Signal::Reflected => Some(aggregate.reflected_dbm),
+            Signal::Temperature => aggregate.temperature_c,
+            Signal::Delivered => Some(aggregate.delivered_watts),
+            Signal::RfEnabled => Some(Stats {
+                min: aggregate.rf_on_fraction,
+                mean: aggregate.rf_on_fraction,
+                max: aggregate.rf_on_fraction,
+            }),
+            Signal::SupplyVoltage => aggregate.supply_voltage_v,
+            Signal::SupplyCurrent => aggregate.supply_current_a,
+            Signal::Efficiency => aggregate.efficiency_percent,
+        }
+    }
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Signal {
+    type Err = String;
+
+    /// Column names and the plain names, e.g. `reflected_dbm`,
+    /// `reflected_power` or `reflected`.
+    fn from_str(name: &str) -> Result<Signal, String> {
+        let name = name.trim().to_ascii_lowercase().replace('-', "_");
+        let signal = match name.as_str() {
+            "frequency" | "frequency_mhz" => Signal::Frequency,
+            "power_setpoint" | "power_setpoint_dbm" | "setpoint" => Signal::PowerSetpoint,
+            "forward" | "forward_power" | "forward_dbm" => Signal::Forward,
+            "reflected" | "reflected_power" | "reflected_dbm" => Signal::Reflected,
+            "temperature" | "temperature_c" => Signal::Temperature,
+            "delivered" | "delivered_power" | "delivered_w" => Signal::Delivered,
+            "rf" | "rf_enabled" => Signal::RfEnabled,
+            "supply_voltage" | "supply_voltage_v" | "voltage" => Signal::SupplyVoltage,
+            "supply_current" | "supply_current_a" | "current" => Signal::SupplyCurrent,
+            "efficiency" | "efficiency_pct" => Signal::Efficiency,
+            _ => {
+                let names: Vec<&str> = Signal::ALL.iter().map(Signal::name).collect();
+                return Err(format!(
+                    "Unknown signal {}; use one of {}",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        };
+        Ok(signal)
+    }
+}
+
+/// One row of the history: a sample, or an aggregate of a downsampled log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HistoryRow {
+    Sample(TelemetrySample),
+    Aggregate(Box<TelemetryAggregate>),
+}
+
+impl HistoryRow {
+    /// Time of the sample or start of the aggregate.
+    pub fn timestamp(&self) -> SystemTime {
+        match self {
+            HistoryRow::Sample(sample) => sample.timestamp,
+            HistoryRow::Aggregate(aggregate) => aggregate.start,
+        }
+    }
+
+    /// The sample's value, or the aggregate's mean.
+    pub fn value(&self, signal: Signal) -> Option<f64> {
+        match self {
+            HistoryRow::Sample(sample) => signal.of_sample(sample),
+            HistoryRow::Aggregate(aggregate) => {
+                signal.of_aggregate(aggregate).map(|stats| stats.mean)
+            }
+        }
+    }
+}
+
+/// One point of a [`TelemetryHistory::series`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryPoint {
+    pub timestamp: SystemTime,
+    pub value: f64,
+    /// Minimum and maximum over the aggregate this point is the mean of;
+    /// `None` for a sample.
+    pub range: Option<(f64, f64)>,
+}
+
+/// Telemetry log files and their aggregates, read on every query.
+#[derive(Debug, Clone)]
+pub struct TelemetryHistory {
+    logs: Vec<PathBuf>,
+    aggregates: Vec<PathBuf>,
+}
+
+impl TelemetryHistory {
+    /// History of the log at `log` and of `<log>-aggregates.csv` if it
+    /// exists, each with its rotated files.
+    pub fn open(log: impl AsRef<Path>) -> io::Result<TelemetryHistory> {
+        let log = log.as_ref();
+        let logs = with_rotated(log);
+        if logs.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("No telemetry log at {}", log.display()),
+            ));
+        }
+        let stem = log.to_string_lossy();
+        let aggregates = format!("{}-aggregates.csv", stem.trim_end_matches(".csv"));
+        Ok(TelemetryHistory {
+            logs,
+            aggregates: with_rotated(Path::new(&aggregates)),
+        })
+    }
+
+    /// Reads the aggregates from `path` instead, as given to `--aggregates`.
+    pub fn with_aggregates(mut self, path: impl AsRef<Path>) -> TelemetryHistory {
+        self.aggregates = with_rotated(path.as_ref());
+        self
+    }
+
+    /// Files that will be read, logs first.
+    pub fn files(&self) -> impl Iterator<Item = &Path> {
+        self.logs
+            .iter()
+            .chain(&self.aggregates)
+            .map(PathBuf::as_path)
+    }
+
+    /// Samples and aggregates from `from` (inclusive) to `to` (exclusive),
+    /// oldest first. Rows that do not parse, such as the header repeated
+    /// at the top of every rotated file, are skipped.
+    pub fn rows(
+        &self,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> io::Result<Vec<HistoryRow>> {
+        let in_range =
+            |at: SystemTime| from.is_none_or(|from| at >= from) && to.is_none_or(|to| at < to);
+        let mut rows = Vec::new();
+        for path in &self.logs {
+            rows.extend(
+                read_lines(path)?
+                    .lines()
+                    .filter_map(|line| TelemetrySample::from_csv_row(line).ok())
+                    .filter(|sample| in_range(sample.timestamp))
+                    .map(HistoryRow::Sample),
+            );
+        }
+        for path in &self.aggregates {
+            rows.extend(
+                read_lines(path)?
+                    .lines()
+                    .filter_map(|line| TelemetryAggregate::from_csv_row(line).ok())
+                    .filter(|aggregate| in_range(aggregate.start))
+                    .map(|aggregate| HistoryRow::Aggregate(Box::new(aggregate))),
+            );
+        }
+        rows.sort_by_key(HistoryRow::timestamp);
+        Ok(rows)
+    }
+
+    /// The samples alone, without aggregates.
+    pub fn samples(
+        &self,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> io::Result<Vec<TelemetrySample>> {
+        Ok(self
+            .rows(from, to)?
+            .into_iter()
+            .filter_map(|row| match row {
+                HistoryRow::Sample(sample) => Some(sample),
+                HistoryRow::Aggregate(_) => None,
+            })
+            .collect())
+    }
+
+    /// Time series of one signal. Rows without it, e.g. samples without a
+    /// temperature, are left out.
+    pub fn series(
+        &self,
+        signal: Signal,
+        from: Option<SystemTime>,
+        to: Option<SystemTime>,
+    ) -> io::Result<Vec<HistoryPoint>> {
+        Ok(self
+            .rows(from, to)?
+            .iter()
+            .filter_map(|row| match row {
+                HistoryRow::Sample(sample) => Some(HistoryPoint {
+                    timestamp: sample.timestamp,
+                    value: signal.of_sample(sample)?,
+                    range: None,
+                }),
+                HistoryRow::Aggregate(aggregate) => {
+                    let stats = signal.of_aggregate(aggregate)?;
+                    Some(HistoryPoint {
+                        timestamp: aggregate.start,
+                        value: stats.mean,
+                        range: Some((stats.min, stats.max)),
+                    })
+                }
+            })
+            .collect())
+    }
+}
+
+/// `path` and its rotated files that exist, oldest first.
+fn with_rotated(path: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for index in 1..=MAX_ROTATED {
+        let rotated: Vec<PathBuf> = ["", ".gz"]
+            .iter()
+            .map(|suffix| {
+                let mut name = path.as_os_str().to_owned();
+                name.push(format!(".{}{}", index, suffix));
+                PathBuf::from(name)
+            })
+            .filter(|rotated| rotated.exists())
+            .collect();
+        if rotated.is_empty() {
+            break;
+        }
+        files.extend(rotated);
+    }
+    files.reverse();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+fn read_lines(path: &Path) -> io::Result<String> {
+    if path.extension().is_some_and(|extension| extension == "gz") {
+        let output = Command::new("gzip").arg("-dc").arg(path).output()?;
+        if !output.status.success() {
+            return Err(io::Error::other(format!(
+                "gzip could not read {}",
+                path.display()
+            )));
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    fs::read_to_string(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))
+}
@@ -0,0 +1,180 @@
+//! Conditions that must hold for RF to be enabled, and a monitor that
+//! disables RF the moment one of them opens.
+//!
+//! Interlocks added with [`Controller::add_interlock`] are checked before
+//! every `RfEnable`. [`spawn_interlock_monitor`] also checks them while RF
+//! is on, so a door switch wired to a GPIO pin, a serial port's modem
+//! status line or anything a callback can read cuts RF when it opens.
+
+use std::str::FromStr;
+#[cfg(feature = "serial")]
+use std::sync::Mutex;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::events::ControllerEvent;
+
+/// Condition that must hold for RF to be enabled, such as a closed door switch.
+pub trait Interlock: Send + Sync {
+    /// Short name used in error messages and logs.
+    fn name(&self) -> &str;
+
+    /// `true` while it is safe to transmit.
+    fn is_closed(&self) -> bool;
+}
+
+/// Interlock read by a callback, e.g. a PLC tag or a vendor SDK.
+pub struct CallbackInterlock {
+    name: String,
+    is_closed: Box<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl CallbackInterlock {
+    pub fn new(
+        name: &str,
+        is_closed: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> CallbackInterlock {
+        CallbackInterlock {
+            name: name.to_string(),
+            is_closed: Box::new(is_closed),
+        }
+    }
+}
+
+impl Interlock for CallbackInterlock {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_closed(&self) -> bool {
+        (self.is_closed)()
+    }
+}
+
+/// Modem status input of a serial port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SerialLine {
+    /// Clear To Send.
+    Cts,
+    /// Data Set Ready.
+    Dsr,
+    /// Carrier Detect.
+    Dcd,
+    /// Ring Indicator.
+    Ri,
+}
+
+impl FromStr for SerialLine {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<SerialLine, String> {
+        match line.to_ascii_lowercase().as_str() {
+            "cts" => Ok(SerialLine::Cts),
+            "dsr" => Ok(SerialLine::Dsr),
+            "dcd" | "cd" => Ok(SerialLine::Dcd),
+            "ri" => Ok(SerialLine::Ri),
+            _ => Err(format!(
+                "Unknown serial line {}; use cts, dsr, dcd or ri",
+                line
+            )),
+        }
+    }
+}
+
+/// Interlock wired to a modem status line of a serial port other than the
+/// board's, e.g. a door switch between DTR and CTS of a USB-serial adapter.
+/// The port's DTR and RTS outputs are asserted so they can power the loop.
+#[cfg(feature = "serial")]
+pub struct SerialLineInterlock {
+    name: String,
+    port: Mutex<Box<dyn serialport::SerialPort>>,
+    line: SerialLine,
+    /// The line reads deasserted while the interlock is closed.
+    active_low: bool,
+}
+
+#[cfg(feature = "serial")]
+impl SerialLineInterlock {
+    pub fn open(
+        name: &str,
+        port_name: &str,
+        line: SerialLine,
+        active_low: bool,
+    ) -> Result<SerialLineInterlock, crate::ControllerError> {
+        let connection_error = |e: serialport::Error| {
+            crate::ControllerError::Connection(format!("{}: {}", port_name, e))
+        };
+        let mut port = serialport::new(port_name, 9600)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(connection_error)?;
+        port.write_data_terminal_ready(true)
+            .map_err(connection_error)?;
+        port.write_request_to_send(true).map_err(connection_error)?;
+        Ok(SerialLineInterlock {
+            name: name.to_string(),
+            port: Mutex::new(port),
+            line,
+            active_low,
+        })
+    }
+
+    /// Reads the line and applies the active-low setting.
+    pub fn is_active(&self) -> Result<bool, serialport::Error> {
+        let mut port = self
+            .port
+            .lock()
+            .map_err(|_| serialport::Error::new(serialport::ErrorKind::Unknown, "poisoned"))?;
+        let asserted = match self.line {
+            SerialLine::Cts => port.read_clear_to_send()?,
+            SerialLine::Dsr => port.read_data_set_ready()?,
+            SerialLine::Dcd => port.read_carrier_detect()?,
+            SerialLine::Ri => port.read_ring_indicator()?,
+        };
+        Ok(asserted != self.active_low)
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Interlock for SerialLineInterlock {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// A read failure, e.g. the adapter being unplugged, counts as open.
+    fn is_closed(&self) -> bool {
+        self.is_active().unwrap_or(false)
+    }
+}
+
+/// Checks `controller`'s interlocks every `interval` until `cancel` is
+/// cancelled, and disables RF as soon as one opens while RF is on, emitting
+/// [`ControllerEvent::InterlockTripped`].
+pub fn spawn_interlock_monitor(
+    controller: &Controller,
+    interval: Duration,
+    cancel: CancellationToken,
+) -> JoinHandle<()> {
+    let controller = controller.clone();
+    thread::spawn(move || {
+        while cancel.sleep(interval) {
+            if !controller.rf_enabled() {
+                continue;
+            }
+            let open = match controller.open_interlock() {
+                Ok(open) => open,
+                Err(e) => Some(e.to_string()),
+            };
+            // Retried every interval until RF is off.
+            if let Some(name) = open {
+                eprintln!("Interlock {} opened, disabling RF.", name);
+                controller.emit(&ControllerEvent::InterlockTripped(name));
+                if let Err(e) = controller.safe_state() {
+                    eprintln!("Failed to disable RF: {}", e);
+                }
+            }
+        }
+    })
+}
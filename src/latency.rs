@@ -0,0 +1,144 @@
+//! Response timeouts learned from how long the board takes to answer each
+//! command.
+//!
+//! A single fixed timeout has to suit the slowest command, so a dead link
+//! is only noticed after waiting that long for every query. Instead, each
+//! command mnemonic keeps a smoothed latency and its mean deviation, as
+//! TCP does for round-trip times, and waits for the smoothed latency plus
+//! four deviations, kept within configured bounds. A command that times
+//! out waits twice as long on its next attempt, up to the upper bound, so
+//! one slower than it has been recently recovers instead of failing for
+//! good.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::json::{FromJson, JsonValue, ToJson};
+
+/// Timeout used for every command unless adaptive timeouts are enabled.
+pub const DEFAULT_RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Range the learned timeouts are kept within.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimeoutBounds {
+    pub min: Duration,
+    /// Also the timeout of a command that has not been answered yet.
+    pub max: Duration,
+}
+
+impl TimeoutBounds {
+    pub fn new(min: Duration, max: Duration) -> Result<TimeoutBounds, String> {
+        if min.is_zero() || min > max {
+            return Err(format!(
+                "Timeout bounds must be above zero with min {:?} at most max {:?}",
+                min, max
+            ));
+        }
+        Ok(TimeoutBounds { min, max })
+    }
+}
+
+impl ToJson for TimeoutBounds {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("min_s", self.min)
+            .with("max_s", self.max)
+    }
+}
+
+impl FromJson for TimeoutBounds {
+    fn from_json(json: &JsonValue) -> Result<TimeoutBounds, String> {
+        TimeoutBounds::new(json.duration("min_s")?, json.duration("max_s")?)
+    }
+}
+
+/// What has been learned about one command's replies.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencyEstimate {
+    /// Replies measured.
+    pub samples: u32,
+    pub smoothed: Duration,
+    pub deviation: Duration,
+    /// Timeouts in a row since the last reply.
+    pub timeouts: u32,
+}
+
+impl LatencyEstimate {
+    fn timeout(&self, bounds: &TimeoutBounds) -> Duration {
+        let learned = self.smoothed + self.deviation * 4;
+        let backed_off = learned.saturating_mul(1 << self.timeouts.min(16));
+        backed_off.clamp(bounds.min, bounds.max)
+    }
+}
+
+/// Per-command latency estimates and the timeouts derived from them.
+#[derive(Debug, Clone)]
+pub struct AdaptiveTimeouts {
+    bounds: TimeoutBounds,
+    estimates: HashMap<String, LatencyEstimate>,
+}
+
+impl AdaptiveTimeouts {
+    pub fn new(bounds: TimeoutBounds) -> AdaptiveTimeouts {
+        AdaptiveTimeouts {
+            bounds,
+            estimates: HashMap::new(),
+        }
+    }
+
+    pub fn bounds(&self) -> TimeoutBounds {
+        self.bounds
+    }
+
+    /// How long to wait for the reply to command line `tx`.
+    pub fn timeout(&self, tx: &str) -> Duration {
+        match self.estimates.get(mnemonic(tx)) {
+            Some(estimate) if estimate.samples > 0 => estimate.timeout(&self.bounds),
+            _ => self.bounds.max,
+        }
+    }
+
+    /// Records that `tx` was answered after `latency`.
+    pub fn observe(&mut self, tx: &str, latency: Duration) {
+        let estimate = self
+            .estimates
+            .entry(mnemonic(tx).to_string())
+            .or_insert(LatencyEstimate {
+                samples: 0,
+                smoothed: latency,
+                deviation: latency / 2,
+                timeouts: 0,
+            });
+        if estimate.samples > 0 {
+            let error = estimate.smoothed.abs_diff(latency);
+            estimate.deviation = (estimate.deviation * 3 + error) / 4;
+            estimate.smoothed = (estimate.smoothed * 7 + latency) / 8;
+        }
+        estimate.samples = estimate.samples.saturating_add(1);
+        estimate.timeouts = 0;
+    }
+
+    /// Records that `tx` was not answered in time.
+    pub fn timed_out(&mut self, tx: &str) {
+        if let Some(estimate) = self.estimates.get_mut(mnemonic(tx)) {
+            estimate.timeouts = estimate.timeouts.saturating_add(1);
+        }
+    }
+
+    /// Estimates by mnemonic, e.g. `$FCS`, sorted by mnemonic.
+    pub fn estimates(&self) -> Vec<(String, LatencyEstimate)> {
+        let mut estimates: Vec<_> = self
+            .estimates
+            .iter()
+            .map(|(mnemonic, estimate)| (mnemonic.clone(), *estimate))
+            .collect();
+        estimates.sort_by(|a, b| a.0.cmp(&b.0));
+        estimates
+    }
+}
+
+/// `$FCS` of `$FCS,0,2450.00`: set and get commands take different times.
+fn mnemonic(tx: &str) -> &str {
+    let tx = tx.trim();
+    tx.split(',').next().unwrap_or(tx)
+}
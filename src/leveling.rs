@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use crate::calibration::CalibrationTable;
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::sweep::{settle_and_measure, Settling, SweepPoint};
+
+/// Closed-loop amplitude leveling for sweeps.
+///
+/// At each frequency the setpoint starts from the calibration table's
+/// correction and is then nudged by the forward power readback error until it
+/// is within `tolerance_db` of the target.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leveling {
+    pub target_dbm: f32,
+    pub tolerance_db: f32,
+    /// Readback corrections attempted per frequency.
+    pub max_iterations: usize,
+    /// Upper bound for the corrected setpoint, protecting the PA from runaway corrections.
+    pub max_setpoint_dbm: f32,
+    pub calibration: CalibrationTable,
+}
+
+impl Leveling {
+    /// Levels the output at the current frequency and returns the final reading.
+    pub fn level(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        frequency_mhz: f32,
+        dwell: Duration,
+        settling: Option<&Settling>,
+    ) -> Result<SweepPoint, ControllerError> {
+        let mut setpoint = self
+            .calibration
+            .corrected_setpoint(frequency_mhz, self.target_dbm)
+            .min(self.max_setpoint_dbm);
+        controller.send(&Command::SetPower(setpoint))?;
+        let mut point = settle_and_measure(controller, cancel, frequency_mhz, dwell, settling)?;
+
+        for _ in 0..self.max_iterations {
+            let error = self.target_dbm - point.forward_dbm;
+            if error.abs() <= self.tolerance_db {
+                break;
+            }
+            let next = (setpoint + error).min(self.max_setpoint_dbm);
+            if next == setpoint {
+                break;
+            }
+            setpoint = next;
+            controller.send(&Command::SetPower(setpoint))?;
+            point = settle_and_measure(controller, cancel, frequency_mhz, dwell, settling)?;
+        }
+
+        Ok(point)
+    }
+}
+
+impl ToJson for Leveling {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("target_dbm", self.target_dbm)
+            .with("tolerance_db", self.tolerance_db)
+            .with("max_iterations", self.max_iterations)
+            .with("max_setpoint_dbm", self.max_setpoint_dbm)
+            .with("calibration", self.calibration.to_json())
+    }
+}
+
+impl FromJson for Leveling {
+    fn from_json(json: &JsonValue) -> Result<Leveling, String> {
+        Ok(Leveling {
+            target_dbm: json.f32("target_dbm")?,
+            tolerance_db: json.f32("tolerance_db")?,
+            max_iterations: json.number("max_iterations")? as usize,
+            max_setpoint_dbm: json.f32("max_setpoint_dbm")?,
+            calibration: match json.get("calibration") {
+                Some(table) => CalibrationTable::from_json(table)?,
+                None => CalibrationTable::default(),
+            },
+        })
+    }
+}
@@ -0,0 +1,136 @@
+pub mod acceptance;
+pub mod access;
+pub mod alarms;
+pub mod alerting;
+pub mod audit;
+pub mod blackbox;
+#[cfg(feature = "ble")]
+pub mod ble;
+pub mod bridge;
+pub mod calibration;
+pub mod cancel;
+pub mod controller;
+pub mod controller_commands;
+pub mod controller_properites;
+pub mod controller_responses;
+pub mod cooling;
+pub mod derating;
+pub mod device_config;
+pub mod device_state;
+pub mod dll;
+pub mod downsample;
+#[cfg(feature = "epics")]
+pub mod epics;
+pub mod error;
+pub mod events;
+pub mod exposure;
+#[cfg(feature = "serial")]
+pub mod ffi;
+pub mod fleet;
+pub mod framing;
+#[cfg(feature = "gpio")]
+pub mod gpio;
+pub mod health;
+pub mod heartbeat;
+pub mod history;
+pub mod interlock;
+pub mod json;
+pub mod latency;
+pub mod leveling;
+pub mod lifecycle;
+pub mod lockout;
+pub mod mdns;
+pub mod modbus;
+pub mod nanovna;
+pub mod notify;
+pub mod opcua;
+pub mod otel;
+pub mod parquet;
+pub mod port_filter;
+pub mod port_lock;
+pub mod port_path;
+pub mod power_meter;
+pub mod priority;
+pub mod profile;
+pub mod progress;
+pub mod protocol;
+#[cfg(unix)]
+pub mod pty;
+pub mod pulse;
+pub mod ramp;
+pub mod recipe;
+pub mod reload;
+pub mod report;
+pub mod resonance;
+pub mod retune;
+pub mod rotation;
+pub mod safety;
+pub mod scheduler;
+pub mod serial_settings;
+pub mod session;
+pub mod settings;
+pub mod sha256;
+pub mod simulator;
+pub mod soak;
+pub mod sweep;
+pub mod sweep2d;
+pub mod telemetry;
+pub mod transport;
+pub mod typestate;
+pub mod units;
+pub mod validate;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use acceptance::{AcceptanceReport, AcceptanceTest};
+pub use access::{AccessPolicy, ApiToken, Role};
+pub use alarms::{AlarmAction, AlarmCondition, AlarmEngine, AlarmRule};
+pub use alerting::{Alert, AlertDispatcher, AlertSink, Severity};
+pub use audit::AuditLog;
+pub use blackbox::BlackBox;
+pub use bridge::LineBridge;
+pub use calibration::CalibrationTable;
+pub use cancel::{CancellationToken, PauseMode};
+pub use controller::Controller;
+pub use controller_commands::Command;
+pub use cooling::{FanControl, FanCurve, FanPolicy};
+pub use derating::{Derater, DeratingPolicy, DeratingRule};
+pub use device_state::DeviceState;
+pub use dll::DllPreset;
+pub use downsample::{Downsampler, Downsampling};
+pub use error::{ControllerError, DeviceError, DeviceErrorKind, DeviceFault};
+pub use events::{ControllerEvent, EventListener, Exchange, ExchangeListener};
+pub use exposure::Exposure;
+pub use fleet::{Fleet, FleetMember, FleetTelemetry};
+pub use framing::LineFramer;
+pub use health::HealthServer;
+pub use heartbeat::Heartbeat;
+pub use interlock::Interlock;
+pub use latency::TimeoutBounds;
+pub use leveling::Leveling;
+pub use lifecycle::Lifecycle;
+pub use lockout::LocalLockout;
+pub use modbus::ModbusGateway;
+pub use opcua::OpcUaServer;
+pub use otel::OtlpExporter;
+pub use port_filter::{PortFilter, UsbId};
+pub use power_meter::{CalibrationRun, PowerMeter};
+pub use priority::Priority;
+pub use profile::DeviceProfile;
+pub use progress::Progress;
+pub use protocol::{StatusFlags, ValidationError};
+pub use pulse::{PulseMethod, PulseReport, PulseTrain};
+pub use report::{ReportRecorder, RunReport};
+pub use retune::Retune;
+pub use rotation::{RotatingWriter, RotationPolicy};
+pub use safety::SafetyGuard;
+pub use scheduler::PollScheduler;
+pub use serial_settings::SerialSettings;
+pub use settings::DeviceSettings;
+pub use simulator::Simulator;
+pub use soak::{SoakResult, SoakTest};
+pub use sweep::{Settling, Sweep, SweepMode, SweepPoint, SweepSegment};
+pub use sweep2d::{FrequencyPowerSweep, SweepMatrix};
+pub use telemetry::{EnergyMeter, PollInterval, TelemetryPoller, TelemetrySample};
+pub use transport::{MockTransport, Reopener, TcpTransport, Transport};
+pub use validate::ConfigSet;
@@ -0,0 +1,82 @@
+//! Lifecycle of the board as the controller sees it, and the commands each
+//! stage allows.
+//!
+//! Stages advance as the board accepts commands: any reply leaves
+//! `Disconnected` for `Connected`, setting a frequency makes it
+//! `Configured`, enabling RF `RfOn` and disabling it `Configured` again. A
+//! shutdown in a status or error reply moves any stage to `Fault`, a link
+//! failure to `Disconnected`. Queries, `RfDisable` and `ClearErrors` are
+//! allowed in every stage.
+
+use std::fmt;
+
+use crate::controller_commands::Command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// The link failed; the board's state is unknown until it answers again.
+    Disconnected,
+    /// The board answers but no frequency has been set through this link.
+    Connected,
+    /// A frequency has been set, so RF may be enabled.
+    Configured,
+    /// RF is enabled.
+    RfOn,
+    /// The board reported a shutdown. Only clearing errors leaves this
+    /// stage, back to `Connected`: the frequency has to be set again.
+    Fault,
+}
+
+impl Lifecycle {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Lifecycle::Disconnected => "disconnected",
+            Lifecycle::Connected => "connected",
+            Lifecycle::Configured => "configured",
+            Lifecycle::RfOn => "RF on",
+            Lifecycle::Fault => "fault",
+        }
+    }
+
+    /// Why `command` may not be sent in this stage, if it may not.
+    pub fn refusal(&self, command: &Command) -> Option<String> {
+        if command.is_query() || matches!(command, Command::RfDisable | Command::ClearErrors) {
+            return None;
+        }
+        match (self, command) {
+            (Lifecycle::Fault, _) => Some(format!(
+                "{} refused after a shutdown; clear the errors first",
+                command
+            )),
+            (Lifecycle::Disconnected | Lifecycle::Connected, Command::RfEnable) => {
+                Some("RF enable refused; set a frequency first".to_string())
+            }
+            (Lifecycle::RfOn, Command::SweepDbm { .. }) => {
+                Some("Sweep refused while RF is on; disable RF first".to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Stage after the board accepted `command`.
+    pub fn after(self, command: &Command) -> Lifecycle {
+        let state = match self {
+            Lifecycle::Disconnected => Lifecycle::Connected,
+            state => state,
+        };
+        match (state, command) {
+            (Lifecycle::Fault, Command::ClearErrors) => Lifecycle::Connected,
+            (Lifecycle::Fault, _) => Lifecycle::Fault,
+            (Lifecycle::Connected, Command::SetFrequency(_)) => Lifecycle::Configured,
+            (Lifecycle::Configured, Command::RfEnable) => Lifecycle::RfOn,
+            (Lifecycle::RfOn, Command::RfDisable) => Lifecycle::Configured,
+            (state, _) => state,
+        }
+    }
+}
+
+impl fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
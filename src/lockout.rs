@@ -0,0 +1,44 @@
+//! Keeping the board's front panel out of an automated run.
+//!
+//! With the lockout on, the board ignores its local controls, so an
+//! operator at the rack cannot change a setpoint the recipe relies on.
+//! [`LocalLockout`] turns it on for as long as it is held, on top of
+//! [`Controller::set_exclusive_remote`].
+
+use crate::controller::Controller;
+use crate::error::ControllerError;
+
+/// Locks the front panel out until dropped, including while unwinding from
+/// an error or a panic.
+pub struct LocalLockout {
+    /// Taken by [`LocalLockout::release`].
+    controller: Option<Controller>,
+}
+
+impl LocalLockout {
+    pub fn engage(controller: &Controller) -> Result<LocalLockout, ControllerError> {
+        controller.set_exclusive_remote(true)?;
+        Ok(LocalLockout {
+            controller: Some(controller.clone()),
+        })
+    }
+
+    /// Gives the front panel back now and reports whether that worked,
+    /// instead of only printing a failure as dropping does.
+    pub fn release(mut self) -> Result<(), ControllerError> {
+        match self.controller.take() {
+            Some(controller) => controller.set_exclusive_remote(false),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for LocalLockout {
+    fn drop(&mut self) {
+        if let Some(controller) = self.controller.take() {
+            if let Err(e) = controller.set_exclusive_remote(false) {
+                eprintln!("Failed to unlock the front panel: {}", e);
+            }
+        }
+    }
+}
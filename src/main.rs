@@ -1,152 +1,43 @@
-use serialport::{available_ports, SerialPort, SerialPortInfo};
-use std::{
-    process,
-    time::{Duration, Instant},
-};
+use std::process;
 
-mod controller_properites;
-use controller_properites::*;
-
-mod controller_commands;
-use controller_commands::Command;
+mod cli;
 
 fn main() {
-    let mut port = match connect_to_signal_generator() {
-        Some(port) => port,
-        None => {
-            eprintln!("Exiting program: No valid connection.");
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("audit") => cli::audit::run(&args[1..]),
+        Some("batch") => cli::batch::run(&args[1..]),
+        Some("calibrate") => cli::calibrate::run(&args[1..]),
+        Some("compare") => cli::compare::run(&args[1..]),
+        Some("config") => cli::config::run(&args[1..]),
+        Some("daemon") => cli::daemon::run(&args[1..]),
+        Some("discover") => cli::discover::run(&args[1..]),
+        Some("dll") => cli::dll::run(&args[1..]),
+        Some("export") => cli::export::run(&args[1..]),
+        Some("expose") => cli::expose::run(&args[1..]),
+        Some("fleet") => cli::fleet::run(&args[1..]),
+        Some("history") => cli::history::run(&args[1..]),
+        Some("modbus") => cli::modbus::run(&args[1..]),
+        Some("monitor") => cli::monitor::run(&args[1..]),
+        Some("ports") => cli::ports::run(&args[1..]),
+        Some("replay") => cli::replay::run(&args[1..]),
+        Some("run") => cli::run::run(&args[1..]),
+        #[cfg(unix)]
+        Some("simulate") => cli::simulate::run(&args[1..]),
+        Some("soak") => cli::soak::run(&args[1..]),
+        Some("sweep") => cli::sweep::run(&args[1..]),
+        Some("tune") => cli::tune::run(&args[1..]),
+        Some("verify") => cli::verify::run(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") | None => {
+            print!("{}", cli::USAGE);
             return;
         }
+        Some(other) => Err(format!("Unknown command: {}\n\n{}", other, cli::USAGE)),
     };
 
-    // // Example commands
-    // let command = Command::SetPower(30.);
-    // let command_string = command.to_string();
-
-    // match write_read(&mut *port, command_string) {
-    //     Ok(response) => println!("Response: {}", response),
-    //     Err(e) => eprintln!("Error: {}", e),
-    // }
-
-    let command = Command::SetFrequency(50.);
-    let command_string = command.to_string();
-    match write_read(&mut *port, command_string) {
-        Ok(response) => println!("Response: {}", response),
-        Err(e) => eprintln!("Error: {}", e),
-    }
-
-    let command = Command::GetFrequency;
-    let command_string = command.to_string();
-    match write_read(&mut *port, command_string) {
-        Ok(response) => println!("Response: {}", response),
-        Err(e) => eprintln!("Error: {}", e),
-    }
-
-    std::thread::sleep(Duration::from_secs(20));
-    disconnect(port);
-}
-
-fn connect_to_signal_generator() -> Option<Box<dyn SerialPort>> {
-    let signal_generators = autodetect_sg_port();
-
-    if signal_generators.is_empty() {
-        eprintln!("No signal generator boards detected.");
-        return None;
+    if let Err(e) = result {
+        eprintln!("Error: {}", e);
+        process::exit(1);
     }
-
-    let first_signal_generator = &signal_generators[0];
-    println!(
-        "Connecting to signal generator: {:?}",
-        first_signal_generator.port_name
-    );
-
-    match serialport::new(&first_signal_generator.port_name, BAUD_RATE)
-        .data_bits(DATA_BITS)
-        .parity(PARITY)
-        .flow_control(FLOW_CONTROL)
-        .stop_bits(STOP_BITS)
-        .timeout(CONNECTION_TIMEOUT)
-        .open()
-    {
-        Ok(port) => {
-            println!(
-                "Successfully connected to {}",
-                first_signal_generator.port_name
-            );
-            Some(port)
-        }
-        Err(e) => {
-            eprintln!(
-                "Failed to connect to {}: {:?}",
-                first_signal_generator.port_name, e
-            );
-            None
-        }
-    }
-}
-
-fn autodetect_sg_port() -> Vec<SerialPortInfo> {
-    let available_ports = match available_ports() {
-        Ok(ports) => ports,
-        Err(e) => {
-            eprintln!("Failed to list serial ports: {:?}", e);
-            process::exit(1);
-        }
-    };
-    println!("Available ports to connect to:\n{:#?}\n", available_ports);
-
-    available_ports
-        .into_iter()
-        .filter(|port| {
-            if let serialport::SerialPortType::UsbPort(usb_info) = &port.port_type {
-                usb_info.vid == TARGET_VENDOR_ID && usb_info.pid == TARGET_PRODUCT_ID
-            } else {
-                false
-            }
-        })
-        .collect()
-}
-
-fn write_read(port: &mut dyn SerialPort, tx: String) -> Result<String, String> {
-    let command = format!("{}\r\n", tx);
-    println!("TX:\t{}", command);
-
-    if let Err(e) = port.write_all(command.as_bytes()) {
-        return Err(format!("Failed to write to the port: {:?}", e));
-    }
-
-    if let Err(e) = port.flush() {
-        return Err(format!("Failed to flush the port: {:?}", e));
-    }
-
-    let mut buffer = String::new();
-    let mut temp_buffer = [0; 256];
-    let timeout = Duration::from_millis(500);
-    let start_time = Instant::now();
-
-    while !buffer.contains("\r\n") {
-        if start_time.elapsed() >= timeout {
-            return Err("Timeout while waiting for response.".to_string());
-        }
-
-        match port.read(&mut temp_buffer) {
-            Ok(bytes_read) => {
-                if bytes_read > 0 {
-                    buffer.push_str(&String::from_utf8_lossy(&temp_buffer[..bytes_read]));
-                }
-            }
-            Err(ref e) if e.kind() == std::io::ErrorKind::TimedOut => continue,
-            Err(e) => return Err(format!("Failed to read from the port: {:?}", e)),
-        }
-    }
-
-    println!("RX:\t{}", buffer);
-    Ok(buffer)
-}
-
-fn disconnect(port: Box<dyn serialport::SerialPort>) {
-    let port_name = port.name().unwrap_or_else(|| "Unknown".to_string());
-    println!("Disconnecting from port: {}", port_name);
-    drop(port); // Explicitly drop the port
-    println!("Disconnected from port: {}", port_name);
 }
@@ -0,0 +1,720 @@
+//! mDNS / DNS-SD advertisement of daemons and discovery from any host on
+//! the LAN, without a hand-edited host list.
+//!
+//! A daemon announces the service `<instance>._mwctl._tcp.local` on
+//! start-up, answers queries for it on 224.0.0.251:5353 and withdraws it
+//! when it stops. The SRV record points at the line bridge when there is
+//! one and at the OPC UA server otherwise; TXT records give the port of
+//! every front end (`bridge=5025`, `opcua=4840`, ...). Avahi, Bonjour and
+//! `dns-sd -B _mwctl._tcp` see the same records as [`browse`].
+//!
+//! Only the parts of RFC 6762 and 6763 a single service needs are
+//! implemented: no probing for name conflicts, no known-answer suppression
+//! and IPv4 only.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::error::ControllerError;
+
+pub const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+pub const SERVICE_TYPE: &str = "_mwctl._tcp.local";
+const SERVICE_ENUMERATION: &str = "_services._dns-sd._udp.local";
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+/// Set on records only this host answers for, so caches replace old ones.
+const CACHE_FLUSH: u16 = 0x8000;
+const FLAGS_RESPONSE: u16 = 0x8400;
+
+/// TTL of the host and SRV records, per RFC 6762 section 10.
+const HOST_TTL: u32 = 120;
+const SERVICE_TTL: u32 = 4500;
+/// Longest TTL in replies to one-shot queries from ports other than 5353.
+const UNICAST_TTL: u32 = 10;
+
+/// Announcements sent on start-up, this far apart.
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(1);
+const ANNOUNCEMENTS: u32 = 2;
+
+/// One daemon's service as announced on the LAN.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServiceAdvertisement {
+    /// Instance name shown by browsers, e.g. the unit name.
+    pub instance: String,
+    /// Host label the SRV record points at, without `.local`.
+    pub host: String,
+    pub port: u16,
+    /// `key=value` pairs published in the TXT record.
+    pub txt: Vec<(String, String)>,
+}
+
+/// A service found by [`browse`].
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct DiscoveredService {
+    pub instance: String,
+    /// Host name from the SRV record, e.g. `rig-3.local`.
+    pub host: String,
+    pub addr: SocketAddr,
+    pub txt: Vec<(String, String)>,
+}
+
+impl DiscoveredService {
+    pub fn txt(&self, key: &str) -> Option<&str> {
+        self.txt
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+impl ServiceAdvertisement {
+    /// Service reachable on `port` of this host, named after the host.
+    pub fn new(port: u16) -> ServiceAdvertisement {
+        let host = host_name();
+        ServiceAdvertisement {
+            instance: host.clone(),
+            host,
+            port,
+            txt: Vec::new(),
+        }
+    }
+
+    /// Dots are not allowed in the instance name and become dashes.
+    pub fn with_instance(mut self, instance: &str) -> ServiceAdvertisement {
+        self.instance = instance.replace('.', "-");
+        self
+    }
+
+    pub fn with_txt(mut self, key: &str, value: impl ToString) -> ServiceAdvertisement {
+        self.txt.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    fn instance_name(&self) -> String {
+        format!("{}.{}", self.instance, SERVICE_TYPE)
+    }
+
+    fn host_name(&self) -> String {
+        format!("{}.local", self.host)
+    }
+
+    /// Announces the service, answers queries for it until `cancel` is
+    /// cancelled and then withdraws it.
+    pub fn serve(&self, cancel: &CancellationToken) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("mDNS: {}", e));
+        let socket = bind_shared(MDNS_PORT).map_err(io_error)?;
+        socket
+            .join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED)
+            .map_err(io_error)?;
+        socket.set_multicast_loop_v4(true).map_err(io_error)?;
+        socket
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .map_err(io_error)?;
+        let group = SocketAddr::V4(SocketAddrV4::new(MDNS_GROUP, MDNS_PORT));
+
+        let mut announced = 0;
+        let mut next_announcement = Instant::now();
+        let mut buffer = [0; 9000];
+        while !cancel.is_cancelled() {
+            if announced < ANNOUNCEMENTS && Instant::now() >= next_announcement {
+                let records = self.records(local_ip(group), None, true);
+                let message = Message::response(0, Vec::new(), records, Vec::new());
+                socket.send_to(&message.encode(), group).map_err(io_error)?;
+                announced += 1;
+                next_announcement += ANNOUNCE_INTERVAL;
+            }
+            let (count, peer) = match socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(io_error(e)),
+            };
+            let Ok(query) = Message::parse(&buffer[..count]) else {
+                continue;
+            };
+            if let Some((reply, to)) = self.answer(&query, peer, group) {
+                socket.send_to(&reply.encode(), to).map_err(io_error)?;
+            }
+        }
+
+        // Goodbye: the same records with TTL 0.
+        let records = self.records(local_ip(group), Some(0), true);
+        let message = Message::response(0, Vec::new(), records, Vec::new());
+        socket.send_to(&message.encode(), group).map_err(io_error)?;
+        Ok(())
+    }
+
+    /// Reply to `query` and where to send it, if it asks about this service.
+    fn answer(
+        &self,
+        query: &Message,
+        peer: SocketAddr,
+        group: SocketAddr,
+    ) -> Option<(Message, SocketAddr)> {
+        if query.response {
+            return None;
+        }
+        // One-shot queriers listen on their own port and get a unicast reply.
+        let legacy = peer.port() != MDNS_PORT;
+        let (answers, additionals): (Vec<Record>, Vec<Record>) = self
+            .records(local_ip(peer), legacy.then_some(UNICAST_TTL), !legacy)
+            .into_iter()
+            .partition(|record| query.questions.iter().any(|q| q.asks_for(record)));
+        if answers.is_empty() {
+            return None;
+        }
+        match legacy {
+            true => Some((
+                Message::response(query.id, query.questions.clone(), answers, additionals),
+                peer,
+            )),
+            false => Some((
+                Message::response(0, Vec::new(), answers, additionals),
+                group,
+            )),
+        }
+    }
+
+    /// PTR, SRV, TXT and A records, with `ttl` overriding the usual TTLs.
+    /// The cache-flush bit is not allowed in replies to one-shot queries.
+    fn records(&self, ip: Ipv4Addr, ttl: Option<u32>, cache_flush: bool) -> Vec<Record> {
+        let class = match cache_flush {
+            true => CLASS_IN | CACHE_FLUSH,
+            false => CLASS_IN,
+        };
+        let txt = match self.txt.is_empty() {
+            // A TXT record may not be empty.
+            true => vec![String::new()],
+            false => self
+                .txt
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect(),
+        };
+        vec![
+            Record {
+                name: SERVICE_TYPE.to_string(),
+                kind: TYPE_PTR,
+                class: CLASS_IN,
+                ttl: ttl.unwrap_or(SERVICE_TTL),
+                data: RecordData::Ptr(self.instance_name()),
+            },
+            Record {
+                name: SERVICE_ENUMERATION.to_string(),
+                kind: TYPE_PTR,
+                class: CLASS_IN,
+                ttl: ttl.unwrap_or(SERVICE_TTL),
+                data: RecordData::Ptr(SERVICE_TYPE.to_string()),
+            },
+            Record {
+                name: self.instance_name(),
+                kind: TYPE_SRV,
+                class,
+                ttl: ttl.unwrap_or(HOST_TTL),
+                data: RecordData::Srv {
+                    port: self.port,
+                    target: self.host_name(),
+                },
+            },
+            Record {
+                name: self.instance_name(),
+                kind: TYPE_TXT,
+                class,
+                ttl: ttl.unwrap_or(SERVICE_TTL),
+                data: RecordData::Txt(txt),
+            },
+            Record {
+                name: self.host_name(),
+                kind: TYPE_A,
+                class,
+                ttl: ttl.unwrap_or(HOST_TTL),
+                data: RecordData::A(ip),
+            },
+        ]
+    }
+}
+
+/// Asks the LAN for `_mwctl._tcp` services and collects the answers that
+/// arrive within `wait`.
+pub fn browse(wait: Duration) -> io::Result<Vec<DiscoveredService>> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_multicast_loop_v4(true)?;
+    let id = std::process::id() as u16;
+    let query = Message {
+        id,
+        response: false,
+        questions: vec![Question {
+            name: SERVICE_TYPE.to_string(),
+            kind: TYPE_PTR,
+        }],
+        answers: Vec::new(),
+        additionals: Vec::new(),
+    };
+    socket.send_to(&query.encode(), (MDNS_GROUP, MDNS_PORT))?;
+
+    let start = Instant::now();
+    let mut received: Vec<(IpAddr, Record)> = Vec::new();
+    let mut buffer = [0; 9000];
+    while let Some(remaining) = wait.checked_sub(start.elapsed()) {
+        socket.set_read_timeout(Some(remaining.max(Duration::from_millis(1))))?;
+        let (count, peer) = match socket.recv_from(&mut buffer) {
+            Ok(received) => received,
+            Err(e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
+                break
+            }
+            Err(e) => return Err(e),
+        };
+        if let Ok(message) = Message::parse(&buffer[..count]) {
+            if message.response {
+                let records = message.answers.into_iter().chain(message.additionals);
+                received.extend(records.map(|record| (peer.ip(), record)));
+            }
+        }
+    }
+
+    let find = |name: &str, kind: u16| {
+        received
+            .iter()
+            .find(|(_, record)| record.kind == kind && record.name.eq_ignore_ascii_case(name))
+    };
+    let mut services: Vec<DiscoveredService> = Vec::new();
+    for (source, record) in &received {
+        let RecordData::Ptr(instance_name) = &record.data else {
+            continue;
+        };
+        if !record.name.eq_ignore_ascii_case(SERVICE_TYPE) || record.ttl == 0 {
+            continue;
+        }
+        let Some((_, srv)) = find(instance_name, TYPE_SRV) else {
+            continue;
+        };
+        let RecordData::Srv { port, target } = &srv.data else {
+            continue;
+        };
+        let ip = match find(target, TYPE_A).map(|(_, record)| &record.data) {
+            Some(RecordData::A(ip)) => IpAddr::V4(*ip),
+            _ => *source,
+        };
+        let txt = match find(instance_name, TYPE_TXT).map(|(_, record)| &record.data) {
+            Some(RecordData::Txt(entries)) => entries
+                .iter()
+                .filter(|entry| !entry.is_empty())
+                .map(|entry| match entry.split_once('=') {
+                    Some((key, value)) => (key.to_string(), value.to_string()),
+                    None => (entry.clone(), String::new()),
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        let service = DiscoveredService {
+            instance: instance_label(instance_name).to_string(),
+            host: target.clone(),
+            addr: SocketAddr::new(ip, *port),
+            txt,
+        };
+        if !services.contains(&service) {
+            services.push(service);
+        }
+    }
+    services.sort();
+    Ok(services)
+}
+
+/// `rig-3` for `rig-3._mwctl._tcp.local`.
+fn instance_label(instance_name: &str) -> &str {
+    instance_name
+        .len()
+        .checked_sub(SERVICE_TYPE.len() + 1)
+        .filter(|&end| instance_name[end + 1..].eq_ignore_ascii_case(SERVICE_TYPE))
+        .map_or(instance_name, |end| &instance_name[..end])
+}
+
+/// This machine's host name as a single DNS label.
+fn host_name() -> String {
+    let name = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_default();
+    let label: String = name
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c,
+            false => '-',
+        })
+        .collect();
+    match label.is_empty() {
+        true => "mwctl".to_string(),
+        false => label,
+    }
+}
+
+/// Address of the interface that reaches `peer`.
+fn local_ip(peer: SocketAddr) -> Ipv4Addr {
+    UdpSocket::bind(("0.0.0.0", 0))
+        .and_then(|socket| {
+            socket.connect(peer)?;
+            socket.local_addr()
+        })
+        .ok()
+        .and_then(|addr| match addr.ip() {
+            IpAddr::V4(ip) if !ip.is_unspecified() => Some(ip),
+            _ => None,
+        })
+        .unwrap_or(Ipv4Addr::LOCALHOST)
+}
+
+/// UDP socket on `port` that shares it with Avahi or mDNSResponder, which
+/// the standard library cannot open since it sets no `SO_REUSEADDR`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    use std::os::fd::FromRawFd;
+    use std::os::raw::{c_int, c_void};
+
+    #[cfg(target_os = "linux")]
+    const SOL_SOCKET: c_int = 1;
+    #[cfg(target_os = "linux")]
+    const SO_REUSEADDR: c_int = 2;
+    #[cfg(target_os = "linux")]
+    const SO_REUSEPORT: c_int = 15;
+    #[cfg(target_os = "macos")]
+    const SOL_SOCKET: c_int = 0xffff;
+    #[cfg(target_os = "macos")]
+    const SO_REUSEADDR: c_int = 4;
+    #[cfg(target_os = "macos")]
+    const SO_REUSEPORT: c_int = 0x200;
+    const AF_INET: c_int = 2;
+    const SOCK_DGRAM: c_int = 2;
+
+    #[repr(C)]
+    struct SockAddrIn {
+        #[cfg(target_os = "macos")]
+        sin_len: u8,
+        #[cfg(target_os = "macos")]
+        sin_family: u8,
+        #[cfg(target_os = "linux")]
+        sin_family: u16,
+        sin_port: u16,
+        sin_addr: u32,
+        sin_zero: [u8; 8],
+    }
+
+    extern "C" {
+        fn socket(domain: c_int, kind: c_int, protocol: c_int) -> c_int;
+        fn setsockopt(
+            fd: c_int,
+            level: c_int,
+            name: c_int,
+            value: *const c_void,
+            length: u32,
+        ) -> c_int;
+        fn bind(fd: c_int, addr: *const SockAddrIn, length: u32) -> c_int;
+    }
+
+    // SAFETY: the descriptor is owned by the returned socket from here on,
+    // and the option and address pointers outlive the calls.
+    unsafe {
+        let fd = socket(AF_INET, SOCK_DGRAM, 0);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let socket = UdpSocket::from_raw_fd(fd);
+        let enabled: c_int = 1;
+        for option in [SO_REUSEADDR, SO_REUSEPORT] {
+            let value = &enabled as *const c_int as *const c_void;
+            let length = std::mem::size_of::<c_int>() as u32;
+            if setsockopt(fd, SOL_SOCKET, option, value, length) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        let addr = SockAddrIn {
+            #[cfg(target_os = "macos")]
+            sin_len: std::mem::size_of::<SockAddrIn>() as u8,
+            sin_family: AF_INET as _,
+            sin_port: port.to_be(),
+            sin_addr: 0,
+            sin_zero: [0; 8],
+        };
+        if bind(fd, &addr, std::mem::size_of::<SockAddrIn>() as u32) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(socket)
+    }
+}
+
+/// Elsewhere port 5353 has to be free.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn bind_shared(port: u16) -> io::Result<UdpSocket> {
+    UdpSocket::bind(("0.0.0.0", port))
+}
+
+#[derive(Debug, Clone)]
+struct Question {
+    name: String,
+    kind: u16,
+}
+
+impl Question {
+    fn asks_for(&self, record: &Record) -> bool {
+        (self.kind == record.kind || self.kind == TYPE_ANY)
+            && self.name.eq_ignore_ascii_case(&record.name)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Record {
+    name: String,
+    kind: u16,
+    class: u16,
+    ttl: u32,
+    data: RecordData,
+}
+
+#[derive(Debug, Clone)]
+enum RecordData {
+    A(Ipv4Addr),
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Txt(Vec<String>),
+    Other,
+}
+
+#[derive(Debug, Clone)]
+struct Message {
+    id: u16,
+    response: bool,
+    questions: Vec<Question>,
+    answers: Vec<Record>,
+    additionals: Vec<Record>,
+}
+
+impl Message {
+    fn response(
+        id: u16,
+        questions: Vec<Question>,
+        answers: Vec<Record>,
+        additionals: Vec<Record>,
+    ) -> Message {
+        Message {
+            id,
+            response: true,
+            questions,
+            answers,
+            additionals,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(512);
+        let flags = match self.response {
+            true => FLAGS_RESPONSE,
+            false => 0,
+        };
+        for field in [
+            self.id,
+            flags,
+            self.questions.len() as u16,
+            self.answers.len() as u16,
+            0,
+            self.additionals.len() as u16,
+        ] {
+            bytes.extend_from_slice(&field.to_be_bytes());
+        }
+        for question in &self.questions {
+            encode_name(&mut bytes, &question.name);
+            bytes.extend_from_slice(&question.kind.to_be_bytes());
+            bytes.extend_from_slice(&CLASS_IN.to_be_bytes());
+        }
+        for record in self.answers.iter().chain(&self.additionals) {
+            encode_name(&mut bytes, &record.name);
+            bytes.extend_from_slice(&record.kind.to_be_bytes());
+            bytes.extend_from_slice(&record.class.to_be_bytes());
+            bytes.extend_from_slice(&record.ttl.to_be_bytes());
+            let mut data = Vec::new();
+            match &record.data {
+                RecordData::A(ip) => data.extend_from_slice(&ip.octets()),
+                RecordData::Ptr(name) => encode_name(&mut data, name),
+                RecordData::Srv { port, target } => {
+                    // Priority and weight.
+                    data.extend_from_slice(&[0, 0, 0, 0]);
+                    data.extend_from_slice(&port.to_be_bytes());
+                    encode_name(&mut data, target);
+                }
+                RecordData::Txt(entries) => {
+                    for entry in entries {
+                        let entry = &entry.as_bytes()[..entry.len().min(255)];
+                        data.push(entry.len() as u8);
+                        data.extend_from_slice(entry);
+                    }
+                }
+                RecordData::Other => {}
+            }
+            bytes.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(&data);
+        }
+        bytes
+    }
+
+    fn parse(bytes: &[u8]) -> Result<Message, String> {
+        let mut reader = Reader { bytes, at: 0 };
+        let id = reader.u16()?;
+        let flags = reader.u16()?;
+        // As usize: three counts near u16::MAX would overflow their sum.
+        let mut counts = [0usize; 4];
+        for count in &mut counts {
+            *count = reader.u16()? as usize;
+        }
+        let mut questions = Vec::new();
+        for _ in 0..counts[0] {
+            let name = reader.name()?;
+            let kind = reader.u16()?;
+            reader.u16()?;
+            questions.push(Question { name, kind });
+        }
+        let mut records = Vec::new();
+        let record_count = counts[1] + counts[2] + counts[3];
+        for _ in 0..record_count {
+            // Fewer records than the header counts fails here.
+            let record = reader.record().map_err(|e| {
+                format!("{} after {} of {} records", e, records.len(), record_count)
+            })?;
+            records.push(record);
+        }
+        // Authority records only matter while probing.
+        let additionals = records.split_off(counts[1]);
+        let additionals = additionals.into_iter().skip(counts[2]).collect();
+        Ok(Message {
+            id,
+            response: flags & 0x8000 != 0,
+            questions,
+            answers: records,
+            additionals,
+        })
+    }
+}
+
+/// Dotted name as length-prefixed labels, without compression.
+fn encode_name(bytes: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        let label = &label.as_bytes()[..label.len().min(63)];
+        bytes.push(label.len() as u8);
+        bytes.extend_from_slice(label);
+    }
+    bytes.push(0);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], String> {
+        let end = self.at + count;
+        let taken = self
+            .bytes
+            .get(self.at..end)
+            .ok_or("Truncated mDNS message")?;
+        self.at = end;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// Follows compression pointers; the reader ends up after the name.
+    fn name(&mut self) -> Result<String, String> {
+        let mut labels = Vec::new();
+        let mut at = self.at;
+        let mut resume = None;
+        for _ in 0..128 {
+            let length = *self.bytes.get(at).ok_or("Truncated mDNS name")? as usize;
+            if length & 0xc0 == 0xc0 {
+                let low = *self.bytes.get(at + 1).ok_or("Truncated mDNS name")? as usize;
+                resume.get_or_insert(at + 2);
+                at = (length & 0x3f) << 8 | low;
+            } else if length == 0 {
+                self.at = resume.unwrap_or(at + 1);
+                return Ok(labels.join("."));
+            } else {
+                let label = self
+                    .bytes
+                    .get(at + 1..at + 1 + length)
+                    .ok_or("Truncated mDNS label")?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                at += 1 + length;
+            }
+        }
+        Err("mDNS name has too many labels or a pointer loop".to_string())
+    }
+
+    fn record(&mut self) -> Result<Record, String> {
+        let name = self.name()?;
+        let kind = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let length = self.u16()? as usize;
+        let end = self.at + length;
+        let data = match kind {
+            TYPE_A if length == 4 => {
+                let octets = self.take(4)?;
+                RecordData::A(Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3]))
+            }
+            TYPE_PTR => RecordData::Ptr(self.name()?),
+            TYPE_SRV => {
+                self.take(4)?;
+                let port = self.u16()?;
+                RecordData::Srv {
+                    port,
+                    target: self.name()?,
+                }
+            }
+            TYPE_TXT => {
+                let mut entries = Vec::new();
+                while self.at < end {
+                    let count = self.take(1)?[0] as usize;
+                    entries.push(String::from_utf8_lossy(self.take(count)?).into_owned());
+                }
+                RecordData::Txt(entries)
+            }
+            _ => RecordData::Other,
+        };
+        if self.at > end || end > self.bytes.len() {
+            return Err("mDNS record overruns its length".to_string());
+        }
+        self.at = end;
+        Ok(Record {
+            name,
+            kind,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
@@ -0,0 +1,482 @@
+//! Modbus RTU and TCP server that maps the generator onto registers, so a PLC
+//! can supervise it without a custom driver.
+//!
+//! Register map (zero based addresses, signed values are two's complement):
+//!
+//! | Table            | Address | Value                              | Access |
+//! |------------------|---------|------------------------------------|--------|
+//! | Coil             | 0       | RF enable                          | R/W    |
+//! | Coil             | 1       | This client holds control          | R/W    |
+//! | Discrete input   | 0       | Any status error bit set           | R      |
+//! | Discrete input   | 1       | An interlock is open               | R      |
+//! | Holding register | 0       | Frequency setpoint, 0.1 MHz        | R/W    |
+//! | Holding register | 1       | Power setpoint, 0.01 dBm (signed)  | R/W    |
+//! | Input register   | 0       | Frequency, 0.1 MHz                 | R      |
+//! | Input register   | 1       | Power setpoint, 0.01 dBm (signed)  | R      |
+//! | Input register   | 2       | Forward power, 0.01 dBm (signed)   | R      |
+//! | Input register   | 3       | Reflected power, 0.01 dBm (signed) | R      |
+//! | Input register   | 4       | PA temperature, 0.1 °C (signed)    | R      |
+//! | Input register   | 5       | Status word, low 16 bits           | R      |
+//! | Input register   | 6       | Status word, high 16 bits          | R      |
+//!
+//! Every read queries the board, so the PLC's poll rate sets the bus load.
+//! Writes a client's [`Role`] does not allow are answered with an
+//! illegal function exception, as are writes while another client holds
+//! control under single-writer arbitration
+//! ([`Controller::set_single_writer`]). Setting coil 1 takes control over,
+//! clearing it releases control, and TCP clients release it on disconnecting.
+
+use std::{
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::Arc,
+    thread,
+    time::Duration,
+};
+
+use crate::access::{AccessPolicy, Role};
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::{parse_status, parse_value, parse_values};
+use crate::error::ControllerError;
+use crate::transport::Transport;
+
+pub const COIL_RF_ENABLE: u16 = 0;
+pub const COIL_CONTROL: u16 = 1;
+pub const INPUT_FAULT: u16 = 0;
+pub const INPUT_INTERLOCK_OPEN: u16 = 1;
+pub const REGISTER_FREQUENCY: u16 = 0;
+pub const REGISTER_POWER_SETPOINT: u16 = 1;
+pub const REGISTER_FORWARD_POWER: u16 = 2;
+pub const REGISTER_REFLECTED_POWER: u16 = 3;
+pub const REGISTER_TEMPERATURE: u16 = 4;
+pub const REGISTER_STATUS_LOW: u16 = 5;
+pub const REGISTER_STATUS_HIGH: u16 = 6;
+
+const COIL_COUNT: u16 = 2;
+const DISCRETE_INPUT_COUNT: u16 = 2;
+const HOLDING_REGISTER_COUNT: u16 = 2;
+const INPUT_REGISTER_COUNT: u16 = 7;
+
+const ILLEGAL_FUNCTION: u8 = 0x01;
+const ILLEGAL_DATA_ADDRESS: u8 = 0x02;
+const ILLEGAL_DATA_VALUE: u8 = 0x03;
+const SERVER_DEVICE_FAILURE: u8 = 0x04;
+
+/// Modbus server state shared by the TCP and RTU front ends.
+#[derive(Clone)]
+pub struct ModbusGateway {
+    controller: Controller,
+    unit_id: u8,
+    access: Arc<AccessPolicy>,
+    /// Role of the client being served; RTU masters get the default role.
+    role: Role,
+}
+
+impl ModbusGateway {
+    pub fn new(controller: Controller, unit_id: u8) -> ModbusGateway {
+        ModbusGateway {
+            controller,
+            unit_id,
+            access: Arc::new(AccessPolicy::default()),
+            role: Role::Engineer,
+        }
+    }
+
+    pub fn with_access_policy(mut self, access: AccessPolicy) -> ModbusGateway {
+        self.role = access.default_role;
+        self.access = Arc::new(access);
+        self
+    }
+
+    /// Sends a write on behalf of the current client.
+    fn send_write(&self, command: &Command) -> Result<(), u8> {
+        self.access
+            .authorize(self.role, command)
+            .map_err(|_| ILLEGAL_FUNCTION)?;
+        match self.controller.send(command) {
+            Ok(_) => Ok(()),
+            Err(ControllerError::NotAuthorized(_)) => Err(ILLEGAL_FUNCTION),
+            // The board rejects out of range setpoints with an error reply.
+            Err(ControllerError::Device(_)) => Err(ILLEGAL_DATA_VALUE),
+            Err(_) => Err(SERVER_DEVICE_FAILURE),
+        }
+    }
+
+    /// Answers one request PDU (function code and data) with a response PDU,
+    /// which is an exception response if the request cannot be served.
+    pub fn handle_pdu(&self, pdu: &[u8]) -> Vec<u8> {
+        let function = match pdu.first() {
+            Some(function) => *function,
+            None => return exception(0, ILLEGAL_FUNCTION),
+        };
+        match self.dispatch(function, &pdu[1..]) {
+            Ok(response) => response,
+            Err(code) => exception(function, code),
+        }
+    }
+
+    fn dispatch(&self, function: u8, data: &[u8]) -> Result<Vec<u8>, u8> {
+        match function {
+            0x01 => {
+                let (start, count) = read_range(data, COIL_COUNT)?;
+                let owner = self
+                    .controller
+                    .control_owner()
+                    .map_err(|_| SERVER_DEVICE_FAILURE)?;
+                let coils = [
+                    self.controller.rf_enabled(),
+                    owner.as_deref() == Some(self.controller.actor()),
+                ];
+                Ok(bit_response(function, &coils[start..start + count]))
+            }
+            0x02 => {
+                let (start, count) = read_range(data, DISCRETE_INPUT_COUNT)?;
+                let inputs = self.discrete_inputs().map_err(|_| SERVER_DEVICE_FAILURE)?;
+                Ok(bit_response(function, &inputs[start..start + count]))
+            }
+            0x03 => {
+                let (start, count) = read_range(data, HOLDING_REGISTER_COUNT)?;
+                let registers = self
+                    .holding_registers()
+                    .map_err(|_| SERVER_DEVICE_FAILURE)?;
+                Ok(register_response(
+                    function,
+                    &registers[start..start + count],
+                ))
+            }
+            0x04 => {
+                let (start, count) = read_range(data, INPUT_REGISTER_COUNT)?;
+                let registers = self.input_registers().map_err(|_| SERVER_DEVICE_FAILURE)?;
+                Ok(register_response(
+                    function,
+                    &registers[start..start + count],
+                ))
+            }
+            0x05 => {
+                let (address, value) = (word(data, 0)?, word(data, 2)?);
+                if address == COIL_CONTROL {
+                    self.write_control(value)?;
+                    return Ok([&[function], &data[..4]].concat());
+                }
+                if address != COIL_RF_ENABLE {
+                    return Err(ILLEGAL_DATA_ADDRESS);
+                }
+                let command = match value {
+                    0xFF00 => Command::RfEnable,
+                    0x0000 => Command::RfDisable,
+                    _ => return Err(ILLEGAL_DATA_VALUE),
+                };
+                self.send_write(&command).map_err(|code| match code {
+                    ILLEGAL_DATA_VALUE => SERVER_DEVICE_FAILURE,
+                    code => code,
+                })?;
+                Ok([&[function], &data[..4]].concat())
+            }
+            0x06 => {
+                let (address, value) = (word(data, 0)?, word(data, 2)?);
+                self.write_register(address, value)?;
+                Ok([&[function], &data[..4]].concat())
+            }
+            0x10 => {
+                let (start, count) = (word(data, 0)?, word(data, 2)?);
+                let byte_count = *data.get(4).ok_or(ILLEGAL_DATA_VALUE)? as usize;
+                if count == 0 || byte_count != count as usize * 2 || data.len() < 5 + byte_count {
+                    return Err(ILLEGAL_DATA_VALUE);
+                }
+                if start as u32 + count as u32 > HOLDING_REGISTER_COUNT as u32 {
+                    return Err(ILLEGAL_DATA_ADDRESS);
+                }
+                for i in 0..count {
+                    self.write_register(start + i, word(data, 5 + 2 * i as usize)?)?;
+                }
+                Ok([&[function], &data[..4]].concat())
+            }
+            _ => Err(ILLEGAL_FUNCTION),
+        }
+    }
+
+    fn write_control(&self, value: u16) -> Result<(), u8> {
+        if self.role < Role::Operator {
+            return Err(ILLEGAL_FUNCTION);
+        }
+        match value {
+            0xFF00 => self.controller.take_control(),
+            0x0000 => self.controller.release_control(),
+            _ => return Err(ILLEGAL_DATA_VALUE),
+        }
+        .map_err(|_| SERVER_DEVICE_FAILURE)
+    }
+
+    fn write_register(&self, address: u16, value: u16) -> Result<(), u8> {
+        let command = match address {
+            REGISTER_FREQUENCY => Command::set_frequency(value as f32 / 10.0),
+            REGISTER_POWER_SETPOINT => Command::set_power(value as i16 as f32 / 100.0),
+            _ => return Err(ILLEGAL_DATA_ADDRESS),
+        }
+        .map_err(|_| ILLEGAL_DATA_VALUE)?;
+        self.send_write(&command)
+    }
+
+    fn discrete_inputs(&self) -> Result<[bool; 2], ControllerError> {
+        let status = parse_status(
+            &self
+                .controller
+                .send_cached(&Command::GetStatus { verbose: false })?,
+        )?;
+        let interlock_open = self.controller.open_interlock()?.is_some();
+        Ok([!status.is_ok(), interlock_open])
+    }
+
+    fn holding_registers(&self) -> Result<[u16; 2], ControllerError> {
+        let frequency = parse_value(&self.controller.send_cached(&Command::GetFrequency)?)?;
+        let power = parse_value(&self.controller.send_cached(&Command::GetPowerSetpoint)?)?;
+        Ok([scaled(frequency, 10.0), scaled_signed(power, 100.0)])
+    }
+
+    fn input_registers(&self) -> Result<[u16; 7], ControllerError> {
+        let [frequency, power] = self.holding_registers()?;
+        let measured = parse_values(&self.controller.send_cached(&Command::GetPaPower)?)?;
+        let (forward, reflected) = match measured.as_slice() {
+            [forward, reflected, ..] => (*forward, *reflected),
+            _ => return Err(ControllerError::InvalidResponse(format!("{:?}", measured))),
+        };
+        let temperature = parse_value(&self.controller.send_cached(&Command::GetPaTemperature)?)?;
+        let status = parse_status(
+            &self
+                .controller
+                .send_cached(&Command::GetStatus { verbose: false })?,
+        )?;
+        Ok([
+            frequency,
+            power,
+            scaled_signed(forward, 100.0),
+            scaled_signed(reflected, 100.0),
+            scaled_signed(temperature, 10.0),
+            (status.0 & 0xFFFF) as u16,
+            (status.0 >> 16) as u16,
+        ])
+    }
+
+    /// Answers one Modbus TCP frame (MBAP header and PDU). Returns `None` for
+    /// frames that are malformed or not Modbus.
+    pub fn handle_tcp_frame(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame.len() < 8 || frame[2..4] != [0, 0] {
+            return None;
+        }
+        let response = self.handle_pdu(&frame[7..]);
+        let length = (response.len() + 1) as u16;
+        let mut reply = Vec::with_capacity(7 + response.len());
+        reply.extend_from_slice(&frame[0..4]);
+        reply.extend_from_slice(&length.to_be_bytes());
+        reply.push(frame[6]);
+        reply.extend_from_slice(&response);
+        Some(reply)
+    }
+
+    /// Answers one Modbus RTU frame (address, PDU, CRC). Frames for other
+    /// units or with a bad CRC get no reply, and neither do broadcasts.
+    pub fn handle_rtu_frame(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        if frame.len() < 4 {
+            return None;
+        }
+        let (body, checksum) = frame.split_at(frame.len() - 2);
+        if crc16(body).to_le_bytes() != checksum {
+            return None;
+        }
+        let address = body[0];
+        if address != self.unit_id && address != 0 {
+            return None;
+        }
+        let response = self.handle_pdu(&body[1..]);
+        if address == 0 {
+            return None;
+        }
+        let mut reply = Vec::with_capacity(3 + response.len());
+        reply.push(self.unit_id);
+        reply.extend_from_slice(&response);
+        let crc = crc16(&reply);
+        reply.extend_from_slice(&crc.to_le_bytes());
+        Some(reply)
+    }
+
+    /// Serves Modbus TCP clients on `addr` until `cancel` is cancelled. Each
+    /// client is handled on its own thread.
+    pub fn serve_tcp<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("Modbus TCP: {:?}", e));
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        println!(
+            "Modbus TCP listening on {}",
+            listener.local_addr().map_err(io_error)?
+        );
+
+        while !cancel.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    let mut gateway = self.clone();
+                    gateway.role = self.access.role_for(peer.ip());
+                    gateway.controller = self.controller.with_actor(&format!("modbus {}", peer));
+                    println!("Modbus client connected: {} ({})", peer, gateway.role);
+                    let cancel = cancel.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = gateway.serve_tcp_client(stream, &cancel) {
+                            eprintln!("Modbus client {}: {:?}", peer, e);
+                        }
+                        let _ = gateway.controller.release_control();
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cancel.sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn serve_tcp_client(
+        &self,
+        mut stream: TcpStream,
+        cancel: &CancellationToken,
+    ) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(200)))?;
+        let mut buffer = Vec::new();
+        let mut chunk = [0; 260];
+        while !cancel.is_cancelled() {
+            match stream.read(&mut chunk) {
+                Ok(0) => return Ok(()),
+                Ok(count) => buffer.extend_from_slice(&chunk[..count]),
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) => return Err(e),
+            }
+            // MBAP length covers the unit id and PDU.
+            while buffer.len() >= 6 {
+                let frame_len = 6 + u16::from_be_bytes([buffer[4], buffer[5]]) as usize;
+                if buffer.len() < frame_len {
+                    break;
+                }
+                let frame: Vec<u8> = buffer.drain(..frame_len).collect();
+                match self.handle_tcp_frame(&frame) {
+                    Some(reply) => stream.write_all(&reply)?,
+                    None => return Ok(()),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Serves Modbus RTU requests on `link` until `cancel` is cancelled. A
+    /// frame ends when a read times out, so the link's timeout should be a
+    /// few character times (the 3.5 character gap of the RTU spec).
+    pub fn serve_rtu<T: Transport>(
+        &self,
+        link: &mut T,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let gateway = ModbusGateway {
+            controller: self.controller.with_actor("modbus rtu"),
+            ..self.clone()
+        };
+        let mut frame = Vec::new();
+        let mut chunk = [0; 256];
+        while !cancel.is_cancelled() {
+            match link.read(&mut chunk) {
+                Ok(count) => {
+                    frame.extend_from_slice(&chunk[..count]);
+                    continue;
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(ControllerError::Io(format!("Modbus RTU: {:?}", e))),
+            }
+            if frame.is_empty() {
+                continue;
+            }
+            if let Some(reply) = gateway.handle_rtu_frame(&frame) {
+                link.write_all(&reply)
+                    .and_then(|_| link.flush())
+                    .map_err(|e| ControllerError::Io(format!("Modbus RTU: {:?}", e)))?;
+            }
+            frame.clear();
+        }
+        Ok(())
+    }
+}
+
+/// CRC-16/MODBUS of `bytes`; transmitted low byte first.
+pub fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc = 0xFFFF_u16;
+    for byte in bytes {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xA001
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn exception(function: u8, code: u8) -> Vec<u8> {
+    vec![function | 0x80, code]
+}
+
+fn word(data: &[u8], offset: usize) -> Result<u16, u8> {
+    match data.get(offset..offset + 2) {
+        Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+        None => Err(ILLEGAL_DATA_VALUE),
+    }
+}
+
+/// Validates a `start, count` read request against a table of `size` entries.
+fn read_range(data: &[u8], size: u16) -> Result<(usize, usize), u8> {
+    let (start, count) = (word(data, 0)?, word(data, 2)?);
+    if count == 0 || count > 125 {
+        return Err(ILLEGAL_DATA_VALUE);
+    }
+    if start as u32 + count as u32 > size as u32 {
+        return Err(ILLEGAL_DATA_ADDRESS);
+    }
+    Ok((start as usize, count as usize))
+}
+
+fn bit_response(function: u8, bits: &[bool]) -> Vec<u8> {
+    let mut bytes = vec![0_u8; bits.len().div_ceil(8)];
+    for (i, bit) in bits.iter().enumerate() {
+        if *bit {
+            bytes[i / 8] |= 1 << (i % 8);
+        }
+    }
+    [vec![function, bytes.len() as u8], bytes].concat()
+}
+
+fn register_response(function: u8, registers: &[u16]) -> Vec<u8> {
+    let mut response = vec![function, (registers.len() * 2) as u8];
+    for register in registers {
+        response.extend_from_slice(&register.to_be_bytes());
+    }
+    response
+}
+
+fn scaled(value: f32, scale: f32) -> u16 {
+    (value * scale).round().clamp(0.0, u16::MAX as f32) as u16
+}
+
+fn scaled_signed(value: f32, scale: f32) -> u16 {
+    (value * scale)
+        .round()
+        .clamp(i16::MIN as f32, i16::MAX as f32) as i16 as u16
+}
@@ -0,0 +1,232 @@
+//! NanoVNA driver for measuring the applicator's S11 before a run.
+//!
+//! The NanoVNA enumerates as a USB CDC serial port and exposes a text shell
+//! (`ch> ` prompt). Measurements use the `scan` command, which runs a single
+//! sweep and prints the requested traces; it needs a 2020 or later firmware
+//! (edy555, hugen or DiSlord builds).
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::controller::Controller;
+use crate::error::ControllerError;
+use crate::sweep::SweepSegment;
+use crate::transport::Transport;
+
+const PROMPT: &str = "ch> ";
+/// Points per `scan`; larger sweeps are split into several scans.
+const MAX_POINTS_PER_SCAN: usize = 101;
+/// `scan` output mask: frequency and S11 (channel 0).
+const OUTPUT_FREQUENCY_S11: u32 = 0b11;
+/// Session trace kind of recorded S11 points.
+pub const S11_RECORD: &str = "S11";
+
+/// One S11 reading as a complex reflection coefficient.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct S11Point {
+    pub frequency_mhz: f32,
+    pub re: f32,
+    pub im: f32,
+}
+
+impl S11Point {
+    /// Return loss as S11 in dB (negative for a passive load).
+    pub fn s11_db(&self) -> f32 {
+        10.0 * (self.re * self.re + self.im * self.im).log10()
+    }
+}
+
+/// S11 of the applicator across a frequency range.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct S11Measurement {
+    pub points: Vec<S11Point>,
+}
+
+impl S11Measurement {
+    /// Best matched point, i.e. lowest S11: the suggested starting frequency.
+    pub fn best_match(&self) -> Option<&S11Point> {
+        self.points
+            .iter()
+            .min_by(|a, b| a.s11_db().total_cmp(&b.s11_db()))
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("frequency_mhz,re,im,s11_db\n");
+        for point in &self.points {
+            csv.push_str(&format!(
+                "{:.3},{:.6},{:.6},{:.2}\n",
+                point.frequency_mhz,
+                point.re,
+                point.im,
+                point.s11_db()
+            ));
+        }
+        csv
+    }
+
+    /// Touchstone (`.s1p`) file of the points with the complex S11, for
+    /// comparison with other VNA measurements.
+    pub fn to_touchstone(&self) -> String {
+        let mut s1p = String::from("! NanoVNA S11\n# MHZ S RI R 50\n");
+        for point in &self.points {
+            s1p.push_str(&format!(
+                "{:.6} {:.6} {:.6}\n",
+                point.frequency_mhz, point.re, point.im
+            ));
+        }
+        s1p
+    }
+
+    /// Writes the points to the controller's session trace as `S11` records
+    /// (`frequency_mhz,re,im`), so the measurement is kept with the run.
+    pub fn record(&self, controller: &Controller) {
+        for point in &self.points {
+            controller.trace(
+                S11_RECORD,
+                &format!("{:.3},{:.6},{:.6}", point.frequency_mhz, point.re, point.im),
+            );
+        }
+    }
+
+    /// Parses the text of an `S11` session record.
+    pub fn parse_record(text: &str) -> Result<S11Point, String> {
+        let fields: Vec<f32> = text
+            .split(',')
+            .map(|field| field.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid S11 record: {}", text))?;
+        match fields.as_slice() {
+            [frequency_mhz, re, im] => Ok(S11Point {
+                frequency_mhz: *frequency_mhz,
+                re: *re,
+                im: *im,
+            }),
+            _ => Err(format!("Invalid S11 record: {}", text)),
+        }
+    }
+}
+
+/// NanoVNA attached over its USB serial shell.
+pub struct NanoVna<T: Transport> {
+    link: T,
+    /// Longest a single command (including a scan) may take.
+    pub reply_timeout: Duration,
+}
+
+#[cfg(feature = "serial")]
+impl NanoVna<Box<dyn serialport::SerialPort>> {
+    pub fn open(
+        port_name: &str,
+    ) -> Result<NanoVna<Box<dyn serialport::SerialPort>>, ControllerError> {
+        let port = serialport::new(port_name, 115_200)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(|e| ControllerError::Connection(format!("NanoVNA {}: {}", port_name, e)))?;
+        NanoVna::new(port)
+    }
+}
+
+impl<T: Transport> NanoVna<T> {
+    /// Takes over the shell on `link`, discarding any pending output.
+    pub fn new(link: T) -> Result<NanoVna<T>, ControllerError> {
+        let mut vna = NanoVna {
+            link,
+            reply_timeout: Duration::from_secs(10),
+        };
+        vna.command("")?;
+        Ok(vna)
+    }
+
+    /// Runs a shell command and returns its output lines, without the echo and prompt.
+    pub fn command(&mut self, command: &str) -> Result<Vec<String>, ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("NanoVNA: {:?}", e));
+        self.link
+            .write_all(format!("{}\r", command).as_bytes())
+            .map_err(io_error)?;
+        self.link.flush().map_err(io_error)?;
+
+        let deadline = Instant::now() + self.reply_timeout;
+        let mut output = Vec::new();
+        let mut buffer = [0; 1024];
+        while !output.ends_with(PROMPT.as_bytes()) {
+            if Instant::now() >= deadline {
+                return Err(ControllerError::Timeout);
+            }
+            match self.link.read(&mut buffer) {
+                Ok(count) => output.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        let output = String::from_utf8_lossy(&output[..output.len() - PROMPT.len()]).into_owned();
+        Ok(output
+            .lines()
+            .skip(1)
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty())
+            .collect())
+    }
+
+    /// Measures S11 at the frequencies of `segment`.
+    pub fn measure_s11(
+        &mut self,
+        segment: &SweepSegment,
+    ) -> Result<S11Measurement, ControllerError> {
+        let frequencies = segment.frequencies();
+        let mut measurement = S11Measurement::default();
+        for chunk in frequencies.chunks(MAX_POINTS_PER_SCAN) {
+            let hz = |mhz: f32| (mhz as f64 * 1e6).round() as u64;
+            // `scan` needs at least two points.
+            let (start, stop) = (chunk[0], chunk[chunk.len() - 1]);
+            let count = chunk.len().max(2);
+            let lines = self.command(&format!(
+                "scan {} {} {} {}",
+                hz(start),
+                hz(stop),
+                count,
+                OUTPUT_FREQUENCY_S11
+            ))?;
+            for line in lines.iter().take(chunk.len()) {
+                measurement.points.push(parse_scan_line(line)?);
+            }
+        }
+        Ok(measurement)
+    }
+}
+
+/// Parses a `scan` output line: `<frequency_hz> <re> <im>`.
+fn parse_scan_line(line: &str) -> Result<S11Point, ControllerError> {
+    let fields: Vec<f64> = line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<_, _>>()
+        .map_err(|_| ControllerError::InvalidResponse(format!("NanoVNA: {}", line)))?;
+    match fields.as_slice() {
+        [frequency_hz, re, im] => Ok(S11Point {
+            frequency_mhz: (frequency_hz / 1e6) as f32,
+            re: *re as f32,
+            im: *im as f32,
+        }),
+        _ => Err(ControllerError::InvalidResponse(format!(
+            "NanoVNA: {}",
+            line
+        ))),
+    }
+}
+
+/// Measures the applicator with RF off and returns the measurement.
+///
+/// The generator is put in its safe state first so the VNA does not see the
+/// PA's output, and the points are recorded to the controller's session trace.
+pub fn characterize<T: Transport>(
+    controller: &Controller,
+    vna: &mut NanoVna<T>,
+    segment: &SweepSegment,
+) -> Result<S11Measurement, ControllerError> {
+    controller.safe_state()?;
+    let measurement = vna.measure_s11(segment)?;
+    measurement.record(controller);
+    Ok(measurement)
+}
@@ -0,0 +1,71 @@
+use std::process::{Command, Stdio};
+use std::sync::Arc;
+
+use crate::events::{ControllerEvent, EventListener};
+
+/// Shows a desktop notification using the platform's notification tool
+/// (`notify-send` on Linux, `osascript` on macOS, a PowerShell balloon on Windows).
+///
+/// The tool is started in the background; failures are reported on stderr and
+/// otherwise ignored so a missing notification daemon never affects control.
+pub fn desktop_notification(title: &str, body: &str) {
+    let mut command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification \"{}\" with title \"{}\"",
+            escape_quotes(body),
+            escape_quotes(title)
+        );
+        let mut command = Command::new("osascript");
+        command.arg("-e").arg(script);
+        command
+    } else if cfg!(target_os = "windows") {
+        let script = format!(
+            "Add-Type -AssemblyName System.Windows.Forms; \
+             $n = New-Object System.Windows.Forms.NotifyIcon; \
+             $n.Icon = [System.Drawing.SystemIcons]::Warning; $n.Visible = $true; \
+             $n.ShowBalloonTip(10000, '{}', '{}', 'Warning'); Start-Sleep -Seconds 10; $n.Dispose()",
+            title.replace('\'', "''"),
+            body.replace('\'', "''")
+        );
+        let mut command = Command::new("powershell");
+        command.arg("-NoProfile").arg("-Command").arg(script);
+        command
+    } else {
+        let mut command = Command::new("notify-send");
+        command.arg("--urgency=critical").arg(title).arg(body);
+        command
+    };
+
+    let spawned = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(e) = spawned {
+        eprintln!("Failed to show desktop notification: {:?}", e);
+    }
+}
+
+fn escape_quotes(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Event listener raising a desktop notification for faults, interlock trips
+/// and connection loss.
+pub fn desktop_notifier() -> EventListener {
+    Arc::new(|event: &ControllerEvent| match event {
+        ControllerEvent::DeviceFault(_)
+        | ControllerEvent::InterlockTripped(_)
+        | ControllerEvent::ConnectionLost(_)
+        | ControllerEvent::AlarmRaised { .. } => {
+            desktop_notification("Microwave controller", &event.to_string())
+        }
+        ControllerEvent::CommandTimeout(_)
+        | ControllerEvent::ErrorsCleared(_)
+        | ControllerEvent::LinkDegraded(_)
+        | ControllerEvent::LinkRestored
+        | ControllerEvent::PortReopened(_)
+        | ControllerEvent::PowerDerated { .. }
+        | ControllerEvent::ControlChanged { .. } => {}
+    })
+}
@@ -0,0 +1,1426 @@
+//! Minimal OPC UA server exposing the generator as an address space.
+//!
+//! Implements the UA TCP binary transport with SecurityPolicy `None`,
+//! anonymous sessions and user name sessions whose password is one of the
+//! access policy's API tokens, and the services a SCADA client needs to
+//! browse, read and write nodes: GetEndpoints, CreateSession, ActivateSession,
+//! CloseSession, Browse, Read and Write. Other services (subscriptions
+//! included) are answered with `BadServiceUnsupported`, so clients poll.
+//!
+//! The generator appears as `Objects/Generator` (namespace 1,
+//! [`NAMESPACE_URI`]) with one variable per value, e.g.
+//! `ns=1;s=Generator.Frequency`. Reads query the board like the Modbus
+//! gateway; writes to setpoint variables send the matching set command,
+//! and fail with `BadUserAccessDenied` if the client's [`Role`] does not
+//! allow it, or while another client holds control under single-writer
+//! arbitration. Writing `Generator.Control` true takes control over, false
+//! releases it; a client releases control when it disconnects.
+//!
+//! Anonymous sessions get the role of the client's address, token sessions
+//! the role of the token. Without SecurityPolicy encryption the token
+//! crosses the network in the clear.
+
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::access::{AccessPolicy, Role};
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::{parse_status, parse_value, parse_values};
+use crate::error::ControllerError;
+use crate::events::ControllerEvent;
+
+pub const NAMESPACE_URI: &str = "urn:microwave_controller";
+const APPLICATION_URI: &str = "urn:microwave_controller:mwctl";
+const SECURITY_POLICY_NONE: &str = "http://opcfoundation.org/UA/SecurityPolicy#None";
+const TRANSPORT_PROFILE: &str = "http://opcfoundation.org/UA-Profile/Transport/uatcp-uasc-uabinary";
+
+const MAX_MESSAGE_SIZE: u32 = 1 << 16;
+const SESSION_TIMEOUT_MS: f64 = 60_000.0;
+const TOKEN_LIFETIME_MS: u32 = 3_600_000;
+
+const GOOD: u32 = 0;
+const BAD_COMMUNICATION_ERROR: u32 = 0x8005_0000;
+const BAD_DECODING_ERROR: u32 = 0x8007_0000;
+const BAD_SERVICE_UNSUPPORTED: u32 = 0x800B_0000;
+const BAD_SESSION_ID_INVALID: u32 = 0x8025_0000;
+const BAD_NODE_ID_UNKNOWN: u32 = 0x8034_0000;
+const BAD_ATTRIBUTE_ID_INVALID: u32 = 0x8035_0000;
+const BAD_NOT_WRITABLE: u32 = 0x803B_0000;
+const BAD_OUT_OF_RANGE: u32 = 0x803C_0000;
+const BAD_SECURITY_MODE_REJECTED: u32 = 0x8054_0000;
+const BAD_TYPE_MISMATCH: u32 = 0x8074_0000;
+const BAD_INVALID_STATE: u32 = 0x80AF_0000;
+const BAD_TCP_MESSAGE_TYPE_INVALID: u32 = 0x807E_0000;
+const BAD_USER_ACCESS_DENIED: u32 = 0x801F_0000;
+const BAD_IDENTITY_TOKEN_INVALID: u32 = 0x8020_0000;
+const BAD_IDENTITY_TOKEN_REJECTED: u32 = 0x8021_0000;
+
+// Binary encoding ids of the service messages (namespace 0).
+const SERVICE_FAULT: u32 = 397;
+const GET_ENDPOINTS_REQUEST: u32 = 428;
+const GET_ENDPOINTS_RESPONSE: u32 = 431;
+const OPEN_SECURE_CHANNEL_REQUEST: u32 = 446;
+const OPEN_SECURE_CHANNEL_RESPONSE: u32 = 449;
+const CREATE_SESSION_REQUEST: u32 = 461;
+const CREATE_SESSION_RESPONSE: u32 = 464;
+const ACTIVATE_SESSION_REQUEST: u32 = 467;
+const ACTIVATE_SESSION_RESPONSE: u32 = 470;
+const CLOSE_SESSION_REQUEST: u32 = 473;
+const CLOSE_SESSION_RESPONSE: u32 = 476;
+const BROWSE_REQUEST: u32 = 527;
+const BROWSE_RESPONSE: u32 = 530;
+const READ_REQUEST: u32 = 631;
+const READ_RESPONSE: u32 = 634;
+const WRITE_REQUEST: u32 = 673;
+const WRITE_RESPONSE: u32 = 676;
+const ANONYMOUS_IDENTITY_TOKEN: u32 = 321;
+const USER_NAME_IDENTITY_TOKEN: u32 = 324;
+
+// Reference and type ids (namespace 0).
+const ORGANIZES: u32 = 35;
+const HAS_TYPE_DEFINITION: u32 = 40;
+const HAS_PROPERTY: u32 = 46;
+const HAS_COMPONENT: u32 = 47;
+const BASE_OBJECT_TYPE: u32 = 58;
+const FOLDER_TYPE: u32 = 61;
+const BASE_DATA_VARIABLE_TYPE: u32 = 63;
+const PROPERTY_TYPE: u32 = 68;
+const SERVER_TYPE: u32 = 2004;
+
+const BOOLEAN: u32 = 1;
+const UINT32: u32 = 7;
+const FLOAT: u32 = 10;
+const STRING: u32 = 12;
+
+const ATTRIBUTE_NODE_ID: u32 = 1;
+const ATTRIBUTE_NODE_CLASS: u32 = 2;
+const ATTRIBUTE_BROWSE_NAME: u32 = 3;
+const ATTRIBUTE_DISPLAY_NAME: u32 = 4;
+const ATTRIBUTE_DESCRIPTION: u32 = 5;
+const ATTRIBUTE_WRITE_MASK: u32 = 6;
+const ATTRIBUTE_USER_WRITE_MASK: u32 = 7;
+const ATTRIBUTE_EVENT_NOTIFIER: u32 = 12;
+const ATTRIBUTE_VALUE: u32 = 13;
+const ATTRIBUTE_DATA_TYPE: u32 = 14;
+const ATTRIBUTE_VALUE_RANK: u32 = 15;
+const ATTRIBUTE_ARRAY_DIMENSIONS: u32 = 16;
+const ATTRIBUTE_ACCESS_LEVEL: u32 = 17;
+const ATTRIBUTE_USER_ACCESS_LEVEL: u32 = 18;
+const ATTRIBUTE_MINIMUM_SAMPLING_INTERVAL: u32 = 19;
+const ATTRIBUTE_HISTORIZING: u32 = 20;
+
+/// OPC UA node identifier. GUID and opaque ids are kept only to be echoed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NodeId {
+    Numeric(u16, u32),
+    String(u16, String),
+    Opaque(Vec<u8>),
+}
+
+impl NodeId {
+    const NULL: NodeId = NodeId::Numeric(0, 0);
+
+    fn generator(name: &str) -> NodeId {
+        NodeId::String(1, format!("Generator.{}", name))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Variant {
+    Empty,
+    Boolean(bool),
+    Byte(u8),
+    Int32(i32),
+    UInt32(u32),
+    Float(f32),
+    Double(f64),
+    String(String),
+    StringArray(Vec<String>),
+    NodeId(NodeId),
+    QualifiedName(u16, String),
+    LocalizedText(String),
+}
+
+impl Variant {
+    fn as_f32(&self) -> Option<f32> {
+        match self {
+            Variant::Float(value) => Some(*value),
+            Variant::Double(value) => Some(*value as f32),
+            Variant::Int32(value) => Some(*value as f32),
+            Variant::UInt32(value) => Some(*value as f32),
+            Variant::Byte(value) => Some(*value as f32),
+            _ => None,
+        }
+    }
+}
+
+/// Values of the generator object's variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Signal {
+    Frequency,
+    PowerSetpoint,
+    RfEnabled,
+    ForwardPower,
+    ReflectedPower,
+    Temperature,
+    Status,
+    StatusText,
+    InterlockOpen,
+    LastAlarm,
+    AlarmCount,
+    Control,
+}
+
+impl Signal {
+    const ALL: [Signal; 12] = [
+        Signal::Frequency,
+        Signal::PowerSetpoint,
+        Signal::RfEnabled,
+        Signal::ForwardPower,
+        Signal::ReflectedPower,
+        Signal::Temperature,
+        Signal::Status,
+        Signal::StatusText,
+        Signal::InterlockOpen,
+        Signal::LastAlarm,
+        Signal::AlarmCount,
+        Signal::Control,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Signal::Frequency => "Frequency",
+            Signal::PowerSetpoint => "PowerSetpoint",
+            Signal::RfEnabled => "RfEnabled",
+            Signal::ForwardPower => "ForwardPower",
+            Signal::ReflectedPower => "ReflectedPower",
+            Signal::Temperature => "Temperature",
+            Signal::Status => "Status",
+            Signal::StatusText => "StatusText",
+            Signal::InterlockOpen => "InterlockOpen",
+            Signal::LastAlarm => "LastAlarm",
+            Signal::AlarmCount => "AlarmCount",
+            Signal::Control => "Control",
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        match self {
+            Signal::Frequency => "Frequency setpoint in MHz",
+            Signal::PowerSetpoint => "Power setpoint in dBm",
+            Signal::RfEnabled => "RF output enabled",
+            Signal::ForwardPower => "Measured forward power in dBm",
+            Signal::ReflectedPower => "Measured reflected power in dBm",
+            Signal::Temperature => "PA temperature in degrees Celsius",
+            Signal::Status => "Status error word",
+            Signal::StatusText => "Decoded status error word",
+            Signal::InterlockOpen => "An interlock is open",
+            Signal::LastAlarm => "Most recent alarm or fault",
+            Signal::AlarmCount => "Alarms and faults since start",
+            Signal::Control => "This client holds single-writer control",
+        }
+    }
+
+    fn data_type(&self) -> u32 {
+        match self {
+            Signal::RfEnabled | Signal::InterlockOpen | Signal::Control => BOOLEAN,
+            Signal::Status | Signal::AlarmCount => UINT32,
+            Signal::StatusText | Signal::LastAlarm => STRING,
+            _ => FLOAT,
+        }
+    }
+
+    fn writable(&self) -> bool {
+        matches!(
+            self,
+            Signal::Frequency | Signal::PowerSetpoint | Signal::RfEnabled | Signal::Control
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum NodeKind {
+    Folder,
+    Object(u32),
+    ServerProperty(Variant),
+    Signal(Signal),
+}
+
+struct Node {
+    id: NodeId,
+    browse_name: (u16, String),
+    description: &'static str,
+    kind: NodeKind,
+    parent: Option<(NodeId, u32)>,
+}
+
+impl Node {
+    fn is_variable(&self) -> bool {
+        matches!(self.kind, NodeKind::ServerProperty(_) | NodeKind::Signal(_))
+    }
+
+    fn type_definition(&self) -> u32 {
+        match &self.kind {
+            NodeKind::Folder => FOLDER_TYPE,
+            NodeKind::Object(type_id) => *type_id,
+            NodeKind::ServerProperty(_) => PROPERTY_TYPE,
+            NodeKind::Signal(_) => BASE_DATA_VARIABLE_TYPE,
+        }
+    }
+}
+
+fn address_space() -> Vec<Node> {
+    let node = |id: NodeId, ns: u16, name: &str, description, kind, parent| Node {
+        id,
+        browse_name: (ns, name.to_string()),
+        description,
+        kind,
+        parent,
+    };
+    let objects = NodeId::Numeric(0, 85);
+    let server = NodeId::Numeric(0, 2253);
+    let generator = NodeId::String(1, "Generator".to_string());
+    let mut nodes = vec![
+        node(
+            NodeId::Numeric(0, 84),
+            0,
+            "Root",
+            "",
+            NodeKind::Folder,
+            None,
+        ),
+        node(
+            objects.clone(),
+            0,
+            "Objects",
+            "",
+            NodeKind::Folder,
+            Some((NodeId::Numeric(0, 84), ORGANIZES)),
+        ),
+        node(
+            server.clone(),
+            0,
+            "Server",
+            "",
+            NodeKind::Object(SERVER_TYPE),
+            Some((objects.clone(), ORGANIZES)),
+        ),
+        node(
+            NodeId::Numeric(0, 2254),
+            0,
+            "ServerArray",
+            "",
+            NodeKind::ServerProperty(Variant::StringArray(vec![APPLICATION_URI.to_string()])),
+            Some((server.clone(), HAS_PROPERTY)),
+        ),
+        node(
+            NodeId::Numeric(0, 2255),
+            0,
+            "NamespaceArray",
+            "",
+            NodeKind::ServerProperty(Variant::StringArray(vec![
+                "http://opcfoundation.org/UA/".to_string(),
+                NAMESPACE_URI.to_string(),
+            ])),
+            Some((server, HAS_PROPERTY)),
+        ),
+        node(
+            generator.clone(),
+            1,
+            "Generator",
+            "ISC signal generator board",
+            NodeKind::Object(BASE_OBJECT_TYPE),
+            Some((objects, ORGANIZES)),
+        ),
+    ];
+    for signal in Signal::ALL {
+        nodes.push(node(
+            NodeId::generator(signal.name()),
+            1,
+            signal.name(),
+            signal.description(),
+            NodeKind::Signal(signal),
+            Some((generator.clone(), HAS_COMPONENT)),
+        ));
+    }
+    nodes
+}
+
+#[derive(Default)]
+struct AlarmSummary {
+    last: String,
+    count: u32,
+}
+
+/// OPC UA front end for a [`Controller`].
+#[derive(Clone)]
+pub struct OpcUaServer {
+    controller: Controller,
+    nodes: Arc<Vec<Node>>,
+    alarms: Arc<Mutex<AlarmSummary>>,
+    access: Arc<AccessPolicy>,
+}
+
+impl OpcUaServer {
+    /// Creates the server and subscribes to the controller's events to
+    /// track alarms and device faults.
+    pub fn new(controller: Controller) -> Result<OpcUaServer, ControllerError> {
+        let alarms = Arc::new(Mutex::new(AlarmSummary::default()));
+        let summary = alarms.clone();
+        controller.subscribe(Arc::new(move |event: &ControllerEvent| {
+            let relevant = matches!(
+                event,
+                ControllerEvent::AlarmRaised { .. }
+                    | ControllerEvent::DeviceFault(_)
+                    | ControllerEvent::InterlockTripped(_)
+            );
+            if let (true, Ok(mut summary)) = (relevant, summary.lock()) {
+                summary.last = event.to_string();
+                summary.count += 1;
+            }
+        }))?;
+        Ok(OpcUaServer {
+            controller,
+            nodes: Arc::new(address_space()),
+            alarms,
+            access: Arc::new(AccessPolicy::default()),
+        })
+    }
+
+    pub fn with_access_policy(mut self, access: AccessPolicy) -> OpcUaServer {
+        self.access = Arc::new(access);
+        self
+    }
+
+    /// Serves OPC UA clients on `addr` (port 4840 by convention) until
+    /// `cancel` is cancelled. Each client is handled on its own thread.
+    pub fn serve<A: ToSocketAddrs>(
+        &self,
+        addr: A,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("OPC UA: {:?}", e));
+        let listener = TcpListener::bind(addr).map_err(io_error)?;
+        listener.set_nonblocking(true).map_err(io_error)?;
+        let local_addr = listener.local_addr().map_err(io_error)?;
+        println!("OPC UA listening on opc.tcp://{}", local_addr);
+
+        let mut channel_id = 0;
+        while !cancel.is_cancelled() {
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    channel_id += 1;
+                    let role = self.access.role_for(peer.ip());
+                    println!("OPC UA client connected: {} ({})", peer, role);
+                    let mut server = self.clone();
+                    server.controller = self.controller.with_actor(&format!("opcua {}", peer));
+                    let mut connection = Connection {
+                        server,
+                        peer,
+                        role,
+                        endpoint_url: format!("opc.tcp://{}", local_addr),
+                        channel_id,
+                        token_id: 1,
+                        sequence_number: 0,
+                        session: None,
+                    };
+                    let cancel = cancel.clone();
+                    thread::spawn(move || {
+                        if let Err(e) = connection.run(stream, &cancel) {
+                            eprintln!("OPC UA client {}: {:?}", peer, e);
+                        }
+                        let _ = connection.server.controller.release_control();
+                    });
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    cancel.sleep(Duration::from_millis(100));
+                }
+                Err(e) => return Err(io_error(e)),
+            }
+        }
+        Ok(())
+    }
+
+    fn node(&self, id: &NodeId) -> Option<&Node> {
+        self.nodes.iter().find(|node| &node.id == id)
+    }
+
+    fn read_signal(&self, signal: Signal) -> Result<Variant, ControllerError> {
+        let controller = &self.controller;
+        let measured = || parse_values(&controller.send_cached(&Command::GetPaPower)?);
+        let status =
+            || parse_status(&controller.send_cached(&Command::GetStatus { verbose: false })?);
+        let value = match signal {
+            Signal::Frequency => Variant::Float(parse_value(
+                &controller.send_cached(&Command::GetFrequency)?,
+            )?),
+            Signal::PowerSetpoint => Variant::Float(parse_value(
+                &controller.send_cached(&Command::GetPowerSetpoint)?,
+            )?),
+            Signal::RfEnabled => Variant::Boolean(controller.rf_enabled()),
+            Signal::ForwardPower => {
+                Variant::Float(measured()?.first().copied().unwrap_or_default())
+            }
+            Signal::ReflectedPower => {
+                Variant::Float(measured()?.get(1).copied().unwrap_or_default())
+            }
+            Signal::Temperature => Variant::Float(parse_value(
+                &controller.send_cached(&Command::GetPaTemperature)?,
+            )?),
+            Signal::Status => Variant::UInt32(status()?.0),
+            Signal::StatusText => Variant::String(status()?.to_string()),
+            Signal::InterlockOpen => Variant::Boolean(controller.open_interlock()?.is_some()),
+            Signal::LastAlarm => Variant::String(
+                self.alarms
+                    .lock()
+                    .map(|alarms| alarms.last.clone())
+                    .map_err(|_| ControllerError::Poisoned)?,
+            ),
+            Signal::AlarmCount => Variant::UInt32(
+                self.alarms
+                    .lock()
+                    .map(|alarms| alarms.count)
+                    .map_err(|_| ControllerError::Poisoned)?,
+            ),
+            Signal::Control => {
+                Variant::Boolean(controller.control_owner()?.as_deref() == Some(controller.actor()))
+            }
+        };
+        Ok(value)
+    }
+
+    fn write_signal(&self, signal: Signal, value: &Variant, role: Role) -> u32 {
+        if let Signal::Control = signal {
+            return self.write_control(value, role);
+        }
+        let command = match (signal, value) {
+            (Signal::RfEnabled, Variant::Boolean(true)) => Command::RfEnable,
+            (Signal::RfEnabled, Variant::Boolean(false)) => Command::RfDisable,
+            (Signal::Frequency, value) => match value.as_f32() {
+                Some(frequency) => match Command::set_frequency(frequency) {
+                    Ok(command) => command,
+                    Err(_) => return BAD_OUT_OF_RANGE,
+                },
+                None => return BAD_TYPE_MISMATCH,
+            },
+            (Signal::PowerSetpoint, value) => match value.as_f32() {
+                Some(power) => match Command::set_power(power) {
+                    Ok(command) => command,
+                    Err(_) => return BAD_OUT_OF_RANGE,
+                },
+                None => return BAD_TYPE_MISMATCH,
+            },
+            (Signal::RfEnabled, _) => return BAD_TYPE_MISMATCH,
+            _ => return BAD_NOT_WRITABLE,
+        };
+        if self.access.authorize(role, &command).is_err() {
+            return BAD_USER_ACCESS_DENIED;
+        }
+        match self.controller.send(&command) {
+            Ok(_) => GOOD,
+            Err(ControllerError::NotAuthorized(_)) => BAD_USER_ACCESS_DENIED,
+            Err(ControllerError::Device(_)) => BAD_OUT_OF_RANGE,
+            Err(ControllerError::InterlockOpen(_) | ControllerError::InvalidState(_)) => {
+                BAD_INVALID_STATE
+            }
+            Err(_) => BAD_COMMUNICATION_ERROR,
+        }
+    }
+
+    /// Takes control over (`true`) or releases it (`false`).
+    fn write_control(&self, value: &Variant, role: Role) -> u32 {
+        if role < Role::Operator {
+            return BAD_USER_ACCESS_DENIED;
+        }
+        let result = match value {
+            Variant::Boolean(true) => self.controller.take_control(),
+            Variant::Boolean(false) => self.controller.release_control(),
+            _ => return BAD_TYPE_MISMATCH,
+        };
+        match result {
+            Ok(()) => GOOD,
+            Err(_) => BAD_COMMUNICATION_ERROR,
+        }
+    }
+
+    fn read_attribute(&self, id: &NodeId, attribute: u32) -> Result<Variant, u32> {
+        let node = self.node(id).ok_or(BAD_NODE_ID_UNKNOWN)?;
+        let (ns, name) = &node.browse_name;
+        let value = match attribute {
+            ATTRIBUTE_NODE_ID => Variant::NodeId(node.id.clone()),
+            ATTRIBUTE_NODE_CLASS => Variant::Int32(if node.is_variable() { 2 } else { 1 }),
+            ATTRIBUTE_BROWSE_NAME => Variant::QualifiedName(*ns, name.clone()),
+            ATTRIBUTE_DISPLAY_NAME => Variant::LocalizedText(name.clone()),
+            ATTRIBUTE_DESCRIPTION => Variant::LocalizedText(node.description.to_string()),
+            ATTRIBUTE_WRITE_MASK | ATTRIBUTE_USER_WRITE_MASK => Variant::UInt32(0),
+            ATTRIBUTE_EVENT_NOTIFIER if !node.is_variable() => Variant::Byte(0),
+            ATTRIBUTE_VALUE => match &node.kind {
+                NodeKind::ServerProperty(value) => value.clone(),
+                NodeKind::Signal(signal) => self
+                    .read_signal(*signal)
+                    .map_err(|_| BAD_COMMUNICATION_ERROR)?,
+                _ => return Err(BAD_ATTRIBUTE_ID_INVALID),
+            },
+            ATTRIBUTE_DATA_TYPE => match &node.kind {
+                NodeKind::ServerProperty(_) => Variant::NodeId(NodeId::Numeric(0, STRING)),
+                NodeKind::Signal(signal) => Variant::NodeId(NodeId::Numeric(0, signal.data_type())),
+                _ => return Err(BAD_ATTRIBUTE_ID_INVALID),
+            },
+            ATTRIBUTE_VALUE_RANK => match &node.kind {
+                NodeKind::ServerProperty(_) => Variant::Int32(1),
+                NodeKind::Signal(_) => Variant::Int32(-1),
+                _ => return Err(BAD_ATTRIBUTE_ID_INVALID),
+            },
+            ATTRIBUTE_ARRAY_DIMENSIONS if node.is_variable() => Variant::Empty,
+            ATTRIBUTE_ACCESS_LEVEL | ATTRIBUTE_USER_ACCESS_LEVEL => match &node.kind {
+                NodeKind::Signal(signal) if signal.writable() => Variant::Byte(3),
+                NodeKind::Signal(_) | NodeKind::ServerProperty(_) => Variant::Byte(1),
+                _ => return Err(BAD_ATTRIBUTE_ID_INVALID),
+            },
+            ATTRIBUTE_MINIMUM_SAMPLING_INTERVAL if node.is_variable() => Variant::Double(0.0),
+            ATTRIBUTE_HISTORIZING if node.is_variable() => Variant::Boolean(false),
+            _ => return Err(BAD_ATTRIBUTE_ID_INVALID),
+        };
+        Ok(value)
+    }
+}
+
+struct Connection {
+    server: OpcUaServer,
+    peer: SocketAddr,
+    role: Role,
+    endpoint_url: String,
+    channel_id: u32,
+    token_id: u32,
+    sequence_number: u32,
+    /// Authentication token of the open session and whether it is activated.
+    session: Option<(u32, bool)>,
+}
+
+impl Connection {
+    fn run(&mut self, mut stream: TcpStream, cancel: &CancellationToken) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(Duration::from_millis(500)))?;
+        let mut said_hello = false;
+        while !cancel.is_cancelled() {
+            let mut header = [0; 8];
+            match stream.read_exact(&mut header) {
+                Ok(()) => {}
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::TimedOut =>
+                {
+                    continue
+                }
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            }
+            let size = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            if !(8..=MAX_MESSAGE_SIZE).contains(&size) {
+                return send_error(
+                    &mut stream,
+                    BAD_TCP_MESSAGE_TYPE_INVALID,
+                    "Bad message size",
+                );
+            }
+            let mut body = vec![0; size as usize - 8];
+            stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+            stream.read_exact(&mut body)?;
+            stream.set_read_timeout(Some(Duration::from_millis(500)))?;
+
+            match (&header[0..3], header[3], said_hello) {
+                (b"HEL", b'F', false) => {
+                    let mut decoder = Decoder::new(&body);
+                    let hello = (0..5)
+                        .map(|_| decoder.u32())
+                        .collect::<Result<Vec<u32>, u32>>()
+                        .and_then(|fields| Ok((fields, decoder.string()?)));
+                    let (fields, url) = match hello {
+                        Ok(hello) => hello,
+                        Err(code) => return send_error(&mut stream, code, "Bad hello"),
+                    };
+                    if let Some(url) = url.filter(|url| !url.is_empty()) {
+                        self.endpoint_url = url;
+                    }
+                    let mut ack = Encoder::default();
+                    ack.u32(0);
+                    ack.u32(fields[1].min(MAX_MESSAGE_SIZE));
+                    ack.u32(fields[2].min(MAX_MESSAGE_SIZE));
+                    ack.u32(MAX_MESSAGE_SIZE);
+                    ack.u32(1);
+                    stream.write_all(&frame(b"ACKF", &ack.bytes))?;
+                    said_hello = true;
+                }
+                (b"OPN", b'F', true) => {
+                    let reply = self.open_secure_channel(&body);
+                    stream.write_all(&reply)?;
+                }
+                (b"MSG", b'F', true) => {
+                    if let Some(reply) = self.message(&body) {
+                        stream.write_all(&reply)?;
+                    }
+                }
+                (b"CLO", _, _) => return Ok(()),
+                _ => {
+                    return send_error(
+                        &mut stream,
+                        BAD_TCP_MESSAGE_TYPE_INVALID,
+                        "Unexpected or chunked message",
+                    )
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn open_secure_channel(&mut self, body: &[u8]) -> Vec<u8> {
+        let parsed = decode_open_request(&mut Decoder::new(body));
+
+        let mut encoder = Encoder::default();
+        encoder.u32(self.channel_id);
+        encoder.string(Some(SECURITY_POLICY_NONE));
+        encoder.byte_string(None);
+        encoder.byte_string(None);
+        let (request_id, handle, request_type, security_mode) = match parsed {
+            Ok(parsed) => parsed,
+            Err(code) => {
+                encoder.u32(self.next_sequence_number());
+                encoder.u32(0);
+                encoder.service_fault(0, code);
+                return frame(b"OPNF", &encoder.bytes);
+            }
+        };
+        encoder.u32(self.next_sequence_number());
+        encoder.u32(request_id);
+        if security_mode != 1 {
+            encoder.service_fault(handle, BAD_SECURITY_MODE_REJECTED);
+            return frame(b"OPNF", &encoder.bytes);
+        }
+        if request_type == 1 {
+            self.token_id += 1;
+        }
+        encoder.node_id(&NodeId::Numeric(0, OPEN_SECURE_CHANNEL_RESPONSE));
+        encoder.response_header(handle, GOOD);
+        encoder.u32(0);
+        encoder.u32(self.channel_id);
+        encoder.u32(self.token_id);
+        encoder.date_time(now());
+        encoder.u32(TOKEN_LIFETIME_MS);
+        encoder.byte_string(Some(&[]));
+        frame(b"OPNF", &encoder.bytes)
+    }
+
+    fn message(&mut self, body: &[u8]) -> Option<Vec<u8>> {
+        let mut decoder = Decoder::new(body);
+        let channel_id = decoder.u32().ok()?;
+        decoder.take(8).ok()?; // token id and sequence number
+        let request_id = decoder.u32().ok()?;
+        let type_id = decoder.expanded_node_id().ok()?;
+        let (token, handle) = decoder.request_header().ok()?;
+        if channel_id != self.channel_id {
+            return None;
+        }
+
+        let mut encoder = Encoder::default();
+        encoder.u32(self.channel_id);
+        encoder.u32(self.token_id);
+        encoder.u32(self.next_sequence_number());
+        encoder.u32(request_id);
+
+        let service = match type_id {
+            NodeId::Numeric(0, id) => id,
+            _ => 0,
+        };
+        let session_ok = matches!(
+            (self.session, &token),
+            (Some((expected, true)), NodeId::Numeric(1, actual)) if expected == *actual
+        );
+        let result = match service {
+            GET_ENDPOINTS_REQUEST => {
+                encoder.node_id(&NodeId::Numeric(0, GET_ENDPOINTS_RESPONSE));
+                encoder.response_header(handle, GOOD);
+                self.endpoints(&mut encoder);
+                Ok(())
+            }
+            CREATE_SESSION_REQUEST => {
+                let auth_token = self.channel_id.wrapping_mul(7919).wrapping_add(handle);
+                self.session = Some((auth_token, false));
+                encoder.node_id(&NodeId::Numeric(0, CREATE_SESSION_RESPONSE));
+                encoder.response_header(handle, GOOD);
+                encoder.node_id(&NodeId::Numeric(1, self.channel_id));
+                encoder.node_id(&NodeId::Numeric(1, auth_token));
+                encoder.f64(SESSION_TIMEOUT_MS);
+                encoder.byte_string(Some(&[0; 32]));
+                encoder.byte_string(None);
+                self.endpoints(&mut encoder);
+                encoder.i32(0); // server software certificates
+                encoder.string(None); // signature algorithm
+                encoder.byte_string(None); // signature
+                encoder.u32(MAX_MESSAGE_SIZE);
+                Ok(())
+            }
+            ACTIVATE_SESSION_REQUEST => match self.session {
+                Some((expected, _)) if token == NodeId::Numeric(1, expected) => {
+                    self.activate(&mut decoder).map(|()| {
+                        self.session = Some((expected, true));
+                        encoder.node_id(&NodeId::Numeric(0, ACTIVATE_SESSION_RESPONSE));
+                        encoder.response_header(handle, GOOD);
+                        encoder.byte_string(Some(&[0; 32]));
+                        encoder.i32(0);
+                        encoder.i32(0);
+                    })
+                }
+                _ => Err(BAD_SESSION_ID_INVALID),
+            },
+            CLOSE_SESSION_REQUEST => {
+                self.session = None;
+                encoder.node_id(&NodeId::Numeric(0, CLOSE_SESSION_RESPONSE));
+                encoder.response_header(handle, GOOD);
+                Ok(())
+            }
+            BROWSE_REQUEST | READ_REQUEST | WRITE_REQUEST if !session_ok => {
+                Err(BAD_SESSION_ID_INVALID)
+            }
+            BROWSE_REQUEST => self.browse(&mut decoder, &mut encoder, handle),
+            READ_REQUEST => self.read(&mut decoder, &mut encoder, handle),
+            WRITE_REQUEST => self.write(&mut decoder, &mut encoder, handle),
+            _ => Err(BAD_SERVICE_UNSUPPORTED),
+        };
+
+        if let Err(code) = result {
+            encoder.bytes.truncate(16);
+            encoder.service_fault(handle, code);
+        }
+        Some(frame(b"MSGF", &encoder.bytes))
+    }
+
+    fn endpoints(&self, encoder: &mut Encoder) {
+        encoder.i32(1);
+        encoder.string(Some(&self.endpoint_url));
+        // Server application description.
+        encoder.string(Some(APPLICATION_URI));
+        encoder.string(Some(NAMESPACE_URI));
+        encoder.localized_text("mwctl microwave controller");
+        encoder.u32(0); // server
+        encoder.string(None);
+        encoder.string(None);
+        encoder.i32(1);
+        encoder.string(Some(&self.endpoint_url));
+        encoder.byte_string(None); // server certificate
+        encoder.u32(1); // security mode None
+        encoder.string(Some(SECURITY_POLICY_NONE));
+        let tokens = !self.server.access.tokens.is_empty();
+        encoder.i32(if tokens { 2 } else { 1 }); // user token policies
+        encoder.string(Some("anonymous"));
+        encoder.u32(0); // anonymous
+        encoder.string(None);
+        encoder.string(None);
+        encoder.string(None);
+        if tokens {
+            encoder.string(Some("token"));
+            encoder.u32(1); // user name, the password being the token
+            encoder.string(None);
+            encoder.string(None);
+            encoder.string(None);
+        }
+        encoder.string(Some(TRANSPORT_PROFILE));
+        encoder.u8(0); // security level
+    }
+
+    /// Picks the session's role from the identity token of an
+    /// ActivateSession request.
+    fn activate(&mut self, decoder: &mut Decoder) -> Result<(), u32> {
+        decoder.string()?; // client signature algorithm
+        decoder.byte_string()?;
+        for _ in 0..decoder.array_len()? {
+            decoder.byte_string()?; // software certificate and signature
+            decoder.byte_string()?;
+        }
+        for _ in 0..decoder.array_len()? {
+            decoder.string()?; // locale id
+        }
+        let identity = decoder.node_id()?;
+        let body = match decoder.u8()? {
+            0 => &[][..],
+            1 => decoder.byte_string()?.unwrap_or_default(),
+            _ => return Err(BAD_IDENTITY_TOKEN_INVALID),
+        };
+
+        let access = &self.server.access;
+        match identity {
+            NodeId::Numeric(0, 0) | NodeId::Numeric(0, ANONYMOUS_IDENTITY_TOKEN) => {
+                self.role = access.role_for(self.peer.ip());
+                self.server.controller = self
+                    .server
+                    .controller
+                    .with_actor(&format!("opcua {}", self.peer));
+            }
+            NodeId::Numeric(0, USER_NAME_IDENTITY_TOKEN) => {
+                let mut token = Decoder::new(body);
+                token.string()?; // policy id
+                token.string()?; // user name
+                let secret = token.byte_string()?.unwrap_or_default();
+                if token
+                    .string()?
+                    .is_some_and(|algorithm| !algorithm.is_empty())
+                {
+                    return Err(BAD_IDENTITY_TOKEN_INVALID);
+                }
+                let token = access.token(secret).ok_or(BAD_IDENTITY_TOKEN_REJECTED)?;
+                self.role = token.role;
+                self.server.controller = self
+                    .server
+                    .controller
+                    .with_actor(&format!("opcua {} {}", self.peer, token.name));
+                println!(
+                    "OPC UA client {} authenticated as {} ({})",
+                    self.peer, token.name, token.role
+                );
+            }
+            _ => return Err(BAD_IDENTITY_TOKEN_INVALID),
+        }
+        Ok(())
+    }
+
+    fn browse(&self, decoder: &mut Decoder, encoder: &mut Encoder, handle: u32) -> Result<(), u32> {
+        decoder.node_id()?; // view id
+        decoder.i64()?;
+        decoder.u32()?;
+        decoder.u32()?; // max references per node
+        let count = decoder.array_len()?;
+        let mut requests = Vec::with_capacity(count);
+        for _ in 0..count {
+            let id = decoder.node_id()?;
+            let direction = decoder.u32()?;
+            decoder.node_id()?; // reference type filter
+            decoder.bool()?;
+            decoder.u32()?;
+            decoder.u32()?;
+            requests.push((id, direction));
+        }
+
+        encoder.node_id(&NodeId::Numeric(0, BROWSE_RESPONSE));
+        encoder.response_header(handle, GOOD);
+        encoder.i32(requests.len() as i32);
+        for (id, direction) in requests {
+            let node = match self.server.node(&id) {
+                Some(node) => node,
+                None => {
+                    encoder.u32(BAD_NODE_ID_UNKNOWN);
+                    encoder.byte_string(None);
+                    encoder.i32(0);
+                    continue;
+                }
+            };
+            // (reference type, forward, target)
+            let mut references: Vec<(u32, bool, &Node)> = Vec::new();
+            if direction != 1 {
+                references.extend(self.server.nodes.iter().filter_map(
+                    |child| match &child.parent {
+                        Some((parent, reference)) if parent == &node.id => {
+                            Some((*reference, true, child))
+                        }
+                        _ => None,
+                    },
+                ));
+            }
+            if direction != 0 {
+                if let Some((parent, reference)) = &node.parent {
+                    if let Some(parent) = self.server.node(parent) {
+                        references.push((*reference, false, parent));
+                    }
+                }
+            }
+
+            encoder.u32(GOOD);
+            encoder.byte_string(None);
+            let type_reference = direction != 1;
+            encoder.i32(references.len() as i32 + type_reference as i32);
+            for (reference, forward, target) in references {
+                encoder.node_id(&NodeId::Numeric(0, reference));
+                encoder.bool(forward);
+                encoder.node_id(&target.id);
+                encoder.qualified_name(target.browse_name.0, &target.browse_name.1);
+                encoder.localized_text(&target.browse_name.1);
+                encoder.u32(if target.is_variable() { 2 } else { 1 });
+                encoder.node_id(&NodeId::Numeric(0, target.type_definition()));
+            }
+            if type_reference {
+                encoder.node_id(&NodeId::Numeric(0, HAS_TYPE_DEFINITION));
+                encoder.bool(true);
+                encoder.node_id(&NodeId::Numeric(0, node.type_definition()));
+                encoder.qualified_name(0, "");
+                encoder.localized_text("");
+                // ObjectType or VariableType node class.
+                encoder.u32(if node.is_variable() { 16 } else { 8 });
+                encoder.node_id(&NodeId::NULL);
+            }
+        }
+        encoder.i32(0);
+        Ok(())
+    }
+
+    fn read(&self, decoder: &mut Decoder, encoder: &mut Encoder, handle: u32) -> Result<(), u32> {
+        decoder.f64()?; // max age
+        decoder.u32()?; // timestamps to return
+        let count = decoder.array_len()?;
+        let mut requests = Vec::with_capacity(count);
+        for _ in 0..count {
+            let id = decoder.node_id()?;
+            let attribute = decoder.u32()?;
+            decoder.string()?; // index range
+            decoder.u16()?; // data encoding
+            decoder.string()?;
+            requests.push((id, attribute));
+        }
+
+        encoder.node_id(&NodeId::Numeric(0, READ_RESPONSE));
+        encoder.response_header(handle, GOOD);
+        encoder.i32(requests.len() as i32);
+        for (id, attribute) in requests {
+            match self.server.read_attribute(&id, attribute) {
+                Ok(value) if attribute == ATTRIBUTE_VALUE => {
+                    encoder.u8(0x05);
+                    encoder.variant(&value);
+                    encoder.date_time(now());
+                }
+                Ok(value) => {
+                    encoder.u8(0x01);
+                    encoder.variant(&value);
+                }
+                Err(code) => {
+                    encoder.u8(0x02);
+                    encoder.u32(code);
+                }
+            }
+        }
+        encoder.i32(0);
+        Ok(())
+    }
+
+    fn write(&self, decoder: &mut Decoder, encoder: &mut Encoder, handle: u32) -> Result<(), u32> {
+        let count = decoder.array_len()?;
+        let mut results = Vec::with_capacity(count);
+        for _ in 0..count {
+            let id = decoder.node_id()?;
+            let attribute = decoder.u32()?;
+            decoder.string()?; // index range
+            let value = decoder.data_value()?;
+            let result = match (self.server.node(&id), attribute) {
+                (None, _) => BAD_NODE_ID_UNKNOWN,
+                (Some(_), attribute) if attribute != ATTRIBUTE_VALUE => BAD_NOT_WRITABLE,
+                (Some(node), _) => match (&node.kind, value) {
+                    (NodeKind::Signal(signal), Some(value)) if signal.writable() => {
+                        self.server.write_signal(*signal, &value, self.role)
+                    }
+                    (NodeKind::Signal(signal), None) if signal.writable() => BAD_TYPE_MISMATCH,
+                    _ => BAD_NOT_WRITABLE,
+                },
+            };
+            results.push(result);
+        }
+
+        encoder.node_id(&NodeId::Numeric(0, WRITE_RESPONSE));
+        encoder.response_header(handle, GOOD);
+        encoder.i32(results.len() as i32);
+        for result in results {
+            encoder.u32(result);
+        }
+        encoder.i32(0);
+        Ok(())
+    }
+
+    fn next_sequence_number(&mut self) -> u32 {
+        self.sequence_number += 1;
+        self.sequence_number
+    }
+}
+
+/// Returns the request id, request handle, request type and security mode
+/// of an OpenSecureChannel message.
+fn decode_open_request(decoder: &mut Decoder) -> Result<(u32, u32, u32, u32), u32> {
+    decoder.u32()?; // requested channel id, 0 for a new channel
+    decoder.string()?; // security policy
+    decoder.byte_string()?; // sender certificate
+    decoder.byte_string()?; // receiver thumbprint
+    decoder.u32()?; // sequence number
+    let request_id = decoder.u32()?;
+    if decoder.expanded_node_id()? != NodeId::Numeric(0, OPEN_SECURE_CHANNEL_REQUEST) {
+        return Err(BAD_SERVICE_UNSUPPORTED);
+    }
+    let handle = decoder.request_header()?.1;
+    decoder.u32()?; // client protocol version
+    let request_type = decoder.u32()?;
+    let security_mode = decoder.u32()?;
+    Ok((request_id, handle, request_type, security_mode))
+}
+
+fn frame(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(8 + body.len());
+    message.extend_from_slice(kind);
+    message.extend_from_slice(&(8 + body.len() as u32).to_le_bytes());
+    message.extend_from_slice(body);
+    message
+}
+
+fn send_error(stream: &mut TcpStream, code: u32, reason: &str) -> io::Result<()> {
+    let mut encoder = Encoder::default();
+    encoder.u32(code);
+    encoder.string(Some(reason));
+    stream.write_all(&frame(b"ERRF", &encoder.bytes))
+}
+
+/// Current time as an OPC UA DateTime (100 ns ticks since 1601-01-01).
+fn now() -> i64 {
+    const UNIX_EPOCH_TICKS: i64 = 116_444_736_000_000_000;
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH_TICKS + (since_epoch.as_nanos() / 100) as i64
+}
+
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+}
+
+impl Encoder {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn f64(&mut self, value: f64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn date_time(&mut self, value: i64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: Option<&str>) {
+        self.byte_string(value.map(str::as_bytes));
+    }
+
+    fn byte_string(&mut self, value: Option<&[u8]>) {
+        match value {
+            Some(bytes) => {
+                self.i32(bytes.len() as i32);
+                self.bytes.extend_from_slice(bytes);
+            }
+            None => self.i32(-1),
+        }
+    }
+
+    fn node_id(&mut self, id: &NodeId) {
+        match id {
+            NodeId::Numeric(0, value) if *value <= 0xFF => {
+                self.u8(0x00);
+                self.u8(*value as u8);
+            }
+            NodeId::Numeric(ns, value) if *ns <= 0xFF && *value <= 0xFFFF => {
+                self.u8(0x01);
+                self.u8(*ns as u8);
+                self.u16(*value as u16);
+            }
+            NodeId::Numeric(ns, value) => {
+                self.u8(0x02);
+                self.u16(*ns);
+                self.u32(*value);
+            }
+            NodeId::String(ns, value) => {
+                self.u8(0x03);
+                self.u16(*ns);
+                self.string(Some(value));
+            }
+            NodeId::Opaque(encoded) => self.bytes.extend_from_slice(encoded),
+        }
+    }
+
+    fn qualified_name(&mut self, ns: u16, name: &str) {
+        self.u16(ns);
+        self.string(Some(name));
+    }
+
+    fn localized_text(&mut self, text: &str) {
+        self.u8(0x02);
+        self.string(Some(text));
+    }
+
+    fn variant(&mut self, value: &Variant) {
+        match value {
+            Variant::Empty => self.u8(0),
+            Variant::Boolean(value) => {
+                self.u8(1);
+                self.bool(*value);
+            }
+            Variant::Byte(value) => {
+                self.u8(3);
+                self.u8(*value);
+            }
+            Variant::Int32(value) => {
+                self.u8(6);
+                self.i32(*value);
+            }
+            Variant::UInt32(value) => {
+                self.u8(7);
+                self.u32(*value);
+            }
+            Variant::Float(value) => {
+                self.u8(10);
+                self.bytes.extend_from_slice(&value.to_le_bytes());
+            }
+            Variant::Double(value) => {
+                self.u8(11);
+                self.f64(*value);
+            }
+            Variant::String(value) => {
+                self.u8(12);
+                self.string(Some(value));
+            }
+            Variant::StringArray(values) => {
+                self.u8(12 | 0x80);
+                self.i32(values.len() as i32);
+                for value in values {
+                    self.string(Some(value));
+                }
+            }
+            Variant::NodeId(id) => {
+                self.u8(17);
+                self.node_id(id);
+            }
+            Variant::QualifiedName(ns, name) => {
+                self.u8(20);
+                self.qualified_name(*ns, name);
+            }
+            Variant::LocalizedText(text) => {
+                self.u8(21);
+                self.localized_text(text);
+            }
+        }
+    }
+
+    fn response_header(&mut self, handle: u32, result: u32) {
+        self.date_time(now());
+        self.u32(handle);
+        self.u32(result);
+        self.u8(0); // diagnostics
+        self.i32(0); // string table
+        self.node_id(&NodeId::NULL); // additional header
+        self.u8(0);
+    }
+
+    fn service_fault(&mut self, handle: u32, code: u32) {
+        self.node_id(&NodeId::Numeric(0, SERVICE_FAULT));
+        self.response_header(handle, code);
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn new(bytes: &'a [u8]) -> Decoder<'a> {
+        Decoder { bytes, position: 0 }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], u32> {
+        let end = self.position.checked_add(len).ok_or(BAD_DECODING_ERROR)?;
+        let bytes = self
+            .bytes
+            .get(self.position..end)
+            .ok_or(BAD_DECODING_ERROR)?;
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, u32> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, u32> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, u32> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, u32> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, u32> {
+        Ok(self.u32()? as i32)
+    }
+
+    fn i64(&mut self) -> Result<i64, u32> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.take(8)?);
+        Ok(i64::from_le_bytes(bytes))
+    }
+
+    fn f32(&mut self) -> Result<f32, u32> {
+        Ok(f32::from_bits(self.u32()?))
+    }
+
+    fn f64(&mut self) -> Result<f64, u32> {
+        Ok(f64::from_bits(self.i64()? as u64))
+    }
+
+    fn array_len(&mut self) -> Result<usize, u32> {
+        let len = self.i32()?;
+        // Every element takes at least one byte; rejects absurd lengths early.
+        if len as i64 > (self.bytes.len() - self.position) as i64 {
+            return Err(BAD_DECODING_ERROR);
+        }
+        Ok(len.max(0) as usize)
+    }
+
+    fn byte_string(&mut self) -> Result<Option<&'a [u8]>, u32> {
+        let len = self.i32()?;
+        if len < 0 {
+            return Ok(None);
+        }
+        self.take(len as usize).map(Some)
+    }
+
+    fn string(&mut self) -> Result<Option<String>, u32> {
+        Ok(self
+            .byte_string()?
+            .map(|bytes| String::from_utf8_lossy(bytes).into_owned()))
+    }
+
+    fn node_id(&mut self) -> Result<NodeId, u32> {
+        let start = self.position;
+        let encoding = self.u8()?;
+        match encoding & 0x3F {
+            0x00 => Ok(NodeId::Numeric(0, self.u8()? as u32)),
+            0x01 => {
+                let ns = self.u8()? as u16;
+                Ok(NodeId::Numeric(ns, self.u16()? as u32))
+            }
+            0x02 => {
+                let ns = self.u16()?;
+                Ok(NodeId::Numeric(ns, self.u32()?))
+            }
+            0x03 => {
+                let ns = self.u16()?;
+                Ok(NodeId::String(ns, self.string()?.unwrap_or_default()))
+            }
+            0x04 => {
+                self.take(18)?;
+                Ok(NodeId::Opaque(self.bytes[start..self.position].to_vec()))
+            }
+            0x05 => {
+                self.u16()?;
+                self.byte_string()?;
+                Ok(NodeId::Opaque(self.bytes[start..self.position].to_vec()))
+            }
+            _ => Err(BAD_DECODING_ERROR),
+        }
+    }
+
+    /// Reads an ExpandedNodeId, dropping the namespace URI and server index.
+    fn expanded_node_id(&mut self) -> Result<NodeId, u32> {
+        let flags = *self.bytes.get(self.position).ok_or(BAD_DECODING_ERROR)?;
+        let id = self.node_id()?;
+        if flags & 0x80 != 0 {
+            self.string()?;
+        }
+        if flags & 0x40 != 0 {
+            self.u32()?;
+        }
+        Ok(id)
+    }
+
+    fn extension_object(&mut self) -> Result<(), u32> {
+        self.node_id()?;
+        match self.u8()? {
+            0 => Ok(()),
+            1 | 2 => self.byte_string().map(|_| ()),
+            _ => Err(BAD_DECODING_ERROR),
+        }
+    }
+
+    /// Returns the authentication token and request handle.
+    fn request_header(&mut self) -> Result<(NodeId, u32), u32> {
+        let token = self.node_id()?;
+        self.i64()?; // timestamp
+        let handle = self.u32()?;
+        self.u32()?; // return diagnostics
+        self.string()?; // audit entry id
+        self.u32()?; // timeout hint
+        self.extension_object()?;
+        Ok((token, handle))
+    }
+
+    /// Reads a DataValue, returning its value if it is a supported scalar.
+    fn data_value(&mut self) -> Result<Option<Variant>, u32> {
+        let mask = self.u8()?;
+        let value = if mask & 0x01 != 0 {
+            self.variant()?
+        } else {
+            None
+        };
+        if mask & 0x02 != 0 {
+            self.u32()?;
+        }
+        if mask & 0x04 != 0 {
+            self.i64()?;
+        }
+        if mask & 0x10 != 0 {
+            self.u16()?;
+        }
+        if mask & 0x08 != 0 {
+            self.i64()?;
+        }
+        if mask & 0x20 != 0 {
+            self.u16()?;
+        }
+        Ok(value)
+    }
+
+    fn variant(&mut self) -> Result<Option<Variant>, u32> {
+        let value = match self.u8()? {
+            1 => Variant::Boolean(self.bool()?),
+            2 => Variant::Int32(self.u8()? as i8 as i32),
+            3 => Variant::Byte(self.u8()?),
+            4 => Variant::Int32(self.u16()? as i16 as i32),
+            5 => Variant::UInt32(self.u16()? as u32),
+            6 => Variant::Int32(self.i32()?),
+            7 => Variant::UInt32(self.u32()?),
+            10 => Variant::Float(self.f32()?),
+            11 => Variant::Double(self.f64()?),
+            12 => Variant::String(self.string()?.unwrap_or_default()),
+            _ => return Ok(None),
+        };
+        Ok(Some(value))
+    }
+}
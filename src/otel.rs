@@ -0,0 +1,300 @@
+//! OpenTelemetry export over OTLP/HTTP with JSON bodies: a client span for
+//! every command line exchanged with the board and gauges for every
+//! telemetry sample, batched by a background thread and posted to a
+//! collector's `/v1/traces` and `/v1/metrics`.
+//!
+//! Spans share one trace per exporter. Started inside a traced test step,
+//! [`OtlpExporter::with_parent`] takes the step's W3C `traceparent` so the
+//! generator's commands appear under it.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::alerting::post_json;
+use crate::events::{Exchange, ExchangeListener};
+use crate::json::JsonValue;
+use crate::telemetry::{TelemetryListener, TelemetrySample};
+
+/// Longest time a span or sample waits before it is posted.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+/// Records posted at most per request.
+const MAX_BATCH: usize = 512;
+/// `SPAN_KIND_CLIENT`: the controller calls the board.
+const SPAN_KIND_CLIENT: u32 = 3;
+const STATUS_OK: u32 = 1;
+const STATUS_ERROR: u32 = 2;
+
+enum Record {
+    Span(String),
+    Sample(TelemetrySample),
+    Flush(Sender<()>),
+}
+
+/// Handle to the export thread. Clones feed the same batches.
+#[derive(Clone)]
+pub struct OtlpExporter {
+    records: Sender<Record>,
+    trace_id: Arc<str>,
+    parent_span_id: Option<Arc<str>>,
+}
+
+impl OtlpExporter {
+    /// Exports to the collector at `endpoint`, e.g. `http://localhost:4318`,
+    /// as service `service_name`.
+    pub fn new(endpoint: &str, service_name: &str) -> OtlpExporter {
+        let (records, receiver) = mpsc::channel();
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let resource = JsonValue::object().with(
+            "attributes",
+            JsonValue::Array(vec![attribute("service.name", service_name)]),
+        );
+        thread::spawn(move || export(receiver, &endpoint, resource));
+        OtlpExporter {
+            records,
+            trace_id: random_hex(16).into(),
+            parent_span_id: None,
+        }
+    }
+
+    /// Puts every span under the span of a W3C `traceparent`
+    /// (`00-<trace id>-<span id>-<flags>`).
+    pub fn with_parent(mut self, traceparent: &str) -> Result<OtlpExporter, String> {
+        let fields: Vec<&str> = traceparent.trim().split('-').collect();
+        let hex = |field: &str, len: usize| {
+            field.len() == len && field.bytes().all(|b| b.is_ascii_hexdigit())
+        };
+        match fields.as_slice() {
+            [_, trace_id, span_id, _] if hex(trace_id, 32) && hex(span_id, 16) => {
+                self.trace_id = trace_id.to_ascii_lowercase().into();
+                self.parent_span_id = Some(span_id.to_ascii_lowercase().into());
+                Ok(self)
+            }
+            _ => Err(format!("Invalid traceparent: {}", traceparent)),
+        }
+    }
+
+    /// Listener for [`Controller::observe_exchanges`](crate::Controller::observe_exchanges).
+    pub fn exchange_listener(&self) -> ExchangeListener {
+        let exporter = self.clone();
+        Arc::new(move |exchange: &Exchange| {
+            let span = exporter.span(exchange);
+            let _ = exporter.records.send(Record::Span(span));
+        })
+    }
+
+    /// Listener for a [`TelemetryPoller`](crate::TelemetryPoller).
+    pub fn telemetry_listener(&self) -> TelemetryListener {
+        let records = self.records.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            let _ = records.send(Record::Sample(*sample));
+        })
+    }
+
+    /// Posts everything queued so far, waiting up to `timeout`.
+    pub fn flush(&self, timeout: Duration) {
+        let (done, wait) = mpsc::channel();
+        if self.records.send(Record::Flush(done)).is_ok() {
+            let _ = wait.recv_timeout(timeout);
+        }
+    }
+
+    fn span(&self, exchange: &Exchange) -> String {
+        let name = exchange
+            .command
+            .split(',')
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        let mut attributes = vec![
+            attribute("mw.command", exchange.command.as_str()),
+            attribute("mw.actor", exchange.actor.as_str()),
+        ];
+        let status = match &exchange.reply {
+            Ok(reply) => {
+                attributes.push(attribute("mw.reply", reply.as_str()));
+                if reply.contains(",ERR") {
+                    JsonValue::object()
+                        .with("code", STATUS_ERROR as f64)
+                        .with("message", reply.as_str())
+                } else {
+                    JsonValue::object().with("code", STATUS_OK as f64)
+                }
+            }
+            Err(e) => JsonValue::object()
+                .with("code", STATUS_ERROR as f64)
+                .with("message", e.as_str()),
+        };
+        let mut span = JsonValue::object()
+            .with("traceId", &*self.trace_id)
+            .with("spanId", random_hex(8));
+        if let Some(parent) = &self.parent_span_id {
+            span = span.with("parentSpanId", &**parent);
+        }
+        span.with("name", name)
+            .with("kind", SPAN_KIND_CLIENT as f64)
+            .with("startTimeUnixNano", unix_nanos(exchange.started))
+            .with(
+                "endTimeUnixNano",
+                unix_nanos(exchange.started + exchange.duration),
+            )
+            .with("attributes", JsonValue::Array(attributes))
+            .with("status", status)
+            .to_string()
+    }
+}
+
+/// Batches records and posts them until every sender is gone.
+fn export(receiver: Receiver<Record>, endpoint: &str, resource: JsonValue) {
+    let mut spans: Vec<String> = Vec::new();
+    let mut samples: Vec<TelemetrySample> = Vec::new();
+    let mut acks: Vec<Sender<()>> = Vec::new();
+    let mut last_flush = Instant::now();
+    loop {
+        let wait = FLUSH_INTERVAL.saturating_sub(last_flush.elapsed());
+        let disconnected = match receiver.recv_timeout(wait) {
+            Ok(Record::Span(span)) => {
+                spans.push(span);
+                false
+            }
+            Ok(Record::Sample(sample)) => {
+                samples.push(sample);
+                false
+            }
+            Ok(Record::Flush(ack)) => {
+                acks.push(ack);
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+        let due = disconnected
+            || !acks.is_empty()
+            || spans.len() + samples.len() >= MAX_BATCH
+            || last_flush.elapsed() >= FLUSH_INTERVAL;
+        if !due {
+            continue;
+        }
+        last_flush = Instant::now();
+        if !spans.is_empty() {
+            let body = traces_body(&resource, &spans);
+            if let Err(e) = post_json(&format!("{}/v1/traces", endpoint), &body) {
+                eprintln!("Failed to export {} spans: {}", spans.len(), e);
+            }
+            spans.clear();
+        }
+        if !samples.is_empty() {
+            let body = metrics_body(&resource, &samples);
+            if let Err(e) = post_json(&format!("{}/v1/metrics", endpoint), &body) {
+                eprintln!("Failed to export {} samples: {}", samples.len(), e);
+            }
+            samples.clear();
+        }
+        for ack in acks.drain(..) {
+            let _ = ack.send(());
+        }
+        if disconnected {
+            return;
+        }
+    }
+}
+
+fn traces_body(resource: &JsonValue, spans: &[String]) -> String {
+    format!(
+        "{{\"resourceSpans\":[{{\"resource\":{},\"scopeSpans\":[{{\"scope\":{},\"spans\":[{}]}}]}}]}}",
+        resource,
+        scope(),
+        spans.join(",")
+    )
+}
+
+fn metrics_body(resource: &JsonValue, samples: &[TelemetrySample]) -> String {
+    let gauge = |name: &str, unit: &str, value: &dyn Fn(&TelemetrySample) -> Option<f64>| {
+        let points: Vec<JsonValue> = samples
+            .iter()
+            .filter_map(|sample| {
+                value(sample).map(|value| {
+                    JsonValue::object()
+                        .with("timeUnixNano", unix_nanos(sample.timestamp))
+                        .with("asDouble", value)
+                })
+            })
+            .collect();
+        JsonValue::object()
+            .with("name", name)
+            .with("unit", unit)
+            .with(
+                "gauge",
+                JsonValue::object().with("dataPoints", JsonValue::Array(points)),
+            )
+    };
+    let metrics = JsonValue::Array(vec![
+        gauge("mw.frequency", "MHz", &|s| Some(s.frequency_mhz as f64)),
+        gauge("mw.power.setpoint", "dBm", &|s| {
+            Some(s.power_setpoint_dbm as f64)
+        }),
+        gauge("mw.power.forward", "dBm", &|s| Some(s.forward_dbm as f64)),
+        gauge("mw.power.reflected", "dBm", &|s| {
+            Some(s.reflected_dbm as f64)
+        }),
+        gauge("mw.power.delivered", "W", &|s| Some(s.delivered_watts())),
+        gauge("mw.temperature", "Cel", &|s| s.temperature_c.map(f64::from)),
+        gauge("mw.supply.voltage", "V", &|s| {
+            s.supply_voltage_v.map(f64::from)
+        }),
+        gauge("mw.supply.current", "A", &|s| {
+            s.supply_current_a.map(f64::from)
+        }),
+        gauge("mw.efficiency", "%", &|s| s.efficiency_percent()),
+        gauge("mw.rf_enabled", "1", &|s| {
+            Some(if s.rf_enabled { 1.0 } else { 0.0 })
+        }),
+    ]);
+    format!(
+        "{{\"resourceMetrics\":[{{\"resource\":{},\"scopeMetrics\":[{{\"scope\":{},\"metrics\":{}}}]}}]}}",
+        resource,
+        scope(),
+        metrics
+    )
+}
+
+fn scope() -> JsonValue {
+    JsonValue::object()
+        .with("name", "microwave_controller")
+        .with("version", env!("CARGO_PKG_VERSION"))
+}
+
+fn attribute(key: &str, value: &str) -> JsonValue {
+    JsonValue::object()
+        .with("key", key)
+        .with("value", JsonValue::object().with("stringValue", value))
+}
+
+/// OTLP/JSON carries 64-bit nanosecond times as decimal strings.
+fn unix_nanos(time: SystemTime) -> String {
+    unix_nanos_u64(time).to_string()
+}
+
+fn unix_nanos_u64(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|since| since.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+/// `bytes` random bytes as lowercase hex, for trace and span ids.
+fn random_hex(bytes: usize) -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let mut hex = String::with_capacity(bytes * 2);
+    while hex.len() < bytes * 2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        hasher.write_u64(unix_nanos_u64(SystemTime::now()));
+        hex.push_str(&format!("{:016x}", hasher.finish()));
+    }
+    hex.truncate(bytes * 2);
+    hex
+}
@@ -0,0 +1,564 @@
+//! Parquet export of telemetry and sweeps, for loading multi-day datasets
+//! straight into pandas, Polars or DuckDB.
+//!
+//! Only what these tables need is written: flat schemas of timestamp,
+//! float, double and boolean columns, PLAIN encoding, one data page per
+//! column and row group, and no statistics. Pages are compressed with the
+//! GZIP codec through the system `gzip` tool, as rotated logs are, or not
+//! at all. Floats take four bytes instead of the seven to ten of a CSV
+//! field and the booleans one bit, before compression.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::downsample::{Stats, TelemetryAggregate};
+use crate::sweep::SweepPoint;
+use crate::telemetry::TelemetrySample;
+
+const MAGIC: &[u8] = b"PAR1";
+/// Rows per row group; readers can load one group at a time.
+const ROW_GROUP_ROWS: usize = 1 << 20;
+
+/// Page compression.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Compression {
+    None,
+    /// Needs `gzip` on the `PATH`.
+    #[default]
+    Gzip,
+}
+
+/// Values of one column; `None` is a null.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValues {
+    /// Milliseconds since the Unix epoch, UTC.
+    Timestamp(Vec<Option<i64>>),
+    Float(Vec<Option<f32>>),
+    Double(Vec<Option<f64>>),
+    Int64(Vec<Option<i64>>),
+    Boolean(Vec<Option<bool>>),
+}
+
+impl ColumnValues {
+    fn len(&self) -> usize {
+        match self {
+            ColumnValues::Timestamp(values) | ColumnValues::Int64(values) => values.len(),
+            ColumnValues::Float(values) => values.len(),
+            ColumnValues::Double(values) => values.len(),
+            ColumnValues::Boolean(values) => values.len(),
+        }
+    }
+
+    /// Whether row `index` holds a value.
+    fn is_present(&self, index: usize) -> bool {
+        match self {
+            ColumnValues::Timestamp(values) | ColumnValues::Int64(values) => {
+                values[index].is_some()
+            }
+            ColumnValues::Float(values) => values[index].is_some(),
+            ColumnValues::Double(values) => values[index].is_some(),
+            ColumnValues::Boolean(values) => values[index].is_some(),
+        }
+    }
+
+    fn physical_type(&self) -> i32 {
+        match self {
+            ColumnValues::Boolean(_) => 0,
+            ColumnValues::Timestamp(_) | ColumnValues::Int64(_) => 2,
+            ColumnValues::Float(_) => 4,
+            ColumnValues::Double(_) => 5,
+        }
+    }
+
+    /// PLAIN encoding of the present values among `rows`.
+    fn plain(&self, rows: std::ops::Range<usize>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        match self {
+            ColumnValues::Timestamp(values) | ColumnValues::Int64(values) => {
+                for value in values[rows].iter().flatten() {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            ColumnValues::Float(values) => {
+                for value in values[rows].iter().flatten() {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            ColumnValues::Double(values) => {
+                for value in values[rows].iter().flatten() {
+                    bytes.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            // Bit-packed, least significant bit first.
+            ColumnValues::Boolean(values) => {
+                for (index, value) in values[rows].iter().flatten().enumerate() {
+                    if index % 8 == 0 {
+                        bytes.push(0);
+                    }
+                    if *value {
+                        *bytes.last_mut().unwrap_or(&mut 0) |= 1 << (index % 8);
+                    }
+                }
+            }
+        }
+        bytes
+    }
+}
+
+type StatsOf = fn(&TelemetryAggregate) -> Option<Stats>;
+
+/// A table of equally long named columns.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParquetTable {
+    columns: Vec<(String, ColumnValues)>,
+}
+
+impl ParquetTable {
+    pub fn new() -> ParquetTable {
+        ParquetTable::default()
+    }
+
+    pub fn column(mut self, name: &str, values: ColumnValues) -> ParquetTable {
+        self.columns.push((name.to_string(), values));
+        self
+    }
+
+    pub fn rows(&self) -> usize {
+        self.columns.first().map_or(0, |(_, values)| values.len())
+    }
+
+    /// One row per sample, with the columns of the telemetry CSV plus the
+    /// delivered power and PA efficiency.
+    pub fn from_telemetry(samples: &[TelemetrySample]) -> ParquetTable {
+        let floats = |value: fn(&TelemetrySample) -> Option<f32>| {
+            ColumnValues::Float(samples.iter().map(value).collect())
+        };
+        ParquetTable::new()
+            .column(
+                "timestamp",
+                ColumnValues::Timestamp(samples.iter().map(|s| millis(s.timestamp)).collect()),
+            )
+            .column("frequency_mhz", floats(|s| Some(s.frequency_mhz)))
+            .column("power_setpoint_dbm", floats(|s| Some(s.power_setpoint_dbm)))
+            .column("forward_dbm", floats(|s| Some(s.forward_dbm)))
+            .column("reflected_dbm", floats(|s| Some(s.reflected_dbm)))
+            .column("temperature_c", floats(|s| s.temperature_c))
+            .column(
+                "delivered_w",
+                ColumnValues::Double(samples.iter().map(|s| Some(s.delivered_watts())).collect()),
+            )
+            .column(
+                "rf_enabled",
+                ColumnValues::Boolean(samples.iter().map(|s| Some(s.rf_enabled)).collect()),
+            )
+            .column("supply_voltage_v", floats(|s| s.supply_voltage_v))
+            .column("supply_current_a", floats(|s| s.supply_current_a))
+            .column(
+                "efficiency_pct",
+                ColumnValues::Double(samples.iter().map(|s| s.efficiency_percent()).collect()),
+            )
+    }
+
+    /// One row per aggregate of a downsampled log, with the columns of its CSV.
+    pub fn from_aggregates(aggregates: &[TelemetryAggregate]) -> ParquetTable {
+        let mut table = ParquetTable::new()
+            .column(
+                "start",
+                ColumnValues::Timestamp(aggregates.iter().map(|a| millis(a.start)).collect()),
+            )
+            .column(
+                "end",
+                ColumnValues::Timestamp(aggregates.iter().map(|a| millis(a.end)).collect()),
+            )
+            .column(
+                "samples",
+                ColumnValues::Int64(aggregates.iter().map(|a| Some(a.samples as i64)).collect()),
+            );
+        let quantities: [(&str, StatsOf); 8] = [
+            ("frequency", |a| Some(a.frequency_mhz)),
+            ("forward", |a| Some(a.forward_dbm)),
+            ("reflected", |a| Some(a.reflected_dbm)),
+            ("temperature", |a| a.temperature_c),
+            ("delivered_w", |a| Some(a.delivered_watts)),
+            ("supply_voltage", |a| a.supply_voltage_v),
+            ("supply_current", |a| a.supply_current_a),
+            ("efficiency", |a| a.efficiency_percent),
+        ];
+        for (name, stats) in quantities {
+            for (suffix, field) in [
+                ("min", (|s| s.min) as fn(&Stats) -> f64),
+                ("mean", |s| s.mean),
+                ("max", |s| s.max),
+            ] {
+                let values = aggregates
+                    .iter()
+                    .map(|a| stats(a).as_ref().map(field))
+                    .collect();
+                table = table.column(
+                    &format!("{}_{}", name, suffix),
+                    ColumnValues::Double(values),
+                );
+            }
+        }
+        table.column(
+            "rf_on_fraction",
+            ColumnValues::Double(aggregates.iter().map(|a| Some(a.rf_on_fraction)).collect()),
+        )
+    }
+
+    /// One row per sweep point, with the columns of `sweep --output`.
+    pub fn from_sweep(points: &[SweepPoint]) -> ParquetTable {
+        let floats = |value: fn(&SweepPoint) -> f32| {
+            ColumnValues::Float(points.iter().map(|point| Some(value(point))).collect())
+        };
+        ParquetTable::new()
+            .column("frequency_mhz", floats(|p| p.frequency_mhz))
+            .column("forward_dbm", floats(|p| p.forward_dbm))
+            .column("reflected_dbm", floats(|p| p.reflected_dbm))
+            .column("s11_db", floats(SweepPoint::s11_db))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>, compression: Compression) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, compression)?;
+        writer.flush()
+    }
+
+    pub fn write(&self, writer: &mut impl Write, compression: Compression) -> io::Result<()> {
+        let rows = self.rows();
+        if let Some((name, _)) = self.columns.iter().find(|(_, values)| values.len() != rows) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Column {} has a different length", name),
+            ));
+        }
+        let optional: Vec<bool> = self
+            .columns
+            .iter()
+            .map(|(_, values)| (0..rows).any(|row| !values.is_present(row)))
+            .collect();
+
+        writer.write_all(MAGIC)?;
+        let mut offset = MAGIC.len() as i64;
+        let mut row_groups = Vec::new();
+        for start in (0..rows.max(1)).step_by(ROW_GROUP_ROWS) {
+            let group = start..(start + ROW_GROUP_ROWS).min(rows);
+            let mut placements = Vec::new();
+            for ((_, values), &optional) in self.columns.iter().zip(&optional) {
+                let chunk = ColumnChunk::encode(values, optional, group.clone(), compression)?;
+                writer.write_all(&chunk.header)?;
+                writer.write_all(&chunk.data)?;
+                placements.push(Placement {
+                    offset,
+                    uncompressed: chunk.header.len() + chunk.uncompressed,
+                    stored: chunk.len(),
+                });
+                offset += chunk.len() as i64;
+            }
+            row_groups.push((group.len(), placements));
+        }
+
+        let metadata = self.metadata(&optional, &row_groups, compression);
+        writer.write_all(&metadata)?;
+        writer.write_all(&(metadata.len() as u32).to_le_bytes())?;
+        writer.write_all(MAGIC)
+    }
+
+    /// Thrift `FileMetaData`; each row group is its rows and where each
+    /// column's page went.
+    fn metadata(
+        &self,
+        optional: &[bool],
+        row_groups: &[(usize, Vec<Placement>)],
+        compression: Compression,
+    ) -> Vec<u8> {
+        let codec = match compression {
+            Compression::None => 0,
+            Compression::Gzip => 2,
+        };
+        let mut thrift = Thrift::default();
+        thrift.i32(1, 1);
+        thrift.list(2, STRUCT, self.columns.len() + 1);
+        thrift.begin_element();
+        thrift.binary(4, b"schema");
+        thrift.i32(5, self.columns.len() as i32);
+        thrift.end_struct();
+        for ((name, values), optional) in self.columns.iter().zip(optional) {
+            thrift.begin_element();
+            thrift.i32(1, values.physical_type());
+            thrift.i32(3, i32::from(*optional));
+            thrift.binary(4, name.as_bytes());
+            if let ColumnValues::Timestamp(_) = values {
+                // TIMESTAMP_MILLIS, and the logical type UTC milliseconds.
+                thrift.i32(6, 9);
+                thrift.begin_struct(10);
+                thrift.begin_struct(8);
+                thrift.bool(1, true);
+                thrift.begin_struct(2);
+                thrift.begin_struct(1);
+                thrift.end_struct();
+                thrift.end_struct();
+                thrift.end_struct();
+                thrift.end_struct();
+            }
+            thrift.end_struct();
+        }
+        thrift.i64(3, self.rows() as i64);
+        thrift.list(4, STRUCT, row_groups.len());
+        for (rows, chunks) in row_groups {
+            thrift.begin_element();
+            thrift.list(1, STRUCT, chunks.len());
+            for ((name, values), placement) in self.columns.iter().zip(chunks) {
+                thrift.begin_element();
+                thrift.i64(2, placement.offset);
+                thrift.begin_struct(3);
+                thrift.i32(1, values.physical_type());
+                // PLAIN values, RLE definition levels.
+                thrift.list(2, I32, 2);
+                thrift.list_i32(0);
+                thrift.list_i32(3);
+                thrift.list(3, BINARY, 1);
+                thrift.list_binary(name.as_bytes());
+                thrift.i32(4, codec);
+                thrift.i64(5, *rows as i64);
+                thrift.i64(6, placement.uncompressed as i64);
+                thrift.i64(7, placement.stored as i64);
+                thrift.i64(9, placement.offset);
+                thrift.end_struct();
+                thrift.end_struct();
+            }
+            let total: usize = chunks.iter().map(|placement| placement.uncompressed).sum();
+            thrift.i64(2, total as i64);
+            thrift.i64(3, *rows as i64);
+            thrift.end_struct();
+        }
+        let created_by = format!("microwave_controller version {}", env!("CARGO_PKG_VERSION"));
+        thrift.binary(6, created_by.as_bytes());
+        thrift.finish()
+    }
+}
+
+/// Where a column chunk was written, and its sizes with the page header.
+struct Placement {
+    offset: i64,
+    uncompressed: usize,
+    stored: usize,
+}
+
+/// One column of one row group as a single data page.
+struct ColumnChunk {
+    header: Vec<u8>,
+    data: Vec<u8>,
+    uncompressed: usize,
+}
+
+impl ColumnChunk {
+    fn encode(
+        values: &ColumnValues,
+        optional: bool,
+        rows: std::ops::Range<usize>,
+        compression: Compression,
+    ) -> io::Result<ColumnChunk> {
+        let mut page = Vec::new();
+        if optional {
+            let levels = definition_levels(rows.clone().map(|row| values.is_present(row)));
+            page.extend_from_slice(&(levels.len() as u32).to_le_bytes());
+            page.extend_from_slice(&levels);
+        }
+        page.extend_from_slice(&values.plain(rows.clone()));
+        let uncompressed = page.len();
+        let data = match compression {
+            Compression::None => page,
+            Compression::Gzip => gzip(page)?,
+        };
+
+        let mut thrift = Thrift::default();
+        thrift.i32(1, 0);
+        thrift.i32(2, uncompressed as i32);
+        thrift.i32(3, data.len() as i32);
+        thrift.begin_struct(5);
+        thrift.i32(1, rows.len() as i32);
+        thrift.i32(2, 0);
+        thrift.i32(3, 3);
+        thrift.i32(4, 3);
+        thrift.end_struct();
+        Ok(ColumnChunk {
+            header: thrift.finish(),
+            data,
+            uncompressed,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.header.len() + self.data.len()
+    }
+}
+
+/// Definition levels of a flat optional column (1 present, 0 null) in the
+/// RLE hybrid encoding with a bit width of one, as runs only.
+fn definition_levels(present: impl Iterator<Item = bool>) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut run: Option<(bool, u64)> = None;
+    let flush = |bytes: &mut Vec<u8>, (value, length): (bool, u64)| {
+        varint(bytes, length << 1);
+        bytes.push(u8::from(value));
+    };
+    for value in present {
+        run = match run {
+            Some((current, length)) if current == value => Some((current, length + 1)),
+            Some(finished) => {
+                flush(&mut bytes, finished);
+                Some((value, 1))
+            }
+            None => Some((value, 1)),
+        };
+    }
+    if let Some(finished) = run {
+        flush(&mut bytes, finished);
+    }
+    bytes
+}
+
+fn gzip(data: Vec<u8>) -> io::Result<Vec<u8>> {
+    let mut child = Command::new("gzip")
+        .arg("-c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| io::Error::new(e.kind(), format!("Failed to run gzip: {}", e)))?;
+    let mut stdin = child.stdin.take().ok_or(io::ErrorKind::BrokenPipe)?;
+    // Written from another thread so a full stdout pipe cannot deadlock us.
+    let writer = thread::spawn(move || stdin.write_all(&data));
+    let mut compressed = Vec::new();
+    child
+        .stdout
+        .take()
+        .ok_or(io::ErrorKind::BrokenPipe)?
+        .read_to_end(&mut compressed)?;
+    writer
+        .join()
+        .map_err(|_| io::Error::other("gzip writer panicked"))??;
+    if !child.wait()?.success() {
+        return Err(io::Error::other("gzip failed"));
+    }
+    Ok(compressed)
+}
+
+fn millis(timestamp: SystemTime) -> Option<i64> {
+    timestamp
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| since.as_millis() as i64)
+}
+
+fn varint(bytes: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        bytes.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    bytes.push(value as u8);
+}
+
+const I32: u8 = 5;
+const I64: u8 = 6;
+const BINARY: u8 = 8;
+const LIST: u8 = 9;
+const STRUCT: u8 = 12;
+
+/// Thrift compact protocol, enough for the Parquet footer and page headers.
+struct Thrift {
+    bytes: Vec<u8>,
+    /// Last field id written in each open struct.
+    last_ids: Vec<i16>,
+}
+
+impl Default for Thrift {
+    fn default() -> Thrift {
+        Thrift {
+            bytes: Vec::new(),
+            last_ids: vec![0],
+        }
+    }
+}
+
+impl Thrift {
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_ids.last_mut().expect("a struct is open");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.bytes.push((delta as u8) << 4 | kind);
+        } else {
+            self.bytes.push(kind);
+            varint(&mut self.bytes, zigzag(id.into()));
+        }
+        *last = id;
+    }
+
+    fn i32(&mut self, id: i16, value: i32) {
+        self.field(id, I32);
+        varint(&mut self.bytes, zigzag(value.into()));
+    }
+
+    fn i64(&mut self, id: i16, value: i64) {
+        self.field(id, I64);
+        varint(&mut self.bytes, zigzag(value));
+    }
+
+    fn bool(&mut self, id: i16, value: bool) {
+        self.field(id, if value { 1 } else { 2 });
+    }
+
+    fn binary(&mut self, id: i16, value: &[u8]) {
+        self.field(id, BINARY);
+        varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    fn begin_struct(&mut self, id: i16) {
+        self.field(id, STRUCT);
+        self.last_ids.push(0);
+    }
+
+    /// Starts a struct that is an element of a list.
+    fn begin_element(&mut self) {
+        self.last_ids.push(0);
+    }
+
+    fn end_struct(&mut self) {
+        self.bytes.push(0);
+        self.last_ids.pop();
+    }
+
+    fn list(&mut self, id: i16, element: u8, length: usize) {
+        self.field(id, LIST);
+        if length < 15 {
+            self.bytes.push((length as u8) << 4 | element);
+        } else {
+            self.bytes.push(0xf0 | element);
+            varint(&mut self.bytes, length as u64);
+        }
+    }
+
+    fn list_i32(&mut self, value: i32) {
+        varint(&mut self.bytes, zigzag(value.into()));
+    }
+
+    fn list_binary(&mut self, value: &[u8]) {
+        varint(&mut self.bytes, value.len() as u64);
+        self.bytes.extend_from_slice(value);
+    }
+
+    /// Ends the outermost struct.
+    fn finish(mut self) -> Vec<u8> {
+        self.bytes.push(0);
+        self.bytes
+    }
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
@@ -0,0 +1,192 @@
+//! Which serial ports autodetection takes for a board.
+//!
+//! The board's USB-serial adapter reports FTDI's stock VID/PID, which other
+//! FTDI cables on the same machine share. A [`PortFilter`] can narrow the
+//! match down by the strings the adapter reports: its manufacturer, its
+//! product description (the device's friendly name on Windows, e.g.
+//! `USB Serial Port (COM4)`) and its serial number.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::controller_properites::{TARGET_PRODUCT_ID, TARGET_VENDOR_ID};
+use crate::json::{FromJson, JsonValue, ToJson};
+
+/// A USB vendor and product ID, written `0403:6001` in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UsbId {
+    pub vid: u16,
+    pub pid: u16,
+}
+
+impl fmt::Display for UsbId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}:{:04x}", self.vid, self.pid)
+    }
+}
+
+impl FromStr for UsbId {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<UsbId, String> {
+        let invalid = || {
+            format!(
+                "Invalid USB ID {}; use <vid>:<pid> in hex, e.g. 0403:6001",
+                text
+            )
+        };
+        let (vid, pid) = text.trim().split_once(':').ok_or_else(invalid)?;
+        Ok(UsbId {
+            vid: u16::from_str_radix(vid, 16).map_err(|_| invalid())?,
+            pid: u16::from_str_radix(pid, 16).map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// USB IDs and description strings a board's port must match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortFilter {
+    /// The port must have one of these IDs.
+    pub usb_ids: Vec<UsbId>,
+    /// Text, ignoring case, that the manufacturer, product description or
+    /// serial number must contain; any one will do. Empty accepts every port
+    /// with a matching ID.
+    pub descriptions: Vec<String>,
+}
+
+impl Default for PortFilter {
+    /// The board's own VID/PID, whatever the strings.
+    fn default() -> PortFilter {
+        PortFilter {
+            usb_ids: vec![UsbId {
+                vid: TARGET_VENDOR_ID,
+                pid: TARGET_PRODUCT_ID,
+            }],
+            descriptions: Vec::new(),
+        }
+    }
+}
+
+impl PortFilter {
+    /// Matches `id` instead of the board's default VID/PID; call again to
+    /// accept several.
+    pub fn with_usb_id(mut self, id: UsbId) -> PortFilter {
+        if self.usb_ids == PortFilter::default().usb_ids {
+            self.usb_ids.clear();
+        }
+        if !self.usb_ids.contains(&id) {
+            self.usb_ids.push(id);
+        }
+        self
+    }
+
+    pub fn with_description(mut self, text: &str) -> PortFilter {
+        self.descriptions.push(text.to_string());
+        self
+    }
+
+    /// Whether a USB port with `id` and these strings is the board's.
+    pub fn matches_usb(&self, id: UsbId, strings: &[Option<&str>]) -> bool {
+        if !self.usb_ids.contains(&id) {
+            return false;
+        }
+        if self.descriptions.is_empty() {
+            return true;
+        }
+        let strings: Vec<String> = strings
+            .iter()
+            .flatten()
+            .map(|text| text.to_lowercase())
+            .collect();
+        self.descriptions.iter().any(|description| {
+            let description = description.to_lowercase();
+            strings.iter().any(|text| text.contains(&description))
+        })
+    }
+
+    /// Whether `port` is the board's. Ports that are not USB never match.
+    #[cfg(feature = "serial")]
+    pub fn matches(&self, port: &serialport::SerialPortInfo) -> bool {
+        match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => self.matches_usb(
+                UsbId {
+                    vid: usb.vid,
+                    pid: usb.pid,
+                },
+                &[
+                    usb.manufacturer.as_deref(),
+                    usb.product.as_deref(),
+                    usb.serial_number.as_deref(),
+                ],
+            ),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PortFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ids: Vec<String> = self.usb_ids.iter().map(UsbId::to_string).collect();
+        write!(f, "USB ID {}", ids.join(" or "))?;
+        if !self.descriptions.is_empty() {
+            write!(
+                f,
+                " described as \"{}\"",
+                self.descriptions.join("\" or \"")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for PortFilter {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with(
+                "usb_ids",
+                JsonValue::Array(
+                    self.usb_ids
+                        .iter()
+                        .map(|id| JsonValue::from(id.to_string().as_str()))
+                        .collect(),
+                ),
+            )
+            .with(
+                "descriptions",
+                JsonValue::Array(
+                    self.descriptions
+                        .iter()
+                        .map(|text| JsonValue::from(text.as_str()))
+                        .collect(),
+                ),
+            )
+    }
+}
+
+impl FromJson for PortFilter {
+    /// Either key may be left out: `usb_ids` defaults to the board's VID/PID
+    /// and `descriptions` to none.
+    fn from_json(json: &JsonValue) -> Result<PortFilter, String> {
+        let strings = |key: &str| -> Result<Vec<String>, String> {
+            match json.get(key) {
+                None | Some(JsonValue::Null) => Ok(Vec::new()),
+                Some(_) => json
+                    .array(key)?
+                    .iter()
+                    .map(|value| {
+                        value
+                            .as_str()
+                            .map(str::to_string)
+                            .ok_or_else(|| format!("{} must hold strings", key))
+                    })
+                    .collect(),
+            }
+        };
+        let mut filter = PortFilter::default();
+        for id in strings("usb_ids")? {
+            filter = filter.with_usb_id(id.parse()?);
+        }
+        filter.descriptions = strings("descriptions")?;
+        Ok(filter)
+    }
+}
@@ -0,0 +1,209 @@
+//! Advisory lock files that keep two processes off the same serial port.
+//!
+//! Locks follow the UUCP convention used by minicom and ModemManager: a
+//! file `LCK..<port>` holding the owner's PID, in `/var/lock` when it is
+//! writable and in the temporary directory otherwise. The time the lock
+//! was taken is stored on a second line for the "busy" message. A lock
+//! whose process has exited, or whose PID is 0 or unreadable, is taken
+//! over.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::error::ControllerError;
+use crate::units::format_timestamp;
+
+const SYSTEM_LOCK_DIR: &str = "/var/lock";
+
+/// How long a lock file without a readable PID is left alone, in case its
+/// owner is still writing it.
+const UNREADABLE_GRACE: Duration = Duration::from_secs(1);
+
+/// Where no process check exists, how long a lock is taken to be held.
+#[cfg(not(any(unix, windows)))]
+const ASSUMED_HELD: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Held for as long as the port is open; the lock file is removed on drop.
+#[derive(Debug)]
+pub struct PortLock {
+    path: PathBuf,
+}
+
+impl PortLock {
+    /// Locks `port_name`, or fails with [`ControllerError::Busy`] naming
+    /// the process that holds it.
+    pub fn acquire(port_name: &str) -> Result<PortLock, ControllerError> {
+        let file_name = format!("LCK..{}", port_basename(port_name));
+        let directories = lock_directories();
+        for directory in &directories {
+            let path = directory.join(&file_name);
+            if let Some(holder) = read_holder(&path) {
+                if holder.alive() {
+                    return Err(ControllerError::Busy(holder.describe(port_name)));
+                }
+                // Left behind by a process that exited without cleaning up.
+                let _ = fs::remove_file(&path);
+            }
+        }
+
+        let path = directories[0].join(&file_name);
+        let created = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .and_then(|mut file| {
+                write!(
+                    file,
+                    "{:>10}\n{}\n",
+                    std::process::id(),
+                    format_timestamp(SystemTime::now())
+                )
+            });
+        match created {
+            Ok(()) => Ok(PortLock { path }),
+            // Another process got there between the check and the create.
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let holder = read_holder(&path).map(|holder| holder.describe(port_name));
+                Err(ControllerError::Busy(holder.unwrap_or_else(|| {
+                    format!("{} is locked by {}", port_name, path.display())
+                })))
+            }
+            Err(e) => Err(ControllerError::Connection(format!(
+                "Failed to create lock file {}: {}",
+                path.display(),
+                e
+            ))),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PortLock {
+    fn drop(&mut self) {
+        if read_holder(&self.path).is_some_and(|holder| holder.pid == Some(std::process::id())) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+struct Holder {
+    /// `None` when the first line is not a PID.
+    pid: Option<u32>,
+    since: Option<String>,
+    /// Time since the lock file was last written.
+    age: Option<Duration>,
+}
+
+impl Holder {
+    fn alive(&self) -> bool {
+        match self.pid {
+            None => self.age.is_some_and(|age| age < UNREADABLE_GRACE),
+            Some(0) => false,
+            Some(pid) => pid == std::process::id() || process_exists(pid, self.age),
+        }
+    }
+
+    fn describe(&self, port_name: &str) -> String {
+        let mut text = match self.pid {
+            Some(pid) => format!("{} held by PID {}", port_name, pid),
+            None => format!("{} held by an unknown process", port_name),
+        };
+        if self.pid == Some(std::process::id()) {
+            text.push_str(" (this process)");
+        }
+        if let Some(since) = &self.since {
+            text.push_str(&format!(" since {}", since));
+        }
+        text
+    }
+}
+
+fn read_holder(path: &Path) -> Option<Holder> {
+    let text = fs::read_to_string(path).ok()?;
+    let mut lines = text.lines();
+    let pid = lines.next().and_then(|line| line.trim().parse().ok());
+    let since = lines.next().map(|line| line.trim().to_string());
+    let age = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok());
+    Some(Holder { pid, since, age })
+}
+
+/// `ttyUSB0` for `/dev/ttyUSB0`, `COM3` for `\\.\COM3`.
+fn port_basename(port_name: &str) -> &str {
+    port_name
+        .rsplit(['/', '\\'])
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(port_name)
+}
+
+/// Where locks are looked for, the one new locks go in first.
+fn lock_directories() -> Vec<PathBuf> {
+    let system = PathBuf::from(SYSTEM_LOCK_DIR);
+    let writable = fs::metadata(&system).is_ok_and(|metadata| {
+        metadata.is_dir() && !metadata.permissions().readonly() && can_create_in(&system)
+    });
+    let temporary = std::env::temp_dir();
+    match writable {
+        true => vec![system, temporary],
+        false => vec![temporary, system],
+    }
+}
+
+fn can_create_in(directory: &Path) -> bool {
+    let probe = directory.join(format!(".mwctl-probe-{}", std::process::id()));
+    let created = fs::write(&probe, b"").is_ok();
+    let _ = fs::remove_file(&probe);
+    created
+}
+
+#[cfg(unix)]
+fn process_exists(pid: u32, _age: Option<Duration>) -> bool {
+    use std::os::raw::c_int;
+    const EPERM: i32 = 1;
+    extern "C" {
+        fn kill(pid: c_int, signal: c_int) -> c_int;
+    }
+    let Ok(pid) = c_int::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists.
+    unsafe { kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(EPERM) }
+}
+
+#[cfg(windows)]
+fn process_exists(pid: u32, _age: Option<Duration>) -> bool {
+    use std::os::raw::c_void;
+    const PROCESS_QUERY_LIMITED_INFORMATION: u32 = 0x1000;
+    const STILL_ACTIVE: u32 = 259;
+    const ERROR_ACCESS_DENIED: i32 = 5;
+    extern "system" {
+        fn OpenProcess(access: u32, inherit: i32, pid: u32) -> *mut c_void;
+        fn GetExitCodeProcess(process: *mut c_void, code: *mut u32) -> i32;
+        fn CloseHandle(handle: *mut c_void) -> i32;
+    }
+    // SAFETY: the handle is only used for the exit code and closed again.
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return io::Error::last_os_error().raw_os_error() == Some(ERROR_ACCESS_DENIED);
+        }
+        let mut code = 0;
+        let running = GetExitCodeProcess(process, &mut code) != 0 && code == STILL_ACTIVE;
+        CloseHandle(process);
+        running
+    }
+}
+
+/// Without a process check, a lock expires [`ASSUMED_HELD`] after it was taken.
+#[cfg(not(any(unix, windows)))]
+fn process_exists(_pid: u32, age: Option<Duration>) -> bool {
+    age.is_some_and(|age| age < ASSUMED_HELD)
+}
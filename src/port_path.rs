@@ -0,0 +1,46 @@
+//! Stable names for serial ports on Linux.
+//!
+//! Kernel names such as `/dev/ttyUSB0` are handed out in plug order, so a
+//! board can come back as `ttyUSB1` after a replug or a reboot. udev also
+//! links every USB-serial port under `/dev/serial/by-id/` by the adapter's
+//! vendor, product and serial number, which stays the same. Profiles and
+//! scripts should name the port by that link; it is resolved to the kernel
+//! name each time the port is opened.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const BY_ID_DIR: &str = "/dev/serial/by-id";
+
+/// The device `port_name` links to, e.g. `/dev/ttyUSB1` for a
+/// `/dev/serial/by-id/` link. Names that are not links, or not paths at
+/// all such as `COM3`, are returned unchanged.
+pub fn resolve(port_name: &str) -> String {
+    let path = Path::new(port_name);
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_symlink() => fs::canonicalize(path)
+            .map(|device| device.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| port_name.to_string()),
+        _ => port_name.to_string(),
+    }
+}
+
+/// The `/dev/serial/by-id/` link to `port_name`, if udev made one.
+pub fn by_id(port_name: &str) -> Option<PathBuf> {
+    let device = fs::canonicalize(port_name).ok()?;
+    let mut links: Vec<PathBuf> = fs::read_dir(BY_ID_DIR)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|link| fs::canonicalize(link).is_ok_and(|target| target == device))
+        .collect();
+    // Composite adapters get one link per interface; take a stable one.
+    links.sort();
+    links.into_iter().next()
+}
+
+/// `port_name` as its `/dev/serial/by-id/` link where there is one.
+pub fn stable_name(port_name: &str) -> String {
+    by_id(port_name)
+        .map(|link| link.to_string_lossy().into_owned())
+        .unwrap_or_else(|| port_name.to_string())
+}
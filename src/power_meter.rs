@@ -0,0 +1,202 @@
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use crate::calibration::CalibrationTable;
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::progress::{Progress, ProgressCallback};
+use crate::simulator::Simulator;
+use crate::sweep::SweepSegment;
+use crate::transport::{TcpTransport, Transport};
+
+/// External RF power meter used as the reference during calibration.
+pub trait PowerMeter: Send {
+    /// Name shown in logs, e.g. the meter's identity string.
+    fn name(&self) -> String {
+        "power meter".to_string()
+    }
+
+    /// Tells the meter the measurement frequency so it applies its sensor's
+    /// calibration factor.
+    fn set_frequency(&mut self, frequency_mhz: f32) -> Result<(), ControllerError>;
+
+    /// Takes one reading in dBm at the sensor.
+    fn read_dbm(&mut self) -> Result<f32, ControllerError>;
+}
+
+/// Meter speaking SCPI over a line link: a USB meter enumerating as a serial
+/// port, or a LAN meter's raw socket (port 5025).
+pub struct ScpiPowerMeter<T: Transport> {
+    link: T,
+    identity: String,
+    /// How long a reading may take, including the meter's own averaging.
+    pub reply_timeout: Duration,
+}
+
+impl ScpiPowerMeter<TcpTransport> {
+    pub fn connect_tcp(addr: &str) -> Result<ScpiPowerMeter<TcpTransport>, ControllerError> {
+        ScpiPowerMeter::new(TcpTransport::connect(addr, Duration::from_millis(100))?)
+    }
+}
+
+impl<T: Transport> ScpiPowerMeter<T> {
+    /// Identifies the meter and switches it to dBm readings.
+    pub fn new(link: T) -> Result<ScpiPowerMeter<T>, ControllerError> {
+        let mut meter = ScpiPowerMeter {
+            link,
+            identity: String::new(),
+            reply_timeout: Duration::from_secs(5),
+        };
+        meter.identity = meter.query("*IDN?")?;
+        meter.write("UNIT:POW DBM")?;
+        Ok(meter)
+    }
+
+    fn write(&mut self, command: &str) -> Result<(), ControllerError> {
+        let io_error = |e: io::Error| ControllerError::Io(format!("Power meter: {:?}", e));
+        self.link
+            .write_all(format!("{}\n", command).as_bytes())
+            .map_err(io_error)?;
+        self.link.flush().map_err(io_error)
+    }
+
+    /// Sends `command` and returns its reply line. SCPI replies end with `\n`.
+    fn query(&mut self, command: &str) -> Result<String, ControllerError> {
+        self.write(command)?;
+        let deadline = Instant::now() + self.reply_timeout;
+        let mut reply = Vec::new();
+        let mut buffer = [0; 256];
+        while Instant::now() < deadline {
+            match self.link.read(&mut buffer) {
+                Ok(count) => {
+                    reply.extend_from_slice(&buffer[..count]);
+                    if let Some(end) = reply.iter().position(|&byte| byte == b'\n') {
+                        return Ok(String::from_utf8_lossy(&reply[..end]).trim().to_string());
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::TimedOut => {}
+                Err(e) => return Err(ControllerError::Io(format!("Power meter: {:?}", e))),
+            }
+        }
+        Err(ControllerError::Timeout)
+    }
+}
+
+impl<T: Transport> PowerMeter for ScpiPowerMeter<T> {
+    fn name(&self) -> String {
+        self.identity.clone()
+    }
+
+    fn set_frequency(&mut self, frequency_mhz: f32) -> Result<(), ControllerError> {
+        self.write(&format!("SENS:FREQ {} MHZ", frequency_mhz))
+    }
+
+    fn read_dbm(&mut self) -> Result<f32, ControllerError> {
+        let reply = self.query("READ?")?;
+        reply
+            .parse()
+            .map_err(|_| ControllerError::InvalidResponse(format!("Power meter: {}", reply)))
+    }
+}
+
+/// Reads the simulated board's delivered power, for trying calibration without hardware.
+impl PowerMeter for Simulator {
+    fn name(&self) -> String {
+        "simulator".to_string()
+    }
+
+    fn set_frequency(&mut self, _frequency_mhz: f32) -> Result<(), ControllerError> {
+        Ok(())
+    }
+
+    fn read_dbm(&mut self) -> Result<f32, ControllerError> {
+        Ok(self.state().measured_power().0)
+    }
+}
+
+/// Measures a [`CalibrationTable`] against a reference power meter.
+///
+/// At each frequency every power level is set in turn and the meter reading
+/// (plus `coupling_db` for the coupler or attenuator in front of the sensor)
+/// is compared with the setpoint. The table offset is the mean deviation over
+/// the power levels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalibrationRun {
+    pub segment: SweepSegment,
+    pub power_levels_dbm: Vec<f32>,
+    /// Wait after each change before reading the meter.
+    pub dwell: Duration,
+    /// Loss between the generator output and the meter sensor.
+    pub coupling_db: f32,
+}
+
+impl CalibrationRun {
+    /// Runs the calibration with RF enabled, disabling RF again when it
+    /// finishes, fails or is cancelled.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        meter: &mut dyn PowerMeter,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<CalibrationTable, ControllerError> {
+        let result = self.run_points(controller, meter, cancel, on_progress);
+        let safe = controller.safe_state();
+        let table = result?;
+        safe?;
+        Ok(table)
+    }
+
+    fn run_points(
+        &self,
+        controller: &Controller,
+        meter: &mut dyn PowerMeter,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<CalibrationTable, ControllerError> {
+        if self.power_levels_dbm.is_empty() {
+            return Err(ControllerError::InvalidResponse(
+                "Calibration needs at least one power level".to_string(),
+            ));
+        }
+        let frequencies = self.segment.frequencies();
+        let total = frequencies.len() * self.power_levels_dbm.len();
+        let start_time = Instant::now();
+        let mut points = Vec::with_capacity(frequencies.len());
+
+        controller.send(&Command::SetPower(self.power_levels_dbm[0]))?;
+        if let Some(first) = frequencies.first() {
+            controller.send(&Command::SetFrequency(*first))?;
+        }
+        controller.send(&Command::RfEnable)?;
+        for frequency_mhz in frequencies {
+            controller.send(&Command::SetFrequency(frequency_mhz))?;
+            meter.set_frequency(frequency_mhz)?;
+            let mut deviation = 0.0;
+            for (level, &power_dbm) in self.power_levels_dbm.iter().enumerate() {
+                cancel.checkpoint(controller, &[Command::RfEnable])?;
+                controller.send(&Command::SetPower(power_dbm))?;
+                if !cancel.sleep(self.dwell) {
+                    return Err(ControllerError::Cancelled);
+                }
+                deviation += meter.read_dbm()? + self.coupling_db - power_dbm;
+                on_progress(&Progress {
+                    completed: points.len() * self.power_levels_dbm.len() + level + 1,
+                    total,
+                    elapsed: start_time.elapsed(),
+                    frequency_mhz: Some(frequency_mhz),
+                    power_dbm: Some(power_dbm),
+                });
+            }
+            points.push((
+                frequency_mhz,
+                deviation / self.power_levels_dbm.len() as f32,
+            ));
+        }
+        Ok(CalibrationTable::new(points))
+    }
+}
@@ -0,0 +1,77 @@
+//! Order in which threads waiting for the link get to use it.
+//!
+//! Every exchange takes a turn on the port. When the port comes free, the
+//! waiting exchange with the highest [`Priority`] goes next, and exchanges
+//! of equal priority go in the order they arrived. `RfDisable` always
+//! waits as [`Priority::Emergency`], so an emergency stop or an interlock
+//! trip is sent as soon as the exchange in progress ends, ahead of any
+//! queued sweep points and telemetry polls.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::{Condvar, Mutex};
+
+use crate::error::ControllerError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Priority {
+    /// Telemetry polls and heartbeats, which can wait for anything else.
+    Background,
+    /// Commands from recipes, sweeps and clients.
+    #[default]
+    Normal,
+    /// RF off.
+    Emergency,
+}
+
+/// Turns on one port, shared by every clone of a controller.
+#[derive(Default)]
+pub(crate) struct PortQueue {
+    state: Mutex<QueueState>,
+    released: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    busy: bool,
+    next_ticket: u64,
+    /// Highest priority first, then lowest ticket.
+    waiting: BinaryHeap<(Priority, Reverse<u64>)>,
+}
+
+/// The port is this thread's until the turn is dropped.
+pub(crate) struct PortTurn<'a> {
+    queue: &'a PortQueue,
+}
+
+impl PortQueue {
+    /// Waits until the port is free and no waiter outranks `priority`.
+    pub(crate) fn acquire(&self, priority: Priority) -> Result<PortTurn<'_>, ControllerError> {
+        let mut state = self.state.lock().map_err(|_| ControllerError::Poisoned)?;
+        let ticket = (priority, Reverse(state.next_ticket));
+        state.next_ticket += 1;
+        state.waiting.push(ticket);
+        while state.busy || state.waiting.peek() != Some(&ticket) {
+            state = self
+                .released
+                .wait(state)
+                .map_err(|_| ControllerError::Poisoned)?;
+        }
+        state.waiting.pop();
+        state.busy = true;
+        Ok(PortTurn { queue: self })
+    }
+}
+
+impl Drop for PortTurn<'_> {
+    fn drop(&mut self) {
+        let mut state = match self.queue.state.lock() {
+            Ok(state) => state,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        state.busy = false;
+        drop(state);
+        // Every waiter checks whether it is now first.
+        self.queue.released.notify_all();
+    }
+}
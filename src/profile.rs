@@ -0,0 +1,202 @@
+//! Per-board settings that depend on the firmware revision rather than on
+//! the application, kept in a JSON file next to the board's calibration.
+
+use std::{fs, io, path::Path, time::Duration};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::dll::DllPreset;
+use crate::error::ControllerError;
+use crate::json::{self, FromJson, JsonValue, ToJson};
+use crate::latency::TimeoutBounds;
+use crate::port_filter::PortFilter;
+use crate::serial_settings::SerialSettings;
+
+/// Link settings for one kind of board, applied with [`DeviceProfile::apply`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeviceProfile {
+    pub name: String,
+    /// Quiet time enforced between a reply and the next command. Some
+    /// firmware revisions drop commands that arrive back-to-back.
+    pub min_command_interval: Duration,
+    /// Bounds for reply timeouts learned from the board's latency, see
+    /// [`Controller::set_adaptive_timeout`]. `None` keeps the fixed timeout.
+    pub adaptive_timeout: Option<TimeoutBounds>,
+    /// UART settings for boards that do not run at 115200 8N1. They are
+    /// used when the port is opened, so [`DeviceProfile::apply`] only takes
+    /// the response timeout from them.
+    pub serial: Option<SerialSettings>,
+    /// The board's serial port, used when none is given. A
+    /// `/dev/serial/by-id/` link keeps working after the board is replugged
+    /// and comes back under another kernel name.
+    pub port: Option<String>,
+    /// Which port autodetection takes for this board, for adapters whose
+    /// VID/PID other devices share. `None` matches the VID/PID alone.
+    pub port_filter: Option<PortFilter>,
+    /// Text the `$IDN` reply must contain before set commands are allowed,
+    /// see [`Controller::verify_identity`].
+    pub family: Option<String>,
+    /// Commands sent on every connection to put the board into a known
+    /// state, for example `$ERRC`, `$ECS,0,0` and a default frequency.
+    pub init_commands: Vec<Command>,
+    /// DLL parameter sets validated on this board, applied by name.
+    pub dll_presets: Vec<DllPreset>,
+}
+
+impl DeviceProfile {
+    pub fn new(name: &str) -> DeviceProfile {
+        DeviceProfile {
+            name: name.to_string(),
+            ..DeviceProfile::default()
+        }
+    }
+
+    pub fn with_min_command_interval(mut self, interval: Duration) -> DeviceProfile {
+        self.min_command_interval = interval;
+        self
+    }
+
+    pub fn with_adaptive_timeout(mut self, bounds: TimeoutBounds) -> DeviceProfile {
+        self.adaptive_timeout = Some(bounds);
+        self
+    }
+
+    pub fn with_serial(mut self, settings: SerialSettings) -> DeviceProfile {
+        self.serial = Some(settings);
+        self
+    }
+
+    pub fn with_port(mut self, port_name: &str) -> DeviceProfile {
+        self.port = Some(port_name.to_string());
+        self
+    }
+
+    pub fn with_port_filter(mut self, filter: PortFilter) -> DeviceProfile {
+        self.port_filter = Some(filter);
+        self
+    }
+
+    pub fn with_family(mut self, family: &str) -> DeviceProfile {
+        self.family = Some(family.to_string());
+        self
+    }
+
+    pub fn init_command(mut self, command: Command) -> DeviceProfile {
+        self.init_commands.push(command);
+        self
+    }
+
+    pub fn dll_preset(mut self, preset: DllPreset) -> DeviceProfile {
+        self.dll_presets.push(preset);
+        self
+    }
+
+    pub fn find_dll_preset(&self, name: &str) -> Option<&DllPreset> {
+        self.dll_presets.iter().find(|preset| preset.name == name)
+    }
+
+    /// Configures `controller` for this board, verifies its identity and
+    /// sends the initialization commands in order, stopping at the first
+    /// step that fails. A read-only controller skips the initialization.
+    pub fn apply(&self, controller: &Controller) -> Result<(), ControllerError> {
+        controller.set_min_command_interval(self.min_command_interval);
+        if self.adaptive_timeout.is_some() {
+            controller.set_adaptive_timeout(self.adaptive_timeout);
+        }
+        if let Some(serial) = &self.serial {
+            controller.set_response_timeout(serial.response_timeout);
+        }
+        if let Some(family) = &self.family {
+            controller.verify_identity(family)?;
+        }
+        if controller.is_read_only() {
+            return Ok(());
+        }
+        for command in &self.init_commands {
+            command.validate()?;
+            controller.send(command)?;
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<DeviceProfile> {
+        let text = fs::read_to_string(path)?;
+        json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json().to_pretty_string() + "\n")
+    }
+}
+
+impl ToJson for DeviceProfile {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("name", self.name.as_str())
+            .with("min_command_interval_s", self.min_command_interval)
+            .with(
+                "adaptive_timeout",
+                self.adaptive_timeout.as_ref().map(ToJson::to_json),
+            )
+            .with("serial", self.serial.as_ref().map(ToJson::to_json))
+            .with("port", self.port.clone())
+            .with("port_match", self.port_filter.as_ref().map(ToJson::to_json))
+            .with("family", self.family.clone())
+            .with(
+                "init",
+                JsonValue::Array(self.init_commands.iter().map(ToJson::to_json).collect()),
+            )
+            .with(
+                "dll_presets",
+                JsonValue::Array(self.dll_presets.iter().map(ToJson::to_json).collect()),
+            )
+    }
+}
+
+impl FromJson for DeviceProfile {
+    fn from_json(json: &JsonValue) -> Result<DeviceProfile, String> {
+        Ok(DeviceProfile {
+            name: json.string("name")?.to_string(),
+            min_command_interval: match json.get("min_command_interval_s") {
+                None | Some(JsonValue::Null) => Duration::ZERO,
+                Some(_) => json.duration("min_command_interval_s")?,
+            },
+            adaptive_timeout: match json.get("adaptive_timeout") {
+                None | Some(JsonValue::Null) => None,
+                Some(bounds) => Some(TimeoutBounds::from_json(bounds)?),
+            },
+            serial: match json.get("serial") {
+                None | Some(JsonValue::Null) => None,
+                Some(settings) => Some(SerialSettings::from_json(settings)?),
+            },
+            port: match json.get("port") {
+                None | Some(JsonValue::Null) => None,
+                Some(_) => Some(json.string("port")?.to_string()),
+            },
+            port_filter: match json.get("port_match") {
+                None | Some(JsonValue::Null) => None,
+                Some(filter) => Some(PortFilter::from_json(filter)?),
+            },
+            family: match json.get("family") {
+                None | Some(JsonValue::Null) => None,
+                Some(_) => Some(json.string("family")?.to_string()),
+            },
+            init_commands: match json.get("init") {
+                None | Some(JsonValue::Null) => Vec::new(),
+                Some(_) => json
+                    .array("init")?
+                    .iter()
+                    .map(Command::from_json)
+                    .collect::<Result<_, _>>()?,
+            },
+            dll_presets: match json.get("dll_presets") {
+                None | Some(JsonValue::Null) => Vec::new(),
+                Some(_) => json
+                    .array("dll_presets")?
+                    .iter()
+                    .map(DllPreset::from_json)
+                    .collect::<Result<_, _>>()?,
+            },
+        })
+    }
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+/// Progress snapshot reported by long running operations after each point.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub completed: usize,
+    pub total: usize,
+    pub elapsed: Duration,
+    /// Frequency of the point just completed, if the operation steps frequency.
+    pub frequency_mhz: Option<f32>,
+    /// Power setpoint of the point just completed.
+    pub power_dbm: Option<f32>,
+}
+
+impl Progress {
+    /// Completed fraction in `0.0..=1.0`.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            1.0
+        } else {
+            self.completed as f32 / self.total as f32
+        }
+    }
+
+    pub fn percent(&self) -> f32 {
+        self.fraction() * 100.0
+    }
+
+    /// Estimated time remaining, extrapolated from the average time per point so far.
+    pub fn eta(&self) -> Option<Duration> {
+        if self.completed == 0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.completed) as u32;
+        Some(self.elapsed / self.completed as u32 * remaining)
+    }
+}
+
+/// Progress sink accepted by sweeps and ramps.
+///
+/// Any `FnMut(&Progress)` works, so a channel can be used with
+/// `&mut |p: &Progress| { let _ = tx.send(*p); }`.
+pub type ProgressCallback<'a> = &'a mut dyn FnMut(&Progress);
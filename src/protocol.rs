@@ -0,0 +1,658 @@
+//! Allocation-free protocol core: command formatting, reply parsing and
+//! status decoding for the ISC board's line protocol.
+//!
+//! This module only depends on `core`, so it can be included unchanged (for
+//! example with `#[path = ".../protocol.rs"] mod protocol;`) in a `#![no_std]`
+//! firmware crate for a supervisor MCU sharing the board. The `std` side of
+//! the crate builds [`parse_values`] and the controller on top of it.
+//! [`Command`] formats with `Display` and reads lines back, e.g. from session
+//! logs, with `FromStr`.
+//!
+//! [`parse_values`]: crate::controller_responses::parse_values
+
+use core::fmt::{self, Write};
+use core::ops::RangeInclusive;
+
+/// Terminator of every command and reply line.
+pub const LINE_TERMINATOR: &str = "\r\n";
+
+/// Longest command line produced by [`Command::write_to`], terminator included.
+pub const MAX_COMMAND_LEN: usize = 96;
+
+/// Frequencies accepted by the validated constructors (the 2.45 GHz ISM band).
+pub const FREQUENCY_RANGE_MHZ: RangeInclusive<f32> = 2400.0..=2500.0;
+
+/// Power setpoints accepted by the validated constructors.
+pub const POWER_RANGE_DBM: RangeInclusive<f32> = 0.0..=53.0;
+
+/// Fan duty cycles accepted by the validated constructors.
+pub const FAN_SPEED_RANGE_PERCENT: RangeInclusive<f32> = 0.0..=100.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    GetIdentity,
+    GetVersion,
+    GetStatus {
+        verbose: bool,
+    },
+    ClearErrors,
+    GetFrequency,
+    SetFrequency(f32),
+    GetPaPower,
+    GetPowerSetpoint,
+    SetPower(f32),
+    ConfigureDll {
+        param1: f32,
+        param2: f32,
+        param3: f32,
+        param4: f32,
+        param5: f32,
+        param6: f32,
+    },
+    DllEnable,
+    DllDisable,
+    RfEnable,
+    RfDisable,
+    SweepDbm {
+        start: f32,
+        stop: f32,
+        step: f32,
+        /// Power of the sweep; mainwindow.cpp fills this field from its dBm box.
+        power_dbm: f32,
+    },
+    /// PA temperature, °C. Like the supply readings, not in the vendor's
+    /// command set and unverified on hardware.
+    GetPaTemperature,
+    /// Voltage of the DC rail feeding the PA, V.
+    GetSupplyVoltage,
+    /// Current drawn from the DC rail feeding the PA, A.
+    GetSupplyCurrent,
+    /// Duty cycle of the cooling fans, %. Not in the vendor's command set;
+    /// `$FNG` and `$FNS` are unverified on hardware.
+    GetFanSpeed,
+    /// Sent to the board in whole percent.
+    SetFanSpeed(f32),
+    /// Locks the board's front panel out, so only the serial link can
+    /// change it. Not in the vendor's command set; `$RLS` and `$RLG` are
+    /// unverified on hardware.
+    LockLocal,
+    UnlockLocal,
+    /// 1 while the front panel is locked out.
+    GetLocalLockout,
+}
+
+impl Command {
+    /// `SetFrequency`, checked against [`FREQUENCY_RANGE_MHZ`].
+    pub fn set_frequency(mhz: f32) -> Result<Command, ValidationError> {
+        check_range("frequency", mhz, &FREQUENCY_RANGE_MHZ)?;
+        Ok(Command::SetFrequency(mhz))
+    }
+
+    /// `SetPower`, checked against [`POWER_RANGE_DBM`].
+    pub fn set_power(dbm: f32) -> Result<Command, ValidationError> {
+        check_range("power", dbm, &POWER_RANGE_DBM)?;
+        Ok(Command::SetPower(dbm))
+    }
+
+    /// `SetFanSpeed`, checked against [`FAN_SPEED_RANGE_PERCENT`].
+    pub fn set_fan_speed(percent: f32) -> Result<Command, ValidationError> {
+        check_range("fan speed", percent, &FAN_SPEED_RANGE_PERCENT)?;
+        Ok(Command::SetFanSpeed(percent))
+    }
+
+    /// `SweepDbm` over `start..=stop` MHz at `power_dbm`. Both ends must lie
+    /// in [`FREQUENCY_RANGE_MHZ`], the step must be positive and the power
+    /// must lie in [`POWER_RANGE_DBM`].
+    pub fn sweep_dbm(
+        start: f32,
+        stop: f32,
+        step: f32,
+        power_dbm: f32,
+    ) -> Result<Command, ValidationError> {
+        let command = Command::SweepDbm {
+            start,
+            stop,
+            step,
+            power_dbm,
+        };
+        command.validate()?;
+        Ok(command)
+    }
+
+    /// Checks the parameters as the validated constructors do. Commands
+    /// without parameters are always valid; `ConfigureDll` values only need
+    /// to be finite.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        match *self {
+            Command::SetFrequency(mhz) => check_range("frequency", mhz, &FREQUENCY_RANGE_MHZ),
+            Command::SetPower(dbm) => check_range("power", dbm, &POWER_RANGE_DBM),
+            Command::SetFanSpeed(percent) => {
+                check_range("fan speed", percent, &FAN_SPEED_RANGE_PERCENT)
+            }
+            Command::ConfigureDll {
+                param1,
+                param2,
+                param3,
+                param4,
+                param5,
+                param6,
+            } => [param1, param2, param3, param4, param5, param6]
+                .into_iter()
+                .try_for_each(|value| check_finite("DLL parameter", value)),
+            Command::SweepDbm {
+                start,
+                stop,
+                step,
+                power_dbm,
+            } => {
+                check_range("start frequency", start, &FREQUENCY_RANGE_MHZ)?;
+                check_range("stop frequency", stop, &FREQUENCY_RANGE_MHZ)?;
+                if start > stop {
+                    return Err(ValidationError::InvertedRange { start, stop });
+                }
+                check_finite("step", step)?;
+                if step <= 0.0 {
+                    return Err(ValidationError::NotPositive("step"));
+                }
+                check_range("sweep power", power_dbm, &POWER_RANGE_DBM)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Formats the command line, without terminator, into `out`.
+    pub fn write_to<W: Write>(&self, out: &mut W) -> fmt::Result {
+        match self {
+            Command::GetIdentity => out.write_str("$IDN,0"),
+            Command::GetVersion => out.write_str("$VER,0"),
+            Command::GetStatus { verbose } => {
+                if *verbose {
+                    out.write_str("$ST,0,1")
+                } else {
+                    out.write_str("$ST,0")
+                }
+            }
+            Command::ClearErrors => out.write_str("$ERRC,0"),
+            Command::GetFrequency => out.write_str("$FCG,0"),
+            Command::SetFrequency(value) => write!(out, "$FCS,0,{:.2}", value),
+            Command::GetPaPower => out.write_str("$PPG,0"),
+            Command::GetPowerSetpoint => out.write_str("$PWRG,0"),
+            Command::SetPower(value) => write!(out, "$PWRS,0,{:.2}", value),
+            Command::ConfigureDll {
+                param1,
+                param2,
+                param3,
+                param4,
+                param5,
+                param6,
+            } => write!(
+                out,
+                "$DLES,0,{:.2},{:.2},{:.2},{:.2},{:.2},{:.2}",
+                param1, param2, param3, param4, param5, param6
+            ),
+            Command::DllEnable => out.write_str("$DLES,0,1"),
+            Command::DllDisable => out.write_str("$DLES,0,0"),
+            Command::RfEnable => out.write_str("$ECS,0,1"),
+            Command::RfDisable => out.write_str("$ECS,0,0"),
+            Command::SweepDbm {
+                start,
+                stop,
+                step,
+                power_dbm,
+            } => write!(
+                out,
+                "$SWPD,0,{:.2},{:.2},{:.2},{:.2},0",
+                start, stop, step, power_dbm
+            ),
+            Command::GetPaTemperature => out.write_str("$PTG,0"),
+            Command::GetSupplyVoltage => out.write_str("$PVG,0"),
+            Command::GetSupplyCurrent => out.write_str("$PCG,0"),
+            Command::GetFanSpeed => out.write_str("$FNG,0"),
+            Command::SetFanSpeed(value) => write!(out, "$FNS,0,{:.0}", value),
+            Command::LockLocal => out.write_str("$RLS,0,1"),
+            Command::UnlockLocal => out.write_str("$RLS,0,0"),
+            Command::GetLocalLockout => out.write_str("$RLG,0"),
+        }
+    }
+
+    /// Parses a command line as written by [`Command::write_to`], the inverse
+    /// of formatting. A trailing terminator is ignored.
+    ///
+    /// `$DLES,0,1` and `$DLES,0,0` parse as [`Command::DllEnable`] and
+    /// [`Command::DllDisable`]; six fields make a [`Command::ConfigureDll`].
+    pub fn parse(line: &str) -> Result<Command, ProtocolError> {
+        let mut parts = line.trim().split(',').map(str::trim);
+        let mnemonic = parts.next().unwrap_or_default();
+        if parts.next() != Some("0") {
+            return Err(ProtocolError::Malformed);
+        }
+        let mut fields = [""; 6];
+        let mut values = [0.0f32; 6];
+        let mut count = 0;
+        for part in parts {
+            let slot = values.get_mut(count).ok_or(ProtocolError::Malformed)?;
+            *slot = part.parse().map_err(|_| ProtocolError::Malformed)?;
+            fields[count] = part;
+            count += 1;
+        }
+        let [a, b, c, d, e, f] = values;
+        let command = match (mnemonic, count) {
+            ("$IDN", 0) => Command::GetIdentity,
+            ("$VER", 0) => Command::GetVersion,
+            ("$ST", 0) => Command::GetStatus { verbose: false },
+            ("$ST", 1) if a == 1.0 => Command::GetStatus { verbose: true },
+            ("$ERRC", 0) => Command::ClearErrors,
+            ("$FCG", 0) => Command::GetFrequency,
+            ("$FCS", 1) => Command::SetFrequency(a),
+            ("$PPG", 0) => Command::GetPaPower,
+            ("$PWRG", 0) => Command::GetPowerSetpoint,
+            ("$PWRS", 1) => Command::SetPower(a),
+            ("$DLES", 1) if a == 1.0 => Command::DllEnable,
+            ("$DLES", 1) if a == 0.0 => Command::DllDisable,
+            ("$DLES", 6) => Command::ConfigureDll {
+                param1: a,
+                param2: b,
+                param3: c,
+                param4: d,
+                param5: e,
+                param6: f,
+            },
+            ("$ECS", 1) if a == 1.0 => Command::RfEnable,
+            ("$ECS", 1) if a == 0.0 => Command::RfDisable,
+            ("$SWPD", 5) if e == 0.0 => Command::SweepDbm {
+                start: a,
+                stop: b,
+                step: c,
+                power_dbm: d,
+            },
+            ("$PTG", 0) => Command::GetPaTemperature,
+            ("$PVG", 0) => Command::GetSupplyVoltage,
+            ("$PCG", 0) => Command::GetSupplyCurrent,
+            ("$FNG", 0) => Command::GetFanSpeed,
+            ("$FNS", 1) => Command::SetFanSpeed(a),
+            ("$RLS", 1) if a == 1.0 => Command::LockLocal,
+            ("$RLS", 1) if a == 0.0 => Command::UnlockLocal,
+            ("$RLG", 0) => Command::GetLocalLockout,
+            _ => return Err(ProtocolError::Malformed),
+        };
+        Ok(command)
+    }
+
+    /// Formats the terminated wire line into a stack buffer.
+    pub fn encode(&self) -> Result<LineBuffer<MAX_COMMAND_LEN>, ProtocolError> {
+        let mut line = LineBuffer::new();
+        self.write_to(&mut line)
+            .and_then(|_| line.write_str(LINE_TERMINATOR))
+            .map_err(|_| ProtocolError::BufferFull)?;
+        Ok(line)
+    }
+
+    /// Whether the command only reads from the board and changes nothing.
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            Command::GetIdentity
+                | Command::GetVersion
+                | Command::GetStatus { .. }
+                | Command::GetFrequency
+                | Command::GetPaPower
+                | Command::GetPowerSetpoint
+                | Command::GetPaTemperature
+                | Command::GetSupplyVoltage
+                | Command::GetSupplyCurrent
+                | Command::GetFanSpeed
+                | Command::GetLocalLockout
+        )
+    }
+
+    /// Stable snake_case name, used for logs and the JSON `command` field.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::GetIdentity => "get_identity",
+            Command::GetVersion => "get_version",
+            Command::GetStatus { .. } => "get_status",
+            Command::ClearErrors => "clear_errors",
+            Command::GetFrequency => "get_frequency",
+            Command::SetFrequency(_) => "set_frequency",
+            Command::GetPaPower => "get_pa_power",
+            Command::GetPowerSetpoint => "get_power_setpoint",
+            Command::SetPower(_) => "set_power",
+            Command::ConfigureDll { .. } => "configure_dll",
+            Command::DllEnable => "dll_enable",
+            Command::DllDisable => "dll_disable",
+            Command::RfEnable => "rf_enable",
+            Command::RfDisable => "rf_disable",
+            Command::SweepDbm { .. } => "sweep_dbm",
+            Command::GetPaTemperature => "get_pa_temperature",
+            Command::GetSupplyVoltage => "get_supply_voltage",
+            Command::GetSupplyCurrent => "get_supply_current",
+            Command::GetFanSpeed => "get_fan_speed",
+            Command::SetFanSpeed(_) => "set_fan_speed",
+            Command::LockLocal => "lock_local",
+            Command::UnlockLocal => "unlock_local",
+            Command::GetLocalLockout => "get_local_lockout",
+        }
+    }
+}
+
+/// Formats the command line without terminator, as [`Command::write_to`].
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write_to(f)
+    }
+}
+
+/// Parses a command line, as [`Command::parse`].
+impl core::str::FromStr for Command {
+    type Err = ProtocolError;
+
+    fn from_str(line: &str) -> Result<Command, ProtocolError> {
+        Command::parse(line)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProtocolError {
+    /// The line is not a known `$MNEMONIC,<channel>,...` line or a field is not numeric.
+    Malformed,
+    /// The board answered with an error reply.
+    Device,
+    /// The output buffer is too small.
+    BufferFull,
+}
+
+impl fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProtocolError::Malformed => write!(f, "Malformed reply"),
+            ProtocolError::Device => write!(f, "Device reported an error"),
+            ProtocolError::BufferFull => write!(f, "Buffer too small"),
+        }
+    }
+}
+
+/// A command parameter rejected by [`Command::validate`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValidationError {
+    /// The named parameter is NaN or infinite.
+    NotFinite(&'static str),
+    /// The named parameter is outside the range the board accepts.
+    OutOfRange {
+        parameter: &'static str,
+        value: f32,
+        min: f32,
+        max: f32,
+    },
+    /// A sweep starts above its stop frequency.
+    InvertedRange { start: f32, stop: f32 },
+    /// The named parameter must be greater than zero.
+    NotPositive(&'static str),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::NotFinite(parameter) => write!(f, "The {} is not a number", parameter),
+            ValidationError::OutOfRange {
+                parameter,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "The {} {} is outside {}..={}",
+                parameter, value, min, max
+            ),
+            ValidationError::InvertedRange { start, stop } => {
+                write!(f, "The sweep start {} is above its stop {}", start, stop)
+            }
+            ValidationError::NotPositive(parameter) => {
+                write!(f, "The {} must be greater than zero", parameter)
+            }
+        }
+    }
+}
+
+fn check_finite(parameter: &'static str, value: f32) -> Result<(), ValidationError> {
+    if value.is_finite() {
+        Ok(())
+    } else {
+        Err(ValidationError::NotFinite(parameter))
+    }
+}
+
+fn check_range(
+    parameter: &'static str,
+    value: f32,
+    range: &RangeInclusive<f32>,
+) -> Result<(), ValidationError> {
+    check_finite(parameter, value)?;
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(ValidationError::OutOfRange {
+            parameter,
+            value,
+            min: *range.start(),
+            max: *range.end(),
+        })
+    }
+}
+
+/// Fixed capacity text buffer implementing [`fmt::Write`].
+pub struct LineBuffer<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> LineBuffer<N> {
+        LineBuffer {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    pub fn as_str(&self) -> &str {
+        // Only whole `&str`s are ever copied in, so the contents stay valid UTF-8.
+        core::str::from_utf8(self.as_bytes()).unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        LineBuffer::new()
+    }
+}
+
+impl<const N: usize> Write for LineBuffer<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Borrowed view of a `$MNEMONIC,<channel>,<fields...>` reply line.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Reply<'a> {
+    line: &'a str,
+}
+
+impl<'a> Reply<'a> {
+    /// Trims the terminator and checks the leading `$` mnemonic. Error replies
+    /// parse successfully; check [`Reply::is_error`].
+    pub fn parse(line: &'a str) -> Result<Reply<'a>, ProtocolError> {
+        let line = line.trim();
+        if !line.starts_with('$') {
+            return Err(ProtocolError::Malformed);
+        }
+        Ok(Reply { line })
+    }
+
+    pub fn line(&self) -> &'a str {
+        self.line
+    }
+
+    pub fn mnemonic(&self) -> &'a str {
+        self.line.split(',').next().unwrap_or_default().trim()
+    }
+
+    pub fn channel(&self) -> Option<&'a str> {
+        self.line.split(',').nth(1).map(str::trim)
+    }
+
+    pub fn is_error(&self) -> bool {
+        is_error_reply(self.line)
+    }
+
+    /// Fields after the channel.
+    pub fn fields(&self) -> impl Iterator<Item = &'a str> {
+        self.line.split(',').skip(2).map(str::trim)
+    }
+
+    /// Fields after the channel parsed as numbers.
+    pub fn values(&self) -> impl Iterator<Item = Result<f32, ProtocolError>> + 'a {
+        self.line
+            .split(',')
+            .skip(2)
+            .map(|field| field.trim().parse().map_err(|_| ProtocolError::Malformed))
+    }
+
+    /// First numeric value, failing on error replies.
+    pub fn value(&self) -> Result<f32, ProtocolError> {
+        if self.is_error() {
+            return Err(ProtocolError::Device);
+        }
+        self.values()
+            .next()
+            .unwrap_or(Err(ProtocolError::Malformed))
+    }
+
+    /// Parses the numeric values into `out` and returns how many were written.
+    pub fn values_into(&self, out: &mut [f32]) -> Result<usize, ProtocolError> {
+        if self.is_error() {
+            return Err(ProtocolError::Device);
+        }
+        let mut count = 0;
+        for value in self.values() {
+            let slot = out.get_mut(count).ok_or(ProtocolError::BufferFull)?;
+            *slot = value?;
+            count += 1;
+        }
+        Ok(count)
+    }
+}
+
+/// The board flags failed commands with an `ERR` field in the reply.
+///
+/// Only fields are checked, not the mnemonic, so `$ERRC,0,OK` is a success.
+pub fn is_error_reply(line: &str) -> bool {
+    line.split(',')
+        .map(str::trim)
+        .any(|field| !field.starts_with('$') && field.starts_with("ERR"))
+}
+
+/// Error bits of the `$ST` status word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct StatusFlags(pub u32);
+
+impl StatusFlags {
+    pub const HIGH_TEMPERATURE: u32 = 1 << 0;
+    pub const SHUTDOWN_TEMPERATURE: u32 = 1 << 1;
+    pub const HIGH_REFLECTION: u32 = 1 << 2;
+    pub const SHUTDOWN_REFLECTION: u32 = 1 << 3;
+    pub const RESET_DETECTED: u32 = 1 << 4;
+    pub const TEMPERATURE_READ_ERROR: u32 = 1 << 5;
+    pub const POWER_MEASUREMENT_FAILURE: u32 = 1 << 6;
+    pub const RF_ENABLE_FAILURE: u32 = 1 << 7;
+    pub const MULTIPLEXER_FAILURE: u32 = 1 << 8;
+    pub const EXTERNAL_SHUTDOWN: u32 = 1 << 9;
+    pub const OUT_OF_MEMORY: u32 = 1 << 10;
+    pub const I2C_ERROR: u32 = 1 << 11;
+    pub const SPI_ERROR: u32 = 1 << 12;
+    pub const IQ_CONVERSION_ERROR: u32 = 1 << 13;
+    pub const SOA_MEASUREMENT_ERROR: u32 = 1 << 14;
+    pub const WATCHDOG_TIMEOUT: u32 = 1 << 15;
+    pub const CALIBRATION_MISSING: u32 = 1 << 16;
+
+    const NAMES: [(u32, &'static str); 17] = [
+        (Self::HIGH_TEMPERATURE, "high PA temperature"),
+        (Self::SHUTDOWN_TEMPERATURE, "PA temperature shutdown"),
+        (Self::HIGH_REFLECTION, "high reflected power"),
+        (Self::SHUTDOWN_REFLECTION, "reflected power shutdown"),
+        (Self::RESET_DETECTED, "reset detected"),
+        (Self::TEMPERATURE_READ_ERROR, "temperature read error"),
+        (Self::POWER_MEASUREMENT_FAILURE, "power measurement failure"),
+        (Self::RF_ENABLE_FAILURE, "RF enable failure"),
+        (Self::MULTIPLEXER_FAILURE, "multiplexer failure"),
+        (Self::EXTERNAL_SHUTDOWN, "external shutdown"),
+        (Self::OUT_OF_MEMORY, "out of memory"),
+        (Self::I2C_ERROR, "I2C communication error"),
+        (Self::SPI_ERROR, "SPI communication error"),
+        (Self::IQ_CONVERSION_ERROR, "IQ conversion error"),
+        (Self::SOA_MEASUREMENT_ERROR, "SOA measurement error"),
+        (Self::WATCHDOG_TIMEOUT, "external watchdog timeout"),
+        (Self::CALIBRATION_MISSING, "calibration missing"),
+    ];
+
+    /// Decodes a `$ST,<channel>,<code>` reply. The code may be decimal or
+    /// `0x` prefixed hexadecimal.
+    pub fn from_reply(line: &str) -> Result<StatusFlags, ProtocolError> {
+        let reply = Reply::parse(line)?;
+        let code = reply.fields().next().ok_or(ProtocolError::Malformed)?;
+        let code = match code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => code.parse(),
+        };
+        code.map(StatusFlags).map_err(|_| ProtocolError::Malformed)
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn contains(&self, flag: u32) -> bool {
+        self.0 & flag == flag
+    }
+
+    /// Whether the board has shut RF down on its own.
+    pub fn is_shutdown(&self) -> bool {
+        self.0 & (Self::SHUTDOWN_TEMPERATURE | Self::SHUTDOWN_REFLECTION | Self::EXTERNAL_SHUTDOWN)
+            != 0
+    }
+
+    /// Descriptions of the set bits. Bits without a known meaning are skipped.
+    pub fn descriptions(&self) -> impl Iterator<Item = &'static str> {
+        let code = self.0;
+        Self::NAMES
+            .into_iter()
+            .filter(move |(bit, _)| code & bit != 0)
+            .map(|(_, name)| name)
+    }
+}
+
+impl fmt::Display for StatusFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_ok() {
+            return write!(f, "OK");
+        }
+        write!(f, "0x{:X}", self.0)?;
+        for (i, name) in self.descriptions().enumerate() {
+            write!(f, "{}{}", if i == 0 { ": " } else { ", " }, name)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,147 @@
+//! The simulator behind a pseudo-terminal, for software that only talks to
+//! serial devices.
+//!
+//! [`VirtualDevice`] creates a pty pair and answers the command lines
+//! written to its slave side, e.g. `/dev/pts/4`, with a [`Simulator`], so
+//! vendor tools and third-party programs can be pointed at a board that is
+//! not there. The pty is put in raw mode with the system `stty` tool before
+//! anyone opens it, as a real USB-serial port would be; baud rate and
+//! framing settings made by the client are accepted and have no effect.
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd};
+use std::os::raw::{c_char, c_int, c_short};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::cancel::CancellationToken;
+use crate::framing::LineFramer;
+use crate::simulator::Simulator;
+
+const O_RDWR: c_int = 2;
+#[cfg(target_os = "linux")]
+const O_NOCTTY: c_int = 0o400;
+#[cfg(not(target_os = "linux"))]
+const O_NOCTTY: c_int = 0x20000;
+const POLLIN: c_short = 1;
+#[cfg(target_os = "linux")]
+type NfdsT = std::os::raw::c_ulong;
+#[cfg(not(target_os = "linux"))]
+type NfdsT = std::os::raw::c_uint;
+/// How often [`VirtualDevice::serve`] checks for cancellation while idle.
+const POLL_INTERVAL_MS: c_int = 100;
+
+#[repr(C)]
+struct PollFd {
+    fd: c_int,
+    events: c_short,
+    revents: c_short,
+}
+
+extern "C" {
+    fn posix_openpt(flags: c_int) -> c_int;
+    fn grantpt(fd: c_int) -> c_int;
+    fn unlockpt(fd: c_int) -> c_int;
+    fn ptsname(fd: c_int) -> *mut c_char;
+    fn poll(fds: *mut PollFd, count: NfdsT, timeout_ms: c_int) -> c_int;
+}
+
+/// A pseudo-terminal answered by a simulated board.
+pub struct VirtualDevice {
+    master: File,
+    /// Held open so the pty survives clients closing their end, and keeps
+    /// the raw mode set on it.
+    _slave: File,
+    path: PathBuf,
+}
+
+impl VirtualDevice {
+    /// Creates the pty. Nothing answers until [`serve`](VirtualDevice::serve).
+    pub fn open() -> io::Result<VirtualDevice> {
+        // SAFETY: plain libc calls on a descriptor owned here; `ptsname`'s
+        // static buffer is copied out before any other pty call.
+        let (master, path) = unsafe {
+            let fd = posix_openpt(O_RDWR | O_NOCTTY);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let master = File::from_raw_fd(fd);
+            if grantpt(fd) != 0 || unlockpt(fd) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let name = ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+            (master, path)
+        };
+        let slave = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(O_NOCTTY)
+            .open(&path)?;
+        let status = Command::new("stty")
+            .args(["raw", "-echo"])
+            .stdin(slave.try_clone()?)
+            .status()?;
+        if !status.success() {
+            return Err(io::Error::other(format!(
+                "stty could not set {} to raw mode",
+                path.display()
+            )));
+        }
+        Ok(VirtualDevice {
+            master,
+            _slave: slave,
+            path,
+        })
+    }
+
+    /// The slave side to give to the client, e.g. `/dev/pts/4`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Answers command lines with `simulator` until `cancel` is cancelled,
+    /// passing each line and its reply to `on_exchange`.
+    pub fn serve(
+        &self,
+        simulator: &Simulator,
+        cancel: &CancellationToken,
+        on_exchange: &mut dyn FnMut(&str, &str),
+    ) -> io::Result<()> {
+        let mut master = &self.master;
+        let mut framer = LineFramer::new();
+        let mut buffer = [0; 256];
+        while !cancel.is_cancelled() {
+            let mut fds = PollFd {
+                fd: master.as_raw_fd(),
+                events: POLLIN,
+                revents: 0,
+            };
+            // SAFETY: one valid pollfd for the duration of the call.
+            let ready = unsafe { poll(&mut fds, 1, POLL_INTERVAL_MS) };
+            if ready < 0 {
+                let e = io::Error::last_os_error();
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(e);
+            }
+            if ready == 0 {
+                continue;
+            }
+            let count = master.read(&mut buffer)?;
+            for line in framer.push(&buffer[..count]) {
+                let reply = simulator.handle(&line);
+                master.write_all(reply.as_bytes())?;
+                on_exchange(&line, &reply);
+            }
+        }
+        Ok(())
+    }
+}
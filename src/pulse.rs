@@ -0,0 +1,168 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+
+/// How the host switches the output between the on and off phases.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PulseMethod {
+    /// Toggle RF enable (`$ECS`).
+    RfToggle,
+    /// Keep RF enabled and switch between two power setpoints (`$PWRS`).
+    PowerToggle { on_dbm: f32, off_dbm: f32 },
+}
+
+/// Host-driven pulse train for boards without a hardware pulse mode.
+///
+/// Edges are scheduled against absolute deadlines so serial latency does not
+/// accumulate, but each edge still lands as late as the command round trip;
+/// the returned [`PulseReport`] shows how far the achieved timing strayed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PulseTrain {
+    pub on_time: Duration,
+    pub off_time: Duration,
+    pub count: usize,
+    pub method: PulseMethod,
+}
+
+/// Achieved timing of a pulse train.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PulseReport {
+    pub pulses: usize,
+    pub requested_on: Duration,
+    pub requested_off: Duration,
+    pub on_times: Vec<Duration>,
+    pub off_times: Vec<Duration>,
+}
+
+/// Summary statistics of one pulse phase.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PhaseTiming {
+    pub mean: Duration,
+    /// Standard deviation of the achieved durations.
+    pub jitter: Duration,
+    /// Largest deviation from the requested duration.
+    pub max_error: Duration,
+}
+
+impl PulseReport {
+    pub fn on_timing(&self) -> PhaseTiming {
+        phase_timing(&self.on_times, self.requested_on)
+    }
+
+    pub fn off_timing(&self) -> PhaseTiming {
+        phase_timing(&self.off_times, self.requested_off)
+    }
+}
+
+impl fmt::Display for PulseReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let on = self.on_timing();
+        let off = self.off_timing();
+        writeln!(f, "Pulses: {}", self.pulses)?;
+        writeln!(
+            f,
+            "On:  requested {:?}, mean {:?}, jitter {:?}, max error {:?}",
+            self.requested_on, on.mean, on.jitter, on.max_error
+        )?;
+        write!(
+            f,
+            "Off: requested {:?}, mean {:?}, jitter {:?}, max error {:?}",
+            self.requested_off, off.mean, off.jitter, off.max_error
+        )
+    }
+}
+
+fn phase_timing(durations: &[Duration], requested: Duration) -> PhaseTiming {
+    if durations.is_empty() {
+        return PhaseTiming::default();
+    }
+    let seconds: Vec<f64> = durations.iter().map(Duration::as_secs_f64).collect();
+    let mean = seconds.iter().sum::<f64>() / seconds.len() as f64;
+    let variance = seconds.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / seconds.len() as f64;
+    let max_error = seconds
+        .iter()
+        .map(|s| (s - requested.as_secs_f64()).abs())
+        .fold(0.0, f64::max);
+
+    PhaseTiming {
+        mean: Duration::from_secs_f64(mean),
+        jitter: Duration::from_secs_f64(variance.sqrt()),
+        max_error: Duration::from_secs_f64(max_error),
+    }
+}
+
+impl PulseTrain {
+    /// Runs the pulse train. The output is left off (RF disabled) when the
+    /// train finishes, fails or is cancelled.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<PulseReport, ControllerError> {
+        let result = self.run_pulses(controller, cancel);
+        let safe = controller.safe_state();
+        let report = result?;
+        safe?;
+        Ok(report)
+    }
+
+    fn run_pulses(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<PulseReport, ControllerError> {
+        let (on_command, off_command) = match self.method {
+            PulseMethod::RfToggle => (Command::RfEnable, Command::RfDisable),
+            PulseMethod::PowerToggle { on_dbm, off_dbm } => {
+                controller.send(&Command::SetPower(off_dbm))?;
+                controller.send(&Command::RfEnable)?;
+                (Command::SetPower(on_dbm), Command::SetPower(off_dbm))
+            }
+        };
+
+        let mut report = PulseReport {
+            requested_on: self.on_time,
+            requested_off: self.off_time,
+            ..PulseReport::default()
+        };
+        let period = self.on_time + self.off_time;
+        let start_time = Instant::now();
+        let mut last_off_edge: Option<Instant> = None;
+
+        for i in 0..self.count {
+            let pulse_start = start_time + period * i as u32;
+            wait_until(cancel, pulse_start)?;
+            controller.send(&on_command)?;
+            let on_edge = Instant::now();
+            if let Some(off_edge) = last_off_edge {
+                report.off_times.push(on_edge - off_edge);
+            }
+
+            wait_until(cancel, pulse_start + self.on_time)?;
+            controller.send(&off_command)?;
+            let off_edge = Instant::now();
+            report.on_times.push(off_edge - on_edge);
+            last_off_edge = Some(off_edge);
+            report.pulses += 1;
+        }
+
+        Ok(report)
+    }
+}
+
+fn wait_until(cancel: &CancellationToken, deadline: Instant) -> Result<(), ControllerError> {
+    let now = Instant::now();
+    if deadline > now && !cancel.sleep(deadline - now) {
+        return Err(ControllerError::Cancelled);
+    }
+    if cancel.is_cancelled() {
+        return Err(ControllerError::Cancelled);
+    }
+    Ok(())
+}
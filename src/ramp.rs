@@ -0,0 +1,145 @@
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::progress::{Progress, ProgressCallback};
+
+/// Stepwise power setpoint ramp.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PowerRamp {
+    pub start_dbm: f32,
+    pub stop_dbm: f32,
+    pub step_dbm: f32,
+    /// Time to hold each setpoint before moving to the next.
+    pub dwell: Duration,
+}
+
+impl PowerRamp {
+    /// Setpoints visited by the ramp, start and stop inclusive. Works in both directions.
+    pub fn setpoints(&self) -> Vec<f32> {
+        let step = self.step_dbm.abs();
+        if step == 0.0 || self.start_dbm == self.stop_dbm {
+            return vec![self.stop_dbm];
+        }
+        let direction = if self.stop_dbm > self.start_dbm {
+            1.0
+        } else {
+            -1.0
+        };
+        let count = ((self.stop_dbm - self.start_dbm).abs() / step + 1e-3).floor() as usize;
+
+        let mut setpoints: Vec<f32> = (0..=count)
+            .map(|i| self.start_dbm + direction * i as f32 * step)
+            .collect();
+        if setpoints.last() != Some(&self.stop_dbm) {
+            setpoints.push(self.stop_dbm);
+        }
+        setpoints
+    }
+
+    /// Steps the power setpoint. RF state is left to the caller, except when
+    /// the ramp fails or is cancelled, in which case RF is disabled. RF turned
+    /// off for a [`PauseMode::DisableRf`] pause is turned back on only if it
+    /// was on when the ramp started.
+    ///
+    /// [`PauseMode::DisableRf`]: crate::cancel::PauseMode::DisableRf
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        self.run_with_progress(controller, cancel, &mut |_| {})
+    }
+
+    /// Same as [`PowerRamp::run`], reporting progress after every setpoint has been held.
+    pub fn run_with_progress(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<(), ControllerError> {
+        let restore: &[Command] = match controller.rf_enabled() {
+            true => &[Command::RfEnable],
+            false => &[],
+        };
+        match self.run_setpoints(controller, cancel, restore, on_progress) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = controller.safe_state();
+                Err(e)
+            }
+        }
+    }
+
+    /// Same as [`PowerRamp::run`], with `restore` sent on resume from a pause
+    /// in place of the RF state at the start, for callers such as recipes
+    /// that track the output themselves.
+    pub fn run_with_restore(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        restore: &[Command],
+    ) -> Result<(), ControllerError> {
+        match self.run_setpoints(controller, cancel, restore, &mut |_| {}) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let _ = controller.safe_state();
+                Err(e)
+            }
+        }
+    }
+
+    fn run_setpoints(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        restore: &[Command],
+        on_progress: ProgressCallback,
+    ) -> Result<(), ControllerError> {
+        let setpoints = self.setpoints();
+        let start_time = Instant::now();
+
+        for (i, setpoint) in setpoints.iter().copied().enumerate() {
+            if cancel.is_cancelled() {
+                return Err(ControllerError::Cancelled);
+            }
+            cancel.checkpoint(controller, restore)?;
+            controller.send(&Command::SetPower(setpoint))?;
+            if !cancel.sleep(self.dwell) {
+                return Err(ControllerError::Cancelled);
+            }
+            on_progress(&Progress {
+                completed: i + 1,
+                total: setpoints.len(),
+                elapsed: start_time.elapsed(),
+                frequency_mhz: None,
+                power_dbm: Some(setpoint),
+            });
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for PowerRamp {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("start_dbm", self.start_dbm)
+            .with("stop_dbm", self.stop_dbm)
+            .with("step_dbm", self.step_dbm)
+            .with("dwell_s", self.dwell)
+    }
+}
+
+impl FromJson for PowerRamp {
+    fn from_json(json: &JsonValue) -> Result<PowerRamp, String> {
+        Ok(PowerRamp {
+            start_dbm: json.f32("start_dbm")?,
+            stop_dbm: json.f32("stop_dbm")?,
+            step_dbm: json.f32("step_dbm")?,
+            dwell: json.duration("dwell_s")?,
+        })
+    }
+}
@@ -0,0 +1,273 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::json::{self, FromJson, JsonValue, ToJson};
+use crate::ramp::PowerRamp;
+use crate::retune::Retune;
+use crate::sweep::{measure_point, Sweep};
+use crate::telemetry::EnergyMeter;
+use crate::units::dbm_to_watts;
+
+/// Readback interval while delivering a fixed energy.
+const ENERGY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A single step of a recipe.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecipeStep {
+    SetFrequency(f32),
+    SetPower(f32),
+    RfOn,
+    RfOff,
+    /// Keep the current output for the given time.
+    Hold(Duration),
+    Ramp(PowerRamp),
+    Sweep(Sweep),
+    /// Enable RF until `energy_j` joules have been delivered to the load, then
+    /// disable it. Fails if that takes longer than `max_duration`.
+    DeliverEnergy {
+        energy_j: f64,
+        max_duration: Option<Duration>,
+    },
+}
+
+/// Ordered list of steps executed against a controller.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recipe {
+    pub name: String,
+    pub steps: Vec<RecipeStep>,
+    /// Re-tune periodically during holds with RF on.
+    pub retune: Option<Retune>,
+}
+
+impl Recipe {
+    pub fn new(name: &str) -> Recipe {
+        Recipe {
+            name: name.to_string(),
+            steps: Vec::new(),
+            retune: None,
+        }
+    }
+
+    pub fn step(mut self, step: RecipeStep) -> Recipe {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn with_retune(mut self, retune: Retune) -> Recipe {
+        self.retune = Some(retune);
+        self
+    }
+
+    /// Highest power setpoint the steps or the retune set, or `None` if the
+    /// recipe runs at whatever setpoint the board already has.
+    pub fn max_power_dbm(&self) -> Option<f32> {
+        let steps = self.steps.iter().flat_map(|step| match step {
+            RecipeStep::SetPower(dbm) => vec![*dbm],
+            RecipeStep::Ramp(ramp) => vec![ramp.start_dbm, ramp.stop_dbm],
+            RecipeStep::Sweep(sweep) => std::iter::once(sweep.power_dbm)
+                .chain(
+                    sweep
+                        .leveling
+                        .as_ref()
+                        .map(|leveling| leveling.max_setpoint_dbm),
+                )
+                .collect(),
+            _ => Vec::new(),
+        });
+        steps
+            .chain(self.retune.as_ref().map(|retune| retune.power_dbm))
+            .reduce(f32::max)
+    }
+
+    /// Executes every step in order. RF is always disabled when the recipe
+    /// ends, whether it completed, failed or was cancelled.
+    ///
+    /// Pauses take effect between steps and during holds; on resume the
+    /// recipe continues with the step it was on.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let result = self.run_steps(controller, cancel);
+        let safe = controller.safe_state();
+        result?;
+        safe
+    }
+
+    fn run_steps(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<(), ControllerError> {
+        let mut rf_on = false;
+
+        for step in &self.steps {
+            if cancel.is_cancelled() {
+                return Err(ControllerError::Cancelled);
+            }
+            let restore: &[Command] = if rf_on { &[Command::RfEnable] } else { &[] };
+            cancel.checkpoint(controller, restore)?;
+
+            match step {
+                RecipeStep::SetFrequency(mhz) => {
+                    controller.send(&Command::SetFrequency(*mhz))?;
+                }
+                RecipeStep::SetPower(dbm) => {
+                    controller.send(&Command::SetPower(*dbm))?;
+                }
+                RecipeStep::RfOn => {
+                    controller.send(&Command::RfEnable)?;
+                    rf_on = true;
+                }
+                RecipeStep::RfOff => {
+                    controller.send(&Command::RfDisable)?;
+                    rf_on = false;
+                }
+                RecipeStep::Hold(duration) => match &self.retune {
+                    Some(retune) if rf_on => retune.hold(controller, cancel, *duration, restore)?,
+                    _ => cancel.hold(controller, *duration, restore)?,
+                },
+                RecipeStep::Ramp(ramp) => ramp.run_with_restore(controller, cancel, restore)?,
+                RecipeStep::Sweep(sweep) => {
+                    sweep.run(controller, cancel)?;
+                    rf_on = false;
+                }
+                RecipeStep::DeliverEnergy {
+                    energy_j,
+                    max_duration,
+                } => {
+                    controller.send(&Command::RfEnable)?;
+                    deliver_energy(controller, cancel, *energy_j, *max_duration)?;
+                    controller.send(&Command::RfDisable)?;
+                    rf_on = false;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Integrates the delivered power readback until `energy_j` is reached.
+/// Time spent paused does not count towards `max_duration`.
+fn deliver_energy(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    energy_j: f64,
+    max_duration: Option<Duration>,
+) -> Result<(), ControllerError> {
+    let meter = EnergyMeter::new();
+    let mut active = Duration::ZERO;
+    let mut last = Instant::now();
+    while meter.joules() < energy_j {
+        if cancel.is_paused() {
+            meter.interrupt();
+            cancel.checkpoint(controller, &[Command::RfEnable])?;
+            last = Instant::now();
+        }
+        active += last.elapsed();
+        last = Instant::now();
+        if max_duration.is_some_and(|max| active > max) {
+            return Err(ControllerError::Timeout);
+        }
+        let point = measure_point(controller, 0.0)?;
+        let watts = dbm_to_watts(point.forward_dbm) - dbm_to_watts(point.reflected_dbm);
+        meter.add(SystemTime::now(), watts.max(0.0));
+        if !cancel.sleep(ENERGY_POLL_INTERVAL) {
+            return Err(ControllerError::Cancelled);
+        }
+    }
+    Ok(())
+}
+
+impl ToJson for RecipeStep {
+    fn to_json(&self) -> JsonValue {
+        let step = |name: &str| JsonValue::object().with("step", name);
+        match self {
+            RecipeStep::SetFrequency(mhz) => step("set_frequency").with("frequency_mhz", *mhz),
+            RecipeStep::SetPower(dbm) => step("set_power").with("power_dbm", *dbm),
+            RecipeStep::RfOn => step("rf_on"),
+            RecipeStep::RfOff => step("rf_off"),
+            RecipeStep::Hold(duration) => step("hold").with("duration_s", *duration),
+            RecipeStep::Ramp(ramp) => step("ramp").with("ramp", ramp.to_json()),
+            RecipeStep::Sweep(sweep) => step("sweep").with("sweep", sweep.to_json()),
+            RecipeStep::DeliverEnergy {
+                energy_j,
+                max_duration,
+            } => step("deliver_energy")
+                .with("energy_j", *energy_j)
+                .with("max_duration_s", *max_duration),
+        }
+    }
+}
+
+impl FromJson for RecipeStep {
+    fn from_json(json: &JsonValue) -> Result<RecipeStep, String> {
+        let member = |key: &str| json.get(key).ok_or(format!("Missing field \"{}\"", key));
+        match json.string("step")? {
+            "set_frequency" => Ok(RecipeStep::SetFrequency(json.f32("frequency_mhz")?)),
+            "set_power" => Ok(RecipeStep::SetPower(json.f32("power_dbm")?)),
+            "rf_on" => Ok(RecipeStep::RfOn),
+            "rf_off" => Ok(RecipeStep::RfOff),
+            "hold" => Ok(RecipeStep::Hold(json.duration("duration_s")?)),
+            "ramp" => Ok(RecipeStep::Ramp(PowerRamp::from_json(member("ramp")?)?)),
+            "sweep" => Ok(RecipeStep::Sweep(Sweep::from_json(member("sweep")?)?)),
+            "deliver_energy" => Ok(RecipeStep::DeliverEnergy {
+                energy_j: json.number("energy_j")?,
+                max_duration: match json.get("max_duration_s") {
+                    None | Some(JsonValue::Null) => None,
+                    Some(_) => Some(json.duration("max_duration_s")?),
+                },
+            }),
+            other => Err(format!("Unknown recipe step: {}", other)),
+        }
+    }
+}
+
+impl ToJson for Recipe {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("name", self.name.as_str())
+            .with(
+                "steps",
+                JsonValue::Array(self.steps.iter().map(ToJson::to_json).collect()),
+            )
+            .with("retune", self.retune.as_ref().map(ToJson::to_json))
+    }
+}
+
+impl FromJson for Recipe {
+    fn from_json(json: &JsonValue) -> Result<Recipe, String> {
+        Ok(Recipe {
+            name: json.string("name")?.to_string(),
+            steps: json
+                .array("steps")?
+                .iter()
+                .map(RecipeStep::from_json)
+                .collect::<Result<_, _>>()?,
+            retune: match json.get("retune") {
+                None | Some(JsonValue::Null) => None,
+                Some(retune) => Some(Retune::from_json(retune)?),
+            },
+        })
+    }
+}
+
+impl Recipe {
+    /// Loads a JSON recipe file.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Recipe> {
+        let text = fs::read_to_string(path)?;
+        json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json().to_pretty_string() + "\n")
+    }
+}
@@ -0,0 +1,326 @@
+//! Settings a running daemon picks up from its files without a restart.
+//!
+//! Three files are safe to change under a running board: the daemon's
+//! [`RuntimeSettings`], with the telemetry polling rate; the limits, as
+//! alarm rules one per line (see [`parse_rules`]); and the calibration
+//! table power deviation alarms are measured against. A [`ConfigWatcher`]
+//! notices that a file changed by polling its modification time, and
+//! [`LiveConfig`] checks the new contents against what is running before
+//! applying them. While RF is on, a reload may tighten the alarms that turn
+//! RF off or reduce power but not loosen or remove them, and a calibration
+//! table must cover the frequency in use; such changes are rejected and the
+//! previous settings stay in force.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::alarms::{parse_rules, AlarmEngine, AlarmRule};
+use crate::alerting::Severity;
+use crate::calibration::CalibrationTable;
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::json::{self, FromJson, JsonValue, ToJson};
+use crate::telemetry::PollInterval;
+use crate::validate::{ConfigSet, Level};
+
+/// Faster polling than this would crowd client commands off the bus.
+pub const MIN_TELEMETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The daemon's own settings, kept in a JSON file such as
+/// `{"telemetry_interval_s": 0.5}`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeSettings {
+    pub telemetry_interval: Duration,
+}
+
+impl Default for RuntimeSettings {
+    fn default() -> RuntimeSettings {
+        RuntimeSettings {
+            telemetry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl RuntimeSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.telemetry_interval < MIN_TELEMETRY_INTERVAL {
+            return Err(format!(
+                "telemetry_interval_s must be at least {:?}",
+                MIN_TELEMETRY_INTERVAL
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<RuntimeSettings> {
+        let text = fs::read_to_string(path)?;
+        json::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_json().to_pretty_string() + "\n")
+    }
+}
+
+impl ToJson for RuntimeSettings {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object().with("telemetry_interval_s", self.telemetry_interval)
+    }
+}
+
+impl FromJson for RuntimeSettings {
+    fn from_json(json: &JsonValue) -> Result<RuntimeSettings, String> {
+        let defaults = RuntimeSettings::default();
+        Ok(RuntimeSettings {
+            telemetry_interval: match json.get("telemetry_interval_s") {
+                None | Some(JsonValue::Null) => defaults.telemetry_interval,
+                Some(_) => json.duration("telemetry_interval_s")?,
+            },
+        })
+    }
+}
+
+/// Critical rules in `current` that `new` drops or makes less strict: a
+/// laxer limit, a longer hold, another signal or a lower severity.
+fn loosened(current: &[AlarmRule], new: &[AlarmRule]) -> Vec<String> {
+    current
+        .iter()
+        .filter(|rule| rule.severity == Severity::Critical)
+        .filter_map(|rule| {
+            let Some(replacement) = new.iter().find(|candidate| candidate.name == rule.name) else {
+                return Some(format!("critical alarm {} would be removed", rule.name));
+            };
+            let strict = replacement.severity == Severity::Critical
+                && replacement.condition.is_as_strict_as(&rule.condition)
+                && replacement.hold <= rule.hold;
+            (!strict).then(|| format!("critical alarm {} would be loosened", rule.name))
+        })
+        .collect()
+}
+
+/// The settings in force, and what they are applied to.
+pub struct LiveConfig {
+    controller: Controller,
+    interval: PollInterval,
+    alarms: Arc<Mutex<AlarmEngine>>,
+    rules: Vec<AlarmRule>,
+}
+
+impl LiveConfig {
+    /// Reloads into the poller behind `interval` and into `alarms`, which
+    /// is running `rules`.
+    pub fn new(
+        controller: &Controller,
+        interval: PollInterval,
+        alarms: Arc<Mutex<AlarmEngine>>,
+        rules: Vec<AlarmRule>,
+    ) -> LiveConfig {
+        LiveConfig {
+            controller: controller.clone(),
+            interval,
+            alarms,
+            rules,
+        }
+    }
+
+    pub fn reload_settings(&mut self, settings: RuntimeSettings) -> Result<String, String> {
+        settings.validate()?;
+        self.interval.set(settings.telemetry_interval);
+        Ok(format!("telemetry every {:?}", settings.telemetry_interval))
+    }
+
+    /// Runs `rules` from now on, unless RF is on and they would weaken a
+    /// critical alarm.
+    pub fn reload_alarms(&mut self, rules: Vec<AlarmRule>) -> Result<String, String> {
+        if self.controller.rf_enabled() {
+            let loosened = loosened(&self.rules, &rules);
+            if !loosened.is_empty() {
+                return Err(format!(
+                    "RF is on and {}; turn RF off first",
+                    loosened.join(", ")
+                ));
+            }
+        }
+        self.alarms
+            .lock()
+            .map_err(|_| "Alarm engine poisoned")?
+            .set_rules(rules.clone());
+        let summary = format!(
+            "{} alarm rule{}",
+            rules.len(),
+            if rules.len() == 1 { "" } else { "s" }
+        );
+        self.rules = rules;
+        Ok(summary)
+    }
+
+    /// Measures power deviation against `table` from now on, if it passes
+    /// the checks of `config validate` and, with RF on, covers the
+    /// frequency in use.
+    pub fn reload_calibration(&mut self, table: CalibrationTable) -> Result<String, String> {
+        let check = ConfigSet {
+            calibration: Some(("calibration".to_string(), table.clone())),
+            ..ConfigSet::default()
+        };
+        if let Some(error) = check
+            .validate()
+            .into_iter()
+            .find(|finding| finding.level == Level::Error)
+        {
+            return Err(error.message);
+        }
+        if self.controller.rf_enabled() {
+            let reply = self
+                .controller
+                .send(&Command::GetFrequency)
+                .map_err(|e| format!("Could not read the frequency in use: {}", e))?;
+            let frequency: f32 = parse_value(&reply).map_err(|e| e.to_string())?;
+            let band = match (table.points().first(), table.points().last()) {
+                (Some(first), Some(last)) => first.0..=last.0,
+                _ => return Err("RF is on and the table is empty".to_string()),
+            };
+            if !band.contains(&frequency) {
+                return Err(format!(
+                    "RF is on at {:.2} MHz, outside the table's {:.2}-{:.2} MHz",
+                    frequency,
+                    band.start(),
+                    band.end()
+                ));
+            }
+        }
+        let points = table.points().len();
+        self.alarms
+            .lock()
+            .map_err(|_| "Alarm engine poisoned")?
+            .set_calibration(Some(table));
+        Ok(format!("{} calibration points", points))
+    }
+}
+
+/// Reads `path` with `load` and applies it with `apply`, for
+/// [`ConfigWatcher::watch`].
+pub fn reloader<T: 'static>(
+    live: &Arc<Mutex<LiveConfig>>,
+    load: fn(&Path) -> Result<T, String>,
+    apply: fn(&mut LiveConfig, T) -> Result<String, String>,
+) -> ReloadHandler {
+    let live = live.clone();
+    Box::new(move |path| {
+        let contents = load(path)?;
+        let mut live = live.lock().map_err(|_| "Live configuration poisoned")?;
+        apply(&mut live, contents)
+    })
+}
+
+/// Reads a settings file for [`reloader`].
+pub fn load_settings(path: &Path) -> Result<RuntimeSettings, String> {
+    RuntimeSettings::load(path).map_err(|e| e.to_string())
+}
+
+/// Reads a limits file for [`reloader`].
+pub fn load_alarms(path: &Path) -> Result<Vec<AlarmRule>, String> {
+    parse_rules(&fs::read_to_string(path).map_err(|e| e.to_string())?)
+}
+
+/// Reads a calibration table for [`reloader`].
+pub fn load_calibration(path: &Path) -> Result<CalibrationTable, String> {
+    CalibrationTable::load(path).map_err(|e| e.to_string())
+}
+
+/// Reloads a changed file's contents, returning what was applied or why
+/// the contents were rejected.
+pub type ReloadHandler = Box<dyn FnMut(&Path) -> Result<String, String> + Send>;
+
+struct WatchedFile {
+    path: PathBuf,
+    /// Modification time and length when last loaded.
+    stamp: Option<(SystemTime, u64)>,
+    /// Modification time and length when last rejected.
+    rejected: Option<(SystemTime, u64)>,
+    reload: ReloadHandler,
+}
+
+/// Polls files for changes and reloads the ones that changed.
+pub struct ConfigWatcher {
+    interval: Duration,
+    files: Vec<WatchedFile>,
+}
+
+impl ConfigWatcher {
+    pub fn new(interval: Duration) -> ConfigWatcher {
+        ConfigWatcher {
+            interval,
+            files: Vec::new(),
+        }
+    }
+
+    /// Calls `reload` whenever `path` changes from how it is now, so the
+    /// caller loads it once itself before watching.
+    pub fn watch(mut self, path: impl AsRef<Path>, reload: ReloadHandler) -> ConfigWatcher {
+        let path = path.as_ref().to_path_buf();
+        self.files.push(WatchedFile {
+            stamp: stamp(&path),
+            rejected: None,
+            path,
+            reload,
+        });
+        self
+    }
+
+    /// Checks every file once and returns the outcome of each reload. A
+    /// file that is missing, e.g. while an editor replaces it, is looked at
+    /// again next time. Rejected contents, which may have been read while
+    /// half written, are tried again at every poll but reported once.
+    pub fn poll(&mut self) -> Vec<(PathBuf, Result<String, String>)> {
+        let mut outcomes = Vec::new();
+        for file in &mut self.files {
+            let Some(current) = stamp(&file.path) else {
+                continue;
+            };
+            if file.stamp == Some(current) {
+                continue;
+            }
+            let outcome = (file.reload)(&file.path);
+            match outcome {
+                Ok(_) => {
+                    file.stamp = Some(current);
+                    file.rejected = None;
+                }
+                Err(_) if file.rejected == Some(current) => continue,
+                Err(_) => file.rejected = Some(current),
+            }
+            outcomes.push((file.path.clone(), outcome));
+        }
+        outcomes
+    }
+
+    /// Polls on a background thread until `cancel` is cancelled, reporting
+    /// every reload on stderr.
+    pub fn spawn(mut self, cancel: CancellationToken) -> JoinHandle<()> {
+        thread::spawn(move || {
+            while cancel.sleep(self.interval) {
+                for (path, outcome) in self.poll() {
+                    match outcome {
+                        Ok(applied) => eprintln!("Reloaded {}: {}", path.display(), applied),
+                        Err(reason) => eprintln!(
+                            "Rejected the change to {}, keeping the previous settings: {}",
+                            path.display(),
+                            reason
+                        ),
+                    }
+                }
+            }
+        })
+    }
+}
+
+fn stamp(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
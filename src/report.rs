@@ -0,0 +1,608 @@
+//! Self-contained run reports for quality records.
+//!
+//! A [`ReportRecorder`] collects telemetry and controller events during a
+//! recipe or sweep; [`RunReport`] renders the result as a single HTML file
+//! (inline CSS and SVG plots) or as a PDF drawn with the standard Helvetica
+//! font, so neither needs anything installed to be viewed.
+
+use std::{
+    fs, io,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::events::ControllerEvent;
+use crate::protocol::Reply;
+use crate::sweep::SweepPoint;
+use crate::telemetry::{EnergyMeter, TelemetryListener, TelemetrySample};
+use crate::units::format_timestamp;
+
+/// Everything recorded about one run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunReport {
+    pub title: String,
+    pub started: SystemTime,
+    pub finished: SystemTime,
+    /// `$IDN` reply of the board, if it answered.
+    pub identity: Option<String>,
+    /// `$VER` reply of the board, if it answered.
+    pub firmware: Option<String>,
+    /// Settings used, e.g. recipe steps, as label and value.
+    pub settings: Vec<(String, String)>,
+    pub samples: Vec<TelemetrySample>,
+    /// Alarms, faults and interlock trips with the time they were observed.
+    pub events: Vec<(SystemTime, String)>,
+    pub energy_j: f64,
+    /// How the run ended, e.g. `Completed` or the error that stopped it.
+    pub outcome: String,
+}
+
+/// Collects telemetry and events for a [`RunReport`].
+///
+/// Subscribe [`listener`](ReportRecorder::listener) to the run's
+/// [`TelemetryPoller`](crate::TelemetryPoller); events are collected from the
+/// controller directly.
+#[derive(Clone)]
+pub struct ReportRecorder {
+    controller: Controller,
+    started: SystemTime,
+    samples: Arc<Mutex<Vec<TelemetrySample>>>,
+    events: Arc<Mutex<Vec<(SystemTime, String)>>>,
+    energy: EnergyMeter,
+}
+
+impl ReportRecorder {
+    pub fn new(controller: &Controller) -> Result<ReportRecorder, ControllerError> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        controller.subscribe(Arc::new(move |event: &ControllerEvent| {
+            if let Ok(mut recorded) = recorded.lock() {
+                recorded.push((SystemTime::now(), event.to_string()));
+            }
+        }))?;
+        Ok(ReportRecorder {
+            controller: controller.clone(),
+            started: SystemTime::now(),
+            samples: Arc::default(),
+            events,
+            energy: EnergyMeter::new(),
+        })
+    }
+
+    pub fn listener(&self) -> TelemetryListener {
+        let recorder = self.clone();
+        Arc::new(move |sample: &TelemetrySample| {
+            recorder.energy.add_sample(sample);
+            if let Ok(mut samples) = recorder.samples.lock() {
+                samples.push(*sample);
+            }
+        })
+    }
+
+    /// Builds the report, querying the board's identity and firmware version.
+    pub fn finish(&self, title: &str, settings: Vec<(String, String)>, outcome: &str) -> RunReport {
+        let query = |command: &Command| {
+            let reply = self.controller.send(command).ok()?;
+            let fields: Vec<&str> = Reply::parse(&reply).ok()?.fields().collect();
+            Some(fields.join(", "))
+        };
+        RunReport {
+            title: title.to_string(),
+            started: self.started,
+            finished: SystemTime::now(),
+            identity: query(&Command::GetIdentity),
+            firmware: query(&Command::GetVersion),
+            settings,
+            samples: self.samples.lock().map(|s| s.clone()).unwrap_or_default(),
+            events: self.events.lock().map(|e| e.clone()).unwrap_or_default(),
+            energy_j: self.energy.joules(),
+            outcome: outcome.to_string(),
+        }
+    }
+}
+
+/// A plotted quantity, against seconds since the start of the run unless
+/// the plot says otherwise.
+struct Series {
+    name: &'static str,
+    color: (u8, u8, u8),
+    points: Vec<(f64, f64)>,
+}
+
+struct Plot {
+    title: &'static str,
+    /// Unit of the x axis labels.
+    x_unit: &'static str,
+    series: Vec<Series>,
+}
+
+impl Plot {
+    /// `(x_min, x_max, y_min, y_max)`, padded so flat lines stay visible.
+    fn bounds(&self) -> Option<(f64, f64, f64, f64)> {
+        let mut points = self.series.iter().flat_map(|series| &series.points);
+        let &(x, y) = points.next()?;
+        let (mut x_min, mut x_max, mut y_min, mut y_max) = (x, x, y, y);
+        for &(x, y) in points {
+            x_min = x_min.min(x);
+            x_max = x_max.max(x);
+            y_min = y_min.min(y);
+            y_max = y_max.max(y);
+        }
+        if x_max - x_min < 1e-9 {
+            x_max = x_min + 1.0;
+        }
+        if y_max - y_min < 1e-9 {
+            y_min -= 1.0;
+            y_max += 1.0;
+        }
+        Some((x_min, x_max, y_min, y_max))
+    }
+}
+
+impl RunReport {
+    pub fn duration_s(&self) -> f64 {
+        self.finished
+            .duration_since(self.started)
+            .unwrap_or_default()
+            .as_secs_f64()
+    }
+
+    /// Summary lines shared by the HTML and PDF layouts.
+    fn summary(&self) -> Vec<(&'static str, String)> {
+        let unknown = || "unknown".to_string();
+        vec![
+            ("Started", format_timestamp(self.started)),
+            ("Finished", format_timestamp(self.finished)),
+            ("Duration", format!("{:.1} s", self.duration_s())),
+            ("Outcome", self.outcome.clone()),
+            ("Device", self.identity.clone().unwrap_or_else(unknown)),
+            ("Firmware", self.firmware.clone().unwrap_or_else(unknown)),
+            (
+                "Energy delivered",
+                format!(
+                    "{:.3} kJ ({:.6} kWh)",
+                    self.energy_j / 1000.0,
+                    self.energy_j / 3.6e6
+                ),
+            ),
+            ("Telemetry samples", self.samples.len().to_string()),
+        ]
+    }
+
+    fn plots(&self) -> Vec<Plot> {
+        let seconds = |sample: &TelemetrySample| {
+            sample
+                .timestamp
+                .duration_since(self.started)
+                .unwrap_or_default()
+                .as_secs_f64()
+        };
+        let series = |name, color, value: &dyn Fn(&TelemetrySample) -> Option<f32>| Series {
+            name,
+            color,
+            points: self
+                .samples
+                .iter()
+                .filter_map(|sample| Some((seconds(sample), value(sample)? as f64)))
+                .collect(),
+        };
+        vec![
+            Plot {
+                title: "Power (dBm)",
+                x_unit: "s",
+                series: vec![
+                    series("Forward", (31, 119, 180), &|s| Some(s.forward_dbm)),
+                    series("Reflected", (214, 39, 40), &|s| Some(s.reflected_dbm)),
+                    series("Setpoint", (127, 127, 127), &|s| Some(s.power_setpoint_dbm)),
+                ],
+            },
+            Plot {
+                title: "Frequency (MHz)",
+                x_unit: "s",
+                series: vec![series("Frequency", (44, 160, 44), &|s| {
+                    Some(s.frequency_mhz)
+                })],
+            },
+            Plot {
+                title: "PA temperature (°C)",
+                x_unit: "s",
+                series: vec![series("Temperature", (255, 127, 14), &|s| s.temperature_c)],
+            },
+        ]
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut html = String::new();
+        html.push_str("<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\n");
+        html.push_str(&format!("<title>{}</title>\n", escape_html(&self.title)));
+        html.push_str(
+            "<style>body{font-family:sans-serif;max-width:60em;margin:2em auto;color:#222}\
+             table{border-collapse:collapse;margin-bottom:1.5em}\
+             td,th{border:1px solid #ccc;padding:.25em .6em;text-align:left}\
+             th{background:#f4f4f4}svg{display:block;margin-bottom:1.5em}</style>\n",
+        );
+        html.push_str("</head><body>\n");
+        html.push_str(&format!("<h1>{}</h1>\n", escape_html(&self.title)));
+
+        html.push_str("<h2>Summary</h2>\n<table>\n");
+        for (label, value) in self.summary() {
+            html.push_str(&format!(
+                "<tr><th>{}</th><td>{}</td></tr>\n",
+                label,
+                escape_html(&value)
+            ));
+        }
+        html.push_str("</table>\n");
+
+        if !self.settings.is_empty() {
+            html.push_str("<h2>Settings</h2>\n<table>\n");
+            for (label, value) in &self.settings {
+                html.push_str(&format!(
+                    "<tr><th>{}</th><td>{}</td></tr>\n",
+                    escape_html(label),
+                    escape_html(value)
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+
+        html.push_str("<h2>Telemetry</h2>\n");
+        for plot in self.plots() {
+            html.push_str(&svg_plot(&plot));
+        }
+
+        html.push_str("<h2>Alarms and events</h2>\n");
+        if self.events.is_empty() {
+            html.push_str("<p>None</p>\n");
+        } else {
+            html.push_str("<table>\n<tr><th>Time</th><th>Event</th></tr>\n");
+            for (time, event) in &self.events {
+                html.push_str(&format!(
+                    "<tr><td>{}</td><td>{}</td></tr>\n",
+                    format_timestamp(*time),
+                    escape_html(event)
+                ));
+            }
+            html.push_str("</table>\n");
+        }
+        html.push_str("</body></html>\n");
+        html
+    }
+
+    pub fn to_pdf(&self) -> Vec<u8> {
+        let mut pdf = PdfWriter::new();
+        pdf.text(18.0, &self.title);
+        pdf.gap(6.0);
+        for (label, value) in self.summary() {
+            pdf.text(10.0, &format!("{}: {}", label, value));
+        }
+        if !self.settings.is_empty() {
+            pdf.gap(8.0);
+            pdf.text(13.0, "Settings");
+            for (label, value) in &self.settings {
+                pdf.text(10.0, &format!("{}: {}", label, value));
+            }
+        }
+        pdf.gap(8.0);
+        pdf.text(13.0, "Telemetry");
+        for plot in self.plots() {
+            pdf.plot(&plot);
+        }
+        pdf.gap(8.0);
+        pdf.text(13.0, "Alarms and events");
+        if self.events.is_empty() {
+            pdf.text(10.0, "None");
+        }
+        for (time, event) in &self.events {
+            pdf.text(10.0, &format!("{}  {}", format_timestamp(*time), event));
+        }
+        pdf.finish()
+    }
+
+    /// Writes the report as PDF if `path` ends in `.pdf`, otherwise as HTML.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let is_pdf = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("pdf"));
+        if is_pdf {
+            fs::write(path, self.to_pdf())
+        } else {
+            fs::write(path, self.to_html())
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Standalone SVG chart of forward and reflected power across a sweep.
+pub fn sweep_svg(points: &[SweepPoint]) -> String {
+    let series = |name, color, value: fn(&SweepPoint) -> f32| Series {
+        name,
+        color,
+        points: points
+            .iter()
+            .map(|point| (point.frequency_mhz as f64, value(point) as f64))
+            .collect(),
+    };
+    svg_plot(&Plot {
+        title: "Power (dBm)",
+        x_unit: "MHz",
+        series: vec![
+            series("Forward", (31, 119, 180), |p| p.forward_dbm),
+            series("Reflected", (214, 39, 40), |p| p.reflected_dbm),
+        ],
+    })
+}
+
+const SVG_WIDTH: f64 = 720.0;
+const SVG_HEIGHT: f64 = 240.0;
+const SVG_MARGIN: f64 = 50.0;
+
+fn svg_plot(plot: &Plot) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" font-size=\"12\">\n",
+        SVG_WIDTH, SVG_HEIGHT
+    );
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"16\" font-weight=\"bold\">{}</text>\n",
+        SVG_MARGIN,
+        escape_html(plot.title)
+    ));
+    let (x_min, x_max, y_min, y_max) = match plot.bounds() {
+        Some(bounds) => bounds,
+        None => {
+            svg.push_str("<text x=\"50\" y=\"40\">No data</text>\n</svg>\n");
+            return svg;
+        }
+    };
+    let (left, right, top, bottom) = (SVG_MARGIN, SVG_WIDTH - 10.0, 30.0, SVG_HEIGHT - 30.0);
+    let x = |value: f64| left + (value - x_min) / (x_max - x_min) * (right - left);
+    let y = |value: f64| bottom - (value - y_min) / (y_max - y_min) * (bottom - top);
+    svg.push_str(&format!(
+        "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#999\"/>\n",
+        left,
+        top,
+        right - left,
+        bottom - top
+    ));
+    svg.push_str(&format!(
+        "<text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{:.2}</text>\n\
+         <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{:.2}</text>\n\
+         <text x=\"{}\" y=\"{}\">{:.0} {}</text>\n\
+         <text x=\"{}\" y=\"{}\" text-anchor=\"end\">{:.0} {}</text>\n",
+        left - 4.0,
+        top + 4.0,
+        y_max,
+        left - 4.0,
+        bottom,
+        y_min,
+        left,
+        bottom + 16.0,
+        x_min,
+        plot.x_unit,
+        right,
+        bottom + 16.0,
+        x_max,
+        plot.x_unit
+    ));
+    for (index, series) in plot.series.iter().enumerate() {
+        let (r, g, b) = series.color;
+        let points: Vec<String> = series
+            .points
+            .iter()
+            .map(|&(px, py)| format!("{:.1},{:.1}", x(px), y(py)))
+            .collect();
+        svg.push_str(&format!(
+            "<polyline fill=\"none\" stroke=\"rgb({},{},{})\" stroke-width=\"1.5\" points=\"{}\"/>\n",
+            r,
+            g,
+            b,
+            points.join(" ")
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{}\" y=\"16\" fill=\"rgb({},{},{})\">{}</text>\n",
+            SVG_WIDTH - 300.0 + index as f64 * 100.0,
+            r,
+            g,
+            b,
+            series.name
+        ));
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// A4 page size in points.
+const PAGE_WIDTH: f32 = 595.0;
+const PAGE_HEIGHT: f32 = 842.0;
+const PAGE_MARGIN: f32 = 50.0;
+const PLOT_HEIGHT: f32 = 170.0;
+
+/// Minimal PDF 1.4 writer: text lines and line plots flowed down A4 pages.
+struct PdfWriter {
+    pages: Vec<String>,
+    content: String,
+    y: f32,
+}
+
+impl PdfWriter {
+    fn new() -> PdfWriter {
+        PdfWriter {
+            pages: Vec::new(),
+            content: String::new(),
+            y: PAGE_HEIGHT - PAGE_MARGIN,
+        }
+    }
+
+    /// Starts a new page when fewer than `height` points are left.
+    fn reserve(&mut self, height: f32) {
+        if self.y - height < PAGE_MARGIN {
+            self.pages.push(std::mem::take(&mut self.content));
+            self.y = PAGE_HEIGHT - PAGE_MARGIN;
+        }
+        self.y -= height;
+    }
+
+    fn gap(&mut self, height: f32) {
+        self.y -= height;
+    }
+
+    fn text(&mut self, size: f32, text: &str) {
+        self.reserve(size * 1.4);
+        let y = self.y;
+        self.text_at(PAGE_MARGIN, y, size, text);
+    }
+
+    fn text_at(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        self.content.push_str(&format!(
+            "BT /F1 {} Tf {:.1} {:.1} Td ({}) Tj ET\n",
+            size,
+            x,
+            y,
+            escape_pdf(text)
+        ));
+    }
+
+    fn plot(&mut self, plot: &Plot) {
+        self.reserve(PLOT_HEIGHT + 20.0);
+        let (left, right) = (PAGE_MARGIN + 45.0, PAGE_WIDTH - PAGE_MARGIN);
+        let (bottom, top) = (self.y + 15.0, self.y + PLOT_HEIGHT);
+        self.text_at(PAGE_MARGIN, top + 6.0, 10.0, plot.title);
+        let (x_min, x_max, y_min, y_max) = match plot.bounds() {
+            Some(bounds) => bounds,
+            None => {
+                self.text_at(left, (top + bottom) / 2.0, 9.0, "No data");
+                return;
+            }
+        };
+        let x = |value: f64| left + ((value - x_min) / (x_max - x_min)) as f32 * (right - left);
+        let y = |value: f64| bottom + ((value - y_min) / (y_max - y_min)) as f32 * (top - bottom);
+        self.content.push_str(&format!(
+            "0.6 G 0.5 w {:.1} {:.1} {:.1} {:.1} re S\n",
+            left,
+            bottom,
+            right - left,
+            top - bottom
+        ));
+        self.text_at(PAGE_MARGIN, top - 8.0, 8.0, &format!("{:.2}", y_max));
+        self.text_at(PAGE_MARGIN, bottom, 8.0, &format!("{:.2}", y_min));
+        self.text_at(
+            left,
+            bottom - 11.0,
+            8.0,
+            &format!("{:.0} {}", x_min, plot.x_unit),
+        );
+        self.text_at(
+            right - 30.0,
+            bottom - 11.0,
+            8.0,
+            &format!("{:.0} {}", x_max, plot.x_unit),
+        );
+        for (index, series) in plot.series.iter().enumerate() {
+            let (r, g, b) = series.color;
+            let color = format!(
+                "{:.3} {:.3} {:.3}",
+                r as f32 / 255.0,
+                g as f32 / 255.0,
+                b as f32 / 255.0
+            );
+            let mut path = String::new();
+            for (i, &(px, py)) in series.points.iter().enumerate() {
+                let op = if i == 0 { "m" } else { "l" };
+                path.push_str(&format!("{:.1} {:.1} {} ", x(px), y(py), op));
+            }
+            if !path.is_empty() {
+                self.content
+                    .push_str(&format!("{} RG 1 w {}S\n", color, path));
+            }
+            self.content.push_str(&format!("{} rg\n", color));
+            self.text_at(
+                right - 240.0 + index as f32 * 80.0,
+                top + 6.0,
+                9.0,
+                series.name,
+            );
+            self.content.push_str("0 g\n");
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        self.pages.push(std::mem::take(&mut self.content));
+        let page_count = self.pages.len();
+        // Objects: 1 catalog, 2 page tree, 3 font, then a page and its content stream per page.
+        let mut objects = vec![
+            "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                (0..page_count)
+                    .map(|i| format!("{} 0 R", 4 + 2 * i))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                page_count
+            ),
+            "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_string(),
+        ];
+        for (i, content) in self.pages.iter().enumerate() {
+            objects.push(format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                5 + 2 * i
+            ));
+            objects.push(format!(
+                "<< /Length {} >>\nstream\n{}endstream",
+                content.len(),
+                content
+            ));
+        }
+
+        let mut pdf = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, object) in objects.iter().enumerate() {
+            offsets.push(pdf.len());
+            pdf.extend_from_slice(format!("{} 0 obj\n{}\nendobj\n", i + 1, object).as_bytes());
+        }
+        let xref = pdf.len();
+        let mut trailer = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            trailer.push_str(&format!("{:010} 00000 n \n", offset));
+        }
+        trailer.push_str(&format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref
+        ));
+        pdf.extend_from_slice(trailer.as_bytes());
+        pdf
+    }
+}
+
+/// Escapes a PDF string literal. Latin-1 characters (e.g. `°`) are written as
+/// octal escapes, which WinAnsiEncoding maps to the same glyphs; others become `?`.
+fn escape_pdf(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            ' '..='~' => escaped.push(c),
+            '\u{a0}'..='\u{ff}' => escaped.push_str(&format!("\\{:03o}", c as u32)),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,167 @@
+//! Cavity characterization from a reflection sweep: fits a Lorentzian to
+//! the power the load absorbs, `1 - |S11|²`, and reports the resonant
+//! frequency, bandwidth and loaded Q.
+//!
+//! The reciprocal of a Lorentzian is a parabola in frequency, so the fit is
+//! a weighted quadratic least-squares fit of `1 / absorbed`. Points
+//! absorbing less than a tenth of the deepest point are left out; far from
+//! resonance they carry mostly the baseline, not the dip.
+//!
+//! [`Resonance::drift`] compares two fits, e.g. a stored baseline sweep of a
+//! clean cavity against a later one: contamination and worn seals show up as
+//! a shifted, shallower or wider dip.
+
+use std::fmt;
+
+use crate::sweep::SweepPoint;
+
+/// Fitted resonance of a load.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Resonance {
+    pub frequency_mhz: f64,
+    /// Full width at half absorbed power.
+    pub bandwidth_mhz: f64,
+    /// `frequency_mhz / bandwidth_mhz`.
+    pub loaded_q: f64,
+    /// S11 of the fitted curve at resonance, dB.
+    pub depth_db: f64,
+}
+
+impl fmt::Display for Resonance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3} MHz, bandwidth {:.3} MHz, loaded Q {:.0}, depth {:.2} dB",
+            self.frequency_mhz, self.bandwidth_mhz, self.loaded_q, self.depth_db
+        )
+    }
+}
+
+impl Resonance {
+    /// Change from `baseline` to this fit.
+    pub fn drift(&self, baseline: &Resonance) -> Drift {
+        Drift {
+            frequency_shift_mhz: self.frequency_mhz - baseline.frequency_mhz,
+            depth_change_db: self.depth_db - baseline.depth_db,
+            bandwidth_change_mhz: self.bandwidth_mhz - baseline.bandwidth_mhz,
+        }
+    }
+}
+
+/// Difference between two [`Resonance`] fits.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Drift {
+    pub frequency_shift_mhz: f64,
+    /// Positive if the dip got shallower.
+    pub depth_change_db: f64,
+    /// Positive if the dip got wider, i.e. the loaded Q dropped.
+    pub bandwidth_change_mhz: f64,
+}
+
+impl fmt::Display for Drift {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:+.3} MHz, depth {:+.2} dB, bandwidth {:+.3} MHz",
+            self.frequency_shift_mhz, self.depth_change_db, self.bandwidth_change_mhz
+        )
+    }
+}
+
+/// Fits the dip of `points`. Returns `None` if fewer than three points are
+/// near the dip or they do not form one inside the swept range (e.g. a
+/// flat or rising curve, or a dip wider than the sweep).
+pub fn fit_resonance(points: &[SweepPoint]) -> Option<Resonance> {
+    let absorbed: Vec<(f64, f64)> = points
+        .iter()
+        .map(|point| {
+            let reflected = 10f64.powf(point.s11_db() as f64 / 10.0);
+            (point.frequency_mhz as f64, 1.0 - reflected.min(1.0))
+        })
+        .collect();
+    let (center, peak) = absorbed
+        .iter()
+        .copied()
+        .max_by(|a, b| a.1.total_cmp(&b.1))?;
+    if peak <= 0.0 {
+        return None;
+    }
+
+    // Normal equations of y = c0 + c1 x + c2 x², x relative to the deepest
+    // point to keep them well conditioned.
+    let mut normal = [[0.0; 4]; 3];
+    let mut used = 0;
+    for &(frequency, absorbed) in &absorbed {
+        if absorbed < peak / 10.0 {
+            continue;
+        }
+        used += 1;
+        let x = frequency - center;
+        let weight = absorbed * absorbed;
+        let powers = [1.0, x, x * x];
+        for (row, power) in normal.iter_mut().zip(powers) {
+            for (cell, other) in row.iter_mut().zip(powers) {
+                *cell += weight * power * other;
+            }
+            row[3] += weight * power / absorbed;
+        }
+    }
+    if used < 3 {
+        return None;
+    }
+    let [c0, c1, c2] = solve(normal)?;
+    if c2 <= 0.0 {
+        return None;
+    }
+
+    let offset = -c1 / (2.0 * c2);
+    let minimum = c0 - c1 * c1 / (4.0 * c2);
+    if minimum <= 0.0 {
+        return None;
+    }
+    // Noise can put the fitted peak slightly above total absorption.
+    let peak_absorbed = (1.0 / minimum).min(1.0);
+    let half_width = (minimum / c2).sqrt();
+    let frequency_mhz = center + offset;
+    let bandwidth_mhz = 2.0 * half_width;
+    let (low, high) = absorbed
+        .iter()
+        .fold((f64::MAX, f64::MIN), |(low, high), &(frequency, _)| {
+            (low.min(frequency), high.max(frequency))
+        });
+    if !(low..=high).contains(&frequency_mhz) || bandwidth_mhz > high - low {
+        return None;
+    }
+    Some(Resonance {
+        frequency_mhz,
+        bandwidth_mhz,
+        loaded_q: frequency_mhz / bandwidth_mhz,
+        depth_db: 10.0 * (1.0 - peak_absorbed).max(1e-12).log10(),
+    })
+}
+
+/// Solves a 3x3 system given as augmented rows, by Gaussian elimination
+/// with partial pivoting.
+fn solve(mut rows: [[f64; 4]; 3]) -> Option<[f64; 3]> {
+    for column in 0..3 {
+        let pivot =
+            (column..3).max_by(|a, b| rows[*a][column].abs().total_cmp(&rows[*b][column].abs()))?;
+        if rows[pivot][column].abs() < 1e-300 {
+            return None;
+        }
+        rows.swap(column, pivot);
+        let pivot_row = rows[column];
+        for row in rows.iter_mut().skip(column + 1) {
+            let factor = row[column] / pivot_row[column];
+            for (cell, pivot_cell) in row.iter_mut().zip(pivot_row).skip(column) {
+                *cell -= factor * pivot_cell;
+            }
+        }
+    }
+    let mut solution = [0.0; 3];
+    for row in (0..3).rev() {
+        let known: f64 = (row + 1..3).map(|k| rows[row][k] * solution[k]).sum();
+        solution[row] = (rows[row][3] - known) / rows[row][row];
+    }
+    Some(solution)
+}
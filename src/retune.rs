@@ -0,0 +1,112 @@
+//! Periodic re-tuning while a load heats. Its permittivity, and with it the
+//! matched frequency, changes during a long run; every `interval` the power
+//! is dropped, a narrow sweep around the current frequency picks the new
+//! optimum and the run resumes there at the previous setpoint.
+
+use std::time::Duration;
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::protocol::FREQUENCY_RANGE_MHZ;
+use crate::sweep::{auto_match, MatchResult, Sweep};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Retune {
+    /// Time at full power between re-tunes.
+    pub interval: Duration,
+    /// Width of the sweep, centred on the current frequency.
+    pub span_mhz: f32,
+    pub step_mhz: f32,
+    /// Power the sweep runs at.
+    pub power_dbm: f32,
+    pub dwell: Duration,
+}
+
+impl Retune {
+    /// Re-tunes every `interval` with a 10 MHz sweep in 0.5 MHz steps at 10 dBm.
+    pub fn new(interval: Duration) -> Retune {
+        Retune {
+            interval,
+            span_mhz: 10.0,
+            step_mhz: 0.5,
+            power_dbm: 10.0,
+            dwell: Duration::from_millis(20),
+        }
+    }
+
+    /// Sweep around `frequency_mhz`, clipped to the board's frequency range.
+    pub fn sweep(&self, frequency_mhz: f32) -> Sweep {
+        let half = self.span_mhz.abs() / 2.0;
+        Sweep::linear(
+            (frequency_mhz - half).max(*FREQUENCY_RANGE_MHZ.start()),
+            (frequency_mhz + half).min(*FREQUENCY_RANGE_MHZ.end()),
+            self.step_mhz,
+            self.power_dbm,
+            self.dwell,
+        )
+    }
+
+    /// Re-tunes once with [`auto_match`] around the current frequency and
+    /// enables RF again at the previous power setpoint.
+    pub fn run_once(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<MatchResult, ControllerError> {
+        let frequency_mhz = parse_value(&controller.send(&Command::GetFrequency)?)?;
+        let result = auto_match(controller, cancel, &self.sweep(frequency_mhz))?;
+        controller.send(&Command::RfEnable)?;
+        Ok(result)
+    }
+
+    /// Holds the current output with RF on for `duration`, re-tuning every
+    /// `interval`. Time spent re-tuning does not count towards `duration`.
+    pub fn hold(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        duration: Duration,
+        restore: &[Command],
+    ) -> Result<(), ControllerError> {
+        if self.interval.is_zero() {
+            return cancel.hold(controller, duration, restore);
+        }
+        let mut remaining = duration;
+        while !remaining.is_zero() {
+            let slice = remaining.min(self.interval);
+            cancel.hold(controller, slice, restore)?;
+            remaining -= slice;
+            if !remaining.is_zero() {
+                self.run_once(controller, cancel)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ToJson for Retune {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("interval_s", self.interval)
+            .with("span_mhz", self.span_mhz)
+            .with("step_mhz", self.step_mhz)
+            .with("power_dbm", self.power_dbm)
+            .with("dwell_s", self.dwell)
+    }
+}
+
+impl FromJson for Retune {
+    fn from_json(json: &JsonValue) -> Result<Retune, String> {
+        Ok(Retune {
+            interval: json.duration("interval_s")?,
+            span_mhz: json.f32("span_mhz")?,
+            step_mhz: json.f32("step_mhz")?,
+            power_dbm: json.f32("power_dbm")?,
+            dwell: json.duration("dwell_s")?,
+        })
+    }
+}
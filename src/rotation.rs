@@ -0,0 +1,159 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    process::Command,
+    time::{Duration, Instant},
+};
+
+/// When and how a [`RotatingWriter`] rotates its file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RotationPolicy {
+    /// Rotate once the file reaches this many bytes.
+    pub max_bytes: Option<u64>,
+    /// Rotate once the file has been written to for this long.
+    pub max_age: Option<Duration>,
+    /// Number of rotated files kept; older ones are deleted.
+    pub retention: usize,
+    /// Compress rotated files with the system `gzip` tool.
+    pub gzip: bool,
+}
+
+impl Default for RotationPolicy {
+    fn default() -> RotationPolicy {
+        RotationPolicy {
+            max_bytes: Some(64 * 1024 * 1024),
+            max_age: None,
+            retention: 10,
+            gzip: false,
+        }
+    }
+}
+
+/// Append-only log file with size and time based rotation.
+///
+/// Rotated files are renamed `<name>.1`, `<name>.2`, ... (newest first, with
+/// a `.gz` suffix when compressed). An optional header line is written at
+/// the top of every new file so each rotated CSV stays self-describing.
+/// Files are only rotated between lines, so a record written in several
+/// calls, as `writeln!` does, stays in one file.
+pub struct RotatingWriter {
+    path: PathBuf,
+    policy: RotationPolicy,
+    header: Option<String>,
+    file: File,
+    written: u64,
+    opened: Instant,
+    /// Whether the last byte written ended a line.
+    at_line_start: bool,
+}
+
+impl RotatingWriter {
+    pub fn open(
+        path: impl AsRef<Path>,
+        policy: RotationPolicy,
+        header: Option<&str>,
+    ) -> io::Result<RotatingWriter> {
+        let path = path.as_ref().to_path_buf();
+        let (file, written) = open_append(&path, header)?;
+        Ok(RotatingWriter {
+            path,
+            policy,
+            header: header.map(str::to_string),
+            file,
+            written,
+            opened: Instant::now(),
+            at_line_start: true,
+        })
+    }
+
+    fn rotated_path(&self, index: usize, suffix: &str) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{}{}", index, suffix));
+        PathBuf::from(name)
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let too_big = self
+            .policy
+            .max_bytes
+            .is_some_and(|max_bytes| self.written >= max_bytes);
+        let too_old = self
+            .policy
+            .max_age
+            .is_some_and(|max_age| self.opened.elapsed() >= max_age);
+        too_big || too_old
+    }
+
+    /// Closes the current file, shifts the rotated files and starts a new one.
+    pub fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.policy.retention == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            // Shift both plain and compressed files, so a failed gzip or a
+            // policy change does not leave files outside the retention count.
+            for suffix in ["", ".gz"] {
+                let _ = fs::remove_file(self.rotated_path(self.policy.retention, suffix));
+                for index in (1..self.policy.retention).rev() {
+                    let from = self.rotated_path(index, suffix);
+                    if from.exists() {
+                        fs::rename(&from, self.rotated_path(index + 1, suffix))?;
+                    }
+                }
+            }
+
+            let first = self.rotated_path(1, "");
+            fs::rename(&self.path, &first)?;
+            if self.policy.gzip {
+                compress(&first);
+            }
+        }
+
+        let (file, written) = open_append(&self.path, self.header.as_deref())?;
+        self.file = file;
+        self.written = written;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.at_line_start && self.needs_rotation() {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        if let Some(last) = buf[..written].last() {
+            self.at_line_start = *last == b'\n';
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path, header: Option<&str>) -> io::Result<(File, u64)> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut written = file.metadata()?.len();
+    if written == 0 {
+        if let Some(header) = header {
+            writeln!(file, "{}", header)?;
+            written = header.len() as u64 + 1;
+        }
+    }
+    Ok((file, written))
+}
+
+/// Replaces `path` with `path.gz`. On failure the file is left uncompressed.
+fn compress(path: &Path) {
+    match Command::new("gzip").arg("-f").arg(path).status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!("gzip of {} failed: {}", path.display(), status),
+        Err(e) => eprintln!("gzip of {} failed: {:?}", path.display(), e),
+    }
+}
@@ -0,0 +1,168 @@
+//! Keeping RF off when the program goes wrong: a panic hook that disables RF
+//! on every registered board before the panic message is printed (and
+//! before the process aborts under `panic = "abort"`), a handler that does
+//! the same, and lets a locked-out front panel back in, before the process
+//! exits on SIGINT, SIGTERM or SIGHUP, and a guard that disables RF when
+//! dropped.
+//!
+//! Both are best effort. RF-off is sent even while an interlock is open or
+//! a session is read-only, and is abandoned if the port stays busy.
+
+use std::panic;
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::Duration;
+
+use crate::controller::Controller;
+use crate::error::ControllerError;
+
+/// How long a hook or guard waits for a port another thread is using.
+const PORT_WAIT: Duration = Duration::from_millis(250);
+
+/// How often the signal watcher checks for a signal.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+static HOOK: Once = Once::new();
+static SIGNAL_WATCH: Once = Once::new();
+/// Last signal received, zero for none. Set by the signal handler, which
+/// may do nothing but store it.
+static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+static REGISTERED: Mutex<Vec<Controller>> = Mutex::new(Vec::new());
+
+/// Disables RF on `controller` whenever any thread panics. The hook is
+/// installed on the first call and runs ahead of the one already set.
+pub fn disable_rf_on_panic(controller: &Controller) {
+    if let Ok(mut registered) = REGISTERED.lock() {
+        registered.push(controller.clone());
+    }
+    HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            // try_lock: the panic may have happened while registering.
+            if let Ok(registered) = REGISTERED.try_lock() {
+                for controller in registered.iter() {
+                    if let Err(e) = controller.emergency_rf_off(PORT_WAIT) {
+                        eprintln!("Failed to disable RF after a panic: {}", e);
+                    }
+                }
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// On SIGINT, SIGTERM or SIGHUP, disables RF on every board registered
+/// with [`disable_rf_on_panic`] that this process enabled it on and lets
+/// the front panel back in where this process locked it out, then exits
+/// with status 128 plus the signal number. Does nothing outside Unix.
+pub fn disable_rf_on_signal() {
+    SIGNAL_WATCH.call_once(|| {
+        if !signals::install() {
+            return;
+        }
+        // Locks and port I/O are not allowed in a signal handler, so a
+        // thread acts on what the handler stored.
+        thread::spawn(|| loop {
+            thread::sleep(SIGNAL_POLL_INTERVAL);
+            let signal = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+            if signal == 0 {
+                continue;
+            }
+            let registered = match REGISTERED.lock() {
+                Ok(registered) => registered.clone(),
+                Err(poisoned) => poisoned.into_inner().clone(),
+            };
+            for controller in registered.iter().filter(|c| c.rf_enabled()) {
+                match controller.emergency_rf_off(PORT_WAIT) {
+                    Ok(()) => eprintln!("Signal {}: RF disabled", signal),
+                    Err(e) => eprintln!("Signal {}: failed to disable RF: {}", signal, e),
+                }
+            }
+            // The exit skips the drop of any `LocalLockout`.
+            for controller in registered.iter().filter(|c| c.is_exclusive_remote()) {
+                match controller.emergency_unlock(PORT_WAIT) {
+                    Ok(()) => eprintln!("Signal {}: front panel unlocked", signal),
+                    Err(e) => {
+                        eprintln!("Signal {}: failed to unlock the front panel: {}", signal, e)
+                    }
+                }
+            }
+            process::exit(128 + signal);
+        });
+    });
+}
+
+#[cfg(unix)]
+mod signals {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGHUP: c_int = 1;
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    extern "C" fn record(signum: c_int) {
+        super::RECEIVED_SIGNAL.store(signum, Ordering::SeqCst);
+    }
+
+    pub fn install() -> bool {
+        [SIGINT, SIGTERM, SIGHUP]
+            .iter()
+            // SAFETY: `record` only stores to an atomic, which is async-signal-safe.
+            .all(|&signum| unsafe { signal(signum, record) } != SIG_ERR)
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() -> bool {
+        false
+    }
+}
+
+/// Disables RF when dropped, including while unwinding from a panic.
+/// Hold one for as long as RF may be on.
+pub struct SafetyGuard {
+    /// Taken by [`SafetyGuard::release`].
+    controller: Option<Controller>,
+}
+
+impl SafetyGuard {
+    pub fn new(controller: &Controller) -> SafetyGuard {
+        SafetyGuard {
+            controller: Some(controller.clone()),
+        }
+    }
+
+    /// Disables RF now and reports whether that worked, instead of only
+    /// printing a failure as dropping does.
+    pub fn release(mut self) -> Result<(), ControllerError> {
+        match self.controller.take() {
+            Some(controller) => controller.safe_state(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for SafetyGuard {
+    fn drop(&mut self) {
+        let Some(controller) = self.controller.take() else {
+            return;
+        };
+        let result = if std::thread::panicking() {
+            controller.emergency_rf_off(PORT_WAIT)
+        } else {
+            controller.safe_state()
+        };
+        if let Err(e) = result {
+            eprintln!("Failed to disable RF: {}", e);
+        }
+    }
+}
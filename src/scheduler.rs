@@ -0,0 +1,197 @@
+//! Telemetry polling of many generators from a small pool of worker
+//! threads.
+//!
+//! The library has no async runtime; a [`PollScheduler`] gives the same
+//! effect for polling. Every device has its own interval and next due
+//! time, and the workers always take the device that is most overdue, so
+//! a device that stalls (a dead link waiting out its timeouts) only holds
+//! up the worker it is on. A device is never polled by two workers at
+//! once, and start times are staggered across the first interval so eight
+//! modules at 2 Hz do not all hit the network in the same millisecond.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::fleet::FleetListener;
+use crate::priority::Priority;
+use crate::telemetry::TelemetrySample;
+
+/// Longest a waiting worker sleeps before re-checking its token.
+const CANCEL_POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// How one device's polling has gone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PollStats {
+    pub polls: u64,
+    pub failures: u64,
+    /// Polls dropped because the previous one ran past their due time.
+    pub skipped: u64,
+}
+
+struct PolledDevice {
+    name: String,
+    controller: Controller,
+    interval: Duration,
+}
+
+/// Devices to poll, each at its own interval, and who gets the samples.
+pub struct PollScheduler {
+    workers: usize,
+    devices: Vec<PolledDevice>,
+    listeners: Vec<FleetListener>,
+}
+
+/// Running scheduler returned by [`PollScheduler::spawn`].
+pub struct PollHandle {
+    threads: Vec<JoinHandle<()>>,
+    stats: Arc<Mutex<BTreeMap<String, PollStats>>>,
+}
+
+impl PollHandle {
+    /// Counts so far, by device name.
+    pub fn stats(&self) -> BTreeMap<String, PollStats> {
+        self.stats
+            .lock()
+            .map(|stats| stats.clone())
+            .unwrap_or_default()
+    }
+
+    /// Waits for the workers to stop after the token is cancelled.
+    pub fn join(self) {
+        for thread in self.threads {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Devices waiting for their due time, most overdue first.
+struct Queue {
+    due: Mutex<BinaryHeap<Reverse<(Instant, usize)>>>,
+    changed: Condvar,
+}
+
+impl PollScheduler {
+    /// Scheduler sharing `workers` threads (at least one) among its devices.
+    pub fn new(workers: usize) -> PollScheduler {
+        PollScheduler {
+            workers: workers.max(1),
+            devices: Vec::new(),
+            listeners: Vec::new(),
+        }
+    }
+
+    /// Polls `controller` every `interval`, reporting samples as `name`.
+    pub fn add(mut self, name: &str, controller: &Controller, interval: Duration) -> PollScheduler {
+        self.devices.push(PolledDevice {
+            name: name.to_string(),
+            controller: controller.with_priority(Priority::Background),
+            interval,
+        });
+        self
+    }
+
+    pub fn subscribe(&mut self, listener: FleetListener) {
+        self.listeners.push(listener);
+    }
+
+    /// Starts the workers; they stop once `cancel` is cancelled. Failed
+    /// reads are reported on stderr and the device stays scheduled.
+    pub fn spawn(self, cancel: CancellationToken) -> PollHandle {
+        let start = Instant::now();
+        let count = self.devices.len().max(1) as u32;
+        let due = self
+            .devices
+            .iter()
+            .enumerate()
+            .map(|(index, device)| Reverse((start + device.interval / count * index as u32, index)))
+            .collect();
+        let queue = Arc::new(Queue {
+            due: Mutex::new(due),
+            changed: Condvar::new(),
+        });
+        let stats = Arc::new(Mutex::new(
+            self.devices
+                .iter()
+                .map(|device| (device.name.clone(), PollStats::default()))
+                .collect(),
+        ));
+        let scheduler = Arc::new(self);
+        let threads = (0..scheduler.workers.min(scheduler.devices.len()))
+            .map(|_| {
+                let (scheduler, queue, stats) = (scheduler.clone(), queue.clone(), stats.clone());
+                let cancel = cancel.clone();
+                thread::spawn(move || {
+                    while let Some((due, index)) = queue.next(&cancel) {
+                        scheduler.poll(index, due, &queue, &stats);
+                    }
+                })
+            })
+            .collect();
+        PollHandle { threads, stats }
+    }
+
+    fn poll(
+        &self,
+        index: usize,
+        due: Instant,
+        queue: &Queue,
+        stats: &Mutex<BTreeMap<String, PollStats>>,
+    ) {
+        let device = &self.devices[index];
+        let result = TelemetrySample::read(&device.controller);
+        match &result {
+            Ok(sample) => {
+                for listener in &self.listeners {
+                    listener(&device.name, sample);
+                }
+            }
+            Err(e) => eprintln!("{}: telemetry read failed: {}", device.name, e),
+        }
+
+        // Keep the phase; polls that are already due again are dropped, not bunched.
+        let mut next = due + device.interval;
+        let mut skipped = 0;
+        while next <= Instant::now() && !device.interval.is_zero() {
+            next += device.interval;
+            skipped += 1;
+        }
+        if let Ok(mut stats) = stats.lock() {
+            if let Some(stats) = stats.get_mut(&device.name) {
+                stats.polls += 1;
+                stats.failures += u64::from(result.is_err());
+                stats.skipped += skipped;
+            }
+        }
+        if let Ok(mut due) = queue.due.lock() {
+            due.push(Reverse((next, index)));
+        }
+        queue.changed.notify_one();
+    }
+}
+
+impl Queue {
+    /// Waits for the most overdue device, or `None` once cancelled.
+    fn next(&self, cancel: &CancellationToken) -> Option<(Instant, usize)> {
+        let mut due = self.due.lock().ok()?;
+        loop {
+            if cancel.is_cancelled() {
+                return None;
+            }
+            let now = Instant::now();
+            let wait = match due.peek() {
+                Some(Reverse((at, _))) if *at <= now => {
+                    return due.pop().map(|Reverse(next)| next);
+                }
+                Some(Reverse((at, _))) => (*at - now).min(CANCEL_POLL_INTERVAL),
+                // Every device is being polled by another worker.
+                None => CANCEL_POLL_INTERVAL,
+            };
+            due = self.changed.wait_timeout(due, wait).ok()?.0;
+        }
+    }
+}
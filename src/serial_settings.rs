@@ -0,0 +1,225 @@
+//! UART settings for opening a board's serial port.
+//!
+//! Boards ship at 115200 8N1 without flow control, but some are
+//! reconfigured or sit behind converters with other settings. The defaults
+//! are the constants in [`controller_properites`](crate::controller_properites);
+//! a [`DeviceProfile`](crate::profile::DeviceProfile) or the CLI can
+//! override any of them.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::controller_properites::{BAUD_RATE, CONNECTION_TIMEOUT};
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::latency::DEFAULT_RESPONSE_TIMEOUT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    None,
+    Odd,
+    Even,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlowControl {
+    None,
+    /// XON/XOFF.
+    Software,
+    /// RTS/CTS.
+    Hardware,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SerialSettings {
+    pub baud_rate: u32,
+    /// 5 to 8.
+    pub data_bits: u8,
+    pub parity: Parity,
+    /// 1 or 2.
+    pub stop_bits: u8,
+    pub flow_control: FlowControl,
+    /// Timeout of a single read from the port.
+    pub read_timeout: Duration,
+    /// How long to wait for a reply unless adaptive timeouts are enabled.
+    pub response_timeout: Duration,
+}
+
+impl Default for SerialSettings {
+    fn default() -> SerialSettings {
+        SerialSettings {
+            baud_rate: BAUD_RATE,
+            data_bits: 8,
+            parity: Parity::None,
+            stop_bits: 1,
+            flow_control: FlowControl::None,
+            read_timeout: CONNECTION_TIMEOUT,
+            response_timeout: DEFAULT_RESPONSE_TIMEOUT,
+        }
+    }
+}
+
+impl SerialSettings {
+    /// Checks the values a UART can actually be set to.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.baud_rate == 0 {
+            return Err("Baud rate must be above zero".to_string());
+        }
+        if !(5..=8).contains(&self.data_bits) {
+            return Err(format!("Data bits must be 5 to 8, got {}", self.data_bits));
+        }
+        if !(1..=2).contains(&self.stop_bits) {
+            return Err(format!("Stop bits must be 1 or 2, got {}", self.stop_bits));
+        }
+        if self.response_timeout.is_zero() {
+            return Err("Response timeout must be above zero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Framing in the usual short form, e.g. `8N1`.
+    pub fn framing(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Odd => 'O',
+            Parity::Even => 'E',
+        };
+        format!("{}{}{}", self.data_bits, parity, self.stop_bits)
+    }
+
+    /// Builder for the port with these settings.
+    #[cfg(feature = "serial")]
+    pub fn builder(&self, port_name: &str) -> serialport::SerialPortBuilder {
+        use serialport::{DataBits, StopBits};
+
+        let data_bits = match self.data_bits {
+            5 => DataBits::Five,
+            6 => DataBits::Six,
+            7 => DataBits::Seven,
+            _ => DataBits::Eight,
+        };
+        let parity = match self.parity {
+            Parity::None => serialport::Parity::None,
+            Parity::Odd => serialport::Parity::Odd,
+            Parity::Even => serialport::Parity::Even,
+        };
+        let stop_bits = match self.stop_bits {
+            2 => StopBits::Two,
+            _ => StopBits::One,
+        };
+        let flow_control = match self.flow_control {
+            FlowControl::None => serialport::FlowControl::None,
+            FlowControl::Software => serialport::FlowControl::Software,
+            FlowControl::Hardware => serialport::FlowControl::Hardware,
+        };
+        serialport::new(port_name, self.baud_rate)
+            .data_bits(data_bits)
+            .parity(parity)
+            .flow_control(flow_control)
+            .stop_bits(stop_bits)
+            .timeout(self.read_timeout)
+    }
+}
+
+impl fmt::Display for SerialSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.baud_rate, self.framing())?;
+        if self.flow_control != FlowControl::None {
+            write!(f, " {} flow control", self.flow_control)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Parity {
+    type Err = String;
+
+    fn from_str(parity: &str) -> Result<Parity, String> {
+        match parity.to_ascii_lowercase().as_str() {
+            "none" | "n" => Ok(Parity::None),
+            "odd" | "o" => Ok(Parity::Odd),
+            "even" | "e" => Ok(Parity::Even),
+            _ => Err(format!("Unknown parity {}; use none, odd or even", parity)),
+        }
+    }
+}
+
+impl fmt::Display for Parity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Parity::None => "none",
+            Parity::Odd => "odd",
+            Parity::Even => "even",
+        })
+    }
+}
+
+impl FromStr for FlowControl {
+    type Err = String;
+
+    fn from_str(flow_control: &str) -> Result<FlowControl, String> {
+        match flow_control.to_ascii_lowercase().as_str() {
+            "none" => Ok(FlowControl::None),
+            "software" | "xonxoff" => Ok(FlowControl::Software),
+            "hardware" | "rtscts" => Ok(FlowControl::Hardware),
+            _ => Err(format!(
+                "Unknown flow control {}; use none, software or hardware",
+                flow_control
+            )),
+        }
+    }
+}
+
+impl fmt::Display for FlowControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FlowControl::None => "none",
+            FlowControl::Software => "software",
+            FlowControl::Hardware => "hardware",
+        })
+    }
+}
+
+impl ToJson for SerialSettings {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("baud_rate", self.baud_rate as u64)
+            .with("data_bits", self.data_bits as u64)
+            .with("parity", self.parity.to_string())
+            .with("stop_bits", self.stop_bits as u64)
+            .with("flow_control", self.flow_control.to_string())
+            .with("read_timeout_s", self.read_timeout)
+            .with("response_timeout_s", self.response_timeout)
+    }
+}
+
+/// Keys left out keep their defaults, so a profile can give only the baud rate.
+impl FromJson for SerialSettings {
+    fn from_json(json: &JsonValue) -> Result<SerialSettings, String> {
+        let present = |key: &str| !matches!(json.get(key), None | Some(JsonValue::Null));
+        let mut settings = SerialSettings::default();
+        if present("baud_rate") {
+            settings.baud_rate = json.number("baud_rate")? as u32;
+        }
+        if present("data_bits") {
+            settings.data_bits = json.number("data_bits")? as u8;
+        }
+        if present("parity") {
+            settings.parity = json.string("parity")?.parse()?;
+        }
+        if present("stop_bits") {
+            settings.stop_bits = json.number("stop_bits")? as u8;
+        }
+        if present("flow_control") {
+            settings.flow_control = json.string("flow_control")?.parse()?;
+        }
+        if present("read_timeout_s") {
+            settings.read_timeout = json.duration("read_timeout_s")?;
+        }
+        if present("response_timeout_s") {
+            settings.response_timeout = json.duration("response_timeout_s")?;
+        }
+        settings.validate()?;
+        Ok(settings)
+    }
+}
@@ -0,0 +1,122 @@
+use std::time::{Duration, SystemTime};
+
+use crate::device_state::DeviceState;
+use crate::telemetry::TelemetrySample;
+use crate::units::parse_timestamp;
+
+/// Kind of a protocol trace line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RecordKind {
+    Tx,
+    Rx,
+    /// A command that failed without a reply (timeout, I/O error).
+    Err,
+    Telemetry(TelemetrySample),
+    Other(String),
+}
+
+/// One line of a recorded session, as written by [`Controller::set_trace`](crate::Controller::set_trace).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionRecord {
+    pub timestamp: SystemTime,
+    pub kind: RecordKind,
+    pub text: String,
+}
+
+/// Parses a session log. Blank lines are skipped; malformed lines are errors.
+pub fn parse_session_log(log: &str) -> Result<Vec<SessionRecord>, String> {
+    let mut records = Vec::new();
+    for (number, line) in log.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut fields = line.splitn(3, '\t');
+        let (timestamp, kind, text) = match (fields.next(), fields.next(), fields.next()) {
+            (Some(timestamp), Some(kind), text) => (timestamp, kind, text.unwrap_or("")),
+            _ => return Err(format!("Malformed session line {}: {}", number + 1, line)),
+        };
+        let timestamp = parse_timestamp(timestamp)
+            .map_err(|e| format!("Malformed session line {}: {}", number + 1, e))?;
+        let kind = match kind {
+            "TX" => RecordKind::Tx,
+            "RX" => RecordKind::Rx,
+            "ERR" => RecordKind::Err,
+            "TELEMETRY" => RecordKind::Telemetry(
+                TelemetrySample::from_csv_row(text)
+                    .map_err(|e| format!("Malformed session line {}: {}", number + 1, e))?,
+            ),
+            other => RecordKind::Other(other.to_string()),
+        };
+        records.push(SessionRecord {
+            timestamp,
+            kind,
+            text: text.to_string(),
+        });
+    }
+    Ok(records)
+}
+
+/// Walks a session in order, reconstructing the device state after every record.
+pub struct SessionReplay {
+    records: Vec<SessionRecord>,
+    position: usize,
+    last_tx: Option<String>,
+    state: DeviceState,
+}
+
+/// A replayed record with its offset from the start of the session.
+pub struct ReplayStep<'a> {
+    pub offset: Duration,
+    pub record: &'a SessionRecord,
+    /// Whether this record changed the reconstructed state.
+    pub state_changed: bool,
+}
+
+impl SessionReplay {
+    pub fn new(records: Vec<SessionRecord>) -> SessionReplay {
+        SessionReplay {
+            records,
+            position: 0,
+            last_tx: None,
+            state: DeviceState::default(),
+        }
+    }
+
+    pub fn state(&self) -> &DeviceState {
+        &self.state
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Advances one record.
+    pub fn next_step(&mut self) -> Option<ReplayStep<'_>> {
+        let record = self.records.get(self.position)?;
+        self.position += 1;
+
+        let before = self.state.clone();
+        match &record.kind {
+            RecordKind::Tx => self.last_tx = Some(record.text.clone()),
+            RecordKind::Rx => {
+                if let Some(tx) = self.last_tx.take() {
+                    self.state.apply_exchange(&tx, &record.text);
+                }
+            }
+            RecordKind::Err => self.last_tx = None,
+            RecordKind::Telemetry(sample) => self.state.apply_telemetry(sample),
+            RecordKind::Other(_) => {}
+        }
+
+        let start = self.records[0].timestamp;
+        Some(ReplayStep {
+            offset: record.timestamp.duration_since(start).unwrap_or_default(),
+            record,
+            state_changed: before != self.state,
+        })
+    }
+}
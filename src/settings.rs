@@ -0,0 +1,79 @@
+//! Everything the board can be asked about its configuration, read in one
+//! call by [`Controller::read_all_settings`](crate::Controller::read_all_settings).
+
+use std::fmt;
+use std::time::SystemTime;
+
+use crate::json::{JsonValue, ToJson};
+use crate::protocol::StatusFlags;
+use crate::units::format_timestamp;
+
+/// DLL settings last sent through the controller. The board cannot be
+/// asked for them, so fields stay `None` until this handle sets them.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DllSettings {
+    pub enabled: Option<bool>,
+    /// Lower, upper and start frequency, step, threshold and delay, in the
+    /// order of `$DLES,0,...`.
+    pub parameters: Option<[f32; 6]>,
+}
+
+/// Snapshot of the board's settings and readings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceSettings {
+    pub read_at: SystemTime,
+    pub frequency_mhz: f32,
+    pub power_setpoint_dbm: f32,
+    pub forward_dbm: f32,
+    pub reflected_dbm: f32,
+    pub status: StatusFlags,
+    /// PA temperature, if the firmware answers `$PTG`.
+    pub temperature_c: Option<f32>,
+    pub dll: DllSettings,
+    /// As tracked by the controller; the board has no query for it.
+    pub rf_enabled: bool,
+}
+
+impl fmt::Display for DeviceSettings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let switch = |value: Option<bool>| match value {
+            Some(true) => "on",
+            Some(false) => "off",
+            None => "?",
+        };
+        write!(
+            f,
+            "freq {:.2} MHz | setpoint {:.2} dBm | RF {} | DLL {} | fwd {:.2} dBm | refl {:.2} dBm | temp {} | status {}",
+            self.frequency_mhz,
+            self.power_setpoint_dbm,
+            switch(Some(self.rf_enabled)),
+            switch(self.dll.enabled),
+            self.forward_dbm,
+            self.reflected_dbm,
+            self.temperature_c
+                .map(|t| format!("{:.1} °C", t))
+                .unwrap_or_else(|| "?".to_string()),
+            self.status
+        )
+    }
+}
+
+impl ToJson for DeviceSettings {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("read_at", format_timestamp(self.read_at))
+            .with("frequency_mhz", self.frequency_mhz)
+            .with("power_setpoint_dbm", self.power_setpoint_dbm)
+            .with("forward_dbm", self.forward_dbm)
+            .with("reflected_dbm", self.reflected_dbm)
+            .with("status", self.status.to_string())
+            .with("status_code", u64::from(self.status.0))
+            .with("temperature_c", self.temperature_c)
+            .with("dll_enabled", self.dll.enabled)
+            .with(
+                "dll_parameters",
+                self.dll.parameters.map(|parameters| parameters.to_vec()),
+            )
+            .with("rf_enabled", self.rf_enabled)
+    }
+}
@@ -0,0 +1,503 @@
+//! Virtual ISC board that answers the serial line protocol from an in-memory
+//! model, for developing against the controller without hardware.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::framing::LineFramer;
+use crate::protocol::StatusFlags;
+use crate::transport::Transport;
+use crate::units::dbm_to_watts;
+
+pub const MIN_FREQUENCY_MHZ: f32 = 2400.0;
+pub const MAX_FREQUENCY_MHZ: f32 = 2500.0;
+pub const MAX_POWER_DBM: f32 = 53.0;
+
+/// Model state of the simulated board.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimulatorState {
+    pub frequency_mhz: f32,
+    pub power_setpoint_dbm: f32,
+    pub rf_enabled: bool,
+    pub dll_enabled: bool,
+    /// Lower, upper, start and step frequency (MHz), threshold (return
+    /// loss, dB) and main delay (ms), as sent with `$DLES`.
+    pub dll_parameters: [f32; 6],
+    pub temperature_c: f32,
+    /// Return loss of the simulated load when `load` is `None`;
+    /// reflected = forward - return loss.
+    pub return_loss_db: f32,
+    /// Frequency-dependent load, e.g. a cavity.
+    pub load: Option<LoadModel>,
+    /// `$ST` error word, see [`StatusFlags`].
+    pub status: u32,
+    /// PA heating; `None` keeps `temperature_c` where it is set.
+    pub thermal: Option<ThermalModel>,
+    /// DC rail answering `$PVG` and `$PCG`.
+    pub supply: SupplyModel,
+    /// Fan duty cycle set with `$FNS`, %.
+    pub fan_speed_percent: f32,
+    /// Front panel locked out with `$RLS`, see [`Simulator::front_panel`].
+    pub local_lockout: bool,
+}
+
+/// Single resonance load: the reflection coefficient follows a Lorentzian
+/// dip around `resonance_mhz`, reaching `match_return_loss_db` at
+/// resonance and total reflection far from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadModel {
+    pub resonance_mhz: f32,
+    /// Loaded Q; the -3 dB bandwidth of the dip is `resonance_mhz / loaded_q`.
+    pub loaded_q: f32,
+    pub match_return_loss_db: f32,
+    /// How fast the resonance moves, MHz/s, e.g. as a load heats up.
+    pub drift_mhz_per_s: f32,
+}
+
+impl Default for LoadModel {
+    fn default() -> LoadModel {
+        LoadModel {
+            resonance_mhz: 2450.0,
+            loaded_q: 500.0,
+            match_return_loss_db: 25.0,
+            drift_mhz_per_s: 0.0,
+        }
+    }
+}
+
+impl LoadModel {
+    /// Return loss at `frequency_mhz`, dB.
+    pub fn return_loss_db(&self, frequency_mhz: f32) -> f32 {
+        let detuning =
+            2.0 * self.loaded_q * (frequency_mhz - self.resonance_mhz) / self.resonance_mhz;
+        let matched = 1.0 - 10f32.powf(-self.match_return_loss_db / 10.0);
+        let reflected = 1.0 - matched / (1.0 + detuning * detuning);
+        -10.0 * reflected.max(1e-12).log10()
+    }
+}
+
+/// First-order thermal model of the PA: the temperature approaches
+/// `ambient_c + thermal_resistance * dissipated power` with time constant
+/// `time_constant`, and falls back towards ambient while RF is off.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThermalModel {
+    pub ambient_c: f32,
+    /// Steady-state rise per watt dissipated with the fans off, °C/W.
+    pub thermal_resistance: f32,
+    pub time_constant: Duration,
+    /// Drain efficiency; the rest of the DC input, plus the reflected
+    /// power, is dissipated in the PA.
+    pub efficiency: f32,
+    /// Share of `thermal_resistance` the fans take away at full speed.
+    pub fan_cooling: f32,
+    /// Sets the high temperature bit while exceeded.
+    pub warning_c: f32,
+    /// Sets the shutdown bit and disables RF.
+    pub shutdown_c: f32,
+}
+
+impl Default for ThermalModel {
+    fn default() -> ThermalModel {
+        ThermalModel {
+            ambient_c: 25.0,
+            thermal_resistance: 0.3,
+            time_constant: Duration::from_secs(30),
+            efficiency: 0.5,
+            fan_cooling: 0.5,
+            warning_c: 65.0,
+            shutdown_c: 80.0,
+        }
+    }
+}
+
+/// PA supply rail: a source with internal resistance, so the voltage sags
+/// as the PA draws more current. Lower `voltage_v` to simulate a failing
+/// supply.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SupplyModel {
+    /// Open-circuit voltage, V.
+    pub voltage_v: f32,
+    /// Source and wiring resistance, Ω.
+    pub resistance_ohm: f32,
+    /// Draw with RF off, A.
+    pub idle_a: f32,
+}
+
+impl Default for SupplyModel {
+    fn default() -> SupplyModel {
+        SupplyModel {
+            voltage_v: 32.0,
+            resistance_ohm: 0.05,
+            idle_a: 0.3,
+        }
+    }
+}
+
+impl Default for SimulatorState {
+    fn default() -> SimulatorState {
+        SimulatorState {
+            frequency_mhz: 2450.0,
+            power_setpoint_dbm: 30.0,
+            rf_enabled: false,
+            dll_enabled: false,
+            dll_parameters: [0.0; 6],
+            temperature_c: 25.0,
+            return_loss_db: 15.0,
+            load: Some(LoadModel::default()),
+            status: 0,
+            thermal: Some(ThermalModel::default()),
+            supply: SupplyModel::default(),
+            fan_speed_percent: 0.0,
+            local_lockout: false,
+        }
+    }
+}
+
+impl SimulatorState {
+    /// Return loss of the load at `frequency_mhz`.
+    pub fn return_loss_db_at(&self, frequency_mhz: f32) -> f32 {
+        match &self.load {
+            Some(load) => load.return_loss_db(frequency_mhz),
+            None => self.return_loss_db,
+        }
+    }
+
+    /// Forward and reflected power as reported by `$PPG`.
+    pub fn measured_power(&self) -> (f32, f32) {
+        if self.rf_enabled {
+            (
+                self.power_setpoint_dbm,
+                self.power_setpoint_dbm - self.return_loss_db_at(self.frequency_mhz),
+            )
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    /// Power dissipated in the PA, W.
+    pub fn dissipated_watts(&self) -> f32 {
+        let efficiency = match &self.thermal {
+            Some(thermal) if self.rf_enabled => thermal.efficiency.clamp(0.01, 1.0),
+            _ => return 0.0,
+        };
+        let (forward, reflected) = self.measured_power();
+        let forward = dbm_to_watts(forward) as f32;
+        forward * (1.0 / efficiency - 1.0) + dbm_to_watts(reflected) as f32
+    }
+
+    /// Supply voltage and current as reported by `$PVG` and `$PCG`: the
+    /// idle draw plus the DC input of the PA at the thermal model's
+    /// efficiency (the default one without a model).
+    pub fn supply_reading(&self) -> (f32, f32) {
+        let supply = &self.supply;
+        let efficiency = self
+            .thermal
+            .as_ref()
+            .map_or(ThermalModel::default().efficiency, |thermal| {
+                thermal.efficiency
+            })
+            .clamp(0.01, 1.0);
+        let dc_input = match self.rf_enabled {
+            true => dbm_to_watts(self.measured_power().0) as f32 / efficiency,
+            false => 0.0,
+        };
+        let current = supply.idle_a + dc_input / supply.voltage_v.max(f32::EPSILON);
+        let voltage = (supply.voltage_v - current * supply.resistance_ohm).max(0.0);
+        (voltage, current)
+    }
+
+    /// Lets `elapsed` pass: drifts the load's resonance, runs the DLL, heats
+    /// or cools the PA and raises the temperature status bits, shutting RF
+    /// down above the shutdown limit.
+    pub fn advance(&mut self, elapsed: Duration) {
+        if let Some(load) = &mut self.load {
+            load.resonance_mhz += load.drift_mhz_per_s * elapsed.as_secs_f32();
+        }
+        self.track_dll(elapsed);
+        let thermal = match self.thermal.clone() {
+            Some(thermal) => thermal,
+            None => return,
+        };
+        let cooling = thermal.fan_cooling.clamp(0.0, 1.0) * self.fan_speed_percent / 100.0;
+        let target = thermal.ambient_c
+            + thermal.thermal_resistance * (1.0 - cooling) * self.dissipated_watts();
+        let tau = thermal.time_constant.as_secs_f32().max(f32::EPSILON);
+        let approach = 1.0 - (-elapsed.as_secs_f32() / tau).exp();
+        self.temperature_c += (target - self.temperature_c) * approach;
+
+        if self.temperature_c > thermal.warning_c {
+            self.status |= StatusFlags::HIGH_TEMPERATURE;
+        }
+        if self.temperature_c > thermal.shutdown_c {
+            self.status |= StatusFlags::SHUTDOWN_TEMPERATURE;
+            self.rf_enabled = false;
+        }
+    }
+
+    /// One DLL iteration per main delay while RF is on: stay while the
+    /// return loss meets the threshold, otherwise move a step towards the
+    /// better neighbour, or restart the search at the lower frequency if
+    /// neither is better. A step too coarse for the threshold never settles.
+    fn track_dll(&mut self, elapsed: Duration) {
+        let [lower, upper, _, step, threshold_db, delay_ms] = self.dll_parameters;
+        if !self.dll_enabled || !self.rf_enabled || step <= 0.0 || lower >= upper {
+            return;
+        }
+        let iterations = (elapsed.as_secs_f32() * 1000.0 / delay_ms.max(1.0)).min(10_000.0);
+        for _ in 0..iterations as usize {
+            let here = self.return_loss_db_at(self.frequency_mhz);
+            if here >= threshold_db {
+                break;
+            }
+            let best = [self.frequency_mhz - step, self.frequency_mhz + step]
+                .map(|frequency| frequency.clamp(lower, upper))
+                .into_iter()
+                .max_by(|a, b| {
+                    self.return_loss_db_at(*a)
+                        .total_cmp(&self.return_loss_db_at(*b))
+                })
+                .unwrap_or(lower);
+            self.frequency_mhz = if self.return_loss_db_at(best) > here {
+                best
+            } else {
+                lower
+            };
+        }
+    }
+}
+
+/// Simulated board usable as a [`Transport`].
+///
+/// Clones share the same model, so a clone kept by the caller can inspect or
+/// alter the board while a controller talks to it.
+#[derive(Clone, Default)]
+pub struct Simulator {
+    model: Arc<Mutex<SimulatorState>>,
+    link: Arc<Mutex<Link>>,
+    /// When the model was last advanced to.
+    clock: Arc<Mutex<Option<Instant>>>,
+}
+
+#[derive(Default)]
+struct Link {
+    framer: LineFramer,
+    rx: VecDeque<u8>,
+}
+
+impl Simulator {
+    pub fn new() -> Simulator {
+        Simulator::default()
+    }
+
+    pub fn with_state(state: SimulatorState) -> Simulator {
+        Simulator {
+            model: Arc::new(Mutex::new(state)),
+            link: Arc::default(),
+            clock: Arc::default(),
+        }
+    }
+
+    /// Snapshot of the model.
+    pub fn state(&self) -> SimulatorState {
+        self.model().clone()
+    }
+
+    /// Changes the model, e.g. to inject a fault bit.
+    pub fn update(&self, change: impl FnOnce(&mut SimulatorState)) {
+        change(&mut self.model());
+    }
+
+    /// Changes the model as someone at the board's front panel would:
+    /// refused, returning `false`, while the panel is locked out.
+    pub fn front_panel(&self, change: impl FnOnce(&mut SimulatorState)) -> bool {
+        let mut state = self.model();
+        if state.local_lockout {
+            return false;
+        }
+        change(&mut state);
+        true
+    }
+
+    /// Answers one command line. The reply includes its `\r\n` terminator(s).
+    /// The model is first [advanced](SimulatorState::advance) by the time
+    /// since the previous command.
+    pub fn handle(&self, line: &str) -> String {
+        let mut state = self.model();
+        let now = Instant::now();
+        let previous = self
+            .clock
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .replace(now);
+        if let Some(previous) = previous {
+            state.advance(now - previous);
+        }
+        let fields: Vec<&str> = line.trim().split(',').map(str::trim).collect();
+        let mnemonic = fields[0];
+        if fields.get(1) != Some(&"0") {
+            return format!("{},ERR\r\n", mnemonic);
+        }
+        let args: Vec<f32> = fields[2..].iter().filter_map(|f| f.parse().ok()).collect();
+        let ok = format!("{},0,OK\r\n", mnemonic);
+        let err = format!("{},0,ERR\r\n", mnemonic);
+        if args.len() != fields.len() - 2 {
+            return err;
+        }
+
+        match (mnemonic, args.as_slice()) {
+            ("$IDN", []) => "$IDN,0,Simulator,ISC-SIM\r\n".to_string(),
+            ("$VER", []) => format!("$VER,0,{}-sim\r\n", env!("CARGO_PKG_VERSION")),
+            ("$ST", []) => format!("$ST,0,0x{:X}\r\n", state.status),
+            ("$ST", [_]) => {
+                let mut reply = String::new();
+                for description in StatusFlags(state.status).descriptions() {
+                    reply.push_str(&format!("$ST,0,{}\r\n", description));
+                }
+                reply.push_str("OK\r\n");
+                reply
+            }
+            ("$ERRC", []) => {
+                state.status = 0;
+                ok
+            }
+            ("$FCG", []) => format!("$FCG,0,{:.2}\r\n", state.frequency_mhz),
+            ("$FCS", [frequency]) => {
+                if (MIN_FREQUENCY_MHZ..=MAX_FREQUENCY_MHZ).contains(frequency) {
+                    state.frequency_mhz = *frequency;
+                    ok
+                } else {
+                    err
+                }
+            }
+            ("$PWRG", []) => format!("$PWRG,0,{:.2}\r\n", state.power_setpoint_dbm),
+            ("$PWRS", [power]) => {
+                if *power <= MAX_POWER_DBM {
+                    state.power_setpoint_dbm = *power;
+                    ok
+                } else {
+                    err
+                }
+            }
+            ("$PPG", []) => {
+                let (forward, reflected) = state.measured_power();
+                format!("$PPG,0,{:.2},{:.2}\r\n", forward, reflected)
+            }
+            ("$PTG", []) => format!("$PTG,0,{:.1}\r\n", state.temperature_c),
+            ("$PVG", []) => format!("$PVG,0,{:.2}\r\n", state.supply_reading().0),
+            ("$PCG", []) => format!("$PCG,0,{:.2}\r\n", state.supply_reading().1),
+            ("$FNG", []) => format!("$FNG,0,{:.0}\r\n", state.fan_speed_percent),
+            ("$RLS", [lock]) => {
+                state.local_lockout = *lock != 0.0;
+                ok
+            }
+            ("$RLG", []) => format!("$RLG,0,{}\r\n", u8::from(state.local_lockout)),
+            ("$FNS", [percent]) => {
+                if (0.0..=100.0).contains(percent) {
+                    state.fan_speed_percent = *percent;
+                    ok
+                } else {
+                    err
+                }
+            }
+            ("$DLES", [enable]) => {
+                state.dll_enabled = *enable != 0.0;
+                let [lower, upper, start, ..] = state.dll_parameters;
+                if state.dll_enabled && (lower..=upper).contains(&start) {
+                    state.frequency_mhz = start;
+                }
+                ok
+            }
+            ("$DLES", [_, _, _, _, _, _]) | ("$DLCS", [_, _, _, _, _, _]) => {
+                state.dll_parameters.copy_from_slice(&args);
+                ok
+            }
+            ("$ECS", [enable]) => {
+                if *enable != 0.0 && StatusFlags(state.status).is_shutdown() {
+                    err
+                } else {
+                    state.rf_enabled = *enable != 0.0;
+                    ok
+                }
+            }
+            ("$SWPD", [start, stop, step, power, _]) if *step > 0.0 && start <= stop => {
+                let power = *power;
+                let mut reply = String::new();
+                let count = ((stop - start) / step).floor() as usize;
+                for i in 0..=count {
+                    let frequency = start + step * i as f32;
+                    reply.push_str(&format!(
+                        "$SWPD,0,{:.2},{:.2},{:.2}\r\n",
+                        frequency,
+                        power,
+                        power - state.return_loss_db_at(frequency)
+                    ));
+                }
+                reply.push_str("OK\r\n");
+                reply
+            }
+            _ => err,
+        }
+    }
+
+    fn model(&self) -> MutexGuard<'_, SimulatorState> {
+        // The model holds plain values, so a panic elsewhere cannot leave it inconsistent.
+        self.model.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn link(&self) -> MutexGuard<'_, Link> {
+        self.link.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Read for Simulator {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut link = self.link();
+        if link.rx.is_empty() {
+            drop(link);
+            std::thread::sleep(Duration::from_millis(1));
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(link.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(link.rx.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for Simulator {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let lines = self.link().framer.push(buf);
+        for line in lines {
+            let reply = self.handle(&line);
+            self.link().rx.extend(reply.bytes());
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for Simulator {
+    fn name(&self) -> Option<String> {
+        Some("simulator".to_string())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(1)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        Ok(self.link().rx.drain(..).collect())
+    }
+}
@@ -0,0 +1,318 @@
+//! Soak (burn-in) tests for outgoing QC: RF on at one setpoint for hours
+//! while telemetry is read, the status word is checked and the power is
+//! derated when the PA runs hot, ending in a pass or a fail.
+//!
+//! A unit fails when a limit is exceeded, the status word reports a
+//! critical [`DeviceFault`], a command fails or the forward power strays
+//! from the setpoint by more than the tolerance. The first three stop the
+//! test with RF off; deviations are counted until the end.
+
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::alerting::Severity;
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_status;
+use crate::derating::{DeratingPolicy, DeratingRule};
+use crate::error::{ControllerError, DeviceFault};
+use crate::events::ControllerEvent;
+use crate::protocol::StatusFlags;
+use crate::safety::SafetyGuard;
+use crate::telemetry::TelemetrySample;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SoakTest {
+    pub power_dbm: f32,
+    /// Time with RF on; pauses do not count.
+    pub duration: Duration,
+    /// Frequency set before RF is enabled; the board's current one otherwise.
+    pub frequency_mhz: Option<f32>,
+    /// How often telemetry is read and the limits and derating checked.
+    pub telemetry_interval: Duration,
+    /// How often the status word is read.
+    pub status_interval: Duration,
+    pub derating: DeratingPolicy,
+    /// Fails the unit when the PA gets hotter.
+    pub max_temperature_c: Option<f32>,
+    /// Fails the unit when more power is reflected.
+    pub max_reflected_dbm: Option<f32>,
+    /// Largest allowed difference between forward power and setpoint.
+    pub tolerance_db: Option<f32>,
+}
+
+impl SoakTest {
+    pub fn new(power_dbm: f32, duration: Duration) -> SoakTest {
+        SoakTest {
+            power_dbm,
+            duration,
+            frequency_mhz: None,
+            telemetry_interval: Duration::from_secs(1),
+            status_interval: Duration::from_secs(60),
+            derating: DeratingPolicy::default(),
+            max_temperature_c: None,
+            max_reflected_dbm: None,
+            tolerance_db: None,
+        }
+    }
+
+    pub fn with_frequency(mut self, mhz: f32) -> SoakTest {
+        self.frequency_mhz = Some(mhz);
+        self
+    }
+
+    pub fn with_telemetry_interval(mut self, interval: Duration) -> SoakTest {
+        self.telemetry_interval = interval;
+        self
+    }
+
+    pub fn with_status_interval(mut self, interval: Duration) -> SoakTest {
+        self.status_interval = interval;
+        self
+    }
+
+    pub fn with_derating(mut self, rule: DeratingRule) -> SoakTest {
+        self.derating.tiers.push(rule);
+        self
+    }
+
+    pub fn with_hysteresis(mut self, celsius: f32) -> SoakTest {
+        self.derating.hysteresis_c = celsius;
+        self
+    }
+
+    pub fn with_max_temperature(mut self, celsius: f32) -> SoakTest {
+        self.max_temperature_c = Some(celsius);
+        self
+    }
+
+    pub fn with_max_reflected(mut self, dbm: f32) -> SoakTest {
+        self.max_reflected_dbm = Some(dbm);
+        self
+    }
+
+    pub fn with_tolerance(mut self, db: f32) -> SoakTest {
+        self.tolerance_db = Some(db);
+        self
+    }
+
+    /// The configuration as label and value, for the burn-in report.
+    pub fn settings(&self) -> Vec<(String, String)> {
+        let mut settings = vec![
+            ("Power".to_string(), format!("{:.2} dBm", self.power_dbm)),
+            (
+                "Duration".to_string(),
+                format!("{:.2} h", self.duration.as_secs_f64() / 3600.0),
+            ),
+        ];
+        if let Some(mhz) = self.frequency_mhz {
+            settings.push(("Frequency".to_string(), format!("{:.2} MHz", mhz)));
+        }
+        settings.push((
+            "Status check".to_string(),
+            format!("every {:.0} s", self.status_interval.as_secs_f64()),
+        ));
+        for rule in &self.derating.tiers {
+            settings.push(("Derating".to_string(), rule.to_string()));
+        }
+        let limits = [
+            ("Max temperature", self.max_temperature_c, "°C"),
+            ("Max reflected", self.max_reflected_dbm, "dBm"),
+            ("Forward tolerance", self.tolerance_db, "dB"),
+        ];
+        for (label, limit, unit) in limits {
+            if let Some(limit) = limit {
+                settings.push((label.to_string(), format!("{} {}", limit, unit)));
+            }
+        }
+        settings
+    }
+
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<SoakResult, ControllerError> {
+        self.run_with_samples(controller, cancel, &mut |_| {})
+    }
+
+    /// Sets the output, enables RF for `duration` and disables it again,
+    /// handing every sample to `on_sample`, e.g. for a telemetry log.
+    ///
+    /// An invalid setpoint fails before RF is enabled; everything after
+    /// that, cancellation included, ends the test as a failed
+    /// [`SoakResult`] so the report can still be written.
+    pub fn run_with_samples(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_sample: &mut dyn FnMut(&TelemetrySample),
+    ) -> Result<SoakResult, ControllerError> {
+        let setup: Vec<Command> = self
+            .frequency_mhz
+            .map(Command::SetFrequency)
+            .into_iter()
+            .chain([Command::SetPower(self.power_dbm)])
+            .collect();
+        for command in &setup {
+            command.validate()?;
+        }
+        for rule in &self.derating.tiers {
+            Command::SetPower(self.power_dbm - rule.reduce_db).validate()?;
+        }
+        for command in &setup {
+            controller.send(command)?;
+        }
+
+        let mut result = SoakResult::default();
+        let guard = SafetyGuard::new(controller);
+        controller.send(&Command::RfEnable)?;
+        let started = Instant::now();
+        let mut last_status = Instant::now();
+        let mut status = StatusFlags::default();
+        let mut derating_db = 0.0;
+        let mut remaining = self.duration;
+        while !remaining.is_zero() {
+            let slice = remaining.min(self.telemetry_interval);
+            let restore = [
+                Command::SetPower(self.power_dbm - derating_db),
+                Command::RfEnable,
+            ];
+            let step = cancel.hold(controller, slice, &restore).and_then(|()| {
+                remaining -= slice;
+                let sample = TelemetrySample::read(controller)?;
+                on_sample(&sample);
+                let due = last_status.elapsed() >= self.status_interval || remaining.is_zero();
+                if due {
+                    last_status = Instant::now();
+                    result.status_checks += 1;
+                    let now =
+                        parse_status(&controller.send(&Command::GetStatus { verbose: false })?)?;
+                    if now != status {
+                        status = now;
+                        result.event(format!("Status {}", status));
+                    }
+                    if let Some(fault) = DeviceFault::of_status(status)
+                        .find(|fault| fault.severity() == Severity::Critical)
+                    {
+                        result.fail(format!("The board reported {} ({})", fault, status));
+                        return Ok(());
+                    }
+                }
+                self.check(&sample, &mut result);
+                if let Some(temperature_c) = sample.temperature_c {
+                    let next = self.derating.reduction_at(temperature_c, derating_db);
+                    if next != derating_db {
+                        controller.send(&Command::SetPower(self.power_dbm - next))?;
+                        let derated = ControllerEvent::PowerDerated {
+                            reduction_db: next,
+                            setpoint_dbm: self.power_dbm - next,
+                            temperature_c,
+                        };
+                        result.event(derated.to_string());
+                        derating_db = next;
+                        result.max_derating_db = result.max_derating_db.max(next);
+                    }
+                }
+                Ok(())
+            });
+            if let Err(e) = step {
+                result.fail(match e {
+                    ControllerError::Cancelled => "Cancelled before the end".to_string(),
+                    e => format!("Stopped by an error: {}", e),
+                });
+            }
+            if result.stopped {
+                break;
+            }
+        }
+        result.rf_on = started.elapsed();
+        if let Some((count, worst)) = result.deviations {
+            result.fail(format!(
+                "Forward power off the setpoint by up to {:.2} dB in {} sample(s)",
+                worst, count
+            ));
+        }
+        if let Err(e) = guard.release() {
+            result.fail(format!("RF disable failed: {}", e));
+        }
+        Ok(result)
+    }
+
+    /// Records the sample's extremes and checks it against the limits.
+    fn check(&self, sample: &TelemetrySample, result: &mut SoakResult) {
+        result.samples += 1;
+        let max = |current: Option<f32>, value: f32| Some(current.map_or(value, |c| c.max(value)));
+        result.max_reflected_dbm = max(result.max_reflected_dbm, sample.reflected_dbm);
+        if let Some(temperature_c) = sample.temperature_c {
+            result.max_temperature_c = max(result.max_temperature_c, temperature_c);
+            if let Some(limit) = self
+                .max_temperature_c
+                .filter(|limit| temperature_c > *limit)
+            {
+                result.fail(format!(
+                    "PA temperature {:.1} °C above the {} °C limit",
+                    temperature_c, limit
+                ));
+            }
+        }
+        if let Some(limit) = self.max_reflected_dbm.filter(|l| sample.reflected_dbm > *l) {
+            result.fail(format!(
+                "Reflected power {:.2} dBm above the {} dBm limit",
+                sample.reflected_dbm, limit
+            ));
+        }
+        let deviation = (sample.forward_dbm - sample.power_setpoint_dbm).abs();
+        if self
+            .tolerance_db
+            .is_some_and(|tolerance| deviation > tolerance)
+        {
+            let (count, worst) = result.deviations.unwrap_or_default();
+            result.deviations = Some((count + 1, worst.max(deviation)));
+        }
+    }
+}
+
+/// What happened during a [`SoakTest`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SoakResult {
+    pub rf_on: Duration,
+    pub samples: usize,
+    pub status_checks: usize,
+    pub max_temperature_c: Option<f32>,
+    pub max_reflected_dbm: Option<f32>,
+    /// Largest power reduction applied by the derating rules.
+    pub max_derating_db: f32,
+    /// Samples off the setpoint by more than the tolerance, and the worst.
+    pub deviations: Option<(usize, f32)>,
+    /// Derating steps and status word changes.
+    pub events: Vec<(SystemTime, String)>,
+    /// Why the unit failed; empty for a pass.
+    pub failures: Vec<String>,
+    stopped: bool,
+}
+
+impl SoakResult {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+
+    /// `PASS`, or `FAIL: ` and the reasons.
+    pub fn verdict(&self) -> String {
+        match self.passed() {
+            true => "PASS".to_string(),
+            false => format!("FAIL: {}", self.failures.join("; ")),
+        }
+    }
+
+    fn event(&mut self, text: String) {
+        self.events.push((SystemTime::now(), text));
+    }
+
+    /// Records a failure that ends the test.
+    fn fail(&mut self, reason: String) {
+        self.event(reason.clone());
+        self.failures.push(reason);
+        self.stopped = true;
+    }
+}
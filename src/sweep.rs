@@ -0,0 +1,679 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::leveling::Leveling;
+use crate::progress::{Progress, ProgressCallback};
+use crate::protocol::FREQUENCY_RANGE_MHZ;
+
+/// Host-side frequency sweep.
+///
+/// Unlike the firmware `$SWPD` sweep, every point is set and measured from the
+/// host, which lets the sweep be cancelled between points.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub mode: SweepMode,
+    pub power_dbm: f32,
+    /// Time to wait after each frequency change before measuring.
+    pub dwell: Duration,
+    /// Wait for readings to settle instead of using the fixed dwell.
+    pub settling: Option<Settling>,
+    /// Adjust the setpoint per frequency to deliver constant power. Overrides `power_dbm`.
+    pub leveling: Option<Leveling>,
+}
+
+/// Settling criterion: consecutive `$PPG` readings must agree within
+/// `tolerance_db`, or `max_time` elapses, before a point is recorded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settling {
+    pub tolerance_db: f32,
+    pub max_time: Duration,
+    /// Delay between consecutive readings.
+    pub interval: Duration,
+}
+
+/// How the sweep frequencies are chosen.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SweepMode {
+    /// Fixed steps from `start_mhz` to `stop_mhz`.
+    Linear(SweepSegment),
+    /// `points` frequencies spaced evenly on a logarithmic scale.
+    Logarithmic {
+        start_mhz: f32,
+        stop_mhz: f32,
+        points: usize,
+    },
+    /// Explicit frequencies, visited in the given order.
+    List(Vec<f32>),
+    /// Consecutive linear segments, each with its own step size. A frequency
+    /// shared by the end of one segment and the start of the next is only
+    /// visited once.
+    Segmented(Vec<SweepSegment>),
+}
+
+/// Linear range of frequencies, start and stop inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepSegment {
+    pub start_mhz: f32,
+    pub stop_mhz: f32,
+    pub step_mhz: f32,
+}
+
+impl SweepSegment {
+    pub fn frequencies(&self) -> Vec<f32> {
+        if self.step_mhz <= 0.0 || self.stop_mhz < self.start_mhz {
+            return vec![self.start_mhz];
+        }
+        let count = ((self.stop_mhz - self.start_mhz) / self.step_mhz + 1e-3).floor() as usize + 1;
+        (0..count)
+            .map(|i| self.start_mhz + i as f32 * self.step_mhz)
+            .collect()
+    }
+}
+
+impl SweepMode {
+    pub fn frequencies(&self) -> Vec<f32> {
+        match self {
+            SweepMode::Linear(segment) => segment.frequencies(),
+            SweepMode::Logarithmic {
+                start_mhz,
+                stop_mhz,
+                points,
+            } => {
+                if *points < 2 || *start_mhz <= 0.0 || *stop_mhz <= 0.0 {
+                    return vec![*start_mhz];
+                }
+                let ratio = (stop_mhz / start_mhz).ln() / (*points - 1) as f32;
+                (0..*points)
+                    .map(|i| start_mhz * (ratio * i as f32).exp())
+                    .collect()
+            }
+            SweepMode::List(frequencies) => frequencies.clone(),
+            SweepMode::Segmented(segments) => {
+                let mut frequencies: Vec<f32> = Vec::new();
+                for segment in segments {
+                    for frequency in segment.frequencies() {
+                        let duplicate = frequencies
+                            .last()
+                            .is_some_and(|last| (frequency - last).abs() < 1e-3);
+                        if !duplicate {
+                            frequencies.push(frequency);
+                        }
+                    }
+                }
+                frequencies
+            }
+        }
+    }
+}
+
+/// One measured sweep point. Powers are as reported by `$PPG`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepPoint {
+    pub frequency_mhz: f32,
+    pub forward_dbm: f32,
+    pub reflected_dbm: f32,
+}
+
+impl SweepPoint {
+    /// S11 in dB (reflected minus forward power).
+    pub fn s11_db(&self) -> f32 {
+        self.reflected_dbm - self.forward_dbm
+    }
+}
+
+/// Point with the lowest S11, i.e. the best matched frequency.
+pub fn best_match(points: &[SweepPoint]) -> Option<&SweepPoint> {
+    points
+        .iter()
+        .min_by(|a, b| a.s11_db().total_cmp(&b.s11_db()))
+}
+
+/// CSV with a `frequency_mhz,forward_dbm,reflected_dbm,s11_db` header.
+pub fn points_to_csv(points: &[SweepPoint]) -> String {
+    let mut csv = String::from("frequency_mhz,forward_dbm,reflected_dbm,s11_db\n");
+    for point in points {
+        csv.push_str(&format!(
+            "{:.3},{:.2},{:.2},{:.2}\n",
+            point.frequency_mhz,
+            point.forward_dbm,
+            point.reflected_dbm,
+            point.s11_db()
+        ));
+    }
+    csv
+}
+
+/// Parses a file written by [`points_to_csv`]. The header, `#` comments and
+/// the derived `s11_db` column are skipped.
+pub fn points_from_csv(csv: &str) -> Result<Vec<SweepPoint>, String> {
+    let mut points = Vec::new();
+    for (number, line) in csv.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("frequency") {
+            continue;
+        }
+        let fields: Vec<f32> = line
+            .split(',')
+            .map(|field| field.trim().parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("Invalid sweep point on line {}: {}", number + 1, line))?;
+        match fields.as_slice() {
+            [frequency_mhz, forward_dbm, reflected_dbm, ..] => points.push(SweepPoint {
+                frequency_mhz: *frequency_mhz,
+                forward_dbm: *forward_dbm,
+                reflected_dbm: *reflected_dbm,
+            }),
+            _ => {
+                return Err(format!(
+                    "Invalid sweep point on line {}: {}",
+                    number + 1,
+                    line
+                ))
+            }
+        }
+    }
+    Ok(points)
+}
+
+/// Reads a sweep saved with `mwctl sweep --output`.
+pub fn load_points(path: impl AsRef<Path>) -> io::Result<Vec<SweepPoint>> {
+    let csv = fs::read_to_string(path)?;
+    points_from_csv(&csv).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Parses a frequency list for a [`SweepMode::List`] sweep: either JSON,
+/// a bare array of MHz values or an object with `frequencies_mhz`, or CSV
+/// with the frequency in MHz in the first column. Further CSV columns such
+/// as a channel name are ignored, as are a header line and `#` comments.
+pub fn frequencies_from_str(text: &str) -> Result<Vec<f32>, String> {
+    let trimmed = text.trim_start();
+    let frequencies = if trimmed.starts_with('[') || trimmed.starts_with('{') {
+        let json = JsonValue::parse(text)?;
+        let list = match json.as_array() {
+            Some(list) => list,
+            None => json.array("frequencies_mhz")?,
+        };
+        list.iter()
+            .map(|f| {
+                f.as_f64()
+                    .map(|f| f as f32)
+                    .ok_or(format!("Non-numeric frequency: {}", f))
+            })
+            .collect::<Result<Vec<f32>, String>>()?
+    } else {
+        let mut frequencies = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let field = line.split(',').next().unwrap_or_default().trim();
+            match field.parse::<f32>() {
+                Ok(mhz) => frequencies.push(mhz),
+                Err(_) if frequencies.is_empty() && number == 0 => {}
+                Err(_) => {
+                    return Err(format!(
+                        "Invalid frequency on line {}: {}",
+                        number + 1,
+                        line
+                    ))
+                }
+            }
+        }
+        frequencies
+    };
+    if frequencies.is_empty() {
+        return Err("The list has no frequencies".to_string());
+    }
+    if let Some(outside) = frequencies
+        .iter()
+        .find(|mhz| !FREQUENCY_RANGE_MHZ.contains(mhz))
+    {
+        return Err(format!(
+            "{} MHz is outside {}..={} MHz",
+            outside,
+            FREQUENCY_RANGE_MHZ.start(),
+            FREQUENCY_RANGE_MHZ.end()
+        ));
+    }
+    Ok(frequencies)
+}
+
+/// Reads a frequency list file, see [`frequencies_from_str`].
+pub fn load_frequencies(path: impl AsRef<Path>) -> io::Result<Vec<f32>> {
+    let text = fs::read_to_string(path)?;
+    frequencies_from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Touchstone (`.s1p`) file of the points for RF tools such as ADS or
+/// scikit-rf. Forward and reflected power carry no phase, so S11 is written
+/// as dB magnitude with a zero angle.
+pub fn points_to_touchstone(points: &[SweepPoint]) -> String {
+    let mut s1p = String::from(
+        "! Host-side reflection sweep, S11 from forward and reflected power\n\
+         ! Phase not measured, written as 0\n\
+         # MHZ S DB R 50\n",
+    );
+    for point in points {
+        s1p.push_str(&format!(
+            "{:.6} {:.3} 0\n",
+            point.frequency_mhz,
+            point.s11_db()
+        ));
+    }
+    s1p
+}
+
+impl Sweep {
+    /// Linear sweep from `start_mhz` to `stop_mhz` in `step_mhz` steps.
+    pub fn linear(
+        start_mhz: f32,
+        stop_mhz: f32,
+        step_mhz: f32,
+        power_dbm: f32,
+        dwell: Duration,
+    ) -> Sweep {
+        Sweep {
+            mode: SweepMode::Linear(SweepSegment {
+                start_mhz,
+                stop_mhz,
+                step_mhz,
+            }),
+            power_dbm,
+            dwell,
+            settling: None,
+            leveling: None,
+        }
+    }
+
+    /// Visits `frequencies_mhz` in the given order.
+    pub fn list(frequencies_mhz: Vec<f32>, power_dbm: f32, dwell: Duration) -> Sweep {
+        Sweep {
+            mode: SweepMode::List(frequencies_mhz),
+            power_dbm,
+            dwell,
+            settling: None,
+            leveling: None,
+        }
+    }
+
+    pub fn with_leveling(mut self, leveling: Leveling) -> Sweep {
+        self.leveling = Some(leveling);
+        self
+    }
+
+    pub fn with_settling(mut self, settling: Settling) -> Sweep {
+        self.settling = Some(settling);
+        self
+    }
+
+    /// Frequencies visited by the sweep, in order.
+    pub fn frequencies(&self) -> Vec<f32> {
+        self.mode.frequencies()
+    }
+
+    /// Runs the sweep with RF enabled, disabling RF again when it finishes,
+    /// fails or is cancelled. A paused sweep resumes at the point it had reached.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<SweepPoint>, ControllerError> {
+        self.run_with_progress(controller, cancel, &mut |_| {})
+    }
+
+    /// Same as [`Sweep::run`], reporting progress after every measured point.
+    pub fn run_with_progress(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<Vec<SweepPoint>, ControllerError> {
+        let result = self.run_points(controller, cancel, on_progress);
+        let safe = controller.safe_state();
+        let points = result?;
+        safe?;
+        Ok(points)
+    }
+
+    fn run_points(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<Vec<SweepPoint>, ControllerError> {
+        let frequencies = self.frequencies();
+        let total = frequencies.len();
+        let mut points = Vec::with_capacity(total);
+        let start_time = Instant::now();
+
+        controller.send(&Command::SetPower(self.power_dbm))?;
+        if let Some(first) = frequencies.first() {
+            controller.send(&Command::SetFrequency(*first))?;
+        }
+        controller.send(&Command::RfEnable)?;
+
+        for frequency_mhz in frequencies {
+            if cancel.is_cancelled() {
+                return Err(ControllerError::Cancelled);
+            }
+            cancel.checkpoint(controller, &[Command::RfEnable])?;
+            controller.send(&Command::SetFrequency(frequency_mhz))?;
+            let point = match &self.leveling {
+                Some(leveling) => leveling.level(
+                    controller,
+                    cancel,
+                    frequency_mhz,
+                    self.dwell,
+                    self.settling.as_ref(),
+                )?,
+                None => settle_and_measure(
+                    controller,
+                    cancel,
+                    frequency_mhz,
+                    self.dwell,
+                    self.settling.as_ref(),
+                )?,
+            };
+            points.push(point);
+            on_progress(&Progress {
+                completed: points.len(),
+                total,
+                elapsed: start_time.elapsed(),
+                frequency_mhz: Some(frequency_mhz),
+                power_dbm: Some(self.power_dbm),
+            });
+        }
+
+        Ok(points)
+    }
+}
+
+/// Reflection before and after [`auto_match`] moved the frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchResult {
+    /// Measured at the frequency the generator was on beforehand.
+    pub before: SweepPoint,
+    /// Measured again at the chosen frequency after the sweep.
+    pub after: SweepPoint,
+    pub points: Vec<SweepPoint>,
+}
+
+/// One-shot matching: measures reflection at the current frequency, runs
+/// `sweep`, sets the best matched frequency and measures it again.
+///
+/// Every measurement is taken at the sweep's (low) power. RF is off
+/// afterwards and the previous power setpoint is restored; the new
+/// frequency stays set.
+pub fn auto_match(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    sweep: &Sweep,
+) -> Result<MatchResult, ControllerError> {
+    let frequency_mhz = parse_value(&controller.send(&Command::GetFrequency)?)?;
+    let setpoint_dbm = parse_value(&controller.send(&Command::GetPowerSetpoint)?)?;
+
+    let result = match_frequency(controller, cancel, sweep, frequency_mhz);
+    let safe = controller.safe_state();
+    let restored = controller.send(&Command::SetPower(setpoint_dbm));
+    let result = result?;
+    safe?;
+    restored?;
+    Ok(result)
+}
+
+fn match_frequency(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    sweep: &Sweep,
+    frequency_mhz: f32,
+) -> Result<MatchResult, ControllerError> {
+    let before = measure_once(controller, cancel, sweep, frequency_mhz)?;
+    let points = sweep.run(controller, cancel)?;
+    let best = best_match(&points)
+        .ok_or_else(|| ControllerError::InvalidResponse("The sweep measured no points".into()))?
+        .frequency_mhz;
+    let after = measure_once(controller, cancel, sweep, best)?;
+    Ok(MatchResult {
+        before,
+        after,
+        points,
+    })
+}
+
+/// Sets `frequency_mhz` at the sweep power, enables RF for one settled
+/// reading and disables it again.
+fn measure_once(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    sweep: &Sweep,
+    frequency_mhz: f32,
+) -> Result<SweepPoint, ControllerError> {
+    controller.send(&Command::SetPower(sweep.power_dbm))?;
+    controller.send(&Command::SetFrequency(frequency_mhz))?;
+    controller.send(&Command::RfEnable)?;
+    let point = settle_and_measure(
+        controller,
+        cancel,
+        frequency_mhz,
+        sweep.dwell,
+        sweep.settling.as_ref(),
+    );
+    controller.send(&Command::RfDisable)?;
+    point
+}
+
+/// Waits for the point to settle, either for the fixed `dwell` or until the
+/// `settling` criterion is met, then returns the last reading.
+pub fn settle_and_measure(
+    controller: &Controller,
+    cancel: &CancellationToken,
+    frequency_mhz: f32,
+    dwell: Duration,
+    settling: Option<&Settling>,
+) -> Result<SweepPoint, ControllerError> {
+    let settling = match settling {
+        Some(settling) => settling,
+        None => {
+            if !cancel.sleep(dwell) {
+                return Err(ControllerError::Cancelled);
+            }
+            return measure_point(controller, frequency_mhz);
+        }
+    };
+
+    let start_time = Instant::now();
+    let mut previous = measure_point(controller, frequency_mhz)?;
+    loop {
+        if !cancel.sleep(settling.interval) {
+            return Err(ControllerError::Cancelled);
+        }
+        let current = measure_point(controller, frequency_mhz)?;
+        let settled = (current.forward_dbm - previous.forward_dbm).abs() <= settling.tolerance_db
+            && (current.reflected_dbm - previous.reflected_dbm).abs() <= settling.tolerance_db;
+        if settled || start_time.elapsed() >= settling.max_time {
+            return Ok(current);
+        }
+        previous = current;
+    }
+}
+
+/// Reads forward and reflected power at the current frequency.
+pub fn measure_point(
+    controller: &Controller,
+    frequency_mhz: f32,
+) -> Result<SweepPoint, ControllerError> {
+    let values = controller.query(&Command::GetPaPower)?;
+    match values.as_slice() {
+        [forward_dbm, reflected_dbm, ..] => Ok(SweepPoint {
+            frequency_mhz,
+            forward_dbm: *forward_dbm,
+            reflected_dbm: *reflected_dbm,
+        }),
+        _ => Err(ControllerError::InvalidResponse(format!(
+            "Expected forward and reflected power, got {:?}",
+            values
+        ))),
+    }
+}
+
+impl ToJson for SweepSegment {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("start_mhz", self.start_mhz)
+            .with("stop_mhz", self.stop_mhz)
+            .with("step_mhz", self.step_mhz)
+    }
+}
+
+impl FromJson for SweepSegment {
+    fn from_json(json: &JsonValue) -> Result<SweepSegment, String> {
+        Ok(SweepSegment {
+            start_mhz: json.f32("start_mhz")?,
+            stop_mhz: json.f32("stop_mhz")?,
+            step_mhz: json.f32("step_mhz")?,
+        })
+    }
+}
+
+impl ToJson for SweepMode {
+    fn to_json(&self) -> JsonValue {
+        match self {
+            SweepMode::Linear(segment) => JsonValue::object()
+                .with("mode", "linear")
+                .with("start_mhz", segment.start_mhz)
+                .with("stop_mhz", segment.stop_mhz)
+                .with("step_mhz", segment.step_mhz),
+            SweepMode::Logarithmic {
+                start_mhz,
+                stop_mhz,
+                points,
+            } => JsonValue::object()
+                .with("mode", "logarithmic")
+                .with("start_mhz", *start_mhz)
+                .with("stop_mhz", *stop_mhz)
+                .with("points", *points),
+            SweepMode::List(frequencies) => JsonValue::object()
+                .with("mode", "list")
+                .with("frequencies_mhz", frequencies.clone()),
+            SweepMode::Segmented(segments) => JsonValue::object().with("mode", "segmented").with(
+                "segments",
+                JsonValue::Array(segments.iter().map(ToJson::to_json).collect()),
+            ),
+        }
+    }
+}
+
+impl FromJson for SweepMode {
+    fn from_json(json: &JsonValue) -> Result<SweepMode, String> {
+        match json.string("mode")? {
+            "linear" => Ok(SweepMode::Linear(SweepSegment::from_json(json)?)),
+            "logarithmic" => Ok(SweepMode::Logarithmic {
+                start_mhz: json.f32("start_mhz")?,
+                stop_mhz: json.f32("stop_mhz")?,
+                points: json.number("points")? as usize,
+            }),
+            "list" => Ok(SweepMode::List(
+                json.array("frequencies_mhz")?
+                    .iter()
+                    .map(|f| f.as_f64().map(|f| f as f32).ok_or("Non-numeric frequency"))
+                    .collect::<Result<_, _>>()?,
+            )),
+            "segmented" => Ok(SweepMode::Segmented(
+                json.array("segments")?
+                    .iter()
+                    .map(SweepSegment::from_json)
+                    .collect::<Result<_, _>>()?,
+            )),
+            other => Err(format!("Unknown sweep mode: {}", other)),
+        }
+    }
+}
+
+impl ToJson for Settling {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("tolerance_db", self.tolerance_db)
+            .with("max_time_s", self.max_time)
+            .with("interval_s", self.interval)
+    }
+}
+
+impl FromJson for Settling {
+    fn from_json(json: &JsonValue) -> Result<Settling, String> {
+        Ok(Settling {
+            tolerance_db: json.f32("tolerance_db")?,
+            max_time: json.duration("max_time_s")?,
+            interval: json.duration("interval_s")?,
+        })
+    }
+}
+
+impl ToJson for Sweep {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("frequencies", self.mode.to_json())
+            .with("power_dbm", self.power_dbm)
+            .with("dwell_s", self.dwell)
+            .with(
+                "settling",
+                self.settling
+                    .as_ref()
+                    .map(ToJson::to_json)
+                    .unwrap_or(JsonValue::Null),
+            )
+            .with(
+                "leveling",
+                self.leveling
+                    .as_ref()
+                    .map(ToJson::to_json)
+                    .unwrap_or(JsonValue::Null),
+            )
+    }
+}
+
+impl FromJson for Sweep {
+    fn from_json(json: &JsonValue) -> Result<Sweep, String> {
+        let optional = |key: &str| match json.get(key) {
+            None | Some(JsonValue::Null) => None,
+            Some(value) => Some(value),
+        };
+        Ok(Sweep {
+            mode: SweepMode::from_json(
+                json.get("frequencies")
+                    .ok_or("Missing field \"frequencies\"")?,
+            )?,
+            power_dbm: json.f32("power_dbm")?,
+            dwell: json.duration("dwell_s")?,
+            settling: optional("settling").map(Settling::from_json).transpose()?,
+            leveling: optional("leveling").map(Leveling::from_json).transpose()?,
+        })
+    }
+}
+
+impl ToJson for SweepPoint {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("frequency_mhz", self.frequency_mhz)
+            .with("forward_dbm", self.forward_dbm)
+            .with("reflected_dbm", self.reflected_dbm)
+    }
+}
+
+impl FromJson for SweepPoint {
+    fn from_json(json: &JsonValue) -> Result<SweepPoint, String> {
+        Ok(SweepPoint {
+            frequency_mhz: json.f32("frequency_mhz")?,
+            forward_dbm: json.f32("forward_dbm")?,
+            reflected_dbm: json.f32("reflected_dbm")?,
+        })
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+use crate::progress::{Progress, ProgressCallback};
+use crate::sweep::{settle_and_measure, Settling, SweepMode, SweepPoint};
+
+/// Frequency × power sweep. Every frequency is swept at each power level in turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrequencyPowerSweep {
+    pub frequencies: SweepMode,
+    pub powers_dbm: Vec<f32>,
+    /// Time to wait after each frequency change before measuring.
+    pub dwell: Duration,
+    /// Wait for readings to settle instead of using the fixed dwell.
+    pub settling: Option<Settling>,
+}
+
+/// Result of a [`FrequencyPowerSweep`]; `points[p][f]` was measured at
+/// `powers_dbm[p]` and `frequencies_mhz[f]`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SweepMatrix {
+    pub frequencies_mhz: Vec<f32>,
+    pub powers_dbm: Vec<f32>,
+    pub points: Vec<Vec<SweepPoint>>,
+}
+
+impl SweepMatrix {
+    /// One row per measured point with the power setpoint it was taken at.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("power_dbm,frequency_mhz,forward_dbm,reflected_dbm,s11_db\n");
+        for (power_dbm, row) in self.powers_dbm.iter().zip(&self.points) {
+            for point in row {
+                csv.push_str(&format!(
+                    "{:.2},{:.2},{:.2},{:.2},{:.2}\n",
+                    power_dbm,
+                    point.frequency_mhz,
+                    point.forward_dbm,
+                    point.reflected_dbm,
+                    point.s11_db()
+                ));
+            }
+        }
+        csv
+    }
+
+    pub fn write_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.to_csv())
+    }
+}
+
+impl FrequencyPowerSweep {
+    /// Runs the sweep with RF enabled, disabling RF again when it finishes,
+    /// fails or is cancelled.
+    pub fn run(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+    ) -> Result<SweepMatrix, ControllerError> {
+        self.run_with_progress(controller, cancel, &mut |_| {})
+    }
+
+    /// Same as [`FrequencyPowerSweep::run`], reporting progress after every measured point.
+    pub fn run_with_progress(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<SweepMatrix, ControllerError> {
+        let result = self.run_matrix(controller, cancel, on_progress);
+        let safe = controller.safe_state();
+        let matrix = result?;
+        safe?;
+        Ok(matrix)
+    }
+
+    fn run_matrix(
+        &self,
+        controller: &Controller,
+        cancel: &CancellationToken,
+        on_progress: ProgressCallback,
+    ) -> Result<SweepMatrix, ControllerError> {
+        let frequencies = self.frequencies.frequencies();
+        let total = frequencies.len() * self.powers_dbm.len();
+        let start_time = Instant::now();
+        let mut completed = 0;
+
+        let mut matrix = SweepMatrix {
+            frequencies_mhz: frequencies.clone(),
+            powers_dbm: self.powers_dbm.clone(),
+            points: Vec::with_capacity(self.powers_dbm.len()),
+        };
+
+        for (i, power_dbm) in self.powers_dbm.iter().copied().enumerate() {
+            controller.send(&Command::SetPower(power_dbm))?;
+            if i == 0 {
+                if let Some(first) = frequencies.first() {
+                    controller.send(&Command::SetFrequency(*first))?;
+                }
+                controller.send(&Command::RfEnable)?;
+            }
+
+            let mut row = Vec::with_capacity(frequencies.len());
+            for frequency_mhz in frequencies.iter().copied() {
+                if cancel.is_cancelled() {
+                    return Err(ControllerError::Cancelled);
+                }
+                cancel.checkpoint(controller, &[Command::RfEnable])?;
+                controller.send(&Command::SetFrequency(frequency_mhz))?;
+                row.push(settle_and_measure(
+                    controller,
+                    cancel,
+                    frequency_mhz,
+                    self.dwell,
+                    self.settling.as_ref(),
+                )?);
+
+                completed += 1;
+                on_progress(&Progress {
+                    completed,
+                    total,
+                    elapsed: start_time.elapsed(),
+                    frequency_mhz: Some(frequency_mhz),
+                    power_dbm: Some(power_dbm),
+                });
+            }
+            matrix.points.push(row);
+        }
+
+        Ok(matrix)
+    }
+}
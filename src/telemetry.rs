@@ -0,0 +1,327 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use crate::cancel::CancellationToken;
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::controller_responses::parse_value;
+use crate::error::ControllerError;
+use crate::json::{FromJson, JsonValue, ToJson};
+use crate::priority::Priority;
+use crate::sweep::measure_point;
+use crate::units::{dbm_to_watts, format_timestamp, parse_timestamp};
+
+/// One periodic reading of the generator's output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetrySample {
+    pub timestamp: SystemTime,
+    pub frequency_mhz: f32,
+    pub power_setpoint_dbm: f32,
+    pub forward_dbm: f32,
+    pub reflected_dbm: f32,
+    /// PA temperature, if the firmware answers `$PTG`.
+    pub temperature_c: Option<f32>,
+    pub rf_enabled: bool,
+    /// PA supply rail, if the firmware answers `$PVG` and `$PCG`. A sagging
+    /// voltage is the first sign of a failing supply.
+    pub supply_voltage_v: Option<f32>,
+    pub supply_current_a: Option<f32>,
+}
+
+/// Callback receiving every telemetry sample.
+pub type TelemetryListener = Arc<dyn Fn(&TelemetrySample) + Send + Sync>;
+
+pub const CSV_HEADER: &str =
+    "timestamp,frequency_mhz,power_setpoint_dbm,forward_dbm,reflected_dbm,\
+temperature_c,rf_enabled,supply_voltage_v,supply_current_a";
+
+impl TelemetrySample {
+    /// Reads a full sample from the controller.
+    pub fn read(controller: &Controller) -> Result<TelemetrySample, ControllerError> {
+        let frequency_mhz = parse_value(&controller.send(&Command::GetFrequency)?)?;
+        let power_setpoint_dbm = parse_value(&controller.send(&Command::GetPowerSetpoint)?)?;
+        let point = measure_point(controller, frequency_mhz)?;
+        let optional = |command: Command| {
+            let response = controller.send_optional(&command).ok()??;
+            parse_value(&response).ok()
+        };
+        let temperature_c = optional(Command::GetPaTemperature);
+        let supply_voltage_v = optional(Command::GetSupplyVoltage);
+        let supply_current_a = optional(Command::GetSupplyCurrent);
+
+        Ok(TelemetrySample {
+            timestamp: SystemTime::now(),
+            frequency_mhz,
+            power_setpoint_dbm,
+            forward_dbm: point.forward_dbm,
+            reflected_dbm: point.reflected_dbm,
+            temperature_c,
+            rf_enabled: controller.rf_enabled(),
+            supply_voltage_v,
+            supply_current_a,
+        })
+    }
+
+    /// Power delivered to the load (forward minus reflected) in watts; zero with RF off.
+    pub fn delivered_watts(&self) -> f64 {
+        if !self.rf_enabled {
+            return 0.0;
+        }
+        (dbm_to_watts(self.forward_dbm) - dbm_to_watts(self.reflected_dbm)).max(0.0)
+    }
+
+    /// DC power drawn from the PA supply rail in watts, if both the voltage
+    /// and the current were read.
+    pub fn dc_input_watts(&self) -> Option<f64> {
+        Some(f64::from(self.supply_voltage_v?) * f64::from(self.supply_current_a?))
+    }
+
+    /// PA efficiency in percent, forward power over DC input; `None` with RF
+    /// off or without supply readings. It includes the idle draw, so it is
+    /// only comparable between samples at the same power.
+    pub fn efficiency_percent(&self) -> Option<f64> {
+        let dc_input = self.dc_input_watts().filter(|watts| *watts > 0.0)?;
+        self.rf_enabled
+            .then(|| 100.0 * dbm_to_watts(self.forward_dbm) / dc_input)
+    }
+
+    /// Parses a row written by [`TelemetrySample::to_csv_row`], or by
+    /// versions without the supply columns.
+    pub fn from_csv_row(row: &str) -> Result<TelemetrySample, String> {
+        let invalid = || format!("Invalid telemetry row: {}", row);
+        let fields: Vec<&str> = row.trim().split(',').collect();
+        if fields.len() != 7 && fields.len() != 9 {
+            return Err(invalid());
+        }
+        let number = |index: usize| fields[index].parse::<f32>().map_err(|_| invalid());
+        let optional = |index: usize| match fields.get(index) {
+            None | Some(&"") => Ok(None),
+            Some(_) => number(index).map(Some),
+        };
+
+        Ok(TelemetrySample {
+            timestamp: parse_timestamp(fields[0])?,
+            frequency_mhz: number(1)?,
+            power_setpoint_dbm: number(2)?,
+            forward_dbm: number(3)?,
+            reflected_dbm: number(4)?,
+            temperature_c: optional(5)?,
+            rf_enabled: fields[6] == "1",
+            supply_voltage_v: optional(7)?,
+            supply_current_a: optional(8)?,
+        })
+    }
+
+    /// CSV row matching [`CSV_HEADER`]. Missing readings are left empty.
+    pub fn to_csv_row(&self) -> String {
+        let optional = |value: Option<f32>, precision: usize| {
+            value
+                .map(|value| format!("{:.p$}", value, p = precision))
+                .unwrap_or_default()
+        };
+        format!(
+            "{},{:.2},{:.2},{:.2},{:.2},{},{},{},{}",
+            format_timestamp(self.timestamp),
+            self.frequency_mhz,
+            self.power_setpoint_dbm,
+            self.forward_dbm,
+            self.reflected_dbm,
+            optional(self.temperature_c, 1),
+            self.rf_enabled as u8,
+            optional(self.supply_voltage_v, 2),
+            optional(self.supply_current_a, 2)
+        )
+    }
+}
+
+impl ToJson for TelemetrySample {
+    fn to_json(&self) -> JsonValue {
+        JsonValue::object()
+            .with("timestamp", format_timestamp(self.timestamp))
+            .with("frequency_mhz", self.frequency_mhz)
+            .with("power_setpoint_dbm", self.power_setpoint_dbm)
+            .with("forward_dbm", self.forward_dbm)
+            .with("reflected_dbm", self.reflected_dbm)
+            .with("temperature_c", self.temperature_c)
+            .with("rf_enabled", self.rf_enabled)
+            .with("supply_voltage_v", self.supply_voltage_v)
+            .with("supply_current_a", self.supply_current_a)
+    }
+}
+
+impl FromJson for TelemetrySample {
+    fn from_json(json: &JsonValue) -> Result<TelemetrySample, String> {
+        Ok(TelemetrySample {
+            timestamp: parse_timestamp(json.string("timestamp")?)?,
+            frequency_mhz: json.f32("frequency_mhz")?,
+            power_setpoint_dbm: json.f32("power_setpoint_dbm")?,
+            forward_dbm: json.f32("forward_dbm")?,
+            reflected_dbm: json.f32("reflected_dbm")?,
+            temperature_c: json.optional_f32("temperature_c")?,
+            rf_enabled: json.boolean("rf_enabled")?,
+            supply_voltage_v: json.optional_f32("supply_voltage_v")?,
+            supply_current_a: json.optional_f32("supply_current_a")?,
+        })
+    }
+}
+
+/// Listener appending every sample as a CSV row to `writer`.
+///
+/// Use a [`RotatingWriter`](crate::rotation::RotatingWriter) opened with
+/// [`CSV_HEADER`] as its header to keep week-long logs bounded.
+pub fn csv_logger(writer: impl Write + Send + 'static) -> TelemetryListener {
+    let writer = Mutex::new(writer);
+    Arc::new(move |sample: &TelemetrySample| {
+        if let Ok(mut writer) = writer.lock() {
+            let written = writeln!(writer, "{}", sample.to_csv_row()).and_then(|_| writer.flush());
+            if let Err(e) = written {
+                eprintln!("Failed to write telemetry: {:?}", e);
+            }
+        }
+    })
+}
+
+/// Running total of the energy delivered to the load.
+///
+/// Delivered power is integrated with the trapezoidal rule between
+/// consecutive readings. Clones share the total, so a clone subscribed to a
+/// [`TelemetryPoller`] can be read from another thread.
+#[derive(Clone, Default)]
+pub struct EnergyMeter {
+    state: Arc<Mutex<EnergyState>>,
+}
+
+#[derive(Default)]
+struct EnergyState {
+    last: Option<(SystemTime, f64)>,
+    joules: f64,
+}
+
+impl EnergyMeter {
+    pub fn new() -> EnergyMeter {
+        EnergyMeter::default()
+    }
+
+    /// Adds a reading of `watts` delivered at `timestamp`.
+    pub fn add(&self, timestamp: SystemTime, watts: f64) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some((last_time, last_watts)) = state.last {
+                let elapsed = timestamp
+                    .duration_since(last_time)
+                    .unwrap_or_default()
+                    .as_secs_f64();
+                state.joules += (last_watts + watts) / 2.0 * elapsed;
+            }
+            state.last = Some((timestamp, watts));
+        }
+    }
+
+    pub fn add_sample(&self, sample: &TelemetrySample) {
+        self.add(sample.timestamp, sample.delivered_watts());
+    }
+
+    /// Forgets the last reading, so the gap until the next one (e.g. a pause
+    /// with RF off) is not integrated.
+    pub fn interrupt(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.last = None;
+        }
+    }
+
+    pub fn reset(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            *state = EnergyState::default();
+        }
+    }
+
+    pub fn joules(&self) -> f64 {
+        self.state.lock().map(|state| state.joules).unwrap_or(0.0)
+    }
+
+    pub fn kwh(&self) -> f64 {
+        self.joules() / 3.6e6
+    }
+
+    /// Listener adding every sample to this meter.
+    pub fn listener(&self) -> TelemetryListener {
+        let meter = self.clone();
+        Arc::new(move |sample: &TelemetrySample| meter.add_sample(sample))
+    }
+}
+
+/// Polling interval of a [`TelemetryPoller`], shared with the polling
+/// thread so it can be changed while the poller runs. A change takes effect
+/// after the current wait.
+#[derive(Clone)]
+pub struct PollInterval(Arc<Mutex<Duration>>);
+
+impl PollInterval {
+    pub fn get(&self) -> Duration {
+        self.0
+            .lock()
+            .map_or(Duration::from_secs(1), |interval| *interval)
+    }
+
+    pub fn set(&self, interval: Duration) {
+        if let Ok(mut current) = self.0.lock() {
+            *current = interval;
+        }
+    }
+}
+
+/// Polls the controller at a fixed interval and hands each sample to its listeners.
+pub struct TelemetryPoller {
+    controller: Controller,
+    interval: PollInterval,
+    listeners: Vec<TelemetryListener>,
+}
+
+impl TelemetryPoller {
+    pub fn new(controller: &Controller, interval: Duration) -> TelemetryPoller {
+        TelemetryPoller {
+            controller: controller.with_priority(Priority::Background),
+            interval: PollInterval(Arc::new(Mutex::new(interval))),
+            listeners: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self, listener: TelemetryListener) {
+        self.listeners.push(listener);
+    }
+
+    /// Handle to change the interval after [`spawn`](TelemetryPoller::spawn).
+    pub fn interval(&self) -> PollInterval {
+        self.interval.clone()
+    }
+
+    /// Starts polling on a background thread until `cancel` is cancelled.
+    /// Failed reads are reported on stderr and polling continues.
+    pub fn spawn(self, cancel: CancellationToken) -> JoinHandle<()> {
+        thread::spawn(move || loop {
+            match TelemetrySample::read(&self.controller) {
+                Ok(sample) => {
+                    for listener in &self.listeners {
+                        listener(&sample);
+                    }
+                }
+                Err(e) => eprintln!("Telemetry read failed: {}", e),
+            }
+            if !cancel.sleep(self.interval.get()) {
+                return;
+            }
+        })
+    }
+}
+
+/// Listener recording every sample as a `TELEMETRY` line in the controller's
+/// protocol trace, so session logs can be replayed with telemetry.
+pub fn trace_logger(controller: &Controller) -> TelemetryListener {
+    let controller = controller.clone();
+    Arc::new(move |sample: &TelemetrySample| {
+        controller.trace("TELEMETRY", &sample.to_csv_row());
+    })
+}
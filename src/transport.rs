@@ -0,0 +1,302 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use crate::error::ControllerError;
+use crate::framing::LineFramer;
+
+/// Byte link between a [`Controller`](crate::Controller) and the board.
+///
+/// The controller only writes command lines and reads reply bytes, so any
+/// `Read + Write` link can carry the protocol. Reads must return within the
+/// transport's timeout, either with data or with `ErrorKind::TimedOut`, so the
+/// controller can enforce its reply deadline.
+pub trait Transport: Read + Write + Send {
+    /// Name of the link shown in logs, e.g. the serial port path.
+    fn name(&self) -> Option<String> {
+        None
+    }
+
+    /// Longest a single read blocks waiting for data.
+    fn timeout(&self) -> Duration;
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()>;
+
+    /// Removes and returns the bytes already received without waiting for
+    /// more, e.g. the tail of a reply that arrived after its command timed
+    /// out. The controller calls this before writing each command.
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        Ok(Vec::new())
+    }
+}
+
+/// Opens a fresh link to the same board, see
+/// [`Controller::set_reopen`](crate::Controller::set_reopen).
+pub type Reopener = Arc<dyn Fn() -> Result<Box<dyn Transport>, ControllerError> + Send + Sync>;
+
+/// Stands in for a port whose handle was closed so it can be opened again;
+/// the operating system may refuse a second handle while the first is open.
+pub(crate) struct ClosedTransport;
+
+impl Read for ClosedTransport {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Write for ClosedTransport {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Transport for ClosedTransport {
+    fn timeout(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+#[cfg(feature = "serial")]
+impl Transport for Box<dyn serialport::SerialPort> {
+    fn name(&self) -> Option<String> {
+        serialport::SerialPort::name(self.as_ref())
+    }
+
+    fn timeout(&self) -> Duration {
+        serialport::SerialPort::timeout(self.as_ref())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        serialport::SerialPort::set_timeout(self.as_mut(), timeout).map_err(io::Error::from)
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        let pending = serialport::SerialPort::bytes_to_read(self.as_ref())?;
+        let mut stale = vec![0; pending as usize];
+        self.read_exact(&mut stale)?;
+        Ok(stale)
+    }
+}
+
+/// Raw TCP link, e.g. to a serial-to-Ethernet bridge in front of the board.
+pub struct TcpTransport {
+    stream: TcpStream,
+    name: String,
+}
+
+impl TcpTransport {
+    pub fn connect<A: ToSocketAddrs>(
+        addr: A,
+        timeout: Duration,
+    ) -> Result<TcpTransport, ControllerError> {
+        let connection_error = |e: io::Error| ControllerError::Connection(format!("{:?}", e));
+        let addr = addr
+            .to_socket_addrs()
+            .map_err(connection_error)?
+            .next()
+            .ok_or_else(|| ControllerError::Connection("No address to connect to".to_string()))?;
+        let stream = TcpStream::connect_timeout(&addr, timeout).map_err(connection_error)?;
+        stream.set_nodelay(true).map_err(connection_error)?;
+        let mut transport = TcpTransport {
+            stream,
+            name: addr.to_string(),
+        };
+        transport.set_timeout(timeout).map_err(connection_error)?;
+        Ok(transport)
+    }
+}
+
+impl Read for TcpTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.stream.read(buf) {
+            // Socket timeouts surface as WouldBlock on Unix; report them the way serialport does.
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                Err(io::Error::new(io::ErrorKind::TimedOut, e))
+            }
+            Ok(0) if !buf.is_empty() => Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Connection closed by peer",
+            )),
+            result => result,
+        }
+    }
+}
+
+impl Write for TcpTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+impl Transport for TcpTransport {
+    fn name(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn timeout(&self) -> Duration {
+        self.stream
+            .read_timeout()
+            .ok()
+            .flatten()
+            .unwrap_or(Duration::ZERO)
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))?;
+        self.stream.set_write_timeout(Some(timeout))
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        self.stream.set_nonblocking(true)?;
+        let mut stale = Vec::new();
+        let mut buffer = [0; 256];
+        let result = loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => break Ok(()),
+                Ok(count) => stale.extend_from_slice(&buffer[..count]),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => break Ok(()),
+                Err(e) => break Err(e),
+            }
+        };
+        self.stream.set_nonblocking(false)?;
+        result.map(|_| stale)
+    }
+}
+
+/// Scripted transport for exercising controller code without a board.
+///
+/// Replies are chosen per command line: queued replies are returned first in
+/// order, then the first [`respond`](MockTransport::respond) rule whose prefix
+/// matches. Commands with no reply time out. Clones share their state, so a
+/// clone kept by the caller can inspect what the controller wrote.
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    state: Arc<Mutex<MockState>>,
+}
+
+#[derive(Default)]
+struct MockState {
+    rules: Vec<(String, String)>,
+    queued: VecDeque<String>,
+    written: Vec<String>,
+    framer: LineFramer,
+    rx: VecDeque<u8>,
+}
+
+impl MockTransport {
+    pub fn new() -> MockTransport {
+        MockTransport::default()
+    }
+
+    /// Answers every command starting with `prefix` with `reply`.
+    pub fn respond(self, prefix: &str, reply: &str) -> MockTransport {
+        if let Ok(mut state) = self.state.lock() {
+            state.rules.push((prefix.to_string(), reply.to_string()));
+        }
+        self
+    }
+
+    /// Answers the next unanswered command with `reply`, ahead of any rule.
+    pub fn queue(&self, reply: &str) {
+        if let Ok(mut state) = self.state.lock() {
+            state.queued.push_back(reply.to_string());
+        }
+    }
+
+    /// Command lines written so far, without terminators.
+    pub fn written(&self) -> Vec<String> {
+        self.state
+            .lock()
+            .map(|state| state.written.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Read for MockTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("Mock transport poisoned"))?;
+        if state.rx.is_empty() {
+            drop(state);
+            std::thread::sleep(Duration::from_millis(1));
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        let count = buf.len().min(state.rx.len());
+        for (slot, byte) in buf.iter_mut().zip(state.rx.drain(..count)) {
+            *slot = byte;
+        }
+        Ok(count)
+    }
+}
+
+impl Write for MockTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("Mock transport poisoned"))?;
+        for line in state.framer.push(buf) {
+            let reply = state.queued.pop_front().or_else(|| {
+                state
+                    .rules
+                    .iter()
+                    .find(|(prefix, _)| line.starts_with(prefix.as_str()))
+                    .map(|(_, reply)| reply.clone())
+            });
+            if let Some(reply) = reply {
+                state.rx.extend(reply.bytes());
+                state.rx.extend(b"\r\n");
+            }
+            state.written.push(line);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for MockTransport {
+    fn name(&self) -> Option<String> {
+        Some("mock".to_string())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(1)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn discard_input(&mut self) -> io::Result<Vec<u8>> {
+        let mut state = self
+            .state
+            .lock()
+            .map_err(|_| io::Error::other("Mock transport poisoned"))?;
+        Ok(state.rx.drain(..).collect())
+    }
+}
@@ -0,0 +1,169 @@
+//! Typed wrapper around [`Controller`] whose RF-on operations only exist
+//! once the generator has been configured and armed, so skipping a step is
+//! a compile error rather than a refused command:
+//!
+//! ```no_run
+//! # use microwave_controller::{typestate::ArmedController, Controller, ControllerError};
+//! # fn run(controller: Controller) -> Result<(), ControllerError> {
+//! let mut on = ArmedController::new(controller)
+//!     .configure(2450.0, 30.0)?
+//!     .arm()?
+//!     .rf_on()?;
+//! on.set_power(35.0)?;
+//! let configured = on.rf_off()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Dropping an [`ArmedController<RfOn>`] disables RF.
+
+use crate::controller::Controller;
+use crate::controller_commands::Command;
+use crate::error::ControllerError;
+
+/// Nothing has been set through this wrapper yet.
+pub struct Idle;
+
+/// Frequency and power are set.
+pub struct Configured {
+    frequency_mhz: f32,
+    power_dbm: f32,
+}
+
+/// Configured, and every interlock was closed when it was armed.
+pub struct Armed(Configured);
+
+/// RF is enabled.
+pub struct RfOn {
+    setpoints: Configured,
+    guard: RfGuard,
+}
+
+/// Disables RF when dropped, unless [`rf_off`](ArmedController::rf_off)
+/// already did.
+struct RfGuard {
+    controller: Controller,
+    enabled: bool,
+}
+
+impl Drop for RfGuard {
+    fn drop(&mut self) {
+        if self.enabled {
+            if let Err(e) = self.controller.safe_state() {
+                eprintln!("Failed to disable RF: {}", e);
+            }
+        }
+    }
+}
+
+/// Handle in stage `S`: [`Idle`], [`Configured`], [`Armed`] or [`RfOn`].
+pub struct ArmedController<S> {
+    controller: Controller,
+    state: S,
+}
+
+impl<S> ArmedController<S> {
+    /// The underlying handle, for queries.
+    pub fn controller(&self) -> &Controller {
+        &self.controller
+    }
+}
+
+impl ArmedController<Idle> {
+    pub fn new(controller: Controller) -> ArmedController<Idle> {
+        ArmedController {
+            controller,
+            state: Idle,
+        }
+    }
+
+    /// Validates and sends both setpoints.
+    pub fn configure(
+        self,
+        frequency_mhz: f32,
+        power_dbm: f32,
+    ) -> Result<ArmedController<Configured>, ControllerError> {
+        self.controller
+            .send(&Command::set_frequency(frequency_mhz)?)?;
+        self.controller.send(&Command::set_power(power_dbm)?)?;
+        Ok(ArmedController {
+            controller: self.controller,
+            state: Configured {
+                frequency_mhz,
+                power_dbm,
+            },
+        })
+    }
+}
+
+impl ArmedController<Configured> {
+    pub fn frequency_mhz(&self) -> f32 {
+        self.state.frequency_mhz
+    }
+
+    pub fn power_dbm(&self) -> f32 {
+        self.state.power_dbm
+    }
+
+    /// Checks the interlocks; fails with [`ControllerError::InterlockOpen`]
+    /// if one is open.
+    pub fn arm(self) -> Result<ArmedController<Armed>, ControllerError> {
+        if let Some(name) = self.controller.open_interlock()? {
+            return Err(ControllerError::InterlockOpen(name));
+        }
+        Ok(ArmedController {
+            controller: self.controller,
+            state: Armed(self.state),
+        })
+    }
+}
+
+impl ArmedController<Armed> {
+    /// Enables RF. The interlocks are checked again by [`Controller::send`].
+    pub fn rf_on(self) -> Result<ArmedController<RfOn>, ControllerError> {
+        self.controller.send(&Command::RfEnable)?;
+        Ok(ArmedController {
+            state: RfOn {
+                setpoints: self.state.0,
+                guard: RfGuard {
+                    controller: self.controller.clone(),
+                    enabled: true,
+                },
+            },
+            controller: self.controller,
+        })
+    }
+}
+
+impl ArmedController<RfOn> {
+    pub fn frequency_mhz(&self) -> f32 {
+        self.state.setpoints.frequency_mhz
+    }
+
+    pub fn power_dbm(&self) -> f32 {
+        self.state.setpoints.power_dbm
+    }
+
+    pub fn set_frequency(&mut self, frequency_mhz: f32) -> Result<(), ControllerError> {
+        self.controller
+            .send(&Command::set_frequency(frequency_mhz)?)?;
+        self.state.setpoints.frequency_mhz = frequency_mhz;
+        Ok(())
+    }
+
+    pub fn set_power(&mut self, power_dbm: f32) -> Result<(), ControllerError> {
+        self.controller.send(&Command::set_power(power_dbm)?)?;
+        self.state.setpoints.power_dbm = power_dbm;
+        Ok(())
+    }
+
+    /// Disables RF, keeping the setpoints. Arm again to re-enable.
+    pub fn rf_off(mut self) -> Result<ArmedController<Configured>, ControllerError> {
+        self.controller.send(&Command::RfDisable)?;
+        self.state.guard.enabled = false;
+        Ok(ArmedController {
+            controller: self.controller,
+            state: self.state.setpoints,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses durations such as `500ms`, `2s`, `1.5s`, `10m` or `1h`. A bare number is seconds.
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid duration: {}", text))?;
+    let seconds = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" | "min" => value * 60.0,
+        "h" => value * 3600.0,
+        _ => return Err(format!("Invalid duration unit: {}", text)),
+    };
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// Converts a power level in dBm to watts.
+pub fn dbm_to_watts(dbm: f32) -> f64 {
+    10f64.powf((dbm as f64 - 30.0) / 10.0)
+}
+
+/// Converts a power in watts to dBm. Zero watts is negative infinity.
+pub fn watts_to_dbm(watts: f64) -> f32 {
+    (10.0 * watts.log10() + 30.0) as f32
+}
+
+/// Parses a power level such as `30dBm`, `1W`, `500mW` or `1.2kW` into
+/// dBm. A bare number is dBm; units are case-insensitive.
+pub fn parse_power(text: &str) -> Result<f32, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+')))
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let value: f64 = number
+        .parse()
+        .map_err(|_| format!("Invalid power: {}", text))?;
+    let watts = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "dbm" => return Ok(value as f32),
+        "mw" => value / 1000.0,
+        "w" => value,
+        "kw" => value * 1000.0,
+        _ => return Err(format!("Invalid power unit: {}", text)),
+    };
+    if watts <= 0.0 {
+        return Err(format!("Power must be positive: {}", text));
+    }
+    Ok(watts_to_dbm(watts))
+}
+
+/// Formats a timestamp as ISO 8601 UTC with millisecond precision,
+/// e.g. `2025-01-22T14:03:07.125Z`.
+pub fn format_timestamp(timestamp: SystemTime) -> String {
+    let since_epoch = timestamp.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86_400) as i64);
+    let time_of_day = seconds % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day % 3600 / 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Parses timestamps written by [`format_timestamp`]. The fraction and the
+/// trailing `Z` are optional.
+pub fn parse_timestamp(text: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("Invalid timestamp: {}", text);
+    let text = text.trim().trim_end_matches('Z');
+    let (date, time) = text.split_once('T').ok_or_else(invalid)?;
+
+    let date: Vec<i64> = date
+        .split('-')
+        .map(|field| field.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (time, fraction) = match time.split_once('.') {
+        Some((time, fraction)) => (time, fraction),
+        None => (time, "0"),
+    };
+    let time: Vec<u64> = time
+        .split(':')
+        .map(|field| field.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    if date.len() != 3 || time.len() != 3 {
+        return Err(invalid());
+    }
+    let fraction: f64 = format!("0.{}", fraction).parse().map_err(|_| invalid())?;
+
+    let days = days_from_civil(date[0], date[1] as u32, date[2] as u32);
+    if days < 0 {
+        return Err(invalid());
+    }
+    let seconds = days as u64 * 86_400 + time[0] * 3600 + time[1] * 60 + time[2];
+    Ok(UNIX_EPOCH + Duration::from_secs(seconds) + Duration::from_secs_f64(fraction))
+}
+
+// Howard Hinnant's civil calendar algorithms.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let month = month as i64;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
@@ -0,0 +1,371 @@
+//! Offline checks of a set of configuration files — device profile,
+//! calibration table, access policy and recipes — against the protocol's
+//! limits and against each other, so a typo is reported before a run
+//! rather than by the board halfway through one.
+
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::access::AccessPolicy;
+use crate::calibration::CalibrationTable;
+use crate::controller_commands::Command;
+use crate::profile::DeviceProfile;
+use crate::protocol::{FREQUENCY_RANGE_MHZ, POWER_RANGE_DBM};
+use crate::recipe::{Recipe, RecipeStep};
+use crate::sweep::Sweep;
+
+/// Calibration offsets beyond this are more likely a unit mix-up than a
+/// real gain error.
+const PLAUSIBLE_OFFSET_DB: f32 = 10.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// The run would fail or misbehave.
+    Error,
+    /// Allowed, but probably not what was meant.
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    pub level: Level,
+    /// File the finding is about.
+    pub file: String,
+    pub message: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        write!(f, "{}: {}: {}", level, self.file, self.message)
+    }
+}
+
+/// Files checked together, each with the path it was loaded from.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSet {
+    pub profile: Option<(String, DeviceProfile)>,
+    pub calibration: Option<(String, CalibrationTable)>,
+    pub access: Option<(String, AccessPolicy)>,
+    pub recipes: Vec<(String, Recipe)>,
+}
+
+impl ConfigSet {
+    /// Every problem found, errors first.
+    pub fn validate(&self) -> Vec<Finding> {
+        let mut findings = Findings::default();
+        if let Some((file, profile)) = &self.profile {
+            findings.file = file.clone();
+            check_profile(&mut findings, profile);
+        }
+        if let Some((file, table)) = &self.calibration {
+            findings.file = file.clone();
+            check_calibration(&mut findings, "Calibration table", table);
+        }
+        if let Some((file, access)) = &self.access {
+            findings.file = file.clone();
+            check_access(&mut findings, access);
+        }
+        for (file, recipe) in &self.recipes {
+            findings.file = file.clone();
+            self.check_recipe(&mut findings, recipe);
+        }
+        let mut findings = findings.findings;
+        findings.sort_by_key(|finding| finding.level);
+        findings
+    }
+
+    fn check_recipe(&self, findings: &mut Findings, recipe: &Recipe) {
+        if recipe.steps.is_empty() {
+            findings.warning("The recipe has no steps".to_string());
+        }
+        // A frequency set by the profile on connect counts as configured.
+        let mut frequency_set = self.profile.as_ref().is_some_and(|(_, profile)| {
+            profile
+                .init_commands
+                .iter()
+                .any(|command| matches!(command, Command::SetFrequency(_)))
+        });
+        let operator_limit = self
+            .access
+            .as_ref()
+            .and_then(|(_, access)| access.operator_power_limit_dbm);
+        let mut frequencies = Vec::new();
+        let mut powers = Vec::new();
+
+        for (index, step) in recipe.steps.iter().enumerate() {
+            let at = format!("Step {}", index + 1);
+            match step {
+                RecipeStep::SetFrequency(mhz) => {
+                    findings.command(&at, &Command::SetFrequency(*mhz));
+                    frequencies.push(*mhz);
+                    frequency_set = true;
+                }
+                RecipeStep::SetPower(dbm) => {
+                    findings.command(&at, &Command::SetPower(*dbm));
+                    powers.push(*dbm);
+                }
+                RecipeStep::RfOn | RecipeStep::DeliverEnergy { .. } if !frequency_set => {
+                    findings.error(format!(
+                        "{}: RF is enabled before any frequency is set, which the board refuses",
+                        at
+                    ));
+                }
+                RecipeStep::RfOn | RecipeStep::RfOff | RecipeStep::Hold(_) => {}
+                RecipeStep::DeliverEnergy {
+                    energy_j,
+                    max_duration,
+                } => {
+                    if *energy_j <= 0.0 || !energy_j.is_finite() {
+                        findings.error(format!("{}: energy_j must be greater than zero", at));
+                    }
+                    if max_duration.is_none() {
+                        findings.warning(format!(
+                            "{}: no max_duration_s, so a load that absorbs nothing never ends the step",
+                            at
+                        ));
+                    }
+                }
+                RecipeStep::Ramp(ramp) => {
+                    findings.command(&format!("{} start", at), &Command::SetPower(ramp.start_dbm));
+                    findings.command(&format!("{} stop", at), &Command::SetPower(ramp.stop_dbm));
+                    if ramp.step_dbm <= 0.0 || !ramp.step_dbm.is_finite() {
+                        findings.error(format!("{}: the ramp step must be greater than zero", at));
+                    }
+                    powers.extend([ramp.start_dbm, ramp.stop_dbm]);
+                }
+                RecipeStep::Sweep(sweep) => {
+                    check_sweep(findings, &at, sweep);
+                    frequencies.extend(sweep.frequencies());
+                    powers.push(sweep.power_dbm);
+                    frequency_set = true;
+                }
+            }
+        }
+
+        if let Some(retune) = &recipe.retune {
+            if retune.interval.is_zero() {
+                findings.error("Retune: interval_s must be greater than zero".to_string());
+            }
+            if retune.step_mhz <= 0.0 || retune.step_mhz > retune.span_mhz {
+                findings.error(format!(
+                    "Retune: step_mhz {} must be above zero and at most span_mhz {}",
+                    retune.step_mhz, retune.span_mhz
+                ));
+            }
+            findings.command("Retune", &Command::SetPower(retune.power_dbm));
+        }
+
+        if let Some(limit) = operator_limit {
+            if let Some(max) = powers.iter().copied().reduce(f32::max) {
+                if max > limit {
+                    findings.warning(format!(
+                        "Reaches {} dBm, above the access policy's operator limit of {} dBm; only engineers may run it through the daemon",
+                        max, limit
+                    ));
+                }
+            }
+        }
+        if let Some((calibration_file, table)) = &self.calibration {
+            let covered = match (table.points().first(), table.points().last()) {
+                (Some(first), Some(last)) => first.0..=last.0,
+                _ => return,
+            };
+            if let Some(outside) = frequencies.iter().find(|mhz| !covered.contains(mhz)) {
+                findings.warning(format!(
+                    "Uses {} MHz, outside the {}..={} MHz covered by {}; the nearest offset is used there",
+                    outside,
+                    covered.start(),
+                    covered.end(),
+                    calibration_file
+                ));
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Findings {
+    file: String,
+    findings: Vec<Finding>,
+}
+
+impl Findings {
+    fn error(&mut self, message: String) {
+        self.push(Level::Error, message);
+    }
+
+    fn warning(&mut self, message: String) {
+        self.push(Level::Warning, message);
+    }
+
+    fn push(&mut self, level: Level, message: String) {
+        self.findings.push(Finding {
+            level,
+            file: self.file.clone(),
+            message,
+        });
+    }
+
+    /// Reports `command` if the board would reject its parameters.
+    fn command(&mut self, at: &str, command: &Command) {
+        if let Err(e) = command.validate() {
+            self.error(format!("{} ({}): {}", at, command, e));
+        }
+    }
+}
+
+fn check_profile(findings: &mut Findings, profile: &DeviceProfile) {
+    for (index, command) in profile.init_commands.iter().enumerate() {
+        let at = format!("Init command {}", index + 1);
+        findings.command(&at, command);
+        if matches!(command, Command::RfEnable) {
+            findings.warning(format!("{}: enables RF on every connection", at));
+        }
+    }
+    let mut names = HashSet::new();
+    for preset in &profile.dll_presets {
+        let at = format!("DLL preset {}", preset.name);
+        if !names.insert(preset.name.as_str()) {
+            findings.error(format!("{}: the name is used twice", at));
+        }
+        let [lower, upper, start, step, threshold, delay] = preset.parameters;
+        findings.command(&at, &preset.command());
+        for (name, mhz) in [("lower", lower), ("upper", upper), ("start", start)] {
+            if !FREQUENCY_RANGE_MHZ.contains(&mhz) {
+                findings.error(format!(
+                    "{}: the {} frequency {} MHz is outside {}..={} MHz",
+                    at,
+                    name,
+                    mhz,
+                    FREQUENCY_RANGE_MHZ.start(),
+                    FREQUENCY_RANGE_MHZ.end()
+                ));
+            }
+        }
+        if lower >= upper {
+            findings.error(format!(
+                "{}: the lower frequency {} MHz is not below the upper {} MHz",
+                at, lower, upper
+            ));
+        } else if !(lower..=upper).contains(&start) {
+            findings.error(format!(
+                "{}: the start frequency {} MHz is outside {}..={} MHz",
+                at, start, lower, upper
+            ));
+        }
+        if step <= 0.0 || step > upper - lower {
+            findings.error(format!(
+                "{}: the step {} MHz must be above zero and fit the band",
+                at, step
+            ));
+        }
+        if threshold <= 0.0 {
+            findings.warning(format!(
+                "{}: a threshold of {} dB return loss locks on anything",
+                at, threshold
+            ));
+        }
+        if delay < 0.0 {
+            findings.error(format!("{}: the delay {} ms is negative", at, delay));
+        }
+    }
+}
+
+fn check_calibration(findings: &mut Findings, name: &str, table: &CalibrationTable) {
+    if table.is_empty() {
+        findings.error(format!("{} has no entries", name));
+        return;
+    }
+    for pair in table.points().windows(2) {
+        if pair[0].0 == pair[1].0 {
+            findings.error(format!("{}: {} MHz is listed twice", name, pair[0].0));
+        }
+    }
+    for (mhz, offset) in table.points() {
+        if !FREQUENCY_RANGE_MHZ.contains(mhz) {
+            findings.warning(format!(
+                "{}: {} MHz is outside the board's {}..={} MHz",
+                name,
+                mhz,
+                FREQUENCY_RANGE_MHZ.start(),
+                FREQUENCY_RANGE_MHZ.end()
+            ));
+        }
+        if !offset.is_finite() || offset.abs() > PLAUSIBLE_OFFSET_DB {
+            findings.warning(format!(
+                "{}: the offset {} dB at {} MHz is implausible; offsets are in dB, not dBm",
+                name, offset, mhz
+            ));
+        }
+    }
+}
+
+fn check_access(findings: &mut Findings, access: &AccessPolicy) {
+    if let Some(limit) = access.operator_power_limit_dbm {
+        if !POWER_RANGE_DBM.contains(&limit) {
+            findings.error(format!(
+                "The operator power limit {} dBm is outside {}..={} dBm",
+                limit,
+                POWER_RANGE_DBM.start(),
+                POWER_RANGE_DBM.end()
+            ));
+        }
+    }
+    let mut clients = HashSet::new();
+    for (address, _) in &access.clients {
+        if !clients.insert(address) {
+            findings.error(format!("Client {} is listed twice", address));
+        }
+    }
+    let mut secrets = HashSet::new();
+    for token in &access.tokens {
+        if token.token.is_empty() {
+            findings.error(format!("Token {} has an empty secret", token.name));
+        } else if !secrets.insert(token.token.as_str()) {
+            findings.error(format!(
+                "Token {} reuses another token's secret, so its role is ambiguous",
+                token.name
+            ));
+        }
+    }
+}
+
+fn check_sweep(findings: &mut Findings, at: &str, sweep: &Sweep) {
+    let frequencies = sweep.frequencies();
+    if frequencies.is_empty() {
+        findings.error(format!("{}: the sweep visits no frequencies", at));
+    }
+    if let Some(outside) = frequencies
+        .iter()
+        .find(|mhz| !FREQUENCY_RANGE_MHZ.contains(mhz))
+    {
+        findings.command(&format!("{} sweep", at), &Command::SetFrequency(*outside));
+    }
+    findings.command(
+        &format!("{} sweep", at),
+        &Command::SetPower(sweep.power_dbm),
+    );
+    if let Some(leveling) = &sweep.leveling {
+        findings.command(
+            &format!("{} leveling", at),
+            &Command::SetPower(leveling.max_setpoint_dbm),
+        );
+        if leveling.target_dbm > leveling.max_setpoint_dbm {
+            findings.warning(format!(
+                "{}: the leveling target {} dBm is above its max setpoint {} dBm",
+                at, leveling.target_dbm, leveling.max_setpoint_dbm
+            ));
+        }
+        if !leveling.calibration.is_empty() {
+            check_calibration(
+                findings,
+                &format!("{} leveling calibration", at),
+                &leveling.calibration,
+            );
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Raw wasm exports for driving the board from a browser.
+//!
+//! The page owns the Web Serial port (see `web/webserial.js`); this module
+//! only turns commands into wire bytes and received bytes into parsed
+//! responses, so nothing here performs I/O. Data is exchanged through two
+//! buffers in linear memory: the caller writes into the input buffer returned
+//! by [`mw_input`], calls an operation, and reads the number of bytes it
+//! returns from [`mw_output`]. A negative return value means the output holds
+//! an error message of `-n` bytes instead.
+
+use std::cell::RefCell;
+
+use crate::controller_commands::Command;
+use crate::controller_responses::Response;
+use crate::framing::{encode_line, LineFramer};
+use crate::json::{self, JsonValue, ToJson};
+
+thread_local! {
+    static INPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static OUTPUT: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+    static FRAMER: RefCell<LineFramer> = RefCell::new(LineFramer::new());
+}
+
+/// Resizes the input buffer to `len` bytes and returns its address.
+#[no_mangle]
+pub extern "C" fn mw_input(len: usize) -> *mut u8 {
+    INPUT.with(|input| {
+        let mut input = input.borrow_mut();
+        input.resize(len, 0);
+        input.as_mut_ptr()
+    })
+}
+
+/// Address of the output of the last operation.
+#[no_mangle]
+pub extern "C" fn mw_output() -> *const u8 {
+    OUTPUT.with(|output| output.borrow().as_ptr())
+}
+
+/// Reads a JSON command from the input buffer and outputs its wire form,
+/// including the `\r\n` terminator.
+#[no_mangle]
+pub extern "C" fn mw_encode_command(len: usize) -> i32 {
+    complete(with_input(len, |input| {
+        let text = std::str::from_utf8(input).map_err(|e| e.to_string())?;
+        let command: Command = json::from_str(text)?;
+        Ok(encode_line(&command.to_string()))
+    }))
+}
+
+/// Feeds received bytes from the input buffer to the line framer and outputs
+/// a JSON array of the responses they completed.
+#[no_mangle]
+pub extern "C" fn mw_feed(len: usize) -> i32 {
+    complete(with_input(len, |input| {
+        let lines = FRAMER.with(|framer| framer.borrow_mut().push(input));
+        let responses: Vec<JsonValue> = lines
+            .iter()
+            .map(|line| Response::parse(line).to_json())
+            .collect();
+        Ok(JsonValue::Array(responses).to_string())
+    }))
+}
+
+/// Drops any partially received reply, e.g. after the port is reopened.
+#[no_mangle]
+pub extern "C" fn mw_reset() {
+    FRAMER.with(|framer| framer.borrow_mut().clear());
+}
+
+fn with_input(
+    len: usize,
+    operation: impl FnOnce(&[u8]) -> Result<String, String>,
+) -> Result<String, String> {
+    INPUT.with(|input| {
+        let input = input.borrow();
+        operation(&input[..len.min(input.len())])
+    })
+}
+
+fn complete(result: Result<String, String>) -> i32 {
+    let (text, sign) = match result {
+        Ok(text) => (text, 1),
+        Err(message) => (message, -1),
+    };
+    let len = text.len() as i32;
+    OUTPUT.with(|output| *output.borrow_mut() = text.into_bytes());
+    sign * len
+}
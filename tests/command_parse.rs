@@ -0,0 +1,185 @@
+//! Round trips between `Command`'s `Display` and `FromStr` implementations.
+//!
+//! Parameters are drawn from a fixed-seed generator covering arbitrary bit
+//! patterns (including NaN, infinities and subnormals) as well as values on
+//! the protocol's 0.01 grid, so failures reproduce exactly.
+
+use microwave_controller::{protocol::ProtocolError, Command};
+
+/// xorshift64*, enough to spread values over the parameter space.
+struct Values(u64);
+
+impl Values {
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// Any `f32`, half of the time from raw bits and otherwise on the 0.01 grid.
+    fn next_f32(&mut self) -> f32 {
+        let bits = self.next_u64();
+        if bits & 1 == 0 {
+            f32::from_bits((bits >> 32) as u32)
+        } else {
+            ((bits >> 32) as i32 % 1_000_000) as f32 / 100.0
+        }
+    }
+}
+
+const EDGE_VALUES: [f32; 10] = [
+    0.0,
+    -0.0,
+    0.005,
+    -0.005,
+    2450.0,
+    f32::MIN_POSITIVE,
+    f32::MAX,
+    f32::MIN,
+    f32::INFINITY,
+    f32::NAN,
+];
+
+fn commands(values: &mut Values) -> Vec<Command> {
+    vec![
+        Command::GetIdentity,
+        Command::GetVersion,
+        Command::GetStatus { verbose: false },
+        Command::GetStatus { verbose: true },
+        Command::ClearErrors,
+        Command::GetFrequency,
+        Command::SetFrequency(values.next_f32()),
+        Command::GetPaPower,
+        Command::GetPowerSetpoint,
+        Command::SetPower(values.next_f32()),
+        Command::ConfigureDll {
+            param1: values.next_f32(),
+            param2: values.next_f32(),
+            param3: values.next_f32(),
+            param4: values.next_f32(),
+            param5: values.next_f32(),
+            param6: values.next_f32(),
+        },
+        Command::DllEnable,
+        Command::DllDisable,
+        Command::RfEnable,
+        Command::RfDisable,
+        Command::SweepDbm {
+            start: values.next_f32(),
+            stop: values.next_f32(),
+            step: values.next_f32(),
+            power_dbm: values.next_f32(),
+        },
+        Command::GetPaTemperature,
+        Command::GetSupplyVoltage,
+        Command::GetSupplyCurrent,
+        Command::GetFanSpeed,
+        Command::SetFanSpeed(values.next_f32()),
+        Command::LockLocal,
+        Command::UnlockLocal,
+        Command::GetLocalLockout,
+    ]
+}
+
+fn assert_round_trip(command: &Command) {
+    let line = command.to_string();
+    let parsed: Command = line.parse().unwrap_or_else(|e| panic!("{}: {}", line, e));
+    assert_eq!(parsed.name(), command.name(), "{}", line);
+    assert_eq!(parsed.to_string(), line);
+}
+
+#[test]
+fn every_command_round_trips() {
+    let mut values = Values(0x9e37_79b9_7f4a_7c15);
+    for _ in 0..2000 {
+        for command in commands(&mut values) {
+            assert_round_trip(&command);
+        }
+    }
+}
+
+#[test]
+fn edge_values_round_trip() {
+    for value in EDGE_VALUES {
+        assert_round_trip(&Command::SetFrequency(value));
+        assert_round_trip(&Command::SetPower(value));
+        assert_round_trip(&Command::SetFanSpeed(value));
+        assert_round_trip(&Command::SweepDbm {
+            start: value,
+            stop: value,
+            step: value,
+            power_dbm: value,
+        });
+    }
+}
+
+#[test]
+fn values_on_the_wire_grid_parse_exactly() {
+    for hundredths in (-100_000..100_000).step_by(7) {
+        let value = hundredths as f32 / 100.0;
+        let command = Command::SetPower(value);
+        assert_eq!(command.to_string().parse(), Ok(command));
+    }
+}
+
+#[test]
+fn dll_lines_are_told_apart_by_field_count() {
+    assert_eq!("$DLES,0,1".parse(), Ok(Command::DllEnable));
+    assert_eq!("$DLES,0,0".parse(), Ok(Command::DllDisable));
+    assert_eq!(
+        "$DLES,0,1,2,3,4,5,6".parse(),
+        Ok(Command::ConfigureDll {
+            param1: 1.0,
+            param2: 2.0,
+            param3: 3.0,
+            param4: 4.0,
+            param5: 5.0,
+            param6: 6.0,
+        })
+    );
+}
+
+#[test]
+fn terminator_and_surrounding_whitespace_are_ignored() {
+    assert_eq!(
+        " $FCS,0,2450.00\r\n".parse(),
+        Ok(Command::SetFrequency(2450.0))
+    );
+}
+
+#[test]
+fn sweep_power_is_sent_in_dbm() {
+    let command = Command::sweep_dbm(2400.0, 2500.0, 0.5, 40.0).unwrap();
+    assert_eq!(command.to_string(), "$SWPD,0,2400.00,2500.00,0.50,40.00,0");
+    assert_eq!(command.to_string().parse(), Ok(command));
+    assert!(Command::sweep_dbm(2400.0, 2500.0, 0.5, 250.0).is_err());
+}
+
+#[test]
+fn rejects_lines_that_are_not_commands() {
+    for line in [
+        "",
+        "FCG,0",
+        "$FCG",
+        "$FCG,1",
+        "$FCG,0,1",
+        "$FCS,0",
+        "$FCS,0,abc",
+        "$FCS,0,1,2",
+        "$ECS,0,2",
+        "$ST,0,0",
+        "$DLES,0,1,2",
+        "$SWPD,0,1,2,3,4,1",
+        "$SWPD,0,1,2,3,dBm,0",
+        "$XYZ,0",
+        "$DLES,0,1,2,3,4,5,6,7",
+    ] {
+        assert_eq!(
+            Command::parse(line).err(),
+            Some(ProtocolError::Malformed),
+            "{:?}",
+            line
+        );
+    }
+}
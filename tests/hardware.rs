@@ -0,0 +1,37 @@
+//! The acceptance sequence of `mwctl verify` against a real board on a
+//! dummy load, for qualifying firmware drops. Built only with the
+//! `hw-tests` feature; the board's port is taken from `MWCTL_PORT`, and
+//! `MWCTL_JUNIT` names a JUnit report to write:
+//!
+//! ```text
+//! MWCTL_PORT=/dev/serial/by-id/usb-FTDI_... cargo test --features hw-tests --test hardware
+//! ```
+#![cfg(feature = "hw-tests")]
+
+use std::env;
+
+use microwave_controller::{acceptance::Outcome, AcceptanceTest, CancellationToken, Controller};
+
+#[test]
+fn board_passes_the_acceptance_sequence() {
+    let port = env::var("MWCTL_PORT").expect("Set MWCTL_PORT to the board's serial port");
+    let controller = Controller::open(&port).expect("Failed to open the board");
+    let report = AcceptanceTest::new(vec![10.0, 20.0])
+        .run(&controller, &CancellationToken::new())
+        .expect("Acceptance sequence could not start");
+    if let Ok(path) = env::var("MWCTL_JUNIT") {
+        report
+            .save_junit(&path, "hardware")
+            .expect("Failed to write the JUnit report");
+    }
+    let problems: Vec<String> = report
+        .cases
+        .iter()
+        .filter_map(|case| match &case.outcome {
+            Outcome::Passed => None,
+            Outcome::Failed(reason) => Some(format!("{} failed: {}", case.name, reason)),
+            Outcome::Skipped(reason) => Some(format!("{} skipped: {}", case.name, reason)),
+        })
+        .collect();
+    assert!(report.passed(), "{}", problems.join("\n"));
+}
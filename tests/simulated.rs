@@ -0,0 +1,382 @@
+//! Controller behaviour against the built-in simulator: what is sent to the
+//! board while recipes pause and background control runs, and what the
+//! lifecycle, roles, single-writer arbitration and derating refuse or allow.
+
+use std::{
+    sync::{Arc, Barrier, Mutex},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use microwave_controller::{
+    device_config::DeviceConfig,
+    ramp::PowerRamp,
+    recipe::{Recipe, RecipeStep},
+    AccessPolicy, AlarmEngine, AlarmRule, AlertDispatcher, CancellationToken, Command, Controller,
+    ControllerError, ControllerEvent, Derater, DeratingPolicy, DeratingRule, Exchange, FanControl,
+    FanPolicy, Lifecycle, PauseMode, Role, Simulator, StatusFlags, TelemetrySample,
+};
+
+/// Every command line sent through `controller`, in order.
+fn sent(controller: &Controller) -> Arc<Mutex<Vec<String>>> {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let recorded = sent.clone();
+    controller
+        .observe_exchanges(Arc::new(move |exchange: &Exchange| {
+            recorded
+                .lock()
+                .unwrap()
+                .push(exchange.command.trim().to_string())
+        }))
+        .unwrap();
+    sent
+}
+
+/// A daemon's board with single-writer arbitration on, and the handle of a
+/// network client.
+fn single_writer() -> (Controller, Controller) {
+    let controller = Controller::from_transport(Simulator::new());
+    controller.set_single_writer(true).unwrap();
+    let client = controller.with_actor("10.0.0.5:4840");
+    (controller, client)
+}
+
+fn hot_sample(power_setpoint_dbm: f32) -> TelemetrySample {
+    TelemetrySample {
+        timestamp: SystemTime::now(),
+        frequency_mhz: 2450.0,
+        power_setpoint_dbm,
+        forward_dbm: power_setpoint_dbm,
+        reflected_dbm: 20.0,
+        temperature_c: Some(70.0),
+        rf_enabled: true,
+        supply_voltage_v: None,
+        supply_current_a: None,
+    }
+}
+
+fn power_setpoint(controller: &Controller) -> f32 {
+    controller.query(&Command::GetPowerSetpoint).unwrap()[0]
+}
+
+#[test]
+fn alarms_reduce_the_power_while_a_client_holds_control() {
+    let (controller, client) = single_writer();
+    client.send(&Command::SetPower(40.0)).unwrap();
+    let mut engine = AlarmEngine::new(&controller, AlertDispatcher::new());
+    engine.add_rule(AlarmRule::parse("hot_pa: temperature > 65 => reduce_power(3)").unwrap());
+
+    engine.evaluate(&hot_sample(40.0));
+    assert_eq!(power_setpoint(&controller), 37.0);
+    assert_eq!(
+        controller.control_owner().unwrap().as_deref(),
+        Some("10.0.0.5:4840")
+    );
+}
+
+#[test]
+fn derating_neither_needs_nor_takes_control() {
+    let (controller, client) = single_writer();
+    let policy = DeratingPolicy::default().with_tier(DeratingRule {
+        above_c: 60.0,
+        reduce_db: 3.0,
+    });
+    let derater = Derater::attach(&controller, policy).unwrap();
+    let derate = derater.listener();
+
+    derate(&hot_sample(40.0));
+    assert_eq!(power_setpoint(&controller), 37.0);
+    assert_eq!(controller.control_owner().unwrap(), None);
+
+    client.send(&Command::SetPower(45.0)).unwrap();
+    assert_eq!(
+        controller.control_owner().unwrap().as_deref(),
+        Some("10.0.0.5:4840")
+    );
+    derate(&hot_sample(45.0));
+    assert_eq!(power_setpoint(&controller), 42.0);
+    client.send(&Command::SetPower(44.0)).unwrap();
+}
+
+#[test]
+fn fan_control_follows_the_curve_while_a_client_holds_control() {
+    let (controller, client) = single_writer();
+    client.take_control().unwrap();
+    let policy = FanPolicy::new("40:30,60:60,75:100".parse().unwrap());
+    let fans = FanControl::new(&controller, policy);
+
+    fans.listener()(&hot_sample(40.0));
+    assert_eq!(fans.speed_percent(), Some(87.0));
+    assert_eq!(
+        controller.control_owner().unwrap().as_deref(),
+        Some("10.0.0.5:4840")
+    );
+}
+
+#[test]
+fn a_ramp_with_rf_off_stays_off_after_a_disable_rf_pause() {
+    let controller = Controller::from_transport(Simulator::new());
+    let sent = sent(&controller);
+    let recipe = Recipe::new("cold ramp").step(RecipeStep::Ramp(PowerRamp {
+        start_dbm: 10.0,
+        stop_dbm: 20.0,
+        step_dbm: 10.0,
+        dwell: Duration::from_millis(300),
+    }));
+    let cancel = CancellationToken::new();
+    let pauser = cancel.clone();
+    let pause = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(100));
+        pauser.pause(PauseMode::DisableRf);
+        thread::sleep(Duration::from_millis(400));
+        pauser.resume();
+    });
+    recipe.run(&controller, &cancel).unwrap();
+    pause.join().unwrap();
+
+    let sent = sent.lock().unwrap();
+    let second = sent
+        .iter()
+        .rposition(|line| line.starts_with("$PWRS,0,20"))
+        .unwrap();
+    assert_eq!(sent[second - 1], "$ECS,0,0", "{:?}", sent);
+    assert!(!sent.contains(&"$ECS,0,1".to_string()), "{:?}", sent);
+}
+
+#[test]
+fn of_two_clients_writing_at_once_only_one_takes_control() {
+    for _ in 0..50 {
+        let (controller, first) = single_writer();
+        let second = controller.with_actor("10.0.0.6:4840");
+        let barrier = Arc::new(Barrier::new(2));
+        let writers: Vec<_> = [first, second]
+            .into_iter()
+            .map(|client| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    client.send(&Command::SetPower(30.0)).is_ok()
+                })
+            })
+            .collect();
+        let accepted: Vec<bool> = writers
+            .into_iter()
+            .map(|writer| writer.join().unwrap())
+            .collect();
+        assert_eq!(
+            accepted.iter().filter(|ok| **ok).count(),
+            1,
+            "{:?}",
+            accepted
+        );
+    }
+}
+
+#[test]
+fn rf_enable_is_refused_until_a_frequency_is_set() {
+    let controller = Controller::from_transport(Simulator::new());
+    let sent = sent(&controller);
+    assert!(matches!(
+        controller.send(&Command::RfEnable),
+        Err(ControllerError::InvalidState(_))
+    ));
+    assert!(sent.lock().unwrap().is_empty());
+
+    controller.send(&Command::SetFrequency(2450.0)).unwrap();
+    assert_eq!(controller.lifecycle().unwrap(), Lifecycle::Configured);
+    controller.send(&Command::RfEnable).unwrap();
+    assert_eq!(controller.lifecycle().unwrap(), Lifecycle::RfOn);
+    let sweep = Command::SweepDbm {
+        start: 2400.0,
+        stop: 2500.0,
+        step: 10.0,
+        power_dbm: 10.0,
+    };
+    assert!(matches!(
+        controller.send(&sweep),
+        Err(ControllerError::InvalidState(_))
+    ));
+    controller.send(&Command::RfDisable).unwrap();
+    assert_eq!(controller.lifecycle().unwrap(), Lifecycle::Configured);
+}
+
+#[test]
+fn a_shutdown_refuses_changes_until_the_errors_are_cleared() {
+    let simulator = Simulator::new();
+    let controller = Controller::from_transport(simulator.clone());
+    controller.send(&Command::SetFrequency(2450.0)).unwrap();
+    simulator.update(|state| state.status = StatusFlags::SHUTDOWN_TEMPERATURE);
+    controller
+        .send(&Command::GetStatus { verbose: false })
+        .unwrap();
+    assert_eq!(controller.lifecycle().unwrap(), Lifecycle::Fault);
+
+    assert!(matches!(
+        controller.send(&Command::SetPower(10.0)),
+        Err(ControllerError::InvalidState(_))
+    ));
+    controller.send(&Command::RfDisable).unwrap();
+    controller.send(&Command::ClearErrors).unwrap();
+    assert_eq!(controller.lifecycle().unwrap(), Lifecycle::Connected);
+    // The frequency has to be set again before RF.
+    assert!(matches!(
+        controller.send(&Command::RfEnable),
+        Err(ControllerError::InvalidState(_))
+    ));
+    controller.send(&Command::SetPower(10.0)).unwrap();
+}
+
+#[test]
+fn roles_limit_what_a_client_may_change() {
+    let policy = AccessPolicy {
+        default_role: Role::Viewer,
+        clients: vec![
+            ("10.0.0.5".parse().unwrap(), Role::Operator),
+            ("10.0.0.6".parse().unwrap(), Role::Engineer),
+        ],
+        operator_power_limit_dbm: Some(40.0),
+        tokens: Vec::new(),
+    };
+    let viewer = policy.role_for("10.0.0.7".parse().unwrap());
+    let operator = policy.role_for("10.0.0.5".parse().unwrap());
+    let engineer = policy.role_for("10.0.0.6".parse().unwrap());
+    let sweep = |power_dbm| Command::SweepDbm {
+        start: 2400.0,
+        stop: 2500.0,
+        step: 10.0,
+        power_dbm,
+    };
+
+    policy.authorize(viewer, &Command::GetFrequency).unwrap();
+    assert!(policy.authorize(viewer, &Command::RfDisable).is_err());
+    policy
+        .authorize(operator, &Command::SetPower(40.0))
+        .unwrap();
+    policy.authorize(operator, &sweep(30.0)).unwrap();
+    for command in [Command::SetPower(40.5), sweep(45.0), Command::DllEnable] {
+        assert!(
+            matches!(
+                policy.authorize(operator, &command),
+                Err(ControllerError::NotAuthorized(_))
+            ),
+            "{}",
+            command
+        );
+        policy.authorize(engineer, &command).unwrap();
+    }
+}
+
+#[test]
+fn control_passes_on_take_and_release() {
+    let (controller, first) = single_writer();
+    let second = controller.with_actor("10.0.0.6:4840");
+    let changes = Arc::new(Mutex::new(Vec::new()));
+    let recorded = changes.clone();
+    controller
+        .subscribe(Arc::new(move |event: &ControllerEvent| {
+            if let ControllerEvent::ControlChanged { owner, .. } = event {
+                recorded.lock().unwrap().push(owner.clone());
+            }
+        }))
+        .unwrap();
+
+    first.send(&Command::SetPower(30.0)).unwrap();
+    assert!(matches!(
+        second.send(&Command::SetPower(20.0)),
+        Err(ControllerError::NotAuthorized(_))
+    ));
+    // Queries and RF off need no control.
+    second.send(&Command::GetPowerSetpoint).unwrap();
+    second.send(&Command::RfDisable).unwrap();
+
+    second.take_control().unwrap();
+    second.send(&Command::SetPower(20.0)).unwrap();
+    assert!(first.send(&Command::SetPower(30.0)).is_err());
+    // Releasing control someone else holds changes nothing.
+    first.release_control().unwrap();
+    assert_eq!(
+        controller.control_owner().unwrap().as_deref(),
+        Some("10.0.0.6:4840")
+    );
+    second.release_control().unwrap();
+    first.send(&Command::SetPower(30.0)).unwrap();
+
+    assert_eq!(power_setpoint(&controller), 30.0);
+    assert_eq!(
+        *changes.lock().unwrap(),
+        [
+            Some("10.0.0.5:4840".to_string()),
+            Some("10.0.0.6:4840".to_string()),
+            None,
+            Some("10.0.0.5:4840".to_string()),
+        ]
+    );
+}
+
+#[test]
+fn derating_applies_the_hottest_tier_and_lifts_it_below_the_hysteresis() {
+    let controller = Controller::from_transport(Simulator::new());
+    let policy = DeratingPolicy::default()
+        .with_tier(DeratingRule {
+            above_c: 60.0,
+            reduce_db: 3.0,
+        })
+        .with_tier(DeratingRule {
+            above_c: 70.0,
+            reduce_db: 6.0,
+        });
+    let derater = Derater::attach(&controller, policy).unwrap();
+    let derate = derater.listener();
+    let at = |temperature_c: f32| {
+        derate(&TelemetrySample {
+            temperature_c: Some(temperature_c),
+            ..hot_sample(power_setpoint(&controller))
+        })
+    };
+    controller.send(&Command::SetPower(40.0)).unwrap();
+
+    // The tiers do not add up.
+    at(72.0);
+    assert_eq!(power_setpoint(&controller), 34.0);
+    at(69.0);
+    assert_eq!(power_setpoint(&controller), 34.0);
+    at(67.0);
+    assert_eq!(power_setpoint(&controller), 37.0);
+    at(59.0);
+    assert_eq!(power_setpoint(&controller), 37.0);
+    at(57.0);
+    assert_eq!(power_setpoint(&controller), 40.0);
+    assert_eq!(derater.reduction_db(), 0.0);
+}
+
+#[test]
+fn derating_stops_at_the_lowest_setpoint() {
+    let controller = Controller::from_transport(Simulator::new());
+    let policy = DeratingPolicy::default().with_tier(DeratingRule {
+        above_c: 60.0,
+        reduce_db: 6.0,
+    });
+    let derater = Derater::attach(&controller, policy).unwrap();
+    controller.send(&Command::SetPower(2.0)).unwrap();
+
+    derater.listener()(&hot_sample(2.0));
+    assert_eq!(power_setpoint(&controller), 0.0);
+    assert_eq!(derater.reduction_db(), 6.0);
+}
+
+#[test]
+fn a_configuration_applies_only_to_the_same_model() {
+    let controller = Controller::from_transport(Simulator::new());
+    let mut config = DeviceConfig::read(&controller).unwrap();
+    assert_eq!(config.identity.as_deref(), Some("Simulator, ISC-SIM"));
+
+    // Another board of the same model, with a serial number.
+    config.identity = Some("Simulator, ISC-SIM, 0042".to_string());
+    config.apply(&controller).unwrap();
+
+    config.identity = Some("Simulator, ISC-SIM2".to_string());
+    assert!(matches!(
+        config.apply(&controller),
+        Err(ControllerError::UnexpectedDevice(_))
+    ));
+}
@@ -0,0 +1,133 @@
+//! Golden transcript conformance: every reply in `tests/transcripts/*.txt`
+//! must parse to its recorded value. See `tests/transcripts/README`.
+
+use std::{fs, path::Path};
+
+use microwave_controller::{
+    controller_responses::{check_reply, parse_status, parse_values, parse_verbose_status},
+    Command, ControllerError, DeviceFault,
+};
+
+/// Checks one `=` expectation against `reply`.
+fn check(reply: &str, expected: &str) -> Result<(), String> {
+    match expected {
+        "ok" => check_reply(reply).map_err(|e| e.to_string()),
+        "error" => match check_reply(reply) {
+            Err(ControllerError::Device(_)) => Ok(()),
+            other => Err(format!("expected a device error, got {:?}", other)),
+        },
+        "invalid" => match (parse_values(reply), parse_status(reply)) {
+            (
+                Err(ControllerError::InvalidResponse(_)),
+                Err(ControllerError::InvalidResponse(_)),
+            ) => Ok(()),
+            other => Err(format!("expected a malformed reply, got {:?}", other)),
+        },
+        _ => {
+            if let Some(name) = expected.strip_prefix("fault ") {
+                let expected: DeviceFault = name.parse()?;
+                let faults = match check_reply(reply) {
+                    Err(ControllerError::Device(error)) => error.faults(),
+                    _ => parse_verbose_status(reply).map_err(|e| e.to_string())?,
+                };
+                return match faults.contains(&expected) {
+                    true => Ok(()),
+                    false => Err(format!("expected fault {}, got {:?}", expected, faults)),
+                };
+            }
+            if let Some(code) = expected.strip_prefix("status ") {
+                let code = match code.strip_prefix("0x") {
+                    Some(hex) => u32::from_str_radix(hex, 16),
+                    None => code.parse(),
+                }
+                .map_err(|_| format!("bad status expectation: {}", code))?;
+                let status = parse_status(reply).map_err(|e| e.to_string())?;
+                return match status.0 == code {
+                    true => Ok(()),
+                    false => Err(format!("expected status 0x{:X}, got {}", code, status)),
+                };
+            }
+            let expected: Vec<f32> = expected
+                .split(',')
+                .map(|value| value.trim().parse())
+                .collect::<Result<_, _>>()
+                .map_err(|_| format!("bad expectation: {}", expected))?;
+            let values = parse_values(reply).map_err(|e| e.to_string())?;
+            match values == expected {
+                true => Ok(()),
+                false => Err(format!("expected {:?}, got {:?}", expected, values)),
+            }
+        }
+    }
+}
+
+/// Runs one transcript and returns its failures as `file:line: message`.
+fn run_transcript(path: &Path) -> (usize, Vec<String>) {
+    let text = fs::read_to_string(path).unwrap();
+    let name = path.file_name().unwrap().to_string_lossy();
+    let mut failures = Vec::new();
+    let mut checked = 0;
+    let mut reply: Option<(usize, &str)> = None;
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let mut fail = |message: String| failures.push(format!("{}:{}: {}", name, number, message));
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (marker, rest) = line.split_at(line.find(' ').unwrap_or(line.len()));
+        let rest = rest.trim();
+        match marker {
+            ">" => match Command::parse(rest) {
+                Ok(command) if command.to_string() == rest => {}
+                Ok(command) => fail(format!("formats as {}", command)),
+                Err(e) => fail(format!("command does not parse: {}", e)),
+            },
+            "<" => {
+                if reply.is_some() {
+                    fail("reply without an expectation".to_string());
+                }
+                reply = Some((number, rest));
+            }
+            "=" => match reply.take() {
+                Some((_, reply)) => {
+                    checked += 1;
+                    if let Err(e) = check(reply, rest) {
+                        fail(format!("{}: {}", reply, e));
+                    }
+                }
+                None => fail("expectation without a reply".to_string()),
+            },
+            _ => fail(format!("unknown line: {}", line)),
+        }
+    }
+    if let Some((number, _)) = reply {
+        failures.push(format!("{}:{}: reply without an expectation", name, number));
+    }
+    (checked, failures)
+}
+
+#[test]
+fn golden_transcripts_parse_as_recorded() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/transcripts");
+    let mut paths: Vec<_> = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "txt"))
+        .collect();
+    paths.sort();
+    assert!(
+        !paths.is_empty(),
+        "no transcripts in {}",
+        directory.display()
+    );
+
+    let mut checked = 0;
+    let mut failures = Vec::new();
+    for path in &paths {
+        let (count, mut errors) = run_transcript(path);
+        checked += count;
+        failures.append(&mut errors);
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    assert!(checked > 0);
+}
@@ -0,0 +1,345 @@
+//! Reply handling of `Controller::write_read` against an in-memory link that
+//! hands out reply bytes in scripted chunks and errors.
+
+use std::{
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use microwave_controller::{
+    Command, Controller, ControllerError, ControllerEvent, DeviceFault, Transport,
+};
+
+/// One scripted outcome of a `read` call.
+enum Chunk {
+    Bytes(&'static [u8]),
+    Error(ErrorKind),
+}
+
+#[derive(Clone, Default)]
+struct FakeLink {
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Default)]
+struct FakeState {
+    reads: VecDeque<Chunk>,
+    written: Vec<u8>,
+    write_error: Option<ErrorKind>,
+}
+
+impl FakeLink {
+    fn with_reads(reads: Vec<Chunk>) -> FakeLink {
+        let link = FakeLink::default();
+        link.state.lock().unwrap().reads = reads.into();
+        link
+    }
+
+    fn written(&self) -> String {
+        String::from_utf8(self.state.lock().unwrap().written.clone()).unwrap()
+    }
+}
+
+impl Read for FakeLink {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let next = self.state.lock().unwrap().reads.pop_front();
+        match next {
+            Some(Chunk::Bytes(bytes)) => {
+                buf[..bytes.len()].copy_from_slice(bytes);
+                Ok(bytes.len())
+            }
+            Some(Chunk::Error(kind)) => Err(kind.into()),
+            None => {
+                thread::sleep(Duration::from_millis(1));
+                Err(ErrorKind::TimedOut.into())
+            }
+        }
+    }
+}
+
+impl Write for FakeLink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(kind) = state.write_error {
+            return Err(kind.into());
+        }
+        state.written.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Transport for FakeLink {
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(1)
+    }
+
+    fn set_timeout(&mut self, _timeout: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn events(controller: &Controller) -> Arc<Mutex<Vec<ControllerEvent>>> {
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = events.clone();
+    controller
+        .subscribe(Arc::new(move |event: &ControllerEvent| {
+            recorded.lock().unwrap().push(event.clone())
+        }))
+        .unwrap();
+    events
+}
+
+#[test]
+fn writes_the_command_with_a_crlf_terminator() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$FCG,0,2450\r\n")]);
+    let controller = Controller::from_transport(link.clone());
+    assert_eq!(controller.write_read("$FCG,0").unwrap(), "$FCG,0,2450\r\n");
+    assert_eq!(link.written(), "$FCG,0\r\n");
+}
+
+#[test]
+fn assembles_a_reply_delivered_one_byte_per_read() {
+    let reply: &'static [u8] = b"$PWRG,0,40.5\r\n";
+    let link = FakeLink::with_reads(reply.chunks(1).map(Chunk::Bytes).collect());
+    let controller = Controller::from_transport(link);
+    assert_eq!(
+        controller.write_read("$PWRG,0").unwrap(),
+        "$PWRG,0,40.5\r\n"
+    );
+}
+
+#[test]
+fn accepts_a_terminator_split_across_reads() {
+    let link = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$ECS,0,OK\r"),
+        Chunk::Error(ErrorKind::TimedOut),
+        Chunk::Bytes(b"\n"),
+    ]);
+    let controller = Controller::from_transport(link);
+    assert_eq!(controller.write_read("$ECS,0,1").unwrap(), "$ECS,0,OK\r\n");
+}
+
+#[test]
+fn keeps_multibyte_characters_split_across_reads() {
+    // "°" is 0xC2 0xB0 in UTF-8.
+    let link = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$IDN,0,25\xc2"),
+        Chunk::Bytes(b"\xb0C\r\n"),
+    ]);
+    let controller = Controller::from_transport(link);
+    assert_eq!(controller.write_read("$IDN,0").unwrap(), "$IDN,0,25°C\r\n");
+}
+
+#[test]
+fn drops_bytes_after_the_first_terminator() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$FCG,0,2450\r\n$FCG,0,24")]);
+    let controller = Controller::from_transport(link);
+    assert_eq!(controller.write_read("$FCG,0").unwrap(), "$FCG,0,2450\r\n");
+}
+
+#[test]
+fn reads_a_verbose_status_up_to_its_ok_line() {
+    let link = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$ST,0,high PA temperature\r\n"),
+        Chunk::Error(ErrorKind::TimedOut),
+        Chunk::Bytes(b"$ST,0,high reflected power\r\nOK\r\n"),
+    ]);
+    let controller = Controller::from_transport(link);
+    assert_eq!(
+        controller.read_faults().unwrap(),
+        [DeviceFault::HighTemperature, DeviceFault::HighReflection]
+    );
+}
+
+#[test]
+fn retries_interrupted_reads() {
+    let link = FakeLink::with_reads(vec![
+        Chunk::Error(ErrorKind::Interrupted),
+        Chunk::Bytes(b"$FCG,0,2450\r\n"),
+    ]);
+    let controller = Controller::from_transport(link);
+    assert_eq!(controller.write_read("$FCG,0").unwrap(), "$FCG,0,2450\r\n");
+}
+
+#[test]
+fn bare_line_feed_is_not_a_terminator() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$FCG,0,2450\n")]);
+    let controller = Controller::from_transport(link);
+    assert!(matches!(
+        controller.write_read("$FCG,0"),
+        Err(ControllerError::Timeout)
+    ));
+}
+
+#[test]
+fn times_out_when_the_board_stays_silent() {
+    let controller = Controller::from_transport(FakeLink::default());
+    let events = events(&controller);
+    let started = Instant::now();
+    assert!(matches!(
+        controller.send(&Command::GetFrequency),
+        Err(ControllerError::Timeout)
+    ));
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(500), "{:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ControllerEvent::CommandTimeout(_)]
+    ));
+}
+
+#[test]
+fn read_errors_report_the_connection_lost() {
+    let link = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$FCG"),
+        Chunk::Error(ErrorKind::UnexpectedEof),
+    ]);
+    let controller = Controller::from_transport(link);
+    let events = events(&controller);
+    assert!(matches!(
+        controller.send(&Command::GetFrequency),
+        Err(ControllerError::Io(_))
+    ));
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ControllerEvent::ConnectionLost(_)]
+    ));
+}
+
+#[test]
+fn write_errors_fail_without_reading() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$FCG,0,2450\r\n")]);
+    link.state.lock().unwrap().write_error = Some(ErrorKind::BrokenPipe);
+    let controller = Controller::from_transport(link.clone());
+    assert!(matches!(
+        controller.write_read("$FCG,0"),
+        Err(ControllerError::Io(_))
+    ));
+    assert_eq!(link.state.lock().unwrap().reads.len(), 1);
+}
+
+#[test]
+fn error_replies_surface_as_device_errors() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$FCS,0,ERR,3\r\n")]);
+    let controller = Controller::from_transport(link);
+    let events = events(&controller);
+    assert!(matches!(
+        controller.send(&Command::SetFrequency(2450.0)),
+        Err(ControllerError::Device(_))
+    ));
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ControllerEvent::DeviceFault(_)]
+    ));
+}
+
+#[test]
+fn optional_queries_the_firmware_rejects_raise_no_fault() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$PVG,0,ERR,1\r\n")]);
+    let controller = Controller::from_transport(link);
+    let events = events(&controller);
+    assert_eq!(
+        controller
+            .send_optional(&Command::GetSupplyVoltage)
+            .unwrap(),
+        None
+    );
+    assert!(events.lock().unwrap().is_empty());
+}
+
+#[test]
+fn stale_handles_are_reopened_and_the_command_resent() {
+    let stale = FakeLink::with_reads(vec![Chunk::Bytes(b"$IDN,0,SG,1.0\r\n")]);
+    let controller = Controller::from_transport(stale.clone());
+    controller.send(&Command::GetIdentity).unwrap();
+    stale.state.lock().unwrap().write_error = Some(ErrorKind::BrokenPipe);
+    let fresh = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$IDN,0,SG,1.0\r\n"),
+        Chunk::Bytes(b"$FCG,0,2450\r\n"),
+    ]);
+    let reopened = fresh.clone();
+    controller
+        .set_reopen(Some(Arc::new(move || {
+            Ok(Box::new(reopened.clone()) as Box<dyn Transport>)
+        })))
+        .unwrap();
+    let events = events(&controller);
+    assert_eq!(
+        controller.send(&Command::GetFrequency).unwrap(),
+        "$FCG,0,2450\r\n"
+    );
+    assert_eq!(fresh.written(), "$IDN,0\r\n$FCG,0\r\n");
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [ControllerEvent::PortReopened(_)]
+    ));
+}
+
+#[test]
+fn a_different_board_behind_the_reopened_port_is_refused() {
+    let stale = FakeLink::with_reads(vec![Chunk::Bytes(b"$IDN,0,SG,1.0\r\n")]);
+    let controller = Controller::from_transport(stale.clone());
+    controller.send(&Command::GetIdentity).unwrap();
+    stale.state.lock().unwrap().write_error = Some(ErrorKind::BrokenPipe);
+    let other = FakeLink::with_reads(vec![Chunk::Bytes(b"$IDN,0,GPS,2.1\r\n")]);
+    controller
+        .set_reopen(Some(Arc::new(move || {
+            Ok(Box::new(other.clone()) as Box<dyn Transport>)
+        })))
+        .unwrap();
+    assert!(matches!(
+        controller.send(&Command::GetFrequency),
+        Err(ControllerError::UnexpectedDevice(_))
+    ));
+    assert!(matches!(
+        controller.send(&Command::SetFrequency(2450.0)),
+        Err(ControllerError::UnexpectedDevice(_))
+    ));
+}
+
+#[test]
+fn the_front_panel_is_locked_out_again_after_reopening() {
+    let stale = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$IDN,0,SG,1.0\r\n"),
+        Chunk::Bytes(b"$RLS,0,OK\r\n"),
+    ]);
+    let controller = Controller::from_transport(stale.clone());
+    controller.send(&Command::GetIdentity).unwrap();
+    controller.set_exclusive_remote(true).unwrap();
+    stale.state.lock().unwrap().write_error = Some(ErrorKind::BrokenPipe);
+    let fresh = FakeLink::with_reads(vec![
+        Chunk::Bytes(b"$IDN,0,SG,1.0\r\n"),
+        Chunk::Bytes(b"$RLS,0,OK\r\n"),
+        Chunk::Bytes(b"$FCG,0,2450\r\n"),
+    ]);
+    let reopened = fresh.clone();
+    controller
+        .set_reopen(Some(Arc::new(move || {
+            Ok(Box::new(reopened.clone()) as Box<dyn Transport>)
+        })))
+        .unwrap();
+    controller.send(&Command::GetFrequency).unwrap();
+    assert_eq!(fresh.written(), "$IDN,0\r\n$RLS,0,1\r\n$FCG,0\r\n");
+    assert!(controller.is_exclusive_remote());
+}
+
+#[test]
+fn a_rejected_lockout_fails_without_a_fault_event() {
+    let link = FakeLink::with_reads(vec![Chunk::Bytes(b"$RLS,0,ERR,1\r\n")]);
+    let controller = Controller::from_transport(link);
+    let events = events(&controller);
+    assert!(matches!(
+        controller.set_exclusive_remote(true),
+        Err(ControllerError::Device(_))
+    ));
+    assert!(!controller.is_exclusive_remote());
+    assert!(events.lock().unwrap().is_empty());
+}
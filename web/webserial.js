@@ -0,0 +1,107 @@
+// Web Serial adapter for the wasm build of microwave_controller.
+//
+// Build the module with
+//   cargo build --release --target wasm32-unknown-unknown --no-default-features --features wasm
+// and serve the resulting microwave_controller.wasm next to this file. The
+// browser (Chrome/Edge) owns the serial port; the wasm module encodes
+// commands and frames replies using the same protocol code as the native
+// controller.
+
+const VENDOR_ID = 0x0403; // TARGET_VENDOR_ID (1027)
+const PRODUCT_ID = 0x6001; // TARGET_PRODUCT_ID (24577)
+const BAUD_RATE = 115200;
+const REPLY_TIMEOUT_MS = 500;
+
+export class WebSerialController {
+  static async load(wasmUrl = "microwave_controller.wasm") {
+    const { instance } = await WebAssembly.instantiateStreaming(fetch(wasmUrl));
+    return new WebSerialController(instance.exports);
+  }
+
+  constructor(exports) {
+    this.wasm = exports;
+    this.encoder = new TextEncoder();
+    this.decoder = new TextDecoder();
+    this.port = null;
+    this.writer = null;
+    this.pending = [];
+  }
+
+  // Prompts the user for the board's port and opens it with the fixed settings.
+  async connect() {
+    this.port = await navigator.serial.requestPort({
+      filters: [{ usbVendorId: VENDOR_ID, usbProductId: PRODUCT_ID }],
+    });
+    await this.port.open({
+      baudRate: BAUD_RATE,
+      dataBits: 8,
+      parity: "none",
+      stopBits: 1,
+      flowControl: "none",
+    });
+    this.wasm.mw_reset();
+    this.writer = this.port.writable.getWriter();
+    this.readLoop();
+  }
+
+  async disconnect() {
+    if (this.reader) await this.reader.cancel();
+    if (this.writer) this.writer.releaseLock();
+    if (this.port) await this.port.close();
+    this.port = this.writer = this.reader = null;
+  }
+
+  // Sends a command object, e.g. { command: "set_frequency", value: 2450 },
+  // and resolves with the parsed response { raw, mnemonic, fields, error }.
+  async send(command) {
+    const wire = this.call(this.wasm.mw_encode_command, JSON.stringify(command));
+    const reply = new Promise((resolve, reject) => {
+      const timer = setTimeout(() => {
+        this.pending.splice(this.pending.findIndex((p) => p.timer === timer), 1);
+        reject(new Error("Timeout waiting for response"));
+      }, REPLY_TIMEOUT_MS);
+      this.pending.push({ resolve, reject, timer });
+    });
+    await this.writer.write(this.encoder.encode(wire));
+    const response = await reply;
+    if (response.error) throw new Error(`Device error: ${response.raw}`);
+    return response;
+  }
+
+  async readLoop() {
+    while (this.port && this.port.readable) {
+      this.reader = this.port.readable.getReader();
+      try {
+        for (;;) {
+          const { value, done } = await this.reader.read();
+          if (done) return;
+          const responses = JSON.parse(this.call(this.wasm.mw_feed, value));
+          for (const response of responses) {
+            const waiter = this.pending.shift();
+            if (!waiter) continue;
+            clearTimeout(waiter.timer);
+            waiter.resolve(response);
+          }
+        }
+      } catch (e) {
+        for (const waiter of this.pending.splice(0)) waiter.reject(e);
+      } finally {
+        this.reader.releaseLock();
+      }
+    }
+  }
+
+  // Copies `input` (string or bytes) into wasm memory, runs `operation` and
+  // returns its output text, throwing on a negative result.
+  call(operation, input) {
+    const bytes = typeof input === "string" ? this.encoder.encode(input) : input;
+    const ptr = this.wasm.mw_input(bytes.length);
+    new Uint8Array(this.wasm.memory.buffer, ptr, bytes.length).set(bytes);
+    const n = operation(bytes.length);
+    const len = Math.abs(n);
+    const out = new Uint8Array(this.wasm.memory.buffer, this.wasm.mw_output(), len);
+    const text = this.decoder.decode(out.slice());
+    if (n < 0) throw new Error(text);
+    return text;
+  }
+}